@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use branchless::core::config::{get_restack_rename_limit, get_restack_rename_threshold};
 use branchless::core::eventlog::{EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
 use branchless::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
@@ -133,6 +134,8 @@ fn bench_cherry_pick_fast(c: &mut Criterion) {
         let head_oid = repo.get_head_info().unwrap().oid.unwrap();
         let head_commit = repo.find_commit_or_fail(head_oid).unwrap();
         let target_commit = nth_parent(head_commit.clone(), 1);
+        let rename_limit = get_restack_rename_limit(&repo).unwrap();
+        let rename_threshold = get_restack_rename_threshold(&repo).unwrap();
 
         b.iter(|| {
             repo.cherry_pick_fast(
@@ -140,6 +143,8 @@ fn bench_cherry_pick_fast(c: &mut Criterion) {
                 &target_commit,
                 &CherryPickFastOptions {
                     reuse_parent_tree_if_possible: false,
+                    rename_limit,
+                    rename_threshold,
                 },
             )
             .unwrap()
@@ -3,6 +3,7 @@
 
 use std::path::PathBuf;
 
+use branchless::core::config::{get_restack_rename_limit, get_restack_rename_threshold};
 use branchless::git::{CherryPickFastOptions, Repo};
 use eyre::Context;
 
@@ -10,6 +11,8 @@ fn main() -> eyre::Result<()> {
     let path_to_repo = std::env::var("PATH_TO_REPO")
         .wrap_err_with(|| "Could not read PATH_TO_REPO environment variable")?;
     let repo = Repo::from_dir(&PathBuf::from(path_to_repo))?;
+    let rename_limit = get_restack_rename_limit(&repo)?;
+    let rename_threshold = get_restack_rename_threshold(&repo)?;
 
     let mut next_commit = repo.find_commit_or_fail(repo.get_head_info()?.oid.unwrap())?;
     for i in 1..1000 {
@@ -39,6 +42,8 @@ fn main() -> eyre::Result<()> {
             &parent_commit,
             &CherryPickFastOptions {
                 reuse_parent_tree_if_possible: false,
+                rename_limit,
+                rename_threshold,
             },
         )?;
         let tree = match tree {
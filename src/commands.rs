@@ -1,12 +1,33 @@
 //! Sub-commands of `git-branchless`.
 
+pub mod annotate;
+pub mod benchmark;
+pub mod branch;
+pub mod bug_report;
+pub mod diff;
+pub mod doctor;
+pub mod edit;
+pub mod export_stack;
+pub mod fold;
 pub mod gc;
 pub mod hide;
 pub mod hooks;
 pub mod init;
+pub mod man;
 pub mod r#move;
 pub mod navigation;
+pub mod plugin;
+pub mod prune;
+pub mod rebase;
+pub mod repair;
 pub mod restack;
+pub mod restore_branch;
 pub mod smartlog;
+pub mod status;
+pub mod submit;
+pub mod swap;
+pub mod switch;
+pub mod sync;
+pub mod sync_events;
 pub mod undo;
 pub mod wrap;
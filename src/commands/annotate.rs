@@ -0,0 +1,45 @@
+//! Attach user-provided notes to past events.
+//!
+//! Long event histories can be hard to navigate in `git undo`. Annotations let
+//! the user mark interesting points in the history (such as "this is where I
+//! broke things") so that they can find them again later.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::git::Repo;
+use crate::tui::Effects;
+
+/// Annotate the transaction containing the event with the given ID.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn annotate(effects: &Effects, event_id: isize, message: &str) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    let cursor = event_replayer.make_cursor(event_id);
+    let event = match event_replayer.get_event_before_cursor(cursor) {
+        Some((cursor_event_id, event)) if cursor_event_id == event_id => event,
+        _ => {
+            writeln!(effects.get_output_stream(), "Event not found: {}", event_id)?;
+            return Ok(1);
+        }
+    };
+
+    let event_tx_id = event.get_event_tx_id();
+    event_log_db.add_transaction_annotation(event_tx_id, now, message)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Annotated transaction {} (event {}).",
+        event_tx_id.to_string(),
+        event_id
+    )?;
+    Ok(0)
+}
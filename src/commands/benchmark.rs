@@ -0,0 +1,171 @@
+//! Time core operations against the current repository.
+//!
+//! The report is printed as tab-separated values, so that it can be attached to
+//! performance bug reports or compared between versions to track regressions.
+
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::instrument;
+
+use crate::commands::smartlog::render_graph;
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::metadata::{
+    BranchesProvider, CommitMessageProvider, CommitOidProvider, DifferentialRevisionProvider,
+    HiddenExplanationProvider, RelativeTimeProvider,
+};
+use crate::core::rewrite::{BuildRebasePlanOptions, RebasePlanBuilder};
+use crate::git::Repo;
+use crate::tui::Effects;
+
+/// The timings collected for a single operation.
+#[derive(Debug)]
+struct Sample {
+    name: &'static str,
+    durations: Vec<Duration>,
+}
+
+impl Sample {
+    fn new(name: &'static str) -> Self {
+        Sample {
+            name,
+            durations: Vec::new(),
+        }
+    }
+
+    /// Run the given function, recording how long it took.
+    fn time<T>(&mut self, f: impl FnOnce() -> eyre::Result<T>) -> eyre::Result<T> {
+        let start = Instant::now();
+        let result = f()?;
+        self.durations.push(start.elapsed());
+        Ok(result)
+    }
+
+    fn describe(&self) -> String {
+        let to_millis = |duration: &Duration| duration.as_secs_f64() * 1000.0;
+        let min = self.durations.iter().min().map(to_millis).unwrap_or(0.0);
+        let max = self.durations.iter().max().map(to_millis).unwrap_or(0.0);
+        let mean = if self.durations.is_empty() {
+            0.0
+        } else {
+            self.durations.iter().map(to_millis).sum::<f64>() / (self.durations.len() as f64)
+        };
+        format!(
+            "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
+            self.name,
+            self.durations.len(),
+            min,
+            mean,
+            max
+        )
+    }
+}
+
+/// Time event replay, graph construction, smartlog rendering, and rebase plan
+/// building against the current repository, and print the results.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn benchmark(effects: &Effects, iterations: usize) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+
+    let mut event_replay_sample = Sample::new("event_replay");
+    let mut make_graph_sample = Sample::new("make_graph");
+    let mut render_smartlog_sample = Sample::new("render_smartlog");
+    let mut build_rebase_plan_sample = Sample::new("build_rebase_plan");
+
+    for _ in 0..iterations {
+        let event_replayer = event_replay_sample
+            .time(|| EventReplayer::from_event_log_db(effects, &repo, &event_log_db))?;
+        let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+
+        let graph = make_graph_sample.time(|| {
+            make_graph(
+                effects,
+                &repo,
+                &merge_base_db,
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+                &HeadOid(head_oid),
+                &MainBranchOid(main_branch_oid),
+                &BranchOids(branch_oid_to_names.keys().copied().collect()),
+                true,
+            )
+        })?;
+
+        render_smartlog_sample.time(|| {
+            render_graph(
+                effects,
+                &repo,
+                &merge_base_db,
+                &graph,
+                &HeadOid(head_oid),
+                &mut [
+                    &mut CommitOidProvider::new(true)?,
+                    &mut RelativeTimeProvider::new(&repo, SystemTime::now())?,
+                    &mut HiddenExplanationProvider::new(
+                        &graph,
+                        &event_replayer,
+                        event_replayer.make_default_cursor(),
+                    )?,
+                    &mut BranchesProvider::new(&repo, &branch_oid_to_names)?,
+                    &mut DifferentialRevisionProvider::new(&repo, &graph)?,
+                    &mut CommitMessageProvider::new()?,
+                ],
+            )
+        })?;
+
+        // Plan to move each line of work onto the main branch, as `git
+        // restack` might.
+        build_rebase_plan_sample.time(|| {
+            let mut builder = RebasePlanBuilder::new(
+                &repo,
+                &graph,
+                &merge_base_db,
+                &MainBranchOid(main_branch_oid),
+            );
+            for (oid, node) in graph.iter() {
+                let is_root = match node.parent {
+                    Some(parent_oid) => !node.is_main && graph[&parent_oid].is_main,
+                    None => false,
+                };
+                if is_root {
+                    builder.move_subtree(*oid, main_branch_oid)?;
+                }
+            }
+            let _rebase_plan = builder.build(
+                effects,
+                &BuildRebasePlanOptions {
+                    dump_rebase_constraints: false,
+                    dump_rebase_plan: false,
+                    detect_duplicate_commits_via_patch_id: true,
+                },
+            )?;
+            Ok(())
+        })?;
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "operation\titerations\tmin_ms\tmean_ms\tmax_ms"
+    )?;
+    for sample in [
+        event_replay_sample,
+        make_graph_sample,
+        render_smartlog_sample,
+        build_rebase_plan_sample,
+    ]
+    .iter()
+    {
+        writeln!(effects.get_output_stream(), "{}", sample.describe())?;
+    }
+
+    Ok(0)
+}
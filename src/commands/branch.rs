@@ -0,0 +1,58 @@
+//! Create branches for commits which don't have one.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::commands::submit::find_head_stack;
+use crate::core::branch_names::create_branch_for_unbranched_commits;
+use crate::core::eventlog::EventLogDb;
+use crate::core::formatting::printable_styled_string;
+use crate::git::{GitRunInfo, Repo};
+use crate::tui::Effects;
+
+/// Create a branch pointing to `HEAD`, named according to
+/// `branchless.branch.nameTemplate`, if none of the commits between the last
+/// branch in the stack and `HEAD` have one.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn branch(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let stack = match find_head_stack(effects, &repo, None, &[])? {
+        Some(stack) => stack,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "No commit is checked out, so there is nothing to create a branch for."
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(now, "branch")?;
+    match create_branch_for_unbranched_commits(effects, git_run_info, &repo, event_tx_id, &stack)? {
+        Some((branch_name, commit_oid)) => {
+            writeln!(
+                effects.get_output_stream(),
+                "Created branch {} at {}",
+                branch_name,
+                printable_styled_string(
+                    effects.get_glyphs(),
+                    repo.friendly_describe_commit_from_oid(commit_oid)?
+                )?
+            )?;
+        }
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "There are no commits without a branch in the current stack."
+            )?;
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,282 @@
+//! Collect information about the repository and the `git-branchless` setup
+//! into a report which can be pasted into a GitHub issue.
+//!
+//! The report includes the versions of Git and `git-branchless`, the status
+//! of the hooks, the `branchless.*` config, the most recent events in the
+//! event log and the smartlog. Config values which look like secrets are
+//! always redacted. With `--anonymize`, reference names (including those in
+//! the config) are replaced with placeholders and commit messages and branch names are left out, so that
+//! only the shape of the history remains.
+
+use std::ffi::OsStr;
+use std::fmt::Write;
+
+use tracing::instrument;
+
+use crate::commands::init::{get_all_hook_types, get_hook_status};
+use crate::core::anonymize::Anonymizer;
+use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::metadata::{
+    BranchesProvider, CommitMessageProvider, CommitMetadataProvider, CommitOidProvider,
+    HiddenExplanationProvider,
+};
+use crate::git::{GitRunInfo, Repo};
+use crate::tui::Effects;
+
+use super::smartlog::render_graph;
+
+/// The number of the most recent events to include in the report.
+const NUM_RECENT_EVENTS: usize = 50;
+
+/// Substrings of config keys whose values should never appear in the report.
+const SECRET_CONFIG_KEY_PATTERNS: &[&str] = &["token", "password", "secret", "credential"];
+
+/// Config keys (in lowercase) whose values are branch names. These are
+/// anonymized when `--anonymize` is passed.
+const BRANCH_NAME_CONFIG_KEYS: &[&str] = &["branchless.core.mainbranch", "branchless.mainbranch"];
+
+/// Config keys (in lowercase) whose values are reference names or patterns.
+/// These are anonymized when `--anonymize` is passed.
+const REF_NAME_CONFIG_KEYS: &[&str] = &[
+    "branchless.core.publicbranches",
+    "branchless.eventlog.ignorerefs",
+];
+
+fn describe_event(event: &Event, anonymizer: Option<&mut Anonymizer>) -> String {
+    match event {
+        Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id,
+            old_commit_oid,
+            new_commit_oid,
+        } => format!(
+            "{} rewrite {} -> {}",
+            event_tx_id.to_string(),
+            old_commit_oid,
+            new_commit_oid
+        ),
+        Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid,
+            message: _,
+        } => {
+            let ref_name = match anonymizer {
+                Some(anonymizer) => anonymizer.anonymize_ref_name(ref_name),
+                None => ref_name.to_string_lossy().into_owned(),
+            };
+            format!(
+                "{} ref-update {} {} -> {}",
+                event_tx_id.to_string(),
+                ref_name,
+                old_oid,
+                new_oid
+            )
+        }
+        Event::CommitEvent {
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+        } => format!("{} commit {}", event_tx_id.to_string(), commit_oid),
+        Event::HideEvent {
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+            reason: _,
+        } => format!("{} hide {}", event_tx_id.to_string(), commit_oid),
+        Event::UnhideEvent {
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+        } => format!("{} unhide {}", event_tx_id.to_string(), commit_oid),
+        Event::StashPushEvent {
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+        } => format!("{} stash-push {}", event_tx_id.to_string(), commit_oid),
+        Event::StashDropEvent {
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+        } => format!("{} stash-drop {}", event_tx_id.to_string(), commit_oid),
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id,
+            old_commit_oid,
+            new_commit_oid,
+        } => format!(
+            "{} copy {} -> {}",
+            event_tx_id.to_string(),
+            old_commit_oid,
+            new_commit_oid
+        ),
+    }
+}
+
+fn is_secret_config_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_CONFIG_KEY_PATTERNS
+        .iter()
+        .any(|pattern| key.contains(pattern))
+}
+
+/// Replace the value of the given config key with a placeholder if it holds a
+/// branch or reference name, using the same placeholders as for the event log.
+fn anonymize_config_value(anonymizer: &mut Anonymizer, key: &str, value: String) -> String {
+    let key = key.to_lowercase();
+    if BRANCH_NAME_CONFIG_KEYS.contains(&key.as_str()) {
+        let ref_name = format!("refs/heads/{}", value);
+        let ref_name = anonymizer.anonymize_ref_name(OsStr::new(&ref_name));
+        match ref_name.strip_prefix("refs/heads/") {
+            Some(branch_name) => branch_name.to_string(),
+            None => ref_name,
+        }
+    } else if REF_NAME_CONFIG_KEYS.contains(&key.as_str()) {
+        anonymizer.anonymize_ref_name(OsStr::new(&value))
+    } else {
+        value
+    }
+}
+
+/// Print a report about the repository and the `git-branchless` setup to be
+/// pasted into a bug report. If `anonymize` is set, then reference names,
+/// branch names and commit messages are left out of the report.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn bug_report(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    anonymize: bool,
+) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let mut report = String::new();
+    writeln!(report, "<details>")?;
+    writeln!(report, "<summary>Show bug report</summary>")?;
+    writeln!(report)?;
+
+    writeln!(report, "#### Environment")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "- git-branchless version: {}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    let git_version = git_run_info.run_silent(&repo, None, &["version"])?;
+    writeln!(report, "- Git version: {}", git_version.trim())?;
+    writeln!(
+        report,
+        "- OS: {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )?;
+    writeln!(report)?;
+
+    writeln!(report, "#### Hooks")?;
+    writeln!(report)?;
+    for hook_type in get_all_hook_types() {
+        let (_path, status) = get_hook_status(&repo, hook_type)?;
+        writeln!(report, "- {}: {:?}", hook_type, status)?;
+    }
+    writeln!(report)?;
+
+    writeln!(report, "#### Config")?;
+    writeln!(report)?;
+    writeln!(report, "```")?;
+    let mut anonymizer = Anonymizer::default();
+    let entries: Vec<(String, String)> = repo.get_config()?.entries("branchless.")?;
+    for (key, value) in entries {
+        let value = if is_secret_config_key(&key) {
+            "<redacted>".to_string()
+        } else if anonymize {
+            anonymize_config_value(&mut anonymizer, &key, value)
+        } else {
+            value
+        };
+        writeln!(report, "{} = {}", key, value)?;
+    }
+    writeln!(report, "```")?;
+    writeln!(report)?;
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let events = event_log_db.get_events()?;
+    writeln!(
+        report,
+        "#### Events (the {} most recent)",
+        NUM_RECENT_EVENTS.min(events.len())
+    )?;
+    writeln!(report)?;
+    writeln!(report, "```")?;
+    for event in events
+        .iter()
+        .skip(events.len().saturating_sub(NUM_RECENT_EVENTS))
+    {
+        let anonymizer = if anonymize {
+            Some(&mut anonymizer)
+        } else {
+            None
+        };
+        writeln!(report, "{}", describe_event(event, anonymizer))?;
+    }
+    writeln!(report, "```")?;
+    writeln!(report)?;
+
+    writeln!(report, "#### Smartlog")?;
+    writeln!(report)?;
+    writeln!(report, "```")?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+    let mut oid_provider = CommitOidProvider::new(false)?;
+    let mut hidden_explanation_provider = HiddenExplanationProvider::new(
+        &graph,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+    )?;
+    let mut branches_provider = BranchesProvider::new(&repo, &branch_oid_to_names)?;
+    let mut message_provider = CommitMessageProvider::new()?;
+    let mut providers: Vec<&mut dyn CommitMetadataProvider> = vec![&mut oid_provider];
+    if !anonymize {
+        providers.push(&mut hidden_explanation_provider);
+        providers.push(&mut branches_provider);
+        providers.push(&mut message_provider);
+    }
+    let lines = render_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &graph,
+        &HeadOid(head_oid),
+        &mut providers,
+    )?;
+    // Don't write ANSI escape codes, since the report is meant to be pasted.
+    let glyphs = Glyphs::text();
+    for line in lines {
+        writeln!(report, "{}", printable_styled_string(&glyphs, line)?)?;
+    }
+    writeln!(report, "```")?;
+    writeln!(report)?;
+    writeln!(report, "</details>")?;
+
+    write!(effects.get_output_stream(), "{}", report)?;
+    Ok(0)
+}
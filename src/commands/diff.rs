@@ -0,0 +1,83 @@
+//! Compare a commit against its previous version.
+//!
+//! The previous version of a commit is found by following the rewrite events
+//! in the event log, so this shows what changed in the last amend or rebase of
+//! the commit, such as when responding to review feedback.
+
+use std::fmt::Write;
+
+use tracing::instrument;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::git::{GitRunInfo, Repo};
+use crate::tui::Effects;
+
+/// Show the difference between the given commit and the version of it before
+/// it was last rewritten.
+///
+/// If both versions have the same parents, the difference between their
+/// contents is shown with `git diff`. Otherwise, the commit was rebased, and
+/// `git range-diff` is used to compare the changes which each version makes
+/// relative to its own parents, so that the changes from the rebase itself
+/// aren't included.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn diff_against_previous(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commit: &str,
+) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let commit = match repo.revparse_single_commit(commit)? {
+        Some(commit) => commit,
+        None => {
+            writeln!(effects.get_error_stream(), "Commit not found: {}", commit)?;
+            return Ok(1);
+        }
+    };
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let previous_oid = match event_replayer
+        .get_cursor_previous_version(event_replayer.make_default_cursor(), commit.get_oid())
+    {
+        Some(previous_oid) => previous_oid,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Commit {} has no previous version in the event log.",
+                commit.get_oid()
+            )?;
+            return Ok(1);
+        }
+    };
+    let previous_commit = match repo.find_commit(previous_oid)? {
+        Some(previous_commit) => previous_commit,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "The previous version of commit {}, {}, is no longer in the repository.",
+                commit.get_oid(),
+                previous_oid
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let args = if previous_commit.get_parent_oids() == commit.get_parent_oids() {
+        vec![
+            "diff".to_string(),
+            previous_oid.to_string(),
+            commit.get_oid().to_string(),
+        ]
+    } else {
+        vec![
+            "range-diff".to_string(),
+            format!("{}^!", previous_oid),
+            format!("{}^!", commit.get_oid()),
+        ]
+    };
+    git_run_info.run(effects, None, &args)
+}
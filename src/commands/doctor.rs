@@ -0,0 +1,347 @@
+//! Diagnose common problems with the `git-branchless` setup of a repository.
+//!
+//! Hooks can be overwritten by other tools, the main branch can be renamed or
+//! deleted, and the on-disk caches can fall out of sync with the repository
+//! (for example, after running `git gc`). This command checks for these
+//! situations and explains how to fix them, or fixes them itself if requested.
+
+use std::convert::TryInto;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::commands::init::{
+    detect_main_branch_name, get_all_hook_types, get_hook_status, is_installed_in_template_dir,
+    reinstall_hook, HookStatus,
+};
+use crate::core::config::get_main_branch_name;
+use crate::core::eventlog::{CommitVisibility, Event, EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Palette, Pluralize, StyledStringBuilder};
+use crate::core::mergebase::prune_dangling_merge_base_entries;
+use crate::git::{NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// An action which can be taken automatically to resolve a `Problem`.
+#[derive(Debug)]
+enum Fix {
+    ReinstallHook { hook_type: &'static str },
+    ReinstallHooks,
+    SetMainBranch { main_branch_name: String },
+    RemoveDanglingMergeBaseEntries,
+    HideMissingCommits { commit_oids: Vec<NonZeroOid> },
+}
+
+#[derive(Debug)]
+struct Problem {
+    description: String,
+    suggestion: String,
+    fix: Option<Fix>,
+}
+
+#[instrument]
+fn check_hooks(repo: &Repo) -> eyre::Result<Vec<Problem>> {
+    let mut hook_statuses = Vec::new();
+    for hook_type in get_all_hook_types() {
+        let (path, status) = get_hook_status(repo, hook_type)?;
+        hook_statuses.push((hook_type, path, status));
+    }
+
+    // If none of the hooks are installed even though `git branchless init
+    // --global` was run, then this repository was most likely created before
+    // then, so report that instead of each missing hook.
+    if hook_statuses
+        .iter()
+        .all(|(_hook_type, _path, status)| *status == HookStatus::Missing)
+        && is_installed_in_template_dir(repo)?
+    {
+        return Ok(vec![Problem {
+            description: "This repository was created before git-branchless was installed globally, so its hooks are not installed.".to_string(),
+            suggestion: "Run `git branchless init` to set it up.".to_string(),
+            fix: Some(Fix::ReinstallHooks),
+        }]);
+    }
+
+    let mut problems = Vec::new();
+    for (hook_type, path, status) in hook_statuses {
+        let problem = match status {
+            HookStatus::Installed => continue,
+            HookStatus::Missing => Problem {
+                description: format!("The {} hook is not installed.", hook_type),
+                suggestion: "Run `git branchless init` to install it.".to_string(),
+                fix: Some(Fix::ReinstallHook { hook_type }),
+            },
+            HookStatus::Uninstalled => Problem {
+                description: format!(
+                    "The {} hook does not invoke git-branchless: {}",
+                    hook_type,
+                    path.to_string_lossy()
+                ),
+                suggestion: "Run `git branchless init` to reinstall it.".to_string(),
+                fix: Some(Fix::ReinstallHook { hook_type }),
+            },
+            HookStatus::Overridden => Problem {
+                description: format!(
+                    "The {} hook was overwritten by something other than git-branchless: {}",
+                    hook_type,
+                    path.to_string_lossy()
+                ),
+                suggestion: format!(
+                    "Add this line to the hook script: git branchless hook-{} \"$@\"",
+                    hook_type
+                ),
+                fix: None,
+            },
+        };
+        problems.push(problem);
+    }
+    Ok(problems)
+}
+
+#[instrument]
+fn check_main_branch(repo: &Repo) -> eyre::Result<Vec<Problem>> {
+    let config = repo.get_config()?;
+    let is_configured = config
+        .get::<String, _>("branchless.core.mainBranch")?
+        .is_some()
+        || config.get::<String, _>("branchless.mainBranch")?.is_some();
+    if !is_configured {
+        let fix = detect_main_branch_name(repo)?
+            .map(|main_branch_name| Fix::SetMainBranch { main_branch_name });
+        return Ok(vec![Problem {
+            description: "The main branch is not configured.".to_string(),
+            suggestion: "Run: git config branchless.core.mainBranch <branch>".to_string(),
+            fix,
+        }]);
+    }
+
+    let main_branch_name = get_main_branch_name(repo)?;
+    let is_found = repo
+        .find_branch(&main_branch_name, git2::BranchType::Local)?
+        .is_some()
+        || repo
+            .find_branch(&main_branch_name, git2::BranchType::Remote)?
+            .is_some();
+    if is_found {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![Problem {
+            description: format!(
+                "The configured main branch {:?} does not exist.",
+                main_branch_name
+            ),
+            suggestion: "Create it, or run: git config branchless.core.mainBranch <branch>"
+                .to_string(),
+            fix: None,
+        }])
+    }
+}
+
+#[instrument]
+fn check_event_log_schema(
+    db_path: PathBuf,
+    event_log_db: &EventLogDb,
+) -> eyre::Result<Vec<Problem>> {
+    let mismatches = event_log_db.check_schema()?;
+    let problems = mismatches
+        .into_iter()
+        .map(|mismatch| Problem {
+            description: format!(
+                "The event log database is not compatible with this version of git-branchless: {}",
+                mismatch
+            ),
+            suggestion: format!(
+                "Upgrade git-branchless, or move {:?} out of the way to start a new event log.",
+                db_path
+            ),
+            fix: None,
+        })
+        .collect();
+    Ok(problems)
+}
+
+#[instrument]
+fn check_merge_base_cache(repo: &Repo, conn: &rusqlite::Connection) -> eyre::Result<Vec<Problem>> {
+    let num_dangling_entries = prune_dangling_merge_base_entries(repo, conn, false)?;
+    if num_dangling_entries == 0 {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![Problem {
+            description: format!(
+                "The merge-base cache has {} referring to commits which no longer exist.",
+                Pluralize {
+                    amount: num_dangling_entries.try_into()?,
+                    singular: "entry",
+                    plural: "entries",
+                }
+                .to_string()
+            ),
+            suggestion: "These entries are harmless, but can be removed.".to_string(),
+            fix: Some(Fix::RemoveDanglingMergeBaseEntries),
+        }])
+    }
+}
+
+#[instrument]
+fn check_missing_commits(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+) -> eyre::Result<Vec<Problem>> {
+    let cursor = event_replayer.make_default_cursor();
+    let mut commit_oids = Vec::new();
+    for oid in event_replayer.get_cursor_active_oids(cursor) {
+        if matches!(
+            event_replayer.get_cursor_commit_visibility(cursor, oid),
+            Some(CommitVisibility::Visible)
+        ) && repo.find_commit(oid)?.is_none()
+        {
+            commit_oids.push(oid);
+        }
+    }
+    commit_oids.sort_unstable();
+
+    if commit_oids.is_empty() {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![Problem {
+            description: format!(
+                "The event log refers to {} missing from the repository: {}",
+                Pluralize {
+                    amount: commit_oids.len().try_into()?,
+                    singular: "visible commit",
+                    plural: "visible commits",
+                }
+                .to_string(),
+                commit_oids
+                    .iter()
+                    .map(|oid| oid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            suggestion: "These commits can no longer be shown, so they should be hidden."
+                .to_string(),
+            fix: Some(Fix::HideMissingCommits { commit_oids }),
+        }])
+    }
+}
+
+#[instrument]
+fn apply_fix(
+    now: SystemTime,
+    repo: &Repo,
+    conn: &rusqlite::Connection,
+    event_log_db: &mut EventLogDb,
+    fix: Fix,
+) -> eyre::Result<()> {
+    match fix {
+        Fix::ReinstallHook { hook_type } => reinstall_hook(repo, hook_type)?,
+
+        Fix::ReinstallHooks => {
+            for hook_type in get_all_hook_types() {
+                reinstall_hook(repo, hook_type)?;
+            }
+        }
+
+        Fix::SetMainBranch { main_branch_name } => {
+            repo.get_config()?
+                .set("branchless.core.mainBranch", main_branch_name)?;
+        }
+
+        Fix::RemoveDanglingMergeBaseEntries => {
+            prune_dangling_merge_base_entries(repo, conn, true)?;
+        }
+
+        Fix::HideMissingCommits { commit_oids } => {
+            let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+            let event_tx_id = event_log_db.make_transaction_id(now, "doctor")?;
+            let events = commit_oids
+                .into_iter()
+                .map(|commit_oid| Event::HideEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                    reason: Some("commit no longer exists".to_string()),
+                })
+                .collect();
+            event_log_db.add_events(events)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check the repository for common problems with the `git-branchless` setup.
+/// If `fix` is set, also try to resolve them.
+///
+/// Returns an exit code (0 denotes successful exit). A non-zero exit code
+/// indicates that problems remain.
+#[instrument]
+pub fn doctor(effects: &Effects, fix: bool) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    let db_path = repo.get_db_path()?;
+    let problems: Vec<Problem> = vec![
+        check_hooks(&repo)?,
+        check_main_branch(&repo)?,
+        check_event_log_schema(db_path, &event_log_db)?,
+        check_merge_base_cache(&repo, &conn)?,
+        check_missing_commits(&repo, &event_replayer)?,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if problems.is_empty() {
+        writeln!(effects.get_output_stream(), "No problems found.")?;
+        return Ok(0);
+    }
+
+    let mut num_remaining_problems = 0;
+    for Problem {
+        description,
+        suggestion,
+        fix: problem_fix,
+    } in problems
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            printable_styled_string(
+                effects.get_glyphs(),
+                StyledStringBuilder::new()
+                    .append_styled("Problem: ", Palette::current().error)
+                    .append_plain(description)
+                    .build()
+            )?
+        )?;
+
+        match problem_fix {
+            Some(problem_fix) if fix => {
+                apply_fix(now, &repo, &conn, &mut event_log_db, problem_fix)?;
+                writeln!(effects.get_output_stream(), "  Fixed.")?;
+            }
+            Some(_) => {
+                num_remaining_problems += 1;
+                writeln!(effects.get_output_stream(), "  Suggestion: {}", suggestion)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "  This can be fixed automatically with: git branchless doctor --fix"
+                )?;
+            }
+            None => {
+                num_remaining_problems += 1;
+                writeln!(effects.get_output_stream(), "  Suggestion: {}", suggestion)?;
+            }
+        }
+    }
+
+    if num_remaining_problems == 0 {
+        Ok(0)
+    } else {
+        Ok(1)
+    }
+}
@@ -0,0 +1,300 @@
+//! Edit a commit in the middle of a stack, like the `edit` action of Mercurial's
+//! `histedit`.
+//!
+//! `git branchless edit <commit>` checks out the commit so that it can be
+//! amended, and remembers where `HEAD` was. `git branchless edit --continue`
+//! then restacks the descendants of the commit onto its amended version and
+//! checks out the previous location again. Both steps are recorded in the
+//! same event transaction.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eyre::Context;
+use tracing::{instrument, warn};
+
+use crate::commands::restack::{restack_branches, restack_commits};
+use crate::core::config::{
+    get_move_force_in_memory, get_move_force_on_disk, get_restack_preserve_timestamps,
+};
+use crate::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use crate::core::formatting::printable_styled_string;
+use crate::core::graph::{
+    make_graph, resolve_commits, BranchOids, HeadOid, MainBranchOid, ResolveCommitsResult,
+};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::{find_rewrite_target, BuildRebasePlanOptions, ExecuteRebasePlanOptions};
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// The state of an edit in progress, saved between `git branchless edit` and
+/// `git branchless edit --continue`.
+#[derive(Debug, PartialEq, Eq)]
+struct EditState {
+    /// The transaction which the edit is recorded in.
+    event_tx_id: EventTransactionId,
+
+    /// The commit being edited.
+    commit_oid: NonZeroOid,
+
+    /// The commit which `HEAD` pointed to when the edit was started.
+    head_oid: NonZeroOid,
+
+    /// The branch which was checked out when the edit was started, if any.
+    branch_name: Option<String>,
+}
+
+fn get_edit_state_path(repo: &Repo) -> PathBuf {
+    repo.get_path().join("branchless").join("edit-state")
+}
+
+fn read_edit_state(repo: &Repo) -> eyre::Result<Option<EditState>> {
+    let path = get_edit_state_path(repo);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading edit state at {:?}", &path)),
+    };
+    let lines: Vec<&str> = contents.lines().collect();
+    let state = match lines.as_slice() {
+        [event_tx_id, commit_oid, head_oid, branch_name @ ..] => {
+            match (event_tx_id.parse(), commit_oid.parse(), head_oid.parse()) {
+                (Ok(event_tx_id), Ok(commit_oid), Ok(head_oid)) => Some(EditState {
+                    event_tx_id,
+                    commit_oid,
+                    head_oid,
+                    branch_name: branch_name.first().map(|name| name.to_string()),
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if state.is_none() {
+        warn!(?contents, "Invalid edit state");
+    }
+    Ok(state)
+}
+
+fn write_edit_state(repo: &Repo, state: &EditState) -> eyre::Result<()> {
+    let EditState {
+        event_tx_id,
+        commit_oid,
+        head_oid,
+        branch_name,
+    } = state;
+    let path = get_edit_state_path(repo);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).wrap_err_with(|| "Creating .git/branchless dir")?;
+    }
+    let mut contents = format!(
+        "{}\n{}\n{}\n",
+        event_tx_id.to_string(),
+        commit_oid,
+        head_oid
+    );
+    if let Some(branch_name) = branch_name {
+        writeln!(contents, "{}", branch_name)?;
+    }
+    std::fs::write(&path, contents)
+        .wrap_err_with(|| format!("Writing edit state to {:?}", &path))?;
+    Ok(())
+}
+
+fn clear_edit_state(repo: &Repo) -> eyre::Result<()> {
+    let path = get_edit_state_path(repo);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).wrap_err_with(|| format!("Removing edit state at {:?}", &path)),
+    }
+}
+
+/// Start editing the given commit: remember the current location of `HEAD`,
+/// then check out the commit so that it can be amended.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn edit(effects: &Effects, git_run_info: &GitRunInfo, commit: String) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    if let Some(EditState { commit_oid, .. }) = read_edit_state(&repo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "Commit {} is already being edited. Run `git branchless edit --continue` to finish editing it first.",
+            commit_oid
+        )?;
+        return Ok(1);
+    }
+
+    let commit = match resolve_commits(&repo, vec![commit])? {
+        ResolveCommitsResult::Ok { mut commits } => match commits.pop() {
+            Some(commit) => commit,
+            None => eyre::bail!("Unexpected number of returns values from resolve_commits"),
+        },
+        ResolveCommitsResult::CommitNotFound { commit } => {
+            writeln!(effects.get_output_stream(), "Commit not found: {}", commit)?;
+            return Ok(1);
+        }
+    };
+    let head_info = repo.get_head_info()?;
+    let head_oid = match head_info.oid {
+        Some(head_oid) => head_oid,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "No OID for HEAD is available to return to after editing."
+            )?;
+            return Ok(1);
+        }
+    };
+    if repo.has_changed_files(effects)? {
+        writeln!(
+            effects.get_output_stream(),
+            "There are uncommitted changes. Commit or stash them before editing a commit."
+        )?;
+        return Ok(1);
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(now, "edit")?;
+    write_edit_state(
+        &repo,
+        &EditState {
+            event_tx_id,
+            commit_oid: commit.get_oid(),
+            head_oid,
+            branch_name: head_info.get_branch_name().map(|name| name.to_string()),
+        },
+    )?;
+    let result = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["checkout", &commit.get_oid().to_string()],
+    )?;
+    if result != 0 {
+        clear_edit_state(&repo)?;
+        return Ok(result);
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Editing {}. Amend it (such as with `git commit --amend`), then run `git branchless edit --continue`.",
+        printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?
+    )?;
+    Ok(0)
+}
+
+/// Finish editing the commit: restack its descendants onto its amended
+/// version, then check out the commit or branch which was checked out when
+/// the edit was started (or its rewritten version).
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn edit_continue(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let EditState {
+        event_tx_id,
+        commit_oid,
+        head_oid,
+        branch_name,
+    } = match read_edit_state(&repo)? {
+        Some(state) => state,
+        None => {
+            writeln!(effects.get_output_stream(), "No edit is in progress.")?;
+            return Ok(1);
+        }
+    };
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let build_options = BuildRebasePlanOptions {
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: true,
+    };
+    let execute_options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id,
+        preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+        force_in_memory: get_move_force_in_memory(&repo)?,
+        force_on_disk: get_move_force_on_disk(&repo)?,
+        strategy_options: Vec::new(),
+        keep_originals: false,
+    };
+    let result = restack_commits(
+        effects,
+        &repo,
+        &conn,
+        git_run_info,
+        &event_log_db,
+        Some(vec![commit_oid]),
+        &build_options,
+        &execute_options,
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+    let result = restack_branches(
+        effects,
+        &repo,
+        &conn,
+        git_run_info,
+        &event_log_db,
+        &execute_options,
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+
+    let target = match branch_name {
+        Some(branch_name)
+            if repo
+                .find_branch(&branch_name, git2::BranchType::Local)?
+                .is_some() =>
+        {
+            branch_name
+        }
+        _ => {
+            let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+            let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+            let main_branch_oid = repo.get_main_branch_oid()?;
+            let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+            let graph = make_graph(
+                effects,
+                &repo,
+                &merge_base_db,
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+                &HeadOid(Some(head_oid)),
+                &MainBranchOid(main_branch_oid),
+                &BranchOids(branch_oid_to_names.keys().copied().collect()),
+                true,
+            )?;
+            match find_rewrite_target(
+                &graph,
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+                head_oid,
+            ) {
+                Some(MaybeZeroOid::NonZero(rewritten_oid)) => rewritten_oid.to_string(),
+                Some(MaybeZeroOid::Zero) | None => head_oid.to_string(),
+            }
+        }
+    };
+    let result = git_run_info.run(effects, Some(event_tx_id), &["checkout", &target])?;
+    if result != 0 {
+        return Ok(result);
+    }
+
+    clear_edit_state(&repo)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Finished editing {}.",
+        commit_oid
+    )?;
+    Ok(0)
+}
@@ -0,0 +1,182 @@
+//! Export a summary of the current stack of commits as Markdown.
+//!
+//! The summary is meant to be pasted into the description of a pull request,
+//! so that reviewers can see the rest of the stack. It's delimited by HTML
+//! comments, which aren't rendered, so that the summary can be found and
+//! replaced as the stack evolves.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::Write;
+
+use tracing::{instrument, warn};
+
+use crate::commands::submit::find_head_stack;
+use crate::core::formatting::Pluralize;
+use crate::core::github::GitHubClient;
+use crate::core::metadata::{extract_diff_number, extract_diff_url};
+use crate::core::submit::Stack;
+use crate::git::{NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// The comment which precedes the exported summary.
+pub const EXPORT_STACK_START_MARKER: &str = "<!-- branchless-stack-start -->";
+
+/// The comment which follows the exported summary.
+pub const EXPORT_STACK_END_MARKER: &str = "<!-- branchless-stack-end -->";
+
+/// Escape the characters in the given text which Markdown would otherwise
+/// interpret, including the `|` which delimits table cells. Example:
+///
+/// ```
+/// # use branchless::commands::export_stack::escape_markdown;
+/// assert_eq!(escape_markdown("Fix `a|b` in [foo]"), "Fix \\`a\\|b\\` in \\[foo\\]");
+/// ```
+pub fn escape_markdown(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' | '~'
+        ) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Find the links to the pull requests for the segments of the stack, keyed by
+/// the last commit of each segment. Failures to query GitHub are logged rather
+/// than returned, since the links are optional.
+fn get_pull_request_links(
+    repo: &Repo,
+    remote_name: &str,
+    stack: &Stack,
+) -> eyre::Result<HashMap<NonZeroOid, String>> {
+    let client = match GitHubClient::new(repo, remote_name)? {
+        Ok(client) => client,
+        Err(message) => {
+            warn!(?message, "Not looking up pull requests on GitHub");
+            return Ok(HashMap::new());
+        }
+    };
+
+    let mut result = HashMap::new();
+    for segment in stack.segments.iter() {
+        let last_commit_oid = match segment.commit_oids.last() {
+            Some(last_commit_oid) => *last_commit_oid,
+            None => continue,
+        };
+        let pull_request = match client.find_pull_request(&segment.branch_name) {
+            Ok(Some(pull_request)) => pull_request,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!(?err, branch_name = ?segment.branch_name, "Could not find pull request");
+                continue;
+            }
+        };
+        let number = pull_request["number"].as_u64();
+        let url = pull_request["html_url"].as_str();
+        if let (Some(number), Some(url)) = (number, url) {
+            result.insert(last_commit_oid, format!("[#{}]({})", number, url));
+        }
+    }
+    Ok(result)
+}
+
+/// Render the given stack as a Markdown table, with one row per commit from
+/// oldest to newest.
+fn render_stack_as_markdown(
+    repo: &Repo,
+    stack: &Stack,
+    pull_request_links: &HashMap<NonZeroOid, String>,
+) -> eyre::Result<String> {
+    let branch_names: HashMap<NonZeroOid, &str> = stack
+        .segments
+        .iter()
+        .filter_map(|segment| {
+            segment
+                .commit_oids
+                .last()
+                .map(|commit_oid| (*commit_oid, segment.branch_name.as_str()))
+        })
+        .collect();
+
+    let mut result = String::new();
+    writeln!(result, "{}", EXPORT_STACK_START_MARKER)?;
+    writeln!(
+        result,
+        "Stack of {} onto `{}`:",
+        Pluralize {
+            amount: stack.commit_oids.len().try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string(),
+        stack.main_branch_name
+    )?;
+    writeln!(result)?;
+    writeln!(result, "| # | Commit | Summary | Branch | Review |")?;
+    writeln!(result, "| --- | --- | --- | --- | --- |")?;
+    for (i, commit_oid) in stack.commit_oids.iter().enumerate() {
+        let commit = repo.find_commit_or_fail(*commit_oid)?;
+        let summary = commit.get_summary()?.to_string_lossy().into_owned();
+        let message = commit.get_message_raw()?.to_string_lossy().into_owned();
+
+        let mut review_links = Vec::new();
+        if let Some(link) = pull_request_links.get(commit_oid) {
+            review_links.push(link.clone());
+        }
+        if let Some(diff_number) = extract_diff_number(&message) {
+            review_links.push(match extract_diff_url(&message) {
+                Some(url) => format!("[{}]({})", diff_number, url),
+                None => diff_number,
+            });
+        }
+
+        writeln!(
+            result,
+            "| {} | `{}` | {} | {} | {} |",
+            i + 1,
+            &commit_oid.to_string()[..8],
+            escape_markdown(&summary),
+            match branch_names.get(commit_oid) {
+                Some(branch_name) => format!("`{}`", branch_name),
+                None => String::new(),
+            },
+            review_links.join(" "),
+        )?;
+    }
+    writeln!(result, "{}", EXPORT_STACK_END_MARKER)?;
+    Ok(result)
+}
+
+/// Print a Markdown summary of the stack of commits ending at `HEAD`, with
+/// links to the pull request or Phabricator revision of each commit where
+/// known. Pull requests are looked up on the GitHub repository of the given
+/// remote.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn export_stack(effects: &Effects, remote_name: &str) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let stack = match find_head_stack(effects, &repo, Some(remote_name), &[])? {
+        Some(stack) if !stack.commit_oids.is_empty() => stack,
+        Some(_) | None => {
+            writeln!(
+                effects.get_error_stream(),
+                "There are no draft commits in the current stack to export."
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let pull_request_links = get_pull_request_links(&repo, remote_name, &stack)?;
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        render_stack_as_markdown(&repo, &stack, &pull_request_links)?
+    )?;
+    Ok(0)
+}
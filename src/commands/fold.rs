@@ -0,0 +1,259 @@
+//! Fold a commit into its parent.
+//!
+//! The commit and its parent are replaced by a single commit which has the
+//! contents of the commit and the parents of its parent, and any descendants of
+//! the two commits are moved onto it. This is the inverse of splitting a
+//! commit.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::config::{
+    get_move_force_in_memory, get_move_force_on_disk, get_restack_preserve_timestamps,
+};
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::printable_styled_string;
+use crate::core::graph::{
+    make_graph, resolve_commits, BranchOids, HeadOid, MainBranchOid, ResolveCommitsResult,
+};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::{
+    execute_rebase_plan, finish_rewrite_in_memory, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, RebasePlanBuilder,
+};
+use crate::git::{CommitSigner, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// Combine the messages of a commit and its parent into the message of the
+/// folded commit, with the parent's message first. Example:
+///
+/// ```
+/// # use branchless::commands::fold::combine_messages;
+/// assert_eq!(
+///     combine_messages("Parent\n\nDetails\n", "Child\n"),
+///     "Parent\n\nDetails\n\nChild\n"
+/// );
+/// ```
+pub fn combine_messages(parent_message: &str, message: &str) -> String {
+    format!(
+        "{}\n\n{}",
+        parent_message.trim_end(),
+        message.trim_start_matches('\n')
+    )
+}
+
+/// Fold the given commit (or `HEAD`) into its parent. The folded commit's
+/// message is `message` if provided, or else the messages of the two commits
+/// combined with `combine_messages`. The descendants of both commits are
+/// rebased onto the folded commit.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn fold(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commit: Option<String>,
+    message: Option<String>,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let head_oid = repo.get_head_info()?.oid;
+    let commit = match commit {
+        Some(commit) => commit,
+        None => match head_oid {
+            Some(head_oid) => head_oid.to_string(),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "No commit was provided, and no OID for HEAD is available as a default"
+                )?;
+                return Ok(1);
+            }
+        },
+    };
+    let commit = match resolve_commits(&repo, vec![commit])? {
+        ResolveCommitsResult::Ok { mut commits } => match commits.pop() {
+            Some(commit) => commit,
+            None => eyre::bail!("Unexpected number of returns values from resolve_commits"),
+        },
+        ResolveCommitsResult::CommitNotFound { commit } => {
+            writeln!(effects.get_output_stream(), "Commit not found: {}", commit)?;
+            return Ok(1);
+        }
+    };
+    let commit_oid = commit.get_oid();
+    let glyphs = effects.get_glyphs();
+    let parent = match commit.get_parent_oids().as_slice() {
+        [parent_oid] => repo.find_commit_or_fail(*parent_oid)?,
+        [] => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot fold {}, since it has no parent.",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?
+            )?;
+            return Ok(1);
+        }
+        _ => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot fold merge commit {} into its parents.",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?
+            )?;
+            return Ok(1);
+        }
+    };
+    let parent_oid = parent.get_oid();
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(Some(commit_oid)),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+    let child_oids: Vec<NonZeroOid> = match (graph.get(&parent_oid), graph.get(&commit_oid)) {
+        (Some(parent_node), Some(node)) if !parent_node.is_main => parent_node
+            .children
+            .iter()
+            .chain(node.children.iter())
+            .copied()
+            .filter(|child_oid| *child_oid != commit_oid)
+            .collect(),
+        _ => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot fold {} into {}, since it is on the main branch.",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?,
+                printable_styled_string(glyphs, parent.friendly_describe()?)?
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "fold")?;
+    let message = match message {
+        Some(message) => message,
+        None => combine_messages(
+            &parent.get_message_raw()?.to_string_lossy(),
+            &commit.get_message_raw()?.to_string_lossy(),
+        ),
+    };
+    let preserve_timestamps = get_restack_preserve_timestamps(&repo)?;
+    let committer = if preserve_timestamps {
+        commit.get_committer()
+    } else {
+        commit.get_committer().update_timestamp(now)?
+    };
+    let grandparents = parent
+        .get_parent_oids()
+        .into_iter()
+        .map(|grandparent_oid| repo.find_commit_or_fail(grandparent_oid))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let tree = commit.get_tree()?;
+    let folded_oid = match CommitSigner::from_repo(&repo)? {
+        Some(signer) => repo.create_signed_commit(
+            &signer,
+            &parent.get_author(),
+            &committer,
+            &message,
+            &tree,
+            grandparents.iter().collect(),
+        )?,
+        None => repo.create_commit(
+            None,
+            &parent.get_author(),
+            &committer,
+            &message,
+            &tree,
+            grandparents.iter().collect(),
+        )?,
+    };
+
+    let execute_options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id,
+        preserve_timestamps,
+        force_in_memory: get_move_force_in_memory(&repo)?,
+        force_on_disk: get_move_force_on_disk(&repo)?,
+        strategy_options: Vec::new(),
+        keep_originals: false,
+    };
+
+    // Move the descendants onto the folded commit before replacing the
+    // original commits with it, so that they're never considered abandoned.
+    if !child_oids.is_empty() {
+        let rebase_plan = {
+            let mut builder = RebasePlanBuilder::new(
+                &repo,
+                &graph,
+                &merge_base_db,
+                &MainBranchOid(main_branch_oid),
+            );
+            for child_oid in child_oids {
+                builder.move_subtree(child_oid, folded_oid)?;
+            }
+            builder.build(
+                effects,
+                &BuildRebasePlanOptions {
+                    dump_rebase_constraints: false,
+                    dump_rebase_plan: false,
+                    detect_duplicate_commits_via_patch_id: true,
+                },
+            )?
+        };
+        let result = match rebase_plan {
+            Ok(None) => 0,
+            Ok(Some(rebase_plan)) => {
+                execute_rebase_plan(effects, git_run_info, &repo, &rebase_plan, &execute_options)?
+            }
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                1
+            }
+        };
+        if result != 0 {
+            return Ok(result);
+        }
+    }
+
+    let new_head_oid = repo.get_head_info()?.oid.map(|head_oid| {
+        if head_oid == commit_oid || head_oid == parent_oid {
+            folded_oid
+        } else {
+            head_oid
+        }
+    });
+    let result = finish_rewrite_in_memory(
+        effects,
+        git_run_info,
+        &repo,
+        &[
+            (parent_oid, MaybeZeroOid::NonZero(folded_oid)),
+            (commit_oid, MaybeZeroOid::NonZero(folded_oid)),
+        ],
+        new_head_oid,
+        &execute_options,
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Folded {} into {}.",
+        printable_styled_string(glyphs, commit.friendly_describe()?)?,
+        printable_styled_string(glyphs, parent.friendly_describe()?)?
+    )?;
+    Ok(0)
+}
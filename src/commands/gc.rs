@@ -8,18 +8,38 @@
 //! This module is responsible for adding extra references to Git, so that Git's
 //! garbage collection doesn't collect commits which branchless thinks are still
 //! visible.
+//!
+//! Commits which were hidden recently are also kept alive for a while, so that
+//! they can still be restored with `git undo`. The event log is snapshotted
+//! into the repository as well, so that the branchless state of the repository
+//! survives being copied with `git clone --mirror`: `git branchless init`
+//! imports the snapshot into the copy's event log. Transactions older than
+//! `branchless.eventLog.retention` are pruned from the event log beforehand.
+//! If `branchless.commitGraph.write` is set, Git's commit-graph file is
+//! rewritten afterwards.
 
 use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::convert::TryInto;
 use std::ffi::OsStr;
 use std::fmt::Write;
+use std::time::{Duration, SystemTime};
 
 use eyre::Context;
 use tracing::instrument;
 
-use crate::core::eventlog::{is_gc_ref, EventLogDb, EventReplayer};
+use crate::commands::sync_events::snapshot_event_log;
+use crate::core::config::{
+    get_commit_graph_write, get_event_log_retention, get_gc_keep_hidden_days,
+};
+use crate::core::eventlog::{
+    is_gc_ref, is_keep_ref, CommitVisibility, EventLogDb, EventReplayer, EventTransactionId,
+    KEEP_REF_PREFIX,
+};
+use crate::core::formatting::Pluralize;
 use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
 use crate::core::mergebase::make_merge_base_db;
-use crate::git::{NonZeroOid, Reference, Repo};
+use crate::git::{GitRunInfo, NonZeroOid, Reference, Repo};
 use crate::tui::Effects;
 
 fn find_dangling_references<'repo>(
@@ -68,14 +88,132 @@ pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Resul
     Ok(())
 }
 
+/// Update the references under `refs/branchless/keep/` so that they point to
+/// exactly the commits which were hidden within the given duration.
+#[instrument]
+fn update_keep_references(
+    now: SystemTime,
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    keep_duration: Duration,
+) -> eyre::Result<()> {
+    let cursor = event_replayer.make_default_cursor();
+    let mut commit_oids_to_keep = HashSet::new();
+    for oid in event_replayer.get_cursor_active_oids(cursor) {
+        if !matches!(
+            event_replayer.get_cursor_commit_visibility(cursor, oid),
+            Some(CommitVisibility::Hidden)
+        ) {
+            continue;
+        }
+        let is_recent = match event_replayer.get_cursor_commit_latest_event(cursor, oid) {
+            Some(event) => match now.duration_since(event.get_timestamp()) {
+                Ok(age) => age <= keep_duration,
+                // The event happened in the future, maybe due to clock skew.
+                Err(_) => true,
+            },
+            None => false,
+        };
+        if is_recent && repo.find_commit(oid)?.is_some() {
+            commit_oids_to_keep.insert(oid);
+        }
+    }
+
+    for mut reference in repo.get_references_matching(&format!("{}*", KEEP_REF_PREFIX))? {
+        let is_kept = match reference.peel_to_commit()? {
+            Some(commit) => commit_oids_to_keep.remove(&commit.get_oid()),
+            None => false,
+        };
+        if !is_kept {
+            reference
+                .delete()
+                .wrap_err_with(|| format!("Deleting reference {:?}", reference.get_name()))?;
+        }
+    }
+
+    for commit_oid in commit_oids_to_keep {
+        let ref_name = format!("{}{}", KEEP_REF_PREFIX, commit_oid);
+        repo.create_reference(
+            OsStr::new(&ref_name),
+            commit_oid,
+            true,
+            "branchless: keeping recently-hidden commit",
+        )
+        .wrap_err_with(|| format!("Creating reference {}", ref_name))?;
+    }
+    Ok(())
+}
+
+/// Get the earliest transaction which can't be pruned from the event log,
+/// since it contains the latest event for a draft commit in the smartlog, or
+/// for a hidden commit which is still kept alive by one of our references.
+/// Pruning any later transaction would change how those commits are
+/// displayed, or make it impossible to restore them with `git undo`.
+///
+/// Returns: The transaction, or `None` if every transaction can be pruned.
+#[instrument]
+fn get_earliest_protected_transaction(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    graph: &CommitGraph,
+) -> eyre::Result<Option<EventTransactionId>> {
+    let mut protected_oids: HashSet<NonZeroOid> = graph
+        .values()
+        .filter(|node| !node.is_main)
+        .map(|node| node.commit.get_oid())
+        .collect();
+    for reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        if is_gc_ref(&reference_name) || is_keep_ref(&reference_name) {
+            if let Some(commit) = reference.peel_to_commit()? {
+                protected_oids.insert(commit.get_oid());
+            }
+        }
+    }
+
+    let cursor = event_replayer.make_default_cursor();
+    let result = protected_oids
+        .into_iter()
+        .filter_map(|oid| event_replayer.get_cursor_commit_latest_event(cursor, oid))
+        .map(|event| event.get_event_tx_id())
+        .min();
+    Ok(result)
+}
+
+/// Rewrite Git's commit-graph file for all reachable commits, if enabled with
+/// `branchless.commitGraph.write`. Failing to write it only produces a
+/// warning, since the commit-graph is just a cache.
+#[instrument]
+pub fn write_commit_graph(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<()> {
+    if !get_commit_graph_write(repo)? {
+        return Ok(());
+    }
+
+    let exit_code = git_run_info.run(effects, None, &["commit-graph", "write", "--reachable"])?;
+    if exit_code != 0 {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: failed to write the commit-graph (exit code {})",
+            exit_code
+        )?;
+    }
+    Ok(())
+}
+
 /// Run branchless's garbage collection.
 ///
-/// Frees any references to commits which are no longer visible in the smartlog.
+/// Frees any references to commits which are no longer visible in the smartlog,
+/// except for those which were hidden recently, and snapshots the event log.
 #[instrument]
-pub fn gc(effects: &Effects) -> eyre::Result<()> {
+pub fn gc(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
+    let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
-    let event_log_db = EventLogDb::new(&conn)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
     let head_oid = repo.get_head_info()?.oid;
@@ -104,5 +242,35 @@ pub fn gc(effects: &Effects) -> eyre::Result<()> {
             .delete()
             .wrap_err_with(|| format!("Deleting reference {:?}", reference.get_name()))?;
     }
+
+    let keep_hidden_days: u64 = get_gc_keep_hidden_days(&repo)?.max(0).try_into()?;
+    update_keep_references(
+        now,
+        &repo,
+        &event_replayer,
+        Duration::from_secs(keep_hidden_days * 24 * 60 * 60),
+    )?;
+
+    if let Some(retention) = get_event_log_retention(&repo)? {
+        let cutoff = now.checked_sub(retention).unwrap_or(SystemTime::UNIX_EPOCH);
+        let earliest_protected_transaction =
+            get_earliest_protected_transaction(&repo, &event_replayer, &graph)?;
+        let num_pruned_events =
+            event_log_db.prune_transactions(cutoff, earliest_protected_transaction)?;
+        if num_pruned_events > 0 {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: pruned {} from the event log",
+                Pluralize {
+                    amount: num_pruned_events.try_into()?,
+                    singular: "event",
+                    plural: "events",
+                }
+                .to_string()
+            )?;
+        }
+    }
+    snapshot_event_log(now, &repo, &event_log_db)?;
+    write_commit_graph(effects, git_run_info, &repo)?;
     Ok(())
 }
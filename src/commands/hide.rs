@@ -2,14 +2,17 @@
 //! automatically as the result of a rewrite operation).
 
 use std::collections::HashSet;
+use std::convert::TryInto;
 use std::fmt::Write;
+use std::io::{stdin, BufRead, BufReader, Read};
 use std::time::SystemTime;
 
 use tracing::instrument;
 
+use crate::core::config::get_hide_confirm_threshold;
 use crate::core::eventlog::{CommitVisibility, Event};
 use crate::core::eventlog::{EventLogDb, EventReplayer};
-use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
 use crate::core::graph::{
     make_graph, resolve_commits, BranchOids, CommitGraph, HeadOid, MainBranchOid, Node,
     ResolveCommitsResult,
@@ -79,11 +82,72 @@ fn recurse_on_commits<'repo, F: Fn(&Node) -> bool>(
     Ok(result)
 }
 
+/// The number of commits to list when asking for confirmation to hide more
+/// commits than the confirmation threshold.
+const NUM_CONFIRM_SAMPLE_COMMITS: usize = 10;
+
+/// Ask the user to confirm hiding the given commits, listing some of them.
+///
+/// Returns: Whether the user confirmed.
+fn confirm_hide_commits(
+    in_: &mut impl Read,
+    effects: &Effects,
+    glyphs: &Glyphs,
+    commits: &[Commit],
+) -> eyre::Result<bool> {
+    writeln!(
+        effects.get_output_stream(),
+        "This will hide {}:",
+        Pluralize {
+            amount: commits.len().try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string()
+    )?;
+    for commit in commits.iter().take(NUM_CONFIRM_SAMPLE_COMMITS) {
+        writeln!(
+            effects.get_output_stream(),
+            "  {}",
+            printable_styled_string(glyphs, commit.friendly_describe()?)?
+        )?;
+    }
+    if commits.len() > NUM_CONFIRM_SAMPLE_COMMITS {
+        writeln!(
+            effects.get_output_stream(),
+            "  ...and {} more",
+            commits.len() - NUM_CONFIRM_SAMPLE_COMMITS
+        )?;
+    }
+
+    write!(effects.get_output_stream(), "Confirm? [yN] ")?;
+    let mut user_input = String::new();
+    let mut reader = BufReader::new(in_);
+    let confirmed = match reader.read_line(&mut user_input) {
+        Ok(_size) => {
+            let user_input = user_input.trim();
+            user_input == "y" || user_input == "Y"
+        }
+        Err(_) => false,
+    };
+    Ok(confirmed)
+}
+
 /// Hide the hashes provided on the command-line.
+///
+/// If `recursive` is set and more commits would be hidden than
+/// `branchless.hide.confirmThreshold`, the user is asked for confirmation
+/// first, unless `no_confirm` is set.
 #[instrument]
-pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Result<isize> {
+pub fn hide(
+    effects: &Effects,
+    hashes: Vec<String>,
+    recursive: bool,
+    reason: Option<String>,
+    no_confirm: bool,
+) -> eyre::Result<isize> {
     let now = SystemTime::now();
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
@@ -111,6 +175,17 @@ pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Re
         commits
     };
 
+    let confirm_threshold = get_hide_confirm_threshold(&repo)?;
+    if recursive
+        && !no_confirm
+        && confirm_threshold > 0
+        && commits.len() > confirm_threshold
+        && !confirm_hide_commits(&mut stdin(), effects, glyphs, &commits)?
+    {
+        writeln!(effects.get_output_stream(), "Aborted.")?;
+        return Ok(1);
+    }
+
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
     let events = commits
@@ -119,6 +194,7 @@ pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Re
             timestamp,
             event_tx_id,
             commit_oid: commit.get_oid(),
+            reason: reason.clone(),
         })
         .collect();
     event_log_db.add_events(events)?;
@@ -128,7 +204,7 @@ pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Re
         writeln!(
             effects.get_output_stream(),
             "Hid commit: {}",
-            printable_styled_string(&glyphs, commit.friendly_describe()?)?
+            printable_styled_string(glyphs, commit.friendly_describe()?)?
         )?;
         if let Some(CommitVisibility::Hidden) =
             event_replayer.get_cursor_commit_visibility(cursor, commit.get_oid())
@@ -144,7 +220,7 @@ pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Re
         writeln!(
             effects.get_output_stream(),
             "To unhide this commit, run: git unhide {}",
-            printable_styled_string(&glyphs, commit_target_oid)?
+            printable_styled_string(glyphs, commit_target_oid)?
         )?;
     }
 
@@ -155,7 +231,7 @@ pub fn hide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Re
 #[instrument]
 pub fn unhide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::Result<isize> {
     let now = SystemTime::now();
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
@@ -200,7 +276,7 @@ pub fn unhide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::
         writeln!(
             effects.get_output_stream(),
             "Unhid commit: {}",
-            printable_styled_string(&glyphs, commit.friendly_describe()?)?,
+            printable_styled_string(glyphs, commit.friendly_describe()?)?,
         )?;
         if let Some(CommitVisibility::Visible) =
             event_replayer.get_cursor_commit_visibility(cursor, commit.get_oid())
@@ -216,7 +292,7 @@ pub fn unhide(effects: &Effects, hashes: Vec<String>, recursive: bool) -> eyre::
         writeln!(
             effects.get_output_stream(),
             "To hide this commit, run: git hide {}",
-            printable_styled_string(&glyphs, commit_target_oid)?
+            printable_styled_string(glyphs, commit_target_oid)?
         )?;
     }
 
@@ -7,8 +7,9 @@
 //! The hooks are installed by the `branchless init` command. This module
 //! contains the implementations for the hooks.
 
+use std::collections::HashSet;
 use std::convert::TryInto;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::io::{stdin, BufRead, Cursor};
 use std::time::SystemTime;
@@ -18,14 +19,21 @@ use itertools::Itertools;
 use os_str_bytes::OsStringBytes;
 use tracing::{error, instrument, warn};
 
-use crate::commands::gc::mark_commit_reachable;
-use crate::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb, EventTransactionId};
-use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
-use crate::git::{CategorizedReferenceName, MaybeZeroOid, Repo};
+use crate::commands::gc::{gc, mark_commit_reachable};
+use crate::core::eventlog::{
+    record_stash_changes, should_ignore_ref_updates, CommitVisibility, Event, EventLogDb,
+    EventReplayer, EventTransactionId, IgnoredRefPatterns, STASH_REF_NAME,
+};
+use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
+use crate::core::config::{get_hook_verbosity, HookVerbosity};
+use crate::core::rewrite::hooks::get_hook_effects;
 pub use crate::core::rewrite::hooks::{
-    hook_drop_commit_if_empty, hook_post_rewrite, hook_register_extra_post_rewrite_hook,
-    hook_skip_upstream_applied_commit,
+    hook_drop_commit_if_empty, hook_post_rewrite, hook_record_replaced_commit,
+    hook_register_extra_post_rewrite_hook, hook_skip_upstream_applied_commit,
 };
 use crate::tui::Effects;
 
@@ -45,12 +53,13 @@ pub fn hook_post_checkout(
 
     let now = SystemTime::now();
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?;
+    let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
     writeln!(
         effects.get_output_stream(),
         "branchless: processing checkout"
     )?;
 
-    let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-checkout")?;
@@ -62,16 +71,28 @@ pub fn hook_post_checkout(
             let oid: MaybeZeroOid = current_head_oid.parse()?;
             oid
         },
-        ref_name: OsString::from("HEAD"),
+        ref_name: repo.get_head_ref_name(),
         message: None,
     }])?;
     Ok(())
 }
 
+/// Handle Git's `pre-auto-gc` hook, by collecting garbage in the event log as
+/// well.
+///
+/// See the man-page for `githooks(5)`.
+#[instrument]
+pub fn hook_pre_auto_gc(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
+    gc(effects, git_run_info)
+}
+
 fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<()> {
     let now = SystemTime::now();
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
 
@@ -103,7 +124,7 @@ fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<(
     writeln!(
         effects.get_output_stream(),
         "branchless: processed commit: {}",
-        printable_styled_string(&glyphs, commit.friendly_describe()?)?,
+        printable_styled_string(glyphs, commit.friendly_describe()?)?,
     )?;
 
     Ok(())
@@ -174,6 +195,154 @@ fn parse_reference_transaction_line(
     }
 }
 
+/// Find the commits which appear in the smartlog, but which were removed from
+/// the given remote branch by a non-fast-forward update (such as when a
+/// collaborator force-pushes a rewritten version of the branch). The update is
+/// assumed to not be a fast-forward.
+#[instrument]
+fn find_commits_rewritten_upstream(
+    effects: &Effects,
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    ref_name: &OsStr,
+    old_oid: NonZeroOid,
+    new_oid: NonZeroOid,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let conn = repo.get_db_conn()?;
+    let merge_base_db = make_merge_base_db(effects, repo, &conn, event_replayer)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        repo,
+        &merge_base_db,
+        event_replayer,
+        event_cursor,
+        &HeadOid(repo.get_head_info()?.oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut result = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![old_oid];
+    while let Some(oid) = queue.pop() {
+        if !visited.insert(oid) {
+            continue;
+        }
+
+        // Stop once we reach commits which are still part of the remote
+        // branch, or which are part of the main branch.
+        if repo.find_merge_base(oid, new_oid)? == Some(oid)
+            || repo.find_merge_base(oid, main_branch_oid)? == Some(oid)
+        {
+            continue;
+        }
+
+        let is_shown = matches!(graph.get(&oid), Some(node) if !node.is_main);
+        let is_hidden = matches!(
+            event_replayer.get_cursor_commit_visibility(event_cursor, oid),
+            Some(CommitVisibility::Hidden)
+        );
+        if is_shown && !is_hidden {
+            result.push(oid);
+        }
+
+        match repo.find_commit(oid)? {
+            Some(commit) => queue.extend(commit.get_parent_oids()),
+            None => warn!(?oid, ?ref_name, "Commit from remote branch not found"),
+        }
+    }
+
+    result.sort_unstable();
+    Ok(result)
+}
+
+/// Mark commits which were rewritten upstream as hidden, so that stale
+/// copies of a collaborator's commits don't linger in the smartlog.
+///
+/// Returns: The events which hide the affected commits.
+#[instrument]
+fn hide_commits_rewritten_upstream(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    ref_update_events: &[Event],
+) -> eyre::Result<Vec<Event>> {
+    let mut remote_branch_updates = Vec::new();
+    for event in ref_update_events {
+        if let Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid: MaybeZeroOid::NonZero(old_oid),
+            new_oid: MaybeZeroOid::NonZero(new_oid),
+            message: _,
+        } = event
+        {
+            let categorized_ref_name = CategorizedReferenceName::new(ref_name);
+            let is_remote_branch = matches!(
+                categorized_ref_name,
+                CategorizedReferenceName::RemoteBranch { .. }
+            );
+            // Fast-forward updates don't rewrite anything.
+            if is_remote_branch && repo.find_merge_base(*old_oid, *new_oid)? != Some(*old_oid) {
+                remote_branch_updates.push((
+                    timestamp,
+                    event_tx_id,
+                    ref_name,
+                    categorized_ref_name,
+                    old_oid,
+                    new_oid,
+                ));
+            }
+        }
+    }
+    if remote_branch_updates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let mut result = Vec::new();
+    for (timestamp, event_tx_id, ref_name, categorized_ref_name, old_oid, new_oid) in
+        remote_branch_updates
+    {
+        let commit_oids = find_commits_rewritten_upstream(
+            effects,
+            repo,
+            &event_replayer,
+            ref_name,
+            *old_oid,
+            *new_oid,
+        )?;
+        if commit_oids.is_empty() {
+            continue;
+        }
+
+        let branch_name = categorized_ref_name.render_suffix();
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: hiding {} rewritten upstream in {}",
+            Pluralize {
+                amount: commit_oids.len().try_into()?,
+                singular: "commit",
+                plural: "commits",
+            }
+            .to_string(),
+            branch_name
+        )?;
+        result.extend(commit_oids.into_iter().map(|commit_oid| Event::HideEvent {
+            timestamp: *timestamp,
+            event_tx_id: *event_tx_id,
+            commit_oid,
+            reason: Some(format!("rewritten upstream in {}", branch_name)),
+        }));
+    }
+    Ok(result)
+}
+
 /// Handle Git's `reference-transaction` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -185,11 +354,19 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
     let now = SystemTime::now();
 
     let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "reference-transaction")?;
+    let ignored_ref_patterns = IgnoredRefPatterns::from_repo(&repo)?;
+    // Git reports updates to the `HEAD` of the current worktree as `HEAD`, so
+    // record which worktree it belongs to.
+    let head_ref_name = repo.get_head_ref_name();
+    // Updates to the stash are recorded by comparing its reflog with the last
+    // snapshot of it, since Git doesn't report every change to it here.
+    let mut stash_updates: Vec<(MaybeZeroOid, MaybeZeroOid)> = Vec::new();
 
-    let events: Vec<Event> = stdin()
+    let mut events: Vec<Event> = stdin()
         .lock()
         .split(b'\n')
         .filter_map(|line| {
@@ -198,6 +375,37 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
                 Err(_) => return None,
             };
             match parse_reference_transaction_line(line.as_slice(), now, event_tx_id) {
+                Ok(Some(Event::RefUpdateEvent { ref_name, .. }))
+                    if ignored_ref_patterns.matches(&ref_name) =>
+                {
+                    None
+                }
+                Ok(Some(Event::RefUpdateEvent {
+                    timestamp,
+                    event_tx_id,
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    message,
+                })) if ref_name == "HEAD" => Some(Event::RefUpdateEvent {
+                    timestamp,
+                    event_tx_id,
+                    ref_name: head_ref_name.clone(),
+                    old_oid,
+                    new_oid,
+                    message,
+                }),
+                Ok(Some(Event::RefUpdateEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    message: _,
+                })) if ref_name == STASH_REF_NAME => {
+                    stash_updates.push((old_oid, new_oid));
+                    None
+                }
                 Ok(event) => event,
                 Err(err) => {
                     error!(?err, "Could not parse reference-transaction-line");
@@ -206,6 +414,31 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
             }
         })
         .collect();
+    if !stash_updates.is_empty() {
+        // If there's no snapshot of the stash yet, assume that this
+        // transaction made the only changes to it.
+        let mut old_stash_oids: Vec<NonZeroOid> = repo
+            .get_stash_oids()?
+            .into_iter()
+            .filter(|oid| {
+                !stash_updates
+                    .iter()
+                    .any(|(_old_oid, new_oid)| *new_oid == MaybeZeroOid::NonZero(*oid))
+            })
+            .collect();
+        for (old_oid, new_oid) in stash_updates {
+            if let (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::Zero) = (old_oid, new_oid) {
+                old_stash_oids.push(old_oid);
+            }
+        }
+        events.extend(record_stash_changes(
+            &repo,
+            &event_log_db,
+            now,
+            event_tx_id,
+            &old_stash_oids,
+        )?);
+    }
     if events.is_empty() {
         return Ok(());
     }
@@ -226,10 +459,14 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
                     Event::RefUpdateEvent { ref_name, .. } => {
                         Some(CategorizedReferenceName::new(ref_name).friendly_describe())
                     }
+                    Event::StashPushEvent { .. } | Event::StashDropEvent { .. } => {
+                        Some("stash".to_string())
+                    }
                     Event::RewriteEvent { .. }
                     | Event::CommitEvent { .. }
                     | Event::HideEvent { .. }
-                    | Event::UnhideEvent { .. } => None,
+                    | Event::UnhideEvent { .. }
+                    | Event::CopyEvent { .. } => None,
                 }
             })
             .map(|description| format!("{}", console::style(description).green()))
@@ -237,7 +474,9 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
             .collect::<Vec<_>>()
             .join(", ")
     )?;
+    let hide_events = hide_commits_rewritten_upstream(effects, &repo, &event_log_db, &events)?;
     event_log_db.add_events(events)?;
+    event_log_db.add_events(hide_events)?;
 
     Ok(())
 }
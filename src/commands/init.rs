@@ -1,15 +1,24 @@
 //! Install any hooks, aliases, etc. to set up `git-branchless` in this repo.
+//!
+//! With `--global`, the hooks are instead installed into the template
+//! directory which Git copies into every repository created by `git init` or
+//! `git clone`, and the aliases are installed into the global config, so that
+//! new repositories are set up automatically.
 
+use std::convert::TryInto;
 use std::fmt::Write;
 use std::io::{stdin, stdout, BufRead, BufReader, Write as WriteIo};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use console::style;
 use eyre::Context;
 use tracing::{instrument, warn};
 
-use crate::core::config::get_core_hooks_path;
-use crate::git::{Config, ConfigValue, GitRunInfo, GitVersion, Repo};
+use crate::commands::sync_events::restore_event_log_snapshot;
+use crate::core::config::{get_core_hooks_path, get_init_template_dir};
+use crate::core::eventlog::{EventLogDb, EVENT_LOG_SYNC_REF_NAME};
+use crate::core::formatting::Pluralize;
+use crate::git::{Config, ConfigLevel, ConfigValue, GitRunInfo, GitVersion, Repo};
 use crate::tui::Effects;
 
 const ALL_HOOKS: &[(&str, &str)] = &[
@@ -67,6 +76,7 @@ const ALL_ALIASES: &[(&str, &str)] = &[
     ("restack", "restack"),
     ("undo", "undo"),
     ("move", "move"),
+    ("sync", "sync"),
 ];
 
 #[derive(Debug)]
@@ -177,6 +187,103 @@ fn install_hook(repo: &Repo, hook_type: &str, hook_script: &str) -> eyre::Result
     Ok(())
 }
 
+/// The installation status of a hook managed by `git-branchless`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookStatus {
+    /// The hook script exists and invokes `git-branchless`.
+    Installed,
+
+    /// There is no hook script at the expected path.
+    Missing,
+
+    /// The hook script has a section managed by `git-branchless`, but that
+    /// section doesn't invoke `git-branchless` (such as after running `git
+    /// branchless init --uninstall`).
+    Uninstalled,
+
+    /// The hook script exists, but it was not written by `git-branchless`
+    /// (such as if another tool overwrote it).
+    Overridden,
+}
+
+/// Determine the status of the given hook, whose script is expected to be at
+/// `path`.
+fn get_hook_status_at_path(
+    path: &Path,
+    is_multi_hook: bool,
+    hook_type: &str,
+) -> eyre::Result<HookStatus> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(HookStatus::Missing)
+        }
+        Err(err) => {
+            return Err(eyre::eyre!(err)).wrap_err_with(|| format!("Reading hook {:?}", path))
+        }
+    };
+
+    let status = if contents.contains(&format!("git branchless hook-{}", hook_type)) {
+        HookStatus::Installed
+    } else if is_multi_hook || contents.lines().any(|line| line == UPDATE_MARKER_START) {
+        HookStatus::Uninstalled
+    } else {
+        HookStatus::Overridden
+    };
+    Ok(status)
+}
+
+/// Determine whether the given hook is installed.
+///
+/// Returns: The path at which the hook script is expected to be, along with
+/// its status.
+#[instrument]
+pub fn get_hook_status(repo: &Repo, hook_type: &str) -> eyre::Result<(PathBuf, HookStatus)> {
+    let hook = determine_hook_path(repo, hook_type)?;
+    let (path, is_multi_hook) = match hook {
+        Hook::RegularHook { path } => (path, false),
+        Hook::MultiHook { path } => (path, true),
+    };
+    let status = get_hook_status_at_path(&path, is_multi_hook, hook_type)?;
+    Ok((path, status))
+}
+
+/// Determine whether the hooks managed by `git-branchless` are installed in
+/// the template directory set by `init.templateDir`, as done by `git
+/// branchless init --global`. If so, repositories created from now on are set
+/// up automatically.
+#[instrument]
+pub fn is_installed_in_template_dir(repo: &Repo) -> eyre::Result<bool> {
+    let template_dir = match get_init_template_dir(repo)? {
+        Some(template_dir) => template_dir,
+        None => return Ok(false),
+    };
+    for hook_type in get_all_hook_types() {
+        let path = template_dir.join("hooks").join(hook_type);
+        if get_hook_status_at_path(&path, false, hook_type)? != HookStatus::Installed {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Install the given hook, whose name must be one of the hooks managed by
+/// `git-branchless`. Existing contents of the hook script outside of the
+/// section managed by `git-branchless` are preserved.
+#[instrument]
+pub fn reinstall_hook(repo: &Repo, hook_type: &str) -> eyre::Result<()> {
+    let hook_script = match ALL_HOOKS.iter().find(|(name, _script)| *name == hook_type) {
+        Some((_name, hook_script)) => hook_script,
+        None => eyre::bail!("Not a hook managed by git-branchless: {}", hook_type),
+    };
+    install_hook(repo, hook_type, hook_script)
+}
+
+/// Get the names of all hooks installed by `git-branchless`.
+pub fn get_all_hook_types() -> impl Iterator<Item = &'static str> {
+    ALL_HOOKS.iter().map(|(hook_type, _hook_script)| *hook_type)
+}
+
 #[instrument]
 fn install_hooks(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
     for (hook_type, hook_script) in ALL_HOOKS {
@@ -216,8 +323,9 @@ fn install_alias(config: &mut Config, from: &str, to: &str) -> eyre::Result<()>
     Ok(())
 }
 
+/// Guess the name of the main branch by looking for commonly-used branch names.
 #[instrument]
-fn detect_main_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
+pub fn detect_main_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
     for branch_name in [
         "master",
         "main",
@@ -392,13 +500,35 @@ fn unset_configs(effects: &Effects, config: &mut Config) -> eyre::Result<()> {
     Ok(())
 }
 
+#[instrument]
+fn restore_event_log(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let num_events = restore_event_log_snapshot(repo, &mut event_log_db)?;
+    if num_events > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Restored {} from the snapshot in {}",
+            Pluralize {
+                amount: num_events.try_into()?,
+                singular: "event",
+                plural: "events",
+            }
+            .to_string(),
+            EVENT_LOG_SYNC_REF_NAME
+        )?;
+    }
+    Ok(())
+}
+
 /// Initialize `git-branchless` in the current repo.
 #[instrument]
 pub fn init(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
     let mut in_ = BufReader::new(stdin());
     let mut repo = Repo::from_current_dir()?;
-    let mut config = repo.get_config()?;
+    let mut config = repo.get_config_at_level(ConfigLevel::Local)?;
     set_configs(&mut in_, effects, &repo, &mut config)?;
+    restore_event_log(effects, &repo)?;
     install_hooks(effects, &repo)?;
     install_aliases(effects, &mut repo, &mut config, git_run_info)?;
     writeln!(
@@ -420,13 +550,168 @@ pub fn init(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
 #[instrument]
 pub fn uninstall(effects: &Effects) -> eyre::Result<()> {
     let repo = Repo::from_current_dir()?;
-    let mut config = repo.get_config().wrap_err_with(|| "Getting repo config")?;
+    let mut config = repo
+        .get_config_at_level(ConfigLevel::Local)
+        .wrap_err_with(|| "Getting repo config")?;
     unset_configs(effects, &mut config)?;
     uninstall_hooks(effects, &repo)?;
     uninstall_aliases(effects, &mut config)?;
     Ok(())
 }
 
+/// Determine the template directory to install the hooks into for `git
+/// branchless init --global`. This is the directory already set by
+/// `init.templateDir` in the global config, if any, and otherwise
+/// `~/.config/git-branchless/template`.
+///
+/// Returns: The template directory, and whether `init.templateDir` needs to be
+/// set to it.
+#[instrument]
+fn determine_global_template_dir(config: &Config) -> eyre::Result<(PathBuf, bool)> {
+    if let Some(template_dir) = config.get::<PathBuf, _>("init.templateDir")? {
+        return Ok((template_dir, false));
+    }
+    let home_dir = match std::env::var_os("HOME") {
+        Some(home_dir) => PathBuf::from(home_dir),
+        None => eyre::bail!(
+            "Could not determine the home directory, so `init.templateDir` must be set to install git-branchless globally"
+        ),
+    };
+    let template_dir = home_dir
+        .join(".config")
+        .join("git-branchless")
+        .join("template");
+    Ok((template_dir, true))
+}
+
+/// Initialize `git-branchless` for every repository created from now on, by
+/// installing the hooks into the template directory set by `init.templateDir`
+/// and the aliases into the global config. Existing repositories are not
+/// affected; run `git branchless init` in them, or `git branchless doctor` to
+/// check whether they need it.
+#[instrument]
+pub fn init_global(effects: &Effects) -> eyre::Result<()> {
+    let mut config = Config::open_global()?;
+    let (template_dir, should_set_template_dir) = determine_global_template_dir(&config)?;
+    if should_set_template_dir {
+        writeln!(
+            effects.get_output_stream(),
+            "Setting config (global): init.templateDir = {}",
+            template_dir.to_string_lossy()
+        )?;
+        config.set(
+            "init.templateDir",
+            template_dir.to_string_lossy().into_owned(),
+        )?;
+    }
+
+    for (hook_type, hook_script) in ALL_HOOKS {
+        writeln!(
+            effects.get_output_stream(),
+            "Installing hook (template): {}",
+            hook_type
+        )?;
+        let path = template_dir.join("hooks").join(hook_type);
+        update_hook_contents(&Hook::RegularHook { path }, hook_script)?;
+    }
+
+    for (from, to) in ALL_ALIASES {
+        writeln!(
+            effects.get_output_stream(),
+            "Installing alias (global): git {} -> git branchless {}",
+            from,
+            to
+        )?;
+        install_alias(&mut config, from, to)?;
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Setting config (global): advice.detachedHead = false"
+    )?;
+    config.set("advice.detachedHead", false)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        console::style("Successfully installed git-branchless globally.")
+            .green()
+            .bold()
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Repositories created with `git init` or `git clone` from now on will be set up automatically."
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Their main branch is assumed to be `master` unless `branchless.core.mainBranch` is set."
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "To set up an existing repository, run: {}",
+        console::style("git branchless init").bold()
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "To uninstall, run: {}",
+        console::style("git branchless init --global --uninstall").bold()
+    )?;
+    Ok(())
+}
+
+/// Undo `init_global`. The hooks in the template directory are replaced with
+/// ones which don't invoke `git-branchless`, like those of an uninstalled
+/// repository. Repositories which were already created are not affected.
+#[instrument]
+pub fn uninstall_global(effects: &Effects) -> eyre::Result<()> {
+    let mut config = Config::open_global()?;
+    if config.get::<String, _>("advice.detachedHead")?.is_some() {
+        writeln!(
+            effects.get_output_stream(),
+            "Unsetting config (global): advice.detachedHead"
+        )?;
+        config
+            .remove("advice.detachedHead")
+            .wrap_err_with(|| "Unsetting config advice.detachedHead")?;
+    }
+
+    if let Some(template_dir) = config.get::<PathBuf, _>("init.templateDir")? {
+        for (hook_type, _hook_script) in ALL_HOOKS {
+            let path = template_dir.join("hooks").join(hook_type);
+            if get_hook_status_at_path(&path, false, hook_type)? == HookStatus::Missing {
+                continue;
+            }
+            writeln!(
+                effects.get_output_stream(),
+                "Uninstalling hook (template): {}",
+                hook_type
+            )?;
+            update_hook_contents(
+                &Hook::RegularHook { path },
+                r#"
+# This hook has been uninstalled.
+# Run `git branchless init --global` to reinstall.
+"#,
+            )?;
+        }
+    }
+
+    for (from, _to) in ALL_ALIASES {
+        let key = format!("alias.{}", from);
+        if config.get::<String, _>(&key)?.is_some() {
+            writeln!(
+                effects.get_output_stream(),
+                "Uninstalling alias (global): git {}",
+                from
+            )?;
+            config
+                .remove(&key)
+                .wrap_err_with(|| format!("Uninstalling alias {}", from))?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::{update_between_lines, UPDATE_MARKER_END, UPDATE_MARKER_START};
@@ -0,0 +1,158 @@
+//! Generate documentation for `git-branchless` and its subcommands from the
+//! command-line definitions, so that it can't go out of date.
+//!
+//! Each page is either the long-form `--help` text of the command, or the same
+//! text wrapped in a troff man page, which packagers can install into
+//! `man1`.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use eyre::Context;
+use structopt::clap::App;
+use tracing::instrument;
+
+use crate::tui::Effects;
+
+/// The name of the program, as used in the man page titles.
+const PROGRAM_NAME: &str = "git-branchless";
+
+/// Get the long-form `--help` text for the given command.
+fn render_help_text(app: &mut App) -> eyre::Result<String> {
+    let mut help = Vec::new();
+    app.write_long_help(&mut help)
+        .map_err(|err| eyre::eyre!("Rendering help text: {}", err))?;
+    let mut help = String::from_utf8(help).wrap_err("Decoding help text")?;
+    if !help.ends_with('\n') {
+        help.push('\n');
+    }
+    Ok(help)
+}
+
+/// Escape text so that troff renders it literally.
+fn escape_troff(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}\n", line)
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect()
+}
+
+/// Render the man page for the given command, where `page_name` is the
+/// name of the page without the section, such as `git-branchless-smartlog`.
+fn render_man_page(page_name: &str, app: &mut App) -> eyre::Result<String> {
+    let about = app
+        .p
+        .meta
+        .about
+        .and_then(|about| about.lines().next())
+        .unwrap_or_default()
+        .trim_end_matches('.');
+    let help = render_help_text(app)?;
+
+    let mut page = String::new();
+    writeln!(
+        page,
+        r#".TH "{}" "1" "" "{} {}" "{} Manual""#,
+        page_name.to_uppercase(),
+        PROGRAM_NAME,
+        env!("CARGO_PKG_VERSION"),
+        PROGRAM_NAME,
+    )?;
+    writeln!(page, ".SH NAME")?;
+    writeln!(page, "{} \\- {}", escape_troff(page_name).trim_end(), about)?;
+    writeln!(page, ".SH DESCRIPTION")?;
+    writeln!(page, ".nf")?;
+    write!(page, "{}", escape_troff(&help))?;
+    writeln!(page, ".fi")?;
+    Ok(page)
+}
+
+/// Render the page for the given command, as troff or as plain help text.
+fn render_page(page_name: &str, app: &mut App, text: bool) -> eyre::Result<String> {
+    if text {
+        render_help_text(app)
+    } else {
+        render_man_page(page_name, app)
+    }
+}
+
+/// Get the page name and the definition of each command which should be
+/// documented: the top-level command and each of its subcommands.
+fn get_pages<'a, 'b>(app: App<'a, 'b>) -> Vec<(String, App<'a, 'b>)> {
+    let subcommands: Vec<(String, App)> = app
+        .p
+        .subcommands
+        .iter()
+        .map(|subcommand| {
+            let name = subcommand.get_name().to_owned();
+            let subcommand = subcommand
+                .clone()
+                .bin_name(format!("git branchless {}", name));
+            (format!("{}-{}", PROGRAM_NAME, name), subcommand)
+        })
+        .collect();
+    let mut pages = vec![(PROGRAM_NAME.to_owned(), app.bin_name("git branchless"))];
+    pages.extend(subcommands);
+    pages
+}
+
+/// Print the documentation for the given subcommand of `app`, or for `app`
+/// itself if no subcommand is given. If `text` is set, print the long-form
+/// `--help` text instead of a man page. If `output_dir` is set, write one file
+/// per command into it instead, named e.g. `git-branchless-smartlog.1`.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument(skip(app))]
+pub fn man(
+    effects: &Effects,
+    app: App,
+    command: Option<String>,
+    text: bool,
+    output_dir: Option<&Path>,
+) -> eyre::Result<isize> {
+    let pages = get_pages(app);
+
+    if let Some(output_dir) = output_dir {
+        std::fs::create_dir_all(output_dir)
+            .wrap_err_with(|| format!("Creating output directory: {:?}", output_dir))?;
+        let extension = if text { "txt" } else { "1" };
+        for (page_name, mut app) in pages {
+            let path = output_dir.join(format!("{}.{}", page_name, extension));
+            let page = render_page(&page_name, &mut app, text)?;
+            std::fs::write(&path, page)
+                .wrap_err_with(|| format!("Writing documentation: {:?}", &path))?;
+            writeln!(
+                effects.get_output_stream(),
+                "Wrote {}",
+                path.to_string_lossy()
+            )?;
+        }
+        return Ok(0);
+    }
+
+    let page_name = match &command {
+        Some(command) => format!("{}-{}", PROGRAM_NAME, command),
+        None => PROGRAM_NAME.to_owned(),
+    };
+    match pages.into_iter().find(|(name, _app)| name == &page_name) {
+        Some((page_name, mut app)) => {
+            let page = render_page(&page_name, &mut app, text)?;
+            write!(effects.get_output_stream(), "{}", page)?;
+            Ok(0)
+        }
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Unknown subcommand: {}",
+                command.unwrap_or_default()
+            )?;
+            Ok(1)
+        }
+    }
+}
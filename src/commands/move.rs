@@ -8,8 +8,13 @@ use std::time::SystemTime;
 
 use tracing::instrument;
 
-use crate::core::config::get_restack_preserve_timestamps;
+use crate::core::autostash::{should_autostash, with_stashed_changes};
+use crate::core::config::{
+    get_move_force_in_memory, get_move_force_on_disk, get_restack_detect_duplicate_commits,
+    get_restack_preserve_timestamps,
+};
 use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::printable_styled_string;
 use crate::core::graph::{
     make_graph, resolve_commits, BranchOids, CommitGraph, HeadOid, MainBranchOid,
     ResolveCommitsResult,
@@ -44,7 +49,54 @@ fn resolve_base_commit(
     }
 }
 
-/// Move a subtree from one place to another.
+/// Explain why the subtree rooted at `source_oid` can't be moved onto its own
+/// descendant `dest_oid`, and how to reorder the commits instead.
+fn describe_move_onto_descendant(
+    effects: &Effects,
+    repo: &Repo,
+    source_oid: NonZeroOid,
+    dest_oid: NonZeroOid,
+) -> eyre::Result<()> {
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "This operation failed because the destination commit is a descendant of the source commit, so it would be moved along with the source commit:"
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Source: {}",
+        printable_styled_string(glyphs, repo.friendly_describe_commit_from_oid(source_oid)?)?
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Destination: {}",
+        printable_styled_string(glyphs, repo.friendly_describe_commit_from_oid(dest_oid)?)?
+    )?;
+
+    let source_commit = repo.find_commit_or_fail(source_oid)?;
+    if let Some(source_parent_oid) = source_commit.get_parent_oids().first() {
+        writeln!(
+            effects.get_output_stream(),
+            "To move the source commit on top of the destination commit, first move the destination commit out of the way:"
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "git move -s {} -d {}",
+            dest_oid,
+            source_parent_oid
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "Then move the source commit onto the rewritten destination commit."
+        )?;
+    }
+    Ok(())
+}
+
+/// Move a subtree from one place to another. If `keep` is set, copy the
+/// subtree instead, leaving the original commits and their branches in place.
+/// If `dedup` is set, it overrides `branchless.restack.detectDuplicateCommits`,
+/// and if `autostash` is set, it overrides `branchless.autoStash`.
 #[instrument]
 pub fn r#move(
     effects: &Effects,
@@ -54,10 +106,25 @@ pub fn r#move(
     base: Option<String>,
     force_in_memory: bool,
     force_on_disk: bool,
+    strategy_options: Vec<String>,
+    keep: bool,
+    dedup: Option<bool>,
+    autostash: Option<bool>,
     dump_rebase_constraints: bool,
     dump_rebase_plan: bool,
 ) -> eyre::Result<isize> {
     let repo = Repo::from_current_dir()?;
+    let (force_in_memory, force_on_disk) = if keep {
+        // Only in-memory rebases can avoid hiding the original commits.
+        (true, false)
+    } else if force_in_memory || force_on_disk {
+        (force_in_memory, force_on_disk)
+    } else {
+        (
+            get_move_force_in_memory(&repo)?,
+            get_move_force_on_disk(&repo)?,
+        )
+    };
     let head_oid = repo.get_head_info()?.oid;
     let (source, should_resolve_base_commit) = match (source, base) {
         (Some(_), Some(_)) => {
@@ -128,6 +195,14 @@ pub fn r#move(
         source_oid
     };
 
+    if source_oid != dest_oid
+        && merge_base_db.get_merge_base_oid(effects, &repo, source_oid, dest_oid)?
+            == Some(source_oid)
+    {
+        describe_move_onto_descendant(effects, &repo, source_oid, dest_oid)?;
+        return Ok(1);
+    }
+
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
     let rebase_plan = {
@@ -143,7 +218,10 @@ pub fn r#move(
             &BuildRebasePlanOptions {
                 dump_rebase_constraints,
                 dump_rebase_plan,
-                detect_duplicate_commits_via_patch_id: true,
+                detect_duplicate_commits_via_patch_id: match dedup {
+                    Some(dedup) => dedup,
+                    None => get_restack_detect_duplicate_commits(&repo)?,
+                },
             },
         )?
     };
@@ -159,8 +237,17 @@ pub fn r#move(
                 preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
                 force_in_memory,
                 force_on_disk,
+                strategy_options,
+                keep_originals: keep,
             };
-            execute_rebase_plan(effects, git_run_info, &repo, &rebase_plan, &options)?
+            with_stashed_changes(
+                effects,
+                git_run_info,
+                &repo,
+                event_tx_id,
+                should_autostash(&repo, autostash)?,
+                || execute_rebase_plan(effects, git_run_info, &repo, &rebase_plan, &options),
+            )?
         }
         Err(err) => {
             err.describe(effects, &repo)?;
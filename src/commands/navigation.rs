@@ -1,36 +1,194 @@
 //! Convenience commands to help the user move through a stack of commits.
 
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
+use eyre::Context;
+use itertools::Itertools;
 use tracing::{instrument, warn};
 
-use crate::commands::smartlog::smartlog;
-use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::commands::smartlog::{describe_operation_in_progress, smartlog, SmartlogOptions};
+use crate::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use crate::core::formatting::printable_styled_string;
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::graph::{
+    make_graph, resolve_commits, BranchOids, CommitGraph, HeadOid, MainBranchOid,
+    ResolveCommitsResult,
+};
 use crate::core::mergebase::{make_merge_base_db, MergeBaseDb};
-use crate::git::{GitRunInfo, NonZeroOid, Repo};
+use crate::core::rewrite::find_rewrite_target;
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 use crate::tui::Effects;
 
-/// Go back a certain number of commits.
+/// Check that there's no rebase, merge, etc. underway, since checking out
+/// another commit in the middle of one would leave it in a confusing state.
+/// If there is one, print how to finish it and return `false`.
+fn check_no_operation_in_progress(effects: &Effects, repo: &Repo) -> eyre::Result<bool> {
+    match describe_operation_in_progress(repo) {
+        None => Ok(true),
+        Some(description) => {
+            writeln!(effects.get_output_stream(), "{}", description)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Finish or abort it before checking out another commit."
+            )?;
+            Ok(false)
+        }
+    }
+}
+
+/// Print the branch or commit which `prev` or `next` would check out, for use
+/// in scripts.
+fn print_checkout_target(effects: &Effects, repo: &Repo, oid: NonZeroOid) -> eyre::Result<()> {
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        get_checkout_target(repo, oid)?
+    )?;
+    Ok(())
+}
+
+/// Get the commit to go to instead of the given commit, which is the commit
+/// itself if it's visible (or not in the graph at all, as for most main branch
+/// commits), or else the visible commit it was rewritten into, if any.
+fn get_visible_successor(
+    graph: &CommitGraph,
+    event_replayer: &EventReplayer,
+    oid: NonZeroOid,
+) -> Option<NonZeroOid> {
+    match graph.get(&oid) {
+        None => return Some(oid),
+        Some(node) if node.is_visible => return Some(oid),
+        Some(_) => {}
+    }
+    match find_rewrite_target(
+        graph,
+        event_replayer,
+        event_replayer.make_default_cursor(),
+        oid,
+    ) {
+        Some(MaybeZeroOid::NonZero(rewritten_oid)) if matches!(graph.get(&rewritten_oid), Some(node) if node.is_visible) => {
+            Some(rewritten_oid)
+        }
+        _ => None,
+    }
+}
+
+/// Find the commit `num_commits` first parents before `head_oid`. Unless
+/// `include_hidden` is set, hidden commits are replaced with the visible
+/// commit they were rewritten into, or else skipped without being counted.
+///
+/// Returns: The commit, or `None` if there aren't that many commits before
+/// `head_oid`.
+#[instrument]
+fn find_previous_commit(
+    repo: &Repo,
+    graph: &CommitGraph,
+    event_replayer: &EventReplayer,
+    head_oid: NonZeroOid,
+    num_commits: isize,
+    include_hidden: bool,
+) -> eyre::Result<Option<NonZeroOid>> {
+    let mut current_oid = head_oid;
+    for _ in 0..num_commits {
+        let mut oid = current_oid;
+        current_oid = loop {
+            let parent_oid = match repo.find_commit_or_fail(oid)?.get_parent_oids().first() {
+                Some(parent_oid) => *parent_oid,
+                None => return Ok(None),
+            };
+            if include_hidden {
+                break parent_oid;
+            }
+            match get_visible_successor(graph, event_replayer, parent_oid) {
+                Some(successor_oid) => break successor_oid,
+                None => oid = parent_oid,
+            }
+        };
+    }
+    Ok(Some(current_oid))
+}
+
+/// Go back a certain number of commits. If `print_only` is set, print the
+/// branch or commit which would be checked out instead of checking it out.
+/// If `include_hidden` is set, hidden commits aren't skipped.
 #[instrument]
 pub fn prev(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     num_commits: Option<isize>,
+    print_only: bool,
+    include_hidden: bool,
 ) -> eyre::Result<isize> {
-    let exit_code = match num_commits {
-        None => git_run_info.run(effects, None, &["checkout", "HEAD^"])?,
-        Some(num_commits) => git_run_info.run(
-            effects,
-            None,
-            &["checkout", &format!("HEAD~{}", num_commits)],
-        )?,
+    let repo = Repo::from_current_dir()?;
+    if !print_only && !check_no_operation_in_progress(effects, &repo)? {
+        return Ok(1);
+    }
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+
+    let head_oid = match repo.get_head_info()?.oid {
+        Some(head_oid) => head_oid,
+        None => eyre::bail!("No HEAD present; cannot calculate previous commit"),
     };
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(Some(head_oid)),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let spec = match num_commits {
+        None => "HEAD^".to_string(),
+        Some(num_commits) => format!("HEAD~{}", num_commits),
+    };
+    let target_oid = find_previous_commit(
+        &repo,
+        &graph,
+        &event_replayer,
+        head_oid,
+        num_commits.unwrap_or(1),
+        include_hidden,
+    )?;
+    let target_oid = match target_oid {
+        Some(target_oid) => target_oid,
+        None => {
+            writeln!(effects.get_error_stream(), "Commit not found: {}", spec)?;
+            return Ok(1);
+        }
+    };
+    if print_only {
+        print_checkout_target(effects, &repo, target_oid)?;
+        return Ok(0);
+    }
+
+    // Check out e.g. `HEAD^` if no commits were skipped, since it's easier to
+    // read in the output.
+    let is_spec_target = match repo.revparse_single_commit(&spec)? {
+        Some(commit) => commit.get_oid() == target_oid,
+        None => false,
+    };
+    let checkout_target = if is_spec_target {
+        spec
+    } else {
+        target_oid.to_string()
+    };
+    let exit_code = git_run_info.run(effects, None, &["checkout", &checkout_target])?;
     if exit_code != 0 {
         return Ok(exit_code);
     }
-    smartlog(effects)?;
+    smartlog(effects, &SmartlogOptions::default())?;
     Ok(0)
 }
 
@@ -46,6 +204,21 @@ pub enum Towards {
     Oldest,
 }
 
+/// Get the default for which child commit `next` should go to when it's
+/// ambiguous, as set by `branchless.next.towards`.
+fn get_next_towards(repo: &Repo) -> eyre::Result<Option<Towards>> {
+    let towards: Option<String> = repo.get_config()?.get("branchless.next.towards")?;
+    match towards.as_deref() {
+        None => Ok(None),
+        Some("newest") => Ok(Some(Towards::Newest)),
+        Some("oldest") => Ok(Some(Towards::Oldest)),
+        Some(towards) => eyre::bail!(
+            "Invalid value for branchless.next.towards: {:?} (expected `newest` or `oldest`)",
+            towards
+        ),
+    }
+}
+
 #[instrument]
 fn advance_towards_main_branch(
     effects: &Effects,
@@ -77,19 +250,57 @@ fn advance_towards_main_branch(
     Ok((0, current_oid))
 }
 
+/// Get the children of the given commit in the graph to go to with `next`.
+/// Unless `include_hidden` is set, hidden children are replaced with the
+/// visible commit they were rewritten into, or else with their own children.
+fn get_next_children(
+    graph: &CommitGraph,
+    event_replayer: &EventReplayer,
+    oid: NonZeroOid,
+    include_hidden: bool,
+) -> Vec<NonZeroOid> {
+    let children = match graph.get(&oid) {
+        Some(node) => &node.children,
+        None => return Vec::new(),
+    };
+    if include_hidden {
+        return children.clone();
+    }
+
+    let mut result = Vec::new();
+    for child_oid in children {
+        match get_visible_successor(graph, event_replayer, *child_oid) {
+            Some(successor_oid) => result.push(successor_oid),
+            None => result.extend(get_next_children(
+                graph,
+                event_replayer,
+                *child_oid,
+                include_hidden,
+            )),
+        }
+    }
+    let result: Vec<NonZeroOid> = result.into_iter().unique().collect();
+    result
+        .into_iter()
+        .sorted_by_key(|oid| (graph[oid].commit.get_time(), oid.to_string()))
+        .collect()
+}
+
 #[instrument]
 fn advance_towards_own_commit(
     effects: &Effects,
     repo: &Repo,
     graph: &CommitGraph,
+    event_replayer: &EventReplayer,
     current_oid: NonZeroOid,
     num_commits: isize,
     towards: Option<Towards>,
+    include_hidden: bool,
 ) -> eyre::Result<Option<NonZeroOid>> {
     let glyphs = effects.get_glyphs();
     let mut current_oid = current_oid;
     for i in 0..num_commits {
-        let children = &graph[&current_oid].children;
+        let children = &get_next_children(graph, event_replayer, current_oid, include_hidden);
         current_oid = match (towards, children.as_slice()) {
             (_, []) => {
                 // It would also make sense to issue an error here, rather than
@@ -134,15 +345,26 @@ fn advance_towards_own_commit(
     Ok(Some(current_oid))
 }
 
-/// Go forward a certain number of commits.
+/// Go forward a certain number of commits. If `print_only` is set, print the
+/// branch or commit which would be checked out instead of checking it out.
+/// If `include_hidden` is set, hidden commits aren't skipped.
 #[instrument]
 pub fn next(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     num_commits: Option<isize>,
     towards: Option<Towards>,
+    print_only: bool,
+    include_hidden: bool,
 ) -> eyre::Result<isize> {
     let repo = Repo::from_current_dir()?;
+    if !print_only && !check_no_operation_in_progress(effects, &repo)? {
+        return Ok(1);
+    }
+    let towards = match towards {
+        Some(towards) => Some(towards),
+        None => get_next_towards(&repo)?,
+    };
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
@@ -176,18 +398,312 @@ pub fn next(
         &MainBranchOid(main_branch_oid),
     )?;
     let num_commits = num_commits - num_commits_traversed_towards_main_branch;
-    let current_oid =
-        advance_towards_own_commit(effects, &repo, &graph, current_oid, num_commits, towards)?;
+    let current_oid = advance_towards_own_commit(
+        effects,
+        &repo,
+        &graph,
+        &event_replayer,
+        current_oid,
+        num_commits,
+        towards,
+        include_hidden,
+    )?;
     let current_oid = match current_oid {
         None => return Ok(1),
         Some(current_oid) => current_oid,
     };
+    if print_only {
+        print_checkout_target(effects, &repo, current_oid)?;
+        return Ok(0);
+    }
 
     let result = git_run_info.run(effects, None, &["checkout", &current_oid.to_string()])?;
     if result != 0 {
         return Ok(result);
     }
 
-    smartlog(effects)?;
+    smartlog(effects, &SmartlogOptions::default())?;
+    Ok(0)
+}
+
+/// Get the argument to `git checkout` to check out the given commit: the name
+/// of the branch pointing to it if there's exactly one, or else the commit
+/// hash itself. The commit hash is also used if the branch is checked out in
+/// another worktree, since Git refuses to check it out here.
+fn get_checkout_target(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<String> {
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let branch_names: Vec<&OsString> = branch_oid_to_names
+        .get(&commit_oid)
+        .map(|names| {
+            names
+                .iter()
+                .filter(|name| name.to_string_lossy().starts_with("refs/heads/"))
+                .collect()
+        })
+        .unwrap_or_default();
+    let branch_name = match branch_names.as_slice() {
+        [branch_name] => branch_name,
+        _ => return Ok(commit_oid.to_string()),
+    };
+    if repo
+        .get_other_worktree_branch_names()?
+        .contains(*branch_name)
+    {
+        return Ok(commit_oid.to_string());
+    }
+    match branch_name
+        .to_str()
+        .and_then(|branch_name| branch_name.strip_prefix("refs/heads/"))
+    {
+        Some(branch_name) => Ok(branch_name.to_string()),
+        None => Ok(commit_oid.to_string()),
+    }
+}
+
+/// Check out the given commit or branch. If the given commit has exactly one
+/// local branch pointing to it, then that branch is checked out, rather than
+/// detaching `HEAD`.
+///
+/// The checkout is recorded in the event log as a transaction named after the
+/// target, so that it's described as e.g. `goto feature-x` in `git undo`.
+#[instrument]
+pub fn goto(effects: &Effects, git_run_info: &GitRunInfo, target: String) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    if !check_no_operation_in_progress(effects, &repo)? {
+        return Ok(1);
+    }
+    let commit = match resolve_commits(&repo, vec![target.clone()])? {
+        ResolveCommitsResult::Ok { commits } => match commits.as_slice() {
+            [commit] => commit.clone(),
+            commits => eyre::bail!(
+                "Expected to resolve exactly one commit for {:?}, but got: {:?}",
+                target,
+                commits
+            ),
+        },
+        ResolveCommitsResult::CommitNotFound { commit } => {
+            writeln!(effects.get_output_stream(), "Commit not found: {}", commit)?;
+            return Ok(1);
+        }
+    };
+
+    let checkout_target = if repo
+        .find_branch(&target, git2::BranchType::Local)?
+        .is_some()
+    {
+        target.clone()
+    } else {
+        get_checkout_target(&repo, commit.get_oid())?
+    };
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(now, format!("goto {}", target))?;
+    let exit_code = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["checkout", &checkout_target, "--"],
+    )?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+    smartlog(effects, &SmartlogOptions::default())?;
     Ok(0)
 }
+
+/// The transaction messages for checkouts made by `back` and `forward`, which
+/// aren't themselves added to the checkout history.
+const HISTORY_NAVIGATION_MESSAGES: &[&str] = &["back", "forward"];
+
+/// Get the commits which `HEAD` has pointed to in the current worktree, from
+/// oldest to newest, with consecutive duplicates removed.
+#[instrument]
+fn get_checkout_history(repo: &Repo, event_log_db: &EventLogDb) -> eyre::Result<Vec<NonZeroOid>> {
+    let head_ref_name = repo.get_head_ref_name();
+    let mut is_navigation_tx: HashMap<EventTransactionId, bool> = HashMap::new();
+    let mut result: Vec<NonZeroOid> = Vec::new();
+    for event in event_log_db.get_events()? {
+        let (event_tx_id, new_oid) = match event {
+            Event::RefUpdateEvent {
+                event_tx_id,
+                ref_name,
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+                ..
+            } if ref_name == head_ref_name => (event_tx_id, new_oid),
+            _ => continue,
+        };
+
+        let is_navigation = match is_navigation_tx.get(&event_tx_id) {
+            Some(is_navigation) => *is_navigation,
+            None => {
+                let is_navigation = match event_log_db.get_transaction_message(event_tx_id)? {
+                    Some(message) => HISTORY_NAVIGATION_MESSAGES.contains(&message.as_str()),
+                    None => false,
+                };
+                is_navigation_tx.insert(event_tx_id, is_navigation);
+                is_navigation
+            }
+        };
+        if !is_navigation && result.last() != Some(&new_oid) {
+            result.push(new_oid);
+        }
+    }
+    Ok(result)
+}
+
+/// The position in the checkout history of the current worktree, as of the
+/// last `back` or `forward`.
+#[derive(Debug, PartialEq, Eq)]
+struct CheckoutHistoryPosition {
+    /// The length of the checkout history when the position was saved. If
+    /// there have been checkouts since, then the position is no longer valid.
+    history_len: usize,
+
+    /// The index of the current entry in the checkout history.
+    index: usize,
+}
+
+fn get_checkout_history_position_path(repo: &Repo) -> PathBuf {
+    repo.get_path()
+        .join("branchless")
+        .join("checkout-history-position")
+}
+
+fn read_checkout_history_position(repo: &Repo) -> eyre::Result<Option<CheckoutHistoryPosition>> {
+    let path = get_checkout_history_position_path(repo);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Reading checkout position at {:?}", &path))
+        }
+    };
+    match contents.split_whitespace().collect_vec().as_slice() {
+        [history_len, index] => match (history_len.parse(), index.parse()) {
+            (Ok(history_len), Ok(index)) => {
+                Ok(Some(CheckoutHistoryPosition { history_len, index }))
+            }
+            _ => {
+                warn!(?contents, "Invalid checkout history position");
+                Ok(None)
+            }
+        },
+        _ => {
+            warn!(?contents, "Invalid checkout history position");
+            Ok(None)
+        }
+    }
+}
+
+fn write_checkout_history_position(
+    repo: &Repo,
+    position: &CheckoutHistoryPosition,
+) -> eyre::Result<()> {
+    let CheckoutHistoryPosition { history_len, index } = position;
+    let path = get_checkout_history_position_path(repo);
+    if let Some(parent) = path.parent() {
+        // The directory may not exist yet in a linked worktree, since its Git
+        // directory is separate from the main one.
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("Creating directory {:?}", parent))?;
+    }
+    std::fs::write(&path, format!("{} {}\n", history_len, index))
+        .wrap_err_with(|| format!("Writing checkout position to {:?}", &path))?;
+    Ok(())
+}
+
+/// Move through the checkout history by the given number of entries: backward
+/// if negative, or forward if positive.
+#[instrument]
+fn navigate_checkout_history(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    offset: isize,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    if !check_no_operation_in_progress(effects, &repo)? {
+        return Ok(1);
+    }
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let history = get_checkout_history(&repo, &event_log_db)?;
+    let head_oid = repo.get_head_info()?.oid;
+
+    // If there have been any checkouts since the last `back` or `forward`, or
+    // `HEAD` was moved without being recorded, then start again from the end of
+    // the history, like a browser does after visiting a new page.
+    let current_index = match read_checkout_history_position(&repo)? {
+        Some(CheckoutHistoryPosition { history_len, index })
+            if history_len == history.len() && history.get(index).copied() == head_oid =>
+        {
+            Some(index)
+        }
+        _ => history.len().checked_sub(1),
+    };
+    let target_index = current_index
+        .and_then(|current_index| (current_index as isize).checked_add(offset))
+        .filter(|target_index| *target_index >= 0 && (*target_index as usize) < history.len())
+        .map(|target_index| target_index as usize);
+    let target_index = match target_index {
+        Some(target_index) => target_index,
+        None => {
+            if offset < 0 {
+                writeln!(
+                    effects.get_output_stream(),
+                    "No earlier checkout to go back to."
+                )?;
+            } else {
+                writeln!(
+                    effects.get_output_stream(),
+                    "No later checkout to go forward to."
+                )?;
+            }
+            return Ok(1);
+        }
+    };
+
+    let checkout_target = get_checkout_target(&repo, history[target_index])?;
+    let event_tx_id =
+        event_log_db.make_transaction_id(now, if offset < 0 { "back" } else { "forward" })?;
+    let exit_code = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["checkout", &checkout_target, "--"],
+    )?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+    write_checkout_history_position(
+        &repo,
+        &CheckoutHistoryPosition {
+            history_len: history.len(),
+            index: target_index,
+        },
+    )?;
+    smartlog(effects, &SmartlogOptions::default())?;
+    Ok(0)
+}
+
+/// Go back to the commit (or branch) which was checked out before the current
+/// one, like the back button in a browser. Checkouts made by `back` and
+/// `forward` aren't added to the history.
+#[instrument]
+pub fn back(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    num_checkouts: Option<isize>,
+) -> eyre::Result<isize> {
+    navigate_checkout_history(effects, git_run_info, -num_checkouts.unwrap_or(1))
+}
+
+/// Undo the last `back`, returning to a later entry in the checkout history.
+#[instrument]
+pub fn forward(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    num_checkouts: Option<isize>,
+) -> eyre::Result<isize> {
+    navigate_checkout_history(effects, git_run_info, num_checkouts.unwrap_or(1))
+}
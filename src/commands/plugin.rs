@@ -0,0 +1,366 @@
+//! Run third-party subcommands ("plugins").
+//!
+//! If `git branchless <name>` isn't a built-in subcommand, then an executable
+//! named `git-branchless-<name>` on the `PATH` is run instead, with the
+//! remaining arguments. The plugin is told about the repository through the
+//! environment variables below, and can read the commit graph and the event
+//! log as JSON by running `git branchless plugin-api graph` and `git
+//! branchless plugin-api events`. The environment variables and the JSON
+//! format are stable for a given `BRANCHLESS_PLUGIN_API_VERSION`.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::SystemTime;
+
+use eyre::Context;
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::core::config::get_main_branch_name;
+use crate::core::eventlog::{
+    Event, EventLogDb, EventReplayer, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::git::{GitRunInfo, Repo};
+use crate::tui::Effects;
+use crate::util::is_executable;
+
+/// The version of the interface between `git-branchless` and its plugins. It's
+/// incremented whenever the environment variables or the JSON output change
+/// in an incompatible way.
+pub const PLUGIN_API_VERSION: usize = 1;
+
+/// The environment variable holding `PLUGIN_API_VERSION`.
+pub const BRANCHLESS_PLUGIN_API_VERSION_ENV_VAR: &str = "BRANCHLESS_PLUGIN_API_VERSION";
+
+/// The environment variable holding the path to the `git-branchless`
+/// executable which ran the plugin.
+pub const BRANCHLESS_EXECUTABLE_ENV_VAR: &str = "BRANCHLESS_EXECUTABLE";
+
+/// The environment variable holding the path to the `.git` directory of the
+/// repository.
+pub const BRANCHLESS_REPO_PATH_ENV_VAR: &str = "BRANCHLESS_REPO_PATH";
+
+/// The environment variable holding the path to the working copy of the
+/// repository. Unset for bare repositories.
+pub const BRANCHLESS_WORKING_COPY_PATH_ENV_VAR: &str = "BRANCHLESS_WORKING_COPY_PATH";
+
+/// The environment variable holding the path to the SQLite database which
+/// stores the event log.
+pub const BRANCHLESS_DB_PATH_ENV_VAR: &str = "BRANCHLESS_DB_PATH";
+
+/// The environment variable holding the name of the main branch.
+pub const BRANCHLESS_MAIN_BRANCH_ENV_VAR: &str = "BRANCHLESS_MAIN_BRANCH";
+
+/// Find the executable for the plugin with the given name in the directories
+/// listed in `path` (in the format of the `PATH` environment variable).
+pub fn find_plugin_executable(name: &str, path: &OsStr) -> Option<PathBuf> {
+    let file_name = format!("git-branchless-{}", name);
+    std::env::split_paths(path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Collect the environment variables describing the repository in the
+/// current directory, if any, for a plugin.
+fn get_plugin_env(repo: Option<&Repo>) -> eyre::Result<HashMap<OsString, OsString>> {
+    let mut env: HashMap<OsString, OsString> = HashMap::new();
+    env.insert(
+        BRANCHLESS_PLUGIN_API_VERSION_ENV_VAR.into(),
+        PLUGIN_API_VERSION.to_string().into(),
+    );
+    if let Ok(executable) = std::env::current_exe() {
+        env.insert(BRANCHLESS_EXECUTABLE_ENV_VAR.into(), executable.into());
+    }
+    if let Some(repo) = repo {
+        env.insert(
+            BRANCHLESS_REPO_PATH_ENV_VAR.into(),
+            repo.get_path().as_os_str().to_owned(),
+        );
+        if let Some(working_copy_path) = repo.get_working_copy_path() {
+            env.insert(
+                BRANCHLESS_WORKING_COPY_PATH_ENV_VAR.into(),
+                working_copy_path.as_os_str().to_owned(),
+            );
+        }
+        env.insert(
+            BRANCHLESS_DB_PATH_ENV_VAR.into(),
+            repo.get_db_path()?.into(),
+        );
+        env.insert(
+            BRANCHLESS_MAIN_BRANCH_ENV_VAR.into(),
+            get_main_branch_name(repo)?.into(),
+        );
+    }
+    Ok(env)
+}
+
+/// Run the plugin for the subcommand `args[0]`, passing it the rest of the
+/// arguments. The plugin runs in an event transaction, so that any commands it
+/// runs can be undone together.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn run_plugin(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    args: &[String],
+) -> eyre::Result<isize> {
+    let (name, plugin_args) = match args.split_first() {
+        Some((name, plugin_args)) => (name, plugin_args),
+        None => eyre::bail!("No subcommand was provided"),
+    };
+    let path = git_run_info
+        .env
+        .get(OsStr::new("PATH"))
+        .cloned()
+        .unwrap_or_default();
+    let executable = match find_plugin_executable(name, &path) {
+        Some(executable) => executable,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Unknown subcommand: {}. (No `git-branchless-{}` executable was found on the PATH.)",
+                name,
+                name
+            )?;
+            return Ok(1);
+        }
+    };
+
+    // Plugins can be run outside of a repository, in which case there's no
+    // repository information or event transaction to pass along.
+    let repo = Repo::from_current_dir().ok();
+    let event_tx_id: Option<EventTransactionId> = match &repo {
+        Some(repo) => {
+            let conn = repo.get_db_conn()?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            Some(event_log_db.make_transaction_id(SystemTime::now(), name)?)
+        }
+        None => None,
+    };
+
+    let mut command = Command::new(&executable);
+    command.current_dir(&git_run_info.working_directory);
+    command.args(plugin_args);
+    command.env_clear();
+    command.envs(git_run_info.env.iter());
+    command.envs(get_plugin_env(repo.as_ref())?);
+    if let Some(event_tx_id) = event_tx_id {
+        command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+    }
+    let exit_status = command
+        .status()
+        .wrap_err_with(|| format!("Running plugin: {:?} {:?}", executable, plugin_args))?;
+    let exit_code = exit_status.code().unwrap_or(1).try_into()?;
+
+    if let (Some(repo), Some(event_tx_id)) = (repo, event_tx_id) {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.add_transaction_command(event_tx_id, args, exit_code)?;
+    }
+    Ok(exit_code)
+}
+
+/// Print the commit graph of the repository as JSON. Each commit lists its
+/// parents and children in the graph, its branches, and whether it's on the
+/// main branch and whether it's visible.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn plugin_api_graph(effects: &Effects) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut oids: Vec<_> = graph.keys().copied().collect();
+    oids.sort_unstable();
+    let mut commits = Vec::new();
+    for oid in oids {
+        let node = &graph[&oid];
+        let mut branch_names: Vec<String> = branch_oid_to_names
+            .get(&oid)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        branch_names.sort_unstable();
+        commits.push(json!({
+            "oid": oid.to_string(),
+            "parent_oids": node
+                .commit
+                .get_parent_oids()
+                .iter()
+                .map(|parent_oid| parent_oid.to_string())
+                .collect::<Vec<_>>(),
+            "child_oids": node
+                .children
+                .iter()
+                .map(|child_oid| child_oid.to_string())
+                .collect::<Vec<_>>(),
+            "summary": node.commit.get_summary()?.to_string_lossy(),
+            "branches": branch_names,
+            "is_main": node.is_main,
+            "is_visible": node.is_visible,
+        }));
+    }
+
+    let output = json!({
+        "api_version": PLUGIN_API_VERSION,
+        "head_oid": head_oid.map(|oid| oid.to_string()),
+        "main_branch_oid": main_branch_oid.to_string(),
+        "commits": commits,
+    });
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        serde_json::to_string_pretty(&output)?
+    )?;
+    Ok(0)
+}
+
+fn event_to_json(event: &Event) -> Value {
+    match event {
+        Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid,
+            new_commit_oid,
+        } => json!({
+            "type": "rewrite",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "old_commit_oid": old_commit_oid.to_string(),
+            "new_commit_oid": new_commit_oid.to_string(),
+        }),
+        Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid,
+            message,
+        } => json!({
+            "type": "ref-update",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "ref_name": ref_name.to_string_lossy(),
+            "old_oid": old_oid.to_string(),
+            "new_oid": new_oid.to_string(),
+            "message": message.as_ref().map(|message| message.to_string_lossy()),
+        }),
+        Event::CommitEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        } => json!({
+            "type": "commit",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::HideEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+            reason,
+        } => json!({
+            "type": "hide",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "commit_oid": commit_oid.to_string(),
+            "reason": reason,
+        }),
+        Event::UnhideEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        } => json!({
+            "type": "unhide",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::StashPushEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        } => json!({
+            "type": "stash-push",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::StashDropEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        } => json!({
+            "type": "stash-drop",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::CopyEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid,
+            new_commit_oid,
+        } => json!({
+            "type": "copy",
+            "timestamp": timestamp,
+            "event_tx_id": event_tx_id.to_string(),
+            "old_commit_oid": old_commit_oid.to_string(),
+            "new_commit_oid": new_commit_oid.to_string(),
+        }),
+    }
+}
+
+/// Print the events in the event log as JSON, from oldest to newest.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn plugin_api_events(effects: &Effects) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let events: Vec<Value> = event_log_db
+        .get_events()?
+        .iter()
+        .map(event_to_json)
+        .collect();
+    let output = json!({
+        "api_version": PLUGIN_API_VERSION,
+        "events": events,
+    });
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        serde_json::to_string_pretty(&output)?
+    )?;
+    Ok(0)
+}
@@ -0,0 +1,410 @@
+//! Permanently delete commits which have been hidden for a long time.
+//!
+//! `git branchless gc` keeps recently-hidden commits alive so that they can be
+//! restored with `git undo`, but commits which are still reachable from some
+//! other reference are kept forever by Git itself. This command finds the
+//! hidden commits which were hidden longer ago than `--older-than` (or
+//! `branchless.prune.olderThan`), deletes the references which branchless
+//! holds on them, and then runs Git's own garbage collection to reclaim their
+//! objects.
+//!
+//! A hidden commit is never pruned while it's still needed: if it's reachable
+//! from a reference outside of `refs/branchless/` or from `HEAD` in any
+//! worktree, if a visible commit descends from it, or if it was rewritten
+//! into a commit which is still visible (since its history would be lost).
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::config::{get_prune_older_than, parse_duration};
+use crate::core::eventlog::{CommitVisibility, EventLogDb, EventReplayer, KEEP_REF_PREFIX};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
+use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::find_rewrite_target;
+use crate::git::{CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// The reason that a hidden commit can't be pruned yet.
+#[derive(Clone, Debug)]
+enum PruneBlocker {
+    /// The commit is reachable from a reference not owned by branchless, such
+    /// as a branch or a tag.
+    Reference(OsString),
+
+    /// The commit is reachable from `HEAD` in the worktree at the given path.
+    WorktreeHead(PathBuf),
+
+    /// A visible commit descends from the commit.
+    VisibleDescendant(NonZeroOid),
+
+    /// The commit was rewritten into a commit which is still visible, so
+    /// deleting it would lose the history of that commit.
+    VisibleRewrite(NonZeroOid),
+}
+
+impl PruneBlocker {
+    fn describe(&self, glyphs: &Glyphs, repo: &Repo) -> eyre::Result<String> {
+        let describe_commit = |oid: NonZeroOid| -> eyre::Result<String> {
+            printable_styled_string(glyphs, repo.friendly_describe_commit_from_oid(oid)?)
+        };
+        let description = match self {
+            PruneBlocker::Reference(ref_name) => format!(
+                "reachable from {}",
+                CategorizedReferenceName::new(ref_name).render_full()
+            ),
+            PruneBlocker::WorktreeHead(path) => {
+                format!("reachable from HEAD in worktree {:?}", path)
+            }
+            PruneBlocker::VisibleDescendant(oid) => {
+                format!("ancestor of visible commit {}", describe_commit(*oid)?)
+            }
+            PruneBlocker::VisibleRewrite(oid) => {
+                format!("rewritten as visible commit {}", describe_commit(*oid)?)
+            }
+        };
+        Ok(description)
+    }
+}
+
+/// Find a visible commit in the graph which descends from the given commit.
+fn find_visible_descendant(graph: &CommitGraph, oid: NonZeroOid) -> Option<NonZeroOid> {
+    let mut seen: HashSet<NonZeroOid> = HashSet::new();
+    let mut stack: Vec<NonZeroOid> = match graph.get(&oid) {
+        Some(node) => node.children.clone(),
+        None => return None,
+    };
+    while let Some(oid) = stack.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        if let Some(node) = graph.get(&oid) {
+            if node.is_visible {
+                return Some(oid);
+            }
+            stack.extend(node.children.iter().copied());
+        }
+    }
+    None
+}
+
+/// Find which of the given commits are reachable from the given roots (a
+/// reference or the `HEAD` of a worktree), along with the first root which
+/// reaches each of them. The history is walked only once, rather than once per
+/// commit.
+#[instrument]
+fn find_reachable_blockers(
+    repo: &Repo,
+    roots: &[(PruneBlocker, NonZeroOid)],
+    oids: &HashSet<NonZeroOid>,
+) -> eyre::Result<HashMap<NonZeroOid, PruneBlocker>> {
+    let mut result = HashMap::new();
+    let mut walked_root_oids = Vec::new();
+    for (blocker, root_oid) in roots {
+        if walked_root_oids.contains(root_oid) {
+            continue;
+        }
+        // Hide the commits reachable from the earlier roots, so that each
+        // commit is only visited once.
+        for oid in repo.get_commits_between_many(&walked_root_oids, &[*root_oid])? {
+            if oids.contains(&oid) {
+                result.entry(oid).or_insert_with(|| blocker.clone());
+            }
+        }
+        walked_root_oids.push(*root_oid);
+    }
+    Ok(result)
+}
+
+/// Check whether the given hidden commit is still needed by a visible commit
+/// or a visible rewrite of it. Whether it's needed by a reference or a
+/// worktree is checked by `find_reachable_blockers`.
+#[instrument]
+fn find_prune_blocker(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    graph: &CommitGraph,
+    oid: NonZeroOid,
+) -> eyre::Result<Option<PruneBlocker>> {
+    if let Some(descendant_oid) = find_visible_descendant(graph, oid) {
+        return Ok(Some(PruneBlocker::VisibleDescendant(descendant_oid)));
+    }
+
+    let cursor = event_replayer.make_default_cursor();
+    if let Some(MaybeZeroOid::NonZero(rewritten_oid)) =
+        find_rewrite_target(graph, event_replayer, cursor, oid)
+    {
+        let is_visible = match graph.get(&rewritten_oid) {
+            Some(node) => node.is_visible,
+            None => !matches!(
+                event_replayer.get_cursor_commit_visibility(cursor, rewritten_oid),
+                Some(CommitVisibility::Hidden)
+            ),
+        };
+        if is_visible && repo.find_commit(rewritten_oid)?.is_some() {
+            return Ok(Some(PruneBlocker::VisibleRewrite(rewritten_oid)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The hidden commits which are old enough to be pruned.
+struct PruneCandidates<'repo> {
+    /// The commits which can be deleted.
+    prunable: Vec<Commit<'repo>>,
+
+    /// The commits which are still needed, along with the reason why.
+    blocked: Vec<(Commit<'repo>, PruneBlocker)>,
+}
+
+/// Find the hidden commits which were hidden before `cutoff`, split into
+/// those which can be pruned and those which are still needed.
+#[instrument]
+fn find_prunable_commits<'repo>(
+    repo: &'repo Repo,
+    event_replayer: &EventReplayer,
+    graph: &CommitGraph,
+    cutoff: SystemTime,
+) -> eyre::Result<PruneCandidates<'repo>> {
+    let mut roots: Vec<(PruneBlocker, NonZeroOid)> = Vec::new();
+    for (path, head_oid) in repo.get_worktree_head_oids()? {
+        roots.push((PruneBlocker::WorktreeHead(path), head_oid));
+    }
+    for reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        if reference_name
+            .to_string_lossy()
+            .starts_with("refs/branchless/")
+        {
+            continue;
+        }
+        if let Some(commit) = reference.peel_to_commit()? {
+            roots.push((PruneBlocker::Reference(reference_name), commit.get_oid()));
+        }
+    }
+
+    let cursor = event_replayer.make_default_cursor();
+    let mut candidate_commits = Vec::new();
+    for oid in event_replayer.get_cursor_active_oids(cursor) {
+        if !matches!(
+            event_replayer.get_cursor_commit_visibility(cursor, oid),
+            Some(CommitVisibility::Hidden)
+        ) {
+            continue;
+        }
+        let is_old = match event_replayer.get_cursor_commit_latest_event(cursor, oid) {
+            Some(event) => event.get_timestamp() <= cutoff,
+            None => false,
+        };
+        if !is_old {
+            continue;
+        }
+
+        let commit = match repo.find_commit(oid)? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        candidate_commits.push(commit);
+    }
+
+    let candidate_oids: HashSet<NonZeroOid> = candidate_commits
+        .iter()
+        .map(|commit| commit.get_oid())
+        .collect();
+    let mut reachable_blockers = find_reachable_blockers(repo, &roots, &candidate_oids)?;
+    let mut prunable_commits = Vec::new();
+    let mut blocked_commits = Vec::new();
+    for commit in candidate_commits {
+        let oid = commit.get_oid();
+        let blocker = match reachable_blockers.remove(&oid) {
+            Some(blocker) => Some(blocker),
+            None => find_prune_blocker(repo, event_replayer, graph, oid)?,
+        };
+        match blocker {
+            Some(blocker) => blocked_commits.push((commit, blocker)),
+            None => prunable_commits.push(commit),
+        }
+    }
+    prunable_commits.sort_by_key(|commit| commit.get_time());
+    blocked_commits.sort_by_key(|(commit, _blocker)| commit.get_time());
+    Ok(PruneCandidates {
+        prunable: prunable_commits,
+        blocked: blocked_commits,
+    })
+}
+
+/// Delete the references under `refs/branchless/` which point to the given
+/// commits.
+#[instrument]
+fn delete_branchless_references(repo: &Repo, commit_oids: &[NonZeroOid]) -> eyre::Result<()> {
+    for commit_oid in commit_oids {
+        for ref_name in [
+            format!("refs/branchless/{}", commit_oid),
+            format!("{}{}", KEEP_REF_PREFIX, commit_oid),
+        ] {
+            if let Some(mut reference) = repo.find_reference(OsStr::new(&ref_name))? {
+                reference
+                    .delete()
+                    .wrap_err_with(|| format!("Deleting reference {}", ref_name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete the commits which have been hidden for longer than `older_than` (or
+/// `branchless.prune.olderThan` if not provided). If `dry_run` is set, only
+/// list the commits which would be deleted.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn prune(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    older_than: Option<String>,
+    dry_run: bool,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let older_than: Duration = match older_than {
+        Some(older_than) => parse_duration("--older-than", &older_than)?,
+        None => get_prune_older_than(&repo)?,
+    };
+    let cutoff = now
+        .checked_sub(older_than)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        false,
+    )?;
+    let PruneCandidates {
+        prunable: commits,
+        blocked: blocked_commits,
+    } = find_prunable_commits(&repo, &event_replayer, &graph, cutoff)?;
+
+    let glyphs = effects.get_glyphs();
+    if !blocked_commits.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Not deleting {}, since they're still needed:",
+            Pluralize {
+                amount: blocked_commits.len().try_into()?,
+                singular: "commit",
+                plural: "commits",
+            }
+            .to_string()
+        )?;
+        for (commit, blocker) in blocked_commits.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "  {} ({})",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?,
+                blocker.describe(glyphs, &repo)?
+            )?;
+        }
+    }
+    if commits.is_empty() {
+        writeln!(effects.get_output_stream(), "No commits to prune.")?;
+        return Ok(0);
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "{} {}:",
+        if dry_run { "Would delete" } else { "Deleting" },
+        Pluralize {
+            amount: commits.len().try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string()
+    )?;
+    for commit in commits.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "  {}",
+            printable_styled_string(glyphs, commit.friendly_describe()?)?
+        )?;
+    }
+    if dry_run {
+        return Ok(0);
+    }
+
+    let commit_oids: Vec<NonZeroOid> = commits.iter().map(|commit| commit.get_oid()).collect();
+    delete_branchless_references(&repo, &commit_oids)?;
+
+    // Only expire objects which became unreachable before the cutoff, so that
+    // objects which were only just created by a concurrent operation survive.
+    let expire = format!("{}.seconds.ago", older_than.as_secs());
+    let exit_code = git_run_info.run(
+        effects,
+        None,
+        &[
+            "reflog",
+            "expire",
+            &format!("--expire-unreachable={}", expire),
+            "--all",
+        ],
+    )?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+    let exit_code = git_run_info.run(effects, None, &["gc", &format!("--prune={}", expire)])?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+
+    let repo = Repo::from_current_dir()?;
+    let mut num_deleted = 0;
+    for commit_oid in commit_oids.iter().copied() {
+        if repo.find_commit(commit_oid)?.is_none() {
+            num_deleted += 1;
+        }
+    }
+    let num_remaining = commit_oids.len() - num_deleted;
+    writeln!(
+        effects.get_output_stream(),
+        "Deleted {}.",
+        Pluralize {
+            amount: num_deleted.try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string()
+    )?;
+    if num_remaining > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "{} could not be deleted, since Git still considers them reachable.",
+            Pluralize {
+                amount: num_remaining.try_into()?,
+                singular: "commit",
+                plural: "commits",
+            }
+            .to_string()
+        )?;
+    }
+    Ok(0)
+}
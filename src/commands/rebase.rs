@@ -0,0 +1,212 @@
+//! Run `git rebase` and make sure that what it did is recorded in the event
+//! log, even if the hooks didn't run.
+//!
+//! The hooks normally record the reference updates and the rewritten commits
+//! of a rebase, but they may not run with old versions of Git or with some
+//! hook managers. Instead of relying on them, the references are compared
+//! before and after the rebase, and any changes the hooks missed are added to
+//! the rebase's transaction, so that `git undo` can always revert it.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use crate::core::formatting::Pluralize;
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// Get the commits which `HEAD` and the branches point to.
+fn get_references_snapshot(repo: &Repo) -> eyre::Result<HashMap<OsString, NonZeroOid>> {
+    let mut result = HashMap::new();
+    for (oid, names) in repo.get_branch_oid_to_names()? {
+        for name in names {
+            result.insert(name, oid);
+        }
+    }
+    if let Some(head_oid) = repo.get_head_info()?.oid {
+        result.insert(OsString::from("HEAD"), head_oid);
+    }
+    Ok(result)
+}
+
+/// Find the commits which were rewritten when a reference was moved from
+/// `old_oid` to `new_oid`, by matching the commits which are no longer
+/// reachable from it with the new ones which have the same patch ID. Commits
+/// whose changes were edited, such as by squashing them, can't be matched.
+fn find_rewritten_commits(
+    effects: &Effects,
+    repo: &Repo,
+    old_oid: NonZeroOid,
+    new_oid: NonZeroOid,
+) -> eyre::Result<Vec<(NonZeroOid, NonZeroOid)>> {
+    let mut new_commits = HashMap::new();
+    for oid in repo.get_commits_between(old_oid, new_oid)? {
+        if let Some(commit) = repo.find_commit(oid)? {
+            if let Some(patch_id) = repo.get_patch_id(effects, &commit)? {
+                new_commits.entry(patch_id).or_insert(oid);
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for oid in repo.get_commits_between(new_oid, old_oid)? {
+        if let Some(commit) = repo.find_commit(oid)? {
+            if let Some(patch_id) = repo.get_patch_id(effects, &commit)? {
+                if let Some(new_commit_oid) = new_commits.get(&patch_id) {
+                    result.push((oid, *new_commit_oid));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Make the events for the changes between the two snapshots of the
+/// references which aren't already among `recorded_events`.
+fn make_missing_events(
+    effects: &Effects,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    timestamp: f64,
+    references_before: &HashMap<OsString, NonZeroOid>,
+    references_after: &HashMap<OsString, NonZeroOid>,
+    recorded_events: &[Event],
+) -> eyre::Result<Vec<Event>> {
+    let mut recorded_ref_updates: HashSet<(OsString, MaybeZeroOid)> = HashSet::new();
+    let mut rewritten_oids: HashSet<NonZeroOid> = HashSet::new();
+    for event in recorded_events {
+        match event {
+            Event::RefUpdateEvent {
+                ref_name, new_oid, ..
+            } => {
+                recorded_ref_updates.insert((ref_name.clone(), *new_oid));
+            }
+            Event::RewriteEvent {
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                ..
+            } => {
+                rewritten_oids.insert(*old_commit_oid);
+            }
+            _ => {}
+        }
+    }
+
+    let mut ref_names: Vec<&OsString> = references_before
+        .keys()
+        .chain(references_after.keys())
+        .collect();
+    ref_names.sort();
+    ref_names.dedup();
+
+    let mut ref_update_events = Vec::new();
+    let mut rewrite_events = Vec::new();
+    for ref_name in ref_names {
+        let old_oid = references_before.get(ref_name).copied();
+        let new_oid = references_after.get(ref_name).copied();
+        if old_oid == new_oid {
+            continue;
+        }
+
+        let old_oid = MaybeZeroOid::from(old_oid);
+        let new_oid = MaybeZeroOid::from(new_oid);
+        if !recorded_ref_updates.contains(&(ref_name.clone(), new_oid)) {
+            ref_update_events.push(Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name: ref_name.clone(),
+                old_oid,
+                new_oid,
+                message: None,
+            });
+        }
+
+        if let (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::NonZero(new_oid)) = (old_oid, new_oid)
+        {
+            for (old_commit_oid, new_commit_oid) in
+                find_rewritten_commits(effects, repo, old_oid, new_oid)?
+            {
+                if rewritten_oids.insert(old_commit_oid) {
+                    rewrite_events.push(Event::RewriteEvent {
+                        timestamp,
+                        event_tx_id,
+                        old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                        new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    });
+                }
+            }
+        }
+    }
+
+    let mut result = ref_update_events;
+    result.extend(rewrite_events);
+    Ok(result)
+}
+
+/// Run `git rebase` with the given arguments in a new transaction, then add
+/// any reference updates and rewritten commits which the hooks didn't record
+/// to it. If the rebase stops, such as due to a merge conflict, the changes so
+/// far are recorded, and the remaining ones are recorded when the rebase is
+/// continued with this command, such as with `git branchless rebase
+/// --continue`.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn rebase(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    args: Vec<String>,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(now, "rebase")?;
+    let references_before = get_references_snapshot(&repo)?;
+
+    let git_args: Vec<&str> = std::iter::once("rebase")
+        .chain(args.iter().map(|arg| arg.as_str()))
+        .collect();
+    let exit_code = git_run_info.run(effects, Some(event_tx_id), &git_args)?;
+    event_log_db.add_transaction_command(event_tx_id, &git_args, exit_code)?;
+
+    // Open the repository again, since the rebase changed it behind our back.
+    let repo = Repo::from_current_dir()?;
+    let references_after = get_references_snapshot(&repo)?;
+    let recorded_events: Vec<Event> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter(|event| event.get_event_tx_id() == event_tx_id)
+        .collect();
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+    let missing_events = make_missing_events(
+        effects,
+        &repo,
+        event_tx_id,
+        timestamp,
+        &references_before,
+        &references_after,
+        &recorded_events,
+    )?;
+    if !missing_events.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: recorded {} which the hooks missed",
+            Pluralize {
+                amount: missing_events.len().try_into()?,
+                singular: "event",
+                plural: "events",
+            }
+            .to_string()
+        )?;
+        event_log_db.add_events(missing_events)?;
+    }
+
+    Ok(exit_code)
+}
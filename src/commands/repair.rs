@@ -0,0 +1,83 @@
+//! Rebuild the caches used by `git-branchless`.
+//!
+//! The merge-base cache and the commit DAG can be reconstructed at any time
+//! from the repository and the event log. If they become corrupted (for
+//! example, if the disk filled up or the process was killed while writing to
+//! them), they can be discarded and rebuilt without losing any history.
+
+use std::convert::TryInto;
+use std::fmt::Write;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::Pluralize;
+use crate::core::mergebase::rebuild_merge_base_db;
+use crate::git::Repo;
+use crate::tui::Effects;
+
+/// Check the integrity of the on-disk SQLite database.
+///
+/// Returns: A list of problems reported by SQLite. If the list is empty, then
+/// no problems were found.
+#[instrument]
+fn check_db_integrity(conn: &rusqlite::Connection) -> eyre::Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages: rusqlite::Result<Vec<String>> = stmt
+        .query_map(rusqlite::params![], |row| row.get(0))?
+        .collect();
+    let messages = messages.wrap_err("Checking database integrity")?;
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .collect())
+}
+
+/// Rebuild the merge-base cache and the commit DAG from scratch.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn repair(effects: &Effects) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+
+    let problems = check_db_integrity(&conn)?;
+    if !problems.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "The git-branchless database is corrupt and cannot be repaired automatically:"
+        )?;
+        for problem in problems {
+            writeln!(effects.get_output_stream(), "  {}", problem)?;
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "To start over with an empty event log, move this file out of the way: {:?}",
+            repo.get_db_path()?
+        )?;
+        return Ok(1);
+    }
+
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Rebuilding merge-base cache..."
+    )?;
+    rebuild_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+
+    let num_events = event_log_db.get_events()?.len();
+    writeln!(
+        effects.get_output_stream(),
+        "Repair complete. The event log was preserved ({}).",
+        Pluralize {
+            amount: num_events.try_into()?,
+            singular: "event",
+            plural: "events",
+        }
+        .to_string()
+    )?;
+    Ok(0)
+}
@@ -61,8 +61,9 @@ use std::time::SystemTime;
 
 use tracing::{instrument, warn};
 
-use crate::commands::smartlog::smartlog;
-use crate::core::config::get_restack_preserve_timestamps;
+use crate::commands::smartlog::{smartlog, SmartlogOptions};
+use crate::core::autostash::{should_autostash, with_stashed_changes};
+use crate::core::config::{get_restack_detect_duplicate_commits, get_restack_preserve_timestamps};
 use crate::core::eventlog::{EventLogDb, EventReplayer};
 use crate::core::graph::{
     make_graph, resolve_commits, BranchOids, HeadOid, MainBranchOid, ResolveCommitsResult,
@@ -76,7 +77,7 @@ use crate::git::{GitRunInfo, NonZeroOid, Repo};
 use crate::tui::Effects;
 
 #[instrument(skip(commits))]
-fn restack_commits(
+pub(crate) fn restack_commits(
     effects: &Effects,
     repo: &Repo,
     conn: &rusqlite::Connection,
@@ -180,7 +181,7 @@ fn restack_commits(
 }
 
 #[instrument]
-fn restack_branches(
+pub(crate) fn restack_branches(
     effects: &Effects,
     repo: &Repo,
     conn: &rusqlite::Connection,
@@ -249,7 +250,63 @@ fn restack_branches(
     Ok(0)
 }
 
-/// Restack all abandoned commits.
+/// Restack the abandoned commits and branches, then check out the commit which
+/// `HEAD` was rewritten into, if any.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument(skip(commits))]
+fn restack_and_checkout(
+    effects: &Effects,
+    repo: &Repo,
+    conn: &rusqlite::Connection,
+    git_run_info: &GitRunInfo,
+    event_log_db: &EventLogDb,
+    head_oid: Option<NonZeroOid>,
+    commits: Option<HashSet<NonZeroOid>>,
+    build_options: &BuildRebasePlanOptions,
+    execute_options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<isize> {
+    let result = restack_commits(
+        effects,
+        repo,
+        conn,
+        git_run_info,
+        event_log_db,
+        commits,
+        build_options,
+        execute_options,
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+
+    let result = restack_branches(
+        effects,
+        repo,
+        conn,
+        git_run_info,
+        event_log_db,
+        execute_options,
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+
+    let result = match head_oid {
+        Some(head_oid) => git_run_info.run(
+            effects,
+            Some(execute_options.event_tx_id),
+            &["checkout", &head_oid.to_string()],
+        )?,
+        None => result,
+    };
+
+    Ok(result)
+}
+
+/// Restack all abandoned commits. If `dedup` is set, it overrides
+/// `branchless.restack.detectDuplicateCommits`, and if `autostash` is set, it
+/// overrides `branchless.autoStash`.
 ///
 /// Returns an exit code (0 denotes successful exit).
 #[instrument]
@@ -257,6 +314,9 @@ pub fn restack(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     commits: Vec<String>,
+    strategy_options: Vec<String>,
+    dedup: Option<bool>,
+    autostash: Option<bool>,
     dump_rebase_constraints: bool,
     dump_rebase_plan: bool,
 ) -> eyre::Result<isize> {
@@ -283,7 +343,10 @@ pub fn restack(
     let build_options = BuildRebasePlanOptions {
         dump_rebase_constraints,
         dump_rebase_plan,
-        detect_duplicate_commits_via_patch_id: true,
+        detect_duplicate_commits_via_patch_id: match dedup {
+            Some(dedup) => dedup,
+            None => get_restack_detect_duplicate_commits(&repo)?,
+        },
     };
     let execute_options = ExecuteRebasePlanOptions {
         now,
@@ -292,43 +355,34 @@ pub fn restack(
         force_in_memory: false,
         // Use on-disk rebases only until `git move` is stabilized.
         force_on_disk: true,
+        strategy_options,
+        keep_originals: false,
     };
 
-    let result = restack_commits(
+    let result = with_stashed_changes(
         effects,
-        &repo,
-        &conn,
         git_run_info,
-        &event_log_db,
-        commits,
-        &build_options,
-        &execute_options,
-    )?;
-    if result != 0 {
-        return Ok(result);
-    }
-
-    let result = restack_branches(
-        effects,
         &repo,
-        &conn,
-        git_run_info,
-        &event_log_db,
-        &execute_options,
+        event_tx_id,
+        should_autostash(&repo, autostash)?,
+        || {
+            restack_and_checkout(
+                effects,
+                &repo,
+                &conn,
+                git_run_info,
+                &event_log_db,
+                head_oid,
+                commits,
+                &build_options,
+                &execute_options,
+            )
+        },
     )?;
     if result != 0 {
         return Ok(result);
     }
 
-    let result = match head_oid {
-        Some(head_oid) => git_run_info.run(
-            effects,
-            Some(event_tx_id),
-            &["checkout", &head_oid.to_string()],
-        )?,
-        None => result,
-    };
-
-    smartlog(effects)?;
-    Ok(result)
+    smartlog(effects, &SmartlogOptions::default())?;
+    Ok(0)
 }
@@ -0,0 +1,201 @@
+//! Recreate branches which were deleted.
+//!
+//! The reference updates recorded in the event log include the commit each
+//! deleted branch pointed to, so a branch can be restored without having to
+//! find its commit with `git undo` or the reflog.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::eventlog::{CommitVisibility, Event, EventLogDb, EventReplayer};
+use crate::core::formatting::printable_styled_string;
+use crate::git::GitRunInfo;
+use crate::git::{MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+const BRANCH_REF_PREFIX: &str = "refs/heads/";
+
+/// A branch which was deleted and hasn't been recreated since.
+#[derive(Debug)]
+struct DeletedBranch {
+    name: String,
+    oid: NonZeroOid,
+    timestamp: SystemTime,
+}
+
+/// Find the branches which were deleted and don't currently exist, along with
+/// the commit each one last pointed to. The most recently deleted branches
+/// come first.
+fn find_deleted_branches(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+) -> eyre::Result<Vec<DeletedBranch>> {
+    let all_events = event_replayer.get_events_since_cursor(event_replayer.make_cursor(0));
+    let mut deleted_branches: HashMap<String, DeletedBranch> = HashMap::new();
+    for event in all_events {
+        let (ref_name, old_oid, new_oid) = match event {
+            Event::RefUpdateEvent {
+                ref_name,
+                old_oid,
+                new_oid,
+                ..
+            } => (ref_name, old_oid, new_oid),
+            _ => continue,
+        };
+        let name = match ref_name
+            .to_str()
+            .and_then(|ref_name| ref_name.strip_prefix(BRANCH_REF_PREFIX))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        match (old_oid, new_oid) {
+            (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::Zero) => {
+                deleted_branches.insert(
+                    name.to_owned(),
+                    DeletedBranch {
+                        name: name.to_owned(),
+                        oid: *old_oid,
+                        timestamp: event.get_timestamp(),
+                    },
+                );
+            }
+            (_, MaybeZeroOid::NonZero(_)) => {
+                deleted_branches.remove(name);
+            }
+            (MaybeZeroOid::Zero, MaybeZeroOid::Zero) => {}
+        }
+    }
+
+    let mut result = Vec::new();
+    for deleted_branch in deleted_branches.into_values() {
+        if repo
+            .find_branch(&deleted_branch.name, git2::BranchType::Local)?
+            .is_none()
+        {
+            result.push(deleted_branch);
+        }
+    }
+    result.sort_by(|lhs, rhs| {
+        rhs.timestamp
+            .cmp(&lhs.timestamp)
+            .then_with(|| lhs.name.cmp(&rhs.name))
+    });
+    Ok(result)
+}
+
+/// Recreate the branch with the given name at the commit it pointed to when it
+/// was last deleted, unhiding the commit if necessary. If no name is given,
+/// list the branches which can be restored instead.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn restore_branch(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    name: Option<String>,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let deleted_branches = find_deleted_branches(&repo, &event_replayer)?;
+
+    let name = match name {
+        Some(name) => name,
+        None => {
+            if deleted_branches.is_empty() {
+                writeln!(effects.get_output_stream(), "No deleted branches found.")?;
+                return Ok(0);
+            }
+            writeln!(effects.get_output_stream(), "Deleted branches:")?;
+            for DeletedBranch {
+                name,
+                oid,
+                timestamp: _,
+            } in deleted_branches
+            {
+                writeln!(
+                    effects.get_output_stream(),
+                    "  {} at {}",
+                    name,
+                    printable_styled_string(glyphs, repo.friendly_describe_commit_from_oid(oid)?)?
+                )?;
+            }
+            writeln!(
+                effects.get_output_stream(),
+                "To restore one of them, run: git branchless restore-branch <name>"
+            )?;
+            return Ok(0);
+        }
+    };
+
+    let name = name
+        .strip_prefix(BRANCH_REF_PREFIX)
+        .map(ToOwned::to_owned)
+        .unwrap_or(name);
+    if repo.find_branch(&name, git2::BranchType::Local)?.is_some() {
+        writeln!(
+            effects.get_error_stream(),
+            "Branch {} already exists.",
+            name
+        )?;
+        return Ok(1);
+    }
+    let oid = match deleted_branches
+        .into_iter()
+        .find(|deleted_branch| deleted_branch.name == name)
+    {
+        Some(DeletedBranch { oid, .. }) => oid,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "No deleted branch named {} was found in the event log.",
+                name
+            )?;
+            return Ok(1);
+        }
+    };
+    if repo.find_commit(oid)?.is_none() {
+        writeln!(
+            effects.get_error_stream(),
+            "Branch {} pointed to commit {}, which no longer exists.",
+            name,
+            oid
+        )?;
+        return Ok(1);
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "restore-branch")?;
+    let cursor = event_replayer.make_default_cursor();
+    if let Some(CommitVisibility::Hidden) = event_replayer.get_cursor_commit_visibility(cursor, oid)
+    {
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        event_log_db.add_events(vec![Event::UnhideEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: oid,
+        }])?;
+    }
+
+    let exit_code = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["branch", &name, &oid.to_string()],
+    )?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Restored branch {} at {}",
+        name,
+        printable_styled_string(glyphs, repo.friendly_describe_commit_from_oid(oid)?)?
+    )?;
+    Ok(0)
+}
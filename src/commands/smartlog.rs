@@ -3,25 +3,41 @@
 //! The set of commits that are still being worked on is inferred from the event
 //! log; see the `eventlog` module.
 
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
-use std::time::SystemTime;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 
-use cursive::theme::Effect;
 use cursive::utils::markup::StyledString;
+use eyre::Context;
 use tracing::instrument;
 
-use crate::core::eventlog::{EventLogDb, EventReplayer};
-use crate::core::formatting::set_effect;
-use crate::core::formatting::{printable_styled_string, Glyphs, StyledStringBuilder};
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::anonymize::Anonymizer;
+use crate::core::config::{
+    get_commit_descriptors, get_smartlog_commit_url_template, get_smartlog_default_revset,
+    get_smartlog_header, get_smartlog_hyperlinks, get_smartlog_truncate_lines,
+};
+use crate::core::eventlog::{get_current_user, EventLogDb, EventReplayer};
+use crate::core::formatting::{
+    display_width, printable_styled_string, render_lines_as_html_document, set_style,
+    truncate_styled_string, Glyphs, Hyperlinks, Palette, Pluralize, StyledStringBuilder,
+};
+use crate::core::graph::{
+    filter_graph, make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid,
+};
 use crate::core::mergebase::{make_merge_base_db, MergeBaseDb};
 use crate::core::metadata::{
-    render_commit_metadata, BranchesProvider, CommitMessageProvider, CommitMetadataProvider,
-    CommitOidProvider, DifferentialRevisionProvider, HiddenExplanationProvider,
-    RelativeTimeProvider,
+    render_commit_metadata, BranchesProvider, ChangeIdProvider, CiStatusProvider,
+    CommitMessageProvider, CommitMetadataProvider, CommitOidProvider, DifferentialRevisionProvider,
+    HiddenExplanationProvider, RelativeTimeProvider, TemplateProvider, TransactionProvider,
 };
-use crate::git::{NonZeroOid, Repo};
+use crate::core::revset::{evaluate_revset, parse_revset};
+use crate::git::{CategorizedReferenceName, Commit, NonZeroOid, Repo};
 use crate::tui::Effects;
 
 /// Split fully-independent subgraphs into multiple graphs.
@@ -78,16 +94,78 @@ fn split_commit_graph_by_roots(
     root_commit_oids
 }
 
+/// For each root (other than the first one) which descends from the previous
+/// root, count the commits between the two roots which aren't part of the
+/// graph, to show how far apart they are in the rendered smartlog.
+///
+/// Returns: A map from each such root to the number of omitted commits. Roots
+/// which are direct children of the previous root aren't included.
+#[instrument(skip(graph))]
+fn count_omitted_commits(
+    effects: &Effects,
+    repo: &Repo,
+    merge_base_db: &impl MergeBaseDb,
+    graph: &CommitGraph,
+    root_oids: &[NonZeroOid],
+) -> eyre::Result<HashMap<NonZeroOid, usize>> {
+    let mut result = HashMap::new();
+    for (prev_root_oid, root_oid) in root_oids.iter().zip(root_oids.iter().skip(1)) {
+        if !graph[root_oid].is_main {
+            continue;
+        }
+        let merge_base_oid =
+            merge_base_db.get_merge_base_oid(effects, repo, *prev_root_oid, *root_oid)?;
+        if merge_base_oid != Some(*prev_root_oid) {
+            continue;
+        }
+        // Don't count the root itself.
+        let num_omitted_commits = repo
+            .count_commits_between(*prev_root_oid, *root_oid)?
+            .saturating_sub(1);
+        if num_omitted_commits > 0 {
+            result.insert(*root_oid, num_omitted_commits);
+        }
+    }
+    Ok(result)
+}
+
+/// A line of the rendered smartlog graph.
+struct GraphLine {
+    line: StyledString,
+
+    /// The commit rendered on this line, if any, and the column of its cursor.
+    commit: Option<(NonZeroOid, usize)>,
+}
+
+impl GraphLine {
+    fn plain(line: StyledString) -> Self {
+        GraphLine { line, commit: None }
+    }
+
+    /// Prepend the given two-column prefix to the line.
+    fn with_prefix(self, prefix: String) -> Self {
+        let GraphLine { line, commit } = self;
+        GraphLine {
+            line: StyledStringBuilder::new()
+                .append_plain(prefix)
+                .append(line)
+                .build(),
+            commit: commit.map(|(oid, column)| (oid, column + 2)),
+        }
+    }
+}
+
 #[instrument(skip(commit_metadata_providers, graph))]
 fn get_child_output(
     glyphs: &Glyphs,
     graph: &CommitGraph,
     root_oids: &[NonZeroOid],
+    duplicated_merge_oids: &HashSet<NonZeroOid>,
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
     head_oid: &HeadOid,
     current_oid: NonZeroOid,
     last_child_line_char: Option<&str>,
-) -> eyre::Result<Vec<StyledString>> {
+) -> eyre::Result<Vec<GraphLine>> {
     let current_node = &graph[&current_oid];
     let is_head = {
         let HeadOid(head_oid) = head_oid;
@@ -112,17 +190,29 @@ fn get_child_output(
         first_line.append_plain(" ");
         first_line.append(text);
         if is_head {
-            set_effect(first_line, Effect::Bold)
+            set_style(first_line, Palette::current().emphasis)
         } else {
             first_line
         }
     };
 
-    let mut lines = vec![first_line];
+    let mut lines = vec![GraphLine {
+        line: first_line,
+        commit: Some((current_oid, 0)),
+    }];
     let children: Vec<_> = current_node
         .children
         .iter()
-        .filter(|child_oid| graph.contains_key(child_oid))
+        .filter(|child_oid| match graph.get(child_oid) {
+            // Merge commits are rendered under their first parent, and
+            // connected to their other parents afterwards.
+            Some(child_node) => {
+                child_node.parent == Some(current_oid)
+                    || !child_node.merge_parents.contains(&current_oid)
+                    || duplicated_merge_oids.contains(child_oid)
+            }
+            None => false,
+        })
         .copied()
         .collect();
     for (child_idx, child_oid) in children.iter().enumerate() {
@@ -139,18 +229,19 @@ fn get_child_output(
 
                 None => StyledString::plain(glyphs.line.to_string()),
             };
-            lines.push(line)
+            lines.push(GraphLine::plain(line))
         } else {
-            lines.push(StyledString::plain(format!(
+            lines.push(GraphLine::plain(StyledString::plain(format!(
                 "{}{}",
                 glyphs.line_with_offshoot, glyphs.slash
-            )))
+            ))))
         }
 
         let child_output = get_child_output(
             glyphs,
             graph,
             root_oids,
+            duplicated_merge_oids,
             commit_metadata_providers,
             head_oid,
             *child_oid,
@@ -159,22 +250,178 @@ fn get_child_output(
         for child_line in child_output {
             let line = if child_idx == children.len() - 1 {
                 match last_child_line_char {
-                    Some(last_child_line_char) => StyledStringBuilder::new()
-                        .append_plain(format!("{} ", last_child_line_char))
-                        .append(child_line)
-                        .build(),
+                    Some(last_child_line_char) => {
+                        child_line.with_prefix(format!("{} ", last_child_line_char))
+                    }
                     None => child_line,
                 }
             } else {
+                child_line.with_prefix(format!("{} ", glyphs.line))
+            };
+            lines.push(line)
+        }
+    }
+    Ok(lines)
+}
+
+/// Split the line at the given display column. The column must not fall in the
+/// middle of a wide character or a hyperlink.
+fn split_at_column(line: &StyledString, column: usize) -> (StyledString, StyledString) {
+    let mut left = StyledString::new();
+    let mut right = StyledString::new();
+    let mut width = 0;
+    for span in line.spans() {
+        let span_width = display_width(span.content);
+        if width >= column {
+            right.append_styled(span.content, *span.attr);
+        } else if width + span_width <= column {
+            left.append_styled(span.content, *span.attr);
+        } else {
+            let left_content = console::truncate_str(span.content, column - width, "");
+            let right_content = &span.content[left_content.len()..];
+            left.append_styled(left_content.as_ref(), *span.attr);
+            right.append_styled(right_content, *span.attr);
+        }
+        width += span_width;
+    }
+    (left, right)
+}
+
+/// Find the lines which a line connecting the given parent commit to the given
+/// merge commit would pass through, given as `(line index, column)` positions.
+/// The last of these lines is where the connecting line joins the merge
+/// commit's column.
+///
+/// Returns: The indexes of the lines, or `None` if there's no room for the
+/// connecting line.
+fn find_merge_lane(
+    glyphs: &Glyphs,
+    lines: &[GraphLine],
+    touched_line_idxs: &HashSet<usize>,
+    (parent_line_idx, parent_column): (usize, usize),
+    (merge_line_idx, merge_column): (usize, usize),
+) -> Option<Range<usize>> {
+    if parent_line_idx + 1 >= merge_line_idx || parent_column != merge_column + 2 {
+        return None;
+    }
+
+    // The connecting line joins the merge commit's column on the line just
+    // above the merge commit, which must be a plain vertical line.
+    let join_line = &lines[merge_line_idx - 1];
+    let join_source = join_line.line.source();
+    if join_line.commit.is_some()
+        || display_width(join_source) != merge_column + 1
+        || !join_source.ends_with(glyphs.line)
+    {
+        return None;
+    }
+
+    let lane_line_idxs = parent_line_idx + 1..merge_line_idx;
+    let has_room = lane_line_idxs.clone().all(|line_idx| {
+        let line = &lines[line_idx];
+        !touched_line_idxs.contains(&line_idx)
+            && match line.commit {
+                Some((_oid, column)) => column == merge_column,
+                None => display_width(line.line.source()) <= merge_column + 1,
+            }
+    });
+    if has_room {
+        Some(lane_line_idxs)
+    } else {
+        None
+    }
+}
+
+/// Draw lines connecting merge commits to their parents other than the one
+/// they were rendered under. This is only possible when the other parent is
+/// rendered above the merge commit in the column directly to its right, and
+/// the lines in between have room for the connecting line.
+///
+/// Returns: The merge commits which couldn't be connected to all of their
+/// parents.
+fn connect_merge_parents(
+    glyphs: &Glyphs,
+    graph: &CommitGraph,
+    lines: &mut [GraphLine],
+    duplicated_merge_oids: &HashSet<NonZeroOid>,
+) -> Vec<NonZeroOid> {
+    let mut positions: HashMap<NonZeroOid, (usize, usize)> = HashMap::new();
+    for (line_idx, line) in lines.iter().enumerate() {
+        if let Some((oid, column)) = line.commit {
+            positions.entry(oid).or_insert((line_idx, column));
+        }
+    }
+
+    let mut merges: Vec<(NonZeroOid, usize, usize)> = graph
+        .iter()
+        .filter(|(oid, node)| {
+            !node.merge_parents.is_empty() && !duplicated_merge_oids.contains(oid)
+        })
+        .filter_map(|(oid, _node)| {
+            positions
+                .get(oid)
+                .map(|(line_idx, column)| (*oid, *line_idx, *column))
+        })
+        .collect();
+    merges.sort_by_key(|(_oid, line_idx, _column)| *line_idx);
+
+    let mut touched_line_idxs: HashSet<usize> = HashSet::new();
+    let mut unconnected_merge_oids = Vec::new();
+    for (merge_oid, merge_line_idx, merge_column) in merges {
+        let merge_parent_oids: Vec<&NonZeroOid> = graph[&merge_oid]
+            .merge_parents
+            .iter()
+            .filter(|parent_oid| graph.contains_key(parent_oid))
+            .collect();
+        let lane_line_idxs = match merge_parent_oids.as_slice() {
+            [parent_oid] => positions.get(parent_oid).and_then(|parent_position| {
+                find_merge_lane(
+                    glyphs,
+                    lines,
+                    &touched_line_idxs,
+                    *parent_position,
+                    (merge_line_idx, merge_column),
+                )
+            }),
+            _ => None,
+        };
+        let lane_line_idxs = match lane_line_idxs {
+            Some(lane_line_idxs) => lane_line_idxs,
+            None => {
+                unconnected_merge_oids.push(merge_oid);
+                continue;
+            }
+        };
+
+        let lane_column = merge_column + 2;
+        for line_idx in lane_line_idxs {
+            touched_line_idxs.insert(line_idx);
+            let line = &mut lines[line_idx];
+            line.line = if line_idx == merge_line_idx - 1 {
+                let (left, _right) = split_at_column(&line.line, merge_column);
+                StyledStringBuilder::new()
+                    .append(left)
+                    .append_plain(glyphs.line_with_offshoot)
+                    .append_plain(glyphs.merge_slash)
+                    .build()
+            } else if line.commit.is_some() {
+                let (left, right) = split_at_column(&line.line, lane_column);
                 StyledStringBuilder::new()
+                    .append(left)
                     .append_plain(format!("{} ", glyphs.line))
-                    .append(child_line)
+                    .append(right)
+                    .build()
+            } else {
+                let padding = lane_column - display_width(line.line.source());
+                StyledStringBuilder::new()
+                    .append(line.line.clone())
+                    .append_plain(" ".repeat(padding))
+                    .append_plain(glyphs.line)
                     .build()
             };
-            lines.push(line)
         }
     }
-    Ok(lines)
+    unconnected_merge_oids
 }
 
 /// Render a pretty graph starting from the given root OIDs in the given graph.
@@ -185,7 +432,39 @@ fn get_output(
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
     head_oid: &HeadOid,
     root_oids: &[NonZeroOid],
+    omitted_commit_counts: &HashMap<NonZeroOid, usize>,
 ) -> eyre::Result<Vec<StyledString>> {
+    // Merge commits which can't be connected to all of their parents are
+    // instead rendered under each of their parents.
+    let mut duplicated_merge_oids = HashSet::new();
+    loop {
+        let mut lines = get_graph_lines(
+            glyphs,
+            graph,
+            commit_metadata_providers,
+            head_oid,
+            root_oids,
+            omitted_commit_counts,
+            &duplicated_merge_oids,
+        )?;
+        let unconnected_merge_oids =
+            connect_merge_parents(glyphs, graph, &mut lines, &duplicated_merge_oids);
+        if unconnected_merge_oids.is_empty() {
+            return Ok(lines.into_iter().map(|line| line.line).collect());
+        }
+        duplicated_merge_oids.extend(unconnected_merge_oids);
+    }
+}
+
+fn get_graph_lines(
+    glyphs: &Glyphs,
+    graph: &CommitGraph,
+    commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
+    head_oid: &HeadOid,
+    root_oids: &[NonZeroOid],
+    omitted_commit_counts: &HashMap<NonZeroOid, usize>,
+    duplicated_merge_oids: &HashSet<NonZeroOid>,
+) -> eyre::Result<Vec<GraphLine>> {
     let mut lines = Vec::new();
 
     // Determine if the provided OID has the provided parent OID as a parent.
@@ -207,13 +486,25 @@ fn get_output(
             let line = if root_idx > 0 && has_real_parent(*root_oid, root_oids[root_idx - 1]) {
                 StyledString::plain(glyphs.line.to_owned())
             } else {
-                StyledString::plain(glyphs.vertical_ellipsis.to_owned())
+                match omitted_commit_counts.get(root_oid) {
+                    Some(num_omitted_commits) => StyledString::plain(format!(
+                        "{} ({})",
+                        glyphs.vertical_ellipsis,
+                        Pluralize {
+                            amount: (*num_omitted_commits).try_into()?,
+                            singular: "commit",
+                            plural: "commits",
+                        }
+                        .to_string()
+                    )),
+                    None => StyledString::plain(glyphs.vertical_ellipsis.to_owned()),
+                }
             };
-            lines.push(line);
+            lines.push(GraphLine::plain(line));
         } else if root_idx > 0 {
             // Pathological case: multiple topologically-unrelated roots.
             // Separate them with a newline.
-            lines.push(StyledString::new());
+            lines.push(GraphLine::plain(StyledString::new()));
         }
 
         let last_child_line_char = {
@@ -233,12 +524,13 @@ fn get_output(
             glyphs,
             graph,
             root_oids,
+            duplicated_merge_oids,
             commit_metadata_providers,
             head_oid,
             *root_oid,
             last_child_line_char,
         )?;
-        lines.extend(child_output.into_iter());
+        lines.extend(child_output);
     }
 
     Ok(lines)
@@ -255,28 +547,147 @@ pub fn render_graph(
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
 ) -> eyre::Result<Vec<StyledString>> {
     let root_oids = split_commit_graph_by_roots(effects, repo, merge_base_db, graph);
+    let omitted_commit_counts =
+        count_omitted_commits(effects, repo, merge_base_db, graph, &root_oids)?;
     let lines = get_output(
         effects.get_glyphs(),
         graph,
         commit_metadata_providers,
         head_oid,
         &root_oids,
+        &omitted_commit_counts,
     )?;
     Ok(lines)
 }
 
+/// The template used to render each commit in the smartlog when no template is
+/// passed to `git smartlog --format`.
+pub const DEFAULT_SMARTLOG_FORMAT: &str = "%h %ar %(provider:hidden-explanation) %d \
+%(provider:ci-status) %(provider:phabricator) %(provider:change-id) \
+%(provider:transaction) %s";
+
+/// Describe the current checkout for the header of the smartlog, such as `On
+/// branch foo, with uncommitted changes` or `Detached HEAD at abc12345, no
+/// uncommitted changes`. Untracked files don't count as uncommitted changes.
+fn describe_checkout_state(
+    effects: &Effects,
+    repo: &Repo,
+    anonymizer: Option<&RefCell<Anonymizer>>,
+) -> eyre::Result<String> {
+    let head_info = repo.get_head_info()?;
+    let checkout = match (head_info.get_branch_name(), head_info.oid) {
+        (Some(branch_name), _) => {
+            let branch_name = match anonymizer {
+                Some(anonymizer) => CategorizedReferenceName::new(OsStr::new(
+                    &anonymizer
+                        .borrow_mut()
+                        .anonymize_ref_name(OsStr::new(&format!("refs/heads/{}", branch_name))),
+                ))
+                .render_suffix(),
+                None => branch_name.to_string(),
+            };
+            format!("On branch {}", branch_name)
+        }
+        (None, Some(head_oid)) => {
+            let head_oid = match anonymizer {
+                Some(anonymizer) => anonymizer.borrow_mut().anonymize_oid(head_oid),
+                None => head_oid.to_string(),
+            };
+            format!("Detached HEAD at {}", &head_oid[..8])
+        }
+        (None, None) => "Detached HEAD".to_string(),
+    };
+    let changes = if repo.has_changed_files(effects)? {
+        "with uncommitted changes"
+    } else {
+        "no uncommitted changes"
+    };
+    Ok(format!("{}, {}", checkout, changes))
+}
+
+/// Describe the multi-step operation (such as a rebase or a merge) which is
+/// underway in the repository and how to finish it, such as `rebase in
+/// progress: 3/10, resolve and run git rebase --continue`. Returns `None` if
+/// there is no such operation.
+pub fn describe_operation_in_progress(repo: &Repo) -> Option<String> {
+    let operation_type = repo.get_current_operation_type()?;
+    let progress = match (operation_type, repo.get_rebase_progress()) {
+        ("rebase", Some((current, total))) => format!(": {}/{}", current, total),
+        _ => String::new(),
+    };
+    Some(format!(
+        "{} in progress{}, resolve and run git {} --continue",
+        operation_type, progress, operation_type
+    ))
+}
+
+/// Options for rendering the smartlog.
+#[derive(Clone, Debug, Default)]
+pub struct SmartlogOptions {
+    /// Also display the change ID and version of each draft commit, and the
+    /// transaction which created or last affected it.
+    pub verbose: bool,
+
+    /// The template to render each commit with. If not provided, the
+    /// providers in `branchless.commitDescriptors` are shown, or those in
+    /// `DEFAULT_SMARTLOG_FORMAT` if it's unset. See `TemplateProvider` for the
+    /// template syntax.
+    pub format: Option<String>,
+
+    /// Consider the events of all users of the repository, rather than only
+    /// those of the current user. Otherwise, commits created or hidden by
+    /// other users of a shared repository don't affect the smartlog.
+    pub all_users: bool,
+
+    /// Render the smartlog as a standalone HTML document, keeping its colors
+    /// and styling, rather than for the terminal.
+    pub html: bool,
+
+    /// Show all of the commits, even if `branchless.smartlog.defaultRevset`
+    /// is set.
+    pub show_all: bool,
+
+    /// Replace commit hashes, commit messages and branch names with stable
+    /// placeholders, and leave out the other metadata which may identify the
+    /// repository, such as transaction commands and Phabricator revisions.
+    /// The shape of the graph and the timestamps are kept, so that the output
+    /// can be shared to reproduce rendering problems.
+    pub anonymize: bool,
+}
+
+/// A provider for metadata which is left out of the smartlog, such as when
+/// anonymizing it.
+#[derive(Debug)]
+struct OmittedProvider;
+
+impl CommitMetadataProvider for OmittedProvider {
+    fn describe_commit(&mut self, _commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        Ok(None)
+    }
+}
+
 /// Display a nice graph of commits you've recently worked on.
 #[instrument]
-pub fn smartlog(effects: &Effects) -> eyre::Result<()> {
+pub fn smartlog(effects: &Effects, options: &SmartlogOptions) -> eyre::Result<()> {
+    let SmartlogOptions {
+        verbose,
+        format,
+        all_users,
+        html,
+        show_all,
+        anonymize,
+    } = options;
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let user = if *all_users { None } else { get_current_user() };
+    let event_replayer =
+        EventReplayer::from_event_log_db_for_user(effects, &repo, &event_log_db, user.as_deref())?;
     let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
     let head_oid = repo.get_head_info()?.oid;
     let main_branch_oid = repo.get_main_branch_oid()?;
     let branch_oid_to_names = repo.get_branch_oid_to_names()?;
-    let graph = make_graph(
+    let mut graph = make_graph(
         effects,
         &repo,
         &merge_base_db,
@@ -287,27 +698,163 @@ pub fn smartlog(effects: &Effects) -> eyre::Result<()> {
         &BranchOids(branch_oid_to_names.keys().cloned().collect()),
         true,
     )?;
+    if !*show_all {
+        if let Some(revset) = get_smartlog_default_revset(&repo)? {
+            let oids_to_keep = parse_revset(&revset)
+                .and_then(|revset| evaluate_revset(&repo, &graph, &revset))
+                .wrap_err_with(|| {
+                    format!("Evaluating branchless.smartlog.defaultRevset: {:?}", revset)
+                })?;
+            filter_graph(&mut graph, &HeadOid(head_oid), &oids_to_keep);
+        }
+    }
+
+    let commit_descriptors = get_commit_descriptors(&repo)?;
+    let (template, is_template_explicit) = match (format, commit_descriptors) {
+        (Some(format), _) => (format.clone(), true),
+        (None, Some(commit_descriptors)) => (
+            commit_descriptors
+                .iter()
+                .map(|name| format!("%(provider:{})", name))
+                .collect::<Vec<_>>()
+                .join(" "),
+            true,
+        ),
+        (None, None) => (DEFAULT_SMARTLOG_FORMAT.to_string(), false),
+    };
 
-    let lines = render_graph(
+    // The change ID and transaction are only shown by default in verbose mode,
+    // but are always shown if they're explicitly included in a template.
+    let is_verbose = *verbose || is_template_explicit;
+    let hyperlinks = if *html {
+        // Links can always be rendered in HTML, regardless of the terminal.
+        Hyperlinks::new(get_smartlog_hyperlinks(&repo)?.unwrap_or(true))
+    } else {
+        Hyperlinks::detect(effects.get_glyphs(), get_smartlog_hyperlinks(&repo)?)
+    };
+    let now = SystemTime::now();
+    let anonymizer = Rc::new(RefCell::new(Anonymizer::default()));
+    let mut template_provider = TemplateProvider::new(
+        &template,
+        |name| -> eyre::Result<Option<Box<dyn CommitMetadataProvider>>> {
+            let provider: Box<dyn CommitMetadataProvider> = match name {
+                "oid" => {
+                    let provider = CommitOidProvider::new(true)?
+                        .with_hyperlinks(hyperlinks, get_smartlog_commit_url_template(&repo)?);
+                    if *anonymize {
+                        Box::new(provider.with_anonymizer(Rc::clone(&anonymizer)))
+                    } else {
+                        Box::new(provider)
+                    }
+                }
+                "relative-time" => Box::new(RelativeTimeProvider::new(&repo, now)?),
+                "hidden-explanation" => {
+                    let provider = HiddenExplanationProvider::new(
+                        &graph,
+                        &event_replayer,
+                        event_replayer.make_default_cursor(),
+                    )?;
+                    if *anonymize {
+                        Box::new(provider.with_anonymizer(Rc::clone(&anonymizer)))
+                    } else {
+                        Box::new(provider)
+                    }
+                }
+                "branches" => {
+                    let provider = BranchesProvider::new(&repo, &branch_oid_to_names)?;
+                    if *anonymize {
+                        Box::new(provider.with_anonymizer(Rc::clone(&anonymizer)))
+                    } else {
+                        Box::new(provider)
+                    }
+                }
+                "phabricator" | "change-id" | "transaction" if *anonymize => {
+                    Box::new(OmittedProvider)
+                }
+                "ci-status" => Box::new(CiStatusProvider::new(
+                    effects.get_glyphs(),
+                    &repo,
+                    &conn,
+                    &branch_oid_to_names,
+                )?),
+                "phabricator" => Box::new(
+                    DifferentialRevisionProvider::new(&repo, &graph)?.with_hyperlinks(hyperlinks),
+                ),
+                "change-id" => Box::new(ChangeIdProvider::new(
+                    is_verbose,
+                    &graph,
+                    &event_replayer,
+                    event_replayer.make_default_cursor(),
+                )?),
+                "transaction" => Box::new(TransactionProvider::new(
+                    is_verbose,
+                    &repo,
+                    now,
+                    &graph,
+                    &event_log_db,
+                    &event_replayer,
+                    event_replayer.make_default_cursor(),
+                )?),
+                "message" => {
+                    let provider = CommitMessageProvider::new()?;
+                    if *anonymize {
+                        Box::new(provider.with_anonymizer(Rc::clone(&anonymizer)))
+                    } else {
+                        Box::new(provider)
+                    }
+                }
+                _ => return Ok(None),
+            };
+            Ok(Some(provider))
+        },
+    )
+    .wrap_err_with(|| format!("Parsing smartlog template: {:?}", template))?;
+    let mut lines = render_graph(
         effects,
         &repo,
         &merge_base_db,
         &graph,
         &HeadOid(head_oid),
-        &mut [
-            &mut CommitOidProvider::new(true)?,
-            &mut RelativeTimeProvider::new(&repo, SystemTime::now())?,
-            &mut HiddenExplanationProvider::new(
-                &graph,
-                &event_replayer,
-                event_replayer.make_default_cursor(),
-            )?,
-            &mut BranchesProvider::new(&repo, &branch_oid_to_names)?,
-            &mut DifferentialRevisionProvider::new(&repo)?,
-            &mut CommitMessageProvider::new()?,
-        ],
+        &mut [&mut template_provider],
     )?;
+    if get_smartlog_header(&repo)? {
+        let anonymizer = if *anonymize { Some(&*anonymizer) } else { None };
+        let header = describe_checkout_state(effects, &repo, anonymizer)?;
+        lines.insert(0, StyledString::styled(header, Palette::current().emphasis));
+    }
+    if *html {
+        write!(
+            effects.get_output_stream(),
+            "{}",
+            render_lines_as_html_document("git smartlog", &lines)?
+        )?;
+        return Ok(());
+    }
+
+    if let Some(description) = describe_operation_in_progress(&repo) {
+        let palette = Palette::current();
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            printable_styled_string(
+                effects.get_glyphs(),
+                StyledString::styled(description, palette.error.combine(palette.emphasis))
+            )?
+        )?;
+    }
+
+    let max_width = if get_smartlog_truncate_lines(&repo)? {
+        effects.get_terminal_width()
+    } else {
+        None
+    };
     for line in lines {
+        let line = match max_width {
+            Some(max_width) => {
+                truncate_styled_string(line, max_width, effects.get_glyphs().ellipsis)
+            }
+            None => line,
+        };
         writeln!(
             effects.get_output_stream(),
             "{}",
@@ -317,3 +864,51 @@ pub fn smartlog(effects: &Effects) -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// How often `watch_smartlog` checks whether the repository has changed.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The state of the repository which the smartlog depends on, used by
+/// `watch_smartlog` to detect when it should be rendered again.
+#[derive(Debug, PartialEq)]
+struct WatchState {
+    events_version: (isize, isize),
+    head_oid: Option<NonZeroOid>,
+    branch_oid_to_names: HashMap<NonZeroOid, HashSet<OsString>>,
+}
+
+impl WatchState {
+    fn read() -> eyre::Result<Self> {
+        let repo = Repo::from_current_dir()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        Ok(WatchState {
+            events_version: event_log_db.get_events_version()?,
+            head_oid: repo.get_head_info()?.oid,
+            branch_oid_to_names: repo.get_branch_oid_to_names()?,
+        })
+    }
+}
+
+/// Display the smartlog, and display it again whenever the event log, `HEAD`
+/// or the branches change, until interrupted. The references are checked as
+/// well as the event log so that changes made without the hooks installed
+/// are also picked up.
+#[instrument]
+pub fn watch_smartlog(effects: &Effects, options: &SmartlogOptions) -> eyre::Result<()> {
+    let term = console::Term::stdout();
+    let mut last_state = None;
+    loop {
+        let state = WatchState::read()?;
+        if last_state.as_ref() != Some(&state) {
+            if term.is_term() {
+                term.clear_screen()?;
+            } else if last_state.is_some() {
+                writeln!(effects.get_output_stream())?;
+            }
+            smartlog(effects, options)?;
+            last_state = Some(state);
+        }
+        std::thread::sleep(WATCH_POLL_INTERVAL);
+    }
+}
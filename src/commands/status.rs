@@ -0,0 +1,206 @@
+//! Report on the state of the commit graph which needs attention.
+//!
+//! Currently, this reports any interrupted rebase or merge, and lists the
+//! commits and branches which have been abandoned by rewriting one of their
+//! ancestors, along with the `git restack` invocation which would fix each of
+//! them up.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fmt::Write;
+
+use tracing::instrument;
+
+use crate::commands::smartlog::describe_operation_in_progress;
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::find_abandoned_children;
+use crate::git::{CategorizedReferenceName, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// A rewritten commit which still has visible descendants or branches
+/// attached to it.
+struct AbandonedSubtree {
+    /// The original version of the commit.
+    old_oid: NonZeroOid,
+
+    /// The rewritten version of the commit, or `None` if it was deleted.
+    rewritten_oid: Option<NonZeroOid>,
+
+    /// The visible commits descending from the abandoned commit.
+    num_commits: usize,
+
+    /// The branches pointing to the abandoned commit.
+    branch_names: Vec<String>,
+}
+
+/// Count the visible commits in the subtrees rooted at the given commits
+/// (including the commits themselves).
+fn count_visible_descendants(graph: &CommitGraph, root_oids: &[NonZeroOid]) -> usize {
+    let mut seen: HashSet<NonZeroOid> = HashSet::new();
+    let mut stack: Vec<NonZeroOid> = root_oids.to_vec();
+    while let Some(oid) = stack.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        if let Some(node) = graph.get(&oid) {
+            stack.extend(node.children.iter().copied());
+        }
+    }
+    seen.into_iter()
+        .filter(|oid| matches!(graph.get(oid), Some(node) if node.is_visible))
+        .count()
+}
+
+/// Print the abandoned subtrees in the commit graph, with the number of
+/// commits and the branches in each, and how to restack them.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn status(effects: &Effects) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_cursor,
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut oids: Vec<NonZeroOid> = graph
+        .iter()
+        .filter(|(_oid, node)| !node.is_visible)
+        .map(|(oid, _node)| *oid)
+        .collect();
+    oids.sort_unstable();
+    let mut abandoned_subtrees = Vec::new();
+    for old_oid in oids {
+        let (rewritten_oid, abandoned_child_oids) =
+            match find_abandoned_children(&graph, &event_replayer, event_cursor, old_oid) {
+                Some(result) => result,
+                None => continue,
+            };
+        let mut branch_names: Vec<String> = branch_oid_to_names
+            .get(&old_oid)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(OsString::as_os_str)
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                    .collect()
+            })
+            .unwrap_or_default();
+        branch_names.sort_unstable();
+        let num_commits = count_visible_descendants(&graph, &abandoned_child_oids);
+        if num_commits == 0 && branch_names.is_empty() {
+            continue;
+        }
+        abandoned_subtrees.push(AbandonedSubtree {
+            old_oid,
+            rewritten_oid: if rewritten_oid == old_oid {
+                None
+            } else {
+                Some(rewritten_oid)
+            },
+            num_commits,
+            branch_names,
+        });
+    }
+
+    if let Some(description) = describe_operation_in_progress(&repo) {
+        writeln!(effects.get_output_stream(), "{}", description)?;
+    }
+
+    if abandoned_subtrees.is_empty() {
+        writeln!(effects.get_output_stream(), "No abandoned commits.")?;
+        return Ok(0);
+    }
+
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "Found {}:",
+        Pluralize {
+            amount: abandoned_subtrees.len().try_into()?,
+            singular: "abandoned subtree",
+            plural: "abandoned subtrees",
+        }
+        .to_string()
+    )?;
+    for AbandonedSubtree {
+        old_oid,
+        rewritten_oid,
+        num_commits,
+        branch_names,
+    } in abandoned_subtrees
+    {
+        let mut items = Vec::new();
+        if num_commits > 0 {
+            items.push(
+                Pluralize {
+                    amount: num_commits.try_into()?,
+                    singular: "commit",
+                    plural: "commits",
+                }
+                .to_string(),
+            );
+        }
+        if !branch_names.is_empty() {
+            items.push(format!(
+                "{} ({})",
+                Pluralize {
+                    amount: branch_names.len().try_into()?,
+                    singular: "branch",
+                    plural: "branches",
+                }
+                .to_string(),
+                branch_names.join(", ")
+            ));
+        }
+
+        let old_commit = repo.find_commit_or_fail(old_oid)?;
+        let rewritten_description = match rewritten_oid {
+            Some(rewritten_oid) => format!(
+                "rewritten as {}",
+                printable_styled_string(
+                    glyphs,
+                    repo.find_commit_or_fail(rewritten_oid)?
+                        .friendly_describe()?
+                )?
+            ),
+            None => "deleted".to_string(),
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "- {} on {}, which was {}",
+            items.join(" and "),
+            printable_styled_string(glyphs, old_commit.friendly_describe()?)?,
+            rewritten_description
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "  To restack: git restack {}",
+            old_oid
+        )?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "To restack all of them: git restack"
+    )?;
+    Ok(0)
+}
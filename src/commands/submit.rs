@@ -0,0 +1,192 @@
+//! Submit the current stack of commits for code review.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::branch_names::{
+    create_branch_for_unbranched_commits, name_branch_for_unbranched_commits,
+};
+use crate::core::config::{get_main_branch_name, get_submit_draft};
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::submit::{
+    find_stack, get_remote_branch_name, GerritBackend, GitHubBackend, PhabricatorBackend, Stack,
+    SubmitBackend, SubmitOptions,
+};
+use crate::git::{GitRunInfo, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// Find the stack of commits ending at `HEAD`. The main branch is named as it's
+/// known on the given remote, if any. The stack is divided into segments as if
+/// the branches in `planned_branches` (given as pairs of branch name, without
+/// the `refs/heads/` prefix, and commit OID) already existed.
+///
+/// Returns: The stack, or `None` if no commit is checked out.
+#[instrument]
+pub fn find_head_stack(
+    effects: &Effects,
+    repo: &Repo,
+    remote_name: Option<&str>,
+    planned_branches: &[(String, NonZeroOid)],
+) -> eyre::Result<Option<Stack>> {
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, repo, &conn, &event_replayer)?;
+    let head_oid = match repo.get_head_info()?.oid {
+        Some(head_oid) => head_oid,
+        None => return Ok(None),
+    };
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let mut branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    for (branch_name, commit_oid) in planned_branches {
+        branch_oid_to_names
+            .entry(*commit_oid)
+            .or_default()
+            .insert(OsString::from(format!("refs/heads/{}", branch_name)));
+    }
+    let graph = make_graph(
+        effects,
+        repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(Some(head_oid)),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let main_branch_name = get_main_branch_name(repo)?;
+    let main_branch_name = match remote_name {
+        Some(remote_name) => get_remote_branch_name(&main_branch_name, remote_name),
+        None => &main_branch_name,
+    };
+    let stack = find_stack(
+        repo,
+        &graph,
+        head_oid,
+        main_branch_name,
+        &branch_oid_to_names,
+    )?;
+    Ok(Some(stack))
+}
+
+/// Submit the segments of the stack ending at `HEAD` for code review, using the
+/// given backend (or the one set in `branchless.submit.backend`). If
+/// `create_branches` is set, a branch is first created for the commits at the
+/// top of the stack which don't have one. If `draft` is set, new reviews are
+/// created as drafts, overriding `branchless.submit.draft`, and if `ready` is
+/// set, draft reviews are marked as ready for review.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn submit(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    remote_name: &str,
+    backend_name: Option<String>,
+    dry_run: bool,
+    update: bool,
+    create_branches: bool,
+    draft: bool,
+    ready: bool,
+) -> eyre::Result<isize> {
+    let repo = Repo::from_current_dir()?;
+    let stack = match find_head_stack(effects, &repo, Some(remote_name), &[])? {
+        Some(stack) => stack,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "No commit is checked out, so there is no stack to submit."
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let stack = if create_branches && dry_run {
+        match name_branch_for_unbranched_commits(effects, &repo, &stack)? {
+            Some(branch) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Would create branch {}.",
+                    branch.0
+                )?;
+                find_head_stack(effects, &repo, Some(remote_name), &[branch])?.unwrap_or(stack)
+            }
+            None => stack,
+        }
+    } else if create_branches {
+        let now = SystemTime::now();
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(now, "submit")?;
+        match create_branch_for_unbranched_commits(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            &stack,
+        )? {
+            Some((branch_name, _commit_oid)) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Created branch {}.",
+                    branch_name
+                )?;
+                match find_head_stack(effects, &repo, Some(remote_name), &[])? {
+                    Some(stack) => stack,
+                    None => eyre::bail!("HEAD disappeared after creating branch {}", branch_name),
+                }
+            }
+            None => stack,
+        }
+    } else {
+        stack
+    };
+
+    let backend_name = match backend_name {
+        Some(backend_name) => backend_name,
+        None => repo
+            .get_config()?
+            .get_or("branchless.submit.backend", "github".to_string())?,
+    };
+    let mut backend: Box<dyn SubmitBackend> = match backend_name.as_str() {
+        "gerrit" => Box::new(GerritBackend::new(remote_name)),
+        "github" => Box::new(GitHubBackend::new(remote_name)),
+        "phabricator" => Box::new(PhabricatorBackend::new()),
+        backend_name => {
+            writeln!(
+                effects.get_error_stream(),
+                "Unknown submit backend: {} (expected one of: gerrit, github, phabricator)",
+                backend_name
+            )?;
+            return Ok(1);
+        }
+    };
+
+    if (draft || ready) && backend_name != "github" {
+        writeln!(
+            effects.get_error_stream(),
+            "The {} backend doesn't support draft reviews, so --draft and --ready can't be used with it.",
+            backend_name
+        )?;
+        return Ok(1);
+    }
+
+    let options = SubmitOptions {
+        update,
+        draft: draft || get_submit_draft(&repo)?,
+        ready,
+    };
+    if dry_run {
+        backend.describe(effects, &repo, &stack, &options)?;
+        Ok(0)
+    } else {
+        backend.submit(effects, git_run_info, &repo, &stack, &options)
+    }
+}
@@ -0,0 +1,236 @@
+//! Swap a commit with its parent.
+//!
+//! The commit is applied onto its grandparent and the parent is applied on top
+//! of it, which reorders the two commits without an interactive rebase. Any
+//! descendants of the two commits are moved on top of the rewritten parent.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use itertools::Itertools;
+use tracing::instrument;
+
+use crate::core::config::{
+    get_move_force_in_memory, get_move_force_on_disk, get_restack_preserve_timestamps,
+    get_restack_rename_limit, get_restack_rename_threshold,
+};
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::printable_styled_string;
+use crate::core::graph::{
+    make_graph, resolve_commits, BranchOids, HeadOid, MainBranchOid, ResolveCommitsResult,
+};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, RebasePlanBuilder,
+};
+use crate::git::{CherryPickFastError, CherryPickFastOptions, Commit, GitRunInfo, Repo};
+use crate::tui::Effects;
+
+/// Check whether the commit can be applied onto its grandparent, and its
+/// parent then applied on top of the result, without merge conflicts. Returns
+/// the conflicting paths, if any. Nothing is rewritten, although an
+/// unreferenced commit may be created for the intermediate result.
+fn find_swap_conflicts(
+    repo: &Repo,
+    commit: &Commit,
+    parent: &Commit,
+    grandparent: &Commit,
+) -> eyre::Result<Vec<String>> {
+    let options = CherryPickFastOptions {
+        reuse_parent_tree_if_possible: true,
+        rename_limit: get_restack_rename_limit(repo)?,
+        rename_threshold: get_restack_rename_threshold(repo)?,
+    };
+    let swapped_oid = match repo.cherry_pick_commit_in_memory(
+        commit,
+        grandparent,
+        &commit.get_committer(),
+        None,
+        &options,
+    )? {
+        Ok(swapped_oid) => swapped_oid,
+        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+            return Ok(conflicting_paths
+                .into_iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .sorted()
+                .collect())
+        }
+    };
+    let swapped_commit = repo.find_commit_or_fail(swapped_oid)?;
+    let conflicting_paths = match repo.cherry_pick_fast(parent, &swapped_commit, &options)? {
+        Ok(_) => Vec::new(),
+        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => conflicting_paths
+            .into_iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .sorted()
+            .collect(),
+    };
+    Ok(conflicting_paths)
+}
+
+/// Swap the given commit (or `HEAD`) with its parent. The descendants of both
+/// commits are moved onto the rewritten parent, which becomes the later of the
+/// two commits. If the changes in the two commits conflict, then the
+/// conflicting files are reported and nothing is rewritten.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn swap(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commit: Option<String>,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let head_oid = repo.get_head_info()?.oid;
+    let commit = match commit {
+        Some(commit) => commit,
+        None => match head_oid {
+            Some(head_oid) => head_oid.to_string(),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "No commit was provided, and no OID for HEAD is available as a default"
+                )?;
+                return Ok(1);
+            }
+        },
+    };
+    let commit = match resolve_commits(&repo, vec![commit])? {
+        ResolveCommitsResult::Ok { mut commits } => match commits.pop() {
+            Some(commit) => commit,
+            None => eyre::bail!("Unexpected number of returns values from resolve_commits"),
+        },
+        ResolveCommitsResult::CommitNotFound { commit } => {
+            writeln!(effects.get_output_stream(), "Commit not found: {}", commit)?;
+            return Ok(1);
+        }
+    };
+    let commit_oid = commit.get_oid();
+    let glyphs = effects.get_glyphs();
+    let (parent, grandparent) = match commit.get_only_parent() {
+        Some(parent) => match parent.get_only_parent() {
+            Some(grandparent) => (parent, grandparent),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Cannot swap {} with its parent {}, since the parent does not have exactly one parent.",
+                    printable_styled_string(glyphs, commit.friendly_describe()?)?,
+                    printable_styled_string(glyphs, parent.friendly_describe()?)?
+                )?;
+                return Ok(1);
+            }
+        },
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot swap {}, since it does not have exactly one parent.",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?
+            )?;
+            return Ok(1);
+        }
+    };
+    let parent_oid = parent.get_oid();
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(Some(commit_oid)),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+    let child_oids = match (graph.get(&parent_oid), graph.get(&commit_oid)) {
+        (Some(parent_node), Some(node)) if !parent_node.is_main => node.children.clone(),
+        _ => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot swap {} with its parent {}, since it is on the main branch.",
+                printable_styled_string(glyphs, commit.friendly_describe()?)?,
+                printable_styled_string(glyphs, parent.friendly_describe()?)?
+            )?;
+            return Ok(1);
+        }
+    };
+
+    let conflicting_paths = find_swap_conflicts(&repo, &commit, &parent, &grandparent)?;
+    if !conflicting_paths.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Cannot swap {} with its parent {}, since their changes conflict in these files:",
+            printable_styled_string(glyphs, commit.friendly_describe()?)?,
+            printable_styled_string(glyphs, parent.friendly_describe()?)?
+        )?;
+        for path in conflicting_paths {
+            writeln!(effects.get_output_stream(), "{}", path)?;
+        }
+        return Ok(1);
+    }
+
+    let rebase_plan = {
+        let mut builder = RebasePlanBuilder::new(
+            &repo,
+            &graph,
+            &merge_base_db,
+            &MainBranchOid(main_branch_oid),
+        );
+        builder.move_subtree(commit_oid, grandparent.get_oid())?;
+        builder.move_subtree(parent_oid, commit_oid)?;
+        for child_oid in child_oids.into_iter().sorted() {
+            builder.move_subtree(child_oid, parent_oid)?;
+        }
+        builder.build(
+            effects,
+            &BuildRebasePlanOptions {
+                dump_rebase_constraints: false,
+                dump_rebase_plan: false,
+                detect_duplicate_commits_via_patch_id: false,
+            },
+        )?
+    };
+    let rebase_plan = match rebase_plan {
+        Ok(Some(rebase_plan)) => rebase_plan,
+        Ok(None) => eyre::bail!("BUG: swapping commits should produce a rebase plan"),
+        Err(err) => {
+            err.describe(effects, &repo)?;
+            return Ok(1);
+        }
+    };
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "swap")?;
+    let result = execute_rebase_plan(
+        effects,
+        git_run_info,
+        &repo,
+        &rebase_plan,
+        &ExecuteRebasePlanOptions {
+            now,
+            event_tx_id,
+            preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+            force_in_memory: get_move_force_in_memory(&repo)?,
+            force_on_disk: get_move_force_on_disk(&repo)?,
+            strategy_options: Vec::new(),
+            keep_originals: false,
+        },
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Swapped {} with {}.",
+        printable_styled_string(glyphs, commit.friendly_describe()?)?,
+        printable_styled_string(glyphs, parent.friendly_describe()?)?
+    )?;
+    Ok(0)
+}
@@ -0,0 +1,424 @@
+//! Interactively select a commit or branch to check out.
+//!
+//! The visible commits and their branches are listed in a TUI, and are
+//! narrowed down by fuzzy-matching the typed filter against their commit
+//! hashes, branch names and subject lines.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+use cursive::event::Key;
+use cursive::traits::Boxable;
+use cursive::utils::markup::StyledString;
+use cursive::views::{EditView, LinearLayout, Panel, ScrollView, TextView};
+use cursive::{Cursive, CursiveRunnable, CursiveRunner};
+use tracing::instrument;
+
+use crate::commands::navigation::goto;
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{Palette, StyledStringBuilder};
+use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::mergebase::{make_merge_base_db, MergeBaseDb};
+use crate::core::metadata::{
+    render_commit_metadata, BranchesProvider, CommitMessageProvider, CommitOidProvider,
+};
+use crate::declare_views;
+use crate::git::{GitRunInfo, NonZeroOid, Repo};
+use crate::tui::{with_siv, Effects, SingletonView};
+
+/// An entry which can be selected in the switcher.
+#[derive(Debug)]
+struct SwitchCandidate {
+    /// The argument to pass to `goto` to check out this entry.
+    target: String,
+
+    /// The text which the filter is matched against.
+    search_text: String,
+
+    /// The description of the entry to display.
+    line: StyledString,
+}
+
+/// Determine whether `text` contains the characters of `pattern` in order,
+/// ignoring case.
+///
+/// Returns: A score for the match, where lower is better, or `None` if the
+/// text doesn't match. Matches which start earlier and which have fewer
+/// characters between the matched characters are scored better.
+fn fuzzy_match(pattern: &str, text: &str) -> Option<usize> {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase).enumerate();
+    let mut score = 0;
+    let mut last_index = None;
+    for pattern_char in pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+    {
+        let (index, _) = text_chars.find(|(_, text_char)| *text_char == pattern_char)?;
+        score += match last_index {
+            Some(last_index) => index - last_index - 1,
+            None => index,
+        };
+        last_index = Some(index);
+    }
+    Some(score)
+}
+
+/// Get the entries which match the given filter, from best to worst.
+fn filter_candidates<'a>(
+    candidates: &'a [SwitchCandidate],
+    filter: &str,
+) -> Vec<&'a SwitchCandidate> {
+    let mut matches: Vec<(usize, &SwitchCandidate)> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            fuzzy_match(filter, &candidate.search_text).map(|score| (score, candidate))
+        })
+        .collect();
+    matches.sort_by_key(|(score, _)| *score);
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// Get the entries for the visible commits in the smartlog, from newest to
+/// oldest. A commit with local branches pointing to it has one entry per
+/// branch, which checks out the branch.
+#[instrument]
+fn get_switch_candidates(
+    effects: &Effects,
+    repo: &Repo,
+    merge_base_db: &impl MergeBaseDb,
+    event_replayer: &EventReplayer,
+) -> eyre::Result<Vec<SwitchCandidate>> {
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        repo,
+        merge_base_db,
+        event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut nodes: Vec<_> = graph.values().filter(|node| node.is_visible).collect();
+    nodes.sort_by_key(|node| {
+        (
+            std::cmp::Reverse(node.commit.get_time().seconds()),
+            node.commit.get_oid(),
+        )
+    });
+
+    let mut result = Vec::new();
+    for node in nodes {
+        let commit = &node.commit;
+        let commit_oid = commit.get_oid();
+        let summary = commit.get_summary()?.to_string_lossy().into_owned();
+        let mut branch_names: Vec<String> = branch_oid_to_names
+            .get(&commit_oid)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| {
+                name.to_str()
+                    .and_then(|name| name.strip_prefix("refs/heads/"))
+                    .map(String::from)
+            })
+            .collect();
+        branch_names.sort_unstable();
+
+        if branch_names.is_empty() {
+            let line = render_commit_metadata(
+                commit,
+                &mut [
+                    &mut CommitOidProvider::new(true)?,
+                    &mut CommitMessageProvider::new()?,
+                ],
+            )?;
+            result.push(SwitchCandidate {
+                target: commit_oid.to_string(),
+                search_text: format!("{} {}", commit_oid, summary),
+                line,
+            });
+        }
+
+        for branch_name in branch_names {
+            // Describe only this branch, rather than all of the branches
+            // pointing to the commit.
+            let single_branch_oid_to_names: HashMap<NonZeroOid, HashSet<OsString>> =
+                std::iter::once((
+                    commit_oid,
+                    std::iter::once(OsString::from(format!("refs/heads/{}", branch_name)))
+                        .collect(),
+                ))
+                .collect();
+            let line = render_commit_metadata(
+                commit,
+                &mut [
+                    &mut CommitOidProvider::new(true)?,
+                    &mut BranchesProvider::new(repo, &single_branch_oid_to_names)?,
+                    &mut CommitMessageProvider::new()?,
+                ],
+            )?;
+            result.push(SwitchCandidate {
+                search_text: format!("{} {} {}", commit_oid, branch_name, summary),
+                target: branch_name,
+                line,
+            });
+        }
+    }
+    Ok(result)
+}
+
+#[instrument(skip(siv, candidates))]
+fn select_switch_target(
+    mut siv: CursiveRunner<CursiveRunnable>,
+    candidates: &[SwitchCandidate],
+    initial_filter: &str,
+) -> eyre::Result<Option<String>> {
+    #[derive(Clone, Debug)]
+    enum Message {
+        Init,
+        SetFilter(String),
+        Next,
+        Previous,
+        Quit,
+        SelectAndQuit,
+    }
+    let (main_tx, main_rx): (Sender<Message>, Receiver<Message>) = channel();
+
+    // The filter input receives all of the printable characters, so only keys
+    // which it ignores are bound here.
+    [
+        (Key::Down.into(), Message::Next),
+        (cursive::event::Event::CtrlChar('n'), Message::Next),
+        (Key::Up.into(), Message::Previous),
+        (cursive::event::Event::CtrlChar('p'), Message::Previous),
+        (Key::Esc.into(), Message::Quit),
+        (Key::Enter.into(), Message::SelectAndQuit),
+    ]
+    .iter()
+    .cloned()
+    .for_each(|(event, message): (cursive::event::Event, Message)| {
+        siv.add_global_callback(event, {
+            let main_tx = main_tx.clone();
+            move |_siv| main_tx.send(message.clone()).unwrap()
+        });
+    });
+
+    let mut filter = initial_filter.to_string();
+    let mut matches = filter_candidates(candidates, &filter);
+    let mut selected_index: usize = 0;
+    main_tx.send(Message::Init)?;
+    while siv.is_running() {
+        let message = main_rx.try_recv();
+        if message.is_err() {
+            // For tests: only pump the Cursive event loop if we have no events
+            // of our own to process. See `select_past_event` in `undo`.
+            siv.step();
+        }
+
+        declare_views! {
+            CandidatesView => ScrollView<TextView>,
+            FilterView => EditView,
+        }
+
+        let redraw = |siv: &mut Cursive,
+                      matches: &[&SwitchCandidate],
+                      selected_index: usize|
+         -> eyre::Result<()> {
+            let lines = if matches.is_empty() {
+                vec![StyledString::plain("No matching commits or branches.")]
+            } else {
+                matches
+                    .iter()
+                    .enumerate()
+                    .map(|(i, candidate)| {
+                        if i == selected_index {
+                            StyledString::styled(
+                                format!("> {}", candidate.line.source()),
+                                Palette::current().selection,
+                            )
+                        } else {
+                            StyledStringBuilder::new()
+                                .append_plain("  ")
+                                .append(candidate.line.clone())
+                                .build()
+                        }
+                    })
+                    .collect()
+            };
+
+            let mut candidates_view = CandidatesView::find(siv);
+            candidates_view
+                .get_inner_mut()
+                .set_content(StyledStringBuilder::from_lines(lines));
+
+            // Keep the selected entry in view.
+            let viewport = candidates_view.content_viewport();
+            if viewport.height() > 0 {
+                if selected_index < viewport.top() {
+                    candidates_view.set_offset((0, selected_index));
+                } else if selected_index > viewport.bottom() {
+                    candidates_view.set_offset((0, selected_index + 1 - viewport.height()));
+                }
+            }
+            Ok(())
+        };
+
+        let is_message = message.is_ok();
+        match message {
+            Err(TryRecvError::Disconnected) => break,
+
+            Err(TryRecvError::Empty) => {
+                // If we haven't received a message yet, defer to `siv.step`
+                // to process the next user input.
+                continue;
+            }
+
+            Ok(Message::Init) => {
+                let candidates_view: CandidatesView = ScrollView::new(TextView::new("")).into();
+                let filter_view: FilterView = EditView::new()
+                    .content(filter.clone())
+                    .on_edit({
+                        let main_tx = main_tx.clone();
+                        move |_siv, text, _cursor| {
+                            main_tx.send(Message::SetFilter(text.to_string())).unwrap()
+                        }
+                    })
+                    .into();
+                siv.add_fullscreen_layer(
+                    LinearLayout::vertical()
+                        .child(
+                            Panel::new(candidates_view)
+                                .title("Commits and branches")
+                                .full_height(),
+                        )
+                        .child(
+                            Panel::new(filter_view)
+                                .title("Filter (<enter> to check out, <esc> to quit)"),
+                        )
+                        .full_width(),
+                );
+                redraw(&mut siv, &matches, selected_index)?;
+            }
+
+            Ok(Message::SetFilter(new_filter)) => {
+                filter = new_filter;
+                matches = filter_candidates(candidates, &filter);
+                selected_index = 0;
+                redraw(&mut siv, &matches, selected_index)?;
+            }
+
+            Ok(Message::Next) => {
+                if selected_index + 1 < matches.len() {
+                    selected_index += 1;
+                }
+                redraw(&mut siv, &matches, selected_index)?;
+            }
+
+            Ok(Message::Previous) => {
+                selected_index = selected_index.saturating_sub(1);
+                redraw(&mut siv, &matches, selected_index)?;
+            }
+
+            Ok(Message::Quit) => siv.quit(),
+
+            Ok(Message::SelectAndQuit) => {
+                if let Some(candidate) = matches.get(selected_index) {
+                    siv.quit();
+                    return Ok(Some(candidate.target.clone()));
+                }
+            }
+        };
+
+        if is_message {
+            siv.refresh();
+        }
+    }
+
+    Ok(None)
+}
+
+/// Check out a commit or branch. If `interactive` is set, the commit or
+/// branch is selected in a TUI, in which `target` is used as the initial
+/// filter. Otherwise, this is the same as `goto`.
+#[instrument]
+pub fn switch(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    interactive: bool,
+    target: Option<String>,
+) -> eyre::Result<isize> {
+    if !interactive {
+        return match target {
+            Some(target) => goto(effects, git_run_info, target),
+            None => eyre::bail!(
+                "A commit or branch to switch to must be given, unless --interactive is passed"
+            ),
+        };
+    }
+
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let candidates = get_switch_candidates(effects, &repo, &merge_base_db, &event_replayer)?;
+
+    let initial_filter = target.unwrap_or_default();
+    let target = with_siv(effects, |_effects, siv| {
+        select_switch_target(siv, &candidates, &initial_filter)
+    })?;
+    match target {
+        Some(target) => goto(effects, git_run_info, target),
+        None => Ok(0),
+    }
+}
+
+#[allow(missing_docs)]
+pub mod testing {
+    use cursive::{CursiveRunnable, CursiveRunner};
+
+    use crate::core::eventlog::{EventLogDb, EventReplayer};
+    use crate::core::mergebase::make_merge_base_db;
+    use crate::git::Repo;
+    use crate::tui::Effects;
+
+    pub fn select_switch_target(
+        siv: CursiveRunner<CursiveRunnable>,
+        effects: &Effects,
+        repo: &Repo,
+        initial_filter: &str,
+    ) -> eyre::Result<Option<String>> {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+        let merge_base_db = make_merge_base_db(effects, repo, &conn, &event_replayer)?;
+        let candidates =
+            super::get_switch_candidates(effects, repo, &merge_base_db, &event_replayer)?;
+        super::select_switch_target(siv, &candidates, initial_filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("", "anything"), Some(0));
+        assert_eq!(fuzzy_match("foo", "foo bar"), Some(0));
+        assert_eq!(fuzzy_match("bar", "foo bar"), Some(4));
+        assert_eq!(fuzzy_match("fb", "foo bar"), Some(3));
+        assert_eq!(fuzzy_match("FOO", "foo"), Some(0));
+        assert_eq!(fuzzy_match("foo bar", "foobar"), Some(0));
+        assert_eq!(fuzzy_match("rab", "foo bar"), None);
+    }
+}
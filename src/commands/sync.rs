@@ -0,0 +1,186 @@
+//! Move the draft stacks onto the latest commit of the main branch.
+//!
+//! A stack is a subtree of draft commits whose root is a child of a commit on
+//! the main branch. By default, all stacks are moved, but the stacks can also
+//! be selected with revsets, so that e.g. a stack which is under review isn't
+//! rebased until it's ready.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::autostash::{should_autostash, with_stashed_changes};
+use crate::core::config::{
+    get_move_force_in_memory, get_move_force_on_disk, get_restack_detect_duplicate_commits,
+    get_restack_preserve_timestamps,
+};
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::revset::{evaluate_revset, parse_revset};
+use crate::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, RebasePlanBuilder,
+};
+use crate::git::{GitRunInfo, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// Find the roots of the stacks in the graph, which are the visible draft
+/// commits whose parents are on the main branch.
+fn find_stack_roots(graph: &CommitGraph) -> Vec<NonZeroOid> {
+    let mut result: Vec<NonZeroOid> = graph
+        .iter()
+        .filter(|(_oid, node)| {
+            !node.is_main
+                && node.is_visible
+                && match node.parent {
+                    Some(parent_oid) => graph[&parent_oid].is_main,
+                    None => true,
+                }
+        })
+        .map(|(oid, _node)| *oid)
+        .collect();
+    result.sort();
+    result
+}
+
+/// Determine whether any commit in the stack rooted at `root_oid` is in
+/// `selected_oids`.
+fn does_stack_contain_any(
+    graph: &CommitGraph,
+    root_oid: NonZeroOid,
+    selected_oids: &HashSet<NonZeroOid>,
+) -> bool {
+    let mut oids_to_visit = vec![root_oid];
+    while let Some(oid) = oids_to_visit.pop() {
+        if selected_oids.contains(&oid) {
+            return true;
+        }
+        oids_to_visit.extend(graph[&oid].children.iter().copied());
+    }
+    false
+}
+
+/// Move the stacks onto the main branch. If `revsets` is non-empty, only the
+/// stacks containing a commit matched by one of the revsets are moved. If
+/// `dedup` is set, it overrides `branchless.restack.detectDuplicateCommits`,
+/// and if `autostash` is set, it overrides `branchless.autoStash`.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn sync(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revsets: Vec<String>,
+    strategy_options: Vec<String>,
+    dedup: Option<bool>,
+    autostash: Option<bool>,
+    dump_rebase_constraints: bool,
+    dump_rebase_plan: bool,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let head_oid = repo.get_head_info()?.oid;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let graph = make_graph(
+        effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut selected_oids: Option<HashSet<NonZeroOid>> = None;
+    for revset in revsets {
+        let oids = parse_revset(&revset)
+            .and_then(|parsed| evaluate_revset(&repo, &graph, &parsed))
+            .wrap_err_with(|| format!("Evaluating revset: {:?}", revset))?;
+        selected_oids.get_or_insert_with(HashSet::new).extend(oids);
+    }
+
+    let root_oids: Vec<NonZeroOid> = find_stack_roots(&graph)
+        .into_iter()
+        .filter(|root_oid| match &selected_oids {
+            Some(selected_oids) => does_stack_contain_any(&graph, *root_oid, selected_oids),
+            None => true,
+        })
+        .collect();
+    if selected_oids.is_some() && root_oids.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No stacks matched the given revsets."
+        )?;
+        return Ok(1);
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "sync")?;
+    let rebase_plan = {
+        let mut builder = RebasePlanBuilder::new(
+            &repo,
+            &graph,
+            &merge_base_db,
+            &MainBranchOid(main_branch_oid),
+        );
+        for root_oid in root_oids {
+            let root_commit = repo.find_commit_or_fail(root_oid)?;
+            if root_commit.get_parent_oids() != vec![main_branch_oid] {
+                builder.move_subtree(root_oid, main_branch_oid)?;
+            }
+        }
+        builder.build(
+            effects,
+            &BuildRebasePlanOptions {
+                dump_rebase_constraints,
+                dump_rebase_plan,
+                detect_duplicate_commits_via_patch_id: match dedup {
+                    Some(dedup) => dedup,
+                    None => get_restack_detect_duplicate_commits(&repo)?,
+                },
+            },
+        )?
+    };
+    let result = match rebase_plan {
+        Ok(None) => {
+            writeln!(
+                effects.get_output_stream(),
+                "All stacks are already up to date with the main branch."
+            )?;
+            0
+        }
+        Ok(Some(rebase_plan)) => {
+            let options = ExecuteRebasePlanOptions {
+                now,
+                event_tx_id,
+                preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+                force_in_memory: get_move_force_in_memory(&repo)?,
+                force_on_disk: get_move_force_on_disk(&repo)?,
+                strategy_options,
+                keep_originals: false,
+            };
+            with_stashed_changes(
+                effects,
+                git_run_info,
+                &repo,
+                event_tx_id,
+                should_autostash(&repo, autostash)?,
+                || execute_rebase_plan(effects, git_run_info, &repo, &rebase_plan, &options),
+            )?
+        }
+        Err(err) => {
+            err.describe(effects, &repo)?;
+            1
+        }
+    };
+    Ok(result)
+}
@@ -0,0 +1,362 @@
+//! Share the event log between machines.
+//!
+//! The events recorded on each machine are serialized into blobs and committed
+//! under the `refs/branchless/event-log` reference, which can be pushed and
+//! fetched like any other reference. Each commit contains the events which
+//! were recorded locally since the previous push. When pulling, the blobs
+//! which haven't been seen before are imported into the local event log, and
+//! the histories of the local and remote references are merged.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::eventlog::{
+    get_event_log_sync_remote_ref_name, EventLogDb, EVENT_LOG_SYNC_REF_NAME,
+};
+use crate::core::formatting::Pluralize;
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// The name of the file in each event log commit which contains the exported
+/// events.
+const EVENTS_FILE_NAME: &str = "events";
+
+fn get_reference_oid(repo: &Repo, ref_name: &str) -> eyre::Result<Option<NonZeroOid>> {
+    match repo.find_reference(OsStr::new(ref_name))? {
+        Some(reference) => match reference.peel_to_commit()? {
+            Some(commit) => Ok(Some(commit.get_oid())),
+            None => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Find the batches of events in the history of the given event log commit
+/// which haven't already been imported.
+#[instrument]
+fn find_unimported_batches(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    commit_oid: NonZeroOid,
+) -> eyre::Result<Vec<(NonZeroOid, String)>> {
+    let mut result = Vec::new();
+    let mut seen_oids = HashSet::new();
+    let mut oids_to_visit = vec![commit_oid];
+    while let Some(oid) = oids_to_visit.pop() {
+        if !seen_oids.insert(oid) {
+            continue;
+        }
+
+        let commit = repo.find_commit_or_fail(oid)?;
+        let blob_oid = match commit
+            .get_tree()?
+            .get_oid_for_path(Path::new(EVENTS_FILE_NAME))?
+        {
+            Some(MaybeZeroOid::NonZero(blob_oid)) => Some(blob_oid),
+            Some(MaybeZeroOid::Zero) | None => None,
+        };
+
+        match blob_oid {
+            // Batches are always imported together with all of their
+            // ancestors, so there's nothing more to find in this part of the
+            // history.
+            Some(blob_oid) if event_log_db.is_sync_blob_imported(blob_oid)? => continue,
+
+            Some(blob_oid) => {
+                let contents = repo
+                    .find_blob_contents(blob_oid)?
+                    .ok_or_else(|| eyre::eyre!("Could not find event log blob {:?}", blob_oid))?;
+                let contents = String::from_utf8(contents)?;
+                result.push((blob_oid, contents));
+            }
+
+            // Merge commits don't contain any events of their own.
+            None => {}
+        }
+        oids_to_visit.extend(commit.get_parent_oids());
+    }
+    Ok(result)
+}
+
+/// Point the local event log reference at a commit which includes the history
+/// of both its current target and the given remote commit.
+#[instrument]
+fn merge_event_log_ref(
+    now: SystemTime,
+    repo: &Repo,
+    remote_name: &str,
+    remote_oid: NonZeroOid,
+) -> eyre::Result<()> {
+    let local_oid = match get_reference_oid(repo, EVENT_LOG_SYNC_REF_NAME)? {
+        None => {
+            repo.create_reference(
+                OsStr::new(EVENT_LOG_SYNC_REF_NAME),
+                remote_oid,
+                true,
+                "branchless: pulling event log",
+            )?;
+            return Ok(());
+        }
+        Some(local_oid) => local_oid,
+    };
+
+    let merge_base_oid = repo.find_merge_base(local_oid, remote_oid)?;
+    let target_oid = if merge_base_oid == Some(remote_oid) {
+        return Ok(());
+    } else if merge_base_oid == Some(local_oid) {
+        remote_oid
+    } else {
+        let local_commit = repo.find_commit_or_fail(local_oid)?;
+        let remote_commit = repo.find_commit_or_fail(remote_oid)?;
+        let signature = repo.get_branchless_signature(now)?;
+        repo.create_commit(
+            None,
+            &signature,
+            &signature,
+            &format!("branchless: merge event log from {}", remote_name),
+            &repo.create_tree_from_blobs(HashMap::new())?,
+            vec![&local_commit, &remote_commit],
+        )?
+    };
+    repo.create_reference(
+        OsStr::new(EVENT_LOG_SYNC_REF_NAME),
+        target_oid,
+        true,
+        "branchless: pulling event log",
+    )?;
+    Ok(())
+}
+
+/// Fetch the event log from the remote and import its new events.
+///
+/// Returns: The exit code of the fetch if it failed, or the number of events
+/// imported.
+#[instrument]
+fn pull(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    now: SystemTime,
+    repo: &Repo,
+    event_log_db: &mut EventLogDb,
+    remote_name: &str,
+) -> eyre::Result<Result<usize, isize>> {
+    let remote_refs = git_run_info.run_silent(
+        repo,
+        None,
+        &["ls-remote", remote_name, EVENT_LOG_SYNC_REF_NAME],
+    )?;
+    if remote_refs.trim().is_empty() {
+        return Ok(Ok(0));
+    }
+
+    let remote_ref_name = get_event_log_sync_remote_ref_name(remote_name);
+    let exit_code = git_run_info.run(
+        effects,
+        None,
+        &[
+            "fetch",
+            remote_name,
+            &format!("+{}:{}", EVENT_LOG_SYNC_REF_NAME, remote_ref_name),
+        ],
+    )?;
+    if exit_code != 0 {
+        return Ok(Err(exit_code));
+    }
+
+    let remote_oid = match get_reference_oid(repo, &remote_ref_name)? {
+        Some(remote_oid) => remote_oid,
+        None => eyre::bail!("Could not find fetched reference {}", remote_ref_name),
+    };
+    let batches = find_unimported_batches(repo, event_log_db, remote_oid)?;
+    let num_events = event_log_db.import_events(&batches)?;
+    merge_event_log_ref(now, repo, remote_name, remote_oid)?;
+    Ok(Ok(num_events))
+}
+
+fn describe_num_events(num_events: usize) -> eyre::Result<String> {
+    Ok(Pluralize {
+        amount: num_events.try_into()?,
+        singular: "event",
+        plural: "events",
+    }
+    .to_string())
+}
+
+/// Commit the events which were recorded locally since the last snapshot to
+/// the local event log reference, without pushing them anywhere. Since the
+/// events are then stored in the repository itself, they survive operations
+/// like `git clone --mirror`.
+///
+/// Returns: The number of events committed.
+#[instrument]
+pub fn snapshot_event_log(
+    now: SystemTime,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+) -> eyre::Result<usize> {
+    let export = match event_log_db.export_events()? {
+        Some(export) => export,
+        None => return Ok(0),
+    };
+
+    let blob_oid = repo.create_blob(export.contents.as_bytes())?;
+    let tree = repo.create_tree_from_blobs(
+        vec![(PathBuf::from(EVENTS_FILE_NAME), blob_oid)]
+            .into_iter()
+            .collect(),
+    )?;
+    let parent = match get_reference_oid(repo, EVENT_LOG_SYNC_REF_NAME)? {
+        Some(parent_oid) => Some(repo.find_commit_or_fail(parent_oid)?),
+        None => None,
+    };
+    let signature = repo.get_branchless_signature(now)?;
+    let commit_oid = repo.create_commit(
+        None,
+        &signature,
+        &signature,
+        &format!("branchless: {}", describe_num_events(export.num_events)?),
+        &tree,
+        parent.iter().collect(),
+    )?;
+    repo.create_reference(
+        OsStr::new(EVENT_LOG_SYNC_REF_NAME),
+        commit_oid,
+        true,
+        "branchless: exporting events",
+    )?;
+    event_log_db.mark_events_exported(&export, blob_oid)?;
+    Ok(export.num_events)
+}
+
+/// Import the events in the snapshots under the local event log reference
+/// which aren't in the event log yet. This is the case when the repository was
+/// copied without its event log database, such as with `git clone --mirror`,
+/// in which case the snapshots written by `snapshot_event_log` are the only
+/// record of its events.
+///
+/// Returns: The number of events imported.
+#[instrument]
+pub fn restore_event_log_snapshot(
+    repo: &Repo,
+    event_log_db: &mut EventLogDb,
+) -> eyre::Result<usize> {
+    let snapshot_oid = match get_reference_oid(repo, EVENT_LOG_SYNC_REF_NAME)? {
+        Some(snapshot_oid) => snapshot_oid,
+        None => return Ok(0),
+    };
+    let batches = find_unimported_batches(repo, event_log_db, snapshot_oid)?;
+    event_log_db.import_events(&batches)
+}
+
+/// Fetch the event log from the given remote and merge its events into the
+/// local event log.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn sync_events_pull(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    remote_name: &str,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+
+    let num_events = match pull(
+        effects,
+        git_run_info,
+        now,
+        &repo,
+        &mut event_log_db,
+        remote_name,
+    )? {
+        Ok(num_events) => num_events,
+        Err(exit_code) => return Ok(exit_code),
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: imported {} from {}",
+        describe_num_events(num_events)?,
+        remote_name
+    )?;
+    Ok(0)
+}
+
+/// Merge the event log from the given remote into the local event log, then
+/// push the events recorded locally since the last push.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn sync_events_push(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    remote_name: &str,
+) -> eyre::Result<isize> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+
+    let num_imported_events = match pull(
+        effects,
+        git_run_info,
+        now,
+        &repo,
+        &mut event_log_db,
+        remote_name,
+    )? {
+        Ok(num_events) => num_events,
+        Err(exit_code) => return Ok(exit_code),
+    };
+    if num_imported_events > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: imported {} from {}",
+            describe_num_events(num_imported_events)?,
+            remote_name
+        )?;
+    }
+
+    let num_exported_events = snapshot_event_log(now, &repo, &event_log_db)?;
+
+    let remote_ref_name = get_event_log_sync_remote_ref_name(remote_name);
+    let local_oid = get_reference_oid(&repo, EVENT_LOG_SYNC_REF_NAME)?;
+    let remote_oid = get_reference_oid(&repo, &remote_ref_name)?;
+    if let Some(local_oid) = local_oid {
+        if remote_oid != Some(local_oid) {
+            let exit_code = git_run_info.run(
+                effects,
+                None,
+                &[
+                    "push",
+                    remote_name,
+                    &format!("{}:{}", EVENT_LOG_SYNC_REF_NAME, EVENT_LOG_SYNC_REF_NAME),
+                ],
+            )?;
+            if exit_code != 0 {
+                return Ok(exit_code);
+            }
+            repo.create_reference(
+                OsStr::new(&remote_ref_name),
+                local_oid,
+                true,
+                "branchless: pushing event log",
+            )?;
+        }
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: exported {} to {}",
+        describe_num_events(num_exported_events)?,
+        remote_name
+    )?;
+    Ok(0)
+}
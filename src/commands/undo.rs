@@ -3,12 +3,14 @@
 //! This is accomplished by finding the events that have happened since a certain
 //! time and inverting them.
 
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::io::{stdin, BufRead, BufReader, Read};
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
-use std::time::SystemTime;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
 
 use cursive::event::Key;
 use cursive::traits::Boxable;
@@ -19,8 +21,15 @@ use eyre::Context;
 use tracing::instrument;
 
 use crate::commands::smartlog::render_graph;
-use crate::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
-use crate::core::formatting::{printable_styled_string, Pluralize, StyledStringBuilder};
+use crate::core::autostash::{should_autostash, with_stashed_changes};
+use crate::core::eventlog::{
+    get_current_user, is_worktree_head_ref, record_stash_changes, Event, EventCursor, EventLogDb,
+    EventReplayer, EventTransactionId, TransactionCommand,
+};
+use crate::core::formatting::{
+    display_width, format_date_time, printable_styled_string, Palette, Pluralize,
+    StyledStringBuilder,
+};
 use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
 use crate::core::mergebase::{make_merge_base_db, MergeBaseDb};
 use crate::core::metadata::{
@@ -28,7 +37,7 @@ use crate::core::metadata::{
     HiddenExplanationProvider, RelativeTimeProvider,
 };
 use crate::declare_views;
-use crate::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo};
+use crate::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 use crate::tui::{with_siv, Effects, SingletonView};
 
 fn render_cursor_smartlog(
@@ -63,13 +72,36 @@ fn render_cursor_smartlog(
             &mut RelativeTimeProvider::new(repo, SystemTime::now())?,
             &mut HiddenExplanationProvider::new(&graph, event_replayer, event_cursor)?,
             &mut BranchesProvider::new(repo, &branch_oid_to_names)?,
-            &mut DifferentialRevisionProvider::new(repo)?,
+            &mut DifferentialRevisionProvider::new(repo, &graph)?,
             &mut CommitMessageProvider::new()?,
         ],
     )?;
     Ok(result)
 }
 
+/// Describe a checkout recorded under the given `HEAD` reference, mentioning
+/// the worktree it happened in if it's not the current one.
+fn describe_checkout(repo: &Repo, head_ref_name: &OsStr) -> String {
+    if head_ref_name == repo.get_head_ref_name() {
+        return "Check out".to_string();
+    }
+    let worktree_name = head_ref_name
+        .to_str()
+        .and_then(|ref_name| ref_name.strip_prefix("worktrees/"))
+        .and_then(|ref_name| ref_name.strip_suffix("/HEAD"));
+    match worktree_name {
+        Some(worktree_name) => format!("Check out in worktree {}", worktree_name),
+        None => "Check out in main worktree".to_string(),
+    }
+}
+
+/// Describe the commit that a reference points to. Annotated tags point to tag
+/// objects rather than commits, so they're described by the commit they tag.
+fn describe_ref_target(repo: &Repo, oid: NonZeroOid) -> eyre::Result<StyledString> {
+    let oid = repo.find_tag_target(oid)?.unwrap_or(oid);
+    repo.friendly_describe_commit_from_oid(oid)
+}
+
 fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>> {
     // Links to https://github.com/arxanas/git-branchless/issues/57
     const EMPTY_EVENT_MESSAGE: &str =
@@ -94,6 +126,25 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
             timestamp: _,
             event_tx_id: _,
             commit_oid,
+            reason: Some(reason),
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Hide commit ")
+                    .append(repo.friendly_describe_commit_from_oid(*commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("     reason: ")
+                    .append_plain(reason)
+                    .build(),
+            ]
+        }
+
+        Event::HideEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+            reason: None,
         }
         | Event::RewriteEvent {
             timestamp: _,
@@ -137,11 +188,12 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
             old_oid: MaybeZeroOid::Zero,
             new_oid: MaybeZeroOid::NonZero(new_oid),
             message: _,
-        } if ref_name == "HEAD" => {
+        } if is_worktree_head_ref(ref_name) => {
             // Not sure if this can happen. When a repo is created, maybe?
             vec![
                 StyledStringBuilder::new()
-                    .append_plain("Check out to ")
+                    .append_plain(describe_checkout(repo, ref_name))
+                    .append_plain(" to ")
                     .append(repo.friendly_describe_commit_from_oid(*new_oid)?)
                     .build(),
                 StyledString::new(),
@@ -155,14 +207,18 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
             old_oid: MaybeZeroOid::NonZero(old_oid),
             new_oid: MaybeZeroOid::NonZero(new_oid),
             message: _,
-        } if ref_name == "HEAD" => {
+        } if is_worktree_head_ref(ref_name) => {
+            let checkout = describe_checkout(repo, ref_name);
+            let padding = " ".repeat(display_width(&checkout) + 3);
             vec![
                 StyledStringBuilder::new()
-                    .append_plain("Check out from ")
+                    .append_plain(checkout)
+                    .append_plain(" from ")
                     .append(repo.friendly_describe_commit_from_oid(*old_oid)?)
                     .build(),
                 StyledStringBuilder::new()
-                    .append_plain("            to ")
+                    .append_plain(padding)
+                    .append_plain("to ")
                     .append(repo.friendly_describe_commit_from_oid(*new_oid)?)
                     .build(),
             ]
@@ -200,7 +256,7 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
                     .append_plain("Create ")
                     .append_plain(CategorizedReferenceName::new(ref_name).friendly_describe())
                     .append_plain(" at ")
-                    .append(repo.friendly_describe_commit_from_oid(*new_oid)?)
+                    .append(describe_ref_target(repo, *new_oid)?)
                     .build(),
                 StyledString::new(),
             ]
@@ -219,7 +275,7 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
                     .append_plain("Delete ")
                     .append_plain(CategorizedReferenceName::new(ref_name).friendly_describe())
                     .append_plain(" at ")
-                    .append(repo.friendly_describe_commit_from_oid(*old_oid)?)
+                    .append(describe_ref_target(repo, *old_oid)?)
                     .build(),
                 StyledString::new(),
             ]
@@ -239,13 +295,13 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
                     .append_plain("Move ")
                     .append_plain(ref_name.clone())
                     .append_plain(" from ")
-                    .append(repo.friendly_describe_commit_from_oid(*old_oid)?)
+                    .append(describe_ref_target(repo, *old_oid)?)
                     .build(),
                 StyledStringBuilder::new()
                     .append_plain("     ")
-                    .append_plain(" ".repeat(ref_name.len()))
+                    .append_plain(" ".repeat(display_width(&ref_name)))
                     .append_plain("   to ")
-                    .append(repo.friendly_describe_commit_from_oid(*new_oid)?)
+                    .append(describe_ref_target(repo, *new_oid)?)
                     .build(),
             ]
         }
@@ -268,6 +324,52 @@ fn describe_event(repo: &Repo, event: &Event) -> eyre::Result<Vec<StyledString>>
             ]
         }
 
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid,
+            new_commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Copy commit ")
+                    .append(repo.friendly_describe_commit_from_oid(*old_commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("        as ")
+                    .append(repo.friendly_describe_commit_from_oid(*new_commit_oid)?)
+                    .build(),
+            ]
+        }
+
+        Event::StashPushEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Push stash entry ")
+                    .append(repo.friendly_describe_commit_from_oid(*commit_oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
+        Event::StashDropEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Drop stash entry ")
+                    .append(repo.friendly_describe_commit_from_oid(*commit_oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
         Event::RewriteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -298,7 +400,7 @@ fn describe_events_numbered(
             let prefix = if j == 0 {
                 num_header.clone()
             } else {
-                " ".repeat(num_header.len())
+                " ".repeat(display_width(&num_header))
             };
             lines.push(
                 StyledStringBuilder::new()
@@ -311,13 +413,210 @@ fn describe_events_numbered(
     Ok(lines)
 }
 
+/// Describe the command which created the transaction with the given message,
+/// for transactions which didn't record the command itself. Transactions
+/// created by hooks aren't described, since their names are only meaningful to
+/// `git-branchless` itself.
+fn describe_transaction_message(message: &str) -> Option<StyledString> {
+    match message {
+        "post-commit" | "post-merge" | "reference-transaction" => None,
+        message if message.starts_with("hook-") => None,
+        message => Some(StyledString::plain(format!("Transaction: {}", message))),
+    }
+}
+
+fn describe_failed_command(command: &TransactionCommand) -> StyledString {
+    StyledStringBuilder::new()
+        .append_styled(
+            format!(
+                "Command failed with exit code {}: git {}",
+                command.exit_code,
+                command.args.join(" ")
+            ),
+            Palette::current().error,
+        )
+        .build()
+}
+
+/// How long to wait for the smartlog of a past state to be rendered before
+/// showing a placeholder instead, so that quick renders don't flicker.
+const SMARTLOG_PLACEHOLDER_DELAY: Duration = Duration::from_millis(100);
+
+/// The maximum number of rendered smartlogs to keep around in the TUI, since
+/// each one can be large in big repositories.
+const SMARTLOG_CACHE_CAPACITY: usize = 16;
+
+/// A cache which holds at most `capacity` entries, evicting the least recently
+/// used one when it's full. The number of entries is expected to be small, so
+/// they're stored in a list ordered from least to most recently used.
+#[derive(Debug)]
+struct LruCache<K, V> {
+    capacity: usize,
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Look up the value for `key`, marking it as the most recently used.
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(entry_key, _)| entry_key == key)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, value)| value)
+    }
+
+    /// Insert the value for `key`, replacing any existing value, and evict the
+    /// least recently used entry if the cache is over capacity.
+    fn insert(&mut self, key: K, value: V) {
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|(entry_key, _)| *entry_key == key)
+        {
+            self.entries.remove(index);
+        }
+        self.entries.push_back((key, value));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// The smartlogs which were already rendered, by the cursor they were rendered
+/// for.
+type SmartlogCache = LruCache<EventCursor, Vec<StyledString>>;
+
+/// A smartlog rendered by `render_smartlogs`, along with the cursor it was
+/// rendered for.
+type RenderedSmartlog = eyre::Result<(EventCursor, Vec<StyledString>)>;
+
+/// Render the smartlogs for the cursors received from `requests` on a worker
+/// thread, so that building large graphs doesn't freeze the TUI. A render
+/// can't be interrupted once it has started, but requests which have been
+/// superseded by the time it finishes are skipped.
+fn render_smartlogs(
+    effects: &Effects,
+    repo_path: &Path,
+    event_replayer: &EventReplayer,
+    requests: Receiver<EventCursor>,
+    results: Sender<RenderedSmartlog>,
+) {
+    let result = (|| -> eyre::Result<()> {
+        // The repository and its database connection can't be shared between
+        // threads, so open them again here.
+        let repo = Repo::from_dir(repo_path)?;
+        let conn = repo.get_db_conn()?;
+        let merge_base_db = make_merge_base_db(effects, &repo, &conn, event_replayer)?;
+        while let Ok(mut cursor) = requests.recv() {
+            while let Ok(next_cursor) = requests.try_recv() {
+                cursor = next_cursor;
+            }
+            let smartlog =
+                render_cursor_smartlog(effects, &repo, &merge_base_db, event_replayer, cursor)?;
+            if results.send(Ok((cursor, smartlog))).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    })();
+    if let Err(err) = result {
+        // If the TUI has already exited, there's nobody left to report to.
+        let _ = results.send(Err(err));
+    }
+}
+
+/// Wait for `render_smartlogs` to finish rendering the smartlog for `cursor`,
+/// caching any other smartlogs which are received in the meantime. Returns
+/// `None` if it isn't finished within `timeout`. If `timeout` is `None`, waits
+/// until it's finished.
+fn receive_smartlog(
+    results: &Receiver<RenderedSmartlog>,
+    smartlog_cache: &mut SmartlogCache,
+    cursor: EventCursor,
+    timeout: Option<Duration>,
+) -> eyre::Result<Option<Vec<StyledString>>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let result = match deadline {
+            Some(deadline) => {
+                match results.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(result) => result,
+                    Err(RecvTimeoutError::Timeout) => return Ok(None),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        eyre::bail!("Smartlog rendering thread exited unexpectedly")
+                    }
+                }
+            }
+            None => match results.recv() {
+                Ok(result) => result,
+                Err(_) => eyre::bail!("Smartlog rendering thread exited unexpectedly"),
+            },
+        };
+        let (result_cursor, smartlog) = result?;
+        smartlog_cache.insert(result_cursor, smartlog.clone());
+        if result_cursor == cursor {
+            return Ok(Some(smartlog));
+        }
+    }
+}
+
+/// Let the user select a past state of the repository. The smartlogs are
+/// rendered on a worker thread. Until the smartlog for the selected state is
+/// ready, a placeholder is shown if it takes longer than `placeholder_delay`,
+/// or the TUI waits for it if `placeholder_delay` is `None`.
 #[instrument(skip(siv))]
 fn select_past_event(
-    mut siv: CursiveRunner<CursiveRunnable>,
+    siv: CursiveRunner<CursiveRunnable>,
     effects: &Effects,
     repo: &Repo,
-    merge_base_db: &impl MergeBaseDb,
-    event_replayer: &mut EventReplayer,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    placeholder_delay: Option<Duration>,
+) -> eyre::Result<Option<EventCursor>> {
+    let repo_path = repo.get_path().to_owned();
+    std::thread::scope(|scope| {
+        let (request_tx, request_rx) = channel();
+        let (result_tx, result_rx) = channel();
+        let worker_effects = effects.clone();
+        let repo_path = &repo_path;
+        scope.spawn(move || {
+            render_smartlogs(
+                &worker_effects,
+                repo_path,
+                event_replayer,
+                request_rx,
+                result_tx,
+            )
+        });
+        select_past_event_with_worker(
+            siv,
+            repo,
+            event_log_db,
+            event_replayer,
+            placeholder_delay,
+            request_tx,
+            result_rx,
+        )
+    })
+}
+
+fn select_past_event_with_worker(
+    mut siv: CursiveRunner<CursiveRunnable>,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    placeholder_delay: Option<Duration>,
+    request_tx: Sender<EventCursor>,
+    result_rx: Receiver<RenderedSmartlog>,
 ) -> eyre::Result<Option<EventCursor>> {
     #[derive(Clone, Copy, Debug)]
     enum Message {
@@ -329,6 +628,12 @@ fn select_past_event(
         Help,
         Quit,
         SelectEventIdAndQuit,
+        ToggleTimeFormat,
+    }
+    #[derive(Clone, Copy, Debug)]
+    enum TimeFormat {
+        Relative,
+        Absolute,
     }
     let (main_tx, main_rx): (Sender<Message>, Receiver<Message>) = channel();
 
@@ -344,6 +649,8 @@ fn select_past_event(
         ('?'.into(), Message::Help),
         ('g'.into(), Message::GoToEvent),
         ('G'.into(), Message::GoToEvent),
+        ('t'.into(), Message::ToggleTimeFormat),
+        ('T'.into(), Message::ToggleTimeFormat),
         ('q'.into(), Message::Quit),
         ('Q'.into(), Message::Quit),
         (
@@ -362,6 +669,20 @@ fn select_past_event(
 
     let mut cursor = event_replayer.make_default_cursor();
     let now = SystemTime::now();
+    let relative_time_provider = RelativeTimeProvider::new(repo, now)?;
+    // Only show times by default if the user wants relative times in the
+    // smartlog, but let them be shown on request either way.
+    let mut time_format = if relative_time_provider.is_enabled() {
+        Some(TimeFormat::Relative)
+    } else {
+        None
+    };
+    // Rendering the smartlog for a cursor means building the graph of that
+    // point in history, which is slow in large repositories, so keep the
+    // result around for when the user steps back to the same cursor. This
+    // also holds the smartlogs received from the worker thread. Only the most
+    // recently viewed ones are kept, since each one can be large.
+    let mut smartlog_cache = SmartlogCache::new(SMARTLOG_CACHE_CAPACITY);
     main_tx.send(Message::Init)?;
     while siv.is_running() {
         let message = main_rx.try_recv();
@@ -378,12 +699,44 @@ fn select_past_event(
             InfoView => TextView,
         }
 
+        loop {
+            let (result_cursor, smartlog) = match result_rx.try_recv() {
+                Ok(result) => result?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    eyre::bail!("Smartlog rendering thread exited unexpectedly")
+                }
+            };
+            if result_cursor == cursor {
+                SmartlogView::find(&mut siv)
+                    .get_inner_mut()
+                    .set_content(StyledStringBuilder::from_lines(smartlog.clone()));
+                siv.refresh();
+            }
+            smartlog_cache.insert(result_cursor, smartlog);
+        }
+
         let redraw = |siv: &mut Cursive,
-                      event_replayer: &mut EventReplayer,
-                      event_cursor: EventCursor|
+                      event_replayer: &EventReplayer,
+                      smartlog_cache: &mut SmartlogCache,
+                      event_cursor: EventCursor,
+                      time_format: Option<TimeFormat>|
          -> eyre::Result<()> {
-            let smartlog =
-                render_cursor_smartlog(effects, repo, merge_base_db, event_replayer, event_cursor)?;
+            let smartlog = match smartlog_cache.get(&event_cursor) {
+                Some(smartlog) => smartlog.clone(),
+                None => {
+                    request_tx.send(event_cursor)?;
+                    match receive_smartlog(
+                        &result_rx,
+                        smartlog_cache,
+                        event_cursor,
+                        placeholder_delay,
+                    )? {
+                        Some(smartlog) => smartlog,
+                        None => vec![StyledString::plain("Rendering commit graph...")],
+                    }
+                }
+            };
             SmartlogView::find(siv)
                 .get_inner_mut()
                 .set_content(StyledStringBuilder::from_lines(smartlog));
@@ -395,28 +748,56 @@ fn select_past_event(
                 )],
                 Some((event_id, events)) => {
                     let event_description_lines = describe_events_numbered(repo, events)?;
-                    let relative_time_provider = RelativeTimeProvider::new(repo, now)?;
-                    let relative_time = if relative_time_provider.is_enabled() {
-                        format!(
+                    let timestamp = events[0].get_timestamp();
+                    let time = match time_format {
+                        None => String::new(),
+                        Some(TimeFormat::Relative) => format!(
                             " ({} ago)",
-                            RelativeTimeProvider::describe_time_delta(
-                                now,
-                                events[0].get_timestamp()
-                            )?
-                        )
-                    } else {
-                        String::new()
+                            RelativeTimeProvider::describe_time_delta(now, timestamp)?
+                        ),
+                        Some(TimeFormat::Absolute) => {
+                            let seconds = timestamp.duration_since(SystemTime::UNIX_EPOCH)?;
+                            format!(
+                                " (at {} UTC)",
+                                format_date_time(seconds.as_secs().try_into()?)
+                            )
+                        }
                     };
 
+                    let event_tx_id = events[0].get_event_tx_id();
                     let mut lines = vec![StyledStringBuilder::new()
                         .append_plain("Repo after transaction ")
-                        .append_plain(events[0].get_event_tx_id().to_string())
+                        .append_plain(event_tx_id.to_string())
                         .append_plain(" (event ")
                         .append_plain(event_id.to_string())
                         .append_plain(")")
-                        .append_plain(relative_time)
+                        .append_plain(time)
                         .append_plain(". Press 'h' for help, 'q' to quit.")
                         .build()];
+                    match event_log_db.get_transaction_command(event_tx_id)? {
+                        Some(command) => {
+                            if command.is_failure() {
+                                lines.push(describe_failed_command(&command));
+                            }
+                        }
+                        None => {
+                            if let Some(line) = event_log_db
+                                .get_transaction_message(event_tx_id)?
+                                .as_deref()
+                                .and_then(describe_transaction_message)
+                            {
+                                lines.push(line);
+                            }
+                        }
+                    }
+                    for annotation in event_log_db.get_transaction_annotations(event_tx_id)? {
+                        lines.push(
+                            StyledStringBuilder::new()
+                                .append_styled("Note: ", Palette::current().warning)
+                                .append_plain(annotation)
+                                .build(),
+                        );
+                    }
                     lines.extend(event_description_lines);
                     lines
                 }
@@ -447,22 +828,60 @@ fn select_past_event(
                         .child(Panel::new(ScrollView::new(info_view)).title("Events"))
                         .full_width(),
                 );
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(
+                    &mut siv,
+                    event_replayer,
+                    &mut smartlog_cache,
+                    cursor,
+                    time_format,
+                )?;
             }
 
             Ok(Message::Next) => {
                 cursor = event_replayer.advance_cursor_by_transaction(cursor, 1);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(
+                    &mut siv,
+                    event_replayer,
+                    &mut smartlog_cache,
+                    cursor,
+                    time_format,
+                )?;
             }
 
             Ok(Message::Previous) => {
                 cursor = event_replayer.advance_cursor_by_transaction(cursor, -1);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(
+                    &mut siv,
+                    event_replayer,
+                    &mut smartlog_cache,
+                    cursor,
+                    time_format,
+                )?;
             }
 
             Ok(Message::SetEventReplayerCursor { event_id }) => {
                 cursor = event_replayer.make_cursor(event_id);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(
+                    &mut siv,
+                    event_replayer,
+                    &mut smartlog_cache,
+                    cursor,
+                    time_format,
+                )?;
+            }
+
+            Ok(Message::ToggleTimeFormat) => {
+                time_format = match time_format {
+                    Some(TimeFormat::Relative) => Some(TimeFormat::Absolute),
+                    Some(TimeFormat::Absolute) | None => Some(TimeFormat::Relative),
+                };
+                redraw(
+                    &mut siv,
+                    event_replayer,
+                    &mut smartlog_cache,
+                    cursor,
+                    time_format,
+                )?;
             }
 
             Ok(Message::GoToEvent) => {
@@ -506,6 +925,7 @@ h/?: Show this help.
 q: Quit.
 p/n or <left>/<right>: View next/previous state.
 g: Go to a provided event ID.
+t: Show when each transaction happened, switching between relative and absolute times.
 <enter>: Revert the repository to the given state (requires confirmation).
 
 You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it.
@@ -547,16 +967,24 @@ fn inverse_event(
             timestamp: _,
             event_tx_id: _,
             commit_oid,
+        }
+        | Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid: _,
+            new_commit_oid: commit_oid,
         } => Event::HideEvent {
             timestamp,
             event_tx_id,
             commit_oid,
+            reason: None,
         },
 
         Event::HideEvent {
             timestamp: _,
             event_tx_id: _,
             commit_oid,
+            reason: _,
         } => Event::UnhideEvent {
             timestamp,
             event_tx_id,
@@ -590,16 +1018,36 @@ fn inverse_event(
             new_oid: old_ref,
             message: None,
         },
+
+        Event::StashPushEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => Event::StashDropEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        },
+
+        Event::StashDropEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => Event::StashPushEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        },
     };
     Ok(inverse_event)
 }
 
-fn optimize_inverse_events(events: Vec<Event>) -> Vec<Event> {
+fn optimize_inverse_events(events: Vec<Event>, head_ref_name: &OsStr) -> Vec<Event> {
     let mut optimized_events = Vec::new();
     let mut seen_checkout = false;
     for event in events.into_iter().rev() {
         match event {
-            Event::RefUpdateEvent { ref ref_name, .. } if ref_name == "HEAD" => {
+            Event::RefUpdateEvent { ref ref_name, .. } if ref_name == head_ref_name => {
                 if seen_checkout {
                     continue;
                 } else {
@@ -623,36 +1071,54 @@ fn undo_events(
     event_log_db: &mut EventLogDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
+    autostash: bool,
 ) -> eyre::Result<isize> {
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
+    let head_ref_name = repo.get_head_ref_name();
     let inverse_events: Vec<Event> = event_replayer
         .get_events_since_cursor(event_cursor)
         .iter()
         .rev()
-        .filter(|event| {
-            !matches!(
-                event,
-                Event::RefUpdateEvent {
-                    timestamp: _,
-                    event_tx_id: _,
-                    ref_name,
-                    old_oid: MaybeZeroOid::Zero,
-                    new_oid: _,
-                    message: _,
-                } if ref_name == "HEAD"
-            )
+        .filter(|event| match event {
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name: _,
+                old_oid: MaybeZeroOid::NonZero(old_oid),
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+                message: _,
+            } if old_oid == new_oid => {
+                // Some commands, such as `git stash`, update references to
+                // the commit they already pointed to. There's nothing to undo
+                // for them, and undoing a checkout like this would detach
+                // `HEAD`.
+                false
+            }
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid,
+                new_oid: _,
+                message: _,
+            } if is_worktree_head_ref(ref_name) => {
+                // Checkouts in other worktrees aren't undone, since doing so
+                // would change the working copy out from under them.
+                ref_name == &head_ref_name && *old_oid != MaybeZeroOid::Zero
+            }
+            _ => true,
         })
         .map(|event| inverse_event(event.clone(), now, event_tx_id))
         .collect::<eyre::Result<Vec<Event>>>()?;
-    let mut inverse_events = optimize_inverse_events(inverse_events);
+    let mut inverse_events = optimize_inverse_events(inverse_events, &head_ref_name);
 
     // Move any checkout operations to be first. Otherwise, we have the risk
     // that `HEAD` is a symbolic reference pointing to another reference, and we
     // update that reference. This would cause the working copy to become dirty
     // from Git's perspective.
     inverse_events.sort_by_key(|event| match event {
-        Event::RefUpdateEvent { ref_name, .. } if ref_name == "HEAD" => 0,
+        Event::RefUpdateEvent { ref_name, .. } if ref_name == &head_ref_name => 0,
         _ => 1,
     });
 
@@ -691,6 +1157,33 @@ fn undo_events(
         return Ok(1);
     }
 
+    // Only stash the changes once the events to undo have been determined, or
+    // else the stash itself would be undone.
+    with_stashed_changes(effects, git_run_info, repo, event_tx_id, autostash, || {
+        apply_inverse_events(
+            effects,
+            repo,
+            git_run_info,
+            event_log_db,
+            event_tx_id,
+            &head_ref_name,
+            inverse_events,
+        )?;
+        Ok(0)
+    })
+}
+
+/// Apply the given inverse events, which are the events to undo.
+#[instrument]
+fn apply_inverse_events(
+    effects: &Effects,
+    repo: &Repo,
+    git_run_info: &GitRunInfo,
+    event_log_db: &mut EventLogDb,
+    event_tx_id: EventTransactionId,
+    head_ref_name: &OsStr,
+    inverse_events: Vec<Event>,
+) -> eyre::Result<()> {
     let num_inverse_events = Pluralize {
         amount: inverse_events.len().try_into().unwrap(),
         singular: "inverse event",
@@ -698,7 +1191,16 @@ fn undo_events(
     }
     .to_string();
 
-    for event in inverse_events.into_iter() {
+    // Queue all of the reference updates (other than the checkout, which is
+    // carried out immediately) into one transaction. The stash and the event
+    // log are only updated once the transaction has been committed, so that a
+    // failure while updating the references doesn't leave the other events
+    // undone without them.
+    let (ref_update_events, other_events): (Vec<Event>, Vec<Event>) = inverse_events
+        .into_iter()
+        .partition(|event| matches!(event, Event::RefUpdateEvent { .. }));
+    let mut transaction = repo.start_reference_transaction()?;
+    for event in ref_update_events {
         match event {
             Event::RefUpdateEvent {
                 timestamp: _,
@@ -707,7 +1209,7 @@ fn undo_events(
                 old_oid: _,
                 new_oid: MaybeZeroOid::NonZero(new_ref),
                 message: _,
-            } if ref_name == "HEAD" => {
+            } if ref_name == head_ref_name => {
                 let target_oid: OsString = new_ref.to_string().into();
                 // Most likely the user wanted to perform an actual checkout in
                 // this case, rather than just update `HEAD` (and be left with a
@@ -743,9 +1245,9 @@ fn undo_events(
                 new_oid: MaybeZeroOid::Zero,
                 message: _,
             } => match repo.find_reference(&ref_name)? {
-                Some(mut reference) => {
-                    reference
-                        .delete()
+                Some(_) => {
+                    transaction
+                        .remove(&ref_name)
                         .wrap_err_with(|| "Applying `RefUpdateEvent`")?;
                 }
                 None => {
@@ -773,12 +1275,97 @@ fn undo_events(
                 message: _,
             } => {
                 // Create or update the given reference.
-                repo.create_reference(&ref_name, new_oid, true, "branchless undo")?;
+                transaction
+                    .set_target(&ref_name, new_oid, "branchless undo")
+                    .wrap_err_with(|| "Applying `RefUpdateEvent`")?;
             }
+            event => eyre::bail!("Unexpected event when updating references: {:?}", event),
+        }
+    }
+    transaction.commit()?;
+
+    for event in other_events {
+        match event {
+            Event::RefUpdateEvent { .. } => {
+                eyre::bail!("Unexpected reference update: {:?}", event)
+            }
+            Event::StashPushEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => match repo.find_commit(commit_oid)? {
+                Some(commit) => {
+                    // `git stash store` invokes our hooks, which record the
+                    // new stash entry in the event log.
+                    let message = commit.get_summary()?;
+                    let commit_oid: OsString = commit_oid.to_string().into();
+                    let exit_code = git_run_info.run(
+                        effects,
+                        Some(event_tx_id),
+                        &[
+                            OsStr::new("stash"),
+                            OsStr::new("store"),
+                            OsStr::new("-m"),
+                            message.as_os_str(),
+                            commit_oid.as_os_str(),
+                        ],
+                    )?;
+                    if exit_code != 0 {
+                        eyre::bail!("Failed to restore stash entry: {:?}", commit_oid);
+                    }
+                }
+                None => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Stash entry {} no longer exists, not restoring it.",
+                        commit_oid
+                    )?;
+                }
+            },
+            Event::StashDropEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => match repo
+                .get_stash_oids()?
+                .into_iter()
+                .position(|stash_oid| stash_oid == commit_oid)
+            {
+                Some(index) => {
+                    let stash_entry = format!("stash@{{{}}}", index);
+                    event_log_db.set_stash_snapshot(&repo.get_stash_oids()?)?;
+                    let exit_code = git_run_info.run(
+                        effects,
+                        Some(event_tx_id),
+                        &["stash", "drop", "--quiet", &stash_entry],
+                    )?;
+                    if exit_code != 0 {
+                        eyre::bail!("Failed to drop stash entry: {}", stash_entry);
+                    }
+                    // Git doesn't invoke any hooks when one of several stash
+                    // entries is dropped, so record it here.
+                    let stash_events = record_stash_changes(
+                        repo,
+                        event_log_db,
+                        SystemTime::now(),
+                        event_tx_id,
+                        &[],
+                    )?;
+                    event_log_db.add_events(stash_events)?;
+                }
+                None => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Stash entry {} is not in the stash, not dropping it.",
+                        commit_oid
+                    )?;
+                }
+            },
             Event::CommitEvent { .. }
             | Event::HideEvent { .. }
             | Event::UnhideEvent { .. }
-            | Event::RewriteEvent { .. } => {
+            | Event::RewriteEvent { .. }
+            | Event::CopyEvent { .. } => {
                 event_log_db.add_events(vec![event])?;
             }
         }
@@ -789,21 +1376,38 @@ fn undo_events(
         "Applied {}.",
         num_inverse_events
     )?;
-    Ok(0)
+    Ok(())
 }
 
 /// Restore the repository to a previous state interactively.
+///
+/// Only the events of the current user are browsed and undone, unless
+/// `all_users` is set. If `autostash` is set, it overrides
+/// `branchless.autoStash`.
 #[instrument]
-pub fn undo(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<isize> {
+pub fn undo(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    all_users: bool,
+    autostash: Option<bool>,
+) -> eyre::Result<isize> {
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
-    let mut event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
-    let merge_base_db = make_merge_base_db(effects, &repo, &conn, &event_replayer)?;
+    let user = if all_users { None } else { get_current_user() };
+    let event_replayer =
+        EventReplayer::from_event_log_db_for_user(effects, &repo, &event_log_db, user.as_deref())?;
 
     let event_cursor = {
         let result = with_siv(effects, |effects, siv| {
-            select_past_event(siv, &effects, &repo, &merge_base_db, &mut event_replayer)
+            select_past_event(
+                siv,
+                &effects,
+                &repo,
+                &event_log_db,
+                &event_replayer,
+                Some(SMARTLOG_PLACEHOLDER_DELAY),
+            )
         })?;
         match result {
             Some(event_cursor) => event_cursor,
@@ -819,6 +1423,7 @@ pub fn undo(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<isize>
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        should_autostash(&repo, autostash)?,
     )?;
     Ok(result)
 }
@@ -830,7 +1435,6 @@ pub mod testing {
     use cursive::{CursiveRunnable, CursiveRunner};
 
     use crate::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
-    use crate::core::mergebase::MergeBaseDb;
     use crate::git::{GitRunInfo, Repo};
     use crate::tui::Effects;
 
@@ -838,10 +1442,12 @@ pub mod testing {
         siv: CursiveRunner<CursiveRunnable>,
         effects: &Effects,
         repo: &Repo,
-        merge_base_db: &impl MergeBaseDb,
-        event_replayer: &mut EventReplayer,
+        event_log_db: &EventLogDb,
+        event_replayer: &EventReplayer,
     ) -> eyre::Result<Option<EventCursor>> {
-        super::select_past_event(siv, effects, repo, merge_base_db, event_replayer)
+        // Wait for each smartlog to be rendered, so that the screenshots don't
+        // depend on how quickly that happens.
+        super::select_past_event(siv, effects, repo, event_log_db, event_replayer, None)
     }
 
     pub fn undo_events(
@@ -852,6 +1458,7 @@ pub mod testing {
         event_log_db: &mut EventLogDb,
         event_replayer: &EventReplayer,
         event_cursor: EventCursor,
+        autostash: bool,
     ) -> eyre::Result<isize> {
         super::undo_events(
             in_,
@@ -861,6 +1468,7 @@ pub mod testing {
             event_log_db,
             event_replayer,
             event_cursor,
+            autostash,
         )
     }
 }
@@ -871,6 +1479,24 @@ mod tests {
 
     use crate::core::eventlog::testing::make_dummy_transaction_id;
 
+    #[test]
+    fn test_lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some(&"a"));
+
+        // `2` is now the least recently used entry.
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+
+        cache.insert(3, "d");
+        assert_eq!(cache.get(&3), Some(&"d"));
+        assert_eq!(cache.entries.len(), 2);
+    }
+
     #[test]
     fn test_optimize_inverse_events() -> eyre::Result<()> {
         let event_tx_id = make_dummy_transaction_id(123);
@@ -900,7 +1526,7 @@ mod tests {
             new_oid: MaybeZeroOid::NonZero("3".parse()?),
             message: None,
         }];
-        assert_eq!(optimize_inverse_events(input), expected);
+        assert_eq!(optimize_inverse_events(input, OsStr::new("HEAD")), expected);
         Ok(())
     }
 }
@@ -6,8 +6,11 @@ use std::process::Command;
 use std::time::SystemTime;
 
 use eyre::Context;
+use tracing::warn;
 
-use crate::core::eventlog::{EventLogDb, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use crate::core::eventlog::{
+    record_stash_changes, EventLogDb, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
 use crate::git::{GitRunInfo, Repo};
 
 fn pass_through_git_command<S: AsRef<str> + std::fmt::Debug>(
@@ -49,6 +52,33 @@ fn make_event_tx_id<S: AsRef<str> + std::fmt::Debug>(
     Ok(event_tx_id)
 }
 
+/// Save a snapshot of the stash before running the command, so that the
+/// entries which the command adds or removes can be recorded afterwards.
+fn save_stash_snapshot() -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    event_log_db.set_stash_snapshot(&repo.get_stash_oids()?)?;
+    Ok(())
+}
+
+fn record_command<S: AsRef<str> + std::fmt::Debug>(
+    args: &[S],
+    event_tx_id: EventTransactionId,
+    exit_code: isize,
+) -> eyre::Result<()> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    event_log_db.add_transaction_command(event_tx_id, args, exit_code)?;
+    // Git doesn't invoke any hooks when one of several stash entries is
+    // dropped, so those changes are only detected here.
+    let stash_events = record_stash_changes(&repo, &event_log_db, now, event_tx_id, &[])?;
+    event_log_db.add_events(stash_events)?;
+    Ok(())
+}
+
 /// Run the provided Git command, but wrapped in an event transaction.
 pub fn wrap<S: AsRef<str> + std::fmt::Debug>(
     git_run_info: &GitRunInfo,
@@ -57,8 +87,20 @@ pub fn wrap<S: AsRef<str> + std::fmt::Debug>(
     // We may not be able to make an event transaction ID (such as if there is
     // no repository in the current directory). Ignore the error in that case.
     let event_tx_id = make_event_tx_id(args).ok();
+    if event_tx_id.is_some() {
+        if let Err(err) = save_stash_snapshot() {
+            warn!(?err, "Could not save a snapshot of the stash");
+        }
+    }
 
     let exit_code = pass_through_git_command(git_run_info, args, event_tx_id)?;
+    if let Some(event_tx_id) = event_tx_id {
+        // The command has already run, so its exit code is returned even if
+        // it couldn't be recorded.
+        if let Err(err) = record_command(args, event_tx_id, exit_code) {
+            warn!(?err, "Could not record the wrapped command");
+        }
+    }
     Ok(exit_code)
 }
 
@@ -1,9 +1,17 @@
 //! Core algorithms and data structures.
 
+pub mod anonymize;
+pub mod autostash;
+pub mod branch_names;
+pub mod ci_status;
 pub mod config;
 pub mod eventlog;
 pub mod formatting;
+pub mod github;
 pub mod graph;
 pub mod mergebase;
 pub mod metadata;
+pub mod phabricator;
+pub mod revset;
 pub mod rewrite;
+pub mod submit;
@@ -0,0 +1,106 @@
+//! Replace identifying information about a repository with placeholders, so
+//! that its output can be shared publicly, such as when reporting a bug.
+//!
+//! Each distinct value is always replaced with the same placeholder, so the
+//! structure of the output (which commits have the same branch, and so on) is
+//! preserved.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+
+use crate::git::NonZeroOid;
+
+/// Replaces commit hashes, reference names and commit messages with stable
+/// placeholders.
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    oids: HashMap<NonZeroOid, String>,
+    ref_names: HashMap<OsString, String>,
+    messages: HashMap<NonZeroOid, String>,
+}
+
+impl Anonymizer {
+    /// Get a pseudo-hash for the given commit, which has the same form as the
+    /// full hash of a commit but can't be looked up in the repository.
+    pub fn anonymize_oid(&mut self, oid: NonZeroOid) -> String {
+        self.oids
+            .entry(oid)
+            .or_insert_with(|| {
+                let bytes = format!("git-branchless anonymized {}", oid);
+                match git2::Oid::hash_object(git2::ObjectType::Blob, bytes.as_bytes()) {
+                    Ok(pseudo_oid) => pseudo_oid.to_string(),
+                    Err(_) => oid.to_string(),
+                }
+            })
+            .clone()
+    }
+
+    /// Get a placeholder for the given reference name which keeps its kind
+    /// (such as `refs/heads/`). `HEAD` is kept as-is.
+    pub fn anonymize_ref_name(&mut self, ref_name: &OsStr) -> String {
+        let ref_name_str = ref_name.to_string_lossy();
+        if ref_name_str == "HEAD" || ref_name_str.ends_with("/HEAD") {
+            return ref_name_str.into_owned();
+        }
+        let num_names = self.ref_names.len();
+        self.ref_names
+            .entry(ref_name.to_owned())
+            .or_insert_with(|| {
+                let prefix = ["refs/heads/", "refs/remotes/", "refs/tags/", "refs/"]
+                    .iter()
+                    .find(|prefix| ref_name_str.starts_with(*prefix))
+                    .copied()
+                    .unwrap_or_default();
+                format!("{}ref-{}", prefix, num_names + 1)
+            })
+            .clone()
+    }
+
+    /// Get a placeholder for the message of the given commit.
+    pub fn anonymize_message(&mut self, oid: NonZeroOid) -> String {
+        let num_messages = self.messages.len();
+        self.messages
+            .entry(oid)
+            .or_insert_with(|| format!("message-{}", num_messages + 1))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anonymizer_is_stable() -> eyre::Result<()> {
+        let oid1: NonZeroOid = "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?;
+        let oid2: NonZeroOid = "96d1c37a3d4363611c49f7e52186e189a04c531f".parse()?;
+        let mut anonymizer = Anonymizer::default();
+
+        let pseudo_oid1 = anonymizer.anonymize_oid(oid1);
+        assert_eq!(pseudo_oid1.len(), 40);
+        assert_ne!(pseudo_oid1, oid1.to_string());
+        assert_eq!(anonymizer.anonymize_oid(oid1), pseudo_oid1);
+        assert_ne!(anonymizer.anonymize_oid(oid2), pseudo_oid1);
+        assert_eq!(Anonymizer::default().anonymize_oid(oid1), pseudo_oid1);
+
+        assert_eq!(
+            anonymizer.anonymize_ref_name(OsStr::new("refs/heads/secret")),
+            "refs/heads/ref-1"
+        );
+        assert_eq!(
+            anonymizer.anonymize_ref_name(OsStr::new("refs/remotes/origin/secret")),
+            "refs/remotes/ref-2"
+        );
+        assert_eq!(
+            anonymizer.anonymize_ref_name(OsStr::new("refs/heads/secret")),
+            "refs/heads/ref-1"
+        );
+        assert_eq!(anonymizer.anonymize_ref_name(OsStr::new("HEAD")), "HEAD");
+
+        assert_eq!(anonymizer.anonymize_message(oid2), "message-1");
+        assert_eq!(anonymizer.anonymize_message(oid1), "message-2");
+        assert_eq!(anonymizer.anonymize_message(oid2), "message-1");
+
+        Ok(())
+    }
+}
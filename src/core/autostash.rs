@@ -0,0 +1,167 @@
+//! Stash the uncommitted changes in the working copy before an operation which
+//! can't be carried out with them, and restore them afterwards.
+//!
+//! The changes are stashed and restored with `git stash`, so the stash entry
+//! is recorded in the event log as part of the operation's transaction, and
+//! `git undo` can restore it.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::{instrument, warn};
+
+use crate::core::config::get_autostash;
+use crate::core::eventlog::{record_stash_changes, EventLogDb, EventTransactionId};
+use crate::git::{GitRunInfo, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// The message of the stash entries created by `stash_changes`.
+const AUTOSTASH_MESSAGE: &str = "branchless: autostash";
+
+/// Uncommitted changes which were stashed by `stash_changes`.
+#[derive(Debug)]
+pub struct AutoStash {
+    stash_oid: NonZeroOid,
+}
+
+/// Determine whether to stash uncommitted changes, where `autostash` is the
+/// value passed on the command-line, if any, and otherwise the value of
+/// `branchless.autoStash` is used.
+pub fn should_autostash(repo: &Repo, autostash: Option<bool>) -> eyre::Result<bool> {
+    match autostash {
+        Some(autostash) => Ok(autostash),
+        None => get_autostash(repo),
+    }
+}
+
+/// Stash the staged and unstaged changes in the working copy, if there are
+/// any. Untracked files are left as they are.
+#[instrument]
+pub fn stash_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<Option<AutoStash>> {
+    if !repo.has_changed_files(effects)? {
+        return Ok(None);
+    }
+
+    let exit_code = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["stash", "push", "--quiet", "--message", AUTOSTASH_MESSAGE],
+    )?;
+    if exit_code != 0 {
+        eyre::bail!("Could not stash uncommitted changes");
+    }
+    match repo.get_stash_oids()?.first() {
+        Some(stash_oid) => {
+            writeln!(effects.get_output_stream(), "Stashed uncommitted changes.")?;
+            Ok(Some(AutoStash {
+                stash_oid: *stash_oid,
+            }))
+        }
+        None => eyre::bail!("Stashed uncommitted changes, but the stash is empty"),
+    }
+}
+
+/// Restore the changes stashed by `stash_changes`, if any. If an operation such as
+/// a rebase is still in progress, or the changes can't be applied cleanly,
+/// they're left in the stash instead.
+///
+/// Returns an exit code (0 denotes successful exit).
+#[instrument]
+pub fn restore_stashed_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    stash: Option<AutoStash>,
+) -> eyre::Result<isize> {
+    let AutoStash { stash_oid } = match stash {
+        Some(stash) => stash,
+        None => return Ok(0),
+    };
+    let stash_entry = match repo
+        .get_stash_oids()?
+        .into_iter()
+        .position(|oid| oid == stash_oid)
+    {
+        Some(index) => format!("stash@{{{}}}", index),
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Stashed changes {} are no longer in the stash, not restoring them.",
+                stash_oid
+            )?;
+            return Ok(1);
+        }
+    };
+
+    if let Some(operation_type) = repo.get_current_operation_type() {
+        writeln!(
+            effects.get_output_stream(),
+            "Your uncommitted changes are saved as {}. Run git stash pop {} once the {} is finished to restore them.",
+            stash_entry, stash_entry, operation_type
+        )?;
+        return Ok(0);
+    }
+
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    event_log_db.set_stash_snapshot(&repo.get_stash_oids()?)?;
+    let exit_code = git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["stash", "pop", "--quiet", "--index", &stash_entry],
+    )?;
+    // Git doesn't invoke any hooks when one of several stash entries is
+    // popped, so record it here.
+    let stash_events =
+        record_stash_changes(repo, &event_log_db, SystemTime::now(), event_tx_id, &[])?;
+    event_log_db.add_events(stash_events)?;
+    if exit_code != 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Your uncommitted changes could not be restored cleanly, so they're still saved as {}.",
+            stash_entry
+        )?;
+        return Ok(exit_code);
+    }
+    writeln!(effects.get_output_stream(), "Restored uncommitted changes.")?;
+    Ok(0)
+}
+
+/// Call `f` with the uncommitted changes stashed, if `autostash` is set, and
+/// restore them afterwards. The changes are restored even if `f` fails, in
+/// which case its error is returned once they have been restored.
+///
+/// Returns an exit code (0 denotes successful exit), which is the exit code of
+/// `f` if it failed, and otherwise the exit code of restoring the changes.
+pub fn with_stashed_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    autostash: bool,
+    f: impl FnOnce() -> eyre::Result<isize>,
+) -> eyre::Result<isize> {
+    let stash = if autostash {
+        stash_changes(effects, git_run_info, repo, event_tx_id)?
+    } else {
+        None
+    };
+    let result = f();
+    let restore_result = restore_stashed_changes(effects, git_run_info, repo, event_tx_id, stash);
+    match (result, restore_result) {
+        (Ok(0), restore_result) => restore_result,
+        (Ok(exit_code), Ok(_)) => Ok(exit_code),
+        (Ok(_), Err(err)) => Err(err),
+        (Err(err), Ok(_)) => Err(err),
+        (Err(err), Err(restore_err)) => {
+            warn!(?restore_err, "Could not restore stashed changes");
+            Err(err)
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Generate names for branches from the commits they point to.
+//!
+//! Names are rendered from the template in `branchless.branch.nameTemplate`,
+//! which can refer to the commit's author (`{user}` or `{username}`), its date
+//! (`{date}`), its change ID (`{change-id}`) and a slug derived from its
+//! subject line (`{slug}`). The slug is truncated to
+//! `branchless.branch.maxSlugLength` characters.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+
+use eyre::Context;
+use os_str_bytes::OsStrBytes;
+use tracing::instrument;
+
+use crate::core::config::{get_branch_max_slug_length, get_branch_name_template};
+use crate::core::eventlog::{
+    ChangeId, ChangeVersion, EventLogDb, EventReplayer, EventTransactionId,
+};
+use crate::core::formatting::format_date;
+use crate::core::submit::Stack;
+use crate::git::{Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+/// Convert a commit subject line into a form suitable for a branch name, such
+/// as `fix-the-frobnicator`, which is at most `max_length` characters long.
+pub fn slugify(subject: &str, max_length: usize) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.len() > max_length {
+        // Prefer to cut the slug at a word boundary.
+        let is_word_boundary = slug.as_bytes()[max_length] == b'-';
+        slug.truncate(max_length);
+        if !is_word_boundary {
+            if let Some(index) = slug.rfind('-') {
+                slug.truncate(index);
+            }
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Render the given branch name template for the given commit, which belongs
+/// to the change `change_id`.
+fn render_template(
+    template: &str,
+    max_slug_length: usize,
+    commit: &Commit,
+    change_id: ChangeId,
+) -> eyre::Result<String> {
+    let author = commit.get_author();
+    let user = author
+        .get_email()
+        .and_then(|email| email.split('@').next())
+        .map(|user| slugify(user, usize::MAX))
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| "user".to_string());
+    let time = commit.get_time();
+    let date = format_date(time.seconds() + i64::from(time.offset_minutes()) * 60);
+    let slug = slugify(&commit.get_summary()?.to_string_lossy(), max_slug_length);
+    let slug = if slug.is_empty() {
+        commit.get_oid().to_string()[..8].to_string()
+    } else {
+        slug
+    };
+    Ok(template
+        .replace("{user}", &user)
+        .replace("{username}", &user)
+        .replace("{date}", &date)
+        .replace("{change-id}", &change_id.to_short_string())
+        .replace("{slug}", &slug))
+}
+
+/// Generate a name for a new branch pointing to the given commit, which
+/// belongs to the change `change_id`. The name doesn't conflict with any
+/// branch in `existing_branch_names` (given without the `refs/heads/` prefix).
+#[instrument(skip(existing_branch_names))]
+pub fn generate_branch_name(
+    repo: &Repo,
+    commit: &Commit,
+    change_id: ChangeId,
+    existing_branch_names: &HashSet<String>,
+) -> eyre::Result<String> {
+    let template = get_branch_name_template(repo)?;
+    let max_slug_length = get_branch_max_slug_length(repo)?;
+    let branch_name = render_template(&template, max_slug_length, commit, change_id)?;
+    if !existing_branch_names.contains(&branch_name) {
+        return Ok(branch_name);
+    }
+    let branch_name = (2..)
+        .map(|suffix| format!("{}-{}", branch_name, suffix))
+        .find(|branch_name| !existing_branch_names.contains(branch_name))
+        .expect("Ran out of branch name suffixes");
+    Ok(branch_name)
+}
+
+/// Get the names of all local branches, without the `refs/heads/` prefix.
+pub fn get_local_branch_names(repo: &Repo) -> eyre::Result<HashSet<String>> {
+    let mut result = HashSet::new();
+    for names in repo.get_branch_oid_to_names()?.values() {
+        for name in names {
+            let name = name.to_string_lossy();
+            if let Some(name) = name.strip_prefix("refs/heads/") {
+                result.insert(name.to_string());
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Get the names which can't be used for a new branch: those of all local
+/// branches, and those of all branches on remotes, so that pushing the new
+/// branch doesn't clobber one which was created elsewhere. The names are given
+/// without the `refs/heads/` or `refs/remotes/<remote>/` prefix.
+pub fn get_taken_branch_names(repo: &Repo) -> eyre::Result<HashSet<String>> {
+    let mut result = get_local_branch_names(repo)?;
+    for reference in repo.get_references_matching("refs/remotes/*")? {
+        let name = reference.get_name()?;
+        let name = name.to_string_lossy();
+        let name = name
+            .strip_prefix("refs/remotes/")
+            .and_then(|name| name.split_once('/'))
+            .map(|(_remote_name, branch_name)| branch_name);
+        match name {
+            Some("HEAD") | None => {}
+            Some(name) => {
+                result.insert(name.to_string());
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Create the given branches, which are given as pairs of the branch name
+/// (without the `refs/heads/` prefix) and the commit it should point to. The
+/// creations are recorded in the event log as part of the given transaction.
+#[instrument]
+pub fn create_branches(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    branches: &[(String, NonZeroOid)],
+) -> eyre::Result<()> {
+    let mut transaction = repo.start_reference_transaction()?;
+    let mut stdin = Vec::new();
+    for (branch_name, commit_oid) in branches {
+        let ref_name = OsString::from(format!("refs/heads/{}", branch_name));
+        transaction.set_target(&ref_name, *commit_oid, "branchless: create branch")?;
+        stdin.extend(format!("{} {} ", MaybeZeroOid::Zero, commit_oid).as_bytes());
+        stdin.extend(ref_name.to_raw_bytes().iter());
+        stdin.push(b'\n');
+    }
+    transaction.commit()?;
+
+    // Creating the references via libgit2 doesn't invoke the
+    // `reference-transaction` hook, so invoke it ourselves to record the new
+    // branches.
+    let stdin = OsStrBytes::from_raw_bytes(stdin).wrap_err("Encoding branch creations stdin")?;
+    let stdin = OsString::from(stdin);
+    git_run_info.run_hook(
+        effects,
+        repo,
+        "reference-transaction",
+        event_tx_id,
+        &["committed"],
+        Some(stdin),
+        &HashMap::new(),
+    )?;
+    Ok(())
+}
+
+/// Generate a name for a branch pointing to the top of the stack, if the
+/// commits at the top of the stack don't have one. The branch is named after
+/// the first of those commits, since that's the one which will be used to
+/// describe them for review. The name doesn't conflict with any local or
+/// remote branch (see `get_taken_branch_names`).
+///
+/// Returns: The name of the branch and the commit it should point to, if a
+/// branch is needed.
+#[instrument(skip(stack))]
+pub fn name_branch_for_unbranched_commits(
+    effects: &Effects,
+    repo: &Repo,
+    stack: &Stack,
+) -> eyre::Result<Option<(String, NonZeroOid)>> {
+    let (first_oid, last_oid) = match (
+        stack.unbranched_commit_oids.first(),
+        stack.unbranched_commit_oids.last(),
+    ) {
+        (Some(first_oid), Some(last_oid)) => (*first_oid, *last_oid),
+        _ => return Ok(None),
+    };
+    let first_commit = repo.find_commit_or_fail(first_oid)?;
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let ChangeVersion { change_id, .. } =
+        event_replayer.get_cursor_change_version(event_replayer.make_default_cursor(), first_oid);
+
+    let branch_name = generate_branch_name(
+        repo,
+        &first_commit,
+        change_id,
+        &get_taken_branch_names(repo)?,
+    )?;
+    Ok(Some((branch_name, last_oid)))
+}
+
+/// Create a branch pointing to the top of the stack, if the commits at the top
+/// of the stack don't have one. See `name_branch_for_unbranched_commits`.
+///
+/// Returns: The name of the new branch and the commit it points to, if one was
+/// created.
+#[instrument(skip(stack))]
+pub fn create_branch_for_unbranched_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    stack: &Stack,
+) -> eyre::Result<Option<(String, NonZeroOid)>> {
+    let branch = match name_branch_for_unbranched_commits(effects, repo, stack)? {
+        Some(branch) => branch,
+        None => return Ok(None),
+    };
+    create_branches(
+        effects,
+        git_run_info,
+        repo,
+        event_tx_id,
+        std::slice::from_ref(&branch),
+    )?;
+    Ok(Some(branch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Fix the frobnicator", 40), "fix-the-frobnicator");
+        assert_eq!(
+            slugify("[core] Don't crash on empty input!", 40),
+            "core-don-t-crash-on-empty-input"
+        );
+        assert_eq!(
+            slugify(
+                "Add support for a very long feature name which goes on and on",
+                40
+            ),
+            "add-support-for-a-very-long-feature-name"
+        );
+        assert_eq!(
+            slugify("Add support for a very long feature description", 40),
+            "add-support-for-a-very-long-feature"
+        );
+        assert_eq!(slugify("Fix the frobnicator", 10), "fix-the");
+        assert_eq!(slugify("Frobnicator", 5), "frobn");
+        assert_eq!(slugify("!!!", 40), "");
+    }
+}
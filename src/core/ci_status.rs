@@ -0,0 +1,309 @@
+//! Fetch the CI status of commits.
+//!
+//! The status is fetched either by running the command in
+//! `branchless.ciStatus.command` with the commit hash as its argument, or from
+//! the GitHub Checks API for the repository of `branchless.ciStatus.remote`.
+//! Results are cached in the database, since fetching them can be slow.
+
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use eyre::Context;
+use rusqlite::OptionalExtension;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+use crate::core::github::GitHubClient;
+use crate::git::{NonZeroOid, Repo};
+
+/// The aggregate CI status of a commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiStatus {
+    /// All checks passed.
+    Success,
+
+    /// At least one check failed.
+    Failure,
+
+    /// No check failed, but at least one hasn't finished yet.
+    Pending,
+}
+
+impl Display for CiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CiStatus::Success => write!(f, "success"),
+            CiStatus::Failure => write!(f, "failure"),
+            CiStatus::Pending => write!(f, "pending"),
+        }
+    }
+}
+
+impl TryFrom<&str> for CiStatus {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "success" => Ok(CiStatus::Success),
+            "failure" => Ok(CiStatus::Failure),
+            "pending" => Ok(CiStatus::Pending),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Combine the statuses of several checks into one.
+fn combine_statuses(statuses: impl IntoIterator<Item = CiStatus>) -> Option<CiStatus> {
+    statuses
+        .into_iter()
+        .fold(None, |acc, status| match (acc, status) {
+            (Some(CiStatus::Failure), _) | (_, CiStatus::Failure) => Some(CiStatus::Failure),
+            (Some(CiStatus::Pending), _) | (_, CiStatus::Pending) => Some(CiStatus::Pending),
+            (_, CiStatus::Success) => Some(CiStatus::Success),
+        })
+}
+
+/// Where to fetch CI statuses from.
+#[derive(Debug)]
+enum CiStatusSource {
+    Command(String),
+    GitHub(GitHubClient),
+}
+
+impl CiStatusSource {
+    fn fetch(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<CiStatus>> {
+        match self {
+            CiStatusSource::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{} {}", command, commit_oid))
+                    .output()
+                    .wrap_err_with(|| format!("Running CI status command: {:?}", command))?;
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                Ok(stdout
+                    .split_whitespace()
+                    .next()
+                    .and_then(|status| CiStatus::try_from(status).ok()))
+            }
+
+            CiStatusSource::GitHub(client) => {
+                let check_runs =
+                    client.request("GET", &format!("/commits/{}/check-runs", commit_oid), None)?;
+                let check_run_statuses: Vec<CiStatus> = check_runs["check_runs"]
+                    .as_array()
+                    .map(|check_runs| check_runs.iter().map(describe_check_run).collect())
+                    .unwrap_or_default();
+                if !check_run_statuses.is_empty() {
+                    return Ok(combine_statuses(check_run_statuses));
+                }
+
+                // Fall back to the legacy commit status API, which some CI
+                // providers still use.
+                let status =
+                    client.request("GET", &format!("/commits/{}/status", commit_oid), None)?;
+                if status["total_count"].as_u64().unwrap_or(0) == 0 {
+                    return Ok(None);
+                }
+                Ok(match status["state"].as_str() {
+                    Some("success") => Some(CiStatus::Success),
+                    Some("failure") | Some("error") => Some(CiStatus::Failure),
+                    Some("pending") => Some(CiStatus::Pending),
+                    _ => None,
+                })
+            }
+        }
+    }
+}
+
+/// Determine the status of a GitHub check run.
+fn describe_check_run(check_run: &Value) -> CiStatus {
+    match (
+        check_run["status"].as_str(),
+        check_run["conclusion"].as_str(),
+    ) {
+        (Some("completed"), Some("success"))
+        | (Some("completed"), Some("neutral"))
+        | (Some("completed"), Some("skipped")) => CiStatus::Success,
+        (Some("completed"), _) => CiStatus::Failure,
+        _ => CiStatus::Pending,
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS ci_statuses (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    status TEXT,
+    timestamp REAL NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `ci_statuses` table")?;
+    Ok(())
+}
+
+/// Fetches the CI status of commits, caching the results in the database for
+/// `branchless.ciStatus.cacheTtlSeconds` seconds.
+pub struct CiStatusFetcher<'conn> {
+    conn: &'conn rusqlite::Connection,
+    now: SystemTime,
+    ttl: Duration,
+    source: Option<CiStatusSource>,
+}
+
+impl std::fmt::Debug for CiStatusFetcher<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CiStatusFetcher source={:?}>", self.source)
+    }
+}
+
+impl<'conn> CiStatusFetcher<'conn> {
+    /// Constructor. If no source of CI statuses is configured, no statuses
+    /// will be fetched.
+    #[instrument]
+    pub fn new(
+        repo: &Repo,
+        conn: &'conn rusqlite::Connection,
+        now: SystemTime,
+    ) -> eyre::Result<Self> {
+        init_tables(conn).wrap_err("Initializing tables")?;
+        let config = repo.get_config()?;
+        let ttl_seconds: i32 = config.get_or("branchless.ciStatus.cacheTtlSeconds", 300)?;
+        let ttl = Duration::from_secs(u64::try_from(ttl_seconds).unwrap_or(0));
+
+        let command: Option<String> = config.get("branchless.ciStatus.command")?;
+        let source = match command {
+            Some(command) => Some(CiStatusSource::Command(command)),
+            None => {
+                let remote_name: String =
+                    config.get_or("branchless.ciStatus.remote", "origin".to_string())?;
+                match GitHubClient::new(repo, &remote_name)? {
+                    Ok(client) => Some(CiStatusSource::GitHub(client)),
+                    Err(message) => {
+                        warn!(?message, "Not fetching CI statuses from GitHub");
+                        None
+                    }
+                }
+            }
+        };
+
+        Ok(CiStatusFetcher {
+            conn,
+            now,
+            ttl,
+            source,
+        })
+    }
+
+    fn get_cached_status(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<Option<CiStatus>>> {
+        let cached: Option<(Option<String>, f64)> = self
+            .conn
+            .query_row(
+                "
+SELECT status, timestamp
+FROM ci_statuses
+WHERE commit_oid = :commit_oid
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                },
+                |row| Ok((row.get("status")?, row.get("timestamp")?)),
+            )
+            .optional()
+            .wrap_err("Querying CI status cache")?;
+        let (status, timestamp) = match cached {
+            Some(cached) => cached,
+            None => return Ok(None),
+        };
+
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs_f64(timestamp.max(0.0));
+        let is_expired = match self.now.duration_since(timestamp) {
+            Ok(age) => age >= self.ttl,
+            Err(_) => false,
+        };
+        if is_expired {
+            Ok(None)
+        } else {
+            Ok(Some(status.and_then(|status| {
+                CiStatus::try_from(status.as_str()).ok()
+            })))
+        }
+    }
+
+    fn cache_status(&self, commit_oid: NonZeroOid, status: Option<CiStatus>) -> eyre::Result<()> {
+        let timestamp = self
+            .now
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO ci_statuses
+VALUES (:commit_oid, :status, :timestamp)
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":status": status.map(|status| status.to_string()),
+                    ":timestamp": timestamp,
+                },
+            )
+            .wrap_err("Caching CI status")?;
+        Ok(())
+    }
+
+    /// Get the CI status of the given commit, or `None` if it has no checks or
+    /// its status couldn't be fetched. Failures to fetch the status are logged
+    /// rather than returned.
+    #[instrument]
+    pub fn get_status(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<CiStatus>> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+        if let Some(status) = self.get_cached_status(commit_oid)? {
+            return Ok(status);
+        }
+
+        match source.fetch(commit_oid) {
+            Ok(status) => {
+                self.cache_status(commit_oid, status)?;
+                Ok(status)
+            }
+            Err(err) => {
+                warn!(?err, ?commit_oid, "Could not fetch CI status");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_statuses() {
+        assert_eq!(combine_statuses(vec![]), None);
+        assert_eq!(
+            combine_statuses(vec![CiStatus::Success, CiStatus::Success]),
+            Some(CiStatus::Success)
+        );
+        assert_eq!(
+            combine_statuses(vec![CiStatus::Success, CiStatus::Pending]),
+            Some(CiStatus::Pending)
+        );
+        assert_eq!(
+            combine_statuses(vec![
+                CiStatus::Failure,
+                CiStatus::Pending,
+                CiStatus::Success
+            ]),
+            Some(CiStatus::Failure)
+        );
+    }
+}
@@ -1,7 +1,13 @@
 //! Accesses repo-specific configuration.
 
+use std::convert::TryInto;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use cursive::theme::Style;
+use eyre::Context;
+
+use crate::core::formatting::{parse_style, ColorSetting, Palette};
 use crate::git::Repo;
 
 /// Get the path where Git hooks are stored on disk.
@@ -10,6 +16,12 @@ pub fn get_core_hooks_path(repo: &Repo) -> eyre::Result<PathBuf> {
         .get_or_else("core.hooksPath", || repo.get_path().join("hooks"))
 }
 
+/// Get the template directory which `git init` and `git clone` copy into new
+/// repositories, as set by `init.templateDir`.
+pub fn get_init_template_dir(repo: &Repo) -> eyre::Result<Option<PathBuf>> {
+    repo.get_config()?.get("init.templateDir")
+}
+
 /// Get the configured name of the main branch.
 pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     let config = repo.get_config()?;
@@ -26,6 +38,14 @@ pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     Ok(main_branch_name)
 }
 
+/// The patterns of references, such as `refs/remotes/origin/release-*`, which
+/// are treated as public in addition to the main branch, as set by
+/// `branchless.core.publicBranches`, which can be set multiple times. Commits
+/// reachable from these references aren't considered to be drafts.
+pub fn get_public_branch_patterns(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_config()?.get_all("branchless.core.publicBranches")
+}
+
 /// If `true`, when restacking a commit, do not update its timestamp to the
 /// current time.
 pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
@@ -33,6 +53,117 @@ pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.restack.preserveTimestamps", false)
 }
 
+/// If `true`, when moving or restacking commits, skip the commits whose changes
+/// have already been applied to the destination, as determined by their patch
+/// IDs. Set with `branchless.restack.detectDuplicateCommits`, and overridden
+/// by `--dedup` and `--no-dedup`.
+pub fn get_restack_detect_duplicate_commits(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.restack.detectDuplicateCommits", true)
+}
+
+/// What to do when a commit can't be signed during an in-memory rebase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigningFailureAction {
+    /// Warn the user and leave the rewritten commits unsigned.
+    Warn,
+
+    /// Abort the in-memory rebase and try again on-disk, where Git signs the
+    /// commits itself.
+    OnDisk,
+}
+
+/// Get what to do when `commit.gpgSign` is set, but a commit can't be signed
+/// during an in-memory rebase. Set with `branchless.restack.signingFailure`,
+/// which can be either `warn` (the default) or `on-disk`.
+pub fn get_restack_signing_failure_action(repo: &Repo) -> eyre::Result<SigningFailureAction> {
+    let value: String = repo
+        .get_config()?
+        .get_or("branchless.restack.signingFailure", "warn".to_string())?;
+    match value.as_str() {
+        "warn" => Ok(SigningFailureAction::Warn),
+        "on-disk" => Ok(SigningFailureAction::OnDisk),
+        value => eyre::bail!(
+            "Invalid value for branchless.restack.signingFailure: {:?} (expected `warn` or `on-disk`)",
+            value
+        ),
+    }
+}
+
+/// The maximum number of files to consider for rename detection when applying
+/// commits in memory, as set by `branchless.restack.renameLimit`. Rename
+/// detection is quadratic in the number of added and removed files, so large
+/// commits skip it once this limit is exceeded. Set to `0` to disable rename
+/// detection altogether.
+pub fn get_restack_rename_limit(repo: &Repo) -> eyre::Result<u32> {
+    let limit = repo.get_config()?.get_usize_or(
+        "branchless.restack.renameLimit",
+        1000,
+        ..=(u32::MAX as usize),
+    )?;
+    Ok(limit as u32)
+}
+
+/// The similarity percentage at which an added and removed file are considered
+/// to be a rename when applying commits in memory, as set by
+/// `branchless.restack.renameThreshold`.
+pub fn get_restack_rename_threshold(repo: &Repo) -> eyre::Result<u32> {
+    let threshold =
+        repo.get_config()?
+            .get_usize_or("branchless.restack.renameThreshold", 50, ..=100)?;
+    Ok(threshold as u32)
+}
+
+/// If `true`, `git move` only attempts an in-memory rebase unless
+/// `--on-disk` is passed, as if `--in-memory` had been passed. Set with
+/// `branchless.move.forceInMemory`.
+pub fn get_move_force_in_memory(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.move.forceInMemory", false)
+}
+
+/// If `true`, `git move`, `git restack` and `git undo` stash any uncommitted
+/// changes beforehand and restore them afterwards, unless `--no-autostash` is
+/// passed. Set with `branchless.autoStash`.
+pub fn get_autostash(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?.get_or("branchless.autoStash", false)
+}
+
+/// If `true`, a desktop notification is shown when a command which can take a
+/// long time, such as `git move` or `git restack`, finishes or fails after
+/// running for at least `get_notify_min_duration`. Set with
+/// `branchless.notify.enabled`.
+pub fn get_notify_enabled(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.notify.enabled", false)
+}
+
+/// How long a command must run for before a desktop notification is shown
+/// when it finishes, as set in seconds by `branchless.notify.minDuration`.
+/// Only applies if `get_notify_enabled` is `true`.
+pub fn get_notify_min_duration(repo: &Repo) -> eyre::Result<Duration> {
+    let seconds = repo
+        .get_config()?
+        .get_usize_or("branchless.notify.minDuration", 30, ..)?;
+    Ok(Duration::from_secs(seconds.try_into()?))
+}
+
+/// If `true`, `git move` skips attempting an in-memory rebase unless
+/// `--in-memory` is passed, as if `--on-disk` had been passed. Set with
+/// `branchless.move.forceOnDisk`.
+pub fn get_move_force_on_disk(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.move.forceOnDisk", false)
+}
+
+/// The number of commits above which `git hide --recursive` asks for
+/// confirmation before hiding them. Set with `branchless.hide.confirmThreshold`;
+/// `0` disables the confirmation.
+pub fn get_hide_confirm_threshold(repo: &Repo) -> eyre::Result<usize> {
+    repo.get_config()?
+        .get_or("branchless.hide.confirmThreshold", 10)
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -43,20 +174,336 @@ pub fn get_restack_warn_abandoned(repo: &Repo) -> eyre::Result<bool> {
         .get_or(RESTACK_WARN_ABANDONED_CONFIG_KEY, true)
 }
 
+/// The minimum number of commits and branches which an operation must abandon
+/// before warning about them, as set by
+/// `branchless.restack.warnAbandonedThreshold`. Only applies if
+/// `get_restack_warn_abandoned` is `true`.
+pub fn get_restack_warn_abandoned_threshold(repo: &Repo) -> eyre::Result<usize> {
+    repo.get_config()?
+        .get_usize_or("branchless.restack.warnAbandonedThreshold", 1, 1..)
+}
+
+/// How much output the hooks installed by `git branchless init` print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HookVerbosity {
+    /// Print nothing.
+    Quiet,
+
+    /// Only print warnings, such as about abandoned commits.
+    Warnings,
+
+    /// Print a summary of the events which were processed.
+    Normal,
+}
+
+/// The verbosity of the hooks, as set by `branchless.hooks.verbosity` (one of
+/// `quiet`, `warnings` or `normal`). Setting `branchless.hooks.quiet` to `true`
+/// is the same as setting the verbosity to `quiet`. Events are recorded
+/// regardless of the verbosity.
+pub fn get_hook_verbosity(repo: &Repo) -> eyre::Result<HookVerbosity> {
+    let config = repo.get_config()?;
+    if config.get_or("branchless.hooks.quiet", false)? {
+        return Ok(HookVerbosity::Quiet);
+    }
+    let verbosity: Option<String> = config.get("branchless.hooks.verbosity")?;
+    match verbosity.as_deref() {
+        None | Some("normal") => Ok(HookVerbosity::Normal),
+        Some("warnings") => Ok(HookVerbosity::Warnings),
+        Some("quiet") => Ok(HookVerbosity::Quiet),
+        Some(other) => eyre::bail!(
+            "Invalid value for branchless.hooks.verbosity (expected `quiet`, `warnings` or `normal`): {:?}",
+            other
+        ),
+    }
+}
+
+/// Whether to style the output with colors, as set by `branchless.color` (one
+/// of `auto`, `always` or `never`). Overridden by `--color`.
+pub fn get_color_setting(repo: &Repo) -> eyre::Result<ColorSetting> {
+    let color: Option<String> = repo.get_config()?.get("branchless.color")?;
+    match color {
+        Some(color) => color
+            .parse()
+            .wrap_err_with(|| "Parsing branchless.color config"),
+        None => Ok(ColorSetting::Auto),
+    }
+}
+
 /// If `true`, show branches pointing to each commit in the smartlog.
 pub fn get_commit_metadata_branches(repo: &Repo) -> eyre::Result<bool> {
     repo.get_config()?
         .get_or("branchless.commitMetadata.branches", true)
 }
 
+/// Get the palette to style the output with. Each style can be overridden by
+/// setting `branchless.color.<name>` (such as `branchless.color.oid` or
+/// `branchless.color.branch.head`) in the format accepted by `parse_style`.
+/// The names are:
+///
+/// - `oid`, `annotation`, `relativeTime`, `revision`,
+///   `revisionNeedsRevision`: parts of the commit metadata in the smartlog.
+/// - `emphasis`, `selection`: the `HEAD` commit and the selected item in
+///   interactive lists.
+/// - `success`, `warning`, `error`: statuses and messages.
+/// - `branch.head`, `branch.local`, `branch.remote`, `branch.other`,
+///   `branch.punctuation`: the branches next to each commit.
+pub fn get_palette(repo: &Repo) -> eyre::Result<Palette> {
+    let config = repo.get_config()?;
+    let mut palette = Palette::default();
+    let entries: Vec<(&str, &mut Style)> = vec![
+        ("branchless.color.oid", &mut palette.oid),
+        ("branchless.color.annotation", &mut palette.annotation),
+        ("branchless.color.relativeTime", &mut palette.relative_time),
+        ("branchless.color.revision", &mut palette.revision),
+        (
+            "branchless.color.revisionNeedsRevision",
+            &mut palette.revision_needs_revision,
+        ),
+        ("branchless.color.emphasis", &mut palette.emphasis),
+        ("branchless.color.selection", &mut palette.selection),
+        ("branchless.color.success", &mut palette.success),
+        ("branchless.color.warning", &mut palette.warning),
+        ("branchless.color.error", &mut palette.error),
+        ("branchless.color.branch.head", &mut palette.branch_head),
+        ("branchless.color.branch.local", &mut palette.branch_local),
+        ("branchless.color.branch.remote", &mut palette.branch_remote),
+        ("branchless.color.branch.other", &mut palette.branch_other),
+        (
+            "branchless.color.branch.punctuation",
+            &mut palette.branch_punctuation,
+        ),
+    ];
+    for (key, style) in entries {
+        let value: Option<String> = config.get(key)?;
+        if let Some(value) = value {
+            *style = parse_style(&value).wrap_err_with(|| format!("Parsing {}", key))?;
+        }
+    }
+    Ok(palette)
+}
+
 /// If `true`, show associated Phabricator commits in the smartlog.
 pub fn get_commit_metadata_differential_revision(repo: &Repo) -> eyre::Result<bool> {
     repo.get_config()?
         .get_or("branchless.commitMetadata.differentialRevision", true)
 }
 
+/// If `true`, also show the review status of associated Phabricator commits in
+/// the smartlog. This requires `arc` to be configured for the repository, and
+/// makes a request to Phabricator for each revision shown.
+pub fn get_commit_metadata_differential_revision_status(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?.get_or(
+        "branchless.commitMetadata.differentialRevisionStatus",
+        false,
+    )
+}
+
+/// If `true`, show the CI status of commits which have a branch pointing to
+/// them in the smartlog. See `crate::core::ci_status` for how it's fetched.
+pub fn get_commit_metadata_ci_status(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.commitMetadata.ciStatus", false)
+}
+
+/// Whether to render commit hashes and revision numbers in the smartlog as
+/// terminal hyperlinks. If unset, this is detected based on the terminal.
+pub fn get_smartlog_hyperlinks(repo: &Repo) -> eyre::Result<Option<bool>> {
+    repo.get_config()?.get("branchless.smartlog.hyperlinks")
+}
+
+/// If `true`, truncate lines in the smartlog which are wider than the terminal.
+/// The terminal width is taken from the `COLUMNS` environment variable if set,
+/// so this can be disabled to avoid truncating output which is piped elsewhere.
+pub fn get_smartlog_truncate_lines(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.smartlog.truncateLines", true)
+}
+
+/// If `true`, show a header above the smartlog which says whether `HEAD` is
+/// on a branch or detached, and whether there are uncommitted changes.
+pub fn get_smartlog_header(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.smartlog.header", false)
+}
+
+/// The URL which commit hashes in the smartlog link to, where `{oid}` is
+/// replaced with the full commit hash. If unset, commit hashes aren't linked.
+pub fn get_smartlog_commit_url_template(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_config()?
+        .get("branchless.smartlog.commitUrlTemplate")
+}
+
+/// The revset which selects the commits to show in the smartlog, as set by
+/// `branchless.smartlog.defaultRevset`, such as `draft() & mine()`. If unset,
+/// or if `git smartlog --all` is passed, all of the commits are shown. See
+/// `crate::core::revset` for the syntax.
+pub fn get_smartlog_default_revset(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_config()?.get("branchless.smartlog.defaultRevset")
+}
+
+/// The names of the metadata providers to show for each commit in the
+/// smartlog, in order, as set by the comma-separated list in
+/// `branchless.commitDescriptors`, such as `oid,branches,message`. If unset,
+/// the default set of providers is shown.
+pub fn get_commit_descriptors(repo: &Repo) -> eyre::Result<Option<Vec<String>>> {
+    let descriptors: Option<String> = repo.get_config()?.get("branchless.commitDescriptors")?;
+    Ok(descriptors.map(|descriptors| {
+        descriptors
+            .split(',')
+            .map(|descriptor| descriptor.trim().to_string())
+            .filter(|descriptor| !descriptor.is_empty())
+            .collect()
+    }))
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 pub fn get_commit_metadata_relative_time(repo: &Repo) -> eyre::Result<bool> {
     repo.get_config()?
         .get_or("branchless.commitMetadata.relativeTime", true)
 }
+
+/// The patterns of references whose updates aren't recorded in the event log,
+/// as set by `branchless.eventLog.ignoreRefs`, which can be set multiple times.
+/// See `IgnoredRefPatterns` for the pattern syntax.
+pub fn get_event_log_ignore_refs(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_config()?.get_all("branchless.eventLog.ignoreRefs")
+}
+
+/// How long to keep transactions in the event log before `git branchless gc`
+/// prunes them, as set by `branchless.eventLog.retention`, such as `90d`. The
+/// units `h` (hours), `d` (days) and `w` (weeks) are supported.
+///
+/// Returns: The retention period, or `None` if transactions are kept forever,
+/// which is the default.
+pub fn get_event_log_retention(repo: &Repo) -> eyre::Result<Option<Duration>> {
+    let retention: Option<String> = repo.get_config()?.get("branchless.eventLog.retention")?;
+    let retention = match retention {
+        Some(retention) => retention,
+        None => return Ok(None),
+    };
+    let retention = retention.trim();
+    if retention == "never" {
+        return Ok(None);
+    }
+    Ok(Some(parse_duration(
+        "branchless.eventLog.retention",
+        retention,
+    )?))
+}
+
+/// Parse a duration like `90d`, as used by `branchless.eventLog.retention`.
+/// The units `h` (hours), `d` (days) and `w` (weeks) are supported. `name` is
+/// the name of the setting, for error messages.
+pub fn parse_duration(name: &str, value: &str) -> eyre::Result<Duration> {
+    let value = value.trim();
+    let (amount, unit) = match value.char_indices().last() {
+        Some((unit_index, _)) => value.split_at(unit_index),
+        None => ("", ""),
+    };
+    let invalid_value = || {
+        eyre::eyre!(
+            "Invalid value for {} (expected e.g. `90d`): {:?}",
+            name,
+            value
+        )
+    };
+    let unit_seconds: u64 = match unit {
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid_value()),
+    };
+    let amount: u64 = amount
+        .parse()
+        .wrap_err_with(|| format!("Parsing value for {}: {:?}", name, value))?;
+    let seconds = amount.checked_mul(unit_seconds).ok_or_else(invalid_value)?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// How long a commit must have been hidden before `git branchless prune`
+/// deletes it, as set by `branchless.prune.olderThan` (in the same format as
+/// `branchless.eventLog.retention`). Defaults to 90 days.
+pub fn get_prune_older_than(repo: &Repo) -> eyre::Result<Duration> {
+    let older_than: Option<String> = repo.get_config()?.get("branchless.prune.olderThan")?;
+    match older_than {
+        Some(older_than) => parse_duration("branchless.prune.olderThan", &older_than),
+        None => Ok(Duration::from_secs(90 * 24 * 60 * 60)),
+    }
+}
+
+/// The number of days after a commit is hidden during which it's still kept
+/// reachable (via a reference under `refs/branchless/keep/`), so that it can be
+/// restored with `git undo` even after Git's garbage collection runs.
+pub fn get_gc_keep_hidden_days(repo: &Repo) -> eyre::Result<i32> {
+    repo.get_config()?
+        .get_or("branchless.gc.keepHiddenDays", 14)
+}
+
+/// If `true`, Git's commit-graph file is rewritten after `git branchless gc`
+/// and after commits are rewritten, so that ancestry queries stay fast even
+/// though branchless creates many commits that Git's porcelain never sees.
+/// Set with `branchless.commitGraph.write`.
+pub fn get_commit_graph_write(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?
+        .get_or("branchless.commitGraph.write", false)
+}
+
+/// The directory to store the branchless database in instead of
+/// `.git/branchless`, as set by `branchless.db.path`. A path component of
+/// `{repo}` is replaced with a name unique to the repository, so that a single
+/// cache directory can be shared between repositories. Relative paths are
+/// relative to the `.git` directory.
+pub fn get_db_dir(repo: &Repo) -> eyre::Result<Option<PathBuf>> {
+    repo.get_config()?.get("branchless.db.path")
+}
+
+/// The template used to generate names for new branches. `{user}` (or
+/// `{username}`), `{date}`, `{change-id}` and `{slug}` are replaced with the
+/// local part of the commit author's email address, the date of the commit, its
+/// change ID, and a slug derived from its subject line.
+pub fn get_branch_name_template(repo: &Repo) -> eyre::Result<String> {
+    repo.get_config()?
+        .get_or("branchless.branch.nameTemplate", "{slug}".to_string())
+}
+
+/// The maximum length of the slug derived from a commit's subject line when
+/// generating names for new branches, as set by
+/// `branchless.branch.maxSlugLength`.
+pub fn get_branch_max_slug_length(repo: &Repo) -> eyre::Result<usize> {
+    repo.get_config()?
+        .get_usize_or("branchless.branch.maxSlugLength", 40, 1..)
+}
+
+/// If `true`, `git branchless submit` creates new reviews as drafts, as set by
+/// `branchless.submit.draft`. Only the `github` backend supports drafts.
+pub fn get_submit_draft(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_config()?.get_or("branchless.submit.draft", false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_notify_config() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let repo = git.get_repo()?;
+        assert!(!get_notify_enabled(&repo)?);
+        assert_eq!(get_notify_min_duration(&repo)?, Duration::from_secs(30));
+
+        git.run(&["config", "branchless.notify.enabled", "true"])?;
+        git.run(&["config", "branchless.notify.minDuration", "5"])?;
+        assert!(get_notify_enabled(&repo)?);
+        assert_eq!(get_notify_min_duration(&repo)?, Duration::from_secs(5));
+
+        git.run(&["config", "branchless.notify.enabled", "sometimes"])?;
+        git.run(&["config", "branchless.notify.minDuration", "soon"])?;
+        assert!(get_notify_enabled(&repo).is_err());
+        assert!(get_notify_min_duration(&repo).is_err());
+
+        Ok(())
+    }
+}
@@ -6,16 +6,22 @@
 //! they're still working on.
 
 use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::ffi::{OsStr, OsString};
+use std::hash::{BuildHasher, Hasher};
 
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
+use itertools::Itertools;
+use regex::Regex;
+use rusqlite::OptionalExtension;
 use tracing::{error, instrument};
 
+use crate::core::config::get_event_log_ignore_refs;
 use crate::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, Repo};
 use crate::tui::{Effects, OperationType};
 
@@ -23,6 +29,16 @@ use crate::tui::{Effects, OperationType};
 /// which the caller has already started.
 pub const BRANCHLESS_TRANSACTION_ID_ENV_VAR: &str = "BRANCHLESS_TRANSACTION_ID";
 
+/// Get the name of the user running the current process, as recorded with
+/// each new transaction. Returns `None` if it can't be determined, in which
+/// case transactions aren't attributed to any user.
+pub fn get_current_user() -> Option<String> {
+    ["USER", "USERNAME"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|user| !user.is_empty())
+}
+
 // Wrapper around the row stored directly in the database.
 #[derive(Clone, Debug)]
 struct Row {
@@ -51,7 +67,7 @@ struct Row {
 ///
 /// Unlike in a database, there is no specific guarantee that an event
 /// transaction is an atomic unit of work.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct EventTransactionId(isize);
 
 impl ToString for EventTransactionId {
@@ -147,6 +163,9 @@ pub enum Event {
 
         /// The OID of the commit that was hidden.
         commit_oid: NonZeroOid,
+
+        /// The reason provided by the user for hiding the commit, if any.
+        reason: Option<String>,
     },
 
     /// Indicates that a commit was explicitly un-hidden by the user.
@@ -163,6 +182,50 @@ pub enum Event {
         /// The OID of the commit that was unhidden.
         commit_oid: NonZeroOid,
     },
+
+    /// Indicates that changes were added to the stash, such as with `git
+    /// stash push` or `git stash store`.
+    StashPushEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the stash commit that was added.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Indicates that an entry was removed from the stash, such as with `git
+    /// stash pop` or `git stash drop`.
+    StashDropEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the stash commit that was removed.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Indicates that a commit was copied, such as with `git move --keep`.
+    ///
+    /// The copy should be marked as visible, while the original commit is left
+    /// as it was.
+    CopyEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the original commit.
+        old_commit_oid: NonZeroOid,
+
+        /// The OID of the copy.
+        new_commit_oid: NonZeroOid,
+    },
 }
 
 impl Event {
@@ -174,6 +237,9 @@ impl Event {
             Event::CommitEvent { timestamp, .. } => timestamp,
             Event::HideEvent { timestamp, .. } => timestamp,
             Event::UnhideEvent { timestamp, .. } => timestamp,
+            Event::StashPushEvent { timestamp, .. } => timestamp,
+            Event::StashDropEvent { timestamp, .. } => timestamp,
+            Event::CopyEvent { timestamp, .. } => timestamp,
         };
         SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp)
     }
@@ -186,6 +252,9 @@ impl Event {
             Event::CommitEvent { event_tx_id, .. } => *event_tx_id,
             Event::HideEvent { event_tx_id, .. } => *event_tx_id,
             Event::UnhideEvent { event_tx_id, .. } => *event_tx_id,
+            Event::StashPushEvent { event_tx_id, .. } => *event_tx_id,
+            Event::StashDropEvent { event_tx_id, .. } => *event_tx_id,
+            Event::CopyEvent { event_tx_id, .. } => *event_tx_id,
         }
     }
 }
@@ -243,6 +312,7 @@ impl From<Event> for Row {
                 timestamp,
                 event_tx_id: EventTransactionId(event_tx_id),
                 commit_oid,
+                reason,
             } => Row {
                 timestamp,
                 event_tx_id,
@@ -250,7 +320,7 @@ impl From<Event> for Row {
                 ref1: Some(commit_oid.to_string().into()),
                 ref2: None,
                 ref_name: None,
-                message: None,
+                message: reason.map(OsString::from),
             },
 
             Event::UnhideEvent {
@@ -266,6 +336,49 @@ impl From<Event> for Row {
                 ref_name: None,
                 message: None,
             },
+
+            Event::StashPushEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("stash-push"),
+                ref1: Some(commit_oid.to_string().into()),
+                ref2: None,
+                ref_name: None,
+                message: None,
+            },
+
+            Event::StashDropEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("stash-drop"),
+                ref1: Some(commit_oid.to_string().into()),
+                ref2: None,
+                ref_name: None,
+                message: None,
+            },
+
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                old_commit_oid,
+                new_commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("copy"),
+                ref1: Some(old_commit_oid.to_string().into()),
+                ref2: Some(new_commit_oid.to_string().into()),
+                ref_name: None,
+                message: None,
+            },
         }
     }
 }
@@ -342,6 +455,7 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
                 timestamp,
                 event_tx_id,
                 commit_oid,
+                reason: message.map(|message| message.to_string_lossy().into_owned()),
             }
         }
 
@@ -354,6 +468,35 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
             }
         }
 
+        "stash-push" => {
+            let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
+            Event::StashPushEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }
+        }
+
+        "stash-drop" => {
+            let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
+            Event::StashDropEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }
+        }
+
+        "copy" => {
+            let old_commit_oid: NonZeroOid = get_oid(&ref1, "old commit OID")?.try_into()?;
+            let new_commit_oid: NonZeroOid = get_oid(&ref2, "new commit OID")?.try_into()?;
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid,
+                new_commit_oid,
+            }
+        }
+
         other => eyre::bail!("Unknown event type {}", other),
     };
     Ok(event)
@@ -420,9 +563,313 @@ CREATE TABLE IF NOT EXISTS event_transactions (
     )
     .wrap_err("Creating `event_transactions` table")?;
 
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_commands (
+    event_tx_id INTEGER NOT NULL PRIMARY KEY,
+
+    -- The arguments passed to Git, separated by NUL characters.
+    args TEXT NOT NULL,
+
+    exit_code INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_commands` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_annotations (
+    event_tx_id INTEGER NOT NULL,
+    timestamp REAL NOT NULL,
+    message TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_annotations` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_origins (
+    event_tx_id INTEGER NOT NULL PRIMARY KEY,
+
+    -- The machine and transaction which originally created the events in this
+    -- transaction, if they were imported via `git branchless sync-events`.
+    origin TEXT NOT NULL UNIQUE
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_origins` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_users (
+    event_tx_id INTEGER NOT NULL PRIMARY KEY,
+
+    -- The user who ran the command which created this transaction. Older
+    -- transactions, and transactions imported via `git branchless
+    -- sync-events`, have no recorded user.
+    user TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_users` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_log_sync_state (
+    key TEXT NOT NULL PRIMARY KEY,
+    value TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_log_sync_state` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_log_sync_imported_blobs (
+    blob_oid TEXT NOT NULL PRIMARY KEY
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_log_sync_imported_blobs` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS stash_snapshot (
+    -- The commits of the stash entries as of the last time that changes to
+    -- the stash were recorded, from the most recent entry to the oldest one,
+    -- separated by spaces. There is at most one row.
+    stash_oids TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `stash_snapshot` table")?;
+
+    Ok(())
+}
+
+/// Merge the rows imported from other machines (marked with `true`) into the
+/// local rows in timestamp order. The local rows are kept in the order in
+/// which they were added, and imported rows with the same timestamp as a local
+/// row come after it.
+fn merge_imported_rows(rows: Vec<(bool, Row)>) -> Vec<Row> {
+    let (imported_rows, local_rows): (Vec<_>, Vec<_>) = rows
+        .into_iter()
+        .partition(|(is_imported, _row)| *is_imported);
+    if imported_rows.is_empty() {
+        return local_rows.into_iter().map(|(_, row)| row).collect();
+    }
+
+    let mut imported_rows: Vec<Row> = imported_rows.into_iter().map(|(_, row)| row).collect();
+    // Stable, so imported rows with the same timestamp stay in the order in
+    // which they were imported.
+    imported_rows.sort_by(|lhs, rhs| {
+        lhs.timestamp
+            .partial_cmp(&rhs.timestamp)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut result = Vec::with_capacity(local_rows.len() + imported_rows.len());
+    let mut imported_rows = imported_rows.into_iter().peekable();
+    for (_, local_row) in local_rows {
+        while let Some(imported_row) =
+            imported_rows.next_if(|imported_row| imported_row.timestamp < local_row.timestamp)
+        {
+            result.push(imported_row);
+        }
+        result.push(local_row);
+    }
+    result.extend(imported_rows);
+    result
+}
+
+/// Determine whether the given row records a move of `HEAD` (in any worktree).
+/// Such events describe the checkouts of the machine they were recorded on, so
+/// they aren't imported from other machines.
+fn is_head_update_row(row: &Row) -> bool {
+    row.type_ == "ref-move"
+        && row
+            .ref_name
+            .as_deref()
+            .map(is_worktree_head_ref)
+            .unwrap_or(false)
+}
+
+/// Insert a single row into the `event_log` table.
+fn insert_row(conn: &rusqlite::Connection, row: Row) -> eyre::Result<()> {
+    let Row {
+        timestamp,
+        type_,
+        event_tx_id,
+        ref1,
+        ref2,
+        ref_name,
+        message,
+    } = row;
+
+    // FIXME: it would be ideal to use BLOBs to store the reference
+    // names instead of TEXT, so that we can represent esoteric
+    // reference names (which are derived from path names).
+    let ref1 = ref1.map(|x| x.to_string_lossy().into_owned());
+    let ref2 = ref2.map(|x| x.to_string_lossy().into_owned());
+    let ref_name = ref_name.map(|x| x.to_string_lossy().into_owned());
+    let message = message.map(|x| x.to_string_lossy().into_owned());
+
+    conn.execute(
+        "
+INSERT INTO event_log VALUES (
+    :timestamp,
+    :type,
+    :event_tx_id,
+    :old_ref,
+    :new_ref,
+    :ref_name,
+    :message
+)
+            ",
+        rusqlite::named_params! {
+            ":timestamp": timestamp,
+            ":type": &type_,
+            ":event_tx_id": event_tx_id,
+            ":old_ref": &ref1,
+            ":new_ref": &ref2,
+            ":ref_name": &ref_name,
+            ":message": &message,
+        },
+    )?;
     Ok(())
 }
 
+/// The first line of every batch of events exported by `git branchless
+/// sync-events`, used to detect batches written in an incompatible format.
+const EVENT_LOG_SYNC_HEADER: &str = "# git-branchless event log v1";
+
+/// Escape an optional field of an exported event so that it doesn't contain
+/// any tabs or newlines. `None` is encoded as the empty string, and all other
+/// values are prefixed with `+`.
+fn escape_sync_field(value: Option<&str>) -> String {
+    match value {
+        None => String::new(),
+        Some(value) => {
+            let mut result = String::from("+");
+            for c in value.chars() {
+                match c {
+                    '\\' => result.push_str("\\\\"),
+                    '\t' => result.push_str("\\t"),
+                    '\n' => result.push_str("\\n"),
+                    c => result.push(c),
+                }
+            }
+            result
+        }
+    }
+}
+
+/// Inverse of `escape_sync_field`.
+fn unescape_sync_field(field: &str) -> eyre::Result<Option<String>> {
+    let value = match field.strip_prefix('+') {
+        None if field.is_empty() => return Ok(None),
+        None => eyre::bail!("Invalid field in exported event: {:?}", field),
+        Some(value) => value,
+    };
+
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\\') => result.push('\\'),
+                Some('t') => result.push('\t'),
+                Some('n') => result.push('\n'),
+                other => eyre::bail!("Invalid escape sequence in exported event: {:?}", other),
+            },
+            c => result.push(c),
+        }
+    }
+    Ok(Some(result))
+}
+
+/// Parse a batch of events exported by `EventLogDb::export_events`.
+///
+/// Returns: The origin of each event, along with the event itself. The event
+/// transaction IDs of the returned rows are not meaningful.
+fn parse_sync_events(contents: &str) -> eyre::Result<Vec<(String, Row)>> {
+    let mut lines = contents.lines();
+    match lines.next() {
+        Some(EVENT_LOG_SYNC_HEADER) => {}
+        header => eyre::bail!(
+            "Unsupported format for exported events (upgrade git-branchless?): {:?}",
+            header
+        ),
+    }
+
+    let mut result = Vec::new();
+    for line in lines {
+        let fields = line.split('\t').collect_vec();
+        let (origin, timestamp, type_, ref1, ref2, ref_name, message) = match fields.as_slice() {
+            [origin, timestamp, type_, ref1, ref2, ref_name, message] => {
+                (origin, timestamp, type_, ref1, ref2, ref_name, message)
+            }
+            _ => eyre::bail!("Invalid exported event: {:?}", line),
+        };
+        let row = Row {
+            timestamp: timestamp
+                .parse()
+                .wrap_err_with(|| format!("Parsing timestamp of exported event: {:?}", line))?,
+            type_: type_.to_string(),
+            event_tx_id: 0,
+            ref1: unescape_sync_field(ref1)?.map(OsString::from),
+            ref2: unescape_sync_field(ref2)?.map(OsString::from),
+            ref_name: unescape_sync_field(ref_name)?.map(OsString::from),
+            message: unescape_sync_field(message)?.map(OsString::from),
+        };
+        try_from_row_helper(&row)
+            .wrap_err_with(|| format!("Validating exported event: {:?}", line))?;
+        result.push((origin.to_string(), row));
+    }
+    Ok(result)
+}
+
+/// A batch of events from the local event log which have not yet been
+/// exported, to be shared with other machines via `git branchless
+/// sync-events`.
+#[derive(Debug)]
+pub struct EventLogExport {
+    /// The serialized events.
+    pub contents: String,
+
+    /// The number of events in the batch.
+    pub num_events: usize,
+
+    last_rowid: isize,
+}
+
+/// A Git command which was run via `git branchless wrap` as part of an event
+/// transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionCommand {
+    /// The arguments passed to Git, not including the Git executable itself.
+    pub args: Vec<String>,
+
+    /// The exit code of the command.
+    pub exit_code: isize,
+}
+
+impl TransactionCommand {
+    /// Whether or not the command exited unsuccessfully.
+    pub fn is_failure(&self) -> bool {
+        self.exit_code != 0
+    }
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[instrument]
@@ -439,136 +886,782 @@ impl<'conn> EventLogDb<'conn> {
     pub fn add_events(&mut self, events: Vec<Event>) -> eyre::Result<()> {
         let tx = self.conn.unchecked_transaction()?;
         for event in events {
-            let Row {
-                timestamp,
-                type_,
-                event_tx_id,
-                ref1,
-                ref2,
-                ref_name,
-                message,
-            } = Row::from(event);
+            insert_row(&tx, Row::from(event))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
 
-            // FIXME: it would be ideal to use BLOBs to store the reference
-            // names instead of TEXT, so that we can represent esoteric
-            // reference names (which are derived from path names).
-            let ref1 = ref1.map(|x| x.to_string_lossy().into_owned());
-            let ref2 = ref2.map(|x| x.to_string_lossy().into_owned());
-            let ref_name = ref_name.map(|x| x.to_string_lossy().into_owned());
-            let message = message.map(|x| x.to_string_lossy().into_owned());
+    /// Get all the events in the database.
+    ///
+    /// Returns: All the events in the database, ordered from oldest to newest.
+    #[instrument]
 
-            tx.execute(
+    pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
+        self.get_events_for_user(None)
+    }
+
+    /// Get a value which changes whenever events are added to or removed from
+    /// the database, without loading the events themselves: the number of
+    /// events and the ID of the most recent one.
+    #[instrument]
+    pub fn get_events_version(&self) -> eyre::Result<(isize, isize)> {
+        let version = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(MAX(rowid), 0) FROM event_log",
+                rusqlite::params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .wrap_err("Querying event log version")?;
+        Ok(version)
+    }
+
+    /// Get the events in the database which belong to transactions created by
+    /// the given user, along with those which aren't attributed to any user.
+    /// If `user` is `None`, all events are returned.
+    ///
+    /// Returns: The matching events, ordered from oldest to newest. Events
+    /// imported from other machines are ordered by their timestamps among the
+    /// local events, rather than by when they were imported.
+    #[instrument]
+    pub fn get_events_for_user(&self, user: Option<&str>) -> eyre::Result<Vec<Event>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT
+    event_log.timestamp AS timestamp,
+    type,
+    event_log.event_tx_id AS event_tx_id,
+    old_ref,
+    new_ref,
+    ref_name,
+    message,
+    event_transaction_origins.origin IS NOT NULL AS is_imported
+FROM event_log
+LEFT JOIN event_transaction_users
+ON event_log.event_tx_id = event_transaction_users.event_tx_id
+LEFT JOIN event_transaction_origins
+ON event_log.event_tx_id = event_transaction_origins.event_tx_id
+WHERE :user IS NULL
+OR event_transaction_users.user IS NULL
+OR event_transaction_users.user = :user
+ORDER BY event_log.rowid ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(bool, Row)>> = stmt
+            .query_map(rusqlite::named_params! { ":user": user }, |row| {
+                let timestamp: f64 = row.get("timestamp")?;
+                let event_tx_id: isize = row.get("event_tx_id")?;
+                let type_: String = row.get("type")?;
+                let ref_name: Option<String> = row.get("ref_name")?;
+                let old_ref: Option<String> = row.get("old_ref")?;
+                let new_ref: Option<String> = row.get("new_ref")?;
+                let message: Option<String> = row.get("message")?;
+                let is_imported: bool = row.get("is_imported")?;
+
+                Ok((
+                    is_imported,
+                    Row {
+                        timestamp,
+                        event_tx_id,
+                        type_,
+                        ref_name: ref_name.map(OsString::from),
+                        ref1: old_ref.map(OsString::from),
+                        ref2: new_ref.map(OsString::from),
+                        message: message.map(OsString::from),
+                    },
+                ))
+            })?
+            .collect();
+        merge_imported_rows(rows?)
+            .into_iter()
+            .map(Event::try_from)
+            .collect()
+    }
+
+    /// Create a new event transaction ID to be used to insert subsequent
+    /// `Event`s into the database.
+    #[instrument(fields(message = message.as_ref()))]
+    pub fn make_transaction_id(
+        &self,
+        now: SystemTime,
+        message: impl AsRef<str>,
+    ) -> eyre::Result<EventTransactionId> {
+        if let Ok(transaction_id) = std::env::var(BRANCHLESS_TRANSACTION_ID_ENV_VAR) {
+            if let Ok(transaction_id) = transaction_id.parse::<EventTransactionId>() {
+                return Ok(transaction_id);
+            }
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+
+        let timestamp = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err_with(|| format!("Calculating event transaction timestamp: {:?}", &now))?
+            .as_secs_f64();
+        self.conn
+            .execute(
                 "
-INSERT INTO event_log VALUES (
-    :timestamp,
-    :type,
+            INSERT INTO event_transactions
+            (timestamp, message)
+            VALUES
+            (:timestamp, :message)
+        ",
+                rusqlite::named_params! {
+                    ":timestamp": timestamp,
+                    ":message": message.as_ref(),
+                },
+            )
+            .wrap_err_with(|| {
+                format!(
+                    "Creating event transaction (now: {:?}, message: {:?})",
+                    &now,
+                    message.as_ref(),
+                )
+            })?;
+
+        // Ensure that we query `last_insert_rowid` in a transaction, in case
+        // there's another thread in this process making queries with the same
+        // SQLite connection.
+        let event_tx_id: isize = self.conn.last_insert_rowid().try_into()?;
+        if let Some(user) = get_current_user() {
+            self.conn
+                .execute(
+                    "INSERT INTO event_transaction_users VALUES (:event_tx_id, :user)",
+                    rusqlite::named_params! {
+                        ":event_tx_id": event_tx_id,
+                        ":user": user,
+                    },
+                )
+                .wrap_err_with(|| format!("Recording user for transaction {:?}", event_tx_id))?;
+        }
+        tx.commit()?;
+        Ok(EventTransactionId(event_tx_id))
+    }
+
+    /// Record the Git command which was run as part of the given transaction,
+    /// along with its exit code. If a command was already recorded for the
+    /// transaction, it is replaced.
+    #[instrument]
+    pub fn add_transaction_command<S: AsRef<str> + std::fmt::Debug>(
+        &self,
+        event_tx_id: EventTransactionId,
+        args: &[S],
+        exit_code: isize,
+    ) -> eyre::Result<()> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let args = args.iter().map(|arg| arg.as_ref()).collect_vec().join("\0");
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO event_transaction_commands VALUES (
     :event_tx_id,
-    :old_ref,
-    :new_ref,
-    :ref_name,
+    :args,
+    :exit_code
+)
+",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                    ":args": &args,
+                    ":exit_code": exit_code,
+                },
+            )
+            .wrap_err_with(|| {
+                format!(
+                    "Recording command for event transaction {:?}: {:?}",
+                    event_tx_id, &args
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Attach a user-provided annotation to the given transaction. A
+    /// transaction may have any number of annotations.
+    #[instrument]
+    pub fn add_transaction_annotation(
+        &self,
+        event_tx_id: EventTransactionId,
+        now: SystemTime,
+        message: &str,
+    ) -> eyre::Result<()> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let timestamp = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err_with(|| format!("Calculating annotation timestamp: {:?}", &now))?
+            .as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT INTO event_transaction_annotations VALUES (
+    :event_tx_id,
+    :timestamp,
     :message
 )
-            ",
+",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                    ":timestamp": timestamp,
+                    ":message": message,
+                },
+            )
+            .wrap_err_with(|| {
+                format!(
+                    "Annotating event transaction {:?} with message: {:?}",
+                    event_tx_id, message
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Get the annotations attached to the given transaction, ordered from
+    /// oldest to newest.
+    #[instrument]
+    pub fn get_transaction_annotations(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Vec<String>> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let mut stmt = self.conn.prepare(
+            "
+SELECT message
+FROM event_transaction_annotations
+WHERE event_tx_id = :event_tx_id
+ORDER BY rowid ASC
+",
+        )?;
+        let annotations: rusqlite::Result<Vec<String>> = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                },
+                |row| row.get("message"),
+            )?
+            .collect();
+        let annotations = annotations
+            .wrap_err_with(|| format!("Querying annotations for transaction {:?}", event_tx_id))?;
+        Ok(annotations)
+    }
+
+    /// Check that the tables used by the event log have the columns expected
+    /// by this version of `git-branchless`. This can fail to be the case if
+    /// the database was written by an incompatible version.
+    ///
+    /// Returns: A description of each mismatch found.
+    #[instrument]
+    pub fn check_schema(&self) -> eyre::Result<Vec<String>> {
+        let expected_tables: &[(&str, &[&str])] = &[
+            (
+                "event_log",
+                &[
+                    "timestamp",
+                    "type",
+                    "event_tx_id",
+                    "old_ref",
+                    "new_ref",
+                    "ref_name",
+                    "message",
+                ],
+            ),
+            (
+                "event_transactions",
+                &["timestamp", "event_tx_id", "message"],
+            ),
+            (
+                "event_transaction_commands",
+                &["event_tx_id", "args", "exit_code"],
+            ),
+            (
+                "event_transaction_annotations",
+                &["event_tx_id", "timestamp", "message"],
+            ),
+            ("event_transaction_origins", &["event_tx_id", "origin"]),
+            ("event_transaction_users", &["event_tx_id", "user"]),
+            ("event_log_sync_state", &["key", "value"]),
+            ("event_log_sync_imported_blobs", &["blob_oid"]),
+            ("stash_snapshot", &["stash_oids"]),
+        ];
+
+        let mut mismatches = Vec::new();
+        for (table_name, expected_columns) in expected_tables {
+            let mut stmt = self
+                .conn
+                .prepare(&format!("PRAGMA table_info({})", table_name))?;
+            let columns: rusqlite::Result<HashSet<String>> = stmt
+                .query_map(rusqlite::params![], |row| row.get("name"))?
+                .collect();
+            let columns =
+                columns.wrap_err_with(|| format!("Querying columns of table {:?}", table_name))?;
+            for expected_column in expected_columns.iter() {
+                if !columns.contains(*expected_column) {
+                    mismatches.push(format!(
+                        "table `{}` is missing column `{}`",
+                        table_name, expected_column
+                    ));
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Delete the transactions which were created before `cutoff`, along with
+    /// their events and any commands, annotations, origins and users recorded
+    /// for them. If `before_event_tx_id` is given, only transactions preceding it
+    /// are deleted.
+    ///
+    /// Returns: The number of events deleted.
+    #[instrument]
+    pub fn prune_transactions(
+        &mut self,
+        cutoff: SystemTime,
+        before_event_tx_id: Option<EventTransactionId>,
+    ) -> eyre::Result<usize> {
+        let cutoff = cutoff
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err_with(|| format!("Calculating prune cutoff timestamp: {:?}", &cutoff))?
+            .as_secs_f64();
+        let before_event_tx_id = match before_event_tx_id {
+            Some(EventTransactionId(event_tx_id)) => event_tx_id,
+            None => isize::MAX,
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "
+CREATE TEMPORARY TABLE pruned_transactions AS
+SELECT event_tx_id
+FROM event_transactions
+WHERE timestamp < :cutoff AND event_tx_id < :before_event_tx_id
+",
+            rusqlite::named_params! {
+                ":cutoff": cutoff,
+                ":before_event_tx_id": before_event_tx_id,
+            },
+        )
+        .wrap_err("Finding transactions to prune")?;
+        let num_pruned_events = tx
+            .execute(
+                "
+DELETE FROM event_log
+WHERE event_tx_id IN (SELECT event_tx_id FROM pruned_transactions)
+",
+                rusqlite::params![],
+            )
+            .wrap_err("Pruning events")?;
+        for table in [
+            "event_transaction_commands",
+            "event_transaction_annotations",
+            "event_transaction_origins",
+            "event_transaction_users",
+            "event_transactions",
+        ] {
+            tx.execute(
+                &format!(
+                    "
+DELETE FROM {}
+WHERE event_tx_id IN (SELECT event_tx_id FROM pruned_transactions)
+",
+                    table
+                ),
+                rusqlite::params![],
+            )
+            .wrap_err_with(|| format!("Pruning `{}` table", table))?;
+        }
+        tx.execute("DROP TABLE pruned_transactions", rusqlite::params![])
+            .wrap_err("Dropping temporary table")?;
+        tx.commit()?;
+        Ok(num_pruned_events)
+    }
+
+    /// Get the message which was provided when the given transaction was
+    /// created, such as the name of the hook which created it. Returns `None`
+    /// if there is no such transaction.
+    #[instrument]
+    pub fn get_transaction_message(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Option<String>> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let message: Option<String> = self
+            .conn
+            .query_row(
+                "
+SELECT message
+FROM event_transactions
+WHERE event_tx_id = :event_tx_id
+",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                },
+                |row| row.get("message"),
+            )
+            .optional()
+            .wrap_err_with(|| {
+                format!("Querying message for event transaction {:?}", event_tx_id)
+            })?;
+        Ok(message)
+    }
+
+    /// Get the Git command which was run as part of the given transaction, if
+    /// any.
+    #[instrument]
+    pub fn get_transaction_command(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Option<TransactionCommand>> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let result: Option<(String, isize)> = self
+            .conn
+            .query_row(
+                "
+SELECT args, exit_code
+FROM event_transaction_commands
+WHERE event_tx_id = :event_tx_id
+",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                },
+                |row| Ok((row.get("args")?, row.get("exit_code")?)),
+            )
+            .optional()
+            .wrap_err_with(|| {
+                format!("Querying command for event transaction {:?}", event_tx_id)
+            })?;
+        Ok(result.map(|(args, exit_code)| TransactionCommand {
+            args: if args.is_empty() {
+                Vec::new()
+            } else {
+                args.split('\0').map(|arg| arg.to_owned()).collect()
+            },
+            exit_code,
+        }))
+    }
+
+    fn get_sync_state(&self, key: &str) -> eyre::Result<Option<String>> {
+        let value: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM event_log_sync_state WHERE key = :key",
+                rusqlite::named_params! {
+                    ":key": key,
+                },
+                |row| row.get("value"),
+            )
+            .optional()
+            .wrap_err_with(|| format!("Querying event log sync state {:?}", key))?;
+        Ok(value)
+    }
+
+    fn set_sync_state(&self, key: &str, value: &str) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO event_log_sync_state VALUES (:key, :value)",
+                rusqlite::named_params! {
+                    ":key": key,
+                    ":value": value,
+                },
+            )
+            .wrap_err_with(|| format!("Updating event log sync state {:?}", key))?;
+        Ok(())
+    }
+
+    /// Get the identifier for this event log, which is used to determine the
+    /// origin of events shared between machines. It's generated randomly the
+    /// first time it's requested.
+    #[instrument]
+    pub fn get_sync_machine_id(&self) -> eyre::Result<String> {
+        if let Some(machine_id) = self.get_sync_state("machine_id")? {
+            return Ok(machine_id);
+        }
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_nanos(),
+        );
+        hasher.write_u32(std::process::id());
+        let machine_id = format!("{:016x}", hasher.finish());
+        self.set_sync_state("machine_id", &machine_id)?;
+        Ok(machine_id)
+    }
+
+    /// Serialize the events which were created locally since the last export.
+    /// Events which were imported from other machines are not included.
+    ///
+    /// Returns: The exported events, or `None` if there are no new events.
+    #[instrument]
+    pub fn export_events(&self) -> eyre::Result<Option<EventLogExport>> {
+        let machine_id = self.get_sync_machine_id()?;
+        let last_exported_rowid: isize = match self.get_sync_state("last_exported_rowid")? {
+            Some(rowid) => rowid.parse()?,
+            None => 0,
+        };
+
+        let mut stmt = self.conn.prepare(
+            "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE rowid > :last_exported_rowid
+AND event_tx_id NOT IN (SELECT event_tx_id FROM event_transaction_origins)
+ORDER BY rowid ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(isize, String)>> = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":last_exported_rowid": last_exported_rowid,
+                },
+                |row| {
+                    let rowid: isize = row.get("rowid")?;
+                    let timestamp: f64 = row.get("timestamp")?;
+                    let type_: String = row.get("type")?;
+                    let event_tx_id: isize = row.get("event_tx_id")?;
+                    let old_ref: Option<String> = row.get("old_ref")?;
+                    let new_ref: Option<String> = row.get("new_ref")?;
+                    let ref_name: Option<String> = row.get("ref_name")?;
+                    let message: Option<String> = row.get("message")?;
+                    let line = [
+                        format!("{}/{}", machine_id, event_tx_id),
+                        timestamp.to_string(),
+                        type_,
+                        escape_sync_field(old_ref.as_deref()),
+                        escape_sync_field(new_ref.as_deref()),
+                        escape_sync_field(ref_name.as_deref()),
+                        escape_sync_field(message.as_deref()),
+                    ]
+                    .join("\t");
+                    Ok((rowid, line))
+                },
+            )?
+            .collect();
+        let rows = rows.wrap_err("Querying events to export")?;
+
+        let last_rowid = match rows.last() {
+            Some((rowid, _line)) => *rowid,
+            None => return Ok(None),
+        };
+        let mut contents = String::from(EVENT_LOG_SYNC_HEADER);
+        contents.push('\n');
+        for (_rowid, line) in rows.iter() {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        Ok(Some(EventLogExport {
+            contents,
+            num_events: rows.len(),
+            last_rowid,
+        }))
+    }
+
+    /// Record that the given batch of events was exported and stored in the
+    /// blob with the given OID, so that neither the events nor the blob are
+    /// processed again.
+    #[instrument]
+    pub fn mark_events_exported(
+        &self,
+        export: &EventLogExport,
+        blob_oid: NonZeroOid,
+    ) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.set_sync_state("last_exported_rowid", &export.last_rowid.to_string())?;
+        self.mark_sync_blob_imported(blob_oid)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn mark_sync_blob_imported(&self, blob_oid: NonZeroOid) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO event_log_sync_imported_blobs VALUES (:blob_oid)",
                 rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":type": &type_,
-                    ":event_tx_id": event_tx_id,
-                    ":old_ref": &ref1,
-                    ":new_ref": &ref2,
-                    ":ref_name": &ref_name,
-                    ":message": &message,
+                    ":blob_oid": blob_oid.to_string(),
                 },
-            )?;
+            )
+            .wrap_err_with(|| format!("Marking blob {:?} as imported", blob_oid))?;
+        Ok(())
+    }
+
+    /// Get the commits of the stash entries as of the last call to
+    /// `set_stash_snapshot`, from the most recent entry to the oldest one.
+    /// Returns `None` if no snapshot has been saved yet.
+    #[instrument]
+    pub fn get_stash_snapshot(&self) -> eyre::Result<Option<Vec<NonZeroOid>>> {
+        let stash_oids: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT stash_oids FROM stash_snapshot",
+                rusqlite::params![],
+                |row| row.get("stash_oids"),
+            )
+            .optional()
+            .wrap_err("Querying stash snapshot")?;
+        match stash_oids {
+            Some(stash_oids) => {
+                let stash_oids = stash_oids
+                    .split_whitespace()
+                    .map(NonZeroOid::from_str)
+                    .collect::<eyre::Result<Vec<_>>>()?;
+                Ok(Some(stash_oids))
+            }
+            None => Ok(None),
         }
+    }
+
+    /// Save the commits of the current stash entries, so that the entries
+    /// which are added or removed later can be determined with
+    /// `record_stash_changes`.
+    #[instrument]
+    pub fn set_stash_snapshot(&self, stash_oids: &[NonZeroOid]) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM stash_snapshot", rusqlite::params![])
+            .wrap_err("Clearing stash snapshot")?;
+        tx.execute(
+            "INSERT INTO stash_snapshot VALUES (:stash_oids)",
+            rusqlite::named_params! {
+                ":stash_oids": stash_oids.iter().map(|oid| oid.to_string()).join(" "),
+            },
+        )
+        .wrap_err("Saving stash snapshot")?;
         tx.commit()?;
         Ok(())
     }
 
-    /// Get all the events in the database.
-    ///
-    /// Returns: All the events in the database, ordered from oldest to newest.
+    /// Determine whether the events in the given blob have already been
+    /// imported into (or were exported from) this event log.
     #[instrument]
+    pub fn is_sync_blob_imported(&self, blob_oid: NonZeroOid) -> eyre::Result<bool> {
+        let count: isize = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM event_log_sync_imported_blobs WHERE blob_oid = :blob_oid",
+                rusqlite::named_params! {
+                    ":blob_oid": blob_oid.to_string(),
+                },
+                |row| row.get(0),
+            )
+            .wrap_err_with(|| format!("Querying whether blob {:?} was imported", blob_oid))?;
+        Ok(count > 0)
+    }
 
-    pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
-        let mut stmt = self.conn.prepare(
-            "
-SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
-FROM event_log
-ORDER BY rowid ASC
-",
-        )?;
-        let rows: rusqlite::Result<Vec<Row>> = stmt
-            .query_map(rusqlite::params![], |row| {
-                let timestamp: f64 = row.get("timestamp")?;
-                let event_tx_id: isize = row.get("event_tx_id")?;
-                let type_: String = row.get("type")?;
-                let ref_name: Option<String> = row.get("ref_name")?;
-                let old_ref: Option<String> = row.get("old_ref")?;
-                let new_ref: Option<String> = row.get("new_ref")?;
-                let message: Option<String> = row.get("message")?;
+    /// Import batches of events exported from other machines.
+    ///
+    /// The events from all batches are appended to the event log ordered by
+    /// timestamp. Ties are broken by origin, and then by the order of the
+    /// events in their batch, so every machine imports a given set of batches
+    /// in the same order. When the events are read back, they're ordered by
+    /// timestamp among the local events (see `get_events_for_user`). The
+    /// events of each originating transaction are grouped into a new local
+    /// transaction. Updates to `HEAD` aren't imported, since they describe
+    /// checkouts on the other machine.
+    ///
+    /// Args:
+    /// * `batches`: The OID of the blob containing each batch, along with its
+    /// contents.
+    ///
+    /// Returns: The number of events imported.
+    #[instrument(skip(batches))]
+    pub fn import_events(&mut self, batches: &[(NonZeroOid, String)]) -> eyre::Result<usize> {
+        let local_origin_prefix = format!("{}/", self.get_sync_machine_id()?);
+        let mut rows = Vec::new();
+        for (blob_oid, contents) in batches {
+            let batch_rows = parse_sync_events(contents)
+                .wrap_err_with(|| format!("Parsing exported events in blob {:?}", blob_oid))?;
+            rows.extend(batch_rows.into_iter().filter(|(origin, row)| {
+                !origin.starts_with(&local_origin_prefix) && !is_head_update_row(row)
+            }));
+        }
+        rows.sort_by(|(lhs_origin, lhs_row), (rhs_origin, rhs_row)| {
+            lhs_row
+                .timestamp
+                .partial_cmp(&rhs_row.timestamp)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| lhs_origin.cmp(rhs_origin))
+        });
 
-                Ok(Row {
-                    timestamp,
-                    event_tx_id,
-                    type_,
-                    ref_name: ref_name.map(OsString::from),
-                    ref1: old_ref.map(OsString::from),
-                    ref2: new_ref.map(OsString::from),
-                    message: message.map(OsString::from),
-                })
-            })?
-            .collect();
-        let rows = rows?;
-        rows.into_iter().map(Event::try_from).collect()
+        let tx = self.conn.unchecked_transaction()?;
+        let mut origin_to_event_tx_id: HashMap<String, isize> = HashMap::new();
+        let num_events = rows.len();
+        for (origin, mut row) in rows {
+            row.event_tx_id = match origin_to_event_tx_id.get(&origin) {
+                Some(event_tx_id) => *event_tx_id,
+                None => {
+                    let event_tx_id =
+                        self.get_or_create_origin_transaction(&origin, row.timestamp)?;
+                    origin_to_event_tx_id.insert(origin, event_tx_id);
+                    event_tx_id
+                }
+            };
+            insert_row(&tx, row)?;
+        }
+        for (blob_oid, _contents) in batches {
+            self.mark_sync_blob_imported(*blob_oid)?;
+        }
+        tx.commit()?;
+        Ok(num_events)
     }
 
-    /// Create a new event transaction ID to be used to insert subsequent
-    /// `Event`s into the database.
-    #[instrument(fields(message = message.as_ref()))]
-    pub fn make_transaction_id(
+    fn get_or_create_origin_transaction(
         &self,
-        now: SystemTime,
-        message: impl AsRef<str>,
-    ) -> eyre::Result<EventTransactionId> {
-        if let Ok(transaction_id) = std::env::var(BRANCHLESS_TRANSACTION_ID_ENV_VAR) {
-            if let Ok(transaction_id) = transaction_id.parse::<EventTransactionId>() {
-                return Ok(transaction_id);
-            }
+        origin: &str,
+        timestamp: f64,
+    ) -> eyre::Result<isize> {
+        let event_tx_id: Option<isize> = self
+            .conn
+            .query_row(
+                "SELECT event_tx_id FROM event_transaction_origins WHERE origin = :origin",
+                rusqlite::named_params! {
+                    ":origin": origin,
+                },
+                |row| row.get("event_tx_id"),
+            )
+            .optional()
+            .wrap_err_with(|| format!("Querying transaction for origin {:?}", origin))?;
+        if let Some(event_tx_id) = event_tx_id {
+            return Ok(event_tx_id);
         }
 
-        let tx = self.conn.unchecked_transaction()?;
-
-        let timestamp = now
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .wrap_err_with(|| format!("Calculating event transaction timestamp: {:?}", &now))?
-            .as_secs_f64();
         self.conn
             .execute(
                 "
-            INSERT INTO event_transactions
-            (timestamp, message)
-            VALUES
-            (:timestamp, :message)
-        ",
+INSERT INTO event_transactions
+(timestamp, message)
+VALUES
+(:timestamp, :message)
+",
                 rusqlite::named_params! {
                     ":timestamp": timestamp,
-                    ":message": message.as_ref(),
+                    ":message": format!("sync-events {}", origin),
                 },
             )
-            .wrap_err_with(|| {
-                format!(
-                    "Creating event transaction (now: {:?}, message: {:?})",
-                    &now,
-                    message.as_ref(),
-                )
-            })?;
-
-        // Ensure that we query `last_insert_rowid` in a transaction, in case
-        // there's another thread in this process making queries with the same
-        // SQLite connection.
+            .wrap_err_with(|| format!("Creating transaction for origin {:?}", origin))?;
         let event_tx_id: isize = self.conn.last_insert_rowid().try_into()?;
-        tx.commit()?;
-        Ok(EventTransactionId(event_tx_id))
+        self.conn
+            .execute(
+                "INSERT INTO event_transaction_origins VALUES (:event_tx_id, :origin)",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                    ":origin": origin,
+                },
+            )
+            .wrap_err_with(|| format!("Recording origin {:?}", origin))?;
+        Ok(event_tx_id)
+    }
+}
+
+/// Determine whether the given reference is the `HEAD` of one of the worktrees
+/// of the repository, as recorded in the event log. See
+/// `Repo::get_head_ref_name`.
+pub fn is_worktree_head_ref(ref_name: &OsStr) -> bool {
+    match ref_name.to_str() {
+        None => false,
+        Some("HEAD") => true,
+        Some(ref_name) => match ref_name
+            .strip_prefix("worktrees/")
+            .and_then(|ref_name| ref_name.strip_suffix("/HEAD"))
+        {
+            Some(worktree_name) => !worktree_name.is_empty() && !worktree_name.contains('/'),
+            None => false,
+        },
     }
 }
 
@@ -583,7 +1676,114 @@ ORDER BY rowid ASC
 pub fn is_gc_ref(ref_name: &OsStr) -> bool {
     match ref_name.to_str() {
         None => false,
-        Some(ref_name) => ref_name.starts_with("refs/branchless/"),
+        Some(ref_name) => {
+            ref_name.starts_with("refs/branchless/")
+                && !is_event_log_sync_ref(OsStr::new(ref_name))
+                && !is_keep_ref(OsStr::new(ref_name))
+        }
+    }
+}
+
+/// The prefix of the references used to keep recently-hidden commits alive,
+/// so that they can still be restored with `git undo` after Git's garbage
+/// collection runs.
+pub const KEEP_REF_PREFIX: &str = "refs/branchless/keep/";
+
+/// Determine whether the given reference is used to keep a recently-hidden
+/// commit alive.
+pub fn is_keep_ref(ref_name: &OsStr) -> bool {
+    match ref_name.to_str() {
+        None => false,
+        Some(ref_name) => ref_name.starts_with(KEEP_REF_PREFIX),
+    }
+}
+
+/// The reference which Git stores the most recent stash entry in. The other
+/// entries are only kept in its reflog. Changes to the stash are recorded as
+/// `StashPushEvent`s and `StashDropEvent`s by `record_stash_changes`.
+pub const STASH_REF_NAME: &str = "refs/stash";
+
+/// Make the events for the changes between two states of the stash, given as
+/// the commits of the stash entries from the most recent one to the oldest
+/// one. The removed entries come first, followed by the added entries from the
+/// oldest one to the most recent one.
+pub fn make_stash_events(
+    old_stash_oids: &[NonZeroOid],
+    new_stash_oids: &[NonZeroOid],
+    timestamp: f64,
+    event_tx_id: EventTransactionId,
+) -> Vec<Event> {
+    let drop_events = old_stash_oids
+        .iter()
+        .filter(|oid| !new_stash_oids.contains(oid))
+        .map(|commit_oid| Event::StashDropEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *commit_oid,
+        });
+    let push_events = new_stash_oids
+        .iter()
+        .rev()
+        .filter(|oid| !old_stash_oids.contains(oid))
+        .map(|commit_oid| Event::StashPushEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *commit_oid,
+        });
+    drop_events.chain(push_events).collect()
+}
+
+/// Find the entries which were added to or removed from the stash since the
+/// stash snapshot was saved, by comparing the snapshot with the stash reflog,
+/// and save a new snapshot. If there is no snapshot yet, `old_stash_oids` is
+/// used in its place.
+///
+/// Git only invokes the `reference-transaction` hook when `refs/stash` itself
+/// is created or deleted, or when an entry is pushed, so dropping one of
+/// several entries can only be detected this way.
+///
+/// Returns: The events for the changes, which should be added to the event
+/// log by the caller.
+#[instrument]
+pub fn record_stash_changes(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+    old_stash_oids: &[NonZeroOid],
+) -> eyre::Result<Vec<Event>> {
+    let old_stash_oids = match event_log_db.get_stash_snapshot()? {
+        Some(stash_oids) => stash_oids,
+        None => old_stash_oids.to_vec(),
+    };
+    let new_stash_oids = repo.get_stash_oids()?;
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err_with(|| format!("Calculating stash event timestamp: {:?}", &now))?
+        .as_secs_f64();
+    let events = make_stash_events(&old_stash_oids, &new_stash_oids, timestamp, event_tx_id);
+    event_log_db.set_stash_snapshot(&new_stash_oids)?;
+    Ok(events)
+}
+
+/// The reference under which the event log is stored when sharing it between
+/// machines with `git branchless sync-events`.
+pub const EVENT_LOG_SYNC_REF_NAME: &str = "refs/branchless/event-log";
+
+/// Get the name of the reference under which the copy of the event log
+/// fetched from the given remote is stored.
+pub fn get_event_log_sync_remote_ref_name(remote_name: &str) -> String {
+    format!("refs/branchless/remotes/{}/event-log", remote_name)
+}
+
+/// Determine whether the given reference is used to share the event log
+/// between machines.
+pub fn is_event_log_sync_ref(ref_name: &OsStr) -> bool {
+    match ref_name.to_str() {
+        None => false,
+        Some(ref_name) => {
+            ref_name == EVENT_LOG_SYNC_REF_NAME || ref_name.starts_with("refs/branchless/remotes/")
+        }
     }
 }
 
@@ -594,7 +1794,7 @@ pub fn is_gc_ref(ref_name: &OsStr) -> bool {
 ///
 /// Returns: Whether or not updates to the given reference should be ignored.
 pub fn should_ignore_ref_updates(ref_name: &OsStr) -> bool {
-    if is_gc_ref(ref_name) {
+    if is_gc_ref(ref_name) || is_event_log_sync_ref(ref_name) || is_keep_ref(ref_name) {
         return true;
     }
 
@@ -604,6 +1804,89 @@ pub fn should_ignore_ref_updates(ref_name: &OsStr) -> bool {
     )
 }
 
+/// The references whose updates shouldn't be recorded in the event log, as
+/// configured by `branchless.eventLog.ignoreRefs`. This is useful for
+/// references which are updated frequently by other tools, such as
+/// `refs/notes/*` or `refs/pull/*`.
+///
+/// Each pattern is matched against the full name of the reference. In a
+/// pattern, `*` matches any sequence of characters (including `/`) and `?`
+/// matches any single character.
+#[derive(Debug)]
+pub struct IgnoredRefPatterns {
+    patterns: Vec<Regex>,
+}
+
+impl IgnoredRefPatterns {
+    /// Constructor.
+    pub fn new(patterns: &[String]) -> eyre::Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let mut regex = String::from("^");
+                for c in pattern.chars() {
+                    match c {
+                        '*' => regex.push_str(".*"),
+                        '?' => regex.push('.'),
+                        c => regex.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                regex.push('$');
+                Regex::new(&regex)
+                    .wrap_err_with(|| format!("Parsing ignored reference pattern: {:?}", pattern))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(IgnoredRefPatterns { patterns })
+    }
+
+    /// Get the patterns configured for the repository.
+    #[instrument]
+    pub fn from_repo(repo: &Repo) -> eyre::Result<Self> {
+        Self::new(&get_event_log_ignore_refs(repo)?)
+    }
+
+    /// Determine whether the given reference matches any of the patterns.
+    pub fn matches(&self, ref_name: &OsStr) -> bool {
+        let ref_name = ref_name.to_string_lossy();
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&ref_name))
+    }
+}
+
+/// A durable identifier for a commit, which is preserved when the commit is
+/// rewritten (such as by amending or rebasing it).
+///
+/// The change ID is derived from the OID of the first version of the commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChangeId(NonZeroOid);
+
+impl ChangeId {
+    /// Get an abbreviated form of the change ID, for display purposes.
+    pub fn to_short_string(&self) -> String {
+        let ChangeId(oid) = self;
+        format!("I{}", &oid.to_string()[..8])
+    }
+}
+
+impl std::fmt::Display for ChangeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ChangeId(oid) = self;
+        write!(f, "I{}", oid)
+    }
+}
+
+/// Which version of a change a given commit is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChangeVersion {
+    /// The ID of the change.
+    pub change_id: ChangeId,
+
+    /// The version number of the commit within the change. The first version
+    /// of a change is version 1, and each rewrite increments the version.
+    pub version: usize,
+}
+
 #[derive(Debug)]
 enum EventClassification {
     Show,
@@ -640,7 +1923,7 @@ struct EventInfo {
 /// Thus, all events before to the cursor are considered to be in effect,
 /// and all events after the cursor are considered to not have happened
 /// yet.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct EventCursor {
     event_id: isize,
 }
@@ -656,6 +1939,11 @@ pub struct EventReplayer {
     /// The name of the reference representing the main branch.
     main_branch_reference_name: OsString,
 
+    /// The name under which `HEAD` is recorded for the worktree which the
+    /// events are being replayed for. Updates to the `HEAD` of other worktrees
+    /// are ignored when determining where `HEAD` pointed.
+    head_ref_name: OsString,
+
     /// The events that have affected each commit.
     commit_history: HashMap<NonZeroOid, Vec<EventInfo>>,
 
@@ -684,6 +1972,7 @@ impl EventReplayer {
             id_counter: 0,
             events: vec![],
             main_branch_reference_name: main_branch_reference_name.into(),
+            head_ref_name: OsString::from("HEAD"),
             commit_history: HashMap::new(),
             ref_locations: HashMap::new(),
         }
@@ -700,12 +1989,34 @@ impl EventReplayer {
         repo: &Repo,
         event_log_db: &EventLogDb,
     ) -> eyre::Result<Self> {
-        let (_effects, _progress) = effects.start_operation(OperationType::ProcessEvents);
+        Self::from_event_log_db_for_user(effects, repo, event_log_db, None)
+    }
+
+    /// Construct the replayer from the events in the database which were
+    /// created by the given user, or which aren't attributed to any user. See
+    /// `EventLogDb::get_events_for_user`.
+    ///
+    /// Args:
+    /// * `event_log_db`: The database to query events from.
+    /// * `user`: The user whose events to replay, or `None` for all users.
+    ///
+    /// Returns: The constructed replayer.
+    pub fn from_event_log_db_for_user(
+        effects: &Effects,
+        repo: &Repo,
+        event_log_db: &EventLogDb,
+        user: Option<&str>,
+    ) -> eyre::Result<Self> {
+        let (_effects, progress) = effects.start_operation(OperationType::ProcessEvents);
 
         let main_branch_reference_name = repo.get_main_branch_reference()?.get_name()?;
         let mut result = EventReplayer::new(main_branch_reference_name);
-        for event in event_log_db.get_events()? {
+        result.head_ref_name = repo.get_head_ref_name();
+        let events = event_log_db.get_events_for_user(user)?;
+        progress.notify_progress(0, events.len());
+        for event in events {
             result.process_event(&event);
+            progress.notify_progress_inc(1);
         }
         Ok(result)
     }
@@ -800,6 +2111,7 @@ impl EventReplayer {
                 timestamp: _,
                 event_tx_id: _,
                 commit_oid,
+                reason: _,
             } => self
                 .commit_history
                 .entry(*commit_oid)
@@ -823,6 +2135,25 @@ impl EventReplayer {
                     event: event.clone(),
                     event_classification: EventClassification::Show,
                 }),
+
+            Event::CopyEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: _,
+                new_commit_oid,
+            } => self
+                .commit_history
+                .entry(*new_commit_oid)
+                .or_default()
+                .push(EventInfo {
+                    id,
+                    event: event.clone(),
+                    event_classification: EventClassification::Show,
+                }),
+
+            // The stash commits aren't part of the commit graph, so changes to
+            // the stash don't affect the visibility of any commit.
+            Event::StashPushEvent { .. } | Event::StashDropEvent { .. } => {}
         };
     }
 
@@ -933,6 +2264,74 @@ impl EventReplayer {
         Some(&event_info.event)
     }
 
+    /// Find the commit which the given commit was rewritten from, considering
+    /// only the rewrites before the event with ID `event_id_bound`.
+    ///
+    /// Returns: The ID of the rewrite event and the OID of the commit before
+    /// the rewrite, or `None` if the commit wasn't rewritten from another one.
+    fn get_cursor_predecessor(
+        &self,
+        cursor: EventCursor,
+        oid: NonZeroOid,
+        event_id_bound: isize,
+    ) -> Option<(isize, NonZeroOid)> {
+        self.get_cursor_commit_history(cursor, oid)
+            .into_iter()
+            .filter(|event_info| event_info.id < event_id_bound)
+            .find_map(|event_info| match event_info.event {
+                Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    ..
+                } if new_commit_oid == oid => Some((event_info.id, old_commit_oid)),
+                _ => None,
+            })
+    }
+
+    /// Get the previous version of the given commit as of the cursor, which is
+    /// the commit it was rewritten from by an amend, rebase or other rewrite.
+    ///
+    /// Returns: The OID of the previous version, or `None` if the commit is the
+    /// first version of its change.
+    pub fn get_cursor_previous_version(
+        &self,
+        cursor: EventCursor,
+        oid: NonZeroOid,
+    ) -> Option<NonZeroOid> {
+        self.get_cursor_predecessor(cursor, oid, cursor.event_id)
+            .map(|(_event_id, old_commit_oid)| old_commit_oid)
+    }
+
+    /// Get the change which the given commit belongs to, as of the cursor's
+    /// point in time.
+    ///
+    /// The history of the commit is followed backwards through rewrite events
+    /// to find the first version of the commit. If the commit was never
+    /// rewritten, then it's the first version of its own change.
+    pub fn get_cursor_change_version(&self, cursor: EventCursor, oid: NonZeroOid) -> ChangeVersion {
+        let mut current_oid = oid;
+        let mut version = 1;
+        // Only consider rewrites which happened strictly before the one
+        // already followed, so that rewrite cycles (such as those produced by
+        // `git undo`) terminate.
+        let mut event_id_bound = cursor.event_id;
+        loop {
+            let predecessor = self.get_cursor_predecessor(cursor, current_oid, event_id_bound);
+            match predecessor {
+                Some((event_id, old_commit_oid)) => {
+                    current_oid = old_commit_oid;
+                    version += 1;
+                    event_id_bound = event_id;
+                }
+                None => break,
+            }
+        }
+        ChangeVersion {
+            change_id: ChangeId(current_oid),
+            version,
+        }
+    }
+
     /// Get the OIDs which have activity according to the repository history.
     ///
     /// Returns: The set of OIDs referring to commits which are thought to be
@@ -1073,7 +2472,7 @@ impl EventReplayer {
                         ref_name,
                         new_oid: MaybeZeroOid::NonZero(new_oid),
                         ..
-                    } if ref_name == "HEAD" => Some(*new_oid),
+                    } if ref_name == &self.head_ref_name => Some(*new_oid),
                     Event::RefUpdateEvent { .. } => None,
 
                     // Not strictly necessary, but helps to compensate in case
@@ -1083,7 +2482,10 @@ impl EventReplayer {
 
                     Event::RewriteEvent { .. }
                     | Event::HideEvent { .. }
-                    | Event::UnhideEvent { .. } => None,
+                    | Event::UnhideEvent { .. }
+                    | Event::StashPushEvent { .. }
+                    | Event::StashDropEvent { .. }
+                    | Event::CopyEvent { .. } => None,
                 }
             })
     }
@@ -1266,6 +2668,15 @@ pub mod testing {
             Event::UnhideEvent {
                 ref mut timestamp, ..
             } => *timestamp = 0.0,
+            Event::StashPushEvent {
+                ref mut timestamp, ..
+            } => *timestamp = 0.0,
+            Event::StashDropEvent {
+                ref mut timestamp, ..
+            } => *timestamp = 0.0,
+            Event::CopyEvent {
+                ref mut timestamp, ..
+            } => *timestamp = 0.0,
         }
         event
     }
@@ -1283,6 +2694,115 @@ mod tests {
     use crate::testing::make_git;
     use testing::make_dummy_transaction_id;
 
+    #[test]
+    fn test_is_worktree_head_ref() {
+        assert!(is_worktree_head_ref(OsStr::new("HEAD")));
+        assert!(is_worktree_head_ref(OsStr::new("worktrees/foo/HEAD")));
+        assert!(!is_worktree_head_ref(OsStr::new("worktrees//HEAD")));
+        assert!(!is_worktree_head_ref(OsStr::new("worktrees/foo/bar/HEAD")));
+        assert!(!is_worktree_head_ref(OsStr::new("refs/heads/HEAD")));
+        assert!(!is_worktree_head_ref(OsStr::new("ORIG_HEAD")));
+    }
+
+    #[test]
+    fn test_make_stash_events() -> eyre::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let oid1: NonZeroOid = "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?;
+        let oid2: NonZeroOid = "96d1c37a3d4363611c49f7e52186e189a04c531f".parse()?;
+        let oid3: NonZeroOid = "70deb1e28791d8e7dd5a1f0c871a51b91282562f".parse()?;
+
+        assert_eq!(
+            make_stash_events(&[oid2, oid1], &[oid2, oid1], 0.0, event_tx_id),
+            vec![]
+        );
+        assert_eq!(
+            make_stash_events(&[oid3, oid2, oid1], &[oid3, oid1], 0.0, event_tx_id),
+            vec![Event::StashDropEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                commit_oid: oid2,
+            }]
+        );
+        assert_eq!(
+            make_stash_events(&[oid1], &[oid3, oid2], 0.0, event_tx_id),
+            vec![
+                Event::StashDropEvent {
+                    timestamp: 0.0,
+                    event_tx_id,
+                    commit_oid: oid1,
+                },
+                Event::StashPushEvent {
+                    timestamp: 0.0,
+                    event_tx_id,
+                    commit_oid: oid2,
+                },
+                Event::StashPushEvent {
+                    timestamp: 0.0,
+                    event_tx_id,
+                    commit_oid: oid3,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_imported_rows() {
+        let row = |timestamp: f64, ref_name: &str| Row {
+            timestamp,
+            type_: "ref-move".to_string(),
+            event_tx_id: 1,
+            ref1: None,
+            ref2: None,
+            ref_name: Some(OsString::from(ref_name)),
+            message: None,
+        };
+        let merged = merge_imported_rows(vec![
+            (false, row(1.0, "local-1")),
+            (false, row(4.0, "local-4")),
+            (false, row(3.0, "local-3")),
+            (true, row(5.0, "imported-5")),
+            (true, row(2.0, "imported-2")),
+            (true, row(4.0, "imported-4")),
+        ]);
+        let ref_names: Vec<_> = merged
+            .iter()
+            .map(|row| row.ref_name.as_ref().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            ref_names,
+            vec![
+                "local-1",
+                "imported-2",
+                "local-4",
+                "local-3",
+                "imported-4",
+                "imported-5"
+            ]
+        );
+
+        assert!(is_head_update_row(&row(1.0, "HEAD")));
+        assert!(is_head_update_row(&row(1.0, "worktrees/foo/HEAD")));
+        assert!(!is_head_update_row(&row(1.0, "refs/heads/master")));
+    }
+
+    #[test]
+    fn test_ignored_ref_patterns() -> eyre::Result<()> {
+        let patterns =
+            IgnoredRefPatterns::new(&["refs/notes/*".to_string(), "refs/pull/?".to_string()])?;
+        assert!(patterns.matches(OsStr::new("refs/notes/commits")));
+        assert!(patterns.matches(OsStr::new("refs/notes/ci/results")));
+        assert!(patterns.matches(OsStr::new("refs/pull/1")));
+        assert!(!patterns.matches(OsStr::new("refs/pull/12")));
+        assert!(!patterns.matches(OsStr::new("refs/heads/refs/notes/foo")));
+        assert!(!patterns.matches(OsStr::new("refs/heads/master")));
+
+        let patterns = IgnoredRefPatterns::new(&["refs/tags/v1.0".to_string()])?;
+        assert!(patterns.matches(OsStr::new("refs/tags/v1.0")));
+        assert!(!patterns.matches(OsStr::new("refs/tags/v1x0")));
+        Ok(())
+    }
+
     #[test]
     fn test_drop_non_meaningful_events() -> eyre::Result<()> {
         let event_tx_id = make_dummy_transaction_id(123);
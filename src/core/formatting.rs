@@ -4,9 +4,14 @@
 //! "TTY"). In the case of interactive output, we render with prettier non-ASCII
 //! characters and with colors, using shell-specific escape codes.
 
-use cursive::theme::{Effect, Style};
+use std::ffi::OsStr;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use cursive::theme::{BaseColor, Effect, Style};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
+use lazy_static::lazy_static;
 
 /// Pluralize a quantity, as appropriate. Example:
 ///
@@ -38,6 +43,274 @@ impl<'a> ToString for Pluralize<'a> {
     }
 }
 
+/// Renders text as a hyperlink via the OSC 8 escape sequence, for terminals
+/// which support it. When disabled, text is rendered as-is. Example:
+///
+/// ```
+/// # use branchless::core::formatting::Hyperlinks;
+/// let hyperlinks = Hyperlinks::new(true);
+/// assert_eq!(
+///     hyperlinks.render("abc", "https://example.com"),
+///     "\x1b]8;;https://example.com\x1b\\abc\x1b]8;;\x1b\\"
+/// );
+/// assert_eq!(Hyperlinks::new(false).render("abc", "https://example.com"), "abc");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hyperlinks {
+    is_enabled: bool,
+}
+
+impl Hyperlinks {
+    /// Constructor.
+    pub fn new(is_enabled: bool) -> Self {
+        Hyperlinks { is_enabled }
+    }
+
+    /// Enable hyperlinks if the output is a terminal which is known to support
+    /// them. `is_enabled_override` takes precedence over the detection if set.
+    pub fn detect(glyphs: &Glyphs, is_enabled_override: Option<bool>) -> Self {
+        let is_enabled = match is_enabled_override {
+            Some(is_enabled) => is_enabled,
+            None => glyphs.should_write_ansi_escape_codes && Self::is_supported_by_terminal(),
+        };
+        Hyperlinks { is_enabled }
+    }
+
+    /// Guess whether the terminal supports hyperlinks from the environment,
+    /// since there's no way to query it directly.
+    fn is_supported_by_terminal() -> bool {
+        let var = |name: &str| std::env::var(name).ok();
+        if let Some(value) = var("FORCE_HYPERLINK") {
+            return value != "0";
+        }
+        if var("DOMTERM").is_some()
+            || var("WT_SESSION").is_some()
+            || var("KONSOLE_VERSION").is_some()
+        {
+            return true;
+        }
+        if let Some(version) = var("VTE_VERSION") {
+            // VTE has supported hyperlinks since 0.50.
+            return matches!(version.parse::<u32>(), Ok(version) if version >= 5000);
+        }
+        if let Some(program) = var("TERM_PROGRAM") {
+            if matches!(
+                program.as_str(),
+                "Hyper" | "iTerm.app" | "terminology" | "WezTerm" | "vscode"
+            ) {
+                return true;
+            }
+        }
+        matches!(
+            var("TERM").as_deref(),
+            Some("xterm-kitty") | Some("alacritty") | Some("foot")
+        )
+    }
+
+    /// Whether hyperlinks will be rendered.
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// Render the given text as a hyperlink to the given URL, if enabled.
+    pub fn render(&self, text: &str, url: &str) -> String {
+        if self.is_enabled {
+            format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Remove any hyperlinks from the given text, leaving only the text which
+    /// would be displayed.
+    fn strip(text: &str) -> String {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("\x1b]8;") {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            rest = match rest.find("\x1b\\") {
+                Some(end) => &rest[end + "\x1b\\".len()..],
+                None => "",
+            };
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+/// Whether to write ANSI escape codes for colors and other styling, as set by
+/// `--color` or `branchless.color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSetting {
+    /// Style the output only if it's written to a terminal.
+    Auto,
+
+    /// Always style the output, even if it's piped to another program.
+    Always,
+
+    /// Never style the output.
+    Never,
+}
+
+impl FromStr for ColorSetting {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorSetting::Auto),
+            "always" => Ok(ColorSetting::Always),
+            "never" => Ok(ColorSetting::Never),
+            other => eyre::bail!(
+                "Invalid color setting (expected `auto`, `always` or `never`): {:?}",
+                other
+            ),
+        }
+    }
+}
+
+impl ColorSetting {
+    /// Determine the setting requested by the environment, following the
+    /// informal standards at <https://no-color.org> and
+    /// <https://bixense.com/clicolors>: `NO_COLOR` (if set to a non-empty
+    /// value) disables styling, and otherwise `CLICOLOR_FORCE` (if set to
+    /// anything but `0`) forces it. Returns `None` if neither is set.
+    pub fn from_env() -> Option<Self> {
+        Self::from_env_values(
+            std::env::var_os("NO_COLOR").as_deref(),
+            std::env::var_os("CLICOLOR_FORCE").as_deref(),
+        )
+    }
+
+    fn from_env_values(no_color: Option<&OsStr>, clicolor_force: Option<&OsStr>) -> Option<Self> {
+        match (no_color, clicolor_force) {
+            (Some(no_color), _) if !no_color.is_empty() => Some(ColorSetting::Never),
+            (_, Some(clicolor_force)) if !clicolor_force.is_empty() && clicolor_force != "0" => {
+                Some(ColorSetting::Always)
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve `Auto` using the environment (see `ColorSetting::from_env`).
+    /// Explicit settings are returned unchanged, since command-line flags and
+    /// configuration take precedence over the environment.
+    pub fn resolve_with_env(self) -> Self {
+        match self {
+            ColorSetting::Auto => ColorSetting::from_env().unwrap_or(ColorSetting::Auto),
+            ColorSetting::Always | ColorSetting::Never => self,
+        }
+    }
+
+    /// Apply the setting to the styling done with the `console` crate, which
+    /// otherwise decides for itself whether to write ANSI escape codes.
+    pub fn apply_to_console(self) {
+        let color = self.resolve_with_env();
+        match color {
+            ColorSetting::Auto => {}
+            ColorSetting::Always | ColorSetting::Never => {
+                let is_enabled = color == ColorSetting::Always;
+                console::set_colors_enabled(is_enabled);
+                console::set_colors_enabled_stderr(is_enabled);
+            }
+        }
+    }
+}
+
+/// The styles for each kind of text which `git-branchless` renders, so that
+/// tools rendering the same data can style it consistently. The styles can be
+/// configured with `branchless.color.*`; see `get_palette` in
+/// `core::config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Commit hashes.
+    pub oid: Style,
+
+    /// Explanations shown next to commits, such as why a commit is hidden.
+    pub annotation: Style,
+
+    /// How long ago a commit was made.
+    pub relative_time: Style,
+
+    /// Phabricator revisions.
+    pub revision: Style,
+
+    /// Phabricator revisions which need revision.
+    pub revision_needs_revision: Style,
+
+    /// Text to draw attention to, such as the line for the `HEAD` commit.
+    pub emphasis: Style,
+
+    /// The currently selected item in an interactive list.
+    pub selection: Style,
+
+    /// Successes, such as passing CI checks or accepted revisions.
+    pub success: Style,
+
+    /// Warnings and notes, such as pending CI checks.
+    pub warning: Style,
+
+    /// Errors and problems, such as failing CI checks or failed commands.
+    pub error: Style,
+
+    /// The branch which is currently checked out.
+    pub branch_head: Style,
+
+    /// Any other local branch.
+    pub branch_local: Style,
+
+    /// A remote-tracking branch.
+    pub branch_remote: Style,
+
+    /// Any other reference.
+    pub branch_other: Style,
+
+    /// The parentheses and commas around the list of branches.
+    pub branch_punctuation: Style,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            oid: BaseColor::Yellow.dark().into(),
+            annotation: BaseColor::Black.light().into(),
+            relative_time: BaseColor::Green.dark().into(),
+            revision: BaseColor::Green.dark().into(),
+            revision_needs_revision: BaseColor::Red.dark().into(),
+            emphasis: Effect::Bold.into(),
+            selection: Effect::Reverse.into(),
+            success: BaseColor::Green.light().into(),
+            warning: BaseColor::Yellow.light().into(),
+            error: BaseColor::Red.light().into(),
+            branch_head: Style::from(BaseColor::Cyan.light()).combine(Effect::Bold),
+            branch_local: BaseColor::Green.light().into(),
+            branch_remote: BaseColor::Red.light().into(),
+            branch_other: BaseColor::Yellow.light().into(),
+            branch_punctuation: BaseColor::Green.light().into(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CURRENT_PALETTE: RwLock<Palette> = RwLock::new(Palette::default());
+}
+
+impl Palette {
+    /// Get the palette which was last installed with `Palette::install`, or
+    /// the default palette if none was installed.
+    pub fn current() -> Palette {
+        *CURRENT_PALETTE.read().unwrap()
+    }
+
+    /// Use this palette for all text rendered afterwards in this process.
+    /// Like `ColorSetting::apply_to_console`, this is done once at startup,
+    /// so that the palette doesn't have to be passed to every place which
+    /// styles text.
+    pub fn install(self) {
+        *CURRENT_PALETTE.write().unwrap() = self;
+    }
+}
+
 /// Glyphs to use for rendering the smartlog.
 #[derive(Clone)]
 pub struct Glyphs {
@@ -57,6 +330,10 @@ pub struct Glyphs {
     /// Line used to connect a parent commit to its non-first child commit.
     pub slash: &'static str,
 
+    /// Line used to connect a merge commit to a parent commit in the column to
+    /// its right.
+    pub merge_slash: &'static str,
+
     /// Cursor for a visible commit which is not currently checked out.
     pub commit_visible: &'static str,
 
@@ -102,16 +379,43 @@ pub struct Glyphs {
 
     /// Corner at the lower left of the arrow used when printing a commit cycle.
     pub cycle_lower_left_corner: &'static str,
+
+    /// Marker for a commit whose CI checks passed.
+    pub ci_success: &'static str,
+
+    /// Marker for a commit whose CI checks failed.
+    pub ci_failure: &'static str,
+
+    /// Marker for a commit whose CI checks haven't finished yet.
+    pub ci_pending: &'static str,
+
+    /// Marks the end of a line which was truncated to fit the terminal.
+    pub ellipsis: &'static str,
 }
 
 impl Glyphs {
-    /// Make the `Glyphs` object appropriate for `stdout`.
+    /// Make the `Glyphs` object appropriate for `stdout`. ANSI escape codes are
+    /// written only if `stdout` is a terminal, unless overridden by the
+    /// environment (see `ColorSetting::from_env`).
     pub fn detect() -> Self {
-        if console::user_attended() {
+        Glyphs::detect_with_color(ColorSetting::Auto)
+    }
+
+    /// Make the `Glyphs` object appropriate for `stdout`, but only write ANSI
+    /// escape codes if `color` allows it. If `color` is `Auto`, then the
+    /// environment is also taken into account.
+    pub fn detect_with_color(color: ColorSetting) -> Self {
+        let mut glyphs = if console::user_attended() {
             Glyphs::pretty()
         } else {
             Glyphs::text()
+        };
+        match color.resolve_with_env() {
+            ColorSetting::Auto => {}
+            ColorSetting::Always => glyphs.should_write_ansi_escape_codes = true,
+            ColorSetting::Never => glyphs.should_write_ansi_escape_codes = false,
         }
+        glyphs
     }
 
     /// Glyphs used for output to a text file or non-TTY.
@@ -122,6 +426,7 @@ impl Glyphs {
             line_with_offshoot: "|",
             vertical_ellipsis: ":",
             slash: "\\",
+            merge_slash: "/",
             commit_visible: "o",
             commit_visible_head: "@",
             commit_hidden: "x",
@@ -136,6 +441,10 @@ impl Glyphs {
             cycle_vertical_line: "|",
             cycle_upper_left_corner: ",",
             cycle_lower_left_corner: "`",
+            ci_success: "+",
+            ci_failure: "!",
+            ci_pending: "*",
+            ellipsis: "...",
         }
     }
 
@@ -147,6 +456,7 @@ impl Glyphs {
             line_with_offshoot: "┣",
             vertical_ellipsis: "⋮",
             slash: "━┓",
+            merge_slash: "━┛",
             commit_visible: "◯",
             commit_visible_head: "●",
             commit_hidden: "✕",
@@ -161,6 +471,10 @@ impl Glyphs {
             cycle_vertical_line: "│",
             cycle_upper_left_corner: "┌",
             cycle_lower_left_corner: "└",
+            ci_success: "✓",
+            ci_failure: "✗",
+            ci_pending: "●",
+            ellipsis: "…",
         }
     }
 }
@@ -263,6 +577,186 @@ pub fn set_effect(mut string: StyledString, effect: Effect) -> StyledString {
     string
 }
 
+/// Apply the provided style on top of the styles of all the internal spans of
+/// the styled string. Colors set by `style` take precedence.
+pub fn set_style(mut string: StyledString, style: Style) -> StyledString {
+    string.spans_raw_attr_mut().for_each(|span| {
+        *span.attr = span.attr.combine(style);
+    });
+    string
+}
+
+/// Get the number of terminal columns that the provided text takes up when
+/// displayed, which accounts for wide characters (such as CJK characters and
+/// most emoji) and ignores escape codes. Example:
+///
+/// ```
+/// # use branchless::core::formatting::display_width;
+/// assert_eq!(display_width("abc"), 3);
+/// assert_eq!(display_width("修正"), 4);
+/// assert_eq!(display_width("\x1b]8;;https://example.com\x1b\\abc\x1b]8;;\x1b\\"), 3);
+/// ```
+pub fn display_width(text: &str) -> usize {
+    console::measure_text_width(&Hyperlinks::strip(text))
+}
+
+/// Format the given number of seconds since the Unix epoch as a date, such as
+/// `2021-09-30`.
+pub fn format_date(seconds: i64) -> String {
+    // Convert days since the epoch to a civil date, per
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let days = seconds.div_euclid(24 * 60 * 60);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format the given number of seconds since the Unix epoch as a date and a
+/// time of day, such as `2021-09-30 23:59:59`.
+pub fn format_date_time(seconds: i64) -> String {
+    let seconds_of_day = seconds.rem_euclid(24 * 60 * 60);
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(seconds),
+        seconds_of_day / (60 * 60),
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Parse a style in the format of Git's color configuration, such as `bold
+/// green` or `brightred ul`: any of `black`, `red`, `green`, `yellow`, `blue`,
+/// `magenta`, `cyan` or `white` (optionally prefixed with `bright`), `normal`
+/// or `default` for the terminal's default color, and any of the attributes
+/// `bold`, `dim`, `italic`, `ul`/`underline`, `blink` and `reverse`. Example:
+///
+/// ```
+/// # use cursive::theme::{BaseColor, Effect, Style};
+/// # use branchless::core::formatting::parse_style;
+/// let style = parse_style("bold brightgreen")?;
+/// assert_eq!(style, Style::from(BaseColor::Green.light()).combine(Effect::Bold));
+/// assert!(parse_style("chartreuse").is_err());
+/// # Ok::<(), eyre::Error>(())
+/// ```
+pub fn parse_style(value: &str) -> eyre::Result<Style> {
+    use cursive::theme::{BaseColor, Color, ColorStyle, ColorType};
+
+    let mut style = Style::none();
+    for word in value.split_whitespace() {
+        let (is_bright, color_name) = match word.strip_prefix("bright") {
+            Some(color_name) => (true, color_name),
+            None => (false, word),
+        };
+        let base_color = match color_name {
+            "black" => Some(BaseColor::Black),
+            "red" => Some(BaseColor::Red),
+            "green" => Some(BaseColor::Green),
+            "yellow" => Some(BaseColor::Yellow),
+            "blue" => Some(BaseColor::Blue),
+            "magenta" => Some(BaseColor::Magenta),
+            "cyan" => Some(BaseColor::Cyan),
+            "white" => Some(BaseColor::White),
+            _ => None,
+        };
+        if let Some(base_color) = base_color {
+            let color = if is_bright {
+                base_color.light()
+            } else {
+                base_color.dark()
+            };
+            style = style.combine(color);
+            continue;
+        }
+
+        let effect = match word {
+            "normal" | "default" => {
+                style.color =
+                    ColorStyle::new(ColorType::Color(Color::TerminalDefault), style.color.back);
+                continue;
+            }
+            "bold" => Effect::Bold,
+            "dim" => Effect::Dim,
+            "italic" => Effect::Italic,
+            "ul" | "underline" => Effect::Underline,
+            "blink" => Effect::Blink,
+            "reverse" => Effect::Reverse,
+            word => eyre::bail!(
+                "Unknown color or attribute {:?} in style: {:?}",
+                word,
+                value
+            ),
+        };
+        style = style.combine(effect);
+    }
+    Ok(style)
+}
+
+/// Truncate the provided string so that it's at most `max_width` columns wide
+/// when displayed, ending it with `ellipsis` if it was truncated. Hyperlinks
+/// are removed from the part of the string which was cut. Example:
+///
+/// ```
+/// # use cursive::utils::markup::StyledString;
+/// # use branchless::core::formatting::{truncate_styled_string, StyledStringBuilder};
+/// let string = StyledStringBuilder::new()
+///     .append_plain("abc ")
+///     .append_plain("def")
+///     .build();
+/// assert_eq!(truncate_styled_string(string.clone(), 7, "...").source(), "abc def");
+/// assert_eq!(truncate_styled_string(string, 6, "...").source(), "abc...");
+///
+/// // Wide characters aren't split.
+/// let string = StyledString::plain("修正する");
+/// assert_eq!(truncate_styled_string(string, 6, "...").source(), "修...");
+/// ```
+pub fn truncate_styled_string(
+    string: StyledString,
+    max_width: usize,
+    ellipsis: &str,
+) -> StyledString {
+    let span_widths: Vec<usize> = string
+        .spans()
+        .map(|span| display_width(span.content))
+        .collect();
+    if span_widths.iter().sum::<usize>() <= max_width {
+        return string;
+    }
+
+    let ellipsis = if display_width(ellipsis) <= max_width {
+        ellipsis
+    } else {
+        ""
+    };
+    let max_width = max_width - display_width(ellipsis);
+    let mut result = StyledString::new();
+    let mut width = 0;
+    for (span, span_width) in string.spans().zip(span_widths) {
+        if width + span_width <= max_width {
+            result.append_styled(span.content, *span.attr);
+            width += span_width;
+        } else {
+            let content = Hyperlinks::strip(span.content);
+            let content = console::truncate_str(&content, max_width - width, "");
+            result.append_styled(content, *span.attr);
+            break;
+        }
+    }
+    result.append_plain(ellipsis);
+    result
+}
+
 impl From<StyledStringBuilder> for StyledString {
     fn from(builder: StyledStringBuilder) -> Self {
         builder.build()
@@ -326,6 +820,224 @@ fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
     Ok(output.to_string())
 }
 
+/// The default foreground color of rendered HTML, which mimics a dark terminal
+/// theme.
+const HTML_FOREGROUND_COLOR: &str = "#e5e5e5";
+
+/// The default background color of rendered HTML.
+const HTML_BACKGROUND_COLOR: &str = "#1e1e1e";
+
+fn render_color_as_css(color: cursive::theme::ColorType) -> eyre::Result<Option<String>> {
+    use cursive::theme::{BaseColor, Color, ColorType};
+    let color = match color {
+        ColorType::Palette(_) => {
+            eyre::bail!("Not implemented: using cursive palette colors")
+        }
+        ColorType::InheritParent | ColorType::Color(Color::TerminalDefault) => return Ok(None),
+        ColorType::Color(Color::Rgb(r, g, b)) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        ColorType::Color(Color::RgbLowRes(r, g, b)) => {
+            format!("#{:02x}{:02x}{:02x}", r * 51, g * 51, b * 51)
+        }
+        ColorType::Color(Color::Light(color)) => match color {
+            BaseColor::Black => "#7f7f7f",
+            BaseColor::Red => "#ff5555",
+            BaseColor::Green => "#55ff55",
+            BaseColor::Yellow => "#ffff55",
+            BaseColor::Blue => "#5c5cff",
+            BaseColor::Magenta => "#ff55ff",
+            BaseColor::Cyan => "#55ffff",
+            BaseColor::White => "#ffffff",
+        }
+        .to_string(),
+        ColorType::Color(Color::Dark(color)) => match color {
+            BaseColor::Black => "#000000",
+            BaseColor::Red => "#cd3131",
+            BaseColor::Green => "#0dbc79",
+            BaseColor::Yellow => "#e5e510",
+            BaseColor::Blue => "#2472c8",
+            BaseColor::Magenta => "#bc3fbc",
+            BaseColor::Cyan => "#11a8cd",
+            BaseColor::White => HTML_FOREGROUND_COLOR,
+        }
+        .to_string(),
+    };
+    Ok(Some(color))
+}
+
+fn render_style_as_css(style: Style) -> eyre::Result<String> {
+    let Style { effects, color } = style;
+    let mut foreground = render_color_as_css(color.front)?;
+    let mut background = None;
+    let mut declarations = Vec::new();
+    let mut text_decorations = Vec::new();
+    for effect in effects.iter() {
+        match effect {
+            Effect::Simple => {}
+            Effect::Dim => declarations.push("opacity: 0.6".to_string()),
+            Effect::Reverse => {
+                background = Some(
+                    foreground
+                        .take()
+                        .unwrap_or_else(|| HTML_FOREGROUND_COLOR.to_string()),
+                );
+                foreground = Some(HTML_BACKGROUND_COLOR.to_string());
+            }
+            Effect::Bold => declarations.push("font-weight: bold".to_string()),
+            Effect::Italic => declarations.push("font-style: italic".to_string()),
+            Effect::Strikethrough => text_decorations.push("line-through"),
+            Effect::Underline => text_decorations.push("underline"),
+            Effect::Blink => text_decorations.push("blink"),
+        }
+    }
+    if let Some(foreground) = foreground {
+        declarations.insert(0, format!("color: {}", foreground));
+    }
+    if let Some(background) = background {
+        declarations.push(format!("background-color: {}", background));
+    }
+    if !text_decorations.is_empty() {
+        declarations.push(format!("text-decoration: {}", text_decorations.join(" ")));
+    }
+    Ok(declarations.join("; "))
+}
+
+/// Escape the given text for inclusion in HTML. Example:
+///
+/// ```
+/// # use branchless::core::formatting::escape_html;
+/// assert_eq!(escape_html("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+/// ```
+pub fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escape the given text for inclusion in HTML, converting any hyperlinks
+/// rendered by `Hyperlinks::render` into `<a>` elements.
+fn render_text_as_html(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b]8;;") {
+        result.push_str(&escape_html(&rest[..start]));
+        rest = &rest[start + "\x1b]8;;".len()..];
+        let (url, after_url) = match rest.find("\x1b\\") {
+            Some(end) => (&rest[..end], &rest[end + "\x1b\\".len()..]),
+            // The sequence is unterminated, so render the remaining text
+            // without the escape code.
+            None => break,
+        };
+        let (link_text, after_link) = match after_url.find("\x1b]8;;\x1b\\") {
+            Some(end) => (
+                &after_url[..end],
+                &after_url[end + "\x1b]8;;\x1b\\".len()..],
+            ),
+            None => (after_url, ""),
+        };
+        result.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(url),
+            escape_html(link_text)
+        ));
+        rest = after_link;
+    }
+    result.push_str(&escape_html(rest));
+    result
+}
+
+/// Render the provided string as HTML, using inline CSS to style each span.
+/// Adjacent spans with the same style are merged. Hyperlinks are rendered as
+/// `<a>` elements. Example:
+///
+/// ```
+/// # use branchless::core::formatting::{render_styled_string_as_html, StyledStringBuilder};
+/// # use cursive::theme::Effect;
+/// let string = StyledStringBuilder::new()
+///     .append_plain("a < b ")
+///     .append_styled("c", Effect::Bold)
+///     .build();
+/// assert_eq!(
+///     render_styled_string_as_html(&string).unwrap(),
+///     "a &lt; b <span style=\"font-weight: bold\">c</span>"
+/// );
+/// ```
+pub fn render_styled_string_as_html(string: &StyledString) -> eyre::Result<String> {
+    let mut runs: Vec<(String, String)> = Vec::new();
+    for span in string.spans() {
+        let Span {
+            content,
+            attr,
+            width: _,
+        } = span;
+        let css = render_style_as_css(*attr)?;
+        match runs.last_mut() {
+            Some((last_css, last_content)) if *last_css == css => {
+                last_content.push_str(content);
+            }
+            _ => runs.push((css, content.to_string())),
+        }
+    }
+
+    let result = runs
+        .into_iter()
+        .map(|(css, content)| {
+            let text = render_text_as_html(&content);
+            if css.is_empty() {
+                text
+            } else {
+                format!("<span style=\"{}\">{}</span>", css, text)
+            }
+        })
+        .collect();
+    Ok(result)
+}
+
+/// Render the provided lines as an HTML `<pre>` element, suitable for pasting
+/// into a page which accepts raw HTML. The element is styled inline, so it
+/// doesn't depend on any stylesheet.
+pub fn render_lines_as_html_snippet(lines: &[StyledString]) -> eyre::Result<String> {
+    let mut result = format!(
+        "<pre style=\"color: {}; background-color: {}; padding: 1em; \
+font-family: monospace; line-height: 1.2;\">",
+        HTML_FOREGROUND_COLOR, HTML_BACKGROUND_COLOR
+    );
+    for line in lines {
+        result.push_str(&render_styled_string_as_html(line)?);
+        result.push('\n');
+    }
+    result.push_str("</pre>");
+    Ok(result)
+}
+
+/// Render the provided lines as a standalone HTML document with the given
+/// title, which contains the snippet from `render_lines_as_html_snippet`.
+pub fn render_lines_as_html_document(title: &str, lines: &[StyledString]) -> eyre::Result<String> {
+    Ok(format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+</head>
+<body>
+{}
+</body>
+</html>
+",
+        escape_html(title),
+        render_lines_as_html_snippet(lines)?
+    ))
+}
+
 /// Write the provided string to `out`, using ANSI escape codfes as necessary to
 /// style it.
 ///
@@ -348,3 +1060,67 @@ pub fn printable_styled_string(glyphs: &Glyphs, string: StyledString) -> eyre::R
         .collect::<eyre::Result<String>>()?;
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use cursive::theme::{BaseColor, Color};
+
+    use super::*;
+
+    #[test]
+    fn test_color_setting_from_env_values() {
+        let from_env = |no_color: Option<&str>, clicolor_force: Option<&str>| {
+            ColorSetting::from_env_values(no_color.map(OsStr::new), clicolor_force.map(OsStr::new))
+        };
+        assert_eq!(from_env(None, None), None);
+        assert_eq!(from_env(Some("1"), None), Some(ColorSetting::Never));
+        assert_eq!(from_env(Some(""), None), None);
+        assert_eq!(from_env(None, Some("1")), Some(ColorSetting::Always));
+        assert_eq!(from_env(None, Some("0")), None);
+        assert_eq!(from_env(Some("1"), Some("1")), Some(ColorSetting::Never));
+        assert_eq!(from_env(Some(""), Some("1")), Some(ColorSetting::Always));
+    }
+
+    #[test]
+    fn test_render_styled_string_as_html() -> eyre::Result<()> {
+        let string = StyledStringBuilder::new()
+            .append_styled("red", BaseColor::Red.light())
+            .append_plain(" ")
+            .append_styled(
+                "selected",
+                Style::from(Color::Dark(BaseColor::Green)).combine(Effect::Reverse),
+            )
+            .append_plain(" ")
+            .append_plain(Hyperlinks::new(true).render("abc", "https://example.com/?a&b"))
+            .build();
+        assert_eq!(
+            render_styled_string_as_html(&string)?,
+            "<span style=\"color: #ff5555\">red</span> \
+<span style=\"color: #1e1e1e; background-color: #0dbc79\">selected</span> \
+<a href=\"https://example.com/?a&amp;b\">abc</a>"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_text_as_html_unterminated_hyperlink() {
+        assert_eq!(
+            render_text_as_html("a <\x1b]8;;https://example.com/?a&b"),
+            "a &lt;https://example.com/?a&amp;b"
+        );
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951782400), "2000-02-29");
+        assert_eq!(format_date(1633046399), "2021-09-30");
+    }
+
+    #[test]
+    fn test_format_date_time() {
+        assert_eq!(format_date_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_date_time(1633046399), "2021-09-30 23:59:59");
+        assert_eq!(format_date_time(-1), "1969-12-31 23:59:59");
+    }
+}
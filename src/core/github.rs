@@ -0,0 +1,278 @@
+//! Access GitHub via its REST API.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::git::Repo;
+
+/// Extract the owner and name of the repository from the URL of a GitHub
+/// remote, such as `git@github.com:owner/repo.git` or
+/// `https://github.com/owner/repo`.
+fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let mut components = url.rsplit(&['/', ':'][..]);
+    let repo_name = components.next()?;
+    let owner = components.next()?;
+    if owner.is_empty() || repo_name.is_empty() {
+        None
+    } else {
+        Some((owner.to_string(), repo_name.to_string()))
+    }
+}
+
+/// Percent-encode a value for use in the query string of a URL.
+fn encode_query_value(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                result.push(char::from(byte))
+            }
+            byte => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// Get the URL of the GraphQL API corresponding to the given REST API URL. For
+/// GitHub Enterprise Server, the REST API is under `/api/v3` and the GraphQL
+/// API is at `/api/graphql`.
+fn get_graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    match api_url.strip_suffix("/v3") {
+        Some(api_url) => format!("{}/graphql", api_url),
+        None => format!("{}/graphql", api_url),
+    }
+}
+
+/// A minimal client for the GitHub REST API, which uses `curl` to make
+/// requests.
+pub struct GitHubClient {
+    api_url: String,
+    token: String,
+    owner: String,
+    repo_name: String,
+}
+
+impl std::fmt::Debug for GitHubClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<GitHubClient api_url={:?} owner={:?} repo_name={:?} token=not shown>",
+            self.api_url, self.owner, self.repo_name
+        )
+    }
+}
+
+impl GitHubClient {
+    /// Make a client for the GitHub repository which the given remote points
+    /// to. The API token is taken from the `GITHUB_TOKEN` environment variable
+    /// or the `branchless.github.token` config option, and the API URL from
+    /// `branchless.github.apiUrl`.
+    ///
+    /// Returns: The client, or a message explaining why it couldn't be made.
+    pub fn new(repo: &Repo, remote_name: &str) -> eyre::Result<Result<Self, String>> {
+        let config = repo.get_config()?;
+        let api_url = config.get_or(
+            "branchless.github.apiUrl",
+            "https://api.github.com".to_string(),
+        )?;
+        let token = match std::env::var("GITHUB_TOKEN") {
+            Ok(token) => Some(token),
+            Err(_) => config.get("branchless.github.token")?,
+        };
+        let token = match token {
+            Some(token) => token,
+            None => {
+                return Ok(Err(
+                    "No GitHub token found. Set the GITHUB_TOKEN environment variable or the branchless.github.token config option.".to_string()
+                ))
+            }
+        };
+
+        let remote_url_key = format!("remote.{}.url", remote_name);
+        let remote_url: Option<String> = config.get(&remote_url_key)?;
+        let (owner, repo_name) = match remote_url.as_deref().and_then(parse_remote_url) {
+            Some(owner_and_repo_name) => owner_and_repo_name,
+            None => {
+                return Ok(Err(format!(
+                    "Could not determine the GitHub repository for remote {:?} from its URL: {:?}",
+                    remote_name, remote_url
+                )))
+            }
+        };
+
+        Ok(Ok(GitHubClient {
+            api_url,
+            token,
+            owner,
+            repo_name,
+        }))
+    }
+
+    /// Make a request to the given path under the repository's API URL, such
+    /// as `/pulls`.
+    ///
+    /// Returns: The parsed JSON response.
+    #[instrument(skip(body))]
+    pub fn request(&self, method: &str, path: &str, body: Option<&Value>) -> eyre::Result<Value> {
+        let url = format!(
+            "{}/repos/{}/{}{}",
+            self.api_url.trim_end_matches('/'),
+            self.owner,
+            self.repo_name,
+            path
+        );
+        self.request_url(method, &url, body)
+    }
+
+    /// Run the given query or mutation against the GraphQL API, for the
+    /// operations which the REST API doesn't support.
+    ///
+    /// Returns: The `data` field of the parsed JSON response.
+    #[instrument(skip(variables))]
+    pub fn graphql(&self, query: &str, variables: Value) -> eyre::Result<Value> {
+        let body = serde_json::json!({
+            "query": query,
+            "variables": variables,
+        });
+        let mut response =
+            self.request_url("POST", &get_graphql_url(&self.api_url), Some(&body))?;
+        if let Some(errors) = response.get("errors") {
+            eyre::bail!("GraphQL request to GitHub failed: {}", errors);
+        }
+        Ok(response["data"].take())
+    }
+
+    /// Make a request to the given URL.
+    #[instrument(skip(body))]
+    fn request_url(&self, method: &str, url: &str, body: Option<&Value>) -> eyre::Result<Value> {
+        let mut command = Command::new("curl");
+        // Pass the token via the config on stdin rather than as an argument,
+        // so that it's not visible to other processes.
+        command
+            .args([
+                "--config",
+                "-",
+                "--silent",
+                "--show-error",
+                "--request",
+                method,
+                "--header",
+                "Accept: application/vnd.github.v3+json",
+                "--write-out",
+                "\n%{http_code}",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(body) = body {
+            command.args(["--data", &body.to_string()]);
+        }
+        command.arg(url);
+
+        let mut child = command.spawn().wrap_err("Spawning curl")?;
+        {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| eyre::eyre!("Could not open stdin for curl"))?;
+            writeln!(
+                stdin,
+                "header = \"Authorization: token {}\"",
+                self.token.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+        }
+        let output = child.wait_with_output().wrap_err("Waiting for curl")?;
+        if !output.status.success() {
+            eyre::bail!(
+                "Request to GitHub failed: {} {}: {}",
+                method,
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let stdout = String::from_utf8(output.stdout)?;
+        let (response, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        let status: u16 = status
+            .trim()
+            .parse()
+            .wrap_err_with(|| format!("Parsing HTTP status from curl: {:?}", status))?;
+        let response: Value = if response.trim().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(response)
+                .wrap_err_with(|| format!("Parsing response from GitHub: {:?}", response))?
+        };
+        if status >= 400 {
+            eyre::bail!(
+                "Request to GitHub failed with status {}: {} {}: {}",
+                status,
+                method,
+                url,
+                response["message"].as_str().unwrap_or("no message")
+            );
+        }
+        Ok(response)
+    }
+
+    /// Find the open pull request whose head is the given branch, if any.
+    pub fn find_pull_request(&self, branch_name: &str) -> eyre::Result<Option<Value>> {
+        let response = self.request(
+            "GET",
+            &format!(
+                "/pulls?state=open&head={}",
+                encode_query_value(&format!("{}:{}", self.owner, branch_name))
+            ),
+            None,
+        )?;
+        match response {
+            Value::Array(pull_requests) => Ok(pull_requests.into_iter().next()),
+            response => eyre::bail!("Unexpected response from GitHub: {}", response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_url() {
+        assert_eq!(
+            parse_remote_url("git@github.com:arxanas/git-branchless.git"),
+            Some(("arxanas".to_string(), "git-branchless".to_string()))
+        );
+        assert_eq!(
+            parse_remote_url("https://github.com/arxanas/git-branchless"),
+            Some(("arxanas".to_string(), "git-branchless".to_string()))
+        );
+        assert_eq!(parse_remote_url("git-branchless"), None);
+    }
+
+    #[test]
+    fn test_get_graphql_url() {
+        assert_eq!(
+            get_graphql_url("https://api.github.com"),
+            "https://api.github.com/graphql"
+        );
+        assert_eq!(
+            get_graphql_url("https://github.example.com/api/v3/"),
+            "https://github.example.com/api/graphql"
+        );
+    }
+
+    #[test]
+    fn test_encode_query_value() {
+        assert_eq!(
+            encode_query_value("owner:user/foo bar"),
+            "owner%3Auser/foo%20bar"
+        );
+    }
+}
@@ -8,6 +8,7 @@ use std::ops::Deref;
 
 use tracing::{instrument, warn};
 
+use crate::core::config::get_public_branch_patterns;
 use crate::core::eventlog::{CommitVisibility, Event, EventCursor, EventReplayer};
 use crate::core::mergebase::MergeBaseDb;
 use crate::git::{Commit, NonZeroOid, Repo};
@@ -42,6 +43,12 @@ pub struct Node<'repo> {
     /// will hide most nodes from the commit graph, including parent nodes.
     pub parent: Option<NonZeroOid>,
 
+    /// The OIDs of the other parent nodes in the smartlog commit graph, if
+    /// this is a merge commit with more than one of its parents in the graph.
+    /// `parent` is the first of the commit's parents which is in the graph.
+    /// For octopus merges, this holds each of the remaining parents in order.
+    pub merge_parents: Vec<NonZeroOid>,
+
     /// The OIDs of the children nodes in the smartlog commit graph.
     pub children: Vec<NonZeroOid>,
 
@@ -99,6 +106,22 @@ impl<'repo> Deref for CommitGraph<'repo> {
     }
 }
 
+/// Get the OIDs of the commits pointed to by the public branches configured
+/// with `branchless.core.publicBranches`. Like the main branch, these are
+/// treated as boundaries when determining which commits are drafts.
+#[instrument]
+fn get_public_branch_oids(repo: &Repo) -> eyre::Result<HashSet<NonZeroOid>> {
+    let mut result = HashSet::new();
+    for pattern in get_public_branch_patterns(repo)? {
+        for reference in repo.get_references_matching(&pattern)? {
+            if let Some(commit) = reference.peel_to_commit()? {
+                result.insert(commit.get_oid());
+            }
+        }
+    }
+    Ok(result)
+}
+
 /// Find additional commits that should be displayed.
 ///
 /// For example, if you check out a commit that has intermediate parent commits
@@ -113,13 +136,16 @@ fn walk_from_commits<'repo>(
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
     main_branch_oid: &MainBranchOid,
+    public_branch_oids: &HashSet<NonZeroOid>,
     commit_oids: &CommitOids,
 ) -> eyre::Result<CommitGraph<'repo>> {
-    let (effects, _progress) = effects.start_operation(OperationType::WalkCommits);
+    let (effects, progress) = effects.start_operation(OperationType::WalkCommits);
+    progress.notify_progress(0, commit_oids.0.len());
 
     let mut graph: HashMap<NonZeroOid, Node> = Default::default();
 
     for commit_oid in &commit_oids.0 {
+        progress.notify_progress_inc(1);
         let commit = repo.find_commit(*commit_oid)?;
         let current_commit = match commit {
             Some(commit) => commit,
@@ -134,6 +160,20 @@ fn walk_from_commits<'repo>(
             current_commit.get_oid(),
             main_branch_oid.0,
         )?;
+
+        // If the commit has been merged into a public branch, then the path to
+        // the main branch stops at the point where it joins the public branch.
+        let mut public_merge_base_oids = HashSet::new();
+        for public_branch_oid in public_branch_oids {
+            if let Some(public_merge_base_oid) = merge_base_db.get_merge_base_oid(
+                &effects,
+                repo,
+                current_commit.get_oid(),
+                *public_branch_oid,
+            )? {
+                public_merge_base_oids.insert(public_merge_base_oid);
+            }
+        }
         let path_to_merge_base = match merge_base_oid {
             // Occasionally we may find a commit that has no merge-base with the
             // main branch. For example: a rewritten initial commit. This is
@@ -160,6 +200,14 @@ fn walk_from_commits<'repo>(
             }
         };
 
+        let public_merge_base_index = path_to_merge_base
+            .iter()
+            .position(|commit| public_merge_base_oids.contains(&commit.get_oid()));
+        let path_to_merge_base = match public_merge_base_index {
+            Some(index) => path_to_merge_base[..=index].to_vec(),
+            None => path_to_merge_base,
+        };
+
         for current_commit in path_to_merge_base.iter() {
             if graph.contains_key(&current_commit.get_oid()) {
                 // This commit (and all of its parents!) should be in the graph
@@ -177,7 +225,7 @@ fn walk_from_commits<'repo>(
             let is_main = match merge_base_oid {
                 Some(merge_base_oid) => (current_commit.get_oid() == merge_base_oid),
                 None => false,
-            };
+            } || public_merge_base_oids.contains(&current_commit.get_oid());
 
             let event = event_replayer
                 .get_cursor_commit_latest_event(event_cursor, current_commit.get_oid())
@@ -187,6 +235,7 @@ fn walk_from_commits<'repo>(
                 Node {
                     commit: current_commit.clone(),
                     parent: None,
+                    merge_parents: Vec::new(),
                     children: Vec::new(),
                     is_main,
                     is_visible,
@@ -196,7 +245,7 @@ fn walk_from_commits<'repo>(
         }
 
         if let Some(merge_base_oid) = merge_base_oid {
-            if !graph.contains_key(&merge_base_oid) {
+            if !graph.contains_key(&merge_base_oid) && public_merge_base_index.is_none() {
                 warn!(?merge_base_oid, "Could not find merge base OID");
             }
         }
@@ -215,7 +264,11 @@ fn walk_from_commits<'repo>(
         })
         .collect();
     for (child_oid, parent_oid) in links.iter() {
-        graph.get_mut(child_oid).unwrap().parent = Some(*parent_oid);
+        let child_node = graph.get_mut(child_oid).unwrap();
+        match child_node.parent {
+            None => child_node.parent = Some(*parent_oid),
+            Some(_) => child_node.merge_parents.push(*parent_oid),
+        }
         graph.get_mut(parent_oid).unwrap().children.push(*child_oid);
     }
 
@@ -294,14 +347,20 @@ fn do_remove_commits(graph: &mut CommitGraph, head_oid: &HeadOid, branch_oids: &
         .cloned()
         .collect();
 
-    // Actually update the graph and delete any parent-child links, as
-    // appropriate.
-    for oid in all_oids_to_hide {
-        let parent_oid = graph[&oid].parent;
-        graph.nodes.remove(&oid);
-        match parent_oid {
-            Some(parent_oid) if graph.contains_key(&parent_oid) => {
-                let children = &mut graph.nodes.get_mut(&parent_oid).unwrap().children;
+    remove_nodes(graph, all_oids_to_hide);
+}
+
+/// Remove the given commits from the graph, along with the links to them from
+/// their parents. The children of a removed commit must also be removed.
+fn remove_nodes(graph: &mut CommitGraph, oids_to_remove: HashSet<NonZeroOid>) {
+    for oid in oids_to_remove {
+        let node = match graph.nodes.remove(&oid) {
+            Some(node) => node,
+            None => continue,
+        };
+        for parent_oid in node.parent.iter().chain(node.merge_parents.iter()) {
+            if let Some(parent_node) = graph.nodes.get_mut(parent_oid) {
+                let children = &mut parent_node.children;
                 *children = children
                     .iter()
                     .filter_map(|child_oid| {
@@ -313,11 +372,56 @@ fn do_remove_commits(graph: &mut CommitGraph, head_oid: &HeadOid, branch_oids: &
                     })
                     .collect();
             }
-            _ => {}
         }
     }
 }
 
+fn should_filter_out(
+    cache: &mut HashMap<NonZeroOid, bool>,
+    graph: &CommitGraph,
+    oids_to_keep: &HashSet<NonZeroOid>,
+    oid: &NonZeroOid,
+) -> bool {
+    if let Some(result) = cache.get(oid) {
+        return *result;
+    }
+    let result = if oids_to_keep.contains(oid) {
+        false
+    } else {
+        let node = &graph[oid];
+        node.children
+            .iter()
+            // As when hiding commits, the next commit in the main branch
+            // doesn't keep a main branch commit in the graph.
+            .filter(|child_oid| !(node.is_main && graph[child_oid].is_main))
+            .all(|child_oid| should_filter_out(cache, graph, oids_to_keep, child_oid))
+    };
+    cache.insert(*oid, result);
+    result
+}
+
+/// Remove the commits from the graph which aren't in `oids_to_keep`, except
+/// for `HEAD` and the commits needed to connect the kept commits to the main
+/// branch, such as when filtering the smartlog with a revset.
+pub fn filter_graph(
+    graph: &mut CommitGraph,
+    head_oid: &HeadOid,
+    oids_to_keep: &HashSet<NonZeroOid>,
+) {
+    let mut oids_to_keep = oids_to_keep.clone();
+    if let HeadOid(Some(head_oid)) = head_oid {
+        oids_to_keep.insert(*head_oid);
+    }
+
+    let mut cache = HashMap::new();
+    let oids_to_remove: HashSet<NonZeroOid> = graph
+        .keys()
+        .filter(|oid| should_filter_out(&mut cache, graph, &oids_to_keep, oid))
+        .copied()
+        .collect();
+    remove_nodes(graph, oids_to_remove);
+}
+
 /// Construct the smartlog graph for the repo.
 ///
 /// Args:
@@ -325,7 +429,8 @@ fn do_remove_commits(graph: &mut CommitGraph, head_oid: &HeadOid, branch_oids: &
 /// * `merge_base_db`: The merge-base database.
 /// * `event_replayer`: The event replayer.
 /// * `head_oid`: The OID of the repository's `HEAD` reference.
-/// * `main_branch_oid`: The OID of the main branch.
+/// * `main_branch_oid`: The OID of the main branch. The branches configured with
+/// `branchless.core.publicBranches` are treated like the main branch.
 /// * `branch_oids`: The set of OIDs pointed to by branches.
 /// * `hide_commits`: If set to `True`, then, after constructing the graph,
 /// remove nodes from it that appear to be hidden by user activity. This should
@@ -355,6 +460,7 @@ pub fn make_graph<'repo>(
         commit_oids.insert(*head_oid);
     }
     let commit_oids = &CommitOids(commit_oids);
+    let public_branch_oids = get_public_branch_oids(repo)?;
     let mut graph = walk_from_commits(
         &effects,
         repo,
@@ -362,6 +468,7 @@ pub fn make_graph<'repo>(
         event_replayer,
         event_cursor,
         main_branch_oid,
+        &public_branch_oids,
         commit_oids,
     )?;
     sort_children(&mut graph);
@@ -96,6 +96,80 @@ impl<'conn> SqliteMergeBaseDb<'conn> {
     }
 }
 
+/// Find entries in the on-disk merge-base cache which refer to commits that no
+/// longer exist in the repository (such as after they were garbage-collected).
+/// If `should_remove` is set, remove those entries from the cache.
+///
+/// Returns: The number of such entries.
+#[instrument]
+pub fn prune_dangling_merge_base_entries(
+    repo: &Repo,
+    conn: &rusqlite::Connection,
+    should_remove: bool,
+) -> eyre::Result<usize> {
+    init_tables(conn).wrap_err("Initializing tables")?;
+
+    let mut stmt = conn.prepare(
+        "
+SELECT lhs_oid, rhs_oid, merge_base_oid
+FROM merge_base_oids
+",
+    )?;
+    let entries: rusqlite::Result<Vec<(String, String, Option<String>)>> = stmt
+        .query_map(rusqlite::params![], |row| {
+            Ok((
+                row.get("lhs_oid")?,
+                row.get("rhs_oid")?,
+                row.get("merge_base_oid")?,
+            ))
+        })?
+        .collect();
+    let entries = entries.wrap_err("Querying merge-base DB")?;
+
+    let mut dangling_entries = Vec::new();
+    for (lhs_oid, rhs_oid, merge_base_oid) in entries {
+        let mut is_dangling = false;
+        for oid in [Some(&lhs_oid), Some(&rhs_oid), merge_base_oid.as_ref()]
+            .iter()
+            .flatten()
+        {
+            let oid: NonZeroOid = match oid.parse() {
+                Ok(oid) => oid,
+                Err(_) => {
+                    is_dangling = true;
+                    break;
+                }
+            };
+            if repo.find_commit(oid)?.is_none() {
+                is_dangling = true;
+                break;
+            }
+        }
+        if is_dangling {
+            dangling_entries.push((lhs_oid, rhs_oid));
+        }
+    }
+
+    if should_remove {
+        for (lhs_oid, rhs_oid) in dangling_entries.iter() {
+            conn.execute(
+                "
+DELETE FROM merge_base_oids
+WHERE lhs_oid = :lhs_oid
+  AND rhs_oid = :rhs_oid
+",
+                rusqlite::named_params! {
+                    ":lhs_oid": lhs_oid,
+                    ":rhs_oid": rhs_oid,
+                },
+            )
+            .wrap_err("Removing dangling merge-base entry")?;
+        }
+    }
+
+    Ok(dangling_entries.len())
+}
+
 fn find_path_to_merge_base_internal<'repo>(
     effects: &Effects,
     repo: &'repo Repo,
@@ -243,20 +317,44 @@ pub fn make_merge_base_db(
     _conn: &rusqlite::Connection,
     event_replayer: &EventReplayer,
 ) -> eyre::Result<crate::git::Dag> {
-    crate::git::Dag::open(effects, repo, event_replayer)
+    let (effects, _progress) = effects.start_operation(OperationType::MakeMergeBaseDb);
+    crate::git::Dag::open(&effects, repo, event_replayer)
 }
 
 /// Instantiate a `MergeBaseDb` based on the requested compile-time feature.
 #[cfg(not(feature = "eden-dag"))]
 pub fn make_merge_base_db<'conn>(
-    _effects: &Effects,
+    effects: &Effects,
     _repo: &Repo,
     conn: &'conn rusqlite::Connection,
     _event_replayer: &EventReplayer,
 ) -> eyre::Result<SqliteMergeBaseDb<'conn>> {
+    let (_effects, _progress) = effects.start_operation(OperationType::MakeMergeBaseDb);
     Ok(SqliteMergeBaseDb::new(conn)?)
 }
 
+/// Discard all cached merge-base information, including the on-disk commit
+/// DAG, and rebuild it from the repository and the event log. This can be used
+/// to recover from a corrupted cache.
+#[instrument]
+pub fn rebuild_merge_base_db(
+    effects: &Effects,
+    repo: &Repo,
+    conn: &rusqlite::Connection,
+    event_replayer: &EventReplayer,
+) -> eyre::Result<()> {
+    init_tables(conn).wrap_err("Initializing tables")?;
+    conn.execute("DELETE FROM merge_base_oids", rusqlite::params![])
+        .wrap_err("Clearing merge-base DB")?;
+
+    let dag_dir = repo.get_dag_dir()?;
+    std::fs::remove_dir_all(&dag_dir)
+        .wrap_err_with(|| format!("Removing DAG directory at: {:?}", &dag_dir))?;
+
+    make_merge_base_db(effects, repo, conn, event_replayer)?;
+    Ok(())
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -4,27 +4,33 @@
 //! These are rendered inline in the smartlog, between the commit hash and the
 //! commit message.
 
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::ffi::{OsStr, OsString};
 use std::ops::Add;
+use std::rc::Rc;
 use std::time::{Duration, SystemTime};
 
-use cursive::theme::BaseColor;
+use cursive::theme::Style;
 use cursive::utils::markup::StyledString;
 use lazy_static::lazy_static;
 use regex::Regex;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::core::config::{
-    get_commit_metadata_branches, get_commit_metadata_differential_revision,
-    get_commit_metadata_relative_time,
+    get_commit_metadata_branches, get_commit_metadata_ci_status,
+    get_commit_metadata_differential_revision, get_commit_metadata_differential_revision_status,
+    get_commit_metadata_relative_time, get_palette,
 };
-use crate::git::{CategorizedReferenceName, Commit, NonZeroOid, Repo};
+use crate::git::{CategorizedReferenceName, Commit, MaybeZeroOid, NonZeroOid, Repo};
 
-use super::eventlog::{Event, EventCursor, EventReplayer};
-use super::formatting::StyledStringBuilder;
+use super::anonymize::Anonymizer;
+use super::ci_status::{CiStatus, CiStatusFetcher};
+use super::eventlog::{ChangeVersion, Event, EventCursor, EventLogDb, EventReplayer};
+use super::formatting::{Glyphs, Hyperlinks, Palette, StyledStringBuilder};
 use super::graph::CommitGraph;
+use super::phabricator::ConduitClient;
 use super::rewrite::find_rewrite_target;
 
 /// Interface to display information about a commit in the smartlog.
@@ -56,22 +62,58 @@ pub fn render_commit_metadata(
 #[derive(Debug)]
 pub struct CommitOidProvider {
     use_color: bool,
+    hyperlinks: Hyperlinks,
+    url_template: Option<String>,
+    anonymizer: Option<Rc<RefCell<Anonymizer>>>,
 }
 
 impl CommitOidProvider {
     /// Constructor.
     pub fn new(use_color: bool) -> eyre::Result<Self> {
-        Ok(CommitOidProvider { use_color })
+        Ok(CommitOidProvider {
+            use_color,
+            hyperlinks: Hyperlinks::default(),
+            url_template: None,
+            anonymizer: None,
+        })
+    }
+
+    /// Display pseudo-hashes from the given anonymizer instead of the real
+    /// commit hashes. Hyperlinks aren't rendered in that case.
+    pub fn with_anonymizer(self, anonymizer: Rc<RefCell<Anonymizer>>) -> Self {
+        CommitOidProvider {
+            anonymizer: Some(anonymizer),
+            ..self
+        }
+    }
+
+    /// Render commit hashes as hyperlinks to the given URL template, where
+    /// `{oid}` is replaced with the full commit hash.
+    pub fn with_hyperlinks(self, hyperlinks: Hyperlinks, url_template: Option<String>) -> Self {
+        CommitOidProvider {
+            hyperlinks,
+            url_template,
+            ..self
+        }
     }
 }
 
 impl CommitMetadataProvider for CommitOidProvider {
     #[instrument]
     fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
-        let oid = commit.get_oid();
-        let oid = &oid.to_string()[..8];
+        let oid = match &self.anonymizer {
+            Some(anonymizer) => anonymizer.borrow_mut().anonymize_oid(commit.get_oid()),
+            None => commit.get_oid().to_string(),
+        };
+        let oid = match &self.url_template {
+            Some(_) if self.anonymizer.is_some() => oid[..8].to_string(),
+            Some(url_template) => self
+                .hyperlinks
+                .render(&oid[..8], &url_template.replace("{oid}", &oid)),
+            None => oid[..8].to_string(),
+        };
         let oid = if self.use_color {
-            StyledString::styled(oid, BaseColor::Yellow.dark())
+            StyledString::styled(oid, Palette::current().oid)
         } else {
             StyledString::plain(oid)
         };
@@ -81,18 +123,33 @@ impl CommitMetadataProvider for CommitOidProvider {
 
 /// Display the first line of the commit message.
 #[derive(Debug)]
-pub struct CommitMessageProvider;
+pub struct CommitMessageProvider {
+    anonymizer: Option<Rc<RefCell<Anonymizer>>>,
+}
 
 impl CommitMessageProvider {
     /// Constructor.
     pub fn new() -> eyre::Result<Self> {
-        Ok(CommitMessageProvider)
+        Ok(CommitMessageProvider { anonymizer: None })
+    }
+
+    /// Display placeholders from the given anonymizer instead of the commit
+    /// messages.
+    pub fn with_anonymizer(self, anonymizer: Rc<RefCell<Anonymizer>>) -> Self {
+        CommitMessageProvider {
+            anonymizer: Some(anonymizer),
+        }
     }
 }
 
 impl CommitMetadataProvider for CommitMessageProvider {
     #[instrument]
     fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        if let Some(anonymizer) = &self.anonymizer {
+            return Ok(Some(StyledString::plain(
+                anonymizer.borrow_mut().anonymize_message(commit.get_oid()),
+            )));
+        }
         Ok(Some(StyledString::plain(
             commit.get_summary()?.to_string_lossy(),
         )))
@@ -104,6 +161,7 @@ pub struct HiddenExplanationProvider<'a> {
     graph: &'a CommitGraph<'a>,
     event_replayer: &'a EventReplayer,
     event_cursor: EventCursor,
+    anonymizer: Option<Rc<RefCell<Anonymizer>>>,
 }
 
 impl<'a> HiddenExplanationProvider<'a> {
@@ -117,8 +175,18 @@ impl<'a> HiddenExplanationProvider<'a> {
             graph,
             event_replayer,
             event_cursor,
+            anonymizer: None,
         })
     }
+
+    /// Refer to the commits which hidden commits were rewritten as by
+    /// pseudo-hashes from the given anonymizer.
+    pub fn with_anonymizer(self, anonymizer: Rc<RefCell<Anonymizer>>) -> Self {
+        HiddenExplanationProvider {
+            anonymizer: Some(anonymizer),
+            ..self
+        }
+    }
 }
 
 impl<'a> CommitMetadataProvider for HiddenExplanationProvider<'a> {
@@ -141,31 +209,182 @@ impl<'a> CommitMetadataProvider for HiddenExplanationProvider<'a> {
                     commit.get_oid(),
                 );
                 rewrite_target.map(|rewritten_oid| {
+                    let rewritten_oid = match (&self.anonymizer, rewritten_oid) {
+                        (Some(anonymizer), MaybeZeroOid::NonZero(rewritten_oid)) => {
+                            anonymizer.borrow_mut().anonymize_oid(rewritten_oid)
+                        }
+                        (_, rewritten_oid) => rewritten_oid.to_string(),
+                    };
                     StyledString::styled(
-                        format!("(rewritten as {})", &rewritten_oid.to_string()[..8]),
-                        BaseColor::Black.light(),
+                        format!("(rewritten as {})", &rewritten_oid[..8]),
+                        Palette::current().annotation,
                     )
                 })
             }
 
-            Event::HideEvent { .. } => Some(StyledString::styled(
+            Event::HideEvent {
+                reason: Some(reason),
+                ..
+            } => Some(StyledString::styled(
+                format!("(hidden: {})", reason),
+                Palette::current().annotation,
+            )),
+
+            Event::HideEvent { reason: None, .. } => Some(StyledString::styled(
                 "(manually hidden)",
-                BaseColor::Black.light(),
+                Palette::current().annotation,
             )),
 
             Event::RefUpdateEvent { .. }
             | Event::CommitEvent { .. }
-            | Event::UnhideEvent { .. } => None,
+            | Event::UnhideEvent { .. }
+            | Event::StashPushEvent { .. }
+            | Event::StashDropEvent { .. }
+            | Event::CopyEvent { .. } => None,
         };
         Ok(result)
     }
 }
 
-/// Display branches that point to a given commit.
+/// For draft commits, display which version of its change the commit is.
+pub struct ChangeIdProvider<'a> {
+    is_enabled: bool,
+    graph: &'a CommitGraph<'a>,
+    event_replayer: &'a EventReplayer,
+    event_cursor: EventCursor,
+}
+
+impl<'a> ChangeIdProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        is_enabled: bool,
+        graph: &'a CommitGraph,
+        event_replayer: &'a EventReplayer,
+        event_cursor: EventCursor,
+    ) -> eyre::Result<Self> {
+        Ok(ChangeIdProvider {
+            is_enabled,
+            graph,
+            event_replayer,
+            event_cursor,
+        })
+    }
+}
+
+impl<'a> CommitMetadataProvider for ChangeIdProvider<'a> {
+    fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let commit_oid = commit.get_oid();
+        match self.graph.get(&commit_oid) {
+            Some(node) if !node.is_main => {}
+            _ => return Ok(None),
+        };
+
+        let ChangeVersion { change_id, version } = self
+            .event_replayer
+            .get_cursor_change_version(self.event_cursor, commit_oid);
+        Ok(Some(StyledString::styled(
+            format!("(v{} of {})", version, change_id.to_short_string()),
+            Palette::current().annotation,
+        )))
+    }
+}
+
+/// For draft commits, display the transaction which created or last affected
+/// the commit, along with when it happened.
+pub struct TransactionProvider<'a> {
+    is_enabled: bool,
+    show_relative_time: bool,
+    now: SystemTime,
+    graph: &'a CommitGraph<'a>,
+    event_log_db: &'a EventLogDb<'a>,
+    event_replayer: &'a EventReplayer,
+    event_cursor: EventCursor,
+}
+
+impl<'a> TransactionProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        is_enabled: bool,
+        repo: &Repo,
+        now: SystemTime,
+        graph: &'a CommitGraph,
+        event_log_db: &'a EventLogDb,
+        event_replayer: &'a EventReplayer,
+        event_cursor: EventCursor,
+    ) -> eyre::Result<Self> {
+        let show_relative_time = get_commit_metadata_relative_time(repo)?;
+        Ok(TransactionProvider {
+            is_enabled,
+            show_relative_time,
+            now,
+            graph,
+            event_log_db,
+            event_replayer,
+            event_cursor,
+        })
+    }
+}
+
+impl<'a> CommitMetadataProvider for TransactionProvider<'a> {
+    fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let commit_oid = commit.get_oid();
+        match self.graph.get(&commit_oid) {
+            Some(node) if !node.is_main => {}
+            _ => return Ok(None),
+        };
+
+        let event = match self
+            .event_replayer
+            .get_cursor_commit_latest_event(self.event_cursor, commit_oid)
+        {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+
+        let event_tx_id = event.get_event_tx_id();
+        let description = match self.event_log_db.get_transaction_command(event_tx_id)? {
+            Some(command) => format!("git {}", command.args.join(" ")),
+            None => match self.event_log_db.get_transaction_message(event_tx_id)? {
+                Some(message) => message,
+                None => return Ok(None),
+            },
+        };
+
+        let text = if self.show_relative_time {
+            format!(
+                "(tx {}: {}, {} ago)",
+                event_tx_id.to_string(),
+                description,
+                RelativeTimeProvider::describe_time_delta(self.now, event.get_timestamp())?
+            )
+        } else {
+            format!("(tx {}: {})", event_tx_id.to_string(), description)
+        };
+        Ok(Some(StyledString::styled(
+            text,
+            Palette::current().annotation,
+        )))
+    }
+}
+
+/// Display branches that point to a given commit. The currently checked-out
+/// branch, other local branches, remote branches and other references are each
+/// styled differently; see `get_palette`.
 #[derive(Debug)]
 pub struct BranchesProvider<'a> {
     is_enabled: bool,
     branch_oid_to_names: &'a HashMap<NonZeroOid, HashSet<OsString>>,
+    head_reference_name: Option<OsString>,
+    palette: Palette,
+    anonymizer: Option<Rc<RefCell<Anonymizer>>>,
 }
 
 impl<'a> BranchesProvider<'a> {
@@ -175,11 +394,57 @@ impl<'a> BranchesProvider<'a> {
         branch_oid_to_names: &'a HashMap<NonZeroOid, HashSet<OsString>>,
     ) -> eyre::Result<Self> {
         let is_enabled = get_commit_metadata_branches(repo)?;
+        let head_reference_name = repo
+            .get_head_info()?
+            .get_branch_name()
+            .map(|branch_name| OsString::from(format!("refs/heads/{}", branch_name)));
         Ok(BranchesProvider {
             is_enabled,
             branch_oid_to_names,
+            head_reference_name,
+            palette: get_palette(repo)?,
+            anonymizer: None,
         })
     }
+
+    /// Display placeholders from the given anonymizer instead of the branch
+    /// names.
+    pub fn with_anonymizer(self, anonymizer: Rc<RefCell<Anonymizer>>) -> Self {
+        BranchesProvider {
+            anonymizer: Some(anonymizer),
+            ..self
+        }
+    }
+
+    fn describe_branch(&self, branch_name: &OsStr) -> (String, Style) {
+        let is_head = self.head_reference_name.as_deref() == Some(branch_name);
+        let anonymized_branch_name = self.anonymizer.as_ref().map(|anonymizer| {
+            OsString::from(anonymizer.borrow_mut().anonymize_ref_name(branch_name))
+        });
+        let branch_name = anonymized_branch_name.as_deref().unwrap_or(branch_name);
+        match CategorizedReferenceName::new(branch_name) {
+            reference_name @ CategorizedReferenceName::LocalBranch { .. } => {
+                let style = if is_head {
+                    self.palette.branch_head
+                } else {
+                    self.palette.branch_local
+                };
+                (reference_name.render_suffix(), style)
+            }
+            reference_name @ CategorizedReferenceName::RemoteBranch { .. } => (
+                format!("remote {}", reference_name.render_suffix()),
+                self.palette.branch_remote,
+            ),
+            reference_name @ CategorizedReferenceName::Tag { .. } => (
+                format!("tag {}", reference_name.render_suffix()),
+                self.palette.branch_other,
+            ),
+            reference_name @ CategorizedReferenceName::OtherRef { .. } => (
+                format!("ref {}", reference_name.render_suffix()),
+                self.palette.branch_other,
+            ),
+        }
+    }
 }
 
 impl<'a> CommitMetadataProvider for BranchesProvider<'a> {
@@ -200,63 +465,185 @@ impl<'a> CommitMetadataProvider for BranchesProvider<'a> {
         if branch_names.is_empty() {
             Ok(None)
         } else {
-            let mut branch_names: Vec<String> = branch_names
+            let mut branch_descriptions: Vec<(String, Style)> = branch_names
                 .into_iter()
-                .map(
-                    |branch_name| match CategorizedReferenceName::new(branch_name) {
-                        reference_name @ CategorizedReferenceName::LocalBranch { .. } => {
-                            reference_name.render_suffix()
-                        }
-                        reference_name @ CategorizedReferenceName::RemoteBranch { .. } => {
-                            format!("remote {}", reference_name.render_suffix())
-                        }
-                        reference_name @ CategorizedReferenceName::OtherRef { .. } => {
-                            format!("ref {}", reference_name.render_suffix())
-                        }
-                    },
-                )
+                .map(|branch_name| self.describe_branch(branch_name))
                 .collect();
-            branch_names.sort_unstable();
-            let result = StyledString::styled(
-                format!("({})", branch_names.join(", ")),
-                BaseColor::Green.light(),
-            );
+            branch_descriptions.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+            let punctuation_style = self.palette.branch_punctuation;
+            let mut result = StyledStringBuilder::new().append_styled("(", punctuation_style);
+            for (i, (description, style)) in branch_descriptions.into_iter().enumerate() {
+                if i > 0 {
+                    result = result.append_styled(", ", punctuation_style);
+                }
+                result = result.append_styled(description, style);
+            }
+            let result = result.append_styled(")", punctuation_style).build();
             Ok(Some(result))
         }
     }
 }
 
-/// Display the associated Phabricator revision for a given commit.
+/// Display the CI status of commits which have a branch pointing to them.
+#[derive(Debug)]
+pub struct CiStatusProvider<'a> {
+    glyphs: &'a Glyphs,
+    fetcher: Option<CiStatusFetcher<'a>>,
+    branch_oid_to_names: &'a HashMap<NonZeroOid, HashSet<OsString>>,
+}
+
+impl<'a> CiStatusProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        glyphs: &'a Glyphs,
+        repo: &Repo,
+        conn: &'a rusqlite::Connection,
+        branch_oid_to_names: &'a HashMap<NonZeroOid, HashSet<OsString>>,
+    ) -> eyre::Result<Self> {
+        let fetcher = if get_commit_metadata_ci_status(repo)? {
+            Some(CiStatusFetcher::new(repo, conn, SystemTime::now())?)
+        } else {
+            None
+        };
+        Ok(CiStatusProvider {
+            glyphs,
+            fetcher,
+            branch_oid_to_names,
+        })
+    }
+}
+
+impl<'a> CommitMetadataProvider for CiStatusProvider<'a> {
+    #[instrument]
+    fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        let fetcher = match &self.fetcher {
+            Some(fetcher) => fetcher,
+            None => return Ok(None),
+        };
+        if !self.branch_oid_to_names.contains_key(&commit.get_oid()) {
+            return Ok(None);
+        }
+
+        let result = match fetcher.get_status(commit.get_oid())? {
+            Some(CiStatus::Success) => {
+                StyledString::styled(self.glyphs.ci_success, Palette::current().success)
+            }
+            Some(CiStatus::Failure) => {
+                StyledString::styled(self.glyphs.ci_failure, Palette::current().error)
+            }
+            Some(CiStatus::Pending) => {
+                StyledString::styled(self.glyphs.ci_pending, Palette::current().warning)
+            }
+            None => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Display the associated Phabricator revision for a given commit, and
+/// optionally its review status.
 #[derive(Debug)]
 pub struct DifferentialRevisionProvider {
     is_enabled: bool,
+    revision_statuses: HashMap<String, String>,
+    hyperlinks: Hyperlinks,
 }
 
 impl DifferentialRevisionProvider {
-    /// Constructor.
-    pub fn new(repo: &Repo) -> eyre::Result<Self> {
+    /// Constructor. If status display is enabled, the statuses of the
+    /// revisions of all the commits in the graph are fetched up front with a
+    /// single query, rather than with one query per commit.
+    pub fn new(repo: &Repo, graph: &CommitGraph) -> eyre::Result<Self> {
         let is_enabled = get_commit_metadata_differential_revision(repo)?;
-        Ok(DifferentialRevisionProvider { is_enabled })
+        let conduit_client =
+            if is_enabled && get_commit_metadata_differential_revision_status(repo)? {
+                Some(ConduitClient::new(repo)?)
+            } else {
+                None
+            };
+        let revision_statuses = match &conduit_client {
+            Some(conduit_client) => Self::query_revision_statuses(conduit_client, graph)?,
+            None => HashMap::new(),
+        };
+        Ok(DifferentialRevisionProvider {
+            is_enabled,
+            revision_statuses,
+            hyperlinks: Hyperlinks::default(),
+        })
+    }
+
+    /// Render revision numbers as hyperlinks to the revision's URL, if it's
+    /// present in the commit message.
+    pub fn with_hyperlinks(self, hyperlinks: Hyperlinks) -> Self {
+        DifferentialRevisionProvider { hyperlinks, ..self }
+    }
+
+    /// Get the review statuses of the revisions of the commits in the graph.
+    /// Failures are logged rather than returned, so that an unreachable
+    /// Phabricator instance doesn't prevent rendering the smartlog.
+    fn query_revision_statuses(
+        conduit_client: &ConduitClient,
+        graph: &CommitGraph,
+    ) -> eyre::Result<HashMap<String, String>> {
+        let mut diff_numbers = Vec::new();
+        for node in graph.values() {
+            let message = node.commit.get_message_raw()?;
+            if let Some(diff_number) = extract_diff_number(&message.to_string_lossy()) {
+                diff_numbers.push(diff_number);
+            }
+        }
+        if diff_numbers.is_empty() {
+            return Ok(HashMap::new());
+        }
+        diff_numbers.sort_unstable();
+        diff_numbers.dedup();
+
+        match conduit_client.query_revision_statuses(&diff_numbers) {
+            Ok(statuses) => Ok(statuses),
+            Err(err) => {
+                warn!(?err, ?diff_numbers, "Could not query revision statuses");
+                Ok(HashMap::new())
+            }
+        }
     }
 }
 
-fn extract_diff_number(message: &str) -> Option<String> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(
-            r"(?mx)
+lazy_static! {
+    static ref DIFFERENTIAL_REVISION_RE: Regex = Regex::new(
+        r"(?mx)
 ^
 Differential[\ ]Revision:[\ ]
-    (.+ /)?
-    (?P<diff>D[0-9]+)
+    (?P<url>
+        (.+ /)?
+        (?P<diff>D[0-9]+)
+    )
 $",
-        )
-        .expect("Failed to compile DifferentialRevisionProvider regex");
-    }
-    let captures = RE.captures(message)?;
+    )
+    .expect("Failed to compile DifferentialRevisionProvider regex");
+}
+
+/// Extract the number of the Phabricator revision (such as `D123`) from the
+/// `Differential Revision` trailer of the given commit message.
+pub fn extract_diff_number(message: &str) -> Option<String> {
+    let captures = DIFFERENTIAL_REVISION_RE.captures(message)?;
     let diff_number = &captures["diff"];
     Some(diff_number.to_owned())
 }
 
+/// Extract the URL of the Phabricator revision from the `Differential
+/// Revision` trailer of the given commit message, if it has one rather than
+/// just the revision number.
+pub fn extract_diff_url(message: &str) -> Option<String> {
+    let captures = DIFFERENTIAL_REVISION_RE.captures(message)?;
+    let url = &captures["url"];
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Some(url.to_owned())
+    } else {
+        None
+    }
+}
+
 impl CommitMetadataProvider for DifferentialRevisionProvider {
     #[instrument]
     fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
@@ -264,11 +651,28 @@ impl CommitMetadataProvider for DifferentialRevisionProvider {
             return Ok(None);
         }
 
-        let diff_number = match extract_diff_number(&commit.get_message_raw()?.to_string_lossy()) {
+        let message = commit.get_message_raw()?.to_string_lossy().into_owned();
+        let diff_number = match extract_diff_number(&message) {
             Some(diff_number) => diff_number,
             None => return Ok(None),
         };
-        let result = StyledString::styled(diff_number, BaseColor::Green.dark());
+        let status = self.revision_statuses.get(&diff_number).cloned();
+        let diff_number = match extract_diff_url(&message) {
+            Some(url) => self.hyperlinks.render(&diff_number, &url),
+            None => diff_number,
+        };
+        let palette = Palette::current();
+        let result = match status {
+            Some(status) => StyledString::styled(
+                format!("{} ({})", diff_number, status),
+                match status.as_str() {
+                    "needs-revision" => palette.revision_needs_revision,
+                    "accepted" | "published" => palette.success,
+                    _ => palette.revision,
+                },
+            ),
+            None => StyledString::styled(diff_number, palette.revision),
+        };
         Ok(Some(result))
     }
 }
@@ -338,15 +742,181 @@ impl CommitMetadataProvider for RelativeTimeProvider {
         let previous_time = SystemTime::UNIX_EPOCH
             .add(Duration::from_secs(commit.get_time().seconds().try_into()?));
         let description = Self::describe_time_delta(self.now, previous_time)?;
-        let result = StyledString::styled(description, BaseColor::Green.dark());
+        let result = StyledString::styled(description, Palette::current().relative_time);
         Ok(Some(result))
     }
 }
 
+/// A piece of a parsed template; see `TemplateProvider`.
+#[derive(Debug, PartialEq, Eq)]
+enum TemplateItem {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Add the given literal text to the parsed template. Leading and trailing
+/// whitespace is split into its own item, so that it can be treated as a
+/// separator.
+fn push_literal(items: &mut Vec<TemplateItem>, literal: &str) {
+    let content = literal.trim();
+    if content.is_empty() {
+        if !literal.is_empty() {
+            items.push(TemplateItem::Literal(literal.to_string()));
+        }
+        return;
+    }
+
+    let content_start = literal.len() - literal.trim_start().len();
+    let content_end = content_start + content.len();
+    if content_start > 0 {
+        items.push(TemplateItem::Literal(literal[..content_start].to_string()));
+    }
+    items.push(TemplateItem::Literal(content.to_string()));
+    if content_end < literal.len() {
+        items.push(TemplateItem::Literal(literal[content_end..].to_string()));
+    }
+}
+
+/// Parse a template such as `%h %d %s` into literal text and the names of the
+/// providers for its placeholders.
+fn parse_template(template: &str) -> eyre::Result<Vec<TemplateItem>> {
+    let mut items = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+
+        let name = match chars.next() {
+            Some('%') => {
+                literal.push('%');
+                continue;
+            }
+            Some('h') => "oid".to_string(),
+            Some('d') => "branches".to_string(),
+            Some('s') => "message".to_string(),
+            Some('a') if chars.peek() == Some(&'r') => {
+                chars.next();
+                "relative-time".to_string()
+            }
+            Some('(') => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some(')') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            eyre::bail!("Unterminated placeholder in template: %({}", placeholder)
+                        }
+                    }
+                }
+                match placeholder.strip_prefix("provider:") {
+                    Some(name) => name.to_string(),
+                    None => eyre::bail!("Unknown placeholder in template: %({})", placeholder),
+                }
+            }
+            Some(c) => eyre::bail!("Unknown placeholder in template: %{}", c),
+            None => eyre::bail!("Unterminated placeholder at the end of template"),
+        };
+        push_literal(&mut items, &std::mem::take(&mut literal));
+        items.push(TemplateItem::Placeholder(name));
+    }
+    push_literal(&mut items, &literal);
+    Ok(items)
+}
+
+/// Display commits according to a template, such as `%h %d %s`, where each
+/// placeholder is rendered by another provider. The supported placeholders
+/// are `%h` (the commit hash), `%d` (its branches), `%s` (its summary), `%ar`
+/// (its age), `%%` (a literal `%`), and `%(provider:<name>)` for any provider.
+///
+/// Placeholders which don't apply to a commit are omitted, along with the
+/// whitespace separating them from the rest of the line.
+pub struct TemplateProvider<'a> {
+    items: Vec<(TemplateItem, Option<Box<dyn CommitMetadataProvider + 'a>>)>,
+}
+
+impl std::fmt::Debug for TemplateProvider<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let items: Vec<&TemplateItem> = self.items.iter().map(|(item, _)| item).collect();
+        write!(f, "<TemplateProvider items={:?}>", items)
+    }
+}
+
+impl<'a> TemplateProvider<'a> {
+    /// Constructor. `make_provider` is called with the name of each provider
+    /// used in the template, and returns `None` if there's no such provider.
+    pub fn new(
+        template: &str,
+        mut make_provider: impl FnMut(
+            &str,
+        )
+            -> eyre::Result<Option<Box<dyn CommitMetadataProvider + 'a>>>,
+    ) -> eyre::Result<Self> {
+        let mut items = Vec::new();
+        for item in parse_template(template)? {
+            let provider = match &item {
+                TemplateItem::Literal(_) => None,
+                TemplateItem::Placeholder(name) => match make_provider(name)? {
+                    Some(provider) => Some(provider),
+                    None => eyre::bail!("Unknown provider in template: {:?}", name),
+                },
+            };
+            items.push((item, provider));
+        }
+        Ok(TemplateProvider { items })
+    }
+}
+
+impl<'a> CommitMetadataProvider for TemplateProvider<'a> {
+    #[instrument]
+    fn describe_commit(&mut self, commit: &Commit) -> eyre::Result<Option<StyledString>> {
+        let mut result = StyledStringBuilder::new();
+        let mut is_empty = true;
+        let mut pending_separator: Option<&str> = None;
+        for (item, provider) in self.items.iter_mut() {
+            let description = match (&*item, provider) {
+                (TemplateItem::Literal(text), _) if text.trim().is_empty() => {
+                    if !is_empty {
+                        pending_separator.get_or_insert(text);
+                    }
+                    continue;
+                }
+                (TemplateItem::Literal(text), _) => StyledString::plain(text.as_str()),
+                (TemplateItem::Placeholder(_), Some(provider)) => {
+                    match provider.describe_commit(commit)? {
+                        Some(description) => description,
+                        None => continue,
+                    }
+                }
+                (TemplateItem::Placeholder(name), None) => {
+                    eyre::bail!("BUG: No provider for placeholder: {:?}", name)
+                }
+            };
+            if let Some(separator) = pending_separator.take() {
+                result = result.append_plain(separator);
+            }
+            result = result.append(description);
+            is_empty = false;
+        }
+
+        if is_empty {
+            Ok(None)
+        } else {
+            Ok(Some(result.build()))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::Sub;
 
+    use crate::core::formatting::parse_style;
+    use crate::testing::make_git;
+
     use super::*;
 
     #[test]
@@ -403,4 +973,72 @@ Differential Revision: phabricator.com/D123";
 
         Ok(())
     }
+
+    #[test]
+    fn test_branches_provider_styles() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.run(&["config", "branchless.color.branch.remote", "dim blue"])?;
+        let repo = git.get_repo()?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+
+        let mut branch_oid_to_names = HashMap::new();
+        branch_oid_to_names.insert(
+            head_oid,
+            vec![
+                "refs/heads/master",
+                "refs/heads/feature",
+                "refs/remotes/origin/master",
+            ]
+            .into_iter()
+            .map(OsString::from)
+            .collect(),
+        );
+        let mut provider = BranchesProvider::new(&repo, &branch_oid_to_names)?;
+        let description = provider
+            .describe_commit(&repo.find_commit_or_fail(head_oid)?)?
+            .unwrap();
+        let spans: Vec<(&str, Style)> = description
+            .spans()
+            .map(|span| (span.content, *span.attr))
+            .filter(|(content, _)| *content != ", " && *content != "(" && *content != ")")
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("feature", parse_style("brightgreen")?),
+                ("master", parse_style("bold brightcyan")?),
+                ("remote origin/master", parse_style("dim blue")?),
+            ]
+        );
+
+        git.run(&["config", "branchless.color.branch.head", "sparkly"])?;
+        assert!(BranchesProvider::new(&repo, &branch_oid_to_names).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_template() -> eyre::Result<()> {
+        assert_eq!(
+            parse_template("%h %d (%ar) %(provider:phabricator)%%")?,
+            vec![
+                TemplateItem::Placeholder("oid".to_string()),
+                TemplateItem::Literal(" ".to_string()),
+                TemplateItem::Placeholder("branches".to_string()),
+                TemplateItem::Literal(" ".to_string()),
+                TemplateItem::Literal("(".to_string()),
+                TemplateItem::Placeholder("relative-time".to_string()),
+                TemplateItem::Literal(")".to_string()),
+                TemplateItem::Literal(" ".to_string()),
+                TemplateItem::Placeholder("phabricator".to_string()),
+                TemplateItem::Literal("%".to_string()),
+            ]
+        );
+        assert!(parse_template("%x").is_err());
+        assert!(parse_template("%(foo)").is_err());
+        assert!(parse_template("foo %").is_err());
+        assert!(parse_template("%(provider:foo").is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,120 @@
+//! Access Phabricator via its Conduit API.
+//!
+//! Rather than handling authentication itself, this uses `arc call-conduit`,
+//! so that requests go to the Phabricator instance and use the credentials
+//! which `arc` is already configured with for the repository.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::git::Repo;
+
+/// A client for the Conduit API, which uses `arc` to make requests.
+#[derive(Debug)]
+pub struct ConduitClient {
+    arc_path: String,
+    working_directory: PathBuf,
+}
+
+impl ConduitClient {
+    /// Constructor. The path to `arc` can be set with the
+    /// `branchless.phabricator.arcPath` config option.
+    pub fn new(repo: &Repo) -> eyre::Result<Self> {
+        let arc_path = repo
+            .get_config()?
+            .get_or("branchless.phabricator.arcPath", "arc".to_string())?;
+        let working_directory = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path())
+            .to_path_buf();
+        Ok(ConduitClient {
+            arc_path,
+            working_directory,
+        })
+    }
+
+    /// Call the given Conduit method with the given parameters.
+    ///
+    /// Returns: The `response` field of the result.
+    #[instrument(skip(params))]
+    pub fn call(&self, method: &str, params: &Value) -> eyre::Result<Value> {
+        let mut child = Command::new(&self.arc_path)
+            .args(["call-conduit", "--", method])
+            .current_dir(&self.working_directory)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Spawning arc: {:?}", self.arc_path))?;
+        {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| eyre::eyre!("Could not open stdin for arc"))?;
+            write!(stdin, "{}", params)?;
+        }
+        let output = child.wait_with_output().wrap_err("Waiting for arc")?;
+        if !output.status.success() {
+            eyre::bail!(
+                "Conduit call {} failed: {}",
+                method,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let result: Value = serde_json::from_slice(&output.stdout).wrap_err_with(|| {
+            format!(
+                "Parsing response from Conduit: {:?}",
+                String::from_utf8_lossy(&output.stdout)
+            )
+        })?;
+        if !result["error"].is_null() {
+            eyre::bail!(
+                "Conduit call {} failed: {}: {}",
+                method,
+                result["error"],
+                result["errorMessage"].as_str().unwrap_or("no message")
+            );
+        }
+        Ok(result["response"].clone())
+    }
+
+    /// Get the status of the given revisions, such as `accepted` or
+    /// `needs-review`.
+    ///
+    /// Returns: A mapping from the revision's number (such as `D123`) to its
+    /// status. Revisions which couldn't be found are omitted.
+    #[instrument]
+    pub fn query_revision_statuses(
+        &self,
+        diff_numbers: &[String],
+    ) -> eyre::Result<HashMap<String, String>> {
+        let ids: Vec<u64> = diff_numbers
+            .iter()
+            .filter_map(|diff_number| diff_number.strip_prefix('D')?.parse().ok())
+            .collect();
+        let response = self.call(
+            "differential.revision.search",
+            &json!({ "constraints": { "ids": ids } }),
+        )?;
+
+        let mut result = HashMap::new();
+        if let Some(revisions) = response["data"].as_array() {
+            for revision in revisions {
+                if let (Some(id), Some(status)) = (
+                    revision["id"].as_u64(),
+                    revision["fields"]["status"]["value"].as_str(),
+                ) {
+                    result.insert(format!("D{}", id), status.to_string());
+                }
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,464 @@
+//! Expressions which select sets of commits ("revsets"), in the style of
+//! Mercurial and Jujutsu.
+//!
+//! A revset is evaluated against a commit graph, and the result is the set of
+//! commits in the graph which it matches. The grammar is:
+//!
+//! ```text
+//! revset := intersection ( "|" intersection )*
+//! intersection := atom ( ( "&" | "~" ) atom )*
+//! atom := "(" revset ")" | name "(" [ revset ( "," revset )* ] ")" | symbol
+//! ```
+//!
+//! where `x | y` is the union of `x` and `y`, `x & y` is their intersection,
+//! and `x ~ y` is the commits in `x` but not in `y`. A symbol (a word, or text
+//! in double quotes) refers to the commit it resolves to, such as a branch
+//! name or a commit hash. The available functions are:
+//!
+//! - `all()`: all commits.
+//! - `none()`: no commits.
+//! - `draft()`: commits which aren't on the main branch.
+//! - `main()`: commits on the main branch.
+//! - `head()`: the commit pointed to by `HEAD`.
+//! - `branches()`: commits pointed to by a branch.
+//! - `author(text)`: commits whose author name or email contains `text`,
+//!   ignoring case.
+//! - `mine()`: commits whose author email is the configured `user.email`.
+//! - `message(text)`: commits whose message contains `text`.
+//! - `ancestors(x)`: the commits in `x` and their ancestors.
+//! - `descendants(x)`: the commits in `x` and their descendants.
+
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use tracing::instrument;
+
+use crate::core::graph::CommitGraph;
+use crate::git::{NonZeroOid, Repo};
+
+/// A parsed revset expression. See the module documentation for the syntax.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Revset {
+    /// A reference to a single commit, such as a branch name or commit hash.
+    /// Also used for text arguments to functions.
+    Symbol(String),
+
+    /// A call to one of the built-in functions.
+    Function {
+        /// The name of the function.
+        name: String,
+
+        /// The arguments to the function.
+        args: Vec<Revset>,
+    },
+
+    /// The commits in either revset.
+    Union(Box<Revset>, Box<Revset>),
+
+    /// The commits in both revsets.
+    Intersection(Box<Revset>, Box<Revset>),
+
+    /// The commits in the first revset but not the second.
+    Difference(Box<Revset>, Box<Revset>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Symbol(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Pipe,
+    Ampersand,
+    Tilde,
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | '@' | '+' | ':' | '^')
+}
+
+fn tokenize(text: &str) -> eyre::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<Chars> = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            '|' => Token::Pipe,
+            '&' => Token::Ampersand,
+            '~' => Token::Tilde,
+            '"' => {
+                let mut symbol = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => symbol.push(c),
+                            None => eyre::bail!("Unterminated string in revset: {:?}", text),
+                        },
+                        Some(c) => symbol.push(c),
+                        None => eyre::bail!("Unterminated string in revset: {:?}", text),
+                    }
+                }
+                Token::Symbol(symbol)
+            }
+            c if is_symbol_char(c) => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if !is_symbol_char(*c) {
+                        break;
+                    }
+                    symbol.push(*c);
+                    chars.next();
+                }
+                Token::Symbol(symbol)
+            }
+            c => eyre::bail!("Unexpected character {:?} in revset: {:?}", c, text),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser<'_> {
+    fn expect(&mut self, expected: Token) -> eyre::Result<()> {
+        match self.tokens.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => eyre::bail!(
+                "Expected {:?} but got {:?} in revset: {:?}",
+                expected,
+                token,
+                self.text
+            ),
+            None => eyre::bail!(
+                "Expected {:?} but reached the end of revset: {:?}",
+                expected,
+                self.text
+            ),
+        }
+    }
+
+    fn parse_union(&mut self) -> eyre::Result<Revset> {
+        let mut result = self.parse_intersection()?;
+        while self.tokens.peek() == Some(&Token::Pipe) {
+            self.tokens.next();
+            let rhs = self.parse_intersection()?;
+            result = Revset::Union(Box::new(result), Box::new(rhs));
+        }
+        Ok(result)
+    }
+
+    fn parse_intersection(&mut self) -> eyre::Result<Revset> {
+        let mut result = self.parse_atom()?;
+        loop {
+            match self.tokens.peek() {
+                Some(Token::Ampersand) => {
+                    self.tokens.next();
+                    let rhs = self.parse_atom()?;
+                    result = Revset::Intersection(Box::new(result), Box::new(rhs));
+                }
+                Some(Token::Tilde) => {
+                    self.tokens.next();
+                    let rhs = self.parse_atom()?;
+                    result = Revset::Difference(Box::new(result), Box::new(rhs));
+                }
+                _ => return Ok(result),
+            }
+        }
+    }
+
+    fn parse_atom(&mut self) -> eyre::Result<Revset> {
+        match self.tokens.next() {
+            Some(Token::LeftParen) => {
+                let result = self.parse_union()?;
+                self.expect(Token::RightParen)?;
+                Ok(result)
+            }
+            Some(Token::Symbol(symbol)) => {
+                if self.tokens.peek() != Some(&Token::LeftParen) {
+                    return Ok(Revset::Symbol(symbol));
+                }
+                self.tokens.next();
+                let mut args = Vec::new();
+                if self.tokens.peek() == Some(&Token::RightParen) {
+                    self.tokens.next();
+                } else {
+                    loop {
+                        args.push(self.parse_union()?);
+                        match self.tokens.next() {
+                            Some(Token::Comma) => continue,
+                            Some(Token::RightParen) => break,
+                            Some(token) => eyre::bail!(
+                                "Expected ',' or ')' but got {:?} in revset: {:?}",
+                                token,
+                                self.text
+                            ),
+                            None => eyre::bail!(
+                                "Expected ')' but reached the end of revset: {:?}",
+                                self.text
+                            ),
+                        }
+                    }
+                }
+                Ok(Revset::Function { name: symbol, args })
+            }
+            Some(token) => eyre::bail!("Unexpected {:?} in revset: {:?}", token, self.text),
+            None => eyre::bail!("Unexpected end of revset: {:?}", self.text),
+        }
+    }
+}
+
+/// Parse the given revset expression. Example:
+///
+/// ```
+/// # use branchless::core::revset::{parse_revset, Revset};
+/// assert_eq!(
+///     parse_revset("draft() & mine()")?,
+///     Revset::Intersection(
+///         Box::new(Revset::Function { name: "draft".to_string(), args: vec![] }),
+///         Box::new(Revset::Function { name: "mine".to_string(), args: vec![] }),
+///     )
+/// );
+/// # Ok::<(), eyre::Error>(())
+/// ```
+pub fn parse_revset(text: &str) -> eyre::Result<Revset> {
+    let mut parser = Parser {
+        text,
+        tokens: tokenize(text)?.into_iter().peekable(),
+    };
+    let result = parser.parse_union()?;
+    match parser.tokens.next() {
+        None => Ok(result),
+        Some(token) => eyre::bail!("Unexpected {:?} in revset: {:?}", token, text),
+    }
+}
+
+fn get_text_arg<'a>(name: &str, args: &'a [Revset]) -> eyre::Result<&'a str> {
+    match args {
+        [Revset::Symbol(text)] => Ok(text),
+        _ => eyre::bail!("Revset function {}() expects a single text argument", name),
+    }
+}
+
+fn get_revset_arg<'a>(name: &str, args: &'a [Revset]) -> eyre::Result<&'a Revset> {
+    match args {
+        [revset] => Ok(revset),
+        _ => eyre::bail!("Revset function {}() expects a single argument", name),
+    }
+}
+
+fn expect_no_args(name: &str, args: &[Revset]) -> eyre::Result<()> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        eyre::bail!("Revset function {}() doesn't take any arguments", name)
+    }
+}
+
+/// Find the commits in the graph which are matched by the given revset.
+#[instrument]
+pub fn evaluate_revset(
+    repo: &Repo,
+    graph: &CommitGraph,
+    revset: &Revset,
+) -> eyre::Result<HashSet<NonZeroOid>> {
+    let filter_graph = |predicate: &dyn Fn(NonZeroOid) -> eyre::Result<bool>| -> eyre::Result<
+        HashSet<NonZeroOid>,
+    > {
+        let mut result = HashSet::new();
+        for oid in graph.keys() {
+            if predicate(*oid)? {
+                result.insert(*oid);
+            }
+        }
+        Ok(result)
+    };
+
+    let result = match revset {
+        Revset::Symbol(symbol) => match repo.revparse_single_commit(symbol)? {
+            Some(commit) if graph.contains_key(&commit.get_oid()) => {
+                std::iter::once(commit.get_oid()).collect()
+            }
+            Some(_) => HashSet::new(),
+            None => eyre::bail!("Commit not found in revset: {}", symbol),
+        },
+
+        Revset::Union(lhs, rhs) => {
+            let mut result = evaluate_revset(repo, graph, lhs)?;
+            result.extend(evaluate_revset(repo, graph, rhs)?);
+            result
+        }
+        Revset::Intersection(lhs, rhs) => {
+            let rhs = evaluate_revset(repo, graph, rhs)?;
+            evaluate_revset(repo, graph, lhs)?
+                .into_iter()
+                .filter(|oid| rhs.contains(oid))
+                .collect()
+        }
+        Revset::Difference(lhs, rhs) => {
+            let rhs = evaluate_revset(repo, graph, rhs)?;
+            evaluate_revset(repo, graph, lhs)?
+                .into_iter()
+                .filter(|oid| !rhs.contains(oid))
+                .collect()
+        }
+
+        Revset::Function { name, args } => match name.as_str() {
+            "all" => {
+                expect_no_args(name, args)?;
+                graph.keys().copied().collect()
+            }
+            "none" => {
+                expect_no_args(name, args)?;
+                HashSet::new()
+            }
+            "draft" => {
+                expect_no_args(name, args)?;
+                filter_graph(&|oid| Ok(!graph[&oid].is_main))?
+            }
+            "main" => {
+                expect_no_args(name, args)?;
+                filter_graph(&|oid| Ok(graph[&oid].is_main))?
+            }
+            "head" => {
+                expect_no_args(name, args)?;
+                repo.get_head_info()?
+                    .oid
+                    .into_iter()
+                    .filter(|oid| graph.contains_key(oid))
+                    .collect()
+            }
+            "branches" => {
+                expect_no_args(name, args)?;
+                let branch_oids = repo.get_branch_oid_to_names()?;
+                filter_graph(&|oid| Ok(branch_oids.contains_key(&oid)))?
+            }
+            "author" => {
+                let text = get_text_arg(name, args)?.to_lowercase();
+                filter_graph(&|oid| {
+                    let author = graph[&oid].commit.get_author();
+                    Ok([author.get_name(), author.get_email()]
+                        .iter()
+                        .flatten()
+                        .any(|value| value.to_lowercase().contains(&text)))
+                })?
+            }
+            "mine" => {
+                expect_no_args(name, args)?;
+                let email: Option<String> = repo.get_config()?.get("user.email")?;
+                match email {
+                    Some(email) => filter_graph(&|oid| {
+                        Ok(graph[&oid].commit.get_author().get_email() == Some(email.as_str()))
+                    })?,
+                    None => HashSet::new(),
+                }
+            }
+            "message" => {
+                let text = get_text_arg(name, args)?;
+                filter_graph(&|oid| {
+                    Ok(graph[&oid]
+                        .commit
+                        .get_message_raw()?
+                        .to_string_lossy()
+                        .contains(text))
+                })?
+            }
+            "ancestors" => {
+                let mut result = HashSet::new();
+                let mut stack: Vec<NonZeroOid> =
+                    evaluate_revset(repo, graph, get_revset_arg(name, args)?)?
+                        .into_iter()
+                        .collect();
+                while let Some(oid) = stack.pop() {
+                    if !result.insert(oid) {
+                        continue;
+                    }
+                    let node = &graph[&oid];
+                    stack.extend(
+                        node.parent
+                            .iter()
+                            .chain(node.merge_parents.iter())
+                            .filter(|parent_oid| graph.contains_key(parent_oid)),
+                    );
+                }
+                result
+            }
+            "descendants" => {
+                let mut result = HashSet::new();
+                let mut stack: Vec<NonZeroOid> =
+                    evaluate_revset(repo, graph, get_revset_arg(name, args)?)?
+                        .into_iter()
+                        .collect();
+                while let Some(oid) = stack.pop() {
+                    if !result.insert(oid) {
+                        continue;
+                    }
+                    stack.extend(graph[&oid].children.iter().copied());
+                }
+                result
+            }
+            _ => eyre::bail!("Unknown revset function: {}()", name),
+        },
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, args: Vec<Revset>) -> Revset {
+        Revset::Function {
+            name: name.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn test_parse_revset() -> eyre::Result<()> {
+        assert_eq!(
+            parse_revset("draft() | foo & author(\"Jane Doe\")")?,
+            Revset::Union(
+                Box::new(function("draft", vec![])),
+                Box::new(Revset::Intersection(
+                    Box::new(Revset::Symbol("foo".to_string())),
+                    Box::new(function(
+                        "author",
+                        vec![Revset::Symbol("Jane Doe".to_string())]
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            parse_revset("(all() ~ main()) & ancestors(origin/feature-1)")?,
+            Revset::Intersection(
+                Box::new(Revset::Difference(
+                    Box::new(function("all", vec![])),
+                    Box::new(function("main", vec![])),
+                )),
+                Box::new(function(
+                    "ancestors",
+                    vec![Revset::Symbol("origin/feature-1".to_string())]
+                )),
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_revset_errors() {
+        assert!(parse_revset("").is_err());
+        assert!(parse_revset("draft(").is_err());
+        assert!(parse_revset("draft() &").is_err());
+        assert!(parse_revset("draft() main()").is_err());
+        assert!(parse_revset("author(\"unterminated)").is_err());
+    }
+}
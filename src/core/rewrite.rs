@@ -3,8 +3,14 @@
 mod evolve;
 mod execute;
 pub mod hooks;
+mod notes;
 mod plan;
 
 pub use evolve::{find_abandoned_children, find_rewrite_target};
-pub use execute::{execute_rebase_plan, move_branches, ExecuteRebasePlanOptions};
-pub use plan::{BuildRebasePlanOptions, RebasePlanBuilder};
+pub use execute::{
+    execute_rebase_plan, finish_rewrite_in_memory, move_branches, ExecuteRebasePlanOptions,
+};
+pub use plan::{
+    BuildRebasePlanError, BuildRebasePlanOptions, OidOrLabel, RebaseCommand, RebasePlan,
+    RebasePlanBuilder,
+};
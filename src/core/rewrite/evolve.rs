@@ -60,7 +60,10 @@ pub fn find_rewrite_target(
         | Event::RefUpdateEvent { .. }
         | Event::CommitEvent { .. }
         | Event::HideEvent { .. }
-        | Event::UnhideEvent { .. } => None,
+        | Event::UnhideEvent { .. }
+        | Event::StashPushEvent { .. }
+        | Event::StashDropEvent { .. }
+        | Event::CopyEvent { .. } => None,
     }
 }
 
@@ -5,15 +5,34 @@ use std::time::SystemTime;
 
 use eyre::Context;
 use os_str_bytes::OsStrBytes;
-use tracing::warn;
+use tracing::{instrument, warn};
 
 use crate::core::eventlog::EventTransactionId;
 use crate::core::formatting::printable_styled_string;
-use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
-use crate::tui::Effects;
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceTarget, Repo};
+use crate::tui::{Effects, Verbosity};
 
 use super::plan::RebasePlan;
 
+/// Describe the upstream commit whose changes are the same as those of a
+/// skipped commit, such as `already in main as abc12345`.
+pub(crate) fn describe_upstream_applied_commit(
+    repo: &Repo,
+    upstream_commit_oid: NonZeroOid,
+) -> eyre::Result<String> {
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let upstream_commit_oid_str = upstream_commit_oid.to_string();
+    let short_oid = &upstream_commit_oid_str[..8];
+    let description = if repo.find_merge_base(upstream_commit_oid, main_branch_oid)?
+        == Some(upstream_commit_oid)
+    {
+        format!("already in main as {}", short_oid)
+    } else {
+        format!("already applied upstream as {}", short_oid)
+    };
+    Ok(description)
+}
+
 /// Given a list of rewritten OIDs, move the branches attached to those OIDs
 /// from their old commits to their new commits. Invoke the
 /// `reference-transaction` hook when done.
@@ -26,11 +45,10 @@ pub fn move_branches<'a>(
 ) -> eyre::Result<()> {
     let branch_oid_to_names = repo.get_branch_oid_to_names()?;
 
-    // We may experience an error in the case of a branch move. Ideally, we
-    // would use `git2::Transaction::commit`, which stops the transaction at the
-    // first error, but we don't know which references we successfully committed
-    // in that case. Instead, we just do things non-atomically and record which
-    // ones succeeded. See https://github.com/libgit2/libgit2/issues/5918
+    // Queue all of the branch moves in a single reference transaction, so
+    // that a failure partway through doesn't leave some branches moved and
+    // others not.
+    let mut transaction = repo.start_reference_transaction()?;
     let mut branch_moves: Vec<(NonZeroOid, MaybeZeroOid, &OsStr)> = Vec::new();
     let mut branch_move_err: Option<eyre::Error> = None;
     'outer: for (old_oid, names) in branch_oid_to_names.iter() {
@@ -58,7 +76,7 @@ pub fn move_branches<'a>(
 
                 for name in names {
                     if let Err(err) =
-                        repo.create_reference(name, new_commit.get_oid(), true, "move branches")
+                        transaction.set_target(name, new_commit.get_oid(), "move branches")
                     {
                         branch_move_err = Some(err);
                         break 'outer;
@@ -70,8 +88,8 @@ pub fn move_branches<'a>(
             MaybeZeroOid::Zero => {
                 for name in names {
                     match repo.find_reference(name) {
-                        Ok(Some(mut reference)) => {
-                            if let Err(err) = reference.delete() {
+                        Ok(Some(_)) => {
+                            if let Err(err) = transaction.remove(name) {
                                 branch_move_err = Some(err);
                                 break 'outer;
                             }
@@ -90,6 +108,45 @@ pub fn move_branches<'a>(
         }
     }
 
+    let branch_moves = match branch_move_err {
+        // Nothing has been written yet, so dropping the transaction leaves all
+        // of the branches where they were.
+        Some(_) => Vec::new(),
+        None => match transaction.commit() {
+            Ok(()) => branch_moves,
+            Err(err) => {
+                branch_move_err = Some(err);
+
+                // libgit2 doesn't report which references were updated before
+                // the failure, so check each of them individually. Errors
+                // while checking are ignored, so that the original error is
+                // the one reported.
+                let mut applied_branch_moves = Vec::new();
+                for (old_oid, new_oid, name) in branch_moves {
+                    let current_oid = match repo.find_reference(name) {
+                        Ok(Some(reference)) => match reference.get_target() {
+                            Ok(ReferenceTarget::Direct { oid }) => oid,
+                            Ok(ReferenceTarget::Symbolic { .. }) => continue,
+                            Err(err) => {
+                                warn!(?name, ?err, "Could not read reference target");
+                                continue;
+                            }
+                        },
+                        Ok(None) => MaybeZeroOid::Zero,
+                        Err(err) => {
+                            warn!(?name, ?err, "Could not look up reference");
+                            continue;
+                        }
+                    };
+                    if current_oid == new_oid {
+                        applied_branch_moves.push((old_oid, new_oid, name));
+                    }
+                }
+                applied_branch_moves
+            }
+        },
+    };
+
     let branch_moves_stdin: Vec<u8> = branch_moves
         .into_iter()
         .flat_map(|(old_oid, new_oid, name)| {
@@ -113,6 +170,7 @@ pub fn move_branches<'a>(
         event_tx_id,
         &["committed"],
         Some(branch_moves_stdin),
+        &HashMap::new(),
     )?;
     match branch_move_err {
         Some(err) => Err(err),
@@ -125,19 +183,27 @@ mod in_memory {
     use std::ffi::OsString;
     use std::fmt::Write;
     use std::path::PathBuf;
+    use std::time::SystemTime;
 
     use eyre::Context;
-    use indicatif::{ProgressBar, ProgressStyle};
     use tracing::{instrument, warn};
 
     use crate::commands::gc::mark_commit_reachable;
-    use crate::core::formatting::printable_styled_string;
+    use crate::core::config::{
+        get_restack_rename_limit, get_restack_rename_threshold, get_restack_signing_failure_action,
+        SigningFailureAction,
+    };
+    use crate::core::eventlog::{Event, EventLogDb, EventTransactionId};
+    use crate::core::formatting::{printable_styled_string, Pluralize};
+    use crate::core::rewrite::execute::describe_upstream_applied_commit;
     use crate::core::rewrite::move_branches;
+    use crate::core::rewrite::notes::copy_notes_for_rewrite;
     use crate::core::rewrite::plan::{OidOrLabel, RebaseCommand, RebasePlan};
     use crate::git::{
-        CherryPickFastError, CherryPickFastOptions, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo,
+        CherryPickFastError, CherryPickFastOptions, CommitSigner, GitRunInfo, MaybeZeroOid,
+        NonZeroOid, Repo,
     };
-    use crate::tui::Effects;
+    use crate::tui::{Effects, OperationType, Verbosity};
 
     use super::ExecuteRebasePlanOptions;
 
@@ -155,10 +221,17 @@ mod in_memory {
         CannotRebaseMergeCommit {
             commit_oid: NonZeroOid,
         },
+        CannotRunExecCommand {
+            command: String,
+        },
         MergeConflict {
             commit_oid: NonZeroOid,
             conflicting_paths: HashSet<PathBuf>,
         },
+        CannotSignCommit {
+            commit_oid: NonZeroOid,
+            err: eyre::Report,
+        },
     }
 
     #[instrument]
@@ -180,9 +253,11 @@ mod in_memory {
                     RebaseCommand::CreateLabel { .. }
                     | RebaseCommand::Reset { .. }
                     | RebaseCommand::Pick { .. }
+                    | RebaseCommand::Replace { .. }
                     | RebaseCommand::RegisterExtraPostRewriteHook
                     | RebaseCommand::DetectEmptyCommit { .. }
-                    | RebaseCommand::SkipUpstreamAppliedCommit { .. } => None,
+                    | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                    | RebaseCommand::Exec { .. } => None,
                 })
         {
             return Ok(RebaseInMemoryResult::CannotRebaseMergeCommit {
@@ -190,6 +265,27 @@ mod in_memory {
             });
         }
 
+        // There's no working copy to run commands in.
+        if let Some(command) = rebase_plan
+            .commands
+            .iter()
+            .find_map(|command| match command {
+                RebaseCommand::Exec { command } => Some(command),
+                RebaseCommand::CreateLabel { .. }
+                | RebaseCommand::Reset { .. }
+                | RebaseCommand::Pick { .. }
+                | RebaseCommand::Replace { .. }
+                | RebaseCommand::Merge { .. }
+                | RebaseCommand::RegisterExtraPostRewriteHook
+                | RebaseCommand::DetectEmptyCommit { .. }
+                | RebaseCommand::SkipUpstreamAppliedCommit { .. } => None,
+            })
+        {
+            return Ok(RebaseInMemoryResult::CannotRunExecCommand {
+                command: command.clone(),
+            });
+        }
+
         let ExecuteRebasePlanOptions {
             now,
             // Transaction ID will be passed to the `post-rewrite` hook via
@@ -198,8 +294,17 @@ mod in_memory {
             preserve_timestamps,
             force_in_memory: _,
             force_on_disk: _,
+            strategy_options: _,
+            keep_originals: _,
         } = options;
 
+        let signer = CommitSigner::from_repo(repo)?;
+        let signing_failure_action = get_restack_signing_failure_action(repo)?;
+        let rename_limit = get_restack_rename_limit(repo)?;
+        let rename_threshold = get_restack_rename_threshold(repo)?;
+        let mut signing_error: Option<eyre::Report> = None;
+        let mut num_dropped_signatures: isize = 0;
+
         let mut current_oid = rebase_plan.first_dest_oid;
         let mut labels: HashMap<String, NonZeroOid> = HashMap::new();
         let mut rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)> = Vec::new();
@@ -225,12 +330,15 @@ mod in_memory {
                 RebaseCommand::CreateLabel { .. }
                 | RebaseCommand::Reset { .. }
                 | RebaseCommand::RegisterExtraPostRewriteHook
-                | RebaseCommand::DetectEmptyCommit { .. } => false,
+                | RebaseCommand::DetectEmptyCommit { .. }
+                | RebaseCommand::Exec { .. } => false,
                 RebaseCommand::Pick { .. }
+                | RebaseCommand::Replace { .. }
                 | RebaseCommand::Merge { .. }
                 | RebaseCommand::SkipUpstreamAppliedCommit { .. } => true,
             })
             .count();
+        let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
 
         for command in rebase_plan.commands.iter() {
             match command {
@@ -253,12 +361,23 @@ mod in_memory {
                     current_oid = *commit_oid;
                 }
 
-                RebaseCommand::Pick { commit_oid } => {
+                RebaseCommand::Pick { commit_oid }
+                | RebaseCommand::Replace {
+                    commit_oid,
+                    replacement_commit_oid: _,
+                } => {
+                    let commit_to_apply_oid = match command {
+                        RebaseCommand::Replace {
+                            commit_oid: _,
+                            replacement_commit_oid,
+                        } => *replacement_commit_oid,
+                        _ => *commit_oid,
+                    };
                     let current_commit = repo
                         .find_commit_or_fail(current_oid)
                         .wrap_err_with(|| "Finding current commit")?;
                     let commit_to_apply = repo
-                        .find_commit_or_fail(*commit_oid)
+                        .find_commit_or_fail(commit_to_apply_oid)
                         .wrap_err_with(|| "Finding commit to apply")?;
                     i += 1;
 
@@ -267,13 +386,10 @@ mod in_memory {
                         commit_to_apply.friendly_describe()?,
                     )?;
                     let commit_num = format!("[{}/{}]", i, num_picks);
-                    let progress_template = format!("{} {{spinner}} {{wide_msg}}", commit_num);
-                    let progress = ProgressBar::new_spinner();
-                    progress.set_style(
-                        ProgressStyle::default_spinner().template(progress_template.trim()),
-                    );
-                    progress.set_message("Starting");
-                    progress.enable_steady_tick(100);
+                    progress.notify_message(format!(
+                        "Rebasing commit {}/{}: {}",
+                        i, num_picks, commit_description
+                    ));
 
                     if commit_to_apply.get_parent_count() > 1 {
                         warn!(
@@ -285,13 +401,13 @@ mod in_memory {
                         });
                     };
 
-                    progress
-                        .set_message(format!("Applying patch for commit: {}", commit_description));
                     let commit_tree = match repo.cherry_pick_fast(
                         &commit_to_apply,
                         &current_commit,
                         &CherryPickFastOptions {
                             reuse_parent_tree_if_possible: true,
+                            rename_limit,
+                            rename_threshold,
                         },
                     )? {
                         Ok(rebased_commit) => rebased_commit,
@@ -303,31 +419,66 @@ mod in_memory {
                         }
                     };
 
-                    let commit_message = commit_to_apply.get_message_raw()?;
-                    let commit_message = commit_message.to_str().ok_or_else(|| {
-                        eyre::eyre!(
-                            "Could not decode commit message for commit: {:?}",
-                            commit_oid
-                        )
-                    })?;
-
-                    progress
-                        .set_message(format!("Committing to repository: {}", commit_description));
                     let committer_signature = if *preserve_timestamps {
                         commit_to_apply.get_committer()
                     } else {
                         commit_to_apply.get_committer().update_timestamp(*now)?
                     };
-                    let rebased_commit_oid = repo
-                        .create_commit(
-                            None,
-                            &commit_to_apply.get_author(),
-                            &committer_signature,
-                            commit_message,
-                            &commit_tree,
-                            vec![&current_commit],
-                        )
-                        .wrap_err_with(|| "Applying rebased commit")?;
+                    let is_unchanged = *preserve_timestamps
+                        && commit_to_apply.get_parent_oids() == vec![current_oid]
+                        && commit_tree.get_oid() == commit_to_apply.get_tree()?.get_oid();
+                    let rebased_commit_oid = if is_unchanged {
+                        // Recreating the commit would produce an identical
+                        // commit, except without its signature (if any), so
+                        // reuse the original.
+                        commit_to_apply_oid
+                    } else {
+                        let signed_commit_oid = match &signer {
+                            Some(signer) if signing_error.is_none() => {
+                                match repo.create_commit_from_tree(
+                                    &commit_to_apply,
+                                    &commit_tree,
+                                    vec![&current_commit],
+                                    &committer_signature,
+                                    Some(signer),
+                                ) {
+                                    Ok(signed_commit_oid) => Some(signed_commit_oid),
+                                    Err(err) => match signing_failure_action {
+                                        SigningFailureAction::OnDisk => {
+                                            return Ok(RebaseInMemoryResult::CannotSignCommit {
+                                                commit_oid: *commit_oid,
+                                                err,
+                                            })
+                                        }
+                                        SigningFailureAction::Warn => {
+                                            // Don't try again for the remaining
+                                            // commits, since they'll most likely
+                                            // fail in the same way.
+                                            signing_error = Some(err);
+                                            None
+                                        }
+                                    },
+                                }
+                            }
+                            Some(_) | None => None,
+                        };
+                        match signed_commit_oid {
+                            Some(signed_commit_oid) => signed_commit_oid,
+                            None => {
+                                if signer.is_none() && repo.is_commit_signed(commit_to_apply_oid)? {
+                                    num_dropped_signatures += 1;
+                                }
+                                repo.create_commit_from_tree(
+                                    &commit_to_apply,
+                                    &commit_tree,
+                                    vec![&current_commit],
+                                    &committer_signature,
+                                    None,
+                                )
+                                .wrap_err_with(|| "Applying rebased commit")?
+                            }
+                        }
+                    };
 
                     let rebased_commit = repo
                         .find_commit_or_fail(rebased_commit_oid)
@@ -340,9 +491,8 @@ mod in_memory {
                         rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
                         maybe_set_skipped_head_new_oid(*commit_oid, current_oid);
 
-                        progress.finish_and_clear();
                         writeln!(
-                            effects.get_output_stream(),
+                            effects.get_output_stream_at(Verbosity::Normal),
                             "[{}/{}] Skipped now-empty commit: {}",
                             i,
                             num_picks,
@@ -353,9 +503,8 @@ mod in_memory {
                             .push((*commit_oid, MaybeZeroOid::NonZero(rebased_commit_oid)));
                         current_oid = rebased_commit_oid;
 
-                        progress.finish_and_clear();
                         writeln!(
-                            effects.get_output_stream(),
+                            effects.get_output_stream_at(Verbosity::Normal),
                             "{} Committed as: {}",
                             commit_num,
                             commit_description
@@ -376,31 +525,43 @@ mod in_memory {
                     });
                 }
 
-                RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => {
-                    let progress = ProgressBar::new_spinner();
+                RebaseCommand::SkipUpstreamAppliedCommit {
+                    commit_oid,
+                    upstream_commit_oid,
+                } => {
                     i += 1;
                     let commit_num = format!("[{}/{}]", i, num_picks);
-                    let progress_template = format!("{} {{spinner}} {{wide_msg}}", commit_num);
-                    progress.set_style(
-                        ProgressStyle::default_spinner().template(progress_template.trim()),
-                    );
-
                     let commit = repo.find_commit_or_fail(*commit_oid)?;
-                    rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
-                    maybe_set_skipped_head_new_oid(*commit_oid, current_oid);
-
-                    progress.finish_and_clear();
                     let commit_description = commit.friendly_describe()?;
                     let commit_description =
                         printable_styled_string(effects.get_glyphs(), commit_description)?;
+                    progress.notify_message(format!(
+                        "Rebasing commit {}/{}: {}",
+                        i, num_picks, commit_description
+                    ));
+
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
+                    maybe_set_skipped_head_new_oid(*commit_oid, current_oid);
+
                     writeln!(
-                        effects.get_output_stream(),
-                        "{} Skipped commit (was already applied upstream): {}",
+                        effects.get_output_stream_at(Verbosity::Normal),
+                        "{} Skipped commit ({}): {}",
                         commit_num,
+                        describe_upstream_applied_commit(repo, *upstream_commit_oid)?,
                         commit_description
                     )?;
                 }
 
+                RebaseCommand::Exec { command } => {
+                    warn!(
+                        ?command,
+                        "BUG: Exec command should have been detected when starting in-memory rebase"
+                    );
+                    return Ok(RebaseInMemoryResult::CannotRunExecCommand {
+                        command: command.clone(),
+                    });
+                }
+
                 RebaseCommand::RegisterExtraPostRewriteHook
                 | RebaseCommand::DetectEmptyCommit { .. } => {
                     // Do nothing. We'll carry out post-rebase operations after the
@@ -409,6 +570,30 @@ mod in_memory {
             }
         }
 
+        if let Some(err) = signing_error {
+            writeln!(
+                effects.get_output_stream(),
+                "Could not sign the rewritten commits, so they were left unsigned: {}",
+                err
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "To rebase on-disk instead when this happens, run: git config branchless.restack.signingFailure on-disk"
+            )?;
+        }
+        if num_dropped_signatures > 0 {
+            writeln!(
+                effects.get_output_stream(),
+                "Dropped the signatures of {}. To re-sign rewritten commits, set commit.gpgSign.",
+                Pluralize {
+                    amount: num_dropped_signatures,
+                    singular: "rewritten commit",
+                    plural: "rewritten commits",
+                }
+                .to_string()
+            )?;
+        }
+
         let new_head_oid: Option<NonZeroOid> = match head_oid {
             None => {
                 // `HEAD` is unborn, so keep it that way.
@@ -455,6 +640,34 @@ mod in_memory {
         })
     }
 
+    /// Record the commits created by copying rather than rewriting the original
+    /// commits, so that the copies are visible and the originals stay as they
+    /// were.
+    fn record_copied_commits(
+        repo: &Repo,
+        rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+        now: SystemTime,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<()> {
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        let events = rewritten_oids
+            .iter()
+            .filter_map(|(old_commit_oid, new_commit_oid)| match new_commit_oid {
+                MaybeZeroOid::NonZero(new_commit_oid) => Some(Event::CopyEvent {
+                    timestamp,
+                    event_tx_id,
+                    old_commit_oid: *old_commit_oid,
+                    new_commit_oid: *new_commit_oid,
+                }),
+                MaybeZeroOid::Zero => None,
+            })
+            .collect();
+        let conn = repo.get_db_conn()?;
+        let mut event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.add_events(events)?;
+        Ok(())
+    }
+
     pub fn post_rebase_in_memory(
         effects: &Effects,
         git_run_info: &GitRunInfo,
@@ -464,11 +677,13 @@ mod in_memory {
         options: &ExecuteRebasePlanOptions,
     ) -> eyre::Result<isize> {
         let ExecuteRebasePlanOptions {
-            now: _,
+            now,
             event_tx_id,
             preserve_timestamps: _,
             force_in_memory: _,
             force_on_disk: _,
+            strategy_options: _,
+            keep_originals,
         } = options;
 
         // Note that if an OID has been mapped to multiple other OIDs, then the last
@@ -482,6 +697,11 @@ mod in_memory {
             }
         }
 
+        if *keep_originals {
+            record_copied_commits(repo, rewritten_oids, *now, *event_tx_id)?;
+            return Ok(0);
+        }
+
         let head_info = repo.get_head_info()?;
         if head_info.oid.is_some() {
             // Avoid moving the branch which HEAD points to, or else the index will show
@@ -497,6 +717,16 @@ mod in_memory {
             &rewritten_oids_map,
         )?;
 
+        // `git rebase` does this itself for on-disk rebases.
+        copy_notes_for_rewrite(
+            effects,
+            git_run_info,
+            repo,
+            *event_tx_id,
+            *now,
+            &rewritten_oids_map,
+        )?;
+
         // Call the `post-rewrite` hook only after moving branches so that we don't
         // produce a spurious abandoned-branch warning.
         let post_rewrite_stdin: String = rewritten_oids
@@ -511,6 +741,7 @@ mod in_memory {
             *event_tx_id,
             &["rebase"],
             Some(post_rewrite_stdin),
+            &HashMap::new(),
         )?;
 
         let (previous_head_oid, new_head_oid) = match head_info.oid {
@@ -567,13 +798,19 @@ mod in_memory {
 
 mod on_disk {
     use std::fmt::Write;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
 
     use eyre::Context;
     use tracing::instrument;
 
-    use crate::core::rewrite::plan::RebasePlan;
-    use crate::git::{GitRunInfo, MaybeZeroOid, Repo};
-    use crate::tui::{Effects, OperationType};
+    use crate::core::formatting::printable_styled_string;
+    use crate::core::rewrite::plan::{RebaseCommand, RebasePlan};
+    use crate::git::{GitRunInfo, GitRunResult, GitVersion, MaybeZeroOid, Repo};
+    use crate::tui::{Effects, OperationType, Verbosity};
 
     use super::ExecuteRebasePlanOptions;
 
@@ -595,6 +832,8 @@ mod on_disk {
             preserve_timestamps,
             force_in_memory: _,
             force_on_disk: _,
+            strategy_options,
+            keep_originals: _,
         } = options;
 
         let (effects, _progress) = effects.start_operation(OperationType::InitializeRebase);
@@ -608,7 +847,7 @@ mod on_disk {
             }));
         }
 
-        if repo.has_changed_files(&effects, git_run_info)? {
+        if repo.has_changed_files(&effects)? {
             return Ok(Err(Error::ChangedFilesInRepository));
         }
 
@@ -726,6 +965,45 @@ mod on_disk {
                 .wrap_err_with(|| "Writing `cdate_is_adate` option file")?;
         }
 
+        // Corresponds to the `--strategy-option` flag. Like `git rebase`, use
+        // the default merge strategy for the Git version, since the options
+        // are only read if a strategy is also set.
+        if !strategy_options.is_empty() {
+            let version_str = git_run_info
+                .run_silent(repo, None, &["version"])
+                .wrap_err_with(|| "Determining Git version")?;
+            let version: GitVersion = version_str
+                .trim()
+                .parse()
+                .wrap_err_with(|| format!("Parsing Git version string: {}", version_str))?;
+            let strategy = if version >= GitVersion(2, 34, 0) {
+                "ort"
+            } else {
+                "recursive"
+            };
+            let strategy_file_path = rebase_state_dir.join("strategy");
+            std::fs::write(&strategy_file_path, strategy)
+                .wrap_err_with(|| "Writing `strategy` option file")?;
+
+            let strategy_opts_file_path = rebase_state_dir.join("strategy_opts");
+            std::fs::write(
+                &strategy_opts_file_path,
+                strategy_options
+                    .iter()
+                    .map(|strategy_option| format!(" --{}", strategy_option))
+                    .collect::<String>(),
+            )
+            .wrap_err_with(|| "Writing `strategy_opts` option file")?;
+        }
+
+        // Corresponds to the `--gpg-sign` flag, which `git rebase` would
+        // otherwise set from `commit.gpgSign` when starting the rebase.
+        if repo.get_config()?.get_or("commit.gpgSign", false)? {
+            let gpg_sign_opt_file_path = rebase_state_dir.join("gpg_sign_opt");
+            std::fs::write(&gpg_sign_opt_file_path, "-S")
+                .wrap_err_with(|| "Writing `gpg_sign_opt` option file")?;
+        }
+
         // Make sure we don't move around the current branch unintentionally. If it
         // actually needs to be moved, then it will be moved as part of the
         // post-rebase operations.
@@ -736,6 +1014,99 @@ mod on_disk {
         Ok(Ok(()))
     }
 
+    /// Reports the progress of an on-disk rebase while Git carries it out.
+    struct ProgressWatcher {
+        should_stop: Arc<AtomicBool>,
+        thread: JoinHandle<()>,
+    }
+
+    impl ProgressWatcher {
+        /// Start watching the `done` file in the rebase state directory, to
+        /// which Git appends each line of the `git-rebase-todo` file as it
+        /// starts carrying it out.
+        fn start(effects: &Effects, repo: &Repo, rebase_plan: &RebasePlan) -> eyre::Result<Self> {
+            // For each line of the `git-rebase-todo` file, the description of
+            // the commit which it applies, if any.
+            let mut line_descriptions: Vec<Option<String>> = Vec::new();
+            for command in rebase_plan.get_commands() {
+                let commit_oid = match command {
+                    RebaseCommand::Pick { commit_oid }
+                    | RebaseCommand::Replace {
+                        commit_oid,
+                        replacement_commit_oid: _,
+                    }
+                    | RebaseCommand::Merge {
+                        commit_oid,
+                        commits_to_merge: _,
+                    }
+                    | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid, .. } => {
+                        Some(*commit_oid)
+                    }
+                    RebaseCommand::CreateLabel { .. }
+                    | RebaseCommand::Reset { .. }
+                    | RebaseCommand::RegisterExtraPostRewriteHook
+                    | RebaseCommand::DetectEmptyCommit { .. }
+                    | RebaseCommand::Exec { .. } => None,
+                };
+                line_descriptions.push(match commit_oid {
+                    Some(commit_oid) => Some(printable_styled_string(
+                        effects.get_glyphs(),
+                        repo.friendly_describe_commit_from_oid(commit_oid)?,
+                    )?),
+                    None => None,
+                });
+                for _ in 1..command.to_string().lines().count() {
+                    line_descriptions.push(None);
+                }
+            }
+
+            let effects = effects.clone();
+            let done_file_path: PathBuf = repo.get_rebase_state_dir_path().join("done");
+            let should_stop = Arc::new(AtomicBool::new(false));
+            let thread = thread::spawn({
+                let should_stop = Arc::clone(&should_stop);
+                move || {
+                    let (_effects, progress) =
+                        effects.start_operation(OperationType::RebaseCommits);
+                    let num_commits = line_descriptions.iter().flatten().count();
+                    while !should_stop.load(Ordering::SeqCst) {
+                        let num_done_lines = std::fs::read_to_string(&done_file_path)
+                            .map(|contents| contents.lines().count())
+                            .unwrap_or(0);
+                        let done_descriptions: Vec<&String> = line_descriptions
+                            .iter()
+                            .take(num_done_lines)
+                            .flatten()
+                            .collect();
+                        match done_descriptions.last() {
+                            Some(description) => progress.notify_message(format!(
+                                "Rebasing commit {}/{} on-disk: {}",
+                                done_descriptions.len(),
+                                num_commits,
+                                description
+                            )),
+                            None => progress.notify_message("Rebasing commits on-disk"),
+                        }
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            });
+            Ok(ProgressWatcher {
+                should_stop,
+                thread,
+            })
+        }
+
+        fn stop(self) {
+            let ProgressWatcher {
+                should_stop,
+                thread,
+            } = self;
+            should_stop.store(true, Ordering::SeqCst);
+            thread.join().unwrap();
+        }
+    }
+
     /// Rebase on-disk. We don't use `git2`'s `Rebase` machinery because it ends up
     /// being too slow.
     ///
@@ -756,6 +1127,8 @@ mod on_disk {
             preserve_timestamps: _,
             force_in_memory: _,
             force_on_disk: _,
+            strategy_options: _,
+            keep_originals: _,
         } = options;
 
         match write_rebase_state_to_disk(effects, git_run_info, repo, rebase_plan, options)? {
@@ -764,10 +1137,25 @@ mod on_disk {
         };
 
         writeln!(
-            effects.get_output_stream(),
+            effects.get_output_stream_at(Verbosity::Normal),
             "Calling Git for on-disk rebase..."
         )?;
-        let exit_code = git_run_info.run(effects, Some(*event_tx_id), &["rebase", "--continue"])?;
+        let progress_watcher = ProgressWatcher::start(effects, repo, rebase_plan)?;
+        let result =
+            git_run_info.run_and_capture(effects, Some(*event_tx_id), &["rebase", "--continue"]);
+        progress_watcher.stop();
+        let GitRunResult {
+            exit_code,
+            stdout,
+            stderr: _,
+        } = result?;
+
+        // Git's explanation of why the rebase stopped (such as which files
+        // have merge conflicts) isn't shown at lower verbosities, so show it
+        // now that it's needed.
+        if exit_code != 0 && effects.get_verbosity() < Verbosity::Normal {
+            write!(effects.get_error_stream(), "{}", stdout)?;
+        }
         Ok(Ok(exit_code))
     }
 }
@@ -791,6 +1179,45 @@ pub struct ExecuteRebasePlanOptions {
 
     /// Force an on-disk rebase (as opposed to an in-memory rebase).
     pub force_on_disk: bool,
+
+    /// Options to pass to the merge strategy for on-disk rebases, as with `git
+    /// rebase --strategy-option`, such as `theirs` or `find-renames=30`. They
+    /// aren't used for in-memory rebases.
+    pub strategy_options: Vec<String>,
+
+    /// If `true`, the rewritten commits are recorded as copies of the
+    /// original commits, which are left visible, and branches and `HEAD`
+    /// aren't moved. This is only supported for in-memory rebases.
+    pub keep_originals: bool,
+}
+
+/// Finish rewriting commits which were rewritten in memory without touching the
+/// working copy: move branches and `HEAD` to the rewritten commits, and record
+/// the rewrite in the event log. Returns the exit status (zero indicates
+/// success).
+///
+/// Args:
+/// * `rewritten_oids`: Pairs of each original commit OID and the OID of the
+/// commit it was rewritten to.
+/// * `new_head_oid`: The commit to check out if `HEAD` isn't attached to a
+/// branch.
+#[instrument]
+pub fn finish_rewrite_in_memory(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+    new_head_oid: Option<NonZeroOid>,
+    options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<isize> {
+    in_memory::post_rebase_in_memory(
+        effects,
+        git_run_info,
+        repo,
+        rewritten_oids,
+        new_head_oid,
+        options,
+    )
 }
 
 /// Execute the provided rebase plan. Returns the exit status (zero indicates
@@ -808,12 +1235,14 @@ pub fn execute_rebase_plan(
         preserve_timestamps: _,
         force_in_memory,
         force_on_disk,
+        strategy_options: _,
+        keep_originals,
     } = options;
 
     if !force_on_disk {
         use in_memory::*;
         writeln!(
-            effects.get_output_stream(),
+            effects.get_output_stream_at(Verbosity::Normal),
             "Attempting rebase in-memory..."
         )?;
 
@@ -849,6 +1278,14 @@ pub fn execute_rebase_plan(
                 )?;
             }
 
+            RebaseInMemoryResult::CannotRunExecCommand { command } => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Commands currently can't be run when rebasing in-memory."
+                )?;
+                writeln!(effects.get_output_stream(), "The command was: {}", command)?;
+            }
+
             RebaseInMemoryResult::MergeConflict {
                 commit_oid,
                 conflicting_paths: _,
@@ -866,18 +1303,42 @@ pub fn execute_rebase_plan(
                     )?,
                 )?;
             }
+            RebaseInMemoryResult::CannotSignCommit { commit_oid, err } => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The commit could not be signed in-memory: {}",
+                    err
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "The commit was: {}",
+                    printable_styled_string(
+                        effects.get_glyphs(),
+                        repo.friendly_describe_commit_from_oid(commit_oid)?
+                    )?,
+                )?;
+            }
         }
 
         // The rebase has failed at this point, decide whether or not to try
         // again with an on-disk rebase.
-        if *force_in_memory {
+        if *keep_originals {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting since commits can only be copied with an in-memory rebase."
+            )?;
+            return Ok(1);
+        } else if *force_in_memory {
             writeln!(
                 effects.get_output_stream(),
                 "Aborting since an in-memory rebase was requested."
             )?;
             return Ok(1);
         } else {
-            writeln!(effects.get_output_stream(), "Trying again on-disk...")?;
+            writeln!(
+                effects.get_output_stream_at(Verbosity::Normal),
+                "Trying again on-disk..."
+            )?;
         }
     }
 
@@ -15,7 +15,11 @@ use itertools::Itertools;
 use tempfile::NamedTempFile;
 use tracing::instrument;
 
-use crate::core::config::{get_restack_warn_abandoned, RESTACK_WARN_ABANDONED_CONFIG_KEY};
+use crate::commands::gc::write_commit_graph;
+use crate::core::config::{
+    get_hook_verbosity, get_restack_warn_abandoned, get_restack_warn_abandoned_threshold,
+    HookVerbosity, RESTACK_WARN_ABANDONED_CONFIG_KEY,
+};
 use crate::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use crate::core::formatting::{printable_styled_string, Pluralize};
 use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
@@ -25,6 +29,7 @@ use crate::git::{
 };
 use crate::tui::Effects;
 
+use super::execute::describe_upstream_applied_commit;
 use super::{find_abandoned_children, move_branches};
 
 #[instrument(skip(stream))]
@@ -93,6 +98,20 @@ fn add_rewritten_list_entries(
     Ok(())
 }
 
+/// Get the `Effects` for hook output of the given verbosity. The output is
+/// suppressed if the hooks are configured to be less verbose than `level`.
+pub(crate) fn get_hook_effects(
+    effects: &Effects,
+    verbosity: HookVerbosity,
+    level: HookVerbosity,
+) -> Effects {
+    if verbosity >= level {
+        effects.clone()
+    } else {
+        effects.suppress()
+    }
+}
+
 /// Handle Git's `post-rewrite` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -106,6 +125,9 @@ pub fn hook_post_rewrite(
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
 
     let repo = Repo::from_current_dir()?;
+    let verbosity = get_hook_verbosity(&repo)?;
+    let warning_effects = get_hook_effects(effects, verbosity, HookVerbosity::Warnings);
+    let effects = &get_hook_effects(effects, verbosity, HookVerbosity::Normal);
     let conn = repo.get_db_conn()?;
     let mut event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-rewrite")?;
@@ -156,7 +178,7 @@ pub fn hook_post_rewrite(
     let should_check_abandoned_commits = get_restack_warn_abandoned(&repo)?;
     if should_check_abandoned_commits && !is_spurious_event {
         warn_abandoned(
-            effects,
+            &warning_effects,
             &repo,
             &conn,
             &event_log_db,
@@ -164,6 +186,10 @@ pub fn hook_post_rewrite(
         )?;
     }
 
+    if !is_spurious_event {
+        write_commit_graph(effects, git_run_info, &repo)?;
+    }
+
     Ok(())
 }
 
@@ -291,7 +317,8 @@ fn warn_abandoned(
     let num_abandoned_children = all_abandoned_children.len();
     let num_abandoned_branches = all_abandoned_branches.len();
 
-    if num_abandoned_children > 0 || num_abandoned_branches > 0 {
+    let threshold = get_restack_warn_abandoned_threshold(repo)?;
+    if num_abandoned_children + num_abandoned_branches >= threshold {
         let warning_items = {
             let mut warning_items = Vec::new();
             if num_abandoned_children > 0 {
@@ -332,7 +359,8 @@ fn warn_abandoned(
             .bold()
             .yellow();
 
-        print!(
+        write!(
+            effects.get_output_stream(),
             "\
 branchless: {warning_message}
 branchless: Consider running one of the following:
@@ -353,7 +381,7 @@ branchless:   - {config_command}: suppress this message
                 RESTACK_WARN_ABANDONED_CONFIG_KEY
             ))
             .bold(),
-        );
+        )?;
     }
 
     Ok(())
@@ -427,6 +455,7 @@ pub fn hook_drop_commit_if_empty(
     old_commit_oid: NonZeroOid,
 ) -> eyre::Result<()> {
     let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
     let head_info = repo.get_head_info()?;
     let head_oid = match head_info.oid {
         Some(head_oid) => head_oid,
@@ -473,16 +502,25 @@ pub fn hook_drop_commit_if_empty(
 }
 
 /// For rebases, if a commit is known to have been applied upstream, skip it
-/// without attempting to apply it.
+/// without attempting to apply it. `upstream_commit_oid` is the upstream commit
+/// with the same changes, which isn't passed by rebase plans made by older
+/// versions.
 pub fn hook_skip_upstream_applied_commit(
     effects: &Effects,
     commit_oid: NonZeroOid,
+    upstream_commit_oid: Option<NonZeroOid>,
 ) -> eyre::Result<()> {
     let repo = Repo::from_current_dir()?;
+    let effects = &get_hook_effects(effects, get_hook_verbosity(&repo)?, HookVerbosity::Normal);
     let commit = repo.find_commit_or_fail(commit_oid)?;
+    let reason = match upstream_commit_oid {
+        Some(upstream_commit_oid) => describe_upstream_applied_commit(&repo, upstream_commit_oid)?,
+        None => "was already applied upstream".to_string(),
+    };
     writeln!(
         effects.get_output_stream(),
-        "Skipping commit (was already applied upstream): {}",
+        "Skipping commit ({}): {}",
+        reason,
         printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?
     )?;
 
@@ -500,3 +538,19 @@ pub fn hook_skip_upstream_applied_commit(
 
     Ok(())
 }
+
+/// For rebases, record that the commit which was just applied is the rewritten
+/// version of the given commit, which it replaced. Git only records it as the
+/// rewritten version of the replacement commit.
+pub fn hook_record_replaced_commit(commit_oid: NonZeroOid) -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    let head_oid = match repo.get_head_info()?.oid {
+        Some(head_oid) => head_oid,
+        None => return Ok(()),
+    };
+    add_rewritten_list_entries(
+        &repo.get_rebase_state_dir_path().join("rewritten-list"),
+        &[(commit_oid, MaybeZeroOid::NonZero(head_oid))],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! Copy notes from rewritten commits to their replacements.
+//!
+//! `git rebase` copies the notes in the refs configured by `notes.rewriteRef`
+//! when it rewrites commits, but in-memory rebases bypass it, so this
+//! implements the same behavior for them. See `git-notes(1)` for the
+//! configuration options involved.
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::time::SystemTime;
+
+use tracing::{instrument, warn};
+
+use crate::core::eventlog::EventTransactionId;
+use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceTarget, Repo};
+use crate::tui::Effects;
+
+/// How to combine a copied note with a note already attached to the rewritten
+/// commit, as set by `notes.rewriteMode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RewriteMode {
+    Overwrite,
+    Concatenate,
+    CatSortUniq,
+    Ignore,
+}
+
+impl RewriteMode {
+    fn combine(&self, existing_note: Option<String>, note: String) -> Option<String> {
+        let existing_note = match existing_note {
+            Some(existing_note) if !existing_note.trim().is_empty() => existing_note,
+            Some(_) | None => return Some(note),
+        };
+        match self {
+            RewriteMode::Overwrite => Some(note),
+            RewriteMode::Concatenate => Some(format!(
+                "{}\n\n{}",
+                existing_note.trim_end_matches('\n'),
+                note
+            )),
+            RewriteMode::CatSortUniq => {
+                let lines: BTreeSet<&str> = existing_note
+                    .lines()
+                    .chain(note.lines())
+                    .filter(|line| !line.trim().is_empty())
+                    .collect();
+                Some(
+                    lines
+                        .into_iter()
+                        .map(|line| format!("{}\n", line))
+                        .collect(),
+                )
+            }
+            RewriteMode::Ignore => None,
+        }
+    }
+}
+
+/// Get the mode to combine notes with, from `GIT_NOTES_REWRITE_MODE` or
+/// `notes.rewriteMode`.
+fn get_rewrite_mode(repo: &Repo) -> eyre::Result<RewriteMode> {
+    let mode = match std::env::var("GIT_NOTES_REWRITE_MODE") {
+        Ok(mode) => mode,
+        Err(_) => repo
+            .get_config()?
+            .get_or("notes.rewriteMode", "concatenate".to_string())?,
+    };
+    match mode.as_str() {
+        "overwrite" => Ok(RewriteMode::Overwrite),
+        "concatenate" => Ok(RewriteMode::Concatenate),
+        "cat_sort_uniq" => Ok(RewriteMode::CatSortUniq),
+        "ignore" => Ok(RewriteMode::Ignore),
+        mode => eyre::bail!("Invalid value for notes.rewriteMode: {:?}", mode),
+    }
+}
+
+/// Get the names of the existing notes references to copy notes from, as
+/// given by `GIT_NOTES_REWRITE_REF` or `notes.rewriteRef`. Like Git, no notes
+/// are copied unless one of these is set, or if `notes.rewrite.rebase` is
+/// `false`.
+fn get_rewrite_refs(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let config = repo.get_config()?;
+    let globs: Vec<String> = match std::env::var("GIT_NOTES_REWRITE_REF") {
+        Ok(globs) => globs.split(':').map(String::from).collect(),
+        Err(_) => {
+            if !config.get_or("notes.rewrite.rebase", true)? {
+                return Ok(Vec::new());
+            }
+            config.get_all("notes.rewriteRef")?
+        }
+    };
+
+    let mut result = BTreeSet::new();
+    for glob in globs {
+        if !glob.starts_with("refs/notes/") {
+            warn!(?glob, "Ignoring notes rewrite ref outside of refs/notes/");
+            continue;
+        }
+        for reference in repo.get_references_matching(&glob)? {
+            result.insert(reference.get_name()?.to_string_lossy().into_owned());
+        }
+    }
+    Ok(result.into_iter().collect())
+}
+
+fn get_notes_ref_oid(repo: &Repo, notes_ref: &str) -> eyre::Result<MaybeZeroOid> {
+    let reference = match repo.find_reference(&OsString::from(notes_ref))? {
+        Some(reference) => reference,
+        None => return Ok(MaybeZeroOid::Zero),
+    };
+    match reference.get_target()? {
+        ReferenceTarget::Direct { oid } => Ok(oid),
+        ReferenceTarget::Symbolic { .. } => {
+            eyre::bail!("Notes reference is symbolic: {:?}", notes_ref)
+        }
+    }
+}
+
+/// Copy the notes attached to each rewritten commit to the commit it was
+/// rewritten to. The updates to the notes references are recorded in the event
+/// log as part of the given transaction.
+#[instrument(skip(rewritten_oids))]
+pub fn copy_notes_for_rewrite(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    now: SystemTime,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+) -> eyre::Result<()> {
+    let notes_refs = get_rewrite_refs(repo)?;
+    if notes_refs.is_empty() {
+        return Ok(());
+    }
+    let mode = get_rewrite_mode(repo)?;
+    let signature = repo.get_branchless_signature(now)?;
+
+    let mut sorted_rewritten_oids: Vec<(NonZeroOid, NonZeroOid)> = rewritten_oids
+        .iter()
+        .filter_map(|(old_oid, new_oid)| match new_oid {
+            MaybeZeroOid::NonZero(new_oid) if new_oid != old_oid => Some((*old_oid, *new_oid)),
+            MaybeZeroOid::NonZero(_) | MaybeZeroOid::Zero => None,
+        })
+        .collect();
+    sorted_rewritten_oids.sort_unstable();
+
+    let mut stdin = String::new();
+    for notes_ref in notes_refs {
+        let old_ref_oid = get_notes_ref_oid(repo, &notes_ref)?;
+        for (old_oid, new_oid) in sorted_rewritten_oids.iter() {
+            let note = match repo.find_note(&notes_ref, *old_oid)? {
+                Some(note) => note,
+                None => continue,
+            };
+            let existing_note = repo.find_note(&notes_ref, *new_oid)?;
+            if let Some(note) = mode.combine(existing_note, note) {
+                repo.create_note(&notes_ref, *new_oid, &note, &signature)?;
+            }
+        }
+
+        let new_ref_oid = get_notes_ref_oid(repo, &notes_ref)?;
+        if new_ref_oid != old_ref_oid {
+            stdin.push_str(&format!("{} {} {}\n", old_ref_oid, new_ref_oid, notes_ref));
+        }
+    }
+
+    // Updating the notes references via libgit2 doesn't invoke the
+    // `reference-transaction` hook, so invoke it ourselves to record the
+    // updates.
+    if !stdin.is_empty() {
+        git_run_info.run_hook(
+            effects,
+            repo,
+            "reference-transaction",
+            event_tx_id,
+            &["committed"],
+            Some(OsString::from(stdin)),
+            &HashMap::new(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_notes() {
+        let existing_note = Some("b\na\n".to_string());
+        assert_eq!(
+            RewriteMode::Overwrite.combine(existing_note.clone(), "c\na\n".to_string()),
+            Some("c\na\n".to_string())
+        );
+        assert_eq!(
+            RewriteMode::Concatenate.combine(existing_note.clone(), "c\na\n".to_string()),
+            Some("b\na\n\nc\na\n".to_string())
+        );
+        assert_eq!(
+            RewriteMode::CatSortUniq.combine(existing_note.clone(), "c\na\n".to_string()),
+            Some("a\nb\nc\n".to_string())
+        );
+        assert_eq!(
+            RewriteMode::Ignore.combine(existing_note, "c\na\n".to_string()),
+            None
+        );
+        assert_eq!(
+            RewriteMode::Ignore.combine(None, "c\n".to_string()),
+            Some("c\n".to_string())
+        );
+    }
+}
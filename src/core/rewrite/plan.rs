@@ -19,9 +19,14 @@ thread_local! {
     static REPO: RefCell<Option<Repo>> = Default::default();
 }
 
+/// A reference to a commit in a rebase plan.
 #[derive(Debug)]
 pub enum OidOrLabel {
+    /// A commit which isn't rewritten by the rebase, referred to by its OID.
     Oid(NonZeroOid),
+
+    /// A commit created earlier during the rebase, referred to by the name of
+    /// the label created for it.
     Label(String),
 }
 
@@ -39,15 +44,37 @@ impl ToString for OidOrLabel {
 pub enum RebaseCommand {
     /// Create a label (a reference stored in `refs/rewritten/`) pointing to the
     /// current rebase head for later use.
-    CreateLabel { label_name: String },
+    CreateLabel {
+        /// The name of the label.
+        label_name: String,
+    },
 
     /// Move the rebase head to the provided label or commit.
-    Reset { target: OidOrLabel },
+    Reset {
+        /// The label or commit to move to.
+        target: OidOrLabel,
+    },
 
     /// Apply the provided commit on top of the rebase head, and update the
     /// rebase head to point to the newly-applied commit.
-    Pick { commit_oid: NonZeroOid },
+    Pick {
+        /// The commit to apply.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Apply the changes and message of the replacement commit on top of the
+    /// rebase head instead of those of the provided commit, and record the
+    /// newly-applied commit as the rewritten version of the provided commit.
+    Replace {
+        /// The commit being rewritten.
+        commit_oid: NonZeroOid,
 
+        /// The commit whose contents should be applied instead.
+        replacement_commit_oid: NonZeroOid,
+    },
+
+    /// Recreate the provided merge commit on top of the rebase head, merging
+    /// in the other provided commits.
     Merge {
         /// The original merge commit to copy the commit message from.
         commit_oid: NonZeroOid,
@@ -55,7 +82,9 @@ pub enum RebaseCommand {
         /// The other commits to merge into this one. This may be a list of
         /// either OIDs or strings. This will always be one fewer commit than
         /// the number of actual parents for this commit, since we always merge
-        /// into the current `HEAD` commit when rebasing.
+        /// into the current `HEAD` commit when rebasing. The rebase plan resets
+        /// `HEAD` to the first parent beforehand if necessary, so that the
+        /// parents stay in the same order.
         commits_to_merge: Vec<OidOrLabel>,
     },
 
@@ -65,11 +94,27 @@ pub enum RebaseCommand {
 
     /// Determine if the current commit is empty. If so, reset the rebase head
     /// to its parent and record that it was empty in the `rewritten-list`.
-    DetectEmptyCommit { commit_oid: NonZeroOid },
+    DetectEmptyCommit {
+        /// The original version of the commit which was just applied.
+        commit_oid: NonZeroOid,
+    },
 
     /// The commit that would have been applied to the rebase head was already
     /// applied upstream. Skip it and record it in the `rewritten-list`.
-    SkipUpstreamAppliedCommit { commit_oid: NonZeroOid },
+    SkipUpstreamAppliedCommit {
+        /// The commit to skip.
+        commit_oid: NonZeroOid,
+
+        /// The upstream commit with the same patch ID as the skipped commit.
+        upstream_commit_oid: NonZeroOid,
+    },
+
+    /// On-disk rebases only. Run the provided shell command with the rebase
+    /// head checked out. The rebase stops if the command fails.
+    Exec {
+        /// The shell command to run.
+        command: String,
+    },
 }
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
@@ -80,12 +125,31 @@ pub struct RebasePlan {
     pub(super) commands: Vec<RebaseCommand>,
 }
 
+impl RebasePlan {
+    /// The commit which the rebase starts from.
+    pub fn get_first_dest_oid(&self) -> NonZeroOid {
+        self.first_dest_oid
+    }
+
+    /// The commands to carry out, in order.
+    pub fn get_commands(&self) -> &[RebaseCommand] {
+        &self.commands
+    }
+}
+
 impl ToString for RebaseCommand {
     fn to_string(&self) -> String {
         match self {
             RebaseCommand::CreateLabel { label_name } => format!("label {}", label_name),
             RebaseCommand::Reset { target } => format!("reset {}", target.to_string()),
             RebaseCommand::Pick { commit_oid } => format!("pick {}", commit_oid),
+            RebaseCommand::Replace {
+                commit_oid,
+                replacement_commit_oid,
+            } => format!(
+                "pick {}\nexec git branchless hook-record-replaced-commit {}",
+                replacement_commit_oid, commit_oid
+            ),
             RebaseCommand::Merge {
                 commit_oid,
                 commits_to_merge,
@@ -106,12 +170,16 @@ impl ToString for RebaseCommand {
                     commit_oid
                 )
             }
-            RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => {
+            RebaseCommand::SkipUpstreamAppliedCommit {
+                commit_oid,
+                upstream_commit_oid,
+            } => {
                 format!(
-                    "exec git branchless hook-skip-upstream-applied-commit {}",
-                    commit_oid
+                    "exec git branchless hook-skip-upstream-applied-commit {} {}",
+                    commit_oid, upstream_commit_oid
                 )
             }
+            RebaseCommand::Exec { command } => format!("exec {}", command),
         }
     }
 }
@@ -151,6 +219,13 @@ struct BuildState {
 
 /// Builder for a rebase plan. Unlike regular Git rebases, a `git-branchless`
 /// rebase plan can move multiple unrelated subtrees to unrelated destinations.
+///
+/// The builder is also intended for use by other tools which need to make
+/// custom rewrites of the commit graph. Describe the rewrite with
+/// `move_subtree`, `replace_commit` and `add_exec_after`, then call `build` to
+/// get a `RebasePlan`, which can be carried out with `execute_rebase_plan`.
+/// Commits which aren't mentioned are left alone, except for the descendants
+/// of rewritten commits, which are rebased on top of their rewritten parents.
 #[derive(Debug)]
 pub struct RebasePlanBuilder<'repo, M: MergeBaseDb + 'repo> {
     repo: &'repo Repo,
@@ -161,6 +236,13 @@ pub struct RebasePlanBuilder<'repo, M: MergeBaseDb + 'repo> {
     /// There is a mapping from from `x` to `y` if `x` must be applied before
     /// `y`.
     initial_constraints: HashMap<NonZeroOid, HashSet<NonZeroOid>>,
+
+    /// There is a mapping from `x` to `y` if the contents of `y` should be
+    /// applied in place of `x`.
+    replacement_commits: HashMap<NonZeroOid, NonZeroOid>,
+
+    /// The shell commands to run after applying each commit.
+    exec_commands: HashMap<NonZeroOid, Vec<String>>,
 }
 
 /// Can't `#[derive(Clone)]` because of the parametrized `M`, which isn't
@@ -174,6 +256,8 @@ impl<'repo, M: MergeBaseDb + 'repo> Clone for RebasePlanBuilder<'repo, M> {
             merge_base_db: self.merge_base_db,
             main_branch_oid: self.main_branch_oid,
             initial_constraints: self.initial_constraints.clone(),
+            replacement_commits: self.replacement_commits.clone(),
+            exec_commands: self.exec_commands.clone(),
         }
     }
 }
@@ -207,6 +291,13 @@ pub enum BuildRebasePlanError {
         /// The OIDs of the commits in the cycle. The first and the last OIDs are the same.
         cycle_oids: Vec<NonZeroOid>,
     },
+
+    /// One of the commits to be moved is an octopus merge (a merge commit
+    /// with more than two parents), which can't be rebased.
+    OctopusMerge {
+        /// The OID of the octopus merge commit.
+        commit_oid: NonZeroOid,
+    },
 }
 
 impl BuildRebasePlanError {
@@ -250,6 +341,24 @@ impl BuildRebasePlanError {
                     )?;
                 }
             }
+
+            BuildRebasePlanError::OctopusMerge { commit_oid } => {
+                let commit = repo.find_commit_or_fail(*commit_oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "This operation failed because it would rebase an octopus merge commit with {} parents:",
+                    commit.get_parent_count(),
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?,
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Merge commits with more than two parents can't be rebased yet."
+                )?;
+            }
         }
         Ok(())
     }
@@ -270,6 +379,8 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
             merge_base_db,
             main_branch_oid: *main_branch_oid,
             initial_constraints: Default::default(),
+            replacement_commits: Default::default(),
+            exec_commands: Default::default(),
         }
     }
 
@@ -290,27 +401,28 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         state: &mut BuildState,
         previous_head_oid: NonZeroOid,
         current_commit: Commit,
-        upstream_patch_ids: &HashSet<PatchId>,
+        upstream_patch_ids: &HashMap<PatchId, NonZeroOid>,
         mut acc: Vec<RebaseCommand>,
     ) -> eyre::Result<Vec<RebaseCommand>> {
-        let patch_already_applied_upstream = {
+        let upstream_commit_oid = {
             if upstream_patch_ids.is_empty() {
                 // Save time in the common case that there are no
                 // similar-looking upstream commits, so that we don't have
                 // to calculate the diff for the patch ID.
-                false
+                None
             } else {
                 match self.repo.get_patch_id(effects, &current_commit)? {
-                    Some(current_patch_id) => upstream_patch_ids.contains(&current_patch_id),
-                    None => false,
+                    Some(current_patch_id) => upstream_patch_ids.get(&current_patch_id).copied(),
+                    None => None,
                 }
             }
         };
 
         let acc = {
-            if patch_already_applied_upstream {
+            if let Some(upstream_commit_oid) = upstream_commit_oid {
                 acc.push(RebaseCommand::SkipUpstreamAppliedCommit {
                     commit_oid: current_commit.get_oid(),
+                    upstream_commit_oid,
                 });
             } else if current_commit.get_parent_count() > 1 {
                 // This is a merge commit. We need to make sure that all parent
@@ -318,15 +430,25 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
                 // applying this commit. Note that parent commits may or may not
                 // be part of the set of commits to rebase (i.e. may or may not
                 // be mentioned in the constraints).
-                let commits_to_merge: Option<Vec<OidOrLabel>> = current_commit
-                    .get_parent_oids()
-                    .into_iter()
-                    .filter(|parent_oid| {
-                        // Don't include the parent which was the previous HEAD
-                        // commit, since we need to merge into that commit.
-                        *parent_oid != previous_head_oid
-                    })
-                    .map(|parent_oid| -> Option<OidOrLabel> {
+                let parent_oids = current_commit.get_parent_oids();
+
+                // The rebase head corresponds to the parent which we reached
+                // this commit from. If the merge commit itself is being moved
+                // onto a new destination, then the destination takes the place
+                // of its first parent.
+                let head_parent_index = parent_oids
+                    .iter()
+                    .position(|parent_oid| *parent_oid == previous_head_oid)
+                    .unwrap_or(0);
+                let parents_to_merge: Option<Vec<OidOrLabel>> = parent_oids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, parent_oid)| -> Option<OidOrLabel> {
+                        let parent_oid = if i == head_parent_index {
+                            previous_head_oid
+                        } else {
+                            *parent_oid
+                        };
                         let does_parent_commit_need_rebase =
                             state.commits_to_move.contains(&parent_oid);
                         if does_parent_commit_need_rebase {
@@ -341,7 +463,7 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
                             state
                                 .merge_commit_parent_labels
                                 .get(&parent_oid)
-                                .map(|parent_oid| OidOrLabel::Label(parent_oid.clone()))
+                                .map(|label_name| OidOrLabel::Label(label_name.clone()))
                         } else {
                             // This parent commit was not supposed to be
                             // rebased, so its OID won't change and we can
@@ -351,11 +473,22 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
                     })
                     .collect();
 
-                if let Some(commits_to_merge) = commits_to_merge {
-                    // All parents have been committed.
+                if let Some(parents_to_merge) = parents_to_merge {
+                    // All parents have been committed. Merge into the first
+                    // parent, so that the parents of the new merge commit are
+                    // in the same order as those of the original.
+                    let mut parents_to_merge = parents_to_merge.into_iter();
+                    let first_parent = parents_to_merge.next();
+                    if head_parent_index != 0 {
+                        if let Some(first_parent) = first_parent {
+                            acc.push(RebaseCommand::Reset {
+                                target: first_parent,
+                            });
+                        }
+                    }
                     acc.push(RebaseCommand::Merge {
                         commit_oid: current_commit.get_oid(),
-                        commits_to_merge,
+                        commits_to_merge: parents_to_merge.collect(),
                     });
                 } else {
                     // Wait for the caller to come back to this commit
@@ -365,13 +498,24 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
             } else {
                 // Normal one-parent commit (or a zero-parent commit?), just
                 // rebase it and continue.
-                acc.push(RebaseCommand::Pick {
-                    commit_oid: current_commit.get_oid(),
-                });
+                match self.replacement_commits.get(&current_commit.get_oid()) {
+                    Some(replacement_commit_oid) => acc.push(RebaseCommand::Replace {
+                        commit_oid: current_commit.get_oid(),
+                        replacement_commit_oid: *replacement_commit_oid,
+                    }),
+                    None => acc.push(RebaseCommand::Pick {
+                        commit_oid: current_commit.get_oid(),
+                    }),
+                }
                 acc.push(RebaseCommand::DetectEmptyCommit {
                     commit_oid: current_commit.get_oid(),
                 });
             }
+            if let Some(commands) = self.exec_commands.get(&current_commit.get_oid()) {
+                acc.extend(commands.iter().map(|command| RebaseCommand::Exec {
+                    command: command.clone(),
+                }));
+            }
             acc
         };
 
@@ -452,6 +596,10 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
 
     /// Generate a sequence of rebase steps that cause the subtree at `source_oid`
     /// to be rebased on top of `dest_oid`.
+    ///
+    /// This can be called any number of times to add constraints for
+    /// different subtrees. If `dest_oid` is itself moved, then the subtree is
+    /// rebased on top of the rewritten version of `dest_oid`.
     pub fn move_subtree(
         &mut self,
         source_oid: NonZeroOid,
@@ -464,6 +612,83 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         Ok(())
     }
 
+    /// When applying the commit at `commit_oid`, apply the changes and commit
+    /// message of the commit at `replacement_commit_oid` instead. The result is
+    /// treated as the rewritten version of `commit_oid`, so its branches and
+    /// descendants are moved to it.
+    ///
+    /// The changes are those between the replacement commit and its parent,
+    /// so the replacement is usually made on top of the parent of
+    /// `commit_oid`. If `commit_oid` isn't otherwise moved, then it's rewritten
+    /// in place. Merge commits can't be replaced.
+    pub fn replace_commit(
+        &mut self,
+        commit_oid: NonZeroOid,
+        replacement_commit_oid: NonZeroOid,
+    ) -> eyre::Result<()> {
+        for oid in [commit_oid, replacement_commit_oid] {
+            if self
+                .repo
+                .find_commit_or_fail(oid)?
+                .get_only_parent_oid()
+                .is_none()
+            {
+                eyre::bail!(
+                    "Only commits with exactly one parent can be replaced: {:?}",
+                    oid
+                );
+            }
+        }
+        self.replacement_commits
+            .insert(commit_oid, replacement_commit_oid);
+        Ok(())
+    }
+
+    /// Run the shell command `command` after applying the commit at
+    /// `commit_oid`, with the rewritten commit checked out, as `git rebase
+    /// --exec` does. The command is only run if the commit is rewritten as
+    /// part of the rebase plan. Commands for the same commit are run in the
+    /// order that they were added.
+    ///
+    /// Rebase plans with commands can't be executed in-memory.
+    pub fn add_exec_after(&mut self, commit_oid: NonZeroOid, command: impl Into<String>) {
+        self.exec_commands
+            .entry(commit_oid)
+            .or_default()
+            .push(command.into());
+    }
+
+    /// Add constraints to rewrite each replaced commit in place, unless it's
+    /// already being moved somewhere else.
+    fn add_replacement_constraints(&self, state: &mut BuildState) -> eyre::Result<()> {
+        for commit_oid in self.replacement_commits.keys().sorted() {
+            let is_moved = state
+                .constraints
+                .values()
+                .any(|child_oids| child_oids.contains(commit_oid));
+            if is_moved {
+                continue;
+            }
+            let parent_oid = match self
+                .repo
+                .find_commit_or_fail(*commit_oid)?
+                .get_only_parent_oid()
+            {
+                Some(parent_oid) => parent_oid,
+                None => eyre::bail!(
+                    "BUG: replaced commit should have one parent: {:?}",
+                    commit_oid
+                ),
+            };
+            state
+                .constraints
+                .entry(parent_oid)
+                .or_default()
+                .insert(*commit_oid);
+        }
+        Ok(())
+    }
+
     #[instrument]
     fn collect_descendants(
         &self,
@@ -543,11 +768,19 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
             }
             acc
         };
+
+        // A commit which was explicitly moved somewhere stays there, even if
+        // it's also a descendant of another moved commit.
+        let explicitly_constrained_oids: HashSet<NonZeroOid> =
+            state.constraints.values().flatten().copied().collect();
         for Constraint {
             parent_oid,
             child_oid,
         } in all_descendants_of_constrained_nodes
         {
+            if explicitly_constrained_oids.contains(&child_oid) {
+                continue;
+            }
             state
                 .constraints
                 .entry(parent_oid)
@@ -577,6 +810,10 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
                 self.check_for_cycles_helper(state, path, *child_oid)?;
             }
         }
+        // A commit may be reachable along several paths without there being a
+        // cycle, such as a merge commit whose parents are both moved, so only
+        // the commits on the current path are considered.
+        path.pop();
         Ok(())
     }
 
@@ -594,6 +831,24 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         Ok(())
     }
 
+    /// The rebase plan can only recreate merge commits with two parents, so
+    /// refuse to move octopus merges rather than dropping some of their
+    /// parents.
+    fn check_for_octopus_merges(
+        &self,
+        state: &BuildState,
+    ) -> eyre::Result<Result<(), BuildRebasePlanError>> {
+        for commit_oid in state.commits_to_move.iter().sorted() {
+            let commit = self.repo.find_commit_or_fail(*commit_oid)?;
+            if commit.get_parent_count() > 2 {
+                return Ok(Err(BuildRebasePlanError::OctopusMerge {
+                    commit_oid: *commit_oid,
+                }));
+            }
+        }
+        Ok(Ok(()))
+    }
+
     fn find_roots(&self, state: &BuildState) -> Vec<Constraint> {
         let unconstrained_nodes = {
             let mut unconstrained_nodes: HashSet<NonZeroOid> =
@@ -650,6 +905,7 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         };
 
         let (effects, _progress) = effects.start_operation(OperationType::BuildRebasePlan);
+        self.add_replacement_constraints(&mut state)?;
 
         if *dump_rebase_constraints {
             // For test: don't print to `effects.get_output_stream()`, as it will
@@ -672,6 +928,9 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         if let Err(err) = self.check_for_cycles(&state, &effects) {
             return Ok(Err(err));
         }
+        if let Err(err) = self.check_for_octopus_merges(&state)? {
+            return Ok(Err(err));
+        }
 
         let roots = self.find_roots(&state);
         let mut acc = vec![RebaseCommand::RegisterExtraPostRewriteHook];
@@ -727,13 +986,18 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
                 RebaseCommand::CreateLabel { label_name: _ }
                 | RebaseCommand::Reset { target: _ }
                 | RebaseCommand::RegisterExtraPostRewriteHook
-                | RebaseCommand::DetectEmptyCommit { commit_oid: _ } => None,
+                | RebaseCommand::DetectEmptyCommit { commit_oid: _ }
+                | RebaseCommand::Exec { command: _ } => None,
                 RebaseCommand::Pick { commit_oid }
+                | RebaseCommand::Replace {
+                    commit_oid,
+                    replacement_commit_oid: _,
+                }
                 | RebaseCommand::Merge {
                     commit_oid,
                     commits_to_merge: _,
                 }
-                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => Some(*commit_oid),
+                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid, .. } => Some(*commit_oid),
             })
             .collect();
         let missing_commit_oids: HashSet<NonZeroOid> = state
@@ -760,12 +1024,12 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         state: &mut BuildState,
         current_oid: NonZeroOid,
         dest_oid: NonZeroOid,
-    ) -> eyre::Result<HashSet<PatchId>> {
+    ) -> eyre::Result<HashMap<PatchId, NonZeroOid>> {
         let merge_base_oid =
             self.merge_base_db
                 .get_merge_base_oid(effects, self.repo, dest_oid, current_oid)?;
         let merge_base_oid = match merge_base_oid {
-            None => return Ok(HashSet::new()),
+            None => return Ok(HashMap::new()),
             Some(merge_base_oid) => merge_base_oid,
         };
 
@@ -776,7 +1040,7 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
             merge_base_oid,
         )?;
         let path = match path {
-            None => return Ok(HashSet::new()),
+            None => return Ok(HashMap::new()),
             Some(path) => path,
         };
 
@@ -797,7 +1061,7 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
         // cached.
         let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
         progress.notify_progress(0, path.len());
-        let result: HashSet<PatchId> = {
+        let result: HashMap<PatchId, NonZeroOid> = {
             let path_oids = path
                 .into_iter()
                 .map(|commit| commit.get_oid())
@@ -806,21 +1070,23 @@ impl<'repo, M: MergeBaseDb + 'repo> RebasePlanBuilder<'repo, M> {
             pool.install(|| {
                 path_oids
                     .into_par_iter()
-                    .map(|commit_oid| -> eyre::Result<Option<PatchId>> {
-                        REPO.with(|repo| {
-                            let repo = repo.borrow();
-                            let repo = repo.as_ref().expect("Could not get thread-local repo");
-                            let commit = match repo.find_commit(commit_oid)? {
-                                Some(commit) => commit,
-                                None => return Ok(None),
-                            };
-                            let result = repo.get_patch_id(&effects, &commit)?;
-                            Ok(result)
-                        })
-                    })
+                    .map(
+                        |commit_oid| -> eyre::Result<Option<(PatchId, NonZeroOid)>> {
+                            REPO.with(|repo| {
+                                let repo = repo.borrow();
+                                let repo = repo.as_ref().expect("Could not get thread-local repo");
+                                let commit = match repo.find_commit(commit_oid)? {
+                                    Some(commit) => commit,
+                                    None => return Ok(None),
+                                };
+                                let result = repo.get_patch_id(&effects, &commit)?;
+                                Ok(result.map(|patch_id| (patch_id, commit_oid)))
+                            })
+                        },
+                    )
                     .inspect(|_| progress.notify_progress_inc(1))
                     .filter_map(|result| result.transpose())
-                    .collect::<eyre::Result<HashSet<PatchId>>>()
+                    .collect::<eyre::Result<HashMap<PatchId, NonZeroOid>>>()
             })?
         };
         Ok(result)
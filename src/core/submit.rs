@@ -0,0 +1,610 @@
+//! Submit stacks of commits for code review.
+//!
+//! A stack is the sequence of draft commits between the main branch and `HEAD`.
+//! It's divided into segments, each of which ends at a commit with a branch
+//! pointing to it. A `SubmitBackend` decides how the segments are presented to
+//! the code review system: for example, as one pull request per segment.
+
+mod gerrit;
+mod github;
+mod phabricator;
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::time::SystemTime;
+
+use eyre::Context;
+use rusqlite::OptionalExtension;
+use tracing::instrument;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::mergebase::make_merge_base_db;
+use crate::core::rewrite::{finish_rewrite_in_memory, ExecuteRebasePlanOptions};
+use crate::git::{Commit, CommitSigner, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use crate::tui::Effects;
+
+pub use gerrit::GerritBackend;
+pub use github::GitHubBackend;
+pub use phabricator::PhabricatorBackend;
+
+/// A contiguous part of a stack which is reviewed as a unit.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StackSegment {
+    /// The name of the branch pointing to the last commit in this segment,
+    /// without the `refs/heads/` prefix.
+    pub branch_name: String,
+
+    /// The name of the branch which this segment will be merged into. This is
+    /// the branch of the previous segment, or the main branch for the first
+    /// segment.
+    pub base_branch_name: String,
+
+    /// The commits in this segment, ordered from oldest to newest.
+    pub commit_oids: Vec<NonZeroOid>,
+
+    /// The title to use for the review, derived from the commit messages.
+    pub title: String,
+
+    /// The description to use for the review, derived from the commit
+    /// messages.
+    pub body: String,
+}
+
+/// The draft commits between the main branch and `HEAD`.
+#[derive(Debug)]
+pub struct Stack {
+    /// The name of the main branch on the remote, which the stack will
+    /// eventually be merged into.
+    pub main_branch_name: String,
+
+    /// All the commits in the stack, ordered from oldest to newest.
+    pub commit_oids: Vec<NonZeroOid>,
+
+    /// The segments of the stack, ordered from oldest to newest.
+    pub segments: Vec<StackSegment>,
+
+    /// The commits at the top of the stack which don't have a branch pointing
+    /// to them or to one of their descendants in the stack, ordered from
+    /// oldest to newest.
+    pub unbranched_commit_oids: Vec<NonZeroOid>,
+}
+
+/// Options for submitting a stack.
+#[derive(Clone, Debug, Default)]
+pub struct SubmitOptions {
+    /// Only push the branches whose commits changed since they were last
+    /// submitted, rather than force-pushing all of them.
+    pub update: bool,
+
+    /// Create new reviews as drafts, unless their segment was previously
+    /// marked as ready for review.
+    pub draft: bool,
+
+    /// Create new reviews as ready for review, and mark existing draft reviews
+    /// as ready for review.
+    pub ready: bool,
+}
+
+/// A code review system which stacks can be submitted to.
+pub trait SubmitBackend {
+    /// Print what would be submitted for the given stack, without submitting
+    /// anything.
+    fn describe(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        stack: &Stack,
+        options: &SubmitOptions,
+    ) -> eyre::Result<()>;
+
+    /// Submit the given stack for review, creating or updating reviews as
+    /// necessary.
+    ///
+    /// Returns an exit code (0 denotes successful exit).
+    fn submit(
+        &mut self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        stack: &Stack,
+        options: &SubmitOptions,
+    ) -> eyre::Result<isize>;
+}
+
+/// Records which commit was last pushed to each branch of each remote when
+/// submitting, so that unchanged branches can be skipped when resubmitting,
+/// and whether the review for each branch is a draft, so that resubmitting
+/// doesn't reset it.
+pub struct PushedBranchesDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for PushedBranchesDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<PushedBranchesDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS submit_pushed_branches (
+    remote_name TEXT NOT NULL,
+    branch_name TEXT NOT NULL,
+    commit_oid TEXT NOT NULL,
+    PRIMARY KEY (remote_name, branch_name)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `submit_pushed_branches` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS submit_draft_states (
+    remote_name TEXT NOT NULL,
+    branch_name TEXT NOT NULL,
+    is_draft INTEGER NOT NULL,
+    PRIMARY KEY (remote_name, branch_name)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `submit_draft_states` table")?;
+    Ok(())
+}
+
+impl<'conn> PushedBranchesDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn).wrap_err("Initializing tables")?;
+        Ok(PushedBranchesDb { conn })
+    }
+
+    /// Get the commit which was last pushed to the given branch on the given
+    /// remote, if any.
+    #[instrument]
+    pub fn get_pushed_oid(
+        &self,
+        remote_name: &str,
+        branch_name: &str,
+    ) -> eyre::Result<Option<NonZeroOid>> {
+        let commit_oid: Option<String> = self
+            .conn
+            .query_row(
+                "
+SELECT commit_oid
+FROM submit_pushed_branches
+WHERE remote_name = :remote_name
+  AND branch_name = :branch_name
+",
+                rusqlite::named_params! {
+                    ":remote_name": remote_name,
+                    ":branch_name": branch_name,
+                },
+                |row| row.get("commit_oid"),
+            )
+            .optional()
+            .wrap_err("Querying pushed branches")?;
+        match commit_oid {
+            Some(commit_oid) => Ok(Some(
+                commit_oid.parse().wrap_err("Parsing pushed commit OID")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Record that the given commit was pushed to the given branch on the given
+    /// remote.
+    #[instrument]
+    pub fn set_pushed_oid(
+        &self,
+        remote_name: &str,
+        branch_name: &str,
+        commit_oid: NonZeroOid,
+    ) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO submit_pushed_branches
+VALUES (:remote_name, :branch_name, :commit_oid)
+",
+                rusqlite::named_params! {
+                    ":remote_name": remote_name,
+                    ":branch_name": branch_name,
+                    ":commit_oid": commit_oid.to_string(),
+                },
+            )
+            .wrap_err("Recording pushed branch")?;
+        Ok(())
+    }
+
+    /// Get whether the review for the given branch on the given remote was last
+    /// known to be a draft, if it has been submitted before.
+    #[instrument]
+    pub fn get_is_draft(&self, remote_name: &str, branch_name: &str) -> eyre::Result<Option<bool>> {
+        self.conn
+            .query_row(
+                "
+SELECT is_draft
+FROM submit_draft_states
+WHERE remote_name = :remote_name
+  AND branch_name = :branch_name
+",
+                rusqlite::named_params! {
+                    ":remote_name": remote_name,
+                    ":branch_name": branch_name,
+                },
+                |row| row.get("is_draft"),
+            )
+            .optional()
+            .wrap_err("Querying draft states")
+    }
+
+    /// Record whether the review for the given branch on the given remote is a
+    /// draft.
+    #[instrument]
+    pub fn set_is_draft(
+        &self,
+        remote_name: &str,
+        branch_name: &str,
+        is_draft: bool,
+    ) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO submit_draft_states
+VALUES (:remote_name, :branch_name, :is_draft)
+",
+                rusqlite::named_params! {
+                    ":remote_name": remote_name,
+                    ":branch_name": branch_name,
+                    ":is_draft": is_draft,
+                },
+            )
+            .wrap_err("Recording draft state")?;
+        Ok(())
+    }
+}
+
+/// Describe a list of branches for a summary, such as `2 branches (foo, bar)`.
+fn describe_branches(branch_names: &[&str]) -> String {
+    match branch_names {
+        [] => "0 branches".to_string(),
+        [branch_name] => format!("1 branch ({})", branch_name),
+        branch_names => format!(
+            "{} branches ({})",
+            branch_names.len(),
+            branch_names.join(", ")
+        ),
+    }
+}
+
+/// Get the name of the given branch as it's known on the given remote. For
+/// example, `origin/master` is known as `master` on the `origin` remote.
+pub fn get_remote_branch_name<'a>(branch_name: &'a str, remote_name: &str) -> &'a str {
+    branch_name
+        .strip_prefix(&format!("{}/", remote_name))
+        .unwrap_or(branch_name)
+}
+
+/// Determine the title and description for a review of the given commits,
+/// which are ordered from oldest to newest.
+fn describe_commits(repo: &Repo, commit_oids: &[NonZeroOid]) -> eyre::Result<(String, String)> {
+    let mut messages = Vec::new();
+    for commit_oid in commit_oids {
+        let commit = repo.find_commit_or_fail(*commit_oid)?;
+        messages.push(commit.get_message_pretty()?.to_string_lossy().into_owned());
+    }
+
+    let title_and_body = |message: &str| -> (String, String) {
+        let message = message.trim();
+        match message.split_once("\n\n") {
+            Some((title, body)) => (title.trim().to_string(), body.trim().to_string()),
+            None => (message.to_string(), String::new()),
+        }
+    };
+
+    match messages.as_slice() {
+        [message] => Ok(title_and_body(message)),
+        messages => {
+            let (title, _body) = match messages.first() {
+                Some(message) => title_and_body(message),
+                None => (String::new(), String::new()),
+            };
+            let body = messages
+                .iter()
+                .map(|message| format!("- {}", title_and_body(message).0))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok((title, body))
+        }
+    }
+}
+
+/// Determine whether the given line of a commit message is a trailer, such as
+/// `Signed-off-by: Foo <foo@example.com>`. Unlike Git, keys may contain spaces,
+/// as in Phabricator's `Differential Revision: D123`.
+fn is_trailer(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((key, _value)) => {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ' ')
+        }
+        None => false,
+    }
+}
+
+/// Get the trailers at the end of the given commit message, if any.
+fn get_trailers(message: &str) -> Option<Vec<&str>> {
+    let message = message.trim_end();
+    let (_, last_paragraph) = message.rsplit_once("\n\n")?;
+    let lines: Vec<&str> = last_paragraph.lines().collect();
+    if lines.iter().all(|line| is_trailer(line)) {
+        Some(lines)
+    } else {
+        None
+    }
+}
+
+/// Add a trailer with the given key and value to the given commit message,
+/// after any existing trailers.
+fn add_trailer(message: &str, key: &str, value: &str) -> String {
+    let separator = if get_trailers(message).is_some() {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    format!("{}{}{}: {}\n", message.trim_end(), separator, key, value)
+}
+
+/// Find the visible commits which descend from the given commits without
+/// being among them, such as the commits of a branch which forks off from the
+/// middle of the stack. Each commit is ordered after its parent.
+#[instrument]
+fn find_other_descendants(
+    effects: &Effects,
+    repo: &Repo,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(effects, repo, &conn, &event_replayer)?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        effects,
+        repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(repo.get_head_info()?.oid),
+        &MainBranchOid(repo.get_main_branch_oid()?),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let stack_oids: HashSet<NonZeroOid> = commit_oids.iter().copied().collect();
+    let mut result = Vec::new();
+    for commit_oid in commit_oids {
+        let mut oids_to_visit: Vec<NonZeroOid> = match graph.get(commit_oid) {
+            Some(node) => node
+                .children
+                .iter()
+                .filter(|child_oid| !stack_oids.contains(child_oid))
+                .copied()
+                .collect(),
+            None => continue,
+        };
+        oids_to_visit.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+        while let Some(oid) = oids_to_visit.pop() {
+            let node = &graph[&oid];
+            if !node.is_visible {
+                continue;
+            }
+            result.push(oid);
+            let mut children = node.children.clone();
+            children.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+            oids_to_visit.extend(children);
+        }
+    }
+    Ok(result)
+}
+
+/// Rewrite the messages of the given commits, which are ordered from oldest to
+/// newest. `make_message` is called with each commit and its message, and
+/// returns the new message, or `None` to keep the existing one. Descendants of
+/// rewritten commits are rewritten as well, including those which aren't in
+/// `commit_oids` (such as the commits of a branch which forks off from the
+/// stack), so that they aren't left on the original commits. The new commits
+/// are signed if `commit.gpgSign` is set.
+///
+/// Returns: The rewritten commits, as pairs of the original commit OID and the
+/// new commit OID.
+fn rewrite_commit_messages(
+    effects: &Effects,
+    repo: &Repo,
+    commit_oids: &[NonZeroOid],
+    mut make_message: impl FnMut(&Commit, &str) -> eyre::Result<Option<String>>,
+) -> eyre::Result<Vec<(NonZeroOid, MaybeZeroOid)>> {
+    let signer = CommitSigner::from_repo(repo)?;
+    let stack_oids: HashSet<NonZeroOid> = commit_oids.iter().copied().collect();
+    let descendant_oids = find_other_descendants(effects, repo, commit_oids)?;
+    let mut rewritten_oids_map: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+    let mut rewritten_oids = Vec::new();
+    for commit_oid in commit_oids.iter().chain(descendant_oids.iter()).copied() {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        let message = commit.get_message_raw()?.to_string_lossy().into_owned();
+        let parent_oids = commit.get_parent_oids();
+        let has_rewritten_parent = parent_oids
+            .iter()
+            .any(|parent_oid| rewritten_oids_map.contains_key(parent_oid));
+        let new_message = if stack_oids.contains(&commit_oid) {
+            make_message(&commit, &message)?
+        } else {
+            None
+        };
+        let new_message = match new_message {
+            Some(new_message) => new_message,
+            None if has_rewritten_parent => message,
+            None => continue,
+        };
+
+        let mut parents = Vec::new();
+        for parent_oid in parent_oids {
+            let parent_oid = rewritten_oids_map
+                .get(&parent_oid)
+                .copied()
+                .unwrap_or(parent_oid);
+            parents.push(repo.find_commit_or_fail(parent_oid)?);
+        }
+        let new_commit_oid = match &signer {
+            Some(signer) => repo.create_signed_commit(
+                signer,
+                &commit.get_author(),
+                &commit.get_committer(),
+                &new_message,
+                &commit.get_tree()?,
+                parents.iter().collect(),
+            )?,
+            None => repo.create_commit(
+                None,
+                &commit.get_author(),
+                &commit.get_committer(),
+                &new_message,
+                &commit.get_tree()?,
+                parents.iter().collect(),
+            )?,
+        };
+        rewritten_oids_map.insert(commit_oid, new_commit_oid);
+        rewritten_oids.push((commit_oid, MaybeZeroOid::NonZero(new_commit_oid)));
+    }
+    Ok(rewritten_oids)
+}
+
+/// Move `HEAD` and any branches from the original commits to the rewritten
+/// ones, and hide the original commits. If the commit at `HEAD` was rewritten,
+/// its rewritten version is checked out.
+///
+/// Returns an exit code (0 denotes successful exit).
+fn finish_rewrite(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+    rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+) -> eyre::Result<isize> {
+    let head_oid = repo.get_head_info()?.oid;
+    let new_head_oid = rewritten_oids
+        .iter()
+        .find_map(|(old_oid, new_oid)| match new_oid {
+            MaybeZeroOid::NonZero(new_oid) if Some(*old_oid) == head_oid => Some(*new_oid),
+            _ => None,
+        });
+    finish_rewrite_in_memory(
+        effects,
+        git_run_info,
+        repo,
+        rewritten_oids,
+        new_head_oid,
+        &ExecuteRebasePlanOptions {
+            now,
+            event_tx_id,
+            preserve_timestamps: true,
+            force_in_memory: true,
+            force_on_disk: false,
+            strategy_options: Vec::new(),
+            keep_originals: false,
+        },
+    )
+}
+
+/// Find the stack of draft commits ending at `HEAD`, and divide it into
+/// segments at the commits which have branches pointing to them. If a commit
+/// has several branches, the first one in sorted order is used.
+#[instrument(skip(graph))]
+pub fn find_stack(
+    repo: &Repo,
+    graph: &CommitGraph,
+    head_oid: NonZeroOid,
+    main_branch_name: &str,
+    branch_oid_to_names: &HashMap<NonZeroOid, HashSet<OsString>>,
+) -> eyre::Result<Stack> {
+    let mut commit_oids = Vec::new();
+    let mut current_oid = Some(head_oid);
+    while let Some(oid) = current_oid {
+        match graph.get(&oid) {
+            Some(node) if !node.is_main => {
+                commit_oids.push(oid);
+                current_oid = node.parent;
+            }
+            _ => break,
+        }
+    }
+    commit_oids.reverse();
+
+    let mut segments = Vec::new();
+    let mut segment_commit_oids = Vec::new();
+    for commit_oid in commit_oids.iter().copied() {
+        segment_commit_oids.push(commit_oid);
+        let branch_name = branch_oid_to_names.get(&commit_oid).and_then(|names| {
+            names
+                .iter()
+                .map(|name| {
+                    let name = name.to_string_lossy();
+                    name.strip_prefix("refs/heads/")
+                        .unwrap_or(&name)
+                        .to_string()
+                })
+                .min()
+        });
+        if let Some(branch_name) = branch_name {
+            let base_branch_name = match segments.last() {
+                Some(StackSegment { branch_name, .. }) => branch_name.clone(),
+                None => main_branch_name.to_string(),
+            };
+            let (title, body) = describe_commits(repo, &segment_commit_oids)?;
+            segments.push(StackSegment {
+                branch_name,
+                base_branch_name,
+                commit_oids: std::mem::take(&mut segment_commit_oids),
+                title,
+                body,
+            });
+        }
+    }
+
+    Ok(Stack {
+        main_branch_name: main_branch_name.to_string(),
+        commit_oids,
+        segments,
+        unbranched_commit_oids: segment_commit_oids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_trailer() {
+        assert_eq!(
+            add_trailer("foo\n", "Change-Id", "I123"),
+            "foo\n\nChange-Id: I123\n"
+        );
+        assert_eq!(
+            add_trailer("foo\n\nbar\n", "Change-Id", "I123"),
+            "foo\n\nbar\n\nChange-Id: I123\n"
+        );
+        assert_eq!(
+            add_trailer("foo\n\nSigned-off-by: Foo\n", "Change-Id", "I123"),
+            "foo\n\nSigned-off-by: Foo\nChange-Id: I123\n"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Submit stacks to Gerrit for review.
+//!
+//! Gerrit tracks each commit as a separate change, identified by the
+//! `Change-Id` footer in its commit message, and creates or updates changes
+//! when commits are pushed to `refs/for/<branch>`. Commits without a
+//! `Change-Id` footer are rewritten in memory to add one before pushing.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use tracing::instrument;
+
+use crate::core::eventlog::EventLogDb;
+use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::git::{Commit, GitRunInfo, MaybeZeroOid, Repo};
+use crate::tui::Effects;
+
+use super::{
+    add_trailer, finish_rewrite, get_trailers, rewrite_commit_messages, PushedBranchesDb, Stack,
+    SubmitBackend, SubmitOptions,
+};
+
+/// Get the value of the `Change-Id` trailer of the given commit message.
+fn get_change_id(message: &str) -> Option<&str> {
+    get_trailers(message)?
+        .into_iter()
+        .find_map(|line| line.strip_prefix("Change-Id: "))
+}
+
+/// Generate a new change ID for the given commit. Like the change IDs
+/// generated by Gerrit's `commit-msg` hook, it's derived from a hash of the
+/// commit.
+fn make_change_id(commit: &Commit, message: &str) -> eyre::Result<String> {
+    let oid = git2::Oid::hash_object(
+        git2::ObjectType::Blob,
+        format!("{}\n{}", commit.get_oid(), message).as_bytes(),
+    )?;
+    Ok(format!("I{}", oid))
+}
+
+/// Submits a stack by pushing its commits to `refs/for/<branch>`.
+#[derive(Debug)]
+pub struct GerritBackend {
+    remote_name: String,
+}
+
+impl GerritBackend {
+    /// Constructor.
+    pub fn new(remote_name: impl Into<String>) -> Self {
+        GerritBackend {
+            remote_name: remote_name.into(),
+        }
+    }
+}
+
+impl SubmitBackend for GerritBackend {
+    fn describe(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        stack: &Stack,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<()> {
+        if stack.commit_oids.is_empty() {
+            writeln!(effects.get_output_stream(), "No commits to submit.")?;
+            return Ok(());
+        }
+
+        writeln!(
+            effects.get_output_stream(),
+            "Would push to refs/for/{}:",
+            stack.main_branch_name
+        )?;
+        for commit_oid in stack.commit_oids.iter() {
+            let commit = repo.find_commit_or_fail(*commit_oid)?;
+            let message = commit.get_message_raw()?.to_string_lossy().into_owned();
+            writeln!(
+                effects.get_output_stream(),
+                "  {}{}",
+                printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?,
+                if get_change_id(&message).is_some() {
+                    ""
+                } else {
+                    " (needs Change-Id)"
+                }
+            )?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(stack))]
+    fn submit(
+        &mut self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        stack: &Stack,
+        options: &SubmitOptions,
+    ) -> eyre::Result<isize> {
+        let top_oid = match stack.commit_oids.last() {
+            Some(top_oid) => *top_oid,
+            None => {
+                writeln!(effects.get_output_stream(), "No commits to submit.")?;
+                return Ok(0);
+            }
+        };
+
+        let now = SystemTime::now();
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(now, "submit")?;
+
+        let mut num_change_ids_added = 0;
+        let rewritten_oids =
+            rewrite_commit_messages(effects, repo, &stack.commit_oids, |commit, message| {
+                match get_change_id(message) {
+                    Some(_) => Ok(None),
+                    None => {
+                        num_change_ids_added += 1;
+                        let change_id = make_change_id(commit, message)?;
+                        Ok(Some(add_trailer(message, "Change-Id", &change_id)))
+                    }
+                }
+            })?;
+        let new_top_oid = rewritten_oids
+            .iter()
+            .find_map(|(old_oid, new_oid)| match new_oid {
+                MaybeZeroOid::NonZero(new_oid) if *old_oid == top_oid => Some(*new_oid),
+                _ => None,
+            });
+        let top_oid = match new_top_oid {
+            Some(new_top_oid) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Added Change-Id to {}.",
+                    Pluralize {
+                        amount: num_change_ids_added,
+                        singular: "commit",
+                        plural: "commits",
+                    }
+                    .to_string()
+                )?;
+                let exit_code = finish_rewrite(
+                    effects,
+                    git_run_info,
+                    repo,
+                    now,
+                    event_tx_id,
+                    &rewritten_oids,
+                )?;
+                if exit_code != 0 {
+                    return Ok(exit_code);
+                }
+                new_top_oid
+            }
+            None => top_oid,
+        };
+
+        // Gerrit rejects pushes which don't contain any new changes, so don't
+        // push again if nothing changed since the last submission.
+        let target_ref_name = format!("refs/for/{}", stack.main_branch_name);
+        let pushed_branches_db = PushedBranchesDb::new(&conn)?;
+        if options.update
+            && pushed_branches_db.get_pushed_oid(&self.remote_name, &target_ref_name)?
+                == Some(top_oid)
+        {
+            writeln!(
+                effects.get_output_stream(),
+                "Skipped pushing to {}: unchanged since the last submission.",
+                target_ref_name
+            )?;
+            return Ok(0);
+        }
+
+        let args = vec![
+            "push".to_string(),
+            self.remote_name.clone(),
+            format!("{}:{}", top_oid, target_ref_name),
+        ];
+        let exit_code = git_run_info.run(effects, Some(event_tx_id), &args)?;
+        event_log_db.add_transaction_command(event_tx_id, &args, exit_code)?;
+        if exit_code == 0 {
+            pushed_branches_db.set_pushed_oid(&self.remote_name, &target_ref_name, top_oid)?;
+        }
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_change_id() {
+        assert_eq!(get_change_id("foo\n"), None);
+        assert_eq!(get_change_id("Change-Id: I123\n"), None);
+        assert_eq!(get_change_id("foo\n\nChange-Id: I123\n"), Some("I123"));
+        assert_eq!(
+            get_change_id("foo\n\nSigned-off-by: Foo\nChange-Id: I123\n"),
+            Some("I123")
+        );
+        assert_eq!(
+            get_change_id("foo\n\nChange-Id: I123\nnot a trailer\n"),
+            None
+        );
+    }
+}
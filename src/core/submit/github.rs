@@ -0,0 +1,265 @@
+//! Submit stacks as GitHub pull requests.
+//!
+//! Each segment of the stack is pushed to its branch on the remote and gets its
+//! own pull request, whose base is the branch of the previous segment. This
+//! way, each pull request only shows the changes of its own segment.
+
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::core::formatting::printable_styled_string;
+use crate::core::github::GitHubClient;
+use crate::git::{GitRunInfo, Repo};
+use crate::tui::Effects;
+
+use super::{
+    describe_branches, PushedBranchesDb, Stack, StackSegment, SubmitBackend, SubmitOptions,
+};
+
+/// Submits each segment of a stack as a GitHub pull request.
+#[derive(Debug)]
+pub struct GitHubBackend {
+    remote_name: String,
+}
+
+impl GitHubBackend {
+    /// Constructor.
+    pub fn new(remote_name: impl Into<String>) -> Self {
+        GitHubBackend {
+            remote_name: remote_name.into(),
+        }
+    }
+
+    /// Determine whether the given segment is unchanged since it was last
+    /// pushed, and so doesn't need to be pushed again.
+    fn is_segment_unchanged(
+        &self,
+        pushed_branches_db: &PushedBranchesDb,
+        segment: &StackSegment,
+        options: &SubmitOptions,
+    ) -> eyre::Result<bool> {
+        if !options.update {
+            return Ok(false);
+        }
+        let pushed_oid =
+            pushed_branches_db.get_pushed_oid(&self.remote_name, &segment.branch_name)?;
+        Ok(pushed_oid.is_some() && pushed_oid == segment.commit_oids.last().copied())
+    }
+
+    /// Determine whether a new pull request for the given segment should be
+    /// created as a draft. If the segment was submitted before, its previous
+    /// state is kept, so that a segment which was marked as ready for review
+    /// isn't made a draft again.
+    fn should_create_as_draft(
+        &self,
+        pushed_branches_db: &PushedBranchesDb,
+        segment: &StackSegment,
+        options: &SubmitOptions,
+    ) -> eyre::Result<bool> {
+        if options.ready {
+            return Ok(false);
+        }
+        let is_draft = pushed_branches_db.get_is_draft(&self.remote_name, &segment.branch_name)?;
+        Ok(is_draft.unwrap_or(options.draft))
+    }
+}
+
+/// Mark the given draft pull request as ready for review. The REST API can't
+/// change whether a pull request is a draft, so this uses the GraphQL API.
+fn mark_ready_for_review(client: &GitHubClient, pull_request: &Value) -> eyre::Result<()> {
+    let node_id = pull_request["node_id"]
+        .as_str()
+        .ok_or_else(|| eyre::eyre!("Pull request has no node ID: {}", pull_request))?;
+    client.graphql(
+        "mutation($id: ID!) { markPullRequestReadyForReview(input: { pullRequestId: $id }) { clientMutationId } }",
+        json!({ "id": node_id }),
+    )?;
+    Ok(())
+}
+
+/// Warn about the commits which won't be submitted because they have no
+/// branch.
+fn warn_unbranched_commits(effects: &Effects, repo: &Repo, stack: &Stack) -> eyre::Result<()> {
+    if stack.unbranched_commit_oids.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "These commits have no branch, so they won't be submitted:"
+    )?;
+    for commit_oid in stack.unbranched_commit_oids.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "  {}",
+            printable_styled_string(
+                effects.get_glyphs(),
+                repo.friendly_describe_commit_from_oid(*commit_oid)?
+            )?
+        )?;
+    }
+    Ok(())
+}
+
+impl SubmitBackend for GitHubBackend {
+    fn describe(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        stack: &Stack,
+        options: &SubmitOptions,
+    ) -> eyre::Result<()> {
+        warn_unbranched_commits(effects, repo, stack)?;
+        if stack.segments.is_empty() {
+            writeln!(effects.get_output_stream(), "No branches to submit.")?;
+        }
+        let conn = repo.get_db_conn()?;
+        let pushed_branches_db = PushedBranchesDb::new(&conn)?;
+        for segment in stack.segments.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "Would submit {} (onto {}{}{}): {}",
+                segment.branch_name,
+                segment.base_branch_name,
+                if self.is_segment_unchanged(&pushed_branches_db, segment, options)? {
+                    ", unchanged"
+                } else {
+                    ""
+                },
+                if self.should_create_as_draft(&pushed_branches_db, segment, options)? {
+                    ", draft"
+                } else {
+                    ""
+                },
+                segment.title
+            )?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(stack))]
+    fn submit(
+        &mut self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        stack: &Stack,
+        options: &SubmitOptions,
+    ) -> eyre::Result<isize> {
+        warn_unbranched_commits(effects, repo, stack)?;
+        if stack.segments.is_empty() {
+            writeln!(effects.get_output_stream(), "No branches to submit.")?;
+            return Ok(0);
+        }
+
+        let client = match GitHubClient::new(repo, &self.remote_name)? {
+            Ok(client) => client,
+            Err(message) => {
+                writeln!(effects.get_error_stream(), "{}", message)?;
+                return Ok(1);
+            }
+        };
+
+        let conn = repo.get_db_conn()?;
+        let pushed_branches_db = PushedBranchesDb::new(&conn)?;
+        let mut segments_to_push = Vec::new();
+        let mut skipped_branch_names = Vec::new();
+        for segment in stack.segments.iter() {
+            if self.is_segment_unchanged(&pushed_branches_db, segment, options)? {
+                skipped_branch_names.push(segment.branch_name.as_str());
+            } else {
+                segments_to_push.push(segment);
+            }
+        }
+
+        if !segments_to_push.is_empty() {
+            let mut args = vec![
+                "push".to_string(),
+                "--force-with-lease".to_string(),
+                self.remote_name.clone(),
+            ];
+            args.extend(
+                segments_to_push
+                    .iter()
+                    .map(|segment| segment.branch_name.clone()),
+            );
+            let exit_code = git_run_info.run(effects, None, &args)?;
+            if exit_code != 0 {
+                return Ok(exit_code);
+            }
+            for segment in segments_to_push.iter() {
+                if let Some(commit_oid) = segment.commit_oids.last() {
+                    pushed_branches_db.set_pushed_oid(
+                        &self.remote_name,
+                        &segment.branch_name,
+                        *commit_oid,
+                    )?;
+                }
+            }
+        }
+        let pushed_branch_names: Vec<&str> = segments_to_push
+            .iter()
+            .map(|segment| segment.branch_name.as_str())
+            .collect();
+        writeln!(
+            effects.get_output_stream(),
+            "Pushed {}, skipped {} unchanged.",
+            describe_branches(&pushed_branch_names),
+            describe_branches(&skipped_branch_names),
+        )?;
+
+        for segment in stack.segments.iter() {
+            let body = json!({
+                "title": segment.title,
+                "body": segment.body,
+                "base": segment.base_branch_name,
+            });
+            let (verb, pull_request) = match client.find_pull_request(&segment.branch_name)? {
+                Some(pull_request) => {
+                    let number = pull_request["number"].as_u64().ok_or_else(|| {
+                        eyre::eyre!("Pull request has no number: {}", pull_request)
+                    })?;
+                    let pull_request =
+                        client.request("PATCH", &format!("/pulls/{}", number), Some(&body))?;
+                    ("Updated", pull_request)
+                }
+                None => {
+                    let mut body = body;
+                    body["head"] = json!(segment.branch_name);
+                    body["draft"] = json!(self.should_create_as_draft(
+                        &pushed_branches_db,
+                        segment,
+                        options
+                    )?);
+                    let pull_request = client.request("POST", "/pulls", Some(&body))?;
+                    ("Created", pull_request)
+                }
+            };
+            let mut is_draft = pull_request["draft"].as_bool().unwrap_or(false);
+            writeln!(
+                effects.get_output_stream(),
+                "{} {}pull request #{} for {} (onto {}): {}",
+                verb,
+                if is_draft { "draft " } else { "" },
+                pull_request["number"],
+                segment.branch_name,
+                segment.base_branch_name,
+                pull_request["html_url"].as_str().unwrap_or("<no URL>")
+            )?;
+            if is_draft && options.ready {
+                mark_ready_for_review(&client, &pull_request)?;
+                is_draft = false;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Marked pull request #{} for {} as ready for review.",
+                    pull_request["number"],
+                    segment.branch_name,
+                )?;
+            }
+            pushed_branches_db.set_is_draft(&self.remote_name, &segment.branch_name, is_draft)?;
+        }
+        Ok(0)
+    }
+}
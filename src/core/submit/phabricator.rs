@@ -0,0 +1,199 @@
+//! Submit stacks to Phabricator as Differential revisions.
+//!
+//! Each commit in the stack becomes its own revision, which depends on the
+//! revision of the previous commit. Like `arc diff`, commits which don't have a
+//! revision yet are amended with a `Differential Revision` trailer pointing to
+//! the new revision, so that submitting them again updates it.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use serde_json::json;
+use tracing::instrument;
+
+use crate::core::eventlog::EventLogDb;
+use crate::core::formatting::printable_styled_string;
+use crate::core::metadata::extract_diff_number;
+use crate::core::phabricator::ConduitClient;
+use crate::git::{Commit, GitRunInfo, Repo};
+use crate::tui::Effects;
+
+use super::{
+    add_trailer, describe_commits, finish_rewrite, rewrite_commit_messages, Stack, SubmitBackend,
+    SubmitOptions,
+};
+
+/// Get the diff of the given commit against its parent, in the format expected
+/// by `differential.createrawdiff`.
+fn get_raw_diff(git_run_info: &GitRunInfo, repo: &Repo, commit: &Commit) -> eyre::Result<String> {
+    let parent_oid = match commit.get_parent_oids().first() {
+        Some(parent_oid) => *parent_oid,
+        None => eyre::bail!(
+            "Cannot submit root commit {:?} to Phabricator",
+            commit.get_oid()
+        ),
+    };
+    git_run_info.run_silent(
+        repo,
+        None,
+        &[
+            "diff".to_string(),
+            "--no-color".to_string(),
+            "--no-ext-diff".to_string(),
+            "--full-index".to_string(),
+            "--binary".to_string(),
+            "-M".to_string(),
+            // Phabricator shows the whole file, so include it all as context,
+            // as `arc diff` does.
+            "-U32767".to_string(),
+            parent_oid.to_string(),
+            commit.get_oid().to_string(),
+        ],
+    )
+}
+
+/// Submits each commit of a stack as a Differential revision.
+#[derive(Debug, Default)]
+pub struct PhabricatorBackend;
+
+impl PhabricatorBackend {
+    /// Constructor.
+    pub fn new() -> Self {
+        PhabricatorBackend
+    }
+}
+
+impl SubmitBackend for PhabricatorBackend {
+    fn describe(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        stack: &Stack,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<()> {
+        if stack.commit_oids.is_empty() {
+            writeln!(effects.get_output_stream(), "No commits to submit.")?;
+            return Ok(());
+        }
+
+        for commit_oid in stack.commit_oids.iter() {
+            let commit = repo.find_commit_or_fail(*commit_oid)?;
+            let description =
+                printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?;
+            match extract_diff_number(&commit.get_message_raw()?.to_string_lossy()) {
+                Some(diff_number) => writeln!(
+                    effects.get_output_stream(),
+                    "Would update {}: {}",
+                    diff_number,
+                    description
+                )?,
+                None => writeln!(
+                    effects.get_output_stream(),
+                    "Would create a revision for: {}",
+                    description
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(stack))]
+    fn submit(
+        &mut self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        stack: &Stack,
+        _options: &SubmitOptions,
+    ) -> eyre::Result<isize> {
+        if stack.commit_oids.is_empty() {
+            writeln!(effects.get_output_stream(), "No commits to submit.")?;
+            return Ok(0);
+        }
+
+        let client = ConduitClient::new(repo)?;
+        let now = SystemTime::now();
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(now, "submit")?;
+
+        let mut parent_revision_phid: Option<String> = None;
+        let rewritten_oids =
+            rewrite_commit_messages(effects, repo, &stack.commit_oids, |commit, message| {
+                let raw_diff = get_raw_diff(git_run_info, repo, commit)?;
+                let diff =
+                    client.call("differential.createrawdiff", &json!({ "diff": raw_diff }))?;
+                let diff_phid = diff["phid"]
+                    .as_str()
+                    .ok_or_else(|| eyre::eyre!("Diff has no PHID: {}", diff))?;
+
+                let mut transactions = vec![json!({ "type": "update", "value": diff_phid })];
+                if let Some(parent_revision_phid) = &parent_revision_phid {
+                    transactions.push(json!({
+                        "type": "parents.set",
+                        "value": [parent_revision_phid],
+                    }));
+                }
+                let diff_number = extract_diff_number(message);
+                let params = match &diff_number {
+                    Some(diff_number) => json!({
+                        "objectIdentifier": diff_number,
+                        "transactions": transactions,
+                    }),
+                    None => {
+                        let (title, summary) = describe_commits(repo, &[commit.get_oid()])?;
+                        transactions.push(json!({ "type": "title", "value": title }));
+                        transactions.push(json!({ "type": "summary", "value": summary }));
+                        json!({ "transactions": transactions })
+                    }
+                };
+                let revision = client.call("differential.revision.edit", &params)?;
+                let revision_id = revision["object"]["id"]
+                    .as_u64()
+                    .ok_or_else(|| eyre::eyre!("Revision has no ID: {}", revision))?;
+                parent_revision_phid = revision["object"]["phid"].as_str().map(String::from);
+
+                let description =
+                    printable_styled_string(effects.get_glyphs(), commit.friendly_describe()?)?;
+                match diff_number {
+                    Some(diff_number) => {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Updated {}: {}",
+                            diff_number,
+                            description
+                        )?;
+                        Ok(None)
+                    }
+                    None => {
+                        let revisions =
+                            client.call("differential.query", &json!({ "ids": [revision_id] }))?;
+                        let uri = match revisions[0]["uri"].as_str() {
+                            Some(uri) => uri.to_string(),
+                            None => format!("D{}", revision_id),
+                        };
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Created D{}: {}",
+                            revision_id,
+                            description
+                        )?;
+                        Ok(Some(add_trailer(message, "Differential Revision", &uri)))
+                    }
+                }
+            })?;
+
+        if rewritten_oids.is_empty() {
+            Ok(0)
+        } else {
+            finish_rewrite(
+                effects,
+                git_run_info,
+                repo,
+                now,
+                event_tx_id,
+                &rewritten_oids,
+            )
+        }
+    }
+}
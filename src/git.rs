@@ -2,17 +2,23 @@
 
 mod config;
 mod dag;
+mod diff;
 mod oid;
 mod repo;
 mod run;
+mod sign;
+mod status;
 mod tree;
 
 pub use self::dag::Dag;
-pub use config::{Config, ConfigValue};
+pub use config::{Config, ConfigLevel, ConfigValue};
+pub use diff::{DiffHunk, DiffLine, DiffLineKind, FileChangeKind, FileDiff};
 pub use oid::{MaybeZeroOid, NonZeroOid};
 pub use repo::{
     Branch, CategorizedReferenceName, CherryPickFastError, CherryPickFastOptions, Commit,
-    GitVersion, PatchId, Reference, ReferenceTarget, Repo,
+    GitVersion, PatchId, Reference, ReferenceTarget, ReferenceTransaction, Repo,
 };
-pub use run::GitRunInfo;
+pub use run::{GitRunInfo, GitRunResult};
+pub use sign::CommitSigner;
+pub use status::StatusEntry;
 pub use tree::Tree;
@@ -1,4 +1,6 @@
+use std::convert::TryFrom;
 use std::fmt::Display;
+use std::ops::RangeBounds;
 use std::path::PathBuf;
 
 use eyre::Context;
@@ -17,6 +19,43 @@ impl From<git2::Config> for Config {
     }
 }
 
+/// Which of the config files to read from or write to, as with the
+/// `--local`, `--global` and `--system` options to `git config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigLevel {
+    /// The repository's own config file, such as `.git/config`.
+    Local,
+
+    /// The user's config file, such as `~/.gitconfig`.
+    Global,
+
+    /// The config file shared by all users of the system, such as
+    /// `/etc/gitconfig`.
+    System,
+}
+
+impl Config {
+    /// Open the user's global config file, such as `~/.gitconfig`. Unlike
+    /// `Repo::get_config`, this doesn't require a repository. The file is
+    /// created if it doesn't exist yet.
+    #[instrument]
+    pub fn open_global() -> eyre::Result<Self> {
+        let path = match git2::Config::find_global() {
+            Ok(path) => path,
+            Err(err) => match std::env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".gitconfig"),
+                None => {
+                    return Err(wrap_git_error(err)).wrap_err_with(|| "Finding global config file")
+                }
+            },
+        };
+        let config = git2::Config::open(&path)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Opening global config: {:?}", path))?;
+        Ok(config.into())
+    }
+}
+
 impl std::fmt::Debug for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<Git repository config>")
@@ -68,10 +107,15 @@ impl Display for ConfigValue {
     }
 }
 
-/// Trait used to make `Config::get` able to return multiple types.
+/// Trait used to make `Config::get` and `Config::get_all` able to return
+/// multiple types.
 pub trait GetConfigValue<V> {
     /// Get the given type of value from the config object.
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<V>>;
+
+    /// Parse one of the raw values of a config key which can be set multiple
+    /// times.
+    fn parse_config_value(key: &str, value: &str) -> eyre::Result<V>;
 }
 
 impl GetConfigValue<String> for String {
@@ -87,6 +131,10 @@ impl GetConfigValue<String> for String {
         };
         Ok(value)
     }
+
+    fn parse_config_value(_key: &str, value: &str) -> eyre::Result<String> {
+        Ok(value.to_string())
+    }
 }
 
 impl GetConfigValue<bool> for bool {
@@ -102,6 +150,12 @@ impl GetConfigValue<bool> for bool {
         };
         Ok(value)
     }
+
+    fn parse_config_value(key: &str, value: &str) -> eyre::Result<bool> {
+        git2::Config::parse_bool(value)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Parsing bool value for config key: {:?}", key))
+    }
 }
 
 impl GetConfigValue<PathBuf> for PathBuf {
@@ -117,6 +171,76 @@ impl GetConfigValue<PathBuf> for PathBuf {
         };
         Ok(value)
     }
+
+    fn parse_config_value(_key: &str, value: &str) -> eyre::Result<PathBuf> {
+        Ok(PathBuf::from(value))
+    }
+}
+
+impl GetConfigValue<i32> for i32 {
+    fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<i32>> {
+        let value = match config.inner.get_i32(key.as_ref()) {
+            Ok(value) => Some(value),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+            Err(err) => {
+                return Err(wrap_git_error(err)).wrap_err_with(|| {
+                    format!("Looking up int value for config key: {:?}", key.as_ref())
+                })
+            }
+        };
+        Ok(value)
+    }
+
+    fn parse_config_value(key: &str, value: &str) -> eyre::Result<i32> {
+        git2::Config::parse_i32(value)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Parsing int value for config key: {:?}", key))
+    }
+}
+
+impl GetConfigValue<i64> for i64 {
+    fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<i64>> {
+        let value = match config.inner.get_i64(key.as_ref()) {
+            Ok(value) => Some(value),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+            Err(err) => {
+                return Err(wrap_git_error(err)).wrap_err_with(|| {
+                    format!("Looking up int value for config key: {:?}", key.as_ref())
+                })
+            }
+        };
+        Ok(value)
+    }
+
+    fn parse_config_value(key: &str, value: &str) -> eyre::Result<i64> {
+        git2::Config::parse_i64(value)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Parsing int value for config key: {:?}", key))
+    }
+}
+
+fn i64_to_usize(key: &str, value: i64) -> eyre::Result<usize> {
+    usize::try_from(value).map_err(|_| {
+        eyre::eyre!(
+            "Invalid value for config key {:?} (expected a non-negative integer): {}",
+            key,
+            value
+        )
+    })
+}
+
+impl GetConfigValue<usize> for usize {
+    fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<usize>> {
+        let value: Option<i64> = config.get(key.as_ref())?;
+        value
+            .map(|value| i64_to_usize(key.as_ref(), value))
+            .transpose()
+    }
+
+    fn parse_config_value(key: &str, value: &str) -> eyre::Result<usize> {
+        let value = i64::parse_config_value(key, value)?;
+        i64_to_usize(key, value)
+    }
 }
 
 impl Config {
@@ -163,6 +287,26 @@ impl Config {
         Ok(result.unwrap_or(default))
     }
 
+    /// Same as `get_or` for a non-negative integer value, but reports an error
+    /// if the value isn't in `range`.
+    pub fn get_usize_or<S: AsRef<str>>(
+        &self,
+        key: S,
+        default: usize,
+        range: impl RangeBounds<usize> + std::fmt::Debug,
+    ) -> eyre::Result<usize> {
+        let value = self.get_or(key.as_ref(), default)?;
+        if !range.contains(&value) {
+            eyre::bail!(
+                "Invalid value for config key {:?} (expected a value in the range {:?}): {}",
+                key.as_ref(),
+                range,
+                value
+            );
+        }
+        Ok(value)
+    }
+
     /// Same as `get`, but computes a default value if the config key doesn't exist.
     pub fn get_or_else<V: GetConfigValue<V>, S: AsRef<str>, F: FnOnce() -> V>(
         &self,
@@ -176,6 +320,90 @@ impl Config {
         }
     }
 
+    /// Set the values of a config key which can be set multiple times: the
+    /// values matching the regular expression `regex` are replaced with
+    /// `value`, or if there are none, `value` is added as a new value.
+    pub fn set_multivar<S: AsRef<str> + std::fmt::Debug>(
+        &mut self,
+        key: S,
+        regex: &str,
+        value: impl Into<ConfigValue>,
+    ) -> eyre::Result<()> {
+        let value = value.into();
+        self.inner
+            .set_multivar(key.as_ref(), regex, &value.to_string())
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Setting values for config key: {:?}", key.as_ref()))?;
+        Ok(())
+    }
+
+    /// Remove the values of a config key which can be set multiple times which
+    /// match the regular expression `regex`.
+    #[instrument(fields(key = key.as_ref()))]
+    pub fn remove_multivar(&mut self, key: impl AsRef<str>, regex: &str) -> eyre::Result<()> {
+        self.inner
+            .remove_multivar(key.as_ref(), regex)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Removing values for config key: {:?}", key.as_ref()))?;
+        Ok(())
+    }
+
+    /// Get all of the values of a config key which can be set multiple times,
+    /// in the order in which they were set.
+    #[instrument(fields(key = key.as_ref()))]
+    pub fn get_all<V: GetConfigValue<V>, S: AsRef<str>>(&self, key: S) -> eyre::Result<Vec<V>> {
+        let entries = self
+            .inner
+            .multivar(key.as_ref(), None)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Looking up values for config key: {:?}", key.as_ref()))?;
+        let mut result = Vec::new();
+        for entry in &entries {
+            let entry = entry.map_err(wrap_git_error)?;
+            if let Some(value) = entry.value() {
+                result.push(V::parse_config_value(key.as_ref(), value)?);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get the names and values of all of the config keys starting with
+    /// `prefix` (such as `branchless.`), in the order in which they were set.
+    /// Keys which can be set multiple times appear once per value. Names are
+    /// normalized as by Git, with the section and variable names in
+    /// lowercase, and are compared with `prefix` case-insensitively.
+    ///
+    /// The entries are read from a snapshot of the config, so that they're
+    /// consistent with each other even if the config is modified concurrently.
+    #[instrument]
+    pub fn entries<V: GetConfigValue<V>>(
+        &mut self,
+        prefix: &str,
+    ) -> eyre::Result<Vec<(String, V)>> {
+        let snapshot = self
+            .inner
+            .snapshot()
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| "Taking config snapshot")?;
+        let entries = snapshot
+            .entries(None)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Looking up config entries with prefix: {:?}", prefix))?;
+        let prefix = prefix.to_lowercase();
+        let mut result = Vec::new();
+        for entry in &entries {
+            let entry = entry.map_err(wrap_git_error)?;
+            let (name, value) = match (entry.name(), entry.value()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => continue,
+            };
+            if name.to_lowercase().starts_with(&prefix) {
+                result.push((name.to_string(), V::parse_config_value(name, value)?));
+            }
+        }
+        Ok(result)
+    }
+
     /// Remove the given key from the configuration.
     #[instrument(fields(key = key.as_ref()))]
     pub fn remove(&mut self, key: impl AsRef<str>) -> eyre::Result<()> {
@@ -186,3 +414,91 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_multivar() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.run(&["config", "--add", "branchless.test.flag", "true"])?;
+        git.run(&["config", "--add", "branchless.test.flag", "no"])?;
+
+        let repo = git.get_repo()?;
+        let mut config = repo.get_config()?;
+        let values: Vec<bool> = config.get_all("branchless.test.flag")?;
+        assert_eq!(values, vec![true, false]);
+        let values: Vec<String> = config.get_all("branchless.test.flag")?;
+        assert_eq!(values, vec!["true", "no"]);
+        assert!(config.get_all::<i32, _>("branchless.test.flag").is_err());
+
+        config.set_multivar("branchless.test.flag", "^no$", true)?;
+        config.set_multivar("branchless.test.flag", "^other$", "other")?;
+        let values: Vec<String> = config.get_all("branchless.test.flag")?;
+        assert_eq!(values, vec!["true", "true", "other"]);
+
+        config.remove_multivar("branchless.test.flag", "^true$")?;
+        let values: Vec<String> = config.get_all("branchless.test.flag")?;
+        assert_eq!(values, vec!["other"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_integer_values() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.run(&["config", "branchless.test.big", "5000000000"])?;
+        git.run(&["config", "branchless.test.negative", "-1"])?;
+        git.run(&["config", "branchless.test.small", "3"])?;
+
+        let repo = git.get_repo()?;
+        let config = repo.get_config()?;
+        assert_eq!(
+            config.get::<i64, _>("branchless.test.big")?,
+            Some(5000000000)
+        );
+        assert_eq!(config.get::<i64, _>("branchless.test.negative")?, Some(-1));
+        assert_eq!(config.get::<usize, _>("branchless.test.small")?, Some(3));
+        assert_eq!(config.get::<usize, _>("branchless.test.missing")?, None);
+        assert!(config.get::<usize, _>("branchless.test.negative").is_err());
+
+        assert_eq!(config.get_usize_or("branchless.test.small", 1, 1..)?, 3);
+        assert_eq!(config.get_usize_or("branchless.test.missing", 1, 1..)?, 1);
+        assert!(config
+            .get_usize_or("branchless.test.small", 1, ..=2)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.run(&["config", "branchless.test.number", "1"])?;
+        git.run(&["config", "--add", "branchless.test.number", "2"])?;
+        git.run(&["config", "branchless.other.Key", "true"])?;
+        git.run(&["config", "notBranchless.test.number", "3"])?;
+
+        let repo = git.get_repo()?;
+        let mut config = repo.get_config()?;
+        let entries: Vec<(String, i32)> = config.entries("branchless.test.")?;
+        assert_eq!(
+            entries,
+            vec![
+                ("branchless.test.number".to_string(), 1),
+                ("branchless.test.number".to_string(), 2)
+            ]
+        );
+        let entries: Vec<(String, String)> = config.entries("branchless.Other.")?;
+        assert_eq!(
+            entries,
+            vec![("branchless.other.key".to_string(), "true".to_string())]
+        );
+
+        Ok(())
+    }
+}
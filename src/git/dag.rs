@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 
 use eden_dag::ops::DagPersistent;
@@ -64,11 +64,19 @@ impl Dag {
         master_oids: impl IntoIterator<Item = NonZeroOid>,
         non_master_oids: impl IntoIterator<Item = NonZeroOid>,
     ) -> eyre::Result<()> {
-        let (_effects, _progress) = effects.start_operation(OperationType::UpdateCommitGraph);
+        let (_effects, progress) = effects.start_operation(OperationType::UpdateCommitGraph);
 
+        // The total number of commits to visit isn't known ahead of time, so
+        // report the number visited so far instead.
+        let num_visited = Cell::new(0);
         let parent_func = |v: Vertex| -> eden_dag::Result<Vec<Vertex>> {
             use eden_dag::errors::BackendError;
             trace!(?v, "visiting Git commit");
+            num_visited.set(num_visited.get() + 1);
+            progress.notify_message(format!(
+                "Updating commit graph ({} commits visited)",
+                num_visited.get()
+            ));
 
             let oid = MaybeZeroOid::from_bytes(v.as_ref())
                 .map_err(|_e| anyhow::anyhow!("Could not convert to Git oid: {:?}", &v))
@@ -0,0 +1,176 @@
+use std::convert::TryInto;
+use std::path::PathBuf;
+
+use eyre::Context;
+
+/// The way in which a file was changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileChangeKind {
+    /// The file was added.
+    Added,
+
+    /// The file was deleted.
+    Deleted,
+
+    /// The contents or the mode of the file were changed.
+    Modified,
+
+    /// The file was replaced by an entry of a different type, such as a
+    /// symlink replacing a regular file.
+    TypeChanged,
+}
+
+impl FileChangeKind {
+    pub(super) fn from_delta(delta: git2::Delta) -> Option<Self> {
+        match delta {
+            git2::Delta::Added | git2::Delta::Untracked | git2::Delta::Copied => {
+                Some(FileChangeKind::Added)
+            }
+            git2::Delta::Deleted => Some(FileChangeKind::Deleted),
+            git2::Delta::Modified | git2::Delta::Renamed | git2::Delta::Conflicted => {
+                Some(FileChangeKind::Modified)
+            }
+            git2::Delta::Typechange => Some(FileChangeKind::TypeChanged),
+            git2::Delta::Unmodified | git2::Delta::Ignored | git2::Delta::Unreadable => None,
+        }
+    }
+}
+
+/// Whether a line in a hunk was kept, added or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiffLineKind {
+    /// The line is unchanged, and only included for context.
+    Context,
+
+    /// The line was added.
+    Added,
+
+    /// The line was removed.
+    Removed,
+}
+
+/// A line in a hunk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffLine {
+    /// Whether the line was kept, added or removed.
+    pub kind: DiffLineKind,
+
+    /// The contents of the line, including the trailing newline (if any).
+    pub content: Vec<u8>,
+}
+
+/// A contiguous region of changed lines in a file, with some surrounding
+/// context lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffHunk {
+    /// The first line of the hunk in the old version of the file (starting
+    /// from 1).
+    pub old_start: usize,
+
+    /// The number of lines of the hunk in the old version of the file.
+    pub old_lines: usize,
+
+    /// The first line of the hunk in the new version of the file (starting
+    /// from 1).
+    pub new_start: usize,
+
+    /// The number of lines of the hunk in the new version of the file.
+    pub new_lines: usize,
+
+    /// The lines of the hunk, in order.
+    pub lines: Vec<DiffLine>,
+}
+
+/// The changes to a single file between two commits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDiff {
+    /// The path of the file in the old commit, or `None` if it was added.
+    pub old_path: Option<PathBuf>,
+
+    /// The path of the file in the new commit, or `None` if it was deleted.
+    pub new_path: Option<PathBuf>,
+
+    /// The way in which the file was changed.
+    pub kind: FileChangeKind,
+
+    /// Whether the file is binary, in which case there are no hunks.
+    pub is_binary: bool,
+
+    /// The changed regions of the file.
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileDiff {
+    /// Get the path of the file, preferring its new path.
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        self.new_path.as_ref().or(self.old_path.as_ref())
+    }
+}
+
+fn get_delta_file_path(file: git2::DiffFile) -> Option<PathBuf> {
+    if file.id().is_zero() {
+        None
+    } else {
+        file.path().map(PathBuf::from)
+    }
+}
+
+/// Convert a `libgit2` diff into a list of per-file diffs, in the order that
+/// `libgit2` produced them (sorted by path).
+pub(super) fn make_file_diffs(diff: &git2::Diff) -> eyre::Result<Vec<FileDiff>> {
+    let mut result = Vec::new();
+    for delta_idx in 0..diff.deltas().len() {
+        let patch = git2::Patch::from_diff(diff, delta_idx)
+            .wrap_err_with(|| format!("Computing patch for delta {}", delta_idx))?;
+        let delta = diff
+            .get_delta(delta_idx)
+            .ok_or_else(|| eyre::eyre!("Could not get delta {}", delta_idx))?;
+        let kind = match FileChangeKind::from_delta(delta.status()) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let mut hunks = Vec::new();
+        let is_binary = match &patch {
+            Some(patch) => patch.delta().flags().is_binary(),
+            None => true,
+        };
+        if let Some(patch) = patch {
+            for hunk_idx in 0..patch.num_hunks() {
+                let (hunk, num_lines) = patch.hunk(hunk_idx)?;
+                let mut lines = Vec::new();
+                for line_idx in 0..num_lines {
+                    let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                    let kind = match line.origin_value() {
+                        git2::DiffLineType::Context => DiffLineKind::Context,
+                        git2::DiffLineType::Addition => DiffLineKind::Added,
+                        git2::DiffLineType::Deletion => DiffLineKind::Removed,
+                        // Markers such as the one for a missing newline at the
+                        // end of the file aren't lines of the file.
+                        _ => continue,
+                    };
+                    lines.push(DiffLine {
+                        kind,
+                        content: line.content().to_vec(),
+                    });
+                }
+                hunks.push(DiffHunk {
+                    old_start: hunk.old_start().try_into()?,
+                    old_lines: hunk.old_lines().try_into()?,
+                    new_start: hunk.new_start().try_into()?,
+                    new_lines: hunk.new_lines().try_into()?,
+                    lines,
+                });
+            }
+        }
+
+        result.push(FileDiff {
+            old_path: get_delta_file_path(delta.old_file()),
+            new_path: get_delta_file_path(delta.new_file()),
+            kind,
+            is_binary,
+            hunks,
+        });
+    }
+    Ok(result)
+}
@@ -18,21 +18,27 @@ use std::str::FromStr;
 use std::time::SystemTime;
 
 use color_eyre::Help;
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use eyre::{eyre, Context};
 use itertools::Itertools;
 use os_str_bytes::{OsStrBytes, OsStringBytes};
 use tracing::{instrument, warn};
 
-use crate::core::config::get_main_branch_name;
+use crate::core::config::{get_db_dir, get_main_branch_name};
+use crate::core::eventlog::STASH_REF_NAME;
+use crate::core::formatting::Palette;
 use crate::core::metadata::{render_commit_metadata, CommitMessageProvider, CommitOidProvider};
-use crate::git::config::Config;
+use crate::git::config::{Config, ConfigLevel};
+use crate::git::diff::{make_file_diffs, FileDiff};
 use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
-use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree};
+use crate::git::sign::CommitSigner;
+use crate::git::status::{make_status_entries, StatusEntry};
+use crate::git::tree::{
+    dehydrate_tree, get_changed_paths_between_trees, get_tree_entry, hydrate_tree,
+};
 use crate::tui::{Effects, OperationType};
 
-use super::{GitRunInfo, Tree};
+use super::Tree;
 
 /// Convert a `git2::Error` into an `eyre::Error` with an auto-generated message.
 pub(super) fn wrap_git_error(error: git2::Error) -> eyre::Error {
@@ -110,12 +116,24 @@ impl FromStr for GitVersion {
     }
 }
 
+/// The new OID and file mode for each changed path in a tree, or `None` for
+/// paths which were removed.
+type ChangedEntries = HashMap<PathBuf, Option<(NonZeroOid, i32)>>;
+
 /// Options for `Repo::cherry_pick_fast`.
 #[derive(Clone, Debug)]
 pub struct CherryPickFastOptions {
     /// Detect if a commit is being applied onto a parent with the same tree,
     /// and skip applying the patch in that case.
     pub reuse_parent_tree_if_possible: bool,
+
+    /// The maximum number of changed files to consider for rename detection.
+    /// If `0`, renames aren't detected at all.
+    pub rename_limit: u32,
+
+    /// The similarity percentage (from 0 to 100) at which an added and
+    /// removed file are considered to be a rename.
+    pub rename_threshold: u32,
 }
 
 /// An error raised when attempting the `Repo::cherry_pick_fast` operation.
@@ -147,11 +165,25 @@ impl Repo {
         Ok(Repo { inner: repo })
     }
 
-    /// Get the Git repository associated with the current directory.
+    /// Get the Git repository associated with the current directory. As with
+    /// Git, the `GIT_DIR` and `GIT_WORK_TREE` environment variables override
+    /// the repository and working copy which would otherwise be discovered.
     #[instrument]
     pub fn from_current_dir() -> eyre::Result<Self> {
         let path = std::env::current_dir().wrap_err_with(|| "Getting working directory")?;
-        Repo::from_dir(&path)
+        let repo = match std::env::var_os("GIT_DIR") {
+            Some(git_dir) => {
+                let repo = git2::Repository::open(path.join(git_dir)).map_err(wrap_git_error)?;
+                Repo { inner: repo }
+            }
+            None => Repo::from_dir(&path)?,
+        };
+        if let Some(work_tree) = std::env::var_os("GIT_WORK_TREE") {
+            repo.inner
+                .set_workdir(&path.join(work_tree), false)
+                .map_err(wrap_git_error)?;
+        }
+        Ok(repo)
     }
 
     /// Open a new copy of the repository.
@@ -166,6 +198,123 @@ impl Repo {
         self.inner.path()
     }
 
+    /// Get the path to the `.git` directory which is shared between all of the
+    /// worktrees of the repository. For a linked worktree (as created by `git
+    /// worktree add`), `get_path` returns its own directory under
+    /// `.git/worktrees/` instead.
+    pub fn get_common_path(&self) -> eyre::Result<PathBuf> {
+        let path = self.get_path();
+        if !self.inner.is_worktree() {
+            return Ok(path.to_path_buf());
+        }
+        let commondir_path = path.join("commondir");
+        let common_path = std::fs::read_to_string(&commondir_path)
+            .wrap_err_with(|| format!("Reading {:?}", commondir_path))?;
+        Ok(path.join(common_path.trim_end()))
+    }
+
+    /// Get the name of the worktree for this repository, if it's a linked
+    /// worktree, as used in `.git/worktrees/<name>`. Returns `None` for the main
+    /// worktree.
+    pub fn get_worktree_name(&self) -> Option<String> {
+        if self.inner.is_worktree() {
+            self.get_path()
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Get the name under which updates to `HEAD` in this worktree are recorded
+    /// in the event log. Like Git's own syntax for referring to the `HEAD` of
+    /// other worktrees, this is `HEAD` for the main worktree and
+    /// `worktrees/<name>/HEAD` for linked worktrees.
+    pub fn get_head_ref_name(&self) -> OsString {
+        match self.get_worktree_name() {
+            Some(worktree_name) => OsString::from(format!("worktrees/{}/HEAD", worktree_name)),
+            None => OsString::from("HEAD"),
+        }
+    }
+
+    /// Open the repository of each of the worktrees of the repository,
+    /// including the main worktree, along with the path to the worktree.
+    /// Worktrees whose directory has been deleted are skipped.
+    fn open_worktree_repos(&self) -> eyre::Result<Vec<(PathBuf, git2::Repository)>> {
+        let common_repo =
+            git2::Repository::open(self.get_common_path()?).map_err(wrap_git_error)?;
+        let mut result = Vec::new();
+        for worktree_name in common_repo.worktrees().map_err(wrap_git_error)?.iter() {
+            let worktree_name = match worktree_name {
+                Some(worktree_name) => worktree_name,
+                None => continue,
+            };
+            let worktree = common_repo
+                .find_worktree(worktree_name)
+                .map_err(wrap_git_error)?;
+            if worktree.validate().is_err() {
+                continue;
+            }
+            let worktree_repo =
+                git2::Repository::open_from_worktree(&worktree).map_err(wrap_git_error)?;
+            result.push((worktree.path().to_path_buf(), worktree_repo));
+        }
+        if let Some(path) = common_repo.workdir() {
+            let path = path.to_path_buf();
+            result.insert(0, (path, common_repo));
+        }
+        Ok(result)
+    }
+
+    /// Get the commit which `HEAD` points to in each of the worktrees of the
+    /// repository, including the main worktree, along with the path to the
+    /// worktree. Worktrees whose `HEAD` is unborn, or whose directory has been
+    /// deleted, are skipped.
+    #[instrument]
+    pub fn get_worktree_head_oids(&self) -> eyre::Result<Vec<(PathBuf, NonZeroOid)>> {
+        fn get_head_oid(repo: &git2::Repository) -> Option<NonZeroOid> {
+            let head = repo.head().ok()?;
+            let commit = head.peel_to_commit().ok()?;
+            Some(make_non_zero_oid(commit.id()))
+        }
+
+        let result = self
+            .open_worktree_repos()?
+            .into_iter()
+            .filter_map(|(path, worktree_repo)| {
+                get_head_oid(&worktree_repo).map(|head_oid| (path, head_oid))
+            })
+            .collect();
+        Ok(result)
+    }
+
+    /// Get the names of the branches which are checked out in the worktrees of
+    /// the repository other than this one, such as `refs/heads/master`. Git
+    /// refuses to check out these branches here.
+    #[instrument]
+    pub fn get_other_worktree_branch_names(&self) -> eyre::Result<HashSet<OsString>> {
+        let path = self.get_path();
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut result = HashSet::new();
+        for (_worktree_path, worktree_repo) in self.open_worktree_repos()? {
+            let worktree_repo_path = worktree_repo.path();
+            let worktree_repo_path = worktree_repo_path
+                .canonicalize()
+                .unwrap_or_else(|_| worktree_repo_path.to_path_buf());
+            if worktree_repo_path == path {
+                continue;
+            }
+            let head = match worktree_repo.find_reference("HEAD") {
+                Ok(head) => head,
+                Err(_) => continue,
+            };
+            if let Some(target) = head.symbolic_target_bytes() {
+                result.insert(OsString::from_raw_vec(target.to_vec())?);
+            }
+        }
+        Ok(result)
+    }
+
     /// Get the path to the directory inside the `.git` directory which contains
     /// state used for the current rebase (if any).
     pub fn get_rebase_state_dir_path(&self) -> PathBuf {
@@ -189,25 +338,112 @@ impl Repo {
         Ok(config.into())
     }
 
+    /// Get the configuration object for only the config file at the given
+    /// level. Unlike `get_config`, values aren't inherited from the other
+    /// levels, and `Config::set` and `Config::remove` are guaranteed to
+    /// modify this file. The global config file is created if it doesn't
+    /// exist yet.
+    #[instrument]
+    pub fn get_config_at_level(&self, level: ConfigLevel) -> eyre::Result<Config> {
+        let config = match level {
+            ConfigLevel::Local => self
+                .inner
+                .config()
+                .and_then(|config| config.open_level(git2::ConfigLevel::Local)),
+            ConfigLevel::Global => return Config::open_global(),
+            ConfigLevel::System => {
+                git2::Config::find_system().and_then(|path| git2::Config::open(&path))
+            }
+        };
+        let config = config
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Opening {:?} config", level))?;
+        Ok(config.into())
+    }
+
     /// Get the directory where the DAG for the repository is stored.
     #[instrument]
     pub fn get_dag_dir(&self) -> eyre::Result<PathBuf> {
-        let path = self.get_path().join("branchless").join("dag");
+        let path = self.get_common_path()?.join("branchless").join("dag");
         std::fs::create_dir_all(&path).wrap_err_with(|| "Creating .git/branchless/dag dir")?;
         Ok(path)
     }
 
+    /// Get the path to the SQLite database for this repository. The database
+    /// is shared between all of the worktrees of the repository, and is
+    /// stored under `.git/branchless` unless `branchless.db.path` is set.
+    pub fn get_db_path(&self) -> eyre::Result<PathBuf> {
+        let common_path = self.get_common_path()?;
+        let db_dir = match get_db_dir(self)? {
+            None => common_path.join("branchless"),
+            Some(db_dir) => {
+                let mut path = common_path.clone();
+                for component in db_dir.components() {
+                    if component.as_os_str() == "{repo}" {
+                        path.push(self.get_db_repo_name()?);
+                    } else {
+                        path.push(component);
+                    }
+                }
+                path
+            }
+        };
+        Ok(db_dir.join("db.sqlite3"))
+    }
+
+    /// Get a name for this repository which can be used as a directory name
+    /// to keep its database apart from those of other repositories. It
+    /// consists of the name of the repository's directory and a hash of its
+    /// full path.
+    fn get_db_repo_name(&self) -> eyre::Result<String> {
+        let common_path = self.get_common_path()?;
+        let common_path = common_path.canonicalize().unwrap_or(common_path);
+        let dir_path = if common_path.file_name() == Some(OsStr::new(".git")) {
+            common_path.parent().unwrap_or(&common_path)
+        } else {
+            &common_path
+        };
+        let dir_name = match dir_path.file_name() {
+            Some(dir_name) => dir_name.to_string_lossy().into_owned(),
+            None => "repo".to_string(),
+        };
+        let path_hash = git2::Oid::hash_object(git2::ObjectType::Blob, &common_path.to_raw_bytes())
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Hashing repository path {:?}", &common_path))?;
+        Ok(format!("{}-{}", dir_name, &path_hash.to_string()[..12]))
+    }
+
     /// Get the connection to the SQLite database for this repository.
     #[instrument]
     pub fn get_db_conn(&self) -> eyre::Result<rusqlite::Connection> {
-        let dir = self.get_path().join("branchless");
-        std::fs::create_dir_all(&dir).wrap_err_with(|| "Creating .git/branchless dir")?;
-        let path = dir.join("db.sqlite3");
+        let path = self.get_db_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Creating database directory {:?}", dir))?;
+        }
         let conn = rusqlite::Connection::open(&path)
             .wrap_err_with(|| format!("Opening database connection at {:?}", &path))?;
         Ok(conn)
     }
 
+    /// Get the commits of the stash entries, from the most recent one
+    /// (`stash@{0}`) to the oldest one.
+    #[instrument]
+    pub fn get_stash_oids(&self) -> eyre::Result<Vec<NonZeroOid>> {
+        let reflog = self
+            .inner
+            .reflog(STASH_REF_NAME)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| "Reading stash reflog")?;
+        let result = reflog
+            .iter()
+            .map(|entry| entry.id_new())
+            .filter(|oid| !oid.is_zero())
+            .map(make_non_zero_oid)
+            .collect();
+        Ok(result)
+    }
+
     /// Get the OID for the repository's `HEAD` reference.
     #[instrument]
     pub fn get_head_info(&self) -> eyre::Result<HeadInfo> {
@@ -424,6 +660,32 @@ Either create it, or update the main branch setting by running:
         }
     }
 
+    /// Get the progress of the rebase which is underway, as the number of the
+    /// commit currently being applied and the total number of commits to
+    /// apply. Returns `None` if there is no rebase underway or if its progress
+    /// couldn't be determined.
+    pub fn get_rebase_progress(&self) -> Option<(usize, usize)> {
+        let read_number = |path: PathBuf| -> Option<usize> {
+            std::fs::read_to_string(path).ok()?.trim().parse().ok()
+        };
+        // Interactive and merge-based rebases keep their state in
+        // `rebase-merge`, while `git am` and apply-based rebases use
+        // `rebase-apply`.
+        for (dir_name, current_file_name, total_file_name) in &[
+            ("rebase-merge", "msgnum", "end"),
+            ("rebase-apply", "next", "last"),
+        ] {
+            let dir = self.inner.path().join(dir_name);
+            if let (Some(current), Some(total)) = (
+                read_number(dir.join(current_file_name)),
+                read_number(dir.join(total_file_name)),
+            ) {
+                return Some((current, total));
+            }
+        }
+        None
+    }
+
     /// Find the merge-base between two commits. Returns `None` if a merge-base
     /// could not be found.
     #[instrument]
@@ -439,6 +701,71 @@ Either create it, or update the main branch setting by running:
         }
     }
 
+    /// Count the commits which are ancestors of `descendant_oid` (including
+    /// itself) but not of `ancestor_oid`, like `git rev-list --count
+    /// ancestor..descendant`.
+    #[instrument]
+    pub fn count_commits_between(
+        &self,
+        ancestor_oid: NonZeroOid,
+        descendant_oid: NonZeroOid,
+    ) -> eyre::Result<usize> {
+        let mut revwalk = self.inner.revwalk().map_err(wrap_git_error)?;
+        revwalk.push(descendant_oid.inner).map_err(wrap_git_error)?;
+        revwalk.hide(ancestor_oid.inner).map_err(wrap_git_error)?;
+        let mut count = 0;
+        for oid in revwalk {
+            oid.map_err(wrap_git_error)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Get the commits which are ancestors of `descendant_oid` (including
+    /// itself) but not of `ancestor_oid`, like `git rev-list
+    /// ancestor..descendant`. The commits are ordered from newest to oldest.
+    #[instrument]
+    pub fn get_commits_between(
+        &self,
+        ancestor_oid: NonZeroOid,
+        descendant_oid: NonZeroOid,
+    ) -> eyre::Result<Vec<NonZeroOid>> {
+        let mut revwalk = self.inner.revwalk().map_err(wrap_git_error)?;
+        revwalk.push(descendant_oid.inner).map_err(wrap_git_error)?;
+        revwalk.hide(ancestor_oid.inner).map_err(wrap_git_error)?;
+        let mut result = Vec::new();
+        for oid in revwalk {
+            let oid = oid.map_err(wrap_git_error)?;
+            result.push(make_non_zero_oid(oid));
+        }
+        Ok(result)
+    }
+
+    /// Get the commits which are ancestors of any of `descendant_oids`
+    /// (including themselves) but not of any of `ancestor_oids`, like `git
+    /// rev-list descendant1 descendant2 ^ancestor1 ^ancestor2`. The commits are
+    /// ordered from newest to oldest.
+    #[instrument]
+    pub fn get_commits_between_many(
+        &self,
+        ancestor_oids: &[NonZeroOid],
+        descendant_oids: &[NonZeroOid],
+    ) -> eyre::Result<Vec<NonZeroOid>> {
+        let mut revwalk = self.inner.revwalk().map_err(wrap_git_error)?;
+        for descendant_oid in descendant_oids {
+            revwalk.push(descendant_oid.inner).map_err(wrap_git_error)?;
+        }
+        for ancestor_oid in ancestor_oids {
+            revwalk.hide(ancestor_oid.inner).map_err(wrap_git_error)?;
+        }
+        let mut result = Vec::new();
+        for oid in revwalk {
+            let oid = oid.map_err(wrap_git_error)?;
+            result.push(make_non_zero_oid(oid));
+        }
+        Ok(result)
+    }
+
     #[instrument]
     fn get_diff_for_commit(
         &self,
@@ -539,25 +866,80 @@ Either create it, or update the main branch setting by running:
         Ok(all_references)
     }
 
-    /// Check if the repository has staged or unstaged changes. Untracked files
-    /// are not included. This operation may take a while.
+    /// Find all references in the repository whose names match the given glob
+    /// pattern, such as `refs/heads/*`.
+    #[instrument]
+    pub fn get_references_matching(&self, glob: &str) -> eyre::Result<Vec<Reference<'_>>> {
+        let mut references = Vec::new();
+        for reference in self
+            .inner
+            .references_glob(glob)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Iterating through references matching: {}", glob))?
+        {
+            let reference = reference.wrap_err_with(|| "Accessing individual reference")?;
+            references.push(Reference { inner: reference });
+        }
+        Ok(references)
+    }
+
+    /// Get the changes between the trees of the two given commits, file by
+    /// file. Renames aren't detected, so a renamed file appears as a deletion
+    /// and an addition.
     #[instrument]
-    pub fn has_changed_files(
+    pub fn get_diff(
         &self,
-        effects: &Effects,
-        git_run_info: &GitRunInfo,
-    ) -> eyre::Result<bool> {
-        let exit_code = git_run_info.run(
-            effects,
-            // This is not a mutating operation, so we don't need a transaction ID.
-            None,
-            &["diff", "--quiet"],
-        )?;
-        if exit_code == 0 {
-            Ok(false)
-        } else {
-            Ok(true)
+        old_commit: &Commit,
+        new_commit: &Commit,
+    ) -> eyre::Result<Vec<FileDiff>> {
+        let old_tree = old_commit.get_tree()?;
+        let new_tree = new_commit.get_tree()?;
+        let diff = self
+            .inner
+            .diff_tree_to_tree(Some(&old_tree.inner), Some(&new_tree.inner), None)
+            .wrap_err_with(|| {
+                format!(
+                    "Calculating diff between: {:?} and {:?}",
+                    old_commit, new_commit
+                )
+            })?;
+        make_file_diffs(&diff)
+    }
+
+    /// Get the status of the working copy: the paths with staged or unstaged
+    /// changes, the untracked paths (but not ignored ones), and the paths with
+    /// merge conflicts, sorted by path. This operation may take a while. A
+    /// bare repository has no working copy, so its status is always empty.
+    #[instrument]
+    pub fn get_status(&self, effects: &Effects) -> eyre::Result<Vec<StatusEntry>> {
+        if self.inner.is_bare() {
+            return Ok(Vec::new());
         }
+        let (_effects, _progress) = effects.start_operation(OperationType::QueryWorkingCopy);
+        let mut status_options = git2::StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false)
+            .renames_head_to_index(false)
+            .renames_index_to_workdir(false);
+        let statuses = self
+            .inner
+            .statuses(Some(&mut status_options))
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| "Getting working copy status")?;
+        make_status_entries(&statuses)
+    }
+
+    /// Check if the repository has staged or unstaged changes. Untracked files
+    /// are not included. This operation may take a while.
+    #[instrument]
+    pub fn has_changed_files(&self, effects: &Effects) -> eyre::Result<bool> {
+        let has_changed_files = self
+            .get_status(effects)?
+            .iter()
+            .any(|entry| !entry.is_untracked());
+        Ok(has_changed_files)
     }
 
     /// Create a new reference or update an existing one.
@@ -600,6 +982,16 @@ Either create it, or update the main branch setting by running:
         }
     }
 
+    /// Start a transaction to update several references together. No
+    /// references are updated until `ReferenceTransaction::commit` is called.
+    #[instrument]
+    pub fn start_reference_transaction(&self) -> eyre::Result<ReferenceTransaction<'_>> {
+        Ok(ReferenceTransaction {
+            repo: &self.inner,
+            updates: Vec::new(),
+        })
+    }
+
     /// Get all local branches in the repository.
     #[instrument]
     pub fn get_all_local_branches(&self) -> eyre::Result<Vec<Branch>> {
@@ -674,11 +1066,24 @@ Either create it, or update the main branch setting by running:
             Some(commit) => Ok(commit.friendly_describe()?),
             None => Ok(StyledString::styled(
                 format!("<commit not found: {:?}>", oid),
-                BaseColor::Red.light(),
+                Palette::current().error,
             )),
         }
     }
 
+    /// Look up the object that an annotated tag points to.
+    ///
+    /// Returns: The OID of the tag's target, or `None` if the given OID doesn't
+    /// refer to an annotated tag object.
+    #[instrument]
+    pub fn find_tag_target(&self, oid: NonZeroOid) -> eyre::Result<Option<NonZeroOid>> {
+        match self.inner.find_tag(oid.inner) {
+            Ok(tag) => Ok(Some(make_non_zero_oid(tag.target_id()))),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(wrap_git_error(err)),
+        }
+    }
+
     /// Create a new commit.
     #[instrument]
     pub fn create_commit(
@@ -708,6 +1113,153 @@ Either create it, or update the main branch setting by running:
         Ok(make_non_zero_oid(oid))
     }
 
+    /// Create a new commit like `create_commit`, but sign it with the given
+    /// signer. Unlike `create_commit`, this doesn't update any reference.
+    #[instrument(skip(signer))]
+    pub fn create_signed_commit(
+        &self,
+        signer: &CommitSigner,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        tree: &Tree,
+        parents: Vec<&Commit>,
+    ) -> eyre::Result<NonZeroOid> {
+        let parents = parents
+            .iter()
+            .map(|commit| &commit.inner)
+            .collect::<Vec<_>>();
+        let content = self
+            .inner
+            .commit_create_buffer(
+                &author.inner,
+                &committer.inner,
+                message,
+                &tree.inner,
+                parents.as_slice(),
+            )
+            .map_err(wrap_git_error)?;
+        let content = content
+            .as_str()
+            .ok_or_else(|| eyre::eyre!("Could not decode commit contents for signing"))?;
+        let signature = signer.sign(content)?;
+        let oid = self
+            .inner
+            .commit_signed(content, &signature, None)
+            .map_err(wrap_git_error)?;
+        Ok(make_non_zero_oid(oid))
+    }
+
+    /// Create a new commit with the given tree and parents, reusing the author
+    /// and message of `original_commit` (which is typically the commit being
+    /// rewritten). The commit is signed with `signer`, if provided. No
+    /// reference is updated.
+    ///
+    /// This is how the rewrite engine creates rebased commits, so it can be
+    /// used together with `cherry_pick_fast` to build custom rewrite tools.
+    #[instrument(skip(signer))]
+    pub fn create_commit_from_tree(
+        &self,
+        original_commit: &Commit,
+        tree: &Tree,
+        parents: Vec<&Commit>,
+        committer: &Signature,
+        signer: Option<&CommitSigner>,
+    ) -> eyre::Result<NonZeroOid> {
+        let message = original_commit.get_message_raw()?;
+        let message = message.to_str().ok_or_else(|| {
+            eyre::eyre!(
+                "Could not decode commit message for commit: {:?}",
+                original_commit.get_oid()
+            )
+        })?;
+        let author = original_commit.get_author();
+        match signer {
+            Some(signer) => {
+                self.create_signed_commit(signer, &author, committer, message, tree, parents)
+            }
+            None => self.create_commit(None, &author, committer, message, tree, parents),
+        }
+    }
+
+    /// Cherry-pick `patch_commit` onto `target_commit` in memory, without
+    /// touching the working copy, the index or any references. The new commit
+    /// has the author and message of `patch_commit` and the given committer,
+    /// and is signed with `signer`, if provided.
+    ///
+    /// Returns the OID of the new commit, or the paths which conflicted, in
+    /// which case no commit is created. See `cherry_pick_fast` and
+    /// `create_commit_from_tree` for the individual steps.
+    #[instrument(skip(signer))]
+    pub fn cherry_pick_commit_in_memory(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+        committer: &Signature,
+        signer: Option<&CommitSigner>,
+        options: &CherryPickFastOptions,
+    ) -> eyre::Result<Result<NonZeroOid, CherryPickFastError>> {
+        let tree = match self.cherry_pick_fast(patch_commit, target_commit, options)? {
+            Ok(tree) => tree,
+            Err(err) => return Ok(Err(err)),
+        };
+        let commit_oid = self.create_commit_from_tree(
+            patch_commit,
+            &tree,
+            vec![target_commit],
+            committer,
+            signer,
+        )?;
+        Ok(Ok(commit_oid))
+    }
+
+    /// Determine whether the given commit has a signature.
+    #[instrument]
+    pub fn is_commit_signed(&self, commit_oid: NonZeroOid) -> eyre::Result<bool> {
+        match self.inner.extract_signature(&commit_oid.inner, None) {
+            Ok(_) => Ok(true),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+            Err(err) => Err(wrap_git_error(err)),
+        }
+    }
+
+    /// Look up the note attached to the given object in the given notes
+    /// reference (such as `refs/notes/commits`). Returns `None` if not found.
+    #[instrument]
+    pub fn find_note(&self, notes_ref: &str, oid: NonZeroOid) -> eyre::Result<Option<String>> {
+        match self.inner.find_note(Some(notes_ref), oid.inner) {
+            Ok(note) => Ok(Some(
+                String::from_utf8_lossy(note.message_bytes()).into_owned(),
+            )),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(wrap_git_error(err)),
+        }
+    }
+
+    /// Attach a note to the given object in the given notes reference,
+    /// replacing any existing note. This creates a commit in the notes
+    /// reference using the given signature.
+    #[instrument]
+    pub fn create_note(
+        &self,
+        notes_ref: &str,
+        oid: NonZeroOid,
+        message: &str,
+        signature: &Signature,
+    ) -> eyre::Result<()> {
+        self.inner
+            .note(
+                &signature.inner,
+                &signature.inner,
+                Some(notes_ref),
+                oid.inner,
+                message,
+                true,
+            )
+            .map_err(wrap_git_error)?;
+        Ok(())
+    }
+
     /// Cherry-pick a commit in memory and return the resulting index.
     #[instrument]
     pub fn cherry_pick_commit(
@@ -723,15 +1275,48 @@ Either create it, or update the main branch setting by running:
         Ok(Index { inner: index })
     }
 
+    /// Merge the given trees in memory and return the resulting index.
+    #[instrument]
+    fn merge_trees(
+        &self,
+        ancestor_tree: &Tree,
+        our_tree: &Tree,
+        their_tree: &Tree,
+        rename_limit: u32,
+        rename_threshold: u32,
+    ) -> eyre::Result<Index> {
+        let mut merge_options = git2::MergeOptions::new();
+        merge_options
+            .find_renames(rename_limit > 0)
+            .target_limit(rename_limit)
+            .rename_threshold(rename_threshold);
+        let index = self
+            .inner
+            .merge_trees(
+                &ancestor_tree.inner,
+                &our_tree.inner,
+                &their_tree.inner,
+                Some(&merge_options),
+            )
+            .map_err(wrap_git_error)?;
+        Ok(Index { inner: index })
+    }
+
     /// Cherry-pick a commit in memory and return the resulting tree.
     ///
     /// The `libgit2` routines operate on entire `Index`es, which contain one
     /// entry per file in the repository. When operating on a large repository,
     /// this is prohibitively slow, as it takes several seconds just to write
     /// the index to disk. To improve performance, we reduce the size of the
-    /// involved indexes by filtering out any unchanged entries from the input
-    /// trees, then call into `libgit2`, then add back the unchanged entries to
-    /// the output tree.
+    /// involved trees by filtering out any unchanged entries, then carry out a
+    /// three-way merge of the filtered trees with `libgit2`, then add back the
+    /// unchanged entries to the output tree.
+    ///
+    /// Most commits only touch a handful of paths, each of which either hasn't
+    /// been changed in the target since the patch's parent, or has already
+    /// been changed in the same way. In that case, the changed entries are
+    /// applied directly to the target tree, and the merge is skipped
+    /// altogether. See `apply_changed_entries`.
     #[instrument]
     pub fn cherry_pick_fast<'repo>(
         &'repo self,
@@ -741,18 +1326,26 @@ Either create it, or update the main branch setting by running:
     ) -> eyre::Result<Result<Tree<'repo>, CherryPickFastError>> {
         let CherryPickFastOptions {
             reuse_parent_tree_if_possible,
+            rename_limit,
+            rename_threshold,
         } = options;
 
-        if *reuse_parent_tree_if_possible {
-            if let Some(only_parent) = patch_commit.get_only_parent() {
-                if only_parent.get_tree()?.get_oid() == target_commit.get_tree()?.get_oid() {
-                    // If this patch is being applied to the same commit it was
-                    // originally based on, then we can skip cherry-picking
-                    // altogether, and use its tree directly. This is common e.g.
-                    // when only rewording a commit message.
-                    return Ok(Ok(patch_commit.get_tree()?));
-                }
-            };
+        let patch_parent = patch_commit.get_only_parent().ok_or_else(|| {
+            eyre::eyre!(
+                "Could not get only parent of commit to cherry-pick: {:?}",
+                &patch_commit
+            )
+        })?;
+        let patch_parent_tree = patch_parent.get_tree()?;
+        let patch_tree = patch_commit.get_tree()?;
+        let target_tree = target_commit.get_tree()?;
+
+        if *reuse_parent_tree_if_possible && patch_parent_tree.get_oid() == target_tree.get_oid() {
+            // If this patch is being applied to the same commit it was
+            // originally based on, then we can skip cherry-picking
+            // altogether, and use its tree directly. This is common e.g.
+            // when only rewording a commit message.
+            return Ok(Ok(patch_tree));
         }
 
         let changed_pathbufs = self
@@ -764,14 +1357,36 @@ Either create it, or update the main branch setting by running:
             .collect_vec();
         let changed_paths = changed_pathbufs.iter().map(PathBuf::borrow).collect_vec();
 
-        let dehydrated_patch_commit =
-            self.dehydrate_commit(patch_commit, changed_paths.as_slice(), true)?;
-        let dehydrated_target_commit =
-            self.dehydrate_commit(target_commit, changed_paths.as_slice(), false)?;
+        if let Some(rebased_entries) = self.apply_changed_entries(
+            &changed_pathbufs,
+            &patch_parent_tree,
+            &patch_tree,
+            &target_tree,
+        )? {
+            if rebased_entries.is_empty() {
+                // The target already has the same contents as the patch for
+                // all of the changed paths, so there's nothing to apply.
+                return Ok(Ok(target_tree));
+            }
+            let rebased_tree_oid = hydrate_tree(self, Some(&target_tree), rebased_entries)?;
+            let rebased_tree = self
+                .find_tree(rebased_tree_oid)?
+                .ok_or_else(|| eyre::eyre!("Could not find just-hydrated tree"))?;
+            return Ok(Ok(rebased_tree));
+        }
 
-        let rebased_index =
-            self.cherry_pick_commit(&dehydrated_patch_commit, &dehydrated_target_commit, 0)?;
-        let rebased_tree = {
+        let dehydrated_ancestor_tree = self.dehydrate_tree(&patch_parent_tree, &changed_paths)?;
+        let dehydrated_patch_tree = self.dehydrate_tree(&patch_tree, &changed_paths)?;
+        let dehydrated_target_tree = self.dehydrate_tree(&target_tree, &changed_paths)?;
+
+        let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, i32)>> = {
+            let rebased_index = self.merge_trees(
+                &dehydrated_ancestor_tree,
+                &dehydrated_target_tree,
+                &dehydrated_patch_tree,
+                *rename_limit,
+                *rename_threshold,
+            )?;
             if rebased_index.has_conflicts() {
                 let conflicting_paths = {
                     let mut result = HashSet::new();
@@ -781,10 +1396,11 @@ Either create it, or update the main branch setting by running:
                         .wrap_err_with(|| "Getting conflicting paths")?
                     {
                         let conflict = conflict.wrap_err_with(|| "Getting conflicting path")?;
-                        if let Some(ancestor) = conflict.ancestor {
-                            // I'm not sure how the ancestor can be `None` here.
-                            result
-                                .insert(PathBuf::from(OsStrBytes::from_raw_bytes(ancestor.path)?));
+                        // The ancestor is absent if both sides added the
+                        // same path, in which case use whichever side is
+                        // present.
+                        if let Some(entry) = conflict.ancestor.or(conflict.our).or(conflict.their) {
+                            result.insert(PathBuf::from(OsStrBytes::from_raw_bytes(entry.path)?));
                         }
                     }
                     result
@@ -793,7 +1409,8 @@ Either create it, or update the main branch setting by running:
                     conflicting_paths,
                 }));
             }
-            let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, i32)>> = changed_pathbufs
+
+            changed_pathbufs
                 .into_iter()
                 .map(|changed_path| {
                     let value = match rebased_index.get_entry(&changed_path) {
@@ -805,8 +1422,8 @@ Either create it, or update the main branch setting by running:
                                 ?patch_commit,
                                 ?changed_path,
                                 "BUG: index entry was zero. \
-                                This probably indicates that a removed path \
-                                was not handled correctly."
+                                    This probably indicates that a removed path \
+                                    was not handled correctly."
                             );
                             None
                         }
@@ -826,65 +1443,83 @@ Either create it, or update the main branch setting by running:
                     };
                     (changed_path, value)
                 })
-                .collect();
-            let rebased_tree_oid =
-                hydrate_tree(self, Some(&target_commit.get_tree()?), rebased_entries)?;
-            self.find_tree(rebased_tree_oid)?
-                .ok_or_else(|| eyre::eyre!("Could not find just-hydrated tree"))?
+                .collect()
         };
+
+        let rebased_tree_oid = hydrate_tree(self, Some(&target_tree), rebased_entries)?;
+        let rebased_tree = self
+            .find_tree(rebased_tree_oid)?
+            .ok_or_else(|| eyre::eyre!("Could not find just-hydrated tree"))?;
         Ok(Ok(rebased_tree))
     }
 
-    #[instrument]
-    fn dehydrate_commit(
+    /// Determine the new entries for the given changed paths when applying the
+    /// patch from `ancestor_tree` to `patch_tree` onto `target_tree`, without
+    /// doing a merge.
+    ///
+    /// Returns: The entries to write into `target_tree` for the paths whose
+    /// contents change, or `None` if a path was changed differently in the
+    /// patch and the target, or if the change is structural (i.e. it involves
+    /// a directory replacing a file or vice-versa), in which case the caller
+    /// should fall back to a full merge.
+    #[instrument(skip(changed_paths))]
+    fn apply_changed_entries(
         &self,
-        commit: &Commit,
-        changed_paths: &[&Path],
-        base_on_parent: bool,
-    ) -> eyre::Result<Commit> {
-        let tree = commit.get_tree()?;
-        let dehydrated_tree_oid = dehydrate_tree(self, &tree, changed_paths)?;
-        let dehydrated_tree = self
-            .find_tree(dehydrated_tree_oid)?
-            .ok_or_else(|| eyre::eyre!("Could not find just-dehydrated tree"))?;
-
-        let signature = Signature {
-            inner: git2::Signature::new(
-                "git-branchless",
-                "git-branchless@example.com",
-                &git2::Time::new(0, 0),
-            )?,
+        changed_paths: &[PathBuf],
+        ancestor_tree: &Tree,
+        patch_tree: &Tree,
+        target_tree: &Tree,
+    ) -> eyre::Result<Option<ChangedEntries>> {
+        let tree_file_mode: i32 = git2::FileMode::Tree.into();
+        let is_tree = |entry: &Option<(NonZeroOid, i32)>| match entry {
+            Some((_oid, file_mode)) => *file_mode == tree_file_mode,
+            None => false,
         };
-        let message = format!(
-            "generated by git-branchless: temporary dehydrated commit \
-                \
-                This commit was originally: {:?}",
-            commit.get_oid()
-        );
 
-        let parents = if base_on_parent {
-            match commit.get_only_parent() {
-                Some(parent) => {
-                    let dehydrated_parent = self.dehydrate_commit(&parent, changed_paths, false)?;
-                    vec![dehydrated_parent]
+        let mut result = HashMap::new();
+        for changed_path in changed_paths {
+            let ancestor_entry = get_tree_entry(ancestor_tree, changed_path)?;
+            let patch_entry = get_tree_entry(patch_tree, changed_path)?;
+            let target_entry = get_tree_entry(target_tree, changed_path)?;
+            if is_tree(&ancestor_entry) || is_tree(&patch_entry) || is_tree(&target_entry) {
+                return Ok(None);
+            }
+
+            if target_entry == patch_entry {
+                // Already applied.
+                continue;
+            }
+            if target_entry != ancestor_entry {
+                return Ok(None);
+            }
+
+            if patch_entry.is_some() {
+                // If the path is being added under what is a file in the
+                // target, then the file would have to be replaced by a
+                // directory.
+                for parent_path in changed_path.ancestors().skip(1) {
+                    if parent_path.as_os_str().is_empty() {
+                        break;
+                    }
+                    match get_tree_entry(target_tree, parent_path)? {
+                        Some((_oid, file_mode)) if file_mode != tree_file_mode => return Ok(None),
+                        Some(_) => {}
+                        None => {}
+                    }
                 }
-                None => vec![],
             }
-        } else {
-            vec![]
-        };
-        let dehydrated_commit_oid = self
-            .create_commit(
-                None,
-                &signature,
-                &signature,
-                &message,
-                &dehydrated_tree,
-                parents.iter().collect_vec(),
-            )
-            .wrap_err_with(|| "Dehydrating commit")?;
-        let dehydrated_commit = self.find_commit_or_fail(dehydrated_commit_oid)?;
-        Ok(dehydrated_commit)
+            result.insert(changed_path.clone(), patch_entry);
+        }
+        Ok(Some(result))
+    }
+
+    #[instrument]
+    fn dehydrate_tree(&self, tree: &Tree, changed_paths: &[&Path]) -> eyre::Result<Tree<'_>> {
+        let dehydrated_tree_oid = dehydrate_tree(self, tree, changed_paths)?;
+        let dehydrated_tree = self
+            .find_tree(dehydrated_tree_oid)?
+            .ok_or_else(|| eyre::eyre!("Could not find just-dehydrated tree"))?;
+        Ok(dehydrated_tree)
     }
 
     /// Look up the tree with the given OID. Returns `None` if not found.
@@ -897,6 +1532,56 @@ Either create it, or update the main branch setting by running:
         }
     }
 
+    /// Write the given contents into the object database as a blob.
+    #[instrument(skip(contents))]
+    pub fn create_blob(&self, contents: &[u8]) -> eyre::Result<NonZeroOid> {
+        let oid = self.inner.blob(contents).map_err(wrap_git_error)?;
+        Ok(make_non_zero_oid(oid))
+    }
+
+    /// Get the contents of the blob with the given OID. Returns `None` if not
+    /// found.
+    #[instrument]
+    pub fn find_blob_contents(&self, oid: NonZeroOid) -> eyre::Result<Option<Vec<u8>>> {
+        match self.inner.find_blob(oid.inner) {
+            Ok(blob) => Ok(Some(blob.content().to_vec())),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(wrap_git_error(err)),
+        }
+    }
+
+    /// Create a tree containing the given blobs as regular files, keyed by
+    /// their paths.
+    #[instrument]
+    pub fn create_tree_from_blobs(
+        &self,
+        entries: HashMap<PathBuf, NonZeroOid>,
+    ) -> eyre::Result<Tree> {
+        let entries = entries
+            .into_iter()
+            .map(|(path, oid)| (path, Some((oid, i32::from(git2::FileMode::Blob)))))
+            .collect();
+        let tree_oid = hydrate_tree(self, None, entries)?;
+        self.find_tree(tree_oid)?
+            .ok_or_else(|| eyre::eyre!("Could not find just-created tree"))
+    }
+
+    /// Get the signature to use for commits created by `git-branchless`
+    /// itself, with the given timestamp. The user's configured identity is
+    /// used if available.
+    #[instrument]
+    pub fn get_branchless_signature(&self, now: SystemTime) -> eyre::Result<Signature<'_>> {
+        let signature = match self.inner.signature() {
+            Ok(signature) => signature,
+            Err(_) => git2::Signature::new(
+                "git-branchless",
+                "git-branchless@example.com",
+                &git2::Time::new(0, 0),
+            )?,
+        };
+        Signature { inner: signature }.update_timestamp(now)
+    }
+
     /// Write the provided in-memory index as a tree into Git`s object database.
     /// There must be no merge conflicts in the index.
     #[instrument]
@@ -951,6 +1636,16 @@ impl<'repo> Signature<'repo> {
     pub fn get_time(&self) -> git2::Time {
         self.inner.when()
     }
+
+    /// Get the name of this signature, if it's valid UTF-8.
+    pub fn get_name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Get the email address of this signature, if it's valid UTF-8.
+    pub fn get_email(&self) -> Option<&str> {
+        self.inner.email()
+    }
 }
 
 pub struct IndexEntry {
@@ -1232,6 +1927,129 @@ impl<'repo> Reference<'repo> {
     }
 }
 
+/// A set of reference updates which are applied together. If the same
+/// reference is updated more than once, only the last update takes effect.
+pub struct ReferenceTransaction<'repo> {
+    repo: &'repo git2::Repository,
+    updates: Vec<(OsString, Option<NonZeroOid>, String)>,
+}
+
+impl std::fmt::Debug for ReferenceTransaction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<ReferenceTransaction reference_names={:?}>",
+            self.get_reference_names()
+        )
+    }
+}
+
+fn reference_name_to_str(name: &OsStr) -> eyre::Result<&str> {
+    match name.to_str() {
+        Some(name) => Ok(name),
+        None => eyre::bail!(
+            "Reference name is not a UTF-8 string (libgit2 limitation): {:?}",
+            name
+        ),
+    }
+}
+
+impl<'repo> ReferenceTransaction<'repo> {
+    fn queue(
+        &mut self,
+        name: &OsStr,
+        oid: Option<NonZeroOid>,
+        log_message: &str,
+    ) -> eyre::Result<()> {
+        // Fail early, rather than when the transaction is committed.
+        reference_name_to_str(name)?;
+        let update = (name.to_owned(), oid, log_message.to_owned());
+        match self
+            .updates
+            .iter_mut()
+            .find(|(existing_name, _, _)| existing_name == name)
+        {
+            Some(existing_update) => *existing_update = update,
+            None => self.updates.push(update),
+        }
+        Ok(())
+    }
+
+    /// Queue an update to make the given reference point to `oid`. The
+    /// reference is created if it doesn't already exist.
+    #[instrument]
+    pub fn set_target(
+        &mut self,
+        name: &OsStr,
+        oid: NonZeroOid,
+        log_message: &str,
+    ) -> eyre::Result<()> {
+        self.queue(name, Some(oid), log_message)
+    }
+
+    /// Queue the deletion of the given reference.
+    #[instrument]
+    pub fn remove(&mut self, name: &OsStr) -> eyre::Result<()> {
+        self.queue(name, None, "")
+    }
+
+    /// Get the names of the references which have been queued for update, in
+    /// the order that they were first queued.
+    pub fn get_reference_names(&self) -> Vec<&OsStr> {
+        self.updates
+            .iter()
+            .map(|(name, _, _)| name.as_os_str())
+            .collect()
+    }
+
+    /// Apply all queued updates.
+    ///
+    /// All of the references are locked before any of them are written, so
+    /// a concurrent update to one of them causes the whole transaction to fail
+    /// without changes. However, libgit2 stops at the first reference which
+    /// fails to be written, without rolling back the references which were
+    /// already written. If an error is returned, callers should re-read the
+    /// queued references to determine their current state. See
+    /// https://github.com/libgit2/libgit2/issues/5918
+    #[instrument]
+    pub fn commit(self) -> eyre::Result<()> {
+        let mut transaction = self
+            .repo
+            .transaction()
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| "Starting reference transaction")?;
+        for (name, _oid, _log_message) in self.updates.iter() {
+            transaction
+                .lock_ref(reference_name_to_str(name)?)
+                .map_err(wrap_git_error)
+                .wrap_err_with(|| format!("Locking reference: {:?}", name))?;
+        }
+        for (name, oid, log_message) in self.updates.iter() {
+            let name_str = reference_name_to_str(name)?;
+            match oid {
+                Some(oid) => transaction
+                    .set_target(name_str, oid.inner, None, log_message)
+                    .map_err(wrap_git_error)
+                    .wrap_err_with(|| format!("Setting target of reference: {:?}", name))?,
+                None => transaction
+                    .remove(name_str)
+                    .map_err(wrap_git_error)
+                    .wrap_err_with(|| format!("Removing reference: {:?}", name))?,
+            }
+        }
+        transaction
+            .commit()
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| {
+                format!(
+                    "Committing reference transaction: {:?}",
+                    self.get_reference_names()
+                )
+            })?;
+        Ok(())
+    }
+}
+
 /// Determine what kind of branch a reference is, given its name. The returned
 /// `suffix` value is converted to a `String` to be rendered to the screen, so
 /// it may have lost some information if the reference name had unusual
@@ -1256,6 +2074,15 @@ pub enum CategorizedReferenceName<'a> {
         prefix: &'static str,
     },
 
+    /// The reference represents a tag.
+    Tag {
+        /// The full name of the reference.
+        name: &'a OsStr,
+
+        /// The string `refs/tags/`.
+        prefix: &'static str,
+    },
+
     /// Some other kind of reference which isn't a branch at all.
     OtherRef {
         /// The full name of the reference.
@@ -1277,6 +2104,11 @@ impl<'a> CategorizedReferenceName<'a> {
                 name,
                 prefix: "refs/remotes/",
             }
+        } else if bytes.starts_with(b"refs/tags/") {
+            Self::Tag {
+                name,
+                prefix: "refs/tags/",
+            }
         } else {
             Self::OtherRef { name }
         }
@@ -1290,6 +2122,7 @@ impl<'a> CategorizedReferenceName<'a> {
         let (name, prefix): (_, &'static str) = match self {
             Self::LocalBranch { name, prefix } => (name, prefix),
             Self::RemoteBranch { name, prefix } => (name, prefix),
+            Self::Tag { name, prefix } => (name, prefix),
             Self::OtherRef { name } => (name, ""),
         };
         let bytes = name.to_raw_bytes();
@@ -1307,6 +2140,7 @@ impl<'a> CategorizedReferenceName<'a> {
         let name = match self {
             Self::LocalBranch { name, prefix: _ } => name,
             Self::RemoteBranch { name, prefix: _ } => name,
+            Self::Tag { name, prefix: _ } => name,
             Self::OtherRef { name } => name,
         };
         name.to_string_lossy().into_owned()
@@ -1319,6 +2153,7 @@ impl<'a> CategorizedReferenceName<'a> {
         let (name, prefix): (_, &'static str) = match self {
             Self::LocalBranch { name, prefix } => (name, prefix),
             Self::RemoteBranch { name, prefix } => (name, prefix),
+            Self::Tag { name, prefix } => (name, prefix),
             Self::OtherRef { name } => (name, ""),
         };
         let name = name.to_string_lossy();
@@ -1339,6 +2174,7 @@ impl<'a> CategorizedReferenceName<'a> {
             CategorizedReferenceName::RemoteBranch { .. } => {
                 format!("remote branch {}", name)
             }
+            CategorizedReferenceName::Tag { .. } => format!("tag {}", name),
             CategorizedReferenceName::OtherRef { .. } => format!("ref {}", name),
         };
         name
@@ -1369,6 +2205,10 @@ impl<'repo> Branch<'repo> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::core::formatting::Glyphs;
+    use crate::git::DiffLineKind;
     use crate::testing::make_git;
 
     use super::*;
@@ -1422,6 +2262,8 @@ mod tests {
             &initial2_commit,
             &CherryPickFastOptions {
                 reuse_parent_tree_if_possible: false,
+                rename_limit: 1000,
+                rename_threshold: 50,
             },
         )?;
 
@@ -1445,4 +2287,453 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_cherry_pick_commit_in_memory() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.run(&["checkout", "-b", "foo"])?;
+        let test1_oid = git.commit_file_with_contents("test1", 1, "test1 contents")?;
+        git.run(&["checkout", "master"])?;
+        let initial2_oid =
+            git.commit_file_with_contents("initial", 2, "updated initial contents")?;
+
+        let repo = git.get_repo()?;
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let initial2_commit = repo.find_commit_or_fail(initial2_oid)?;
+        let options = CherryPickFastOptions {
+            reuse_parent_tree_if_possible: false,
+            rename_limit: 1000,
+            rename_threshold: 50,
+        };
+        let rebased_oid = repo
+            .cherry_pick_commit_in_memory(
+                &test1_commit,
+                &initial2_commit,
+                &test1_commit.get_committer(),
+                None,
+                &options,
+            )?
+            .unwrap();
+
+        let rebased_commit = repo.find_commit_or_fail(rebased_oid)?;
+        assert_eq!(rebased_commit.get_parent_oids(), vec![initial2_oid]);
+        assert_eq!(
+            rebased_commit.get_message_raw()?,
+            test1_commit.get_message_raw()?
+        );
+        assert_eq!(
+            rebased_commit.get_author().get_name(),
+            test1_commit.get_author().get_name()
+        );
+        insta::assert_debug_snapshot!(rebased_commit.get_tree()?.inner.iter().map(|entry| entry.name().unwrap().to_string()).collect_vec(), @r###"
+        [
+            "initial.txt",
+            "test1.txt",
+        ]
+        "###);
+
+        // Nothing else was changed.
+        assert_eq!(repo.get_head_info()?.oid, Some(initial2_oid));
+        let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+        assert!(!branch_oid_to_names.contains_key(&rebased_oid));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_diff() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let test1_oid = git.commit_file_with_contents("test1", 1, "line 1\nline 2\nline 3\n")?;
+        git.commit_file_with_contents("test1", 2, "line 1\nline 2 updated\nline 3\n")?;
+        git.run(&["rm", "initial.txt"])?;
+        git.run(&["commit", "-m", "remove initial.txt"])?;
+        let head_oid = git.commit_file("test2", 3)?;
+
+        let repo = git.get_repo()?;
+        let old_commit = repo.find_commit_or_fail(test1_oid)?;
+        let new_commit = repo.find_commit_or_fail(head_oid)?;
+        let diff = repo.get_diff(&old_commit, &new_commit)?;
+        let diff = diff
+            .iter()
+            .map(|file_diff| {
+                let hunks = file_diff
+                    .hunks
+                    .iter()
+                    .map(|hunk| {
+                        let lines = hunk
+                            .lines
+                            .iter()
+                            .map(|line| {
+                                let prefix = match line.kind {
+                                    DiffLineKind::Context => ' ',
+                                    DiffLineKind::Added => '+',
+                                    DiffLineKind::Removed => '-',
+                                };
+                                format!("{}{}", prefix, String::from_utf8_lossy(&line.content))
+                            })
+                            .join("");
+                        format!(
+                            "@@ -{},{} +{},{} @@\n{}",
+                            hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines, lines
+                        )
+                    })
+                    .join("");
+                format!(
+                    "{:?} {:?}\n{}",
+                    file_diff.kind,
+                    file_diff.get_path().unwrap(),
+                    hunks
+                )
+            })
+            .join("");
+        insta::assert_snapshot!(diff, @r###"
+        Deleted "initial.txt"
+        @@ -1,1 +0,0 @@
+        -initial contents
+        Modified "test1.txt"
+        @@ -1,3 +1,3 @@
+         line 1
+        -line 2
+        +line 2 updated
+         line 3
+        Added "test2.txt"
+        @@ -0,0 +1,1 @@
+        +test2 contents
+        "###);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_db_path() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let repo = git.get_repo()?;
+        assert_eq!(
+            repo.get_db_path()?,
+            repo.get_path().join("branchless").join("db.sqlite3")
+        );
+
+        let cache_dir = tempfile::tempdir()?;
+        git.set_config(
+            "branchless.db.path",
+            &cache_dir.path().join("{repo}").to_string_lossy(),
+        )?;
+        let repo = git.get_repo()?;
+        let db_path = repo.get_db_path()?;
+        let repo_dir = db_path
+            .strip_prefix(cache_dir.path())?
+            .parent()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(!repo_dir.contains('/'), "{:?}", repo_dir);
+        assert_eq!(repo_dir, repo.get_db_repo_name()?);
+        assert!(db_path.ends_with("db.sqlite3"));
+
+        repo.get_db_conn()?;
+        assert!(db_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_status() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        let repo = git.get_repo()?;
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let effects = Effects::new_from_buffer_for_test(Glyphs::text(), &buffer);
+        assert_eq!(repo.get_status(&effects)?, Vec::new());
+        assert!(!repo.has_changed_files(&effects)?);
+
+        std::fs::write(git.repo_path.join("untracked.txt"), "untracked\n")?;
+        assert!(!repo.has_changed_files(&effects)?);
+
+        std::fs::write(git.repo_path.join("test1.txt"), "staged\n")?;
+        git.run(&["add", "test1.txt"])?;
+        std::fs::write(git.repo_path.join("test1.txt"), "staged and unstaged\n")?;
+        std::fs::remove_file(git.repo_path.join("test2.txt"))?;
+        std::fs::write(git.repo_path.join("new.txt"), "new\n")?;
+        git.run(&["add", "new.txt"])?;
+
+        insta::assert_debug_snapshot!(repo.get_status(&effects)?, @r###"
+        [
+            StatusEntry {
+                path: "new.txt",
+                staged: Some(
+                    Added,
+                ),
+                unstaged: None,
+                is_conflicted: false,
+            },
+            StatusEntry {
+                path: "test1.txt",
+                staged: Some(
+                    Modified,
+                ),
+                unstaged: Some(
+                    Modified,
+                ),
+                is_conflicted: false,
+            },
+            StatusEntry {
+                path: "test2.txt",
+                staged: None,
+                unstaged: Some(
+                    Deleted,
+                ),
+                is_conflicted: false,
+            },
+            StatusEntry {
+                path: "untracked.txt",
+                staged: None,
+                unstaged: Some(
+                    Added,
+                ),
+                is_conflicted: false,
+            },
+        ]
+        "###);
+        assert!(repo.has_changed_files(&effects)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cherry_pick_fast_rename_detection() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let contents: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        git.commit_file_with_contents("foo", 1, &contents)?;
+        git.run(&["checkout", "-b", "rename"])?;
+        git.run(&["mv", "foo.txt", "bar.txt"])?;
+        git.run(&["commit", "-m", "rename foo to bar"])?;
+        let rename_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+        git.run(&["checkout", "master"])?;
+        let update_oid =
+            git.commit_file_with_contents("foo", 2, &format!("{}line 21\n", contents))?;
+
+        let repo = git.get_repo()?;
+        let rename_commit = repo.find_commit_or_fail(rename_oid)?;
+        let update_commit = repo.find_commit_or_fail(update_oid)?;
+        let cherry_pick = |rename_limit| {
+            repo.cherry_pick_fast(
+                &rename_commit,
+                &update_commit,
+                &CherryPickFastOptions {
+                    reuse_parent_tree_if_possible: false,
+                    rename_limit,
+                    rename_threshold: 50,
+                },
+            )
+        };
+
+        let tree = cherry_pick(1000)?.unwrap();
+        insta::assert_debug_snapshot!(tree.inner.iter().map(|entry| entry.name().unwrap().to_string()).collect_vec(), @r###"
+        [
+            "bar.txt",
+            "initial.txt",
+        ]
+        "###);
+        let bar_oid = tree.get_oid_for_path(Path::new("bar.txt"))?;
+        let foo_oid = update_commit
+            .get_tree()?
+            .get_oid_for_path(Path::new("foo.txt"))?;
+        assert_eq!(bar_oid, foo_oid);
+
+        let result = cherry_pick(0)?;
+        insta::assert_debug_snapshot!(result, @r###"
+        Err(
+            MergeConflict {
+                conflicting_paths: {
+                    "foo.txt",
+                },
+            },
+        )
+        "###);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cherry_pick_fast_already_applied() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.run(&["checkout", "-b", "foo"])?;
+        let test1_oid = git.commit_file_with_contents("test1", 1, "test1 contents")?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file_with_contents("test1", 1, "test1 contents")?;
+        let initial2_oid =
+            git.commit_file_with_contents("initial", 2, "updated initial contents")?;
+
+        let repo = git.get_repo()?;
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let initial2_commit = repo.find_commit_or_fail(initial2_oid)?;
+        let tree = repo
+            .cherry_pick_fast(
+                &test1_commit,
+                &initial2_commit,
+                &CherryPickFastOptions {
+                    reuse_parent_tree_if_possible: false,
+                    rename_limit: 1000,
+                    rename_threshold: 50,
+                },
+            )?
+            .unwrap();
+        assert_eq!(tree.get_oid(), initial2_commit.get_tree()?.get_oid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cherry_pick_fast_conflicting_paths() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "foo"])?;
+        let test1_oid = git.commit_file_with_contents("test1", 2, "foo contents")?;
+        let test2_oid = git.commit_file("test2", 3)?;
+        git.run(&["checkout", "master"])?;
+        let test1_updated_oid = git.commit_file_with_contents("test1", 2, "master contents")?;
+
+        let repo = git.get_repo()?;
+        let options = CherryPickFastOptions {
+            reuse_parent_tree_if_possible: false,
+            rename_limit: 1000,
+            rename_threshold: 50,
+        };
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let test2_commit = repo.find_commit_or_fail(test2_oid)?;
+        let test1_updated_commit = repo.find_commit_or_fail(test1_updated_oid)?;
+
+        // `test1.txt` was changed differently in both commits, so a merge is
+        // necessary.
+        insta::assert_debug_snapshot!(repo.cherry_pick_fast(&test1_commit, &test1_updated_commit, &options)?, @r###"
+        Err(
+            MergeConflict {
+                conflicting_paths: {
+                    "test1.txt",
+                },
+            },
+        )
+        "###);
+
+        // `test2.txt` wasn't touched on `master`, so the new entry can be
+        // added directly.
+        let tree = repo
+            .cherry_pick_fast(&test2_commit, &test1_updated_commit, &options)?
+            .unwrap();
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test1.txt"))?,
+            test1_updated_commit
+                .get_tree()?
+                .get_oid_for_path(Path::new("test1.txt"))?
+        );
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test2.txt"))?,
+            test2_commit
+                .get_tree()?
+                .get_oid_for_path(Path::new("test2.txt"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_transaction() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["branch", "foo", &test1_oid.to_string()])?;
+        git.run(&["branch", "bar", &test1_oid.to_string()])?;
+
+        let repo = git.get_repo()?;
+        let names_matching = |glob: &str| -> eyre::Result<Vec<OsString>> {
+            let mut names = repo
+                .get_references_matching(glob)?
+                .into_iter()
+                .map(|reference| reference.get_name())
+                .collect::<eyre::Result<Vec<_>>>()?;
+            names.sort_unstable();
+            Ok(names)
+        };
+        insta::assert_debug_snapshot!(names_matching("refs/heads/*")?, @r###"
+        [
+            "refs/heads/bar",
+            "refs/heads/foo",
+            "refs/heads/master",
+        ]
+        "###);
+
+        let mut transaction = repo.start_reference_transaction()?;
+        transaction.set_target(OsStr::new("refs/heads/foo"), test2_oid, "test")?;
+        transaction.remove(OsStr::new("refs/heads/bar"))?;
+        transaction.remove(OsStr::new("refs/heads/baz"))?;
+        transaction.set_target(OsStr::new("refs/heads/baz"), test1_oid, "test")?;
+        assert_eq!(
+            transaction.get_reference_names(),
+            vec!["refs/heads/foo", "refs/heads/bar", "refs/heads/baz"]
+        );
+
+        // Nothing should be updated until the transaction is committed.
+        insta::assert_debug_snapshot!(names_matching("refs/heads/*")?, @r###"
+        [
+            "refs/heads/bar",
+            "refs/heads/foo",
+            "refs/heads/master",
+        ]
+        "###);
+
+        transaction.commit()?;
+        insta::assert_debug_snapshot!(names_matching("refs/heads/*")?, @r###"
+        [
+            "refs/heads/baz",
+            "refs/heads/foo",
+            "refs/heads/master",
+        ]
+        "###);
+        let foo_oid = repo
+            .find_reference(OsStr::new("refs/heads/foo"))?
+            .unwrap()
+            .peel_to_commit()?
+            .map(|commit| commit.get_oid());
+        assert_eq!(foo_oid, Some(test2_oid));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_config_at_level() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let repo = git.get_repo()?;
+        let config_path = repo.get_path().join("config");
+        let mut local_config = repo.get_config_at_level(ConfigLevel::Local)?;
+        local_config.set("branchless.test.key", "local value")?;
+        assert!(std::fs::read_to_string(&config_path)?.contains("local value"));
+        assert_eq!(
+            repo.get_config()?.get::<String, _>("branchless.test.key")?,
+            Some("local value".to_string())
+        );
+
+        local_config.remove("branchless.test.key")?;
+        assert!(!std::fs::read_to_string(&config_path)?.contains("local value"));
+
+        Ok(())
+    }
 }
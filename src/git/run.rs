@@ -3,7 +3,7 @@ use std::convert::TryInto;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::io::{BufRead, BufReader, Read, Write as WriteIo};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
@@ -15,8 +15,8 @@ use tracing::instrument;
 use crate::core::config::get_core_hooks_path;
 use crate::core::eventlog::{EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
 use crate::git::repo::Repo;
-use crate::tui::{Effects, OperationType};
-use crate::util::get_sh;
+use crate::tui::{Effects, OperationType, Verbosity};
+use crate::util::{get_sh, is_executable};
 
 /// Path to the `git` executable on disk to be executed.
 #[derive(Clone)]
@@ -41,7 +41,22 @@ impl std::fmt::Debug for GitRunInfo {
     }
 }
 
+/// The result of running Git with `GitRunInfo::run_and_capture`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitRunResult {
+    /// The exit code of Git (non-zero signifies error).
+    pub exit_code: isize,
+
+    /// Everything that Git wrote to stdout.
+    pub stdout: String,
+
+    /// Everything that Git wrote to stderr.
+    pub stderr: String,
+}
+
 impl GitRunInfo {
+    /// Copy each line of `stream` to `output` as soon as it's available, and
+    /// return all of the lines once `stream` is closed.
     fn spawn_writer_thread<
         InputStream: Read + Send + 'static,
         OutputStream: Write + Send + 'static,
@@ -49,17 +64,21 @@ impl GitRunInfo {
         &self,
         stream: Option<InputStream>,
         mut output: OutputStream,
-    ) -> JoinHandle<()> {
+    ) -> JoinHandle<String> {
         thread::spawn(move || {
+            let mut captured = String::new();
             let stream = match stream {
                 Some(stream) => stream,
-                None => return,
+                None => return captured,
             };
             let reader = BufReader::new(stream);
             for line in reader.lines() {
                 let line = line.expect("Reading line from subprocess");
                 writeln!(output, "{}", line).expect("Writing line from subprocess");
+                captured.push_str(&line);
+                captured.push('\n');
             }
+            captured
         })
     }
 
@@ -72,7 +91,6 @@ impl GitRunInfo {
     /// executable itself.
     ///
     /// Returns the exit code of Git (non-zero signifies error).
-    #[instrument]
     #[must_use = "The return code for `run_git` must be checked"]
     pub fn run<S: AsRef<OsStr> + std::fmt::Debug>(
         &self,
@@ -80,6 +98,25 @@ impl GitRunInfo {
         event_tx_id: Option<EventTransactionId>,
         args: &[S],
     ) -> eyre::Result<isize> {
+        let GitRunResult {
+            exit_code,
+            stdout: _,
+            stderr: _,
+        } = self.run_and_capture(effects, event_tx_id, args)?;
+        Ok(exit_code)
+    }
+
+    /// Same as `run`, but also captures the output of Git while it's being
+    /// shown to the user, such as to report it again if the command fails.
+    /// Output which isn't shown at the current verbosity is still captured.
+    #[instrument]
+    #[must_use = "The return code for `run_and_capture` must be checked"]
+    pub fn run_and_capture<S: AsRef<OsStr> + std::fmt::Debug>(
+        &self,
+        effects: &Effects,
+        event_tx_id: Option<EventTransactionId>,
+        args: &[S],
+    ) -> eyre::Result<GitRunResult> {
         let GitRunInfo {
             path_to_git,
             working_directory,
@@ -95,7 +132,7 @@ impl GitRunInfo {
         let (effects, _progress) =
             effects.start_operation(OperationType::RunGitCommand(Arc::new(command_string)));
         writeln!(
-            effects.get_output_stream(),
+            effects.get_output_stream_at(Verbosity::Normal),
             "branchless: running command: {} {}",
             &path_to_git.to_string_lossy(),
             &args_string
@@ -117,7 +154,8 @@ impl GitRunInfo {
             .wrap_err_with(|| format!("Spawning Git subprocess: {:?} {:?}", path_to_git, args))?;
 
         let stdout = child.stdout.take();
-        let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
+        let stdout_thread =
+            self.spawn_writer_thread(stdout, effects.get_output_stream_at(Verbosity::Normal));
         let stderr = child.stderr.take();
         let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
 
@@ -127,8 +165,8 @@ impl GitRunInfo {
                 path_to_git, args
             )
         })?;
-        stdout_thread.join().unwrap();
-        stderr_thread.join().unwrap();
+        let stdout = stdout_thread.join().unwrap();
+        let stderr = stderr_thread.join().unwrap();
 
         // On Unix, if the child process was terminated by a signal, we need to call
         // some Unix-specific functions to access the signal that terminated it. For
@@ -137,7 +175,11 @@ impl GitRunInfo {
         let exit_code = exit_code
             .try_into()
             .wrap_err_with(|| format!("Converting exit code {} from i32 to isize", exit_code))?;
-        Ok(exit_code)
+        Ok(GitRunResult {
+            exit_code,
+            stdout,
+            stderr,
+        })
     }
 
     /// Run Git silently (don't display output to the user).
@@ -193,7 +235,14 @@ impl GitRunInfo {
         Ok(result)
     }
 
-    /// Run a provided Git hook if it exists for the repository.
+    /// Run a provided Git hook if it exists for the repository, the way that
+    /// Git itself would: in the root of the working copy, with the hooks
+    /// directory on the `PATH`, `GIT_PREFIX` set, and the provided `stdin`
+    /// payload. The variables in `env` are set in addition to the ones in
+    /// this `GitRunInfo`.
+    ///
+    /// Returns the exit code of the hook, or 0 if it wasn't run. It's up to
+    /// the caller to decide whether a failing hook should abort the operation.
     ///
     /// See the man page for `githooks(5)` for more detail on Git hooks.
     #[instrument]
@@ -205,73 +254,148 @@ impl GitRunInfo {
         event_tx_id: EventTransactionId,
         args: &[S],
         stdin: Option<OsString>,
-    ) -> eyre::Result<()> {
+        env: &HashMap<OsString, OsString>,
+    ) -> eyre::Result<isize> {
         let hook_dir = get_core_hooks_path(repo)?;
+        let hook_path = hook_dir.join(hook_name);
+        // As with Git, hooks which aren't executable are ignored.
+        if !is_executable(&hook_path) {
+            return Ok(0);
+        }
 
         let GitRunInfo {
             // We're calling a Git hook, but not Git itself.
             path_to_git: _,
-            // We always want to call the hook in the Git working copy,
-            // regardless of where the Git executable was invoked.
-            working_directory: _,
-            env,
+            working_directory,
+            env: base_env,
         } = self;
         let path = {
             let mut path_components: Vec<PathBuf> = vec![std::fs::canonicalize(&hook_dir)?];
-            if let Some(path) = env.get(&OsString::from("PATH")) {
+            if let Some(path) = base_env.get(&OsString::from("PATH")) {
                 path_components.extend(std::env::split_paths(path));
             }
             std::env::join_paths(path_components)?
         };
 
-        if hook_dir.join(hook_name).exists() {
-            let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
-                // From `githooks(5)`: Before Git invokes a hook, it changes its
-                // working directory to either $GIT_DIR in a bare repository or the
-                // root of the working tree in a non-bare repository.
-                .current_dir(
-                    repo.get_working_copy_path()
-                        .unwrap_or_else(|| repo.get_path()),
-                )
-                .arg("-c")
-                .arg(format!("{} \"$@\"", hook_name))
-                .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
-                .args(args.iter().map(|arg| arg.as_ref()))
-                .env_clear()
-                .envs(env.iter())
-                .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
-                .env("PATH", &path)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
-
-            if let Some(stdin) = stdin {
-                child
-                    .stdin
-                    .as_mut()
-                    .unwrap()
-                    .write_all(&stdin.to_raw_bytes())
-                    .wrap_err_with(|| "Writing hook process stdin")?;
+        // From `githooks(5)`: Before Git invokes a hook, it changes its
+        // working directory to either $GIT_DIR in a bare repository or the
+        // root of the working tree in a non-bare repository.
+        let hook_working_directory = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path());
+        // Git also tells the hook which subdirectory it was invoked from, with
+        // a trailing slash (or the empty string for the root).
+        let git_prefix = get_git_prefix(hook_working_directory, working_directory);
+
+        let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
+            .current_dir(hook_working_directory)
+            .arg("-c")
+            .arg(format!("{} \"$@\"", hook_name))
+            .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
+            .args(args.iter().map(|arg| arg.as_ref()))
+            .env_clear()
+            .envs(base_env.iter())
+            .env("GIT_PREFIX", git_prefix)
+            .envs(env.iter())
+            .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
+            .env("PATH", &path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
+
+        // Start reading the output before writing the input, so that a hook
+        // which produces a lot of output can't block on a full pipe.
+        let stdout = child.stdout.take();
+        let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
+        let stderr = child.stderr.take();
+        let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+
+        if let Some(stdin) = stdin {
+            let mut child_stdin = child.stdin.take().unwrap();
+            // The hook may exit without reading all of its input, which isn't
+            // an error.
+            match child_stdin.write_all(&stdin.to_raw_bytes()) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+                Err(err) => {
+                    return Err(err).wrap_err_with(|| "Writing hook process stdin");
+                }
             }
+        }
 
-            let stdout = child.stdout.take();
-            let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
-            let stderr = child.stderr.take();
-            let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+        let exit_status: ExitStatus = child.wait()?;
+        stdout_thread.join().unwrap();
+        stderr_thread.join().unwrap();
+        let exit_code: isize = exit_status.code().unwrap_or(1).try_into()?;
+        Ok(exit_code)
+    }
+}
 
-            let _ignored: ExitStatus = child.wait()?;
-            stdout_thread.join().unwrap();
-            stderr_thread.join().unwrap();
+/// Compute the value of `GIT_PREFIX` for a hook: the path of
+/// `working_directory` relative to `root`, with a trailing slash, or the empty
+/// string if it's not inside `root`.
+fn get_git_prefix(root: &Path, working_directory: &Path) -> OsString {
+    let canonicalize =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let root = canonicalize(root);
+    let working_directory = canonicalize(working_directory);
+    match working_directory.strip_prefix(&root) {
+        Ok(prefix) if prefix.as_os_str().is_empty() => OsString::new(),
+        Ok(prefix) => {
+            let mut prefix = prefix.as_os_str().to_owned();
+            prefix.push("/");
+            prefix
         }
-        Ok(())
+        Err(_) => OsString::new(),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::core::formatting::Glyphs;
     use crate::testing::make_git;
+    use crate::tui::Effects;
+
+    use super::GitRunResult;
+
+    #[test]
+    fn test_run_and_capture() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let git_run_info = git.get_git_run_info();
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let effects = Effects::new_from_buffer_for_test(Glyphs::text(), &buffer);
+        let GitRunResult {
+            exit_code,
+            stdout,
+            stderr,
+        } = git_run_info.run_and_capture(&effects, None, &["rev-parse", "HEAD", "nonexistent"])?;
+        assert_eq!(exit_code, 128);
+        assert_eq!(
+            stdout,
+            "f777ecc9b0db5ed372b2615695191a8a17f79f24\nnonexistent\n"
+        );
+        assert!(
+            stderr.contains("ambiguous argument 'nonexistent'"),
+            "{}",
+            stderr
+        );
+
+        // The output was also shown to the user as it was produced.
+        let output = String::from_utf8(buffer.lock().unwrap().clone())?;
+        assert!(
+            output.contains("f777ecc9b0db5ed372b2615695191a8a17f79f24"),
+            "{}",
+            output
+        );
+
+        Ok(())
+    }
 
     #[test]
     fn test_hook_working_dir() -> eyre::Result<()> {
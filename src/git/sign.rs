@@ -0,0 +1,185 @@
+//! Sign commits in the same way as `git commit --gpg-sign`.
+//!
+//! Git doesn't expose its signing machinery, so this reads the same
+//! configuration (`commit.gpgSign`, `gpg.format`, `user.signingKey` and
+//! `gpg.<format>.program`) and invokes the signing program with the same
+//! arguments that Git would.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use tracing::instrument;
+
+use super::Repo;
+
+/// The kind of key used to sign commits, as set by `gpg.format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SigningFormat {
+    OpenPgp,
+    X509,
+    Ssh,
+}
+
+/// Signs commits with the key configured for the repository.
+#[derive(Debug)]
+pub struct CommitSigner {
+    format: SigningFormat,
+    program: String,
+    key: Option<String>,
+}
+
+impl CommitSigner {
+    /// Get the signer configured for the repository.
+    ///
+    /// Returns: The signer, or `None` if `commit.gpgSign` isn't set.
+    #[instrument]
+    pub fn from_repo(repo: &Repo) -> eyre::Result<Option<Self>> {
+        let config = repo.get_config()?;
+        if !config.get_or("commit.gpgSign", false)? {
+            return Ok(None);
+        }
+
+        let format: String = config.get_or("gpg.format", "openpgp".to_string())?;
+        let (format, default_program) = match format.as_str() {
+            "openpgp" => (SigningFormat::OpenPgp, "gpg"),
+            "x509" => (SigningFormat::X509, "gpgsm"),
+            "ssh" => (SigningFormat::Ssh, "ssh-keygen"),
+            format => eyre::bail!("Unsupported value for gpg.format: {:?}", format),
+        };
+        let program: Option<String> = config.get(format!(
+            "gpg.{}.program",
+            match format {
+                SigningFormat::OpenPgp => "openpgp",
+                SigningFormat::X509 => "x509",
+                SigningFormat::Ssh => "ssh",
+            }
+        ))?;
+        let program = match program {
+            Some(program) => program,
+            None => match format {
+                // `gpg.program` is the older name for `gpg.openpgp.program`.
+                SigningFormat::OpenPgp => {
+                    config.get_or("gpg.program", default_program.to_string())?
+                }
+                SigningFormat::X509 | SigningFormat::Ssh => default_program.to_string(),
+            },
+        };
+
+        // Like Git, default to the committer's identity as the key for GPG.
+        let key: Option<String> = config.get("user.signingKey")?;
+        let key = match key {
+            Some(key) => Some(key),
+            None if format == SigningFormat::Ssh => None,
+            None => {
+                let name: Option<String> = config.get("user.name")?;
+                let email: Option<String> = config.get("user.email")?;
+                match (name, email) {
+                    (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+                    _ => None,
+                }
+            }
+        };
+
+        Ok(Some(CommitSigner {
+            format,
+            program,
+            key,
+        }))
+    }
+
+    /// Sign the given commit object contents.
+    ///
+    /// Returns: The armored signature, suitable for the `gpgsig` header of the
+    /// commit.
+    #[instrument(skip(content))]
+    pub fn sign(&self, content: &str) -> eyre::Result<String> {
+        match self.format {
+            SigningFormat::OpenPgp | SigningFormat::X509 => self.sign_gpg(content),
+            SigningFormat::Ssh => self.sign_ssh(content),
+        }
+    }
+
+    fn sign_gpg(&self, content: &str) -> eyre::Result<String> {
+        let mut command = Command::new(&self.program);
+        command.arg("--status-fd=2");
+        match &self.key {
+            Some(key) => command.args(["-bsau", key]),
+            None => command.arg("-bsa"),
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Spawning signing program: {:?}", self.program))?;
+        {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| eyre::eyre!("Could not open stdin for signing program"))?;
+            stdin.write_all(content.as_bytes())?;
+        }
+        let output = child
+            .wait_with_output()
+            .wrap_err("Waiting for signing program")?;
+
+        // Like Git, check the status output as well as the exit code, since
+        // `gpg` can exit successfully without producing a signature.
+        let status = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+            eyre::bail!(
+                "{} failed to sign the data: {}",
+                self.program,
+                status.trim()
+            );
+        }
+        String::from_utf8(output.stdout).wrap_err("Decoding signature")
+    }
+
+    fn sign_ssh(&self, content: &str) -> eyre::Result<String> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => eyre::bail!("No SSH key to sign with; set user.signingKey"),
+        };
+
+        // `ssh-keygen` signs files rather than its standard input, and writes
+        // the signature next to the file.
+        let temp_dir = tempfile::tempdir().wrap_err("Creating temporary directory")?;
+        let buffer_path = temp_dir.path().join("buffer");
+        std::fs::write(&buffer_path, content).wrap_err("Writing data to sign")?;
+
+        let mut command = Command::new(&self.program);
+        command.args(["-Y", "sign", "-n", "git", "-f"]);
+        match key.strip_prefix("key::").or_else(|| {
+            if key.starts_with("ssh-") {
+                Some(key.as_str())
+            } else {
+                None
+            }
+        }) {
+            Some(literal_key) => {
+                // A literal public key, whose private key is expected to be in
+                // `ssh-agent`.
+                let key_path = temp_dir.path().join("key.pub");
+                std::fs::write(&key_path, literal_key).wrap_err("Writing SSH public key")?;
+                command.arg(&key_path).arg("-U");
+            }
+            None => {
+                command.arg(key);
+            }
+        }
+        let output = command
+            .arg(&buffer_path)
+            .output()
+            .wrap_err_with(|| format!("Running signing program: {:?}", self.program))?;
+        if !output.status.success() {
+            eyre::bail!(
+                "{} failed to sign the data: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        std::fs::read_to_string(temp_dir.path().join("buffer.sig")).wrap_err("Reading signature")
+    }
+}
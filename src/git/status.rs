@@ -0,0 +1,89 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use eyre::Context;
+use os_str_bytes::OsStrBytes;
+
+use super::diff::FileChangeKind;
+
+/// The status of a single path in the working copy, like a line of `git
+/// status --porcelain`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusEntry {
+    /// The path, relative to the root of the working copy.
+    pub path: PathBuf,
+
+    /// The change between `HEAD` and the index, if any.
+    pub staged: Option<FileChangeKind>,
+
+    /// The change between the index and the working copy, if any. Untracked
+    /// files are reported as unstaged additions.
+    pub unstaged: Option<FileChangeKind>,
+
+    /// Whether the path has unresolved merge conflicts.
+    pub is_conflicted: bool,
+}
+
+impl StatusEntry {
+    /// Whether the path is untracked, i.e. neither in `HEAD` nor in the
+    /// index.
+    pub fn is_untracked(&self) -> bool {
+        self.staged.is_none() && self.unstaged == Some(FileChangeKind::Added)
+    }
+}
+
+fn get_staged_change(status: git2::Status) -> Option<FileChangeKind> {
+    if status.is_index_new() {
+        Some(FileChangeKind::Added)
+    } else if status.is_index_deleted() {
+        Some(FileChangeKind::Deleted)
+    } else if status.is_index_typechange() {
+        Some(FileChangeKind::TypeChanged)
+    } else if status.is_index_modified() || status.is_index_renamed() {
+        Some(FileChangeKind::Modified)
+    } else {
+        None
+    }
+}
+
+fn get_unstaged_change(status: git2::Status) -> Option<FileChangeKind> {
+    if status.is_wt_new() {
+        Some(FileChangeKind::Added)
+    } else if status.is_wt_deleted() {
+        Some(FileChangeKind::Deleted)
+    } else if status.is_wt_typechange() {
+        Some(FileChangeKind::TypeChanged)
+    } else if status.is_wt_modified() || status.is_wt_renamed() {
+        Some(FileChangeKind::Modified)
+    } else {
+        None
+    }
+}
+
+fn path_from_bytes(path: &[u8]) -> eyre::Result<PathBuf> {
+    let path = OsStr::from_raw_bytes(path).wrap_err_with(|| "Decoding status path")?;
+    Ok(PathBuf::from(path.into_owned()))
+}
+
+/// Convert the `libgit2` statuses into a list of entries, sorted by path.
+pub(super) fn make_status_entries(statuses: &git2::Statuses) -> eyre::Result<Vec<StatusEntry>> {
+    let mut result = Vec::new();
+    for status_entry in statuses.iter() {
+        let status = status_entry.status();
+        if status.is_ignored() {
+            continue;
+        }
+        let entry = StatusEntry {
+            path: path_from_bytes(status_entry.path_bytes())?,
+            staged: get_staged_change(status),
+            unstaged: get_unstaged_change(status),
+            is_conflicted: status.is_conflicted(),
+        };
+        if entry.staged.is_none() && entry.unstaged.is_none() && !entry.is_conflicted {
+            continue;
+        }
+        result.push(entry);
+    }
+    result.sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+    Ok(result)
+}
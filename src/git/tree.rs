@@ -283,6 +283,19 @@ fn remove_entry_if_exists(builder: &mut git2::TreeBuilder, name: &Path) -> eyre:
     Ok(())
 }
 
+/// Get the OID and file mode of the entry at the given path in the tree, or
+/// `None` if there's no such entry. The path can contain slashes.
+pub fn get_tree_entry(tree: &Tree, path: &Path) -> eyre::Result<Option<(NonZeroOid, i32)>> {
+    match tree.inner.get_path(path) {
+        Ok(tree_entry) => Ok(Some((
+            make_non_zero_oid(tree_entry.id()),
+            tree_entry.filemode(),
+        ))),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
 /// Filter the entries in the provided tree by only keeping the provided paths.
 ///
 /// If a provided path does not appear in the tree at all, then it's ignored.
@@ -291,15 +304,7 @@ pub fn dehydrate_tree(repo: &Repo, tree: &Tree, paths: &[&Path]) -> eyre::Result
     let entries: HashMap<PathBuf, Option<(NonZeroOid, i32)>> = paths
         .iter()
         .map(|path| -> eyre::Result<(PathBuf, _)> {
-            let key = path.to_path_buf();
-            match tree.inner.get_path(path) {
-                Ok(tree_entry) => {
-                    let value = Some((make_non_zero_oid(tree_entry.id()), tree_entry.filemode()));
-                    Ok((key, value))
-                }
-                Err(err) if err.code() == git2::ErrorCode::NotFound => Ok((key, None)),
-                Err(err) => Err(err.into()),
-            }
+            Ok((path.to_path_buf(), get_tree_entry(tree, path)?))
         })
         .try_collect()?;
 
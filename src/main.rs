@@ -1,12 +1,18 @@
 use std::convert::TryInto;
 use std::ffi::OsString;
 use std::path::PathBuf;
+use std::time::Instant;
 
 use branchless::commands::wrap;
-use branchless::core::formatting::Glyphs;
-use branchless::git::{GitRunInfo, NonZeroOid};
-use branchless::tui::Effects;
+use branchless::core::config::{
+    get_color_setting, get_notify_enabled, get_notify_min_duration, get_palette,
+};
+use branchless::core::formatting::{ColorSetting, Glyphs, Palette};
+use branchless::git::{GitRunInfo, NonZeroOid, Repo};
+use branchless::tui::{Effects, Verbosity};
+use eyre::Context;
 use structopt::StructOpt;
+use tracing::warn;
 
 #[derive(StructOpt)]
 enum WrappedCommand {
@@ -14,21 +20,125 @@ enum WrappedCommand {
     WrappedCommand(Vec<String>),
 }
 
-/// Branchless workflow for Git.
-///
-/// See the documentation at https://github.com/arxanas/git-branchless/wiki.
 #[derive(StructOpt)]
-#[structopt(version = env!("CARGO_PKG_VERSION"), author = "Waleed Khan <me@waleedkhan.name>")]
-enum Opts {
+enum SyncEventsDirection {
+    /// Push the events recorded locally since the last push to the remote,
+    /// after merging in the events from the remote.
+    Push {
+        /// The remote to push to.
+        #[structopt(default_value = "origin")]
+        remote: String,
+    },
+
+    /// Fetch the events from the remote and merge them into the local event
+    /// log.
+    Pull {
+        /// The remote to pull from.
+        #[structopt(default_value = "origin")]
+        remote: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum PluginApiQuery {
+    /// Print the commit graph.
+    Graph,
+
+    /// Print the events in the event log.
+    Events,
+}
+
+#[derive(StructOpt)]
+enum Command {
     /// Initialize the branchless workflow for this repository.
     Init {
         /// Uninstall the branchless workflow instead of initializing it.
         #[structopt(long = "--uninstall")]
         uninstall: bool,
+
+        /// Initialize the branchless workflow for every repository created by
+        /// `git init` or `git clone` from now on, rather than for this
+        /// repository, by installing the hooks into the template directory
+        /// set by `init.templateDir` and the aliases into the global config.
+        #[structopt(long = "--global")]
+        global: bool,
     },
 
     /// Display a nice graph of the commits you've recently worked on.
-    Smartlog,
+    Smartlog {
+        /// Also display the change ID and version of each draft commit, and
+        /// the transaction which created or last affected it.
+        #[structopt(short = "-v", long = "--verbose")]
+        verbose: bool,
+
+        /// The template to render each commit with, such as `%h %d %s`.
+        /// Supports `%h` (the abbreviated commit hash), `%d` (its branches),
+        /// `%s` (its summary), `%ar` (its relative time), `%%` (a literal
+        /// `%`), and `%(provider:<name>)` for the metadata providers `oid`,
+        /// `relative-time`, `hidden-explanation`, `branches`, `ci-status`,
+        /// `phabricator`, `change-id`, `transaction` and `message`.
+        #[structopt(long = "--format")]
+        format: Option<String>,
+
+        /// Also consider the events of other users of the repository, rather
+        /// than only those of the current user.
+        #[structopt(long = "--all-users")]
+        all_users: bool,
+
+        /// Render the smartlog as a standalone HTML document, keeping its
+        /// colors and styling, such as for pasting into a wiki page.
+        #[structopt(long = "--html")]
+        html: bool,
+
+        /// Show all commits, rather than only those selected by
+        /// `branchless.smartlog.defaultRevset`.
+        #[structopt(long = "--all")]
+        show_all: bool,
+
+        /// Replace commit hashes, commit messages and branch names with
+        /// placeholders, so that the smartlog can be shared without revealing
+        /// the contents of the repository.
+        #[structopt(long = "--anonymize")]
+        anonymize: bool,
+
+        /// Keep running, and display the smartlog again whenever the event
+        /// log, `HEAD` or the branches change.
+        #[structopt(long = "--watch", conflicts_with = "html")]
+        watch: bool,
+    },
+
+    /// Fold a commit into its parent, combining their changes and commit
+    /// messages into a single commit. Descendants of both commits are moved
+    /// onto the folded commit.
+    Fold {
+        /// The commit to fold into its parent. Defaults to `HEAD`.
+        commit: Option<String>,
+
+        /// The message to use for the folded commit, instead of combining the
+        /// messages of the two commits.
+        #[structopt(short = "-m", long = "--message")]
+        message: Option<String>,
+    },
+
+    /// Swap a commit with its parent, so that the commit is applied first.
+    /// Descendants of both commits are moved onto the rewritten parent.
+    Swap {
+        /// The commit to swap with its parent. Defaults to `HEAD`.
+        commit: Option<String>,
+    },
+
+    /// Check out a commit so that it can be amended. Afterwards, run with
+    /// `--continue` to restack its descendants onto the amended commit and
+    /// return to the commit or branch which was checked out before.
+    Edit {
+        /// The commit to edit.
+        #[structopt(required_unless = "resume")]
+        commit: Option<String>,
+
+        /// Finish the edit in progress.
+        #[structopt(long = "--continue", conflicts_with = "commit")]
+        resume: bool,
+    },
 
     /// Hide the provided commits from the smartlog.
     Hide {
@@ -40,6 +150,16 @@ enum Opts {
         /// Also recursively hide all children commits of the provided commits.
         #[structopt(short = "-r", long = "--recursive")]
         recursive: bool,
+
+        /// The reason for hiding the commits, such as "superseded by D1234".
+        /// It's displayed alongside the hidden commits later.
+        #[structopt(short = "-m", long = "--message")]
+        message: Option<String>,
+
+        /// Don't ask for confirmation when `--recursive` would hide more
+        /// commits than `branchless.hide.confirmThreshold` (10 by default).
+        #[structopt(long = "--no-confirm")]
+        no_confirm: bool,
     },
 
     /// Unhide previously-hidden commits from the smartlog.
@@ -58,6 +178,16 @@ enum Opts {
     Prev {
         /// The number of commits backward to go.
         num_commits: Option<isize>,
+
+        /// Only print the branch or commit which would be checked out, for
+        /// use in scripts.
+        #[structopt(long = "--print-only", alias = "quiet")]
+        print_only: bool,
+
+        /// Also go to hidden commits, rather than skipping them or going to
+        /// the commits they were rewritten into.
+        #[structopt(long = "--include-hidden")]
+        include_hidden: bool,
     },
 
     /// Move to a later commit in the current stack.
@@ -67,13 +197,68 @@ enum Opts {
         /// If not provided, defaults to 1.
         num_commits: Option<isize>,
 
-        /// When encountering multiple next commits, choose the oldest.
+        /// When encountering multiple next commits, choose the oldest. To
+        /// make this the default, set `branchless.next.towards` to `oldest`.
         #[structopt(short = "-o", long = "--oldest")]
         oldest: bool,
 
-        /// When encountering multiple next commits, choose the newest.
+        /// When encountering multiple next commits, choose the newest. To
+        /// make this the default, set `branchless.next.towards` to `newest`.
         #[structopt(short = "-n", long = "--newest", conflicts_with("oldest"))]
         newest: bool,
+
+        /// Only print the branch or commit which would be checked out, for
+        /// use in scripts.
+        #[structopt(long = "--print-only", alias = "quiet")]
+        print_only: bool,
+
+        /// Also go to hidden commits, rather than skipping them or going to
+        /// the commits they were rewritten into.
+        #[structopt(long = "--include-hidden")]
+        include_hidden: bool,
+    },
+
+    /// Go back to the previously checked-out commit or branch.
+    ///
+    /// Like the back button in a browser, this moves through the history of
+    /// checkouts without adding to it.
+    Back {
+        /// The number of checkouts backward to go.
+        ///
+        /// If not provided, defaults to 1.
+        num_checkouts: Option<isize>,
+    },
+
+    /// Go forward again after `back`.
+    Forward {
+        /// The number of checkouts forward to go.
+        ///
+        /// If not provided, defaults to 1.
+        num_checkouts: Option<isize>,
+    },
+
+    /// Check out the given commit or branch, and record it in the event log.
+    ///
+    /// If the commit has exactly one local branch pointing to it, then that
+    /// branch is checked out.
+    Goto {
+        /// The commit or branch to check out.
+        target: String,
+    },
+
+    /// Switch to the given commit or branch, as with `goto`.
+    ///
+    /// With `--interactive`, select the commit or branch to switch to from a
+    /// list of the visible commits and branches instead, filtered by
+    /// fuzzy-matching their commit hashes, branch names and messages.
+    Switch {
+        /// Select the commit or branch to switch to interactively.
+        #[structopt(short = "-i", long = "--interactive")]
+        interactive: bool,
+
+        /// The commit or branch to switch to. With `--interactive`, this is
+        /// used as the initial filter.
+        target: Option<String>,
     },
 
     /// Move a subtree of commits from one location to another.
@@ -103,15 +288,49 @@ enum Opts {
         dest: Option<String>,
 
         /// Only attempt to perform an in-memory rebase. If it fails, do not
-        /// attempt an on-disk rebase.
+        /// attempt an on-disk rebase. To make this the default, set
+        /// `branchless.move.forceInMemory`.
         #[structopt(long = "--in-memory", conflicts_with = "force_on_disk")]
         force_in_memory: bool,
 
         /// Skip attempting to use an in-memory rebase, and try an
-        /// on-disk rebase directly.
+        /// on-disk rebase directly. To make this the default, set
+        /// `branchless.move.forceOnDisk`.
         #[structopt(long = "--on-disk")]
         force_on_disk: bool,
 
+        /// Pass the given option to the merge strategy for on-disk rebases, as
+        /// with `git rebase --strategy-option`. Can be given multiple times.
+        #[structopt(short = "-X", long = "--strategy-option", number_of_values = 1)]
+        strategy_options: Vec<String>,
+
+        /// Copy the source commits onto the destination instead of moving
+        /// them, leaving the original commits and their branches in place.
+        /// Only supported for in-memory rebases.
+        #[structopt(long = "--keep", conflicts_with = "force-on-disk")]
+        keep: bool,
+
+        /// Skip the commits whose changes have already been applied to the
+        /// destination, as determined by their patch IDs. This is the default
+        /// unless `branchless.restack.detectDuplicateCommits` is `false`.
+        #[structopt(long = "--dedup", conflicts_with = "no-dedup")]
+        dedup: bool,
+
+        /// Replay every commit, even if its changes have already been applied
+        /// to the destination. This is faster for large stacks.
+        #[structopt(long = "--no-dedup")]
+        no_dedup: bool,
+
+        /// Stash any uncommitted changes beforehand and restore them
+        /// afterwards. This is the default if `branchless.autoStash` is set.
+        #[structopt(long = "--autostash", conflicts_with = "no-autostash")]
+        autostash: bool,
+
+        /// Don't stash uncommitted changes, even if `branchless.autoStash` is
+        /// set.
+        #[structopt(long = "--no-autostash")]
+        no_autostash: bool,
+
         /// Debugging option. Print the constraints used to create the rebase
         /// plan before executing it.
         #[structopt(long = "--debug-dump-rebase-constraints")]
@@ -129,6 +348,32 @@ enum Opts {
         /// restacked. If not provided, all abandoned commits are restacked.
         commits: Vec<String>,
 
+        /// Pass the given option to the merge strategy for on-disk rebases, as
+        /// with `git rebase --strategy-option`. Can be given multiple times.
+        #[structopt(short = "-X", long = "--strategy-option", number_of_values = 1)]
+        strategy_options: Vec<String>,
+
+        /// Skip the commits whose changes have already been applied to the
+        /// destination, as determined by their patch IDs. This is the default
+        /// unless `branchless.restack.detectDuplicateCommits` is `false`.
+        #[structopt(long = "--dedup", conflicts_with = "no-dedup")]
+        dedup: bool,
+
+        /// Replay every commit, even if its changes have already been applied
+        /// to the destination. This is faster for large stacks.
+        #[structopt(long = "--no-dedup")]
+        no_dedup: bool,
+
+        /// Stash any uncommitted changes beforehand and restore them
+        /// afterwards. This is the default if `branchless.autoStash` is set.
+        #[structopt(long = "--autostash", conflicts_with = "no-autostash")]
+        autostash: bool,
+
+        /// Don't stash uncommitted changes, even if `branchless.autoStash` is
+        /// set.
+        #[structopt(long = "--no-autostash")]
+        no_autostash: bool,
+
         /// Debugging option. Print the constraints used to create the rebase
         /// plan before executing it.
         #[structopt(long = "--debug-dump-rebase-constraints")]
@@ -140,12 +385,231 @@ enum Opts {
         dump_rebase_plan: bool,
     },
 
+    /// Move the stacks of draft commits onto the latest commit of the main
+    /// branch.
+    Sync {
+        /// Only move the stacks which contain a commit matched by one of these
+        /// revsets, such as `my-branch` or `mine() & draft()`. If not
+        /// provided, all stacks are moved.
+        revsets: Vec<String>,
+
+        /// Pass the given option to the merge strategy for on-disk rebases, as
+        /// with `git rebase --strategy-option`. Can be given multiple times.
+        #[structopt(short = "-X", long = "--strategy-option", number_of_values = 1)]
+        strategy_options: Vec<String>,
+
+        /// Skip the commits whose changes have already been applied to the
+        /// main branch, as determined by their patch IDs. This is the default
+        /// unless `branchless.restack.detectDuplicateCommits` is `false`.
+        #[structopt(long = "--dedup", conflicts_with = "no-dedup")]
+        dedup: bool,
+
+        /// Replay every commit, even if its changes have already been applied
+        /// to the main branch. This is faster for large stacks.
+        #[structopt(long = "--no-dedup")]
+        no_dedup: bool,
+
+        /// Stash any uncommitted changes beforehand and restore them
+        /// afterwards. This is the default if `branchless.autoStash` is set.
+        #[structopt(long = "--autostash", conflicts_with = "no-autostash")]
+        autostash: bool,
+
+        /// Don't stash uncommitted changes, even if `branchless.autoStash` is
+        /// set.
+        #[structopt(long = "--no-autostash")]
+        no_autostash: bool,
+
+        /// Debugging option. Print the constraints used to create the rebase
+        /// plan before executing it.
+        #[structopt(long = "--debug-dump-rebase-constraints")]
+        dump_rebase_constraints: bool,
+
+        /// Debugging option. Print the rebase plan that will be executed before
+        /// executing it.
+        #[structopt(long = "--debug-dump-rebase-plan")]
+        dump_rebase_plan: bool,
+    },
+
+    /// List the commits and branches which have been abandoned by rewriting
+    /// one of their ancestors, and how to restack them.
+    Status,
+
     /// Browse or return to a previous state of the repository.
-    Undo,
+    Undo {
+        /// Also browse and undo the events of other users of the repository,
+        /// rather than only those of the current user.
+        #[structopt(long = "--all-users")]
+        all_users: bool,
+
+        /// Stash any uncommitted changes beforehand and restore them
+        /// afterwards. This is the default if `branchless.autoStash` is set.
+        #[structopt(long = "--autostash", conflicts_with = "no-autostash")]
+        autostash: bool,
+
+        /// Don't stash uncommitted changes, even if `branchless.autoStash` is
+        /// set.
+        #[structopt(long = "--no-autostash")]
+        no_autostash: bool,
+    },
+
+    /// Attach a note to a past event, to be displayed in `git undo`.
+    ///
+    /// The note is attached to the transaction containing the given event.
+    Annotate {
+        /// The ID of the event to annotate, as displayed in `git undo`.
+        event_id: isize,
+
+        /// The note to attach.
+        #[structopt(short = "-m", long = "--message")]
+        message: String,
+    },
+
+    /// Time core operations against this repository and print a report.
+    Benchmark {
+        /// The number of times to run each operation.
+        #[structopt(short = "-n", long = "--iterations", default_value = "5")]
+        iterations: usize,
+    },
+
+    /// Print a report about this repository and the git-branchless setup to
+    /// include when filing a bug.
+    BugReport {
+        /// Leave out branch names, reference names and commit messages.
+        #[structopt(long = "--anonymize")]
+        anonymize: bool,
+    },
+
+    /// Print the documentation for git-branchless or one of its subcommands,
+    /// as a man page or as long-form help text.
+    Man {
+        /// The subcommand to document, such as `smartlog`.
+        command: Option<String>,
+
+        /// Print the long-form `--help` text instead of a man page.
+        #[structopt(long = "--text")]
+        text: bool,
+
+        /// Write the documentation for every subcommand into the given
+        /// directory, one file per subcommand.
+        #[structopt(long = "--output-dir", parse(from_os_str))]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Check for common problems with the git-branchless setup of this
+    /// repository.
+    Doctor {
+        /// Automatically fix the problems which can be fixed.
+        #[structopt(long = "--fix")]
+        fix: bool,
+    },
+
+    /// Rebuild the caches used by git-branchless from the repository and the
+    /// event log. The event log itself is preserved.
+    Repair,
+
+    /// Push the stack of commits ending at `HEAD` and submit it for code
+    /// review.
+    Submit {
+        /// The remote to push the branches to.
+        #[structopt(long = "--remote", default_value = "origin")]
+        remote: String,
+
+        /// The code review system to submit to: `gerrit`, `github` or
+        /// `phabricator`. Defaults to the value of `branchless.submit.backend`,
+        /// or `github` if not set.
+        #[structopt(long = "--backend")]
+        backend: Option<String>,
+
+        /// Only print what would be submitted.
+        #[structopt(long = "--dry-run")]
+        dry_run: bool,
+
+        /// Only push the branches whose commits changed since they were last
+        /// submitted, rather than force-pushing all of them.
+        #[structopt(long = "--update")]
+        update: bool,
+
+        /// Before submitting, create a branch for the commits at the top of
+        /// the stack which don't have one, named according to
+        /// `branchless.branch.nameTemplate`.
+        #[structopt(long = "--create-branches")]
+        create_branches: bool,
+
+        /// Create new pull requests as drafts. To make this the default, set
+        /// `branchless.submit.draft`. A branch which was marked as ready for
+        /// review stays ready when it's submitted again.
+        #[structopt(long = "--draft", conflicts_with = "ready")]
+        draft: bool,
+
+        /// Create new pull requests as ready for review, and mark existing
+        /// draft pull requests as ready for review.
+        #[structopt(long = "--ready")]
+        ready: bool,
+    },
+
+    /// Create a branch for the commits at the top of the current stack which
+    /// don't have one, named according to `branchless.branch.nameTemplate`.
+    Branch,
+
+    /// Recreate a deleted branch at the commit it last pointed to, unhiding
+    /// the commit if necessary. Without a branch name, list the deleted
+    /// branches which can be restored.
+    RestoreBranch {
+        /// The name of the branch to restore.
+        name: Option<String>,
+    },
+
+    /// Compare a commit against the version of it before it was last amended
+    /// or rebased.
+    Diff {
+        /// The commit to compare against its previous version.
+        #[structopt(long = "--against-previous", value_name = "commit")]
+        against_previous: String,
+    },
+
+    /// Print a Markdown summary of the stack of commits ending at `HEAD`, to
+    /// paste into the description of a pull request.
+    ExportStack {
+        /// The remote whose GitHub repository to look up pull requests in.
+        #[structopt(long = "--remote", default_value = "origin")]
+        remote: String,
+    },
+
+    /// Share the event log with other machines via a remote.
+    SyncEvents {
+        #[structopt(subcommand)]
+        direction: SyncEventsDirection,
+    },
 
     /// Run internal garbage collection.
     Gc,
 
+    /// Permanently delete commits which have been hidden for a long time and
+    /// aren't reachable from any other reference.
+    Prune {
+        /// How long a commit must have been hidden for to be deleted, such as
+        /// `90d`. Defaults to `branchless.prune.olderThan`.
+        #[structopt(long = "--older-than", value_name = "duration")]
+        older_than: Option<String>,
+
+        /// Only list the commits which would be deleted.
+        #[structopt(long = "--dry-run")]
+        dry_run: bool,
+    },
+
+    /// Run `git rebase` with the given arguments, and record any reference
+    /// updates and rewritten commits which the hooks missed, so that `git undo`
+    /// can always revert the rebase.
+    #[structopt(
+        setting = structopt::clap::AppSettings::TrailingVarArg,
+        setting = structopt::clap::AppSettings::AllowLeadingHyphen
+    )]
+    Rebase {
+        /// The arguments to pass to `git rebase`.
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
     /// Wrap a Git command inside a branchless transaction.
     Wrap {
         #[structopt(long = "--git-executable")]
@@ -155,6 +619,12 @@ enum Opts {
         command: WrappedCommand,
     },
 
+    /// Print information about the repository as JSON, for use by plugins.
+    PluginApi {
+        #[structopt(subcommand)]
+        query: PluginApiQuery,
+    },
+
     /// Internal use.
     HookPreAutoGc,
 
@@ -168,7 +638,13 @@ enum Opts {
     HookDetectEmptyCommit { old_commit_oid: NonZeroOid },
 
     /// Internal use.
-    HookSkipUpstreamAppliedCommit { commit_oid: NonZeroOid },
+    HookSkipUpstreamAppliedCommit {
+        commit_oid: NonZeroOid,
+        upstream_commit_oid: Option<NonZeroOid>,
+    },
+
+    /// Internal use.
+    HookRecordReplacedCommit { commit_oid: NonZeroOid },
 
     /// Internal use.
     HookPostCheckout {
@@ -185,13 +661,92 @@ enum Opts {
 
     /// Internal use.
     HookReferenceTransaction { transaction_state: String },
+
+    /// Run the `git-branchless-<name>` executable on the `PATH` for any other
+    /// subcommand `<name>`.
+    #[structopt(external_subcommand)]
+    Plugin(Vec<String>),
+}
+
+/// Branchless workflow for Git.
+///
+/// See the documentation at https://github.com/arxanas/git-branchless/wiki.
+#[derive(StructOpt)]
+#[structopt(version = env!("CARGO_PKG_VERSION"), author = "Waleed Khan <me@waleedkhan.name>")]
+struct Opts {
+    /// Change to the given directory before doing anything else, as with `git
+    /// -C`.
+    #[structopt(short = "-C", parse(from_os_str))]
+    working_directory: Option<PathBuf>,
+
+    /// When to style the output with colors: `auto`, `always` or `never`.
+    /// Defaults to the `branchless.color` config, or `auto` if not set. With
+    /// `auto`, styling is disabled if `NO_COLOR` is set and forced if
+    /// `CLICOLOR_FORCE` is set.
+    #[structopt(long = "--color", possible_values = &["auto", "always", "never"])]
+    color: Option<ColorSetting>,
+
+    /// Only print errors and the final results of operations, such as for
+    /// scripts.
+    #[structopt(short = "-q", long = "--quiet", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print the sub-operations carried out by each command, such as
+    /// updating the commit graph cache.
+    #[structopt(short = "-v", long = "--verbose")]
+    verbose: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+impl Command {
+    /// The name of the command to mention in the desktop notification shown
+    /// when it finishes, or `None` if no notification should be shown, such
+    /// as for hooks and for commands which are quick or interactive.
+    fn get_notification_name(&self) -> Option<&'static str> {
+        match self {
+            Command::Move { .. } => Some("move"),
+            Command::Restack { .. } => Some("restack"),
+            Command::Sync { .. } => Some("sync"),
+            Command::Fold { .. } => Some("branchless fold"),
+            Command::Swap { .. } => Some("branchless swap"),
+            Command::Submit { .. } => Some("branchless submit"),
+            Command::SyncEvents { .. } => Some("branchless sync-events"),
+            Command::Gc => Some("branchless gc"),
+            Command::Prune { .. } => Some("branchless prune"),
+            Command::Repair => Some("branchless repair"),
+            Command::Benchmark { .. } => Some("branchless benchmark"),
+            _ => None,
+        }
+    }
+}
+
+/// Combine a pair of `--foo` and `--no-foo` flags into an override of the
+/// corresponding config value, which is `None` if neither flag was passed.
+fn get_flag_override(enabled: bool, disabled: bool) -> Option<bool> {
+    match (enabled, disabled) {
+        (true, _) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    }
 }
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
     install_tracing();
 
-    let opts = Opts::from_args();
+    let Opts {
+        working_directory,
+        color,
+        quiet,
+        verbose,
+        command,
+    } = Opts::from_args();
+    if let Some(working_directory) = working_directory {
+        std::env::set_current_dir(&working_directory)
+            .wrap_err_with(|| format!("Changing to working directory: {:?}", &working_directory))?;
+    }
     let path_to_git = std::env::var_os("PATH_TO_GIT").unwrap_or_else(|| OsString::from("git"));
     let path_to_git = PathBuf::from(&path_to_git);
     let git_run_info = GitRunInfo {
@@ -199,40 +754,180 @@ fn main() -> eyre::Result<()> {
         working_directory: std::env::current_dir()?,
         env: std::env::vars_os().collect(),
     };
-    let effects = Effects::new(Glyphs::detect());
+    let repo = Repo::from_current_dir().ok();
+    let color = match color {
+        Some(color) => color,
+        None => match &repo {
+            Some(repo) => get_color_setting(repo).unwrap_or_else(|err| {
+                warn!(?err, "Could not read color setting");
+                ColorSetting::Auto
+            }),
+            None => ColorSetting::Auto,
+        },
+    };
+    color.apply_to_console();
+    if let Some(repo) = &repo {
+        let palette = get_palette(repo).unwrap_or_else(|err| {
+            warn!(?err, "Could not read color palette");
+            Palette::default()
+        });
+        palette.install();
+    }
+    drop(repo);
+    let verbosity = match (quiet, verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, true) => Verbosity::Verbose,
+        (false, false) => Verbosity::Normal,
+    };
+    let effects = Effects::new(Glyphs::detect_with_color(color)).with_verbosity(verbosity);
+
+    let notification_name = command.get_notification_name();
+    let start_time = Instant::now();
+    let result = command_main(effects.clone(), git_run_info, command);
+    if let Some(name) = notification_name {
+        notify_on_completion(&effects, name, start_time, &result);
+    }
+    let exit_code: i32 = result?.try_into()?;
+    std::process::exit(exit_code)
+}
 
-    let exit_code = match opts {
-        Opts::Init { uninstall: false } => {
+/// Show a desktop notification that the given command has finished, if
+/// notifications are enabled and the command ran for long enough, since the
+/// user may have switched to another window in the meantime. This is
+/// best-effort, so invalid settings are only logged, and never change the
+/// exit code of the command.
+fn notify_on_completion(
+    effects: &Effects,
+    name: &str,
+    start_time: Instant,
+    result: &eyre::Result<isize>,
+) {
+    let repo = match Repo::from_current_dir() {
+        Ok(repo) => repo,
+        Err(_) => return,
+    };
+    let min_duration = match (get_notify_enabled(&repo), get_notify_min_duration(&repo)) {
+        (Ok(false), _) => return,
+        (Ok(true), Ok(min_duration)) => min_duration,
+        (Err(err), _) | (Ok(true), Err(err)) => {
+            warn!(?err, "Could not read notification settings");
+            return;
+        }
+    };
+    let exit_code = match result {
+        Ok(exit_code) => Some(*exit_code),
+        Err(_) => None,
+    };
+    effects.notify_on_completion(name, start_time.elapsed(), min_duration, exit_code);
+}
+
+fn command_main(
+    effects: Effects,
+    git_run_info: GitRunInfo,
+    command: Command,
+) -> eyre::Result<isize> {
+    let exit_code = match command {
+        Command::Init {
+            uninstall: false,
+            global: false,
+        } => {
             branchless::commands::init::init(&effects, &git_run_info)?;
             0
         }
 
-        Opts::Init { uninstall: true } => {
+        Command::Init {
+            uninstall: true,
+            global: false,
+        } => {
             branchless::commands::init::uninstall(&effects)?;
             0
         }
 
-        Opts::Smartlog => {
-            branchless::commands::smartlog::smartlog(&effects)?;
+        Command::Init {
+            uninstall: false,
+            global: true,
+        } => {
+            branchless::commands::init::init_global(&effects)?;
+            0
+        }
+
+        Command::Init {
+            uninstall: true,
+            global: true,
+        } => {
+            branchless::commands::init::uninstall_global(&effects)?;
             0
         }
 
-        Opts::Hide { commits, recursive } => {
-            branchless::commands::hide::hide(&effects, commits, recursive)?
+        Command::Smartlog {
+            verbose,
+            format,
+            all_users,
+            html,
+            show_all,
+            anonymize,
+            watch,
+        } => {
+            let options = branchless::commands::smartlog::SmartlogOptions {
+                verbose,
+                format,
+                all_users,
+                html,
+                show_all,
+                anonymize,
+            };
+            if watch {
+                branchless::commands::smartlog::watch_smartlog(&effects, &options)?;
+            } else {
+                branchless::commands::smartlog::smartlog(&effects, &options)?;
+            }
+            0
         }
 
-        Opts::Unhide { commits, recursive } => {
-            branchless::commands::hide::unhide(&effects, commits, recursive)?
+        Command::Fold { commit, message } => {
+            branchless::commands::fold::fold(&effects, &git_run_info, commit, message)?
         }
 
-        Opts::Prev { num_commits } => {
-            branchless::commands::navigation::prev(&effects, &git_run_info, num_commits)?
+        Command::Swap { commit } => {
+            branchless::commands::swap::swap(&effects, &git_run_info, commit)?
         }
 
-        Opts::Next {
+        Command::Edit { commit, resume } => match commit {
+            Some(commit) if !resume => {
+                branchless::commands::edit::edit(&effects, &git_run_info, commit)?
+            }
+            _ => branchless::commands::edit::edit_continue(&effects, &git_run_info)?,
+        },
+
+        Command::Hide {
+            commits,
+            recursive,
+            message,
+            no_confirm,
+        } => branchless::commands::hide::hide(&effects, commits, recursive, message, no_confirm)?,
+
+        Command::Unhide { commits, recursive } => {
+            branchless::commands::hide::unhide(&effects, commits, recursive)?
+        }
+
+        Command::Prev {
+            num_commits,
+            print_only,
+            include_hidden,
+        } => branchless::commands::navigation::prev(
+            &effects,
+            &git_run_info,
+            num_commits,
+            print_only,
+            include_hidden,
+        )?,
+
+        Command::Next {
             num_commits,
             oldest,
             newest,
+            print_only,
+            include_hidden,
         } => {
             let towards = match (oldest, newest) {
                 (false, false) => None,
@@ -240,15 +935,45 @@ fn main() -> eyre::Result<()> {
                 (false, true) => Some(branchless::commands::navigation::Towards::Newest),
                 (true, true) => eyre::bail!("Both --oldest and --newest were set"),
             };
-            branchless::commands::navigation::next(&effects, &git_run_info, num_commits, towards)?
+            branchless::commands::navigation::next(
+                &effects,
+                &git_run_info,
+                num_commits,
+                towards,
+                print_only,
+                include_hidden,
+            )?
+        }
+
+        Command::Back { num_checkouts } => {
+            branchless::commands::navigation::back(&effects, &git_run_info, num_checkouts)?
+        }
+
+        Command::Forward { num_checkouts } => {
+            branchless::commands::navigation::forward(&effects, &git_run_info, num_checkouts)?
+        }
+
+        Command::Goto { target } => {
+            branchless::commands::navigation::goto(&effects, &git_run_info, target)?
         }
 
-        Opts::Move {
+        Command::Switch {
+            interactive,
+            target,
+        } => branchless::commands::switch::switch(&effects, &git_run_info, interactive, target)?,
+
+        Command::Move {
             source,
             dest,
             base,
             force_in_memory,
             force_on_disk,
+            strategy_options,
+            keep,
+            dedup,
+            no_dedup,
+            autostash,
+            no_autostash,
             dump_rebase_constraints,
             dump_rebase_plan,
         } => branchless::commands::r#move::r#move(
@@ -259,30 +984,159 @@ fn main() -> eyre::Result<()> {
             base,
             force_in_memory,
             force_on_disk,
+            strategy_options,
+            keep,
+            get_flag_override(dedup, no_dedup),
+            get_flag_override(autostash, no_autostash),
             dump_rebase_constraints,
             dump_rebase_plan,
         )?,
 
-        Opts::Restack {
+        Command::Restack {
             commits,
+            strategy_options,
+            dedup,
+            no_dedup,
+            autostash,
+            no_autostash,
             dump_rebase_constraints,
             dump_rebase_plan,
         } => branchless::commands::restack::restack(
             &effects,
             &git_run_info,
             commits,
+            strategy_options,
+            get_flag_override(dedup, no_dedup),
+            get_flag_override(autostash, no_autostash),
+            dump_rebase_constraints,
+            dump_rebase_plan,
+        )?,
+
+        Command::Sync {
+            revsets,
+            strategy_options,
+            dedup,
+            no_dedup,
+            autostash,
+            no_autostash,
             dump_rebase_constraints,
             dump_rebase_plan,
+        } => branchless::commands::sync::sync(
+            &effects,
+            &git_run_info,
+            revsets,
+            strategy_options,
+            get_flag_override(dedup, no_dedup),
+            get_flag_override(autostash, no_autostash),
+            dump_rebase_constraints,
+            dump_rebase_plan,
+        )?,
+
+        Command::Status => branchless::commands::status::status(&effects)?,
+
+        Command::Undo {
+            all_users,
+            autostash,
+            no_autostash,
+        } => branchless::commands::undo::undo(
+            &effects,
+            &git_run_info,
+            all_users,
+            get_flag_override(autostash, no_autostash),
+        )?,
+
+        Command::Annotate { event_id, message } => {
+            branchless::commands::annotate::annotate(&effects, event_id, &message)?
+        }
+
+        Command::Benchmark { iterations } => {
+            branchless::commands::benchmark::benchmark(&effects, iterations)?
+        }
+
+        Command::BugReport { anonymize } => {
+            branchless::commands::bug_report::bug_report(&effects, &git_run_info, anonymize)?
+        }
+
+        Command::Man {
+            command,
+            text,
+            output_dir,
+        } => branchless::commands::man::man(
+            &effects,
+            Opts::clap(),
+            command,
+            text,
+            output_dir.as_deref(),
+        )?,
+
+        Command::Doctor { fix } => branchless::commands::doctor::doctor(&effects, fix)?,
+
+        Command::Repair => branchless::commands::repair::repair(&effects)?,
+
+        Command::Submit {
+            remote,
+            backend,
+            dry_run,
+            update,
+            create_branches,
+            draft,
+            ready,
+        } => branchless::commands::submit::submit(
+            &effects,
+            &git_run_info,
+            &remote,
+            backend,
+            dry_run,
+            update,
+            create_branches,
+            draft,
+            ready,
         )?,
 
-        Opts::Undo => branchless::commands::undo::undo(&effects, &git_run_info)?,
+        Command::Branch => branchless::commands::branch::branch(&effects, &git_run_info)?,
 
-        Opts::Gc | Opts::HookPreAutoGc => {
-            branchless::commands::gc::gc(&effects)?;
+        Command::RestoreBranch { name } => {
+            branchless::commands::restore_branch::restore_branch(&effects, &git_run_info, name)?
+        }
+
+        Command::Diff { against_previous } => branchless::commands::diff::diff_against_previous(
+            &effects,
+            &git_run_info,
+            &against_previous,
+        )?,
+
+        Command::ExportStack { remote } => {
+            branchless::commands::export_stack::export_stack(&effects, &remote)?
+        }
+
+        Command::SyncEvents {
+            direction: SyncEventsDirection::Push { remote },
+        } => branchless::commands::sync_events::sync_events_push(&effects, &git_run_info, &remote)?,
+
+        Command::SyncEvents {
+            direction: SyncEventsDirection::Pull { remote },
+        } => branchless::commands::sync_events::sync_events_pull(&effects, &git_run_info, &remote)?,
+
+        Command::Gc => {
+            branchless::commands::gc::gc(&effects, &git_run_info)?;
+            0
+        }
+
+        Command::Prune {
+            older_than,
+            dry_run,
+        } => branchless::commands::prune::prune(&effects, &git_run_info, older_than, dry_run)?,
+
+        Command::HookPreAutoGc => {
+            branchless::commands::hooks::hook_pre_auto_gc(&effects, &git_run_info)?;
             0
         }
 
-        Opts::Wrap {
+        Command::Rebase { args } => {
+            branchless::commands::rebase::rebase(&effects, &git_run_info, args)?
+        }
+
+        Command::Wrap {
             git_executable: explicit_git_executable,
             command: WrappedCommand::WrappedCommand(args),
         } => {
@@ -297,27 +1151,47 @@ fn main() -> eyre::Result<()> {
             exit_code
         }
 
-        Opts::HookPostRewrite { rewrite_type } => {
+        Command::PluginApi {
+            query: PluginApiQuery::Graph,
+        } => branchless::commands::plugin::plugin_api_graph(&effects)?,
+
+        Command::PluginApi {
+            query: PluginApiQuery::Events,
+        } => branchless::commands::plugin::plugin_api_events(&effects)?,
+
+        Command::HookPostRewrite { rewrite_type } => {
             branchless::commands::hooks::hook_post_rewrite(&effects, &git_run_info, &rewrite_type)?;
             0
         }
 
-        Opts::HookRegisterExtraPostRewriteHook => {
+        Command::HookRegisterExtraPostRewriteHook => {
             branchless::commands::hooks::hook_register_extra_post_rewrite_hook()?;
             0
         }
 
-        Opts::HookDetectEmptyCommit { old_commit_oid } => {
+        Command::HookDetectEmptyCommit { old_commit_oid } => {
             branchless::commands::hooks::hook_drop_commit_if_empty(&effects, old_commit_oid)?;
             0
         }
 
-        Opts::HookSkipUpstreamAppliedCommit { commit_oid } => {
-            branchless::commands::hooks::hook_skip_upstream_applied_commit(&effects, commit_oid)?;
+        Command::HookSkipUpstreamAppliedCommit {
+            commit_oid,
+            upstream_commit_oid,
+        } => {
+            branchless::commands::hooks::hook_skip_upstream_applied_commit(
+                &effects,
+                commit_oid,
+                upstream_commit_oid,
+            )?;
+            0
+        }
+
+        Command::HookRecordReplacedCommit { commit_oid } => {
+            branchless::commands::hooks::hook_record_replaced_commit(commit_oid)?;
             0
         }
 
-        Opts::HookPostCheckout {
+        Command::HookPostCheckout {
             previous_commit,
             current_commit,
             is_branch_checkout,
@@ -331,24 +1205,27 @@ fn main() -> eyre::Result<()> {
             0
         }
 
-        Opts::HookPostCommit => {
+        Command::HookPostCommit => {
             branchless::commands::hooks::hook_post_commit(&effects)?;
             0
         }
 
-        Opts::HookPostMerge { is_squash_merge } => {
+        Command::HookPostMerge { is_squash_merge } => {
             branchless::commands::hooks::hook_post_merge(&effects, is_squash_merge)?;
             0
         }
 
-        Opts::HookReferenceTransaction { transaction_state } => {
+        Command::HookReferenceTransaction { transaction_state } => {
             branchless::commands::hooks::hook_reference_transaction(&effects, &transaction_state)?;
             0
         }
+
+        Command::Plugin(args) => {
+            branchless::commands::plugin::run_plugin(&effects, &git_run_info, &args)?
+        }
     };
 
-    let exit_code: i32 = exit_code.try_into()?;
-    std::process::exit(exit_code)
+    Ok(exit_code)
 }
 
 fn install_tracing() {
@@ -385,3 +1262,23 @@ fn install_tracing() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_notification_name() {
+        let get_notification_name = |args: &[&str]| {
+            let Opts { command, .. } =
+                Opts::from_iter(std::iter::once("git-branchless").chain(args.iter().copied()));
+            command.get_notification_name()
+        };
+        assert_eq!(get_notification_name(&["move"]), Some("move"));
+        assert_eq!(get_notification_name(&["restack"]), Some("restack"));
+        assert_eq!(get_notification_name(&["sync"]), Some("sync"));
+        assert_eq!(get_notification_name(&["gc"]), Some("branchless gc"));
+        assert_eq!(get_notification_name(&["smartlog"]), None);
+        assert_eq!(get_notification_name(&["undo"]), None);
+    }
+}
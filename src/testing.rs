@@ -3,11 +3,13 @@
 //! This is inside `src` rather than `tests` since we use this code in some unit
 //! tests.
 
-use std::ffi::{OsStr, OsString};
+use std::ffi::OsString;
 use std::io::Write;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::Arc;
 
 use crate::git::{GitRunInfo, GitVersion, NonZeroOid, Repo};
 use crate::util::get_sh;
@@ -34,6 +36,11 @@ pub struct Git {
     /// The path to the Git executable on disk. This is important since we test
     /// against multiple Git versions.
     pub path_to_git: PathBuf,
+
+    /// The current time of the fake clock, which is added to
+    /// `GitRunOptions::time` for each command. It's shared between clones of
+    /// this `Git`.
+    clock: Arc<AtomicIsize>,
 }
 
 /// Options for `Git::init_repo_with_options`.
@@ -59,7 +66,8 @@ impl Default for GitInitOptions {
 /// Options for `Git::run_with_options`.
 #[derive(Debug)]
 pub struct GitRunOptions {
-    /// The timestamp of the command. Mostly useful for `git commit`. This should
+    /// The timestamp of the command, relative to the current time of the
+    /// clock (see `Git::set_time`). Mostly useful for `git commit`. This should
     /// be a number like 0, 1, 2, 3...
     pub time: isize,
 
@@ -68,6 +76,9 @@ pub struct GitRunOptions {
 
     /// The input to write to the child process's stdin.
     pub input: Option<String>,
+
+    /// Additional environment variables to set for the child process.
+    pub env: Vec<(String, String)>,
 }
 
 impl Default for GitRunOptions {
@@ -76,6 +87,7 @@ impl Default for GitRunOptions {
             time: 0,
             expected_exit_code: 0,
             input: None,
+            env: Vec::new(),
         }
     }
 }
@@ -93,9 +105,27 @@ impl Git {
         Git {
             repo_path,
             path_to_git,
+            clock: Default::default(),
         }
     }
 
+    /// Get the current time of the clock used for commit timestamps. It starts
+    /// at 0 and only changes when `set_time` or `advance_time` is called, so
+    /// that commit hashes are deterministic.
+    pub fn get_time(&self) -> isize {
+        self.clock.load(Ordering::SeqCst)
+    }
+
+    /// Set the current time of the clock used for commit timestamps.
+    pub fn set_time(&self, time: isize) {
+        self.clock.store(time, Ordering::SeqCst);
+    }
+
+    /// Advance the clock used for commit timestamps by the given amount.
+    pub fn advance_time(&self, amount: isize) {
+        self.clock.fetch_add(amount, Ordering::SeqCst);
+    }
+
     /// Replace dynamic strings in the output, for testing purposes.
     pub fn preprocess_output(&self, stdout: String) -> eyre::Result<String> {
         let path_to_git = self
@@ -170,6 +200,47 @@ impl Git {
         .expect("joining paths")
     }
 
+    /// Get the environment variables to run Git with at the given timestamp.
+    fn get_base_env(&self, time: isize) -> Vec<(OsString, OsString)> {
+        // Required for determinism, as these values will be baked into the commit
+        // hash.
+        let date: OsString = format!("{date} -{time:0>2}", date = DUMMY_DATE, time = time).into();
+
+        // Fake "editor" which accepts the default contents of any commit
+        // messages. Usually, we can set this with `git commit -m`, but we have
+        // no such option for things such as `git rebase`, which may call `git
+        // commit` later as a part of their execution.
+        //
+        // ":" is understood by `git` to skip editing.
+        let git_editor = OsString::from(":");
+
+        vec![
+            (OsString::from("GIT_AUTHOR_DATE"), date.clone()),
+            (OsString::from("GIT_COMMITTER_DATE"), date),
+            (OsString::from("GIT_EDITOR"), git_editor),
+            (
+                OsString::from("GIT_EXEC_PATH"),
+                self.get_git_exec_path().into_os_string(),
+            ),
+            (
+                OsString::from("PATH_TO_GIT"),
+                self.path_to_git.clone().into_os_string(),
+            ),
+            (OsString::from("PATH"), self.get_path_for_env()),
+        ]
+    }
+
+    /// Get a `GitRunInfo` which runs Git commands in this repository in the
+    /// same environment as `run`, for tests which call into the library
+    /// directly.
+    pub fn get_git_run_info(&self) -> GitRunInfo {
+        GitRunInfo {
+            path_to_git: self.path_to_git.clone(),
+            working_directory: self.repo_path.clone(),
+            env: self.get_base_env(self.get_time()).into_iter().collect(),
+        }
+    }
+
     /// Run a Git command.
     #[instrument]
     pub fn run_with_options<S: AsRef<str> + std::fmt::Debug>(
@@ -181,12 +252,9 @@ impl Git {
             time,
             expected_exit_code,
             input,
+            env: extra_env,
         } = options;
 
-        // Required for determinism, as these values will be baked into the commit
-        // hash.
-        let date: OsString = format!("{date} -{time:0>2}", date = DUMMY_DATE, time = time).into();
-
         let args: Vec<&str> = {
             let repo_path = self.repo_path.to_str().expect("Could not decode repo path");
             let mut new_args: Vec<&str> = vec!["-C", repo_path];
@@ -194,27 +262,13 @@ impl Git {
             new_args
         };
 
-        // Fake "editor" which accepts the default contents of any commit
-        // messages. Usually, we can set this with `git commit -m`, but we have
-        // no such option for things such as `git rebase`, which may call `git
-        // commit` later as a part of their execution.
-        //
-        // ":" is understood by `git` to skip editing.
-        let git_editor = OsString::from(":");
-
-        let git_exec_path = self.get_git_exec_path();
-        let new_path = self.get_path_for_env();
-        let env: Vec<(&str, &OsStr)> = vec![
-            ("GIT_AUTHOR_DATE", &date),
-            ("GIT_COMMITTER_DATE", &date),
-            ("GIT_EDITOR", &git_editor),
-            ("GIT_EXEC_PATH", git_exec_path.as_os_str()),
-            ("PATH_TO_GIT", self.path_to_git.as_os_str()),
-            ("PATH", &new_path),
-        ];
-
+        let env = self.get_base_env(self.get_time() + *time);
         let mut command = Command::new(&self.path_to_git);
-        command.args(&args).env_clear().envs(env.iter().copied());
+        command
+            .args(&args)
+            .env_clear()
+            .envs(env.iter().map(|(k, v)| (k, v)))
+            .envs(extra_env.iter().map(|(k, v)| (k, v)));
 
         let result = if let Some(input) = input {
             let mut child = command
@@ -281,6 +335,28 @@ impl Git {
         self.run_with_options(args, &Default::default())
     }
 
+    /// Run a Git command, writing the given input to its stdin.
+    pub fn run_with_input<S: AsRef<str> + std::fmt::Debug>(
+        &self,
+        args: &[S],
+        input: &str,
+    ) -> eyre::Result<(String, String)> {
+        self.run_with_options(
+            args,
+            &GitRunOptions {
+                input: Some(input.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Set the given config key in the repository's local config.
+    #[instrument]
+    pub fn set_config(&self, key: &str, value: &str) -> eyre::Result<()> {
+        self.run(&["config", key, value])?;
+        Ok(())
+    }
+
     /// Set up a Git repo in the directory and initialize git-branchless to work
     /// with it.
     #[instrument]
@@ -452,8 +528,14 @@ impl Deref for GitWrapper {
 
 /// Create a temporary directory for testing and a `Git` instance to use with it.
 pub fn make_git() -> eyre::Result<GitWrapper> {
+    make_git_with_path_to_git(get_path_to_git()?)
+}
+
+/// Create a temporary directory for testing and a `Git` instance to use with
+/// it, which runs the given Git executable rather than the one in
+/// `PATH_TO_GIT`. This is useful for testing against a specific Git version.
+pub fn make_git_with_path_to_git(path_to_git: PathBuf) -> eyre::Result<GitWrapper> {
     let repo_dir = tempfile::tempdir()?;
-    let path_to_git = get_path_to_git()?;
     let path_to_git = GitRunInfo {
         path_to_git,
         working_directory: repo_dir.path().to_path_buf(),
@@ -5,4 +5,4 @@ mod effects;
 
 pub use self::cursive::testing;
 pub use self::cursive::{with_siv, SingletonView};
-pub use effects::{Effects, OperationType};
+pub use effects::{Effects, OperationType, Verbosity};
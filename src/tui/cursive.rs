@@ -94,11 +94,38 @@ pub mod testing {
     use std::rc::Rc;
 
     use cursive::backend::Backend;
-    use cursive::theme::Color;
+    use cursive::reexports::enumset::EnumSet;
+    use cursive::theme::{Color, ColorPair, Effect};
+    use cursive::Vec2;
+    use unicode_width::UnicodeWidthChar;
 
     /// Represents a "screenshot" of the terminal taken at a point in time.
     pub type Screen = Vec<Vec<char>>;
 
+    /// A single cell of the terminal, along with the style it was drawn with.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct StyledCell {
+        /// The character in the cell.
+        pub character: char,
+
+        /// The foreground and background colors of the cell.
+        pub colors: ColorPair,
+
+        /// The effects (such as bold or reverse video) applied to the cell.
+        pub effects: EnumSet<Effect>,
+    }
+
+    /// Represents a "screenshot" of the terminal taken at a point in time,
+    /// including the style of each cell.
+    pub type StyledScreen = Vec<Vec<StyledCell>>;
+
+    /// Placeholder for the cells which are covered by the preceding wide
+    /// character on the screen.
+    const WIDE_CHAR_CONTINUATION: char = '\0';
+
+    /// The size of the virtual terminal, unless otherwise specified.
+    const DEFAULT_SCREEN_SIZE: (usize, usize) = (120, 24);
+
     /// The kind of events that can be
     #[derive(Clone, Debug)]
     pub enum CursiveTestingEvent {
@@ -108,6 +135,15 @@ pub mod testing {
         /// Take a screenshot at the current point in time and store it in the
         /// provided screenshot cell.
         TakeScreenshot(Rc<RefCell<Screen>>),
+
+        /// Take a screenshot at the current point in time, including the
+        /// colors and effects of each cell, and store it in the provided
+        /// screenshot cell.
+        TakeStyledScreenshot(Rc<RefCell<StyledScreen>>),
+
+        /// Resize the virtual terminal to the given width and height, clearing
+        /// its contents, and notify Cursive that the window was resized.
+        Resize(Vec2),
     }
 
     /// The testing backend. It feeds a predetermined list of events to the
@@ -117,18 +153,41 @@ pub mod testing {
         events: Vec<CursiveTestingEvent>,
         event_index: usize,
         just_emitted_event: bool,
-        screen: RefCell<Screen>,
+        screen: RefCell<StyledScreen>,
+        colors: RefCell<ColorPair>,
+        effects: RefCell<EnumSet<Effect>>,
         screenshots: Vec<Screen>,
     }
 
+    fn make_blank_screen(size: Vec2, colors: ColorPair) -> StyledScreen {
+        let cell = StyledCell {
+            character: ' ',
+            colors,
+            effects: EnumSet::new(),
+        };
+        vec![vec![cell; size.x]; size.y]
+    }
+
     impl<'screenshot> CursiveTestingBackend {
         /// Construct the testing backend with the provided set of events.
         pub fn init(events: Vec<CursiveTestingEvent>) -> Box<dyn Backend> {
+            Self::init_with_size(events, DEFAULT_SCREEN_SIZE.into())
+        }
+
+        /// Construct the testing backend with the provided set of events and
+        /// the given initial size of the virtual terminal.
+        pub fn init_with_size(events: Vec<CursiveTestingEvent>, size: Vec2) -> Box<dyn Backend> {
+            let colors = ColorPair {
+                front: Color::TerminalDefault,
+                back: Color::TerminalDefault,
+            };
             Box::new(CursiveTestingBackend {
                 events,
                 event_index: 0,
                 just_emitted_event: false,
-                screen: RefCell::new(vec![vec![' '; 120]; 24]),
+                screen: RefCell::new(make_blank_screen(size, colors)),
+                colors: RefCell::new(colors),
+                effects: RefCell::new(EnumSet::new()),
                 screenshots: Vec::new(),
             })
         }
@@ -147,10 +206,25 @@ pub mod testing {
             self.event_index += 1;
             match self.events.get(event_index)?.to_owned() {
                 CursiveTestingEvent::TakeScreenshot(screen_target) => {
+                    let mut screen_target = (*screen_target).borrow_mut();
+                    *screen_target = self
+                        .screen
+                        .borrow()
+                        .iter()
+                        .map(|row| row.iter().map(|cell| cell.character).collect())
+                        .collect();
+                    self.poll_event()
+                }
+                CursiveTestingEvent::TakeStyledScreenshot(screen_target) => {
                     let mut screen_target = (*screen_target).borrow_mut();
                     *screen_target = self.screen.borrow().clone();
                     self.poll_event()
                 }
+                CursiveTestingEvent::Resize(size) => {
+                    *self.screen.borrow_mut() = make_blank_screen(size, *self.colors.borrow());
+                    self.just_emitted_event = true;
+                    Some(cursive::event::Event::WindowResize)
+                }
                 CursiveTestingEvent::Event(event) => {
                     self.just_emitted_event = true;
                     Some(event)
@@ -161,43 +235,77 @@ pub mod testing {
         fn refresh(&mut self) {}
 
         fn has_colors(&self) -> bool {
-            false
+            true
         }
 
         fn screen_size(&self) -> cursive::Vec2 {
             let screen = self.screen.borrow();
-            (screen[0].len(), screen.len()).into()
+            let width = screen.first().map(Vec::len).unwrap_or_default();
+            (width, screen.len()).into()
         }
 
         fn print_at(&self, pos: cursive::Vec2, text: &str) {
-            for (i, c) in text.char_indices() {
-                let mut screen = self.screen.borrow_mut();
-                let screen_width = screen[0].len();
-                if pos.x + i < screen_width {
-                    screen[pos.y][pos.x + i] = c;
-                } else {
+            let mut screen = self.screen.borrow_mut();
+            if pos.y >= screen.len() {
+                return;
+            }
+            let screen_width = screen[pos.y].len();
+            let colors = *self.colors.borrow();
+            let effects = *self.effects.borrow();
+            let make_cell = |character| StyledCell {
+                character,
+                colors,
+                effects,
+            };
+            let mut x = pos.x;
+            for c in text.chars() {
+                // Like a real terminal, wide characters take up more than one
+                // cell, and zero-width characters don't take up any.
+                let width = c.width().unwrap_or(0);
+                if width == 0 {
+                    continue;
+                }
+                if x + width <= screen_width {
+                    screen[pos.y][x] = make_cell(c);
+                    for i in 1..width {
+                        screen[pos.y][x + i] = make_cell(WIDE_CHAR_CONTINUATION);
+                    }
+                } else if screen_width > 0 {
                     // Indicate that the screen was overfull.
-                    screen[pos.y][screen_width - 1] = '$';
+                    screen[pos.y][screen_width - 1] = make_cell('$');
                 }
+                x += width;
             }
         }
 
-        fn clear(&self, _color: Color) {
+        fn clear(&self, color: Color) {
             let mut screen = self.screen.borrow_mut();
-            for i in 0..screen.len() {
-                for j in 0..screen[i].len() {
-                    screen[i][j] = ' ';
+            let cell = StyledCell {
+                character: ' ',
+                colors: ColorPair {
+                    front: color,
+                    back: color,
+                },
+                effects: EnumSet::new(),
+            };
+            for row in screen.iter_mut() {
+                for screen_cell in row.iter_mut() {
+                    *screen_cell = cell.clone();
                 }
             }
         }
 
-        fn set_color(&self, colors: cursive::theme::ColorPair) -> cursive::theme::ColorPair {
-            colors
+        fn set_color(&self, colors: ColorPair) -> ColorPair {
+            self.colors.replace(colors)
         }
 
-        fn set_effect(&self, _effect: cursive::theme::Effect) {}
+        fn set_effect(&self, effect: Effect) {
+            self.effects.borrow_mut().insert(effect);
+        }
 
-        fn unset_effect(&self, _effect: cursive::theme::Effect) {}
+        fn unset_effect(&self, effect: Effect) {
+            self.effects.borrow_mut().remove(effect);
+        }
     }
 
     /// Convert the screenshot into a string for assertions, such as for use
@@ -208,7 +316,34 @@ pub mod testing {
         screen
             .iter()
             .map(|row| {
-                let line: String = row.iter().collect();
+                let line: String = row
+                    .iter()
+                    .filter(|c| **c != WIDE_CHAR_CONTINUATION)
+                    .collect();
+                line.trim().to_owned() + "\n"
+            })
+            .collect::<String>()
+            .trim()
+            .to_owned()
+    }
+
+    /// Convert the styled screenshot into a string for assertions, showing
+    /// only the characters of the cells for which `predicate` returns `true`,
+    /// such as the cells which are highlighted.
+    pub fn styled_screen_to_string(
+        screen: &Rc<RefCell<StyledScreen>>,
+        predicate: impl Fn(&StyledCell) -> bool,
+    ) -> String {
+        let screen = Rc::borrow(screen);
+        let screen = RefCell::borrow(screen);
+        screen
+            .iter()
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .filter(|cell| cell.character != WIDE_CHAR_CONTINUATION)
+                    .map(|cell| if predicate(cell) { cell.character } else { ' ' })
+                    .collect();
                 line.trim().to_owned() + "\n"
             })
             .collect::<String>()
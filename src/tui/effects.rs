@@ -3,6 +3,7 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::io::{stderr, stdout, Stderr, Stdout, Write as WriteIo};
 use std::mem::take;
+use std::process::Command;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -30,7 +31,10 @@ pub enum OperationType {
     GetUpstreamPatchIds,
     InitializeRebase,
     MakeGraph,
+    MakeMergeBaseDb,
     ProcessEvents,
+    QueryWorkingCopy,
+    RebaseCommits,
     RunGitCommand(Arc<String>),
     UpdateCommitGraph,
     WalkCommits,
@@ -52,7 +56,10 @@ impl ToString for OperationType {
             OperationType::GetUpstreamPatchIds => "Enumerating patch IDs",
             OperationType::InitializeRebase => "Initializing rebase",
             OperationType::MakeGraph => "Examining local history",
+            OperationType::MakeMergeBaseDb => "Loading merge-base database",
             OperationType::ProcessEvents => "Processing events",
+            OperationType::QueryWorkingCopy => "Querying working copy",
+            OperationType::RebaseCommits => "Rebasing commits",
             OperationType::RunGitCommand(command) => {
                 return format!("Running Git command: {}", &command)
             }
@@ -63,6 +70,21 @@ impl ToString for OperationType {
     }
 }
 
+/// How much detail to print, as set by `--quiet` and `--verbose`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only print errors and the final results of operations.
+    Quiet,
+
+    /// Also print the progress of operations, such as each commit being
+    /// rebased and the Git commands being run.
+    Normal,
+
+    /// Also print the sub-operations being carried out, such as updating the
+    /// commit graph cache.
+    Verbose,
+}
+
 #[derive(Clone, Debug)]
 enum OutputDest {
     Stdout,
@@ -75,6 +97,7 @@ struct OperationState {
     operation_type: OperationType,
     progress_bar: ProgressBar,
     has_meter: bool,
+    message: Option<String>,
     start_times: Vec<Instant>,
     elapsed_duration: Duration,
 }
@@ -90,6 +113,10 @@ impl OperationState {
         self.progress_bar.inc(increment.try_into().unwrap());
     }
 
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
     pub fn tick(&self) {
         lazy_static! {
             static ref CHECKMARK: String = console::style("✓").green().to_string();
@@ -113,7 +140,10 @@ impl OperationState {
 
         self.progress_bar.set_message(format!(
             "{} ({:.1}s)",
-            self.operation_type.to_string(),
+            match &self.message {
+                Some(message) => message.clone(),
+                None => self.operation_type.to_string(),
+            },
             elapsed_duration.as_secs_f64(),
         ));
         self.progress_bar
@@ -132,6 +162,7 @@ impl OperationState {
 pub struct Effects {
     glyphs: Glyphs,
     dest: OutputDest,
+    verbosity: Verbosity,
     multi_progress: Arc<MultiProgress>,
     nesting_level: usize,
     operation_states: Arc<RwLock<HashMap<OperationType, OperationState>>>,
@@ -189,6 +220,7 @@ impl Effects {
         Effects {
             glyphs,
             dest: OutputDest::Stdout,
+            verbosity: Verbosity::Normal,
             multi_progress,
             nesting_level: Default::default(),
             operation_states,
@@ -200,6 +232,7 @@ impl Effects {
         Effects {
             glyphs,
             dest: OutputDest::Suppress,
+            verbosity: Verbosity::Normal,
             multi_progress: Default::default(),
             nesting_level: Default::default(),
             operation_states: Default::default(),
@@ -211,6 +244,7 @@ impl Effects {
         Effects {
             glyphs,
             dest: OutputDest::BufferForTest(Arc::clone(buffer)),
+            verbosity: Verbosity::Normal,
             multi_progress: Default::default(),
             nesting_level: Default::default(),
             operation_states: Default::default(),
@@ -228,6 +262,31 @@ impl Effects {
         }
     }
 
+    /// Suppress all output and progress indicators, such as for hooks which
+    /// have been configured to be quiet.
+    pub fn suppress(&self) -> Self {
+        let multi_progress = Arc::clone(&self.multi_progress);
+        multi_progress.set_draw_target(ProgressDrawTarget::hidden());
+        Self {
+            dest: OutputDest::Suppress,
+            ..self.clone()
+        }
+    }
+
+    /// Print output with the given amount of detail. See
+    /// `get_output_stream_at`.
+    pub fn with_verbosity(&self, verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            ..self.clone()
+        }
+    }
+
+    /// Get the amount of detail which output is printed with.
+    pub fn get_verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
     /// Start reporting progress for the specified operation type.
     ///
     /// A progress spinner is shown until the returned `ProgressHandle` is
@@ -261,6 +320,8 @@ impl Effects {
         let mut operation_states = self.operation_states.write().unwrap();
 
         let mut nesting_level = self.nesting_level;
+        let is_new_operation = !operation_states.contains_key(&operation_type);
+        let description = operation_type.to_string();
         let operation_state = operation_states
             .entry(operation_type.clone())
             .or_insert_with(|| {
@@ -272,12 +333,26 @@ impl Effects {
                     progress_bar,
                     start_times: Vec::new(),
                     has_meter: false,
+                    message: None,
                     elapsed_duration: Default::default(),
                 };
                 operation_state.tick();
                 operation_state
             });
         operation_state.start_times.push(now);
+        // Release the lock before writing output, since writing output
+        // acquires it as well.
+        drop(operation_states);
+
+        if is_new_operation && self.verbosity >= Verbosity::Verbose {
+            // Not much can be done if writing the output fails.
+            let _ = writeln!(
+                self.get_error_stream(),
+                "branchless: {}{}",
+                "  ".repeat(self.nesting_level),
+                description
+            );
+        }
 
         let effects = Self {
             nesting_level,
@@ -287,6 +362,11 @@ impl Effects {
     }
 
     fn on_notify_progress(&self, operation_type: OperationType, current: usize, total: usize) {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Suppress | OutputDest::BufferForTest(_) => return,
+        }
+
         let mut operation_states = self.operation_states.write().unwrap();
         let operation_state = match operation_states.get_mut(&operation_type) {
             Some(operation_state) => operation_state,
@@ -297,6 +377,11 @@ impl Effects {
     }
 
     fn on_notify_progress_inc(&self, operation_type: OperationType, increment: usize) {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Suppress | OutputDest::BufferForTest(_) => return,
+        }
+
         let mut operation_states = self.operation_states.write().unwrap();
         let operation_state = match operation_states.get_mut(&operation_type) {
             Some(operation_state) => operation_state,
@@ -306,6 +391,21 @@ impl Effects {
         operation_state.inc_progress(increment);
     }
 
+    fn on_notify_message(&self, operation_type: OperationType, message: String) {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Suppress | OutputDest::BufferForTest(_) => return,
+        }
+
+        let mut operation_states = self.operation_states.write().unwrap();
+        let operation_state = match operation_states.get_mut(&operation_type) {
+            Some(operation_state) => operation_state,
+            None => return,
+        };
+
+        operation_state.set_message(message);
+    }
+
     fn on_drop_progress_handle(&self, operation_type: OperationType) {
         match self.dest {
             OutputDest::Stdout => {}
@@ -356,11 +456,80 @@ impl Effects {
         }
     }
 
+    /// Show a desktop notification, such as when a long-running command
+    /// finishes while the user is looking at another window. This is
+    /// best-effort: the notification is sent with `notify-send`, or with
+    /// `osascript` on macOS, and failures are only logged. Nothing is shown
+    /// if output is suppressed or written to a buffer for testing.
+    pub fn send_notification(&self, summary: &str, body: &str) {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Suppress | OutputDest::BufferForTest(_) => return,
+        }
+
+        let result = if cfg!(target_os = "macos") {
+            Command::new("osascript")
+                .arg("-e")
+                .arg(format!(
+                    "display notification {:?} with title {:?}",
+                    body, summary
+                ))
+                .output()
+        } else {
+            Command::new("notify-send").arg(summary).arg(body).output()
+        };
+        match result {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => warn!(?output, "Notification command failed"),
+            Err(err) => warn!(?err, "Could not run notification command"),
+        }
+    }
+
+    /// Show a desktop notification that the command `name` has finished with
+    /// the given exit code (or `None` if it failed with an error), if it ran
+    /// for at least `min_duration`. See `send_notification`.
+    pub fn notify_on_completion(
+        &self,
+        name: &str,
+        elapsed: Duration,
+        min_duration: Duration,
+        exit_code: Option<isize>,
+    ) {
+        if elapsed < min_duration {
+            return;
+        }
+        self.send_notification("git-branchless", &describe_completion(name, exit_code));
+    }
+
     /// Get the set of glyphs associated with the output.
     pub fn get_glyphs(&self) -> &Glyphs {
         &self.glyphs
     }
 
+    /// Get the width of the terminal that output is written to, in columns. Like
+    /// Git, the `COLUMNS` environment variable takes precedence over the size of
+    /// the terminal.
+    ///
+    /// Returns: The width, or `None` if it's unknown, such as when output isn't
+    /// written to a terminal.
+    pub fn get_terminal_width(&self) -> Option<usize> {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Suppress | OutputDest::BufferForTest(_) => return None,
+        }
+
+        let columns = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse::<usize>().ok())
+            .filter(|columns| *columns > 0);
+        match columns {
+            Some(columns) => Some(columns),
+            None => console::Term::stdout()
+                .size_checked()
+                .map(|(_rows, columns)| columns.into()),
+        }
+    }
+
     /// Create a stream that can be written to. The output might go to stdout or
     /// be rendered specially in the terminal.
     pub fn get_output_stream(&self) -> OutputStream {
@@ -371,6 +540,23 @@ impl Effects {
         }
     }
 
+    /// Like `get_output_stream`, but the output is only printed if the
+    /// verbosity is at least `verbosity`. Use `Verbosity::Normal` for the
+    /// progress of an operation, which `--quiet` should hide, and
+    /// `Verbosity::Verbose` for extra detail.
+    pub fn get_output_stream_at(&self, verbosity: Verbosity) -> OutputStream {
+        let dest = if self.verbosity >= verbosity {
+            self.dest.clone()
+        } else {
+            OutputDest::Suppress
+        };
+        OutputStream {
+            dest,
+            buffer: Default::default(),
+            operation_states: Arc::clone(&self.operation_states),
+        }
+    }
+
     /// Create a stream that error output can be written to, rather than regular
     /// output.
     pub fn get_error_stream(&self) -> ErrorStream {
@@ -573,21 +759,52 @@ impl Drop for ProgressHandle<'_> {
 }
 
 impl ProgressHandle<'_> {
+    /// Report that `current` out of `total` items have been processed. This
+    /// turns the spinner into a progress bar.
     pub fn notify_progress(&self, current: usize, total: usize) {
         self.effects
             .on_notify_progress(self.operation_type.clone(), current, total);
     }
 
+    /// Report that `increment` more items have been processed since the last
+    /// call to `notify_progress`.
     pub fn notify_progress_inc(&self, increment: usize) {
         self.effects
             .on_notify_progress_inc(self.operation_type.clone(), increment);
     }
+
+    /// Describe the current state of the operation, such as which item is
+    /// being processed. The message is displayed instead of the name of the
+    /// operation.
+    pub fn notify_message(&self, message: impl Into<String>) {
+        self.effects
+            .on_notify_message(self.operation_type.clone(), message.into());
+    }
+}
+
+/// Describe how the command `name` finished, for `Effects::notify_on_completion`.
+fn describe_completion(name: &str, exit_code: Option<isize>) -> String {
+    match exit_code {
+        Some(0) => format!("git {} finished.", name),
+        Some(exit_code) => format!("git {} failed with exit code {}.", name, exit_code),
+        None => format!("git {} failed.", name),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_describe_completion() {
+        assert_eq!(describe_completion("move", Some(0)), "git move finished.");
+        assert_eq!(
+            describe_completion("restack", Some(1)),
+            "git restack failed with exit code 1."
+        );
+        assert_eq!(describe_completion("sync", None), "git sync failed.");
+    }
+
     #[test]
     fn test_effects_progress() -> eyre::Result<()> {
         let effects = Effects::new(Glyphs::text());
@@ -637,6 +854,17 @@ mod tests {
             assert!(calculate_diff_operation.elapsed_duration >= Duration::from_millis(1));
         }
 
+        progress2.notify_message("Calculating merge-base 1/2");
+        {
+            let operation_states = effects.operation_states.read().unwrap();
+            let get_merge_base_operation =
+                operation_states.get(&OperationType::GetMergeBase).unwrap();
+            assert_eq!(
+                get_merge_base_operation.message.as_deref(),
+                Some("Calculating merge-base 1/2")
+            );
+        }
+
         drop(progress2);
         {
             let operation_states = effects.operation_states.read().unwrap();
@@ -645,4 +873,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_effects_progress_counts() -> eyre::Result<()> {
+        let effects = Effects::new(Glyphs::text());
+        let (_effects, progress) = effects.start_operation(OperationType::ProcessEvents);
+        progress.notify_progress(0, 3);
+        progress.notify_progress_inc(1);
+        progress.notify_progress_inc(1);
+        {
+            let operation_states = effects.operation_states.read().unwrap();
+            let process_events_operation =
+                operation_states.get(&OperationType::ProcessEvents).unwrap();
+            assert!(process_events_operation.has_meter);
+            assert_eq!(process_events_operation.progress_bar.position(), 2);
+            assert_eq!(process_events_operation.progress_bar.length(), 3);
+        }
+
+        drop(progress);
+        {
+            let operation_states = effects.operation_states.read().unwrap();
+            assert!(operation_states.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_effects_progress_suppressed_for_test() -> eyre::Result<()> {
+        let buffer = Default::default();
+        let effects = Effects::new_from_buffer_for_test(Glyphs::text(), &buffer);
+        let (effects2, progress) = effects.start_operation(OperationType::ProcessEvents);
+        progress.notify_progress(0, 3);
+        progress.notify_progress_inc(1);
+        progress.notify_message("Processing event 1/3");
+        let (_effects3, progress2) = effects2.start_operation(OperationType::WalkCommits);
+        drop(progress2);
+        drop(progress);
+
+        assert!(effects.operation_states.read().unwrap().is_empty());
+        assert!(buffer.lock().unwrap().is_empty());
+
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 //! Utility functions.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Returns a path for a given file, searching through PATH to find it.
 pub fn get_from_path(exe_name: &str) -> Option<PathBuf> {
@@ -37,3 +37,23 @@ pub fn get_sh() -> Option<PathBuf> {
     }
     get_from_path(exe_name)
 }
+
+/// Returns whether the file at the given path exists and can be executed.
+pub fn is_executable(path: &Path) -> bool {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return false,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        true
+    }
+}
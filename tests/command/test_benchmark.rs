@@ -0,0 +1,35 @@
+use branchless::testing::make_git;
+
+#[test]
+fn test_benchmark_report() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let (stdout, _stderr) = git.run(&["branchless", "benchmark", "-n", "2"])?;
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split('\t').collect())
+        .collect();
+    for row in rows.iter().skip(1) {
+        assert_eq!(row.len(), 5);
+        for column in &row[2..] {
+            assert!(column.parse::<f64>().is_ok(), "not a timing: {:?}", column);
+        }
+    }
+    let operations: Vec<String> = rows.iter().map(|row| row[..2].join(" ")).collect();
+    insta::assert_debug_snapshot!(operations, @r###"
+    [
+        "operation iterations",
+        "event_replay 2",
+        "make_graph 2",
+        "render_smartlog 2",
+        "build_rebase_plan 2",
+    ]
+    "###);
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use branchless::core::eventlog::{Event, EventLogDb};
+use branchless::git::MaybeZeroOid;
+use branchless::testing::make_git;
+
+#[test]
+fn test_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch create-test1-txt
+        Created branch create-test1-txt at 96d1c37a create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        There are no commits without a branch in the current stack.
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.branch.nameTemplate",
+        "{user}/{date}/{slug}",
+    ])?;
+    git.commit_file("test3", 3)?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch test/2020-10-29/create-test3-txt
+        Created branch test/2020-10-29/create-test3-txt at 70deb1e2 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        o 96d1c37a (create-test1-txt) create test2.txt
+        |
+        @ 70deb1e2 (test/2020-10-29/create-test3-txt) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_branch_name_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["branch", "create-test1-txt"])?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch create-test1-txt-2
+        Created branch create-test1-txt-2 at 62fc20d2 create test1.txt
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let events = event_log_db.get_events()?;
+        match events.last() {
+            Some(Event::RefUpdateEvent {
+                ref_name,
+                old_oid: MaybeZeroOid::Zero,
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+                ..
+            }) => {
+                assert_eq!(ref_name, "refs/heads/create-test1-txt-2");
+                assert_eq!(Some(*new_oid), repo.get_head_info()?.oid);
+            }
+            event => panic!("Unexpected last event: {:?}", event),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_branch_name_template_with_change_id() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&[
+        "config",
+        "branchless.branch.nameTemplate",
+        "{username}/{change-id}/{slug}",
+    ])?;
+    git.run(&["config", "branchless.branch.maxSlugLength", "10"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    // A branch with the same name on a remote should also be avoided.
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/test/I62fc20d2/create",
+        &test1_oid.to_string(),
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch test/I62fc20d2/create-2
+        Created branch test/I62fc20d2/create-2 at 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use branchless::testing::{make_git, Git};
+
+/// Run `git branchless bug-report`, replacing the parts of the report which
+/// depend on the environment with placeholders.
+fn run_bug_report(git: &Git, args: &[&str]) -> eyre::Result<String> {
+    let mut command = vec!["branchless", "bug-report"];
+    command.extend(args);
+    let (stdout, _stderr) = git.run(&command)?;
+    let stdout = stdout
+        .lines()
+        .map(|line| {
+            if line.starts_with("- git-branchless version: ") {
+                "- git-branchless version: <version>"
+            } else if line.starts_with("- Git version: ") {
+                "- Git version: <version>"
+            } else if line.starts_with("- OS: ") {
+                "- OS: <os>"
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(stdout)
+}
+
+#[test]
+fn test_bug_report() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["config", "branchless.submit.githubToken", "hunter2"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let stdout = run_bug_report(&git, &[])?;
+        assert!(!stdout.contains("hunter2"));
+        insta::assert_snapshot!(stdout, @r###"
+        <details>
+        <summary>Show bug report</summary>
+
+        #### Environment
+
+        - git-branchless version: <version>
+        - Git version: <version>
+        - OS: <os>
+
+        #### Hooks
+
+        - post-commit: Installed
+        - post-merge: Installed
+        - post-rewrite: Installed
+        - post-checkout: Installed
+        - pre-auto-gc: Installed
+        - reference-transaction: Installed
+
+        #### Config
+
+        ```
+        branchless.commitMetadata.relativetime = false
+        branchless.core.mainbranch = master
+        branchless.restack.preservetimestamps = true
+        branchless.submit.githubtoken = <redacted>
+        ```
+
+        #### Events (the 8 most recent)
+
+        ```
+        1 ref-update HEAD f777ecc9b0db5ed372b2615695191a8a17f79f24 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        1 ref-update refs/heads/master f777ecc9b0db5ed372b2615695191a8a17f79f24 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        2 commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        3 ref-update refs/heads/foo 0000000000000000000000000000000000000000 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        4 ref-update HEAD 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        5 ref-update HEAD 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f
+        5 ref-update refs/heads/foo 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f
+        6 commit 96d1c37a3d4363611c49f7e52186e189a04c531f
+        ```
+
+        #### Smartlog
+
+        ```
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 96d1c37a (foo) create test2.txt
+        ```
+
+        </details>
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bug_report_anonymize() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "config",
+        "branchless.core.publicBranches",
+        "refs/remotes/origin/release-*",
+    ])?;
+
+    {
+        let stdout = run_bug_report(&git, &["--anonymize"])?;
+        assert!(!stdout.contains("foo"));
+        assert!(!stdout.contains("master"));
+        assert!(!stdout.contains("release"));
+        assert!(!stdout.contains("create test"));
+        insta::assert_snapshot!(stdout, @r###"
+        <details>
+        <summary>Show bug report</summary>
+
+        #### Environment
+
+        - git-branchless version: <version>
+        - Git version: <version>
+        - OS: <os>
+
+        #### Hooks
+
+        - post-commit: Installed
+        - post-merge: Installed
+        - post-rewrite: Installed
+        - post-checkout: Installed
+        - pre-auto-gc: Installed
+        - reference-transaction: Installed
+
+        #### Config
+
+        ```
+        branchless.commitMetadata.relativetime = false
+        branchless.core.mainbranch = ref-1
+        branchless.core.publicbranches = refs/remotes/ref-2
+        ```
+
+        #### Events (the 8 most recent)
+
+        ```
+        1 ref-update HEAD f777ecc9b0db5ed372b2615695191a8a17f79f24 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        1 ref-update refs/heads/ref-1 f777ecc9b0db5ed372b2615695191a8a17f79f24 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        2 commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        3 ref-update refs/heads/ref-3 0000000000000000000000000000000000000000 -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        4 ref-update HEAD 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        5 ref-update HEAD 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f
+        5 ref-update refs/heads/ref-3 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f
+        6 commit 96d1c37a3d4363611c49f7e52186e189a04c531f
+        ```
+
+        #### Smartlog
+
+        ```
+        :
+        O 62fc20d2
+        |
+        @ 96d1c37a
+        ```
+
+        </details>
+        "###);
+    }
+
+    Ok(())
+}
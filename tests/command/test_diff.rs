@@ -0,0 +1,76 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_diff_against_previous_amend() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.write_file("test1", "amended contents\n")?;
+    git.run(&["commit", "-a", "--amend", "--no-edit"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "diff", "--against-previous", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> diff 62fc20d2a290daea0d52bdc2ed2ad4be6491010e 89b6eac7325326119fb289f5679fcd880907dae1
+        diff --git a/test1.txt b/test1.txt
+        index 7432a8f..7df3c9f 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -1 +1 @@
+        -test1 contents
+        +amended contents
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_against_previous_rebase() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&["branchless", "restack"])?;
+    git.run(&["next"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "diff", "--against-previous", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> range-diff 96d1c37a3d4363611c49f7e52186e189a04c531f^! e3d80a16647b7e44ea2d8cb6c57ee1bde8e44b91^!
+        1:  96d1c37 = 1:  e3d80a1 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_against_previous_no_previous_version() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "diff", "--against-previous", "HEAD"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e has no previous version in the event log.
+        "###);
+    }
+
+    Ok(())
+}
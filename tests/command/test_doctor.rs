@@ -0,0 +1,194 @@
+use std::time::SystemTime;
+
+use branchless::core::eventlog::{Event, EventLogDb};
+use branchless::testing::{make_git, GitInitOptions, GitRunOptions};
+
+#[test]
+fn test_doctor_no_problems() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No problems found.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_doctor_fix_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    std::fs::remove_file(hooks_dir.join("post-commit"))?;
+    std::fs::write(
+        hooks_dir.join("post-merge"),
+        "#!/bin/sh\necho 'some other tool'\n",
+    )?;
+    git.run(&["config", "--unset", "branchless.core.mainBranch"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: The post-commit hook is not installed.
+          Suggestion: Run `git branchless init` to install it.
+          This can be fixed automatically with: git branchless doctor --fix
+        Problem: The post-merge hook was overwritten by something other than git-branchless: <repo-path>/.git/hooks/post-merge
+          Suggestion: Add this line to the hook script: git branchless hook-post-merge "$@"
+        Problem: The main branch is not configured.
+          Suggestion: Run: git config branchless.core.mainBranch <branch>
+          This can be fixed automatically with: git branchless doctor --fix
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor", "--fix"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: The post-commit hook is not installed.
+          Fixed.
+        Problem: The post-merge hook was overwritten by something other than git-branchless: <repo-path>/.git/hooks/post-merge
+          Suggestion: Add this line to the hook script: git branchless hook-post-merge "$@"
+        Problem: The main branch is not configured.
+          Fixed.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: The post-merge hook was overwritten by something other than git-branchless: <repo-path>/.git/hooks/post-merge
+          Suggestion: Add this line to the hook script: git branchless hook-post-merge "$@"
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_doctor_hide_missing_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let mut event_log_db = EventLogDb::new(&conn)?;
+        let now = SystemTime::now();
+        let event_tx_id = event_log_db.make_transaction_id(now, "test")?;
+        event_log_db.add_events(vec![Event::CommitEvent {
+            timestamp: now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64(),
+            event_tx_id,
+            commit_oid: "1234567890123456789012345678901234567890".parse()?,
+        }])?;
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor", "--fix"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: The event log refers to 1 visible commit missing from the repository: 1234567890123456789012345678901234567890
+          Fixed.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No problems found.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_doctor_repo_predates_global_install() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    git.run(&["config", "branchless.core.mainBranch", "master"])?;
+
+    let home_dir = git.repo_path.join(".git").join("home");
+    std::fs::create_dir_all(&home_dir)?;
+    let env = vec![("HOME".to_string(), home_dir.to_str().unwrap().to_string())];
+    git.run_with_options(
+        &["branchless", "init", "--global"],
+        &GitRunOptions {
+            env: env.clone(),
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                env: env.clone(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: This repository was created before git-branchless was installed globally, so its hooks are not installed.
+          Suggestion: Run `git branchless init` to set it up.
+          This can be fixed automatically with: git branchless doctor --fix
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor", "--fix"],
+            &GitRunOptions {
+                env: env.clone(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Problem: This repository was created before git-branchless was installed globally, so its hooks are not installed.
+          Fixed.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "doctor"],
+            &GitRunOptions {
+                env,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No problems found.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_edit_amend_and_continue() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["checkout", "-b", "foo"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "edit", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        Editing 62fc20d2 create test1.txt. Amend it (such as with `git commit --amend`), then run `git branchless edit --continue`.
+        "###);
+    }
+
+    git.write_file("test1", "amended contents\n")?;
+    git.run(&["commit", "--amend", "-a", "--no-edit"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "edit", "--continue"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 407b9249 create test2.txt
+        [2/2] Committed as: ea784fd8 create test3.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 89b6eac7325326119fb289f5679fcd880907dae1
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        branchless: running command: <git-executable> checkout foo
+        Finished editing 62fc20d2a290daea0d52bdc2ed2ad4be6491010e.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 89b6eac7 create test1.txt
+        |
+        o 407b9249 create test2.txt
+        |
+        @ ea784fd8 (foo) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short", "--branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ## foo
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_already_in_progress() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    git.run(&["branchless", "edit", &test1_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "edit", &test2_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e is already being edited. Run `git branchless edit --continue` to finish editing it first.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "edit", "--continue"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        Finished editing 62fc20d2a290daea0d52bdc2ed2ad4be6491010e.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "edit", "--continue"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No edit is in progress.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_export_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.write_file("test2", "test2 contents")?;
+    git.run(&["add", "."])?;
+    git.run(&[
+        "commit",
+        "-m",
+        "Fix a|b in *foo*",
+        "-m",
+        "Differential Revision: https://phabricator.example.com/D123",
+    ])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "export-stack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        <!-- branchless-stack-start -->
+        Stack of 3 commits onto `master`:
+
+        | # | Commit | Summary | Branch | Review |
+        | --- | --- | --- | --- | --- |
+        | 1 | `62fc20d2` | create test1.txt | `foo` |  |
+        | 2 | `9f0cd43d` | Fix a\|b in \*foo\* |  | [D123](https://phabricator.example.com/D123) |
+        | 3 | `9fbdd33b` | create test3.txt |  |  |
+        <!-- branchless-stack-end -->
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_export_stack_no_draft_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "export-stack"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        There are no draft commits in the current stack to export.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
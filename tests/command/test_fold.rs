@@ -0,0 +1,126 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_fold_head() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["prev"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "fold"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 85a589da create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        In-memory rebase succeeded.
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout b8cd6afd462b1dbee59edb869fa35f7e51a69abd
+        Folded 96d1c37a create test2.txt into 62fc20d2 create test1.txt.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ b8cd6afd create test1.txt
+        |
+        o 85a589da create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "--format=%B", "--stat", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test1.txt
+
+        create test2.txt
+
+
+         test1.txt | 1 +
+         test2.txt | 1 +
+         2 files changed, 2 insertions(+)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fold_moves_siblings_and_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "fold",
+            &test2_oid.to_string(),
+            "-m",
+            "create test1.txt and test2.txt",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4a43e2d4 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 4a43e2d4d19381f72b08d6c64052123f9072c383
+        In-memory rebase succeeded.
+        branchless: processing 1 update: branch foo
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 4a43e2d4d19381f72b08d6c64052123f9072c383
+        Folded 96d1c37a create test2.txt into 62fc20d2 create test1.txt.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o f6838a2f (foo) create test1.txt and test2.txt
+        |
+        @ 4a43e2d4 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fold_main_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "fold"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Cannot fold 62fc20d2 create test1.txt into f777ecc9 create initial.txt, since it is on the main branch.
+        "###);
+    }
+
+    Ok(())
+}
@@ -104,6 +104,27 @@ fn test_hide_current_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_with_reason() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test", 1)?;
+    git.run(&["hide", "-m", "superseded by D1234", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        % 3df4b935 (hidden: superseded by D1234) create test.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hidden_commit_with_head_as_child() -> eyre::Result<()> {
     let git = make_git()?;
@@ -309,3 +330,72 @@ fn test_hide_recursive() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hide_recursive_confirm() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["config", "branchless.hide.confirmThreshold", "2"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["hide", "-r", &test1_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                input: Some("n\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This will hide 3 commits:
+          62fc20d2 create test1.txt
+          96d1c37a create test2.txt
+          70deb1e2 create test3.txt
+        Confirm? [yN] Aborted.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["hide", "-r", &test1_oid.to_string()],
+            &GitRunOptions {
+                input: Some("y\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This will hide 3 commits:
+          62fc20d2 create test1.txt
+          96d1c37a create test2.txt
+          70deb1e2 create test3.txt
+        Confirm? [yN] Hid commit: 62fc20d2 create test1.txt
+        To unhide this commit, run: git unhide 62fc20d2
+        Hid commit: 96d1c37a create test2.txt
+        To unhide this commit, run: git unhide 96d1c37a
+        Hid commit: 70deb1e2 create test3.txt
+        To unhide this commit, run: git unhide 70deb1e2
+        "###);
+    }
+
+    git.run(&["unhide", "-r", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["hide", "-r", "--no-confirm", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d2 create test1.txt
+        To unhide this commit, run: git unhide 62fc20d2
+        Hid commit: 96d1c37a create test2.txt
+        To unhide this commit, run: git unhide 96d1c37a
+        Hid commit: 70deb1e2 create test3.txt
+        To unhide this commit, run: git unhide 70deb1e2
+        "###);
+    }
+
+    Ok(())
+}
@@ -79,6 +79,7 @@ fn test_old_git_version_warning() -> eyre::Result<()> {
         Installing alias (non-global): git restack -> git branchless restack
         Installing alias (non-global): git undo -> git branchless undo
         Installing alias (non-global): git move -> git branchless move
+        Installing alias (non-global): git sync -> git branchless sync
         Warning: the branchless workflow's `git undo` command requires Git
         v2.29 or later, but your Git version is: <git version output>
 
@@ -133,6 +134,7 @@ fn test_init_basic() -> eyre::Result<()> {
         Installing alias (non-global): git restack -> git branchless restack
         Installing alias (non-global): git undo -> git branchless undo
         Installing alias (non-global): git move -> git branchless move
+        Installing alias (non-global): git sync -> git branchless sync
         Successfully installed git-branchless.
         To uninstall, run: git branchless init --uninstall
         "###);
@@ -186,6 +188,7 @@ fn test_init_prompt_for_main_branch() -> eyre::Result<()> {
         Installing alias (non-global): git restack -> git branchless restack
         Installing alias (non-global): git undo -> git branchless undo
         Installing alias (non-global): git move -> git branchless move
+        Installing alias (non-global): git sync -> git branchless sync
         Successfully installed git-branchless.
         To uninstall, run: git branchless init --uninstall
         "###);
@@ -230,7 +233,7 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
       ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ SPANTRACE ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
-       0: branchless::commands::smartlog::smartlog with effects=<Output fancy=false>
+       0: branchless::commands::smartlog::smartlog with effects=<Output fancy=false> options=SmartlogOptions { verbose: false, format: None, all_users: false, html: false, show_all: false, anonymize: false }
           at some/file/path.rs:123
 
     Suggestion:
@@ -278,6 +281,94 @@ fn test_init_uninstall() -> eyre::Result<()> {
         Uninstalling alias (non-global): git restack
         Uninstalling alias (non-global): git undo
         Uninstalling alias (non-global): git move
+        Uninstalling alias (non-global): git sync
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_init_global() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    let home_dir = git.repo_path.join("home");
+    std::fs::create_dir_all(&home_dir)?;
+    let options = GitRunOptions {
+        env: vec![("HOME".to_string(), home_dir.to_str().unwrap().to_string())],
+        ..Default::default()
+    };
+
+    {
+        let (stdout, stderr) =
+            git.run_with_options(&["branchless", "init", "--global"], &options)?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Setting config (global): init.templateDir = <repo-path>/home/.config/git-branchless/template
+        Installing hook (template): post-commit
+        Installing hook (template): post-merge
+        Installing hook (template): post-rewrite
+        Installing hook (template): post-checkout
+        Installing hook (template): pre-auto-gc
+        Installing hook (template): reference-transaction
+        Installing alias (global): git smartlog -> git branchless smartlog
+        Installing alias (global): git sl -> git branchless smartlog
+        Installing alias (global): git hide -> git branchless hide
+        Installing alias (global): git unhide -> git branchless unhide
+        Installing alias (global): git prev -> git branchless prev
+        Installing alias (global): git next -> git branchless next
+        Installing alias (global): git restack -> git branchless restack
+        Installing alias (global): git undo -> git branchless undo
+        Installing alias (global): git move -> git branchless move
+        Installing alias (global): git sync -> git branchless sync
+        Setting config (global): advice.detachedHead = false
+        Successfully installed git-branchless globally.
+        Repositories created with `git init` or `git clone` from now on will be set up automatically.
+        Their main branch is assumed to be `master` unless `branchless.core.mainBranch` is set.
+        To set up an existing repository, run: git branchless init
+        To uninstall, run: git branchless init --global --uninstall
+        "###);
+    }
+
+    // Repositories created from now on should be set up automatically.
+    git.run_with_options(&["init", "new-repo"], &options)?;
+    let hook_path = git
+        .repo_path
+        .join("new-repo")
+        .join(".git")
+        .join("hooks")
+        .join("post-commit");
+    assert!(std::fs::read_to_string(hook_path)?.contains("git branchless hook-post-commit"));
+    {
+        let (stdout, _stderr) =
+            git.run_with_options(&["config", "--global", "alias.sl"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless smartlog
+        "###);
+    }
+
+    {
+        let (stdout, stderr) =
+            git.run_with_options(&["branchless", "init", "--global", "--uninstall"], &options)?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Unsetting config (global): advice.detachedHead
+        Uninstalling hook (template): post-commit
+        Uninstalling hook (template): post-merge
+        Uninstalling hook (template): post-rewrite
+        Uninstalling hook (template): post-checkout
+        Uninstalling hook (template): pre-auto-gc
+        Uninstalling hook (template): reference-transaction
+        Uninstalling alias (global): git smartlog
+        Uninstalling alias (global): git sl
+        Uninstalling alias (global): git hide
+        Uninstalling alias (global): git unhide
+        Uninstalling alias (global): git prev
+        Uninstalling alias (global): git next
+        Uninstalling alias (global): git restack
+        Uninstalling alias (global): git undo
+        Uninstalling alias (global): git move
+        Uninstalling alias (global): git sync
         "###);
     }
 
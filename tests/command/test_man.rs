@@ -0,0 +1,85 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_man() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "man", "gc"])?;
+        let stdout = stdout.replace(env!("CARGO_PKG_VERSION"), "<version>");
+        insta::assert_snapshot!(stdout, @r###"
+        .TH "GIT-BRANCHLESS-GC" "1" "" "git-branchless <version>" "git-branchless Manual"
+        .SH NAME
+        git\-branchless\-gc \- Run internal garbage collection
+        .SH DESCRIPTION
+        .nf
+        git\-branchless\-gc <version>
+        Run internal garbage collection
+
+        USAGE:
+            git branchless gc
+
+        FLAGS:
+            \-h, \-\-help       
+                    Prints help information
+
+            \-V, \-\-version    
+                    Prints version information
+        .fi
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "man", "gc", "--text"])?;
+        let stdout = stdout.replace(env!("CARGO_PKG_VERSION"), "<version>");
+        insta::assert_snapshot!(stdout, @r###"
+        git-branchless-gc <version>
+        Run internal garbage collection
+
+        USAGE:
+            git branchless gc
+
+        FLAGS:
+            -h, --help       
+                    Prints help information
+
+            -V, --version    
+                    Prints version information
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "man", "nonexistent"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"");
+        insta::assert_snapshot!(stderr, @r###"
+        Unknown subcommand: nonexistent
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_man_output_dir() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["branchless", "man", "--output-dir", "man1"])?;
+
+    let man_dir = git.repo_path.join("man1");
+    let page = std::fs::read_to_string(man_dir.join("git-branchless-smartlog.1"))?;
+    assert!(page.starts_with(r#".TH "GIT-BRANCHLESS-SMARTLOG" "1""#));
+    assert!(page.contains("\\-\\-anonymize"));
+    assert!(man_dir.join("git-branchless.1").exists());
+    assert!(man_dir.join("git-branchless-man.1").exists());
+
+    Ok(())
+}
@@ -1,4 +1,7 @@
-use branchless::testing::{make_git, GitRunOptions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use branchless::testing::{make_git, Git, GitRunOptions};
 
 use crate::command::test_restack::remove_rebase_lines;
 
@@ -226,7 +229,7 @@ fn test_move_with_source_not_in_smartlog_on_disk() -> eyre::Result<()> {
         O 62fc20d2 create test1.txt
         |\
         : o 96d1c37a create test2.txt
-        :
+        : (1 commit)
         @ 5bb72580 (master) create test4.txt
         "###);
     }
@@ -299,7 +302,7 @@ fn test_move_with_source_not_in_smartlog_in_memory() -> eyre::Result<()> {
         O 62fc20d2 create test1.txt
         |\
         : o 96d1c37a create test2.txt
-        :
+        : (1 commit)
         @ a2482074 (master) create test4.txt
         "###);
     }
@@ -358,7 +361,6 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
         There was a merge conflict, which currently can't be resolved when rebasing in-memory.
         The conflicting commit was: e85d25c7 create conflict.txt
         Trying again on-disk...
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         CONFLICT (add/add): Merge conflict in conflict.txt
@@ -389,6 +391,183 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_merge_conflict_quiet() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    {
+        // Git's output isn't shown while the rebase runs, but it's shown
+        // afterwards, since it explains what to do next.
+        let (stdout, stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "--quiet",
+                "move",
+                "-s",
+                &other_oid.to_string(),
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        There was a merge conflict, which currently can't be resolved when rebasing in-memory.
+        The conflicting commit was: e85d25c7 create conflict.txt
+        "###);
+        assert!(
+            stderr.contains("CONFLICT (add/add): Merge conflict in conflict.txt"),
+            "stderr was: {}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_runs_user_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+
+    // Replace the installed `post-rewrite` hook with one which records what
+    // it was invoked with, like a metadata updater would.
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let post_rewrite_path = hooks_dir.join("post-rewrite");
+    std::fs::write(
+        &post_rewrite_path,
+        r#"#!/bin/sh
+echo "args: $*" >>post-rewrite.log
+echo "prefix: $GIT_PREFIX" >>post-rewrite.log
+cat >>post-rewrite.log
+"#,
+    )?;
+    std::fs::set_permissions(&post_rewrite_path, std::fs::Permissions::from_mode(0o755))?;
+
+    // Hooks which aren't executable are ignored, as they are by Git.
+    let reference_transaction_path = hooks_dir.join("reference-transaction");
+    std::fs::write(
+        &reference_transaction_path,
+        "#!/bin/sh\necho should not run >>reference-transaction.log\n",
+    )?;
+    std::fs::set_permissions(
+        &reference_transaction_path,
+        std::fs::Permissions::from_mode(0o644),
+    )?;
+
+    std::fs::create_dir(git.repo_path.join("subdir"))?;
+    git.run(&[
+        "-C",
+        "subdir",
+        "branchless",
+        "move",
+        "--in-memory",
+        "-s",
+        &test2_oid.to_string(),
+    ])?;
+
+    let log = std::fs::read_to_string(git.repo_path.join("post-rewrite.log"))?;
+    insta::assert_snapshot!(log, @r###"
+    args: rebase
+    prefix: subdir/
+    96d1c37a3d4363611c49f7e52186e189a04c531f d742fb97c050479c1d3057d8de0f05190dd3a89f
+    "###);
+    assert!(!git.repo_path.join("reference-transaction.log").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_move_strategy_option() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--on-disk",
+            "-X",
+            "theirs",
+            "-s",
+            &other_oid.to_string(),
+        ])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 202143f2 create conflict.txt
+        |
+        o 8adb3d9c create conflict.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "8adb3d9c:conflict.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        conflict 1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_force_on_disk_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.run(&["reset", "--hard", &test1_oid.to_string()])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    git.run(&["config", "branchless.move.forceOnDisk", "true"])?;
+    {
+        let (stdout, _stderr) = git.run(&["move", "-s", &test3_oid.to_string(), "-d", "master"])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_base() -> eyre::Result<()> {
     let git = make_git()?;
@@ -660,10 +839,12 @@ fn test_move_base_onto_head() -> eyre::Result<()> {
         )?;
         insta::assert_snapshot!(stderr, @"");
         insta::assert_snapshot!(stdout, @r###"
-        This operation failed because it would introduce a cycle:
-        ,-> 70deb1e2 create test3.txt
-        |   96d1c37a create test2.txt
-        `-- 70deb1e2 create test3.txt
+        This operation failed because the destination commit is a descendant of the source commit, so it would be moved along with the source commit:
+        Source: 96d1c37a create test2.txt
+        Destination: 70deb1e2 create test3.txt
+        To move the source commit on top of the destination commit, first move the destination commit out of the way:
+        git move -s 70deb1e28791d8e7dd5a1f0c871a51b91282562f -d 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        Then move the source commit onto the rewritten destination commit.
         "###);
     }
 
@@ -682,6 +863,66 @@ fn test_move_base_onto_head() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_onto_descendant() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &[
+                "move",
+                "-s",
+                &test1_oid.to_string(),
+                "-d",
+                &test3_oid.to_string(),
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This operation failed because the destination commit is a descendant of the source commit, so it would be moved along with the source commit:
+        Source: 62fc20d2 create test1.txt
+        Destination: 70deb1e2 create test3.txt
+        To move the source commit on top of the destination commit, first move the destination commit out of the way:
+        git move -s 70deb1e28791d8e7dd5a1f0c871a51b91282562f -d f777ecc9b0db5ed372b2615695191a8a17f79f24
+        Then move the source commit onto the rewritten destination commit.
+        "###);
+    }
+
+    // Follow the suggestion to reorder the commits.
+    git.run(&["move", "-s", &test3_oid.to_string(), "-d", "master"])?;
+    git.run(&["move", "-s", &test1_oid.to_string(), "-d", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 98b9119d create test3.txt
+        |
+        o 4b9ce31b create test1.txt
+        |
+        o 9f77bc5f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_force_in_memory() -> eyre::Result<()> {
     let git = make_git()?;
@@ -879,7 +1120,7 @@ fn test_move_main_branch_commits() -> eyre::Result<()> {
         O 62fc20d2 create test1.txt
         |\
         : o 96d1c37a create test2.txt
-        :
+        : (2 commits)
         @ 566e4341 (master) create test5.txt
         "###);
     }
@@ -972,7 +1213,6 @@ fn test_move_branches_after_move_on_disk() -> eyre::Result<()> {
         Successfully rebased and updated detached HEAD.
         "###);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         "###);
@@ -1046,7 +1286,7 @@ fn test_move_no_reapply_upstream_commits_in_memory() -> eyre::Result<()> {
         "###);
         insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
-        [1/2] Skipped commit (was already applied upstream): 62fc20d2 create test1.txt
+        [1/2] Skipped commit (already in main as 047b7ad7): 62fc20d2 create test1.txt
         [2/2] Committed as: fa466332 create test2.txt
         branchless: processing 1 update: branch should-be-deleted
         branchless: processing 2 rewritten commits
@@ -1068,6 +1308,47 @@ fn test_move_no_reapply_upstream_commits_in_memory() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_no_dedup() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+    git.run(&["checkout", &test2_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--in-memory",
+            "--no-dedup",
+            "-b",
+            "HEAD",
+            "-d",
+            "master",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Skipped now-empty commit: cfea32a9 create test1.txt
+        [2/2] Committed as: fa466332 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout fa46633239bfa767036e41a77b67258286e4ddb9
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_no_reapply_squashed_commits_in_memory() -> eyre::Result<()> {
     let git = make_git()?;
@@ -1158,7 +1439,7 @@ fn test_move_no_reapply_upstream_commits_on_disk() -> eyre::Result<()> {
         insta::assert_snapshot!(stderr, @r###"
         Executing: git branchless hook-register-extra-post-rewrite-hook
         branchless: processing 1 update: ref HEAD
-        Executing: git branchless hook-skip-upstream-applied-commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        Executing: git branchless hook-skip-upstream-applied-commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a
         branchless: processing 1 update: ref HEAD
         branchless: processed commit: fa466332 create test2.txt
         Executing: git branchless hook-detect-empty-commit 96d1c37a3d4363611c49f7e52186e189a04c531f
@@ -1172,10 +1453,9 @@ fn test_move_no_reapply_upstream_commits_on_disk() -> eyre::Result<()> {
         Successfully rebased and updated master.
         "###);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
-        Skipping commit (was already applied upstream): 62fc20d2 create test1.txt
+        Skipping commit (already in main as 047b7ad7): 62fc20d2 create test1.txt
         "###);
     }
 
@@ -1251,7 +1531,6 @@ fn test_move_no_reapply_squashed_commits_on_disk() -> eyre::Result<()> {
         Successfully rebased and updated master.
         "###);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Skipped now-empty commit: e7bcdd60 create test1.txt
@@ -1303,7 +1582,7 @@ fn test_move_delete_checked_out_branch_in_memory() -> eyre::Result<()> {
         : o 96d1c37a (work) create test2.txt
         : |
         : o ffcba554 (more-work) create test3.txt
-        :
+        : (1 commit)
         @ 91c5ce63 (master) create test2.txt
         "###);
     }
@@ -1319,8 +1598,8 @@ fn test_move_delete_checked_out_branch_in_memory() -> eyre::Result<()> {
         "###);
         insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
-        [1/3] Skipped commit (was already applied upstream): 62fc20d2 create test1.txt
-        [2/3] Skipped commit (was already applied upstream): 96d1c37a create test2.txt
+        [1/3] Skipped commit (already in main as 047b7ad7): 62fc20d2 create test1.txt
+        [2/3] Skipped commit (already in main as 91c5ce63): 96d1c37a create test2.txt
         [3/3] Committed as: 012efd6e create test3.txt
         branchless: processing 2 updates: branch more-work, branch work
         branchless: processing 3 rewritten commits
@@ -1375,7 +1654,7 @@ fn test_move_delete_checked_out_branch_on_disk() -> eyre::Result<()> {
         : o 96d1c37a (work) create test2.txt
         : |
         : o ffcba554 (more-work) create test3.txt
-        :
+        : (1 commit)
         @ 91c5ce63 (master) create test2.txt
         "###);
     }
@@ -1386,8 +1665,8 @@ fn test_move_delete_checked_out_branch_on_disk() -> eyre::Result<()> {
         insta::assert_snapshot!(stderr, @r###"
         Executing: git branchless hook-register-extra-post-rewrite-hook
         branchless: processing 1 update: ref HEAD
-        Executing: git branchless hook-skip-upstream-applied-commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
-        Executing: git branchless hook-skip-upstream-applied-commit 96d1c37a3d4363611c49f7e52186e189a04c531f
+        Executing: git branchless hook-skip-upstream-applied-commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a
+        Executing: git branchless hook-skip-upstream-applied-commit 96d1c37a3d4363611c49f7e52186e189a04c531f 91c5ce63686889388daec1120bf57bea8a744bc2
         branchless: processing 1 update: ref HEAD
         branchless: processed commit: 012efd6e create test3.txt
         Executing: git branchless hook-detect-empty-commit ffcba554683d83de283de084a7d3896e332bbcdb
@@ -1401,11 +1680,10 @@ fn test_move_delete_checked_out_branch_on_disk() -> eyre::Result<()> {
         Successfully rebased and updated work.
         "###);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
-        Skipping commit (was already applied upstream): 62fc20d2 create test1.txt
-        Skipping commit (was already applied upstream): 96d1c37a create test2.txt
+        Skipping commit (already in main as 047b7ad7): 62fc20d2 create test1.txt
+        Skipping commit (already in main as 91c5ce63): 96d1c37a create test2.txt
         "###);
     }
 
@@ -1445,7 +1723,6 @@ fn test_move_on_disk_with_unstaged_changes() -> eyre::Result<()> {
         )?;
         insta::assert_snapshot!(stderr, @"");
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         This operation would modify the working copy, but you have uncommitted changes
         in your working copy which might be overwritten as a result.
         Commit your changes and then try again.
@@ -1544,18 +1821,22 @@ fn test_move_on_disk_merge_commit() -> eyre::Result<()> {
                     CreateLabel {
                         label_name: "merge-parent-4",
                     },
+                    Reset {
+                        target: Oid(
+                            NonZeroOid(98b9119d16974f372e76cb64a3b77c528fc0b18b),
+                        ),
+                    },
                     Merge {
                         commit_oid: NonZeroOid(28790c73f13f38ce0d3beb6cfeb2d818b32bcd09),
                         commits_to_merge: [
-                            Oid(
-                                NonZeroOid(98b9119d16974f372e76cb64a3b77c528fc0b18b),
+                            Label(
+                                "merge-parent-4",
                             ),
                         ],
                     },
                 ],
             },
         )
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         "###);
@@ -1566,9 +1847,10 @@ fn test_move_on_disk_merge_commit() -> eyre::Result<()> {
         branchless: processed commit: 96d1c37a create test2.txt
         Executing: git branchless hook-detect-empty-commit fe65c1fe15584744e649b2c79d4cf9b0d878f92e
         branchless: processing 1 update: ref refs/rewritten/merge-parent-4
+        branchless: processing 1 update: ref HEAD
         branchless: processing 2 rewritten commits
         branchless: running command: <git-executable> checkout 98b9119d16974f372e76cb64a3b77c528fc0b18b
-        Previous HEAD position was 96a2c4b Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        Previous HEAD position was 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         branchless: processing 1 update: ref HEAD
         HEAD is now at 98b9119 create test3.txt
         branchless: processing checkout
@@ -1584,13 +1866,13 @@ fn test_move_on_disk_merge_commit() -> eyre::Result<()> {
         |\
         | @ 98b9119d create test3.txt
         | |
-        | o 96a2c4be Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        | o 5a6a761e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         |
         O 62fc20d2 (master) create test1.txt
         |
         o 96d1c37a create test2.txt
         |
-        o 96a2c4be Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        o 5a6a761e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         "###);
     }
 
@@ -1702,7 +1984,6 @@ fn test_move_merge_commit() -> eyre::Result<()> {
         Merge commits currently can't be rebased in-memory.
         The merge commit was: 28790c73 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         Trying again on-disk...
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         "###);
@@ -1713,9 +1994,10 @@ fn test_move_merge_commit() -> eyre::Result<()> {
         branchless: processed commit: 96d1c37a create test2.txt
         Executing: git branchless hook-detect-empty-commit fe65c1fe15584744e649b2c79d4cf9b0d878f92e
         branchless: processing 1 update: ref refs/rewritten/merge-parent-4
+        branchless: processing 1 update: ref HEAD
         branchless: processing 2 rewritten commits
         branchless: running command: <git-executable> checkout 98b9119d16974f372e76cb64a3b77c528fc0b18b
-        Previous HEAD position was 96a2c4b Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        Previous HEAD position was 5a6a761 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         branchless: processing 1 update: ref HEAD
         HEAD is now at 98b9119 create test3.txt
         branchless: processing checkout
@@ -1731,13 +2013,446 @@ fn test_move_merge_commit() -> eyre::Result<()> {
         |\
         | @ 98b9119d create test3.txt
         | |
-        | o 96a2c4be Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        | o 5a6a761e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
         |
         O 62fc20d2 (master) create test1.txt
         |
         o 96d1c37a create test2.txt
         |
-        o 96a2c4be Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        o 5a6a761e Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
+        "###);
+    }
+
+    Ok(())
+}
+
+/// Make a fake `gpg` program which produces a fixed signature. If
+/// `num_failures` is nonzero, it fails that many times before succeeding.
+fn make_fake_gpg(git: &Git, num_failures: usize) -> eyre::Result<PathBuf> {
+    let gpg_path = git.repo_path.join(".git").join("fake-gpg");
+    std::fs::write(
+        &gpg_path,
+        format!(
+            r#"#!/bin/sh
+dir=$(dirname "$0")
+cat >/dev/null
+echo "$@" >>"$dir/gpg.log"
+if [ "$(wc -l <"$dir/gpg.log")" -le {num_failures} ]; then
+    echo "gpg: signing failed: No secret key" >&2
+    exit 2
+fi
+printf '\n[GNUPG:] SIG_CREATED D 22 8 00 0 FAKE\n' >&2
+printf -- '-----BEGIN PGP SIGNATURE-----\n\nZmFrZSBzaWduYXR1cmU=\n-----END PGP SIGNATURE-----\n'
+"#,
+            num_failures = num_failures
+        ),
+    )?;
+    std::fs::set_permissions(&gpg_path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(gpg_path)
+}
+
+#[test]
+fn test_move_sign_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let gpg_path = make_fake_gpg(&git, 0)?;
+    git.run(&["config", "gpg.program", gpg_path.to_str().unwrap()])?;
+    git.run(&["config", "commit.gpgSign", "true"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--in-memory",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: cc132207 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout cc132207893e6d2c27b6a60de42ba33558dedb57
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["cat-file", "commit", "HEAD"])?;
+        assert!(stdout.contains("\ngpgsig -----BEGIN PGP SIGNATURE-----\n"));
+        let gpg_log = std::fs::read_to_string(git.repo_path.join(".git").join("gpg.log"))?;
+        insta::assert_snapshot!(gpg_log, @r###"
+        --status-fd=2 -bsau Testy McTestface <test@example.com>
+        "###);
+    }
+
+    // Moving the commit without signing it should warn that its signature was
+    // dropped.
+    git.run(&["config", "commit.gpgSign", "false"])?;
+    {
+        let (stdout, _stderr) = git.run(&["move", "--in-memory", "-d", "master"])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 70deb1e2 create test3.txt
+        Dropped the signatures of 1 rewritten commit. To re-sign rewritten commits, set commit.gpgSign.
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_sign_failure() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+
+    let gpg_path = make_fake_gpg(&git, 1)?;
+    git.run(&["config", "gpg.program", gpg_path.to_str().unwrap()])?;
+    git.run(&["config", "commit.gpgSign", "true"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--in-memory",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 4838e49b create test3.txt
+        [2/2] Committed as: a2482074 create test4.txt
+        Could not sign the rewritten commits, so they were left unsigned: <repo-path>/.git/fake-gpg failed to sign the data: gpg: signing failed: No secret key
+        To rebase on-disk instead when this happens, run: git config branchless.restack.signingFailure on-disk
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout a248207402822b7396cabe0f1011d8a7ce7daf1b
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    std::fs::remove_file(git.repo_path.join(".git").join("gpg.log"))?;
+    git.run(&["config", "branchless.restack.signingFailure", "on-disk"])?;
+    {
+        let (stdout, _stderr) = git.run(&["move", "-d", "master"])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        The commit could not be signed in-memory: <repo-path>/.git/fake-gpg failed to sign the data: gpg: signing failed: No secret key
+        The commit was: 4838e49b create test3.txt
+        Trying again on-disk...
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["cat-file", "commit", "HEAD"])?;
+        assert!(stdout.contains("\ngpgsig -----BEGIN PGP SIGNATURE-----\n"));
+        let gpg_log = std::fs::read_to_string(git.repo_path.join(".git").join("gpg.log"))?;
+        insta::assert_snapshot!(gpg_log, @r###"
+        --status-fd=2 -bsau Testy McTestface <test@example.com>
+        --status-fd=2 -bsau Testy McTestface <test@example.com>
+        --status-fd=2 -bsau Testy McTestface <test@example.com>
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_copies_notes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["config", "notes.rewriteRef", "refs/notes/*"])?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["notes", "add", "-m", "Reviewed-by: Foo"])?;
+    git.run(&["notes", "--ref", "other", "add", "-m", "Tested"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--in-memory",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4838e49b create test3.txt
+        branchless: processing 2 updates: ref refs/notes/commits, ref refs/notes/other
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%h %s%n%N", "--notes=*", "-1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        4838e49 create test3.txt
+        Reviewed-by: Foo
+        Tested
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_refuses_octopus_merge() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test4", 4)?;
+
+    // Commit the octopus merge by hand, since the `octopus` merge strategy
+    // may not be available.
+    std::fs::write(
+        git.repo_path.join(".git").join("MERGE_HEAD"),
+        format!("{}\n{}\n", test2_oid, test3_oid),
+    )?;
+    git.run(&["commit", "-m", "octopus"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "-s", &test2_oid.to_string(), "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This operation failed because it would rebase an octopus merge commit with 3 parents:
+        13a6bf93 octopus
+        Merge commits with more than two parents can't be rebased yet.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 create initial.txt
+        |\
+        | o fe65c1fe create test2.txt
+        | |
+        | @ 13a6bf93 octopus
+        |\
+        | o 98b9119d create test3.txt
+        | |
+        | @ 13a6bf93 octopus
+        |\
+        | o 8f7aef57 create test4.txt
+        | |
+        | @ 13a6bf93 octopus
+        |
+        O 62fc20d2 (master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_keep() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--keep",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4838e49b create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d2 create test1.txt
+        |\
+        | o 4838e49b create test3.txt
+        |
+        O 96d1c37a (master) create test2.txt
+        |
+        @ 70deb1e2 (foo) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "plugin-api", "events"])?;
+        let stdout: String = stdout
+            .lines()
+            .filter(|line| line.contains("\"type\"") || line.contains("commit_oid"))
+            .map(|line| format!("{}\n", line.trim()))
+            .collect();
+        insta::assert_snapshot!(stdout, @r###"
+        "type": "ref-update"
+        "type": "ref-update"
+        "commit_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+        "type": "commit"
+        "type": "ref-update"
+        "type": "ref-update"
+        "commit_oid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+        "type": "commit"
+        "type": "ref-update"
+        "type": "ref-update"
+        "type": "ref-update"
+        "commit_oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+        "type": "commit"
+        "type": "ref-update"
+        "new_commit_oid": "4838e49b08954becdd17c0900c1179c2c654c627",
+        "old_commit_oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+        "type": "copy"
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &[
+                "move",
+                "--keep",
+                "--on-disk",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The argument '--on-disk' cannot be used with '--keep'
+
+        USAGE:
+            git-branchless move --dest <dest> --on-disk --keep --source <source>
+
+        For more information try --help
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_autostash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.write_file("test1", "uncommitted changes\n")?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "--on-disk",
+            "--autostash",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> stash push --quiet --message branchless: autostash
+        Stashed uncommitted changes.
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        branchless: running command: <git-executable> stash pop --quiet --index stash@{0}
+        Restored uncommitted changes.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["diff", "--name-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d2 create test1.txt
+        |\
+        | @ 4838e49b create test3.txt
+        |
+        O 96d1c37a (master) create test2.txt
         "###);
     }
 
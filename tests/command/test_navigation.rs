@@ -1,4 +1,5 @@
-use branchless::testing::{make_git, GitRunOptions};
+use branchless::core::eventlog::EventLogDb;
+use branchless::testing::{make_git, Git, GitRunOptions};
 
 #[test]
 fn test_prev() -> eyre::Result<()> {
@@ -25,15 +26,67 @@ fn test_prev() -> eyre::Result<()> {
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @"branchless: running command: <git-executable> checkout HEAD^
-");
-        insta::assert_snapshot!(stderr, @"error: pathspec 'HEAD^' did not match any file(s) known to git
+        insta::assert_snapshot!(stdout, @"");
+        insta::assert_snapshot!(stderr, @"Commit not found: HEAD^
 ");
     }
 
     Ok(())
 }
 
+#[test]
+fn test_navigation_skip_hidden() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&["checkout", "HEAD~"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["next", "--print-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ae94dc2a748bc0965c88fcf3edac2e30074ff7e2
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["next", "--print-only", "--include-hidden"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found multiple possible next commits to go to after traversing 0 children:
+          - ae94dc2a amended test1 (oldest)
+          - 62fc20d2 create test1.txt (newest)
+        (Pass --oldest (-o) or --newest (-n) to select between ambiguous next commits)
+        "###);
+    }
+
+    git.run(&["checkout", "96d1c37a3d4363611c49f7e52186e189a04c531f"])?;
+    {
+        let (stdout, _stderr) = git.run(&["prev", "--print-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ae94dc2a748bc0965c88fcf3edac2e30074ff7e2
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["prev", "--print-only", "--include-hidden"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_prev_multiple() -> eyre::Result<()> {
     let git = make_git()?;
@@ -47,7 +100,7 @@ fn test_prev_multiple() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         branchless: running command: <git-executable> checkout HEAD~2
         @ f777ecc9 create initial.txt
-        :
+        : (1 commit)
         O 96d1c37a (master) create test2.txt
         "###);
     }
@@ -144,6 +197,48 @@ fn test_next_ambiguous() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_next_towards_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    git.run(&["config", "branchless.next.towards", "newest"])?;
+    {
+        let (stdout, _stderr) = git.run(&["next"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout fe65c1fe15584744e649b2c79d4cf9b0d878f92e
+        O f777ecc9 (master) create initial.txt
+        |\
+        | o 62fc20d2 create test1.txt
+        |
+        @ fe65c1fe create test2.txt
+        "###);
+    }
+
+    // Flags passed on the command-line take precedence over the config.
+    git.run(&["checkout", "master"])?;
+    {
+        let (stdout, _stderr) = git.run(&["next", "--oldest"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        O f777ecc9 (master) create initial.txt
+        |\
+        | @ 62fc20d2 create test1.txt
+        |
+        o fe65c1fe create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_next_on_master() -> eyre::Result<()> {
     let git = make_git()?;
@@ -195,3 +290,297 @@ fn test_next_on_master2() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_navigation_print_only() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["next", "--print-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37a3d4363611c49f7e52186e189a04c531f
+        "###);
+        let (stdout, _stderr) = git.run(&["next", "2", "--quiet"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        "###);
+        let (stdout, _stderr) = git.run(&["prev", "--print-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        f777ecc9b0db5ed372b2615695191a8a17f79f24
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert_eq!(repo.get_head_info()?.get_branch_name(), Some("master"));
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["prev", "5", "--print-only"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"");
+        insta::assert_snapshot!(stderr, @r###"
+        Commit not found: HEAD~5
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_goto() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "feature"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        // The only branch pointing to the commit is checked out.
+        let (stdout, _stderr) = git.run(&["branchless", "goto", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout feature --
+        O f777ecc9 (master) create initial.txt
+        |\
+        | @ 62fc20d2 (feature) create test1.txt
+        |
+        o fe65c1fe create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "goto", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout master --
+        @ f777ecc9 (master) create initial.txt
+        |\
+        | o 62fc20d2 (feature) create test1.txt
+        |
+        o fe65c1fe create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "goto", "nonexistent"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Commit not found: nonexistent
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let events = event_log_db.get_events()?;
+        let last_event = events.last().unwrap();
+        insta::assert_debug_snapshot!(event_log_db.get_transaction_message(last_event.get_event_tx_id())?, @r###"
+        Some(
+            "goto master",
+        )
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_back_forward() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "back"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout foo --
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "back"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout master --
+        :
+        @ 62fc20d2 (master) create test1.txt
+        |
+        o 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "forward", "2"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout master --
+        :
+        @ 62fc20d2 (master) create test1.txt
+        |
+        o 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "forward"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No later checkout to go forward to.
+        "###);
+    }
+
+    // A new checkout discards the later entries in the history.
+    git.run(&["branchless", "back"])?;
+    git.run(&["checkout", "HEAD^"])?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "forward"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No later checkout to go forward to.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "back"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout foo --
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_back_forward_worktree() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    let worktree_dir = tempfile::tempdir()?;
+    let worktree_path = worktree_dir.path().join("test-worktree");
+    git.run(&[
+        "worktree",
+        "add",
+        "--detach",
+        worktree_path.to_str().unwrap(),
+        &test2_oid.to_string(),
+    ])?;
+    let worktree_git = Git::new(worktree_path, git.get_git_run_info());
+
+    {
+        // `master` is checked out in the main worktree, so Git wouldn't let it
+        // be checked out here too.
+        let (stdout, _stderr) =
+            worktree_git.run(&["branchless", "goto", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --
+        :
+        @ 62fc20d2 (master) create test1.txt
+        |
+        o 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = worktree_git.run(&["branchless", "back"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout foo --
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = worktree_git.run(&["branchless", "forward"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --
+        :
+        @ 62fc20d2 (master) create test1.txt
+        |
+        o 96d1c37a (foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_rebase_in_progress() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "branch1", "master"])?;
+    git.commit_file_with_contents("test", 1, "contents 1\n")?;
+    git.run(&["checkout", "-b", "branch2", "master"])?;
+    git.commit_file_with_contents("test", 2, "contents 2\n")?;
+
+    // Should produce a conflict.
+    git.run_with_options(
+        &["rebase", "branch1"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    for args in [&["prev"][..], &["next"], &["branchless", "goto", "master"]].iter() {
+        let (stdout, _stderr) = git.run_with_options(
+            args,
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        rebase in progress: 1/1, resolve and run git rebase --continue
+        Finish or abort it before checking out another commit.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use branchless::testing::{make_git, Git, GitRunOptions};
+
+fn write_plugin(dir: &Path, name: &str, contents: &str) -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join(format!("git-branchless-{}", name));
+    std::fs::write(&path, contents)?;
+    let mut permissions = std::fs::metadata(&path)?.permissions();
+    permissions.set_mode(0o755);
+    std::fs::set_permissions(&path, permissions)?;
+    Ok(())
+}
+
+fn make_plugin_options(git: &Git, plugin_dir: &Path, expected_exit_code: i32) -> GitRunOptions {
+    let path = std::env::join_paths(
+        std::iter::once(plugin_dir.to_path_buf())
+            .chain(std::env::split_paths(&git.get_path_for_env())),
+    )
+    .expect("joining paths");
+    GitRunOptions {
+        expected_exit_code,
+        env: vec![("PATH".into(), path.to_string_lossy().into_owned())],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_plugin_run() -> eyre::Result<()> {
+    let git = make_git()?;
+    let plugin_dir = tempfile::tempdir()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    write_plugin(
+        plugin_dir.path(),
+        "hello",
+        r#"#!/bin/sh
+echo "args: $*"
+echo "api version: $BRANCHLESS_PLUGIN_API_VERSION"
+echo "main branch: $BRANCHLESS_MAIN_BRANCH"
+test -f "$BRANCHLESS_DB_PATH" && echo "db exists"
+test -n "$BRANCHLESS_TRANSACTION_ID" && echo "in transaction"
+exit 3
+"#,
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "hello", "world", "--flag"],
+            &make_plugin_options(&git, plugin_dir.path(), 3),
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        args: world --flag
+        api version: 1
+        main branch: master
+        db exists
+        in transaction
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "goodbye"],
+            &make_plugin_options(&git, plugin_dir.path(), 1),
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Unknown subcommand: goodbye. (No `git-branchless-goodbye` executable was found on the PATH.)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_plugin_api() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "plugin-api", "graph"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "api_version": 1,
+          "commits": [
+            {
+              "branches": [
+                "refs/heads/foo"
+              ],
+              "child_oids": [],
+              "is_main": false,
+              "is_visible": true,
+              "oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "parent_oids": [
+                "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+              ],
+              "summary": "create test1.txt"
+            },
+            {
+              "branches": [
+                "refs/heads/master"
+              ],
+              "child_oids": [
+                "62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+              ],
+              "is_main": true,
+              "is_visible": true,
+              "oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "parent_oids": [],
+              "summary": "create initial.txt"
+            }
+          ],
+          "head_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+          "main_branch_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+        }
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "plugin-api", "events"])?;
+        // Event timestamps are recorded using the actual time, so remove them.
+        let mut output: serde_json::Value = serde_json::from_str(&stdout)?;
+        for event in output["events"].as_array_mut().unwrap() {
+            event.as_object_mut().unwrap().remove("timestamp");
+        }
+        let output = serde_json::to_string_pretty(&output)?;
+        insta::assert_snapshot!(output, @r###"
+        {
+          "api_version": 1,
+          "events": [
+            {
+              "event_tx_id": "1",
+              "message": null,
+              "new_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "old_oid": "0000000000000000000000000000000000000000",
+              "ref_name": "HEAD",
+              "type": "ref-update"
+            },
+            {
+              "event_tx_id": "2",
+              "message": null,
+              "new_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "old_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "ref_name": "HEAD",
+              "type": "ref-update"
+            },
+            {
+              "event_tx_id": "3",
+              "message": null,
+              "new_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "old_oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "ref_name": "HEAD",
+              "type": "ref-update"
+            },
+            {
+              "commit_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "event_tx_id": "4",
+              "type": "commit"
+            },
+            {
+              "event_tx_id": "5",
+              "message": null,
+              "new_oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "old_oid": "0000000000000000000000000000000000000000",
+              "ref_name": "refs/heads/foo",
+              "type": "ref-update"
+            }
+          ]
+        }
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_prune() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["branch", "foo", "62fc20d2"])?;
+    git.run(&["hide", "62fc20d2", "96d1c37a"])?;
+    git.run(&["branchless", "gc"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "prune", "--older-than", "1w"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No commits to prune.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "prune", "--older-than", "0h", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Not deleting 1 commit, since they're still needed:
+          62fc20d2 create test1.txt (reachable from refs/heads/foo)
+        Would delete 1 commit:
+          96d1c37a create test2.txt
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("96d1c37a")?.is_some());
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "prune", "--older-than", "0h"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Not deleting 1 commit, since they're still needed:
+          62fc20d2 create test1.txt (reachable from refs/heads/foo)
+        Deleting 1 commit:
+          96d1c37a create test2.txt
+        branchless: running command: <git-executable> reflog expire --expire-unreachable=0.seconds.ago --all
+        branchless: running command: <git-executable> gc --prune=0.seconds.ago
+        Deleted 1 commit.
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("96d1c37a")?.is_none());
+        // Still reachable from the `foo` branch.
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_some());
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["for-each-ref", "--format=%(refname)", "refs/branchless/"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        refs/branchless/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        refs/branchless/event-log
+        refs/branchless/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_older_than_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["hide", "62fc20d2"])?;
+
+    git.run(&["config", "branchless.prune.olderThan", "0h"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "prune", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would delete 1 commit:
+          62fc20d2 create test1.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.prune.olderThan", "soon"])?;
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "prune", "--dry-run"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("Invalid value for branchless.prune.olderThan"));
+    }
+
+    git.run(&["config", "branchless.prune.olderThan", "99999999999999999w"])?;
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "prune", "--dry-run"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("Invalid value for branchless.prune.olderThan"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_keeps_needed_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["hide", "62fc20d2"])?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.run(&["commit", "--amend", "-m", "amended test3"])?;
+
+    let worktree_dir = tempfile::tempdir()?;
+    let worktree_path = worktree_dir.path().join("test-worktree");
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&[
+        "worktree",
+        "add",
+        "--detach",
+        worktree_path.to_str().unwrap(),
+        &test4_oid.to_string(),
+    ])?;
+    git.run(&["hide", &test4_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "prune", "--older-than", "0h", "--dry-run"])?;
+        let stdout = stdout.replace(&*worktree_path.to_string_lossy(), "<worktree-path>");
+        insta::assert_snapshot!(stdout, @r###"
+        Not deleting 3 commits, since they're still needed:
+          62fc20d2 create test1.txt (ancestor of visible commit 96d1c37a create test2.txt)
+          98b9119d create test3.txt (rewritten as visible commit dec52eae amended test3)
+          8f7aef57 create test4.txt (reachable from HEAD in worktree "<worktree-path>")
+        No commits to prune.
+        "###);
+    }
+
+    Ok(())
+}
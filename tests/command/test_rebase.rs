@@ -0,0 +1,75 @@
+use branchless::testing::make_git;
+
+#[test]
+fn test_rebase_with_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "rebase", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> rebase master
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 98b9119d (master) create test3.txt
+        |
+        o 64e0ba65 create test1.txt
+        |
+        @ 5b8b988e (foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rebase_without_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "foo"])?;
+    git.run(&["branchless", "init", "--uninstall"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "rebase", "--quiet", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> rebase --quiet master
+        branchless: recorded 4 events which the hooks missed
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 98b9119d (master) create test3.txt
+        |
+        o 64e0ba65 create test1.txt
+        |
+        @ 5b8b988e (foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
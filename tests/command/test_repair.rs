@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use branchless::testing::{make_git, GitRunOptions};
+
+fn corrupt_files(dir: &Path) -> eyre::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            corrupt_files(&path)?;
+        } else {
+            std::fs::write(&path, "garbage")?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_repair_corrupted_dag() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["smartlog"])?;
+
+    corrupt_files(&git.repo_path.join(".git").join("branchless").join("dag"))?;
+    git.run_with_options(
+        &["smartlog"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "repair"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Rebuilding merge-base cache...
+        Repair complete. The event log was preserved (4 events).
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
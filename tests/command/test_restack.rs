@@ -58,7 +58,6 @@ fn test_restack_amended_commit() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run(&["restack"])?;
         let stdout = remove_rebase_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -101,7 +100,6 @@ fn test_restack_consecutive_rewrites() -> eyre::Result<()> {
         let stdout = remove_rebase_lines(stdout);
 
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -178,7 +176,6 @@ fn test_amended_initial_commit() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run(&["restack"])?;
         let stdout = remove_rebase_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -214,7 +211,6 @@ fn test_restack_amended_master() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run(&["restack"])?;
         let stdout = remove_rebase_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -255,7 +251,6 @@ fn test_restack_aborts_during_rebase_conflict() -> eyre::Result<()> {
         let stdout = remove_rebase_lines(stdout);
 
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         CONFLICT (add/add): Merge conflict in test2.txt
@@ -287,7 +282,6 @@ fn test_restack_multiple_amended() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["restack"])?;
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -371,7 +365,6 @@ fn test_restack_single_of_many_commits() -> eyre::Result<()> {
         branchless: processing checkout
         "###);
         insta::assert_snapshot!(stdout, @r###"
-        branchless: running command: <git-executable> diff --quiet
         Calling Git for on-disk rebase...
         branchless: running command: <git-executable> rebase --continue
         Finished restacking commits.
@@ -394,3 +387,194 @@ fn test_restack_single_of_many_commits() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restack_verbosity() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--quiet", "restack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 024c35ce amend test1.txt
+        |
+        o 8cd7de68 create test2.txt
+        "###);
+    }
+
+    git.run(&["commit", "--amend", "-m", "amend test1.txt again"])?;
+    {
+        let (_stdout, stderr) = git.run(&["branchless", "--verbose", "restack"])?;
+        assert!(
+            stderr.contains("branchless: Examining local history"),
+            "{:?}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_merge_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["merge", &test2_oid.to_string()])?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", "HEAD~3"])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |\
+        | @ 024c35ce amend test1.txt
+        |
+        x 62fc20d2 (rewritten as 024c35ce) create test1.txt
+        |\
+        | o 96d1c37a create test2.txt
+        | |
+        o | 4838e49b create test3.txt
+        |/
+        o a4dd9b08 Merge commit '96d1c37a3d4363611c49f7e52186e189a04c531f' into HEAD
+        |
+        o b5391973 create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["restack", "--debug-dump-rebase-plan"])?;
+        let stdout = remove_rebase_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Rebase plan: Some(
+            RebasePlan {
+                first_dest_oid: NonZeroOid(024c35ce32dae6b12e981963465ee8a62b7eff9b),
+                commands: [
+                    RegisterExtraPostRewriteHook,
+                    Reset {
+                        target: Oid(
+                            NonZeroOid(024c35ce32dae6b12e981963465ee8a62b7eff9b),
+                        ),
+                    },
+                    Pick {
+                        commit_oid: NonZeroOid(4838e49b08954becdd17c0900c1179c2c654c627),
+                    },
+                    DetectEmptyCommit {
+                        commit_oid: NonZeroOid(4838e49b08954becdd17c0900c1179c2c654c627),
+                    },
+                    CreateLabel {
+                        label_name: "merge-parent-4",
+                    },
+                    Reset {
+                        target: Oid(
+                            NonZeroOid(024c35ce32dae6b12e981963465ee8a62b7eff9b),
+                        ),
+                    },
+                    Pick {
+                        commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+                    },
+                    DetectEmptyCommit {
+                        commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+                    },
+                    CreateLabel {
+                        label_name: "merge-parent-8",
+                    },
+                    Reset {
+                        target: Label(
+                            "merge-parent-4",
+                        ),
+                    },
+                    Merge {
+                        commit_oid: NonZeroOid(a4dd9b0845621233ad773bf6f5a113e58863a489),
+                        commits_to_merge: [
+                            Label(
+                                "merge-parent-8",
+                            ),
+                        ],
+                    },
+                    Pick {
+                        commit_oid: NonZeroOid(b5391973166dcd56cb8116644d0d2a30d3ba16bc),
+                    },
+                    DetectEmptyCommit {
+                        commit_oid: NonZeroOid(b5391973166dcd56cb8116644d0d2a30d3ba16bc),
+                    },
+                ],
+            },
+        )
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        Finished restacking commits.
+        No abandoned branches to restack.
+        branchless: running command: <git-executable> checkout 024c35ce32dae6b12e981963465ee8a62b7eff9b
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 024c35ce amend test1.txt
+        |\
+        | o 8cd7de68 create test2.txt
+        | |
+        o | 21f9c32b create test3.txt
+        |/
+        o c53d2e1a Merge commit '96d1c37a3d4363611c49f7e52186e189a04c531f' into HEAD
+        |
+        o c8ec5938 create test4.txt
+        "###);
+    }
+
+    {
+        // The merge commit should still have the same first parent.
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "-1", "c53d2e1a^1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_invalid_notify_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "branchless.notify.enabled", "true"])?;
+    git.run(&["config", "branchless.notify.minDuration", "soon"])?;
+
+    {
+        // Notifications are best-effort, so invalid settings don't cause the
+        // command to fail.
+        let (stdout, _stderr) = git.run(&["restack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        :
+        @ 62fc20d2 (master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_restore_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "restore-branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No deleted branches found.
+        "###);
+    }
+
+    git.run(&["branch", "-D", "foo"])?;
+    git.run(&["branch", "-D", "bar"])?;
+    git.run(&["hide", "96d1c37a"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "restore-branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Deleted branches:
+          bar at 96d1c37a create test2.txt
+          foo at 62fc20d2 create test1.txt
+        To restore one of them, run: git branchless restore-branch <name>
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "restore-branch", "bar"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> branch bar 96d1c37a3d4363611c49f7e52186e189a04c531f
+        Restored branch bar at 96d1c37a create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        o 96d1c37a (bar) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "restore-branch", "bar"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Branch bar already exists.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "restore-branch", "baz"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        No deleted branch named baz was found in the event log.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
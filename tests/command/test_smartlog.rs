@@ -1,3 +1,5 @@
+use std::os::unix::fs::PermissionsExt;
+
 use branchless::git::GitRunInfo;
 use branchless::testing::{get_path_to_git, make_git, Git, GitInitOptions, GitRunOptions};
 
@@ -129,18 +131,16 @@ fn test_merge_commit() -> eyre::Result<()> {
         // Rendering here is arbitrary and open to change.
         let (stdout, _stderr) = git.run(&["smartlog"])?;
         insta::assert_snapshot!(stdout, @r###"
-            O f777ecc9 (master) create initial.txt
-            |\
-            | o 62fc20d2 (test1) create test1.txt
-            | |
-            | @ fa4e4e1a (test2and3) Merge branch 'test1' into test2and3
-            |
-            o fe65c1fe create test2.txt
-            |
-            o 02067177 create test3.txt
-            |
-            @ fa4e4e1a (test2and3) Merge branch 'test1' into test2and3
-            "###);
+        O f777ecc9 (master) create initial.txt
+        |\
+        | o 62fc20d2 (test1) create test1.txt
+        | |
+        o | fe65c1fe create test2.txt
+        | |
+        o | 02067177 create test3.txt
+        |/
+        @ fa4e4e1a (test2and3) Merge branch 'test1' into test2and3
+        "###);
     }
 
     Ok(())
@@ -181,6 +181,43 @@ fn test_rebase_conflict() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_smartlog_rebase_in_progress() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "branch1", "master"])?;
+    git.commit_file_with_contents("test", 1, "contents 1\n")?;
+    git.run(&["checkout", "-b", "branch2", "master"])?;
+    git.commit_file_with_contents("test", 2, "contents 2\n")?;
+    git.commit_file("test3", 3)?;
+
+    // Should produce a conflict.
+    git.run_with_options(
+        &["rebase", "branch1"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        rebase in progress: 1/2, resolve and run git rebase --continue
+        O f777ecc9 (master) create initial.txt
+        |\
+        | @ 88646b56 (branch1) create test.txt
+        |
+        o 9cc7268d create test.txt
+        |
+        o f31c8456 (branch2) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_non_adjacent_commits() -> eyre::Result<()> {
     let git = make_git()?;
@@ -197,14 +234,14 @@ fn test_non_adjacent_commits() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
         insta::assert_snapshot!(stdout, @r###"
-            O f777ecc9 create initial.txt
-            |\
-            : o 62fc20d2 create test1.txt
-            :
-            O 02067177 (master) create test3.txt
-            |
-            @ 8e62740b create test4.txt
-            "###);
+        O f777ecc9 create initial.txt
+        |\
+        : o 62fc20d2 create test1.txt
+        : (1 commit)
+        O 02067177 (master) create test3.txt
+        |
+        @ 8e62740b create test4.txt
+        "###);
     }
 
     Ok(())
@@ -227,16 +264,16 @@ fn test_non_adjacent_commits2() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
         insta::assert_snapshot!(stdout, @r###"
-            O f777ecc9 create initial.txt
-            |\
-            : o 62fc20d2 create test1.txt
-            : |
-            : o 96d1c37a create test2.txt
-            :
-            O 2b633ed7 (master) create test4.txt
-            |
-            @ 13932989 create test5.txt
-            "###);
+        O f777ecc9 create initial.txt
+        |\
+        : o 62fc20d2 create test1.txt
+        : |
+        : o 96d1c37a create test2.txt
+        : (1 commit)
+        O 2b633ed7 (master) create test4.txt
+        |
+        @ 13932989 create test5.txt
+        "###);
     }
 
     Ok(())
@@ -261,17 +298,17 @@ fn test_non_adjacent_commits3() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
         insta::assert_snapshot!(stdout, @r###"
-            :
-            O 62fc20d2 create test1.txt
-            |\
-            | o 96d1c37a create test2.txt
-            |
-            O 4838e49b create test3.txt
-            |\
-            : o a2482074 create test4.txt
-            :
-            @ 500c9b3e (master) create test6.txt
-            "###);
+        :
+        O 62fc20d2 create test1.txt
+        |\
+        | o 96d1c37a create test2.txt
+        |
+        O 4838e49b create test3.txt
+        |\
+        : o a2482074 create test4.txt
+        : (1 commit)
+        @ 500c9b3e (master) create test6.txt
+        "###);
     }
 
     Ok(())
@@ -389,3 +426,771 @@ fn test_show_rewritten_commit_hash() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_smartlog_verbose() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test-branch", "master"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "branchless",
+        "wrap",
+        "--",
+        "commit",
+        "--amend",
+        "-m",
+        "amended test2",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "-v"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 (v1 of I62fc20d2) (tx 4: post-commit) create test1.txt
+        |
+        @ cb8137ad (test-branch) (v2 of I96d1c37a) (tx 7: git commit --amend -m amended test2) amended test2
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_format() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test-branch", "master"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--format", "%s [%h] %d 100%%"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O create initial.txt [f777ecc9] (master) 100%
+        |
+        o create test1.txt [62fc20d2] 100%
+        |
+        @ create test2.txt [96d1c37a] (test-branch) 100%
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "smartlog",
+            "--format",
+            "%h %(provider:change-id) %(provider:phabricator) %s",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 create initial.txt
+        |
+        o 62fc20d2 (v1 of I62fc20d2) create test1.txt
+        |
+        @ 96d1c37a (v1 of I96d1c37a) create test2.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["smartlog", "--format", "%h %(provider:foo)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("Unknown provider in template: \"foo\""),
+            "{}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_commit_descriptors() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test-branch", "master"])?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors",
+        "message, branches,change-id,oid",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O create initial.txt (master) f777ecc9
+        |
+        @ create test1.txt (test-branch) (v1 of I62fc20d2) 62fc20d2
+        "###);
+    }
+
+    git.run(&["config", "branchless.commitDescriptors", "oid,foo"])?;
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["smartlog"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("Unknown provider in template: \"foo\""),
+            "{}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_truncate_lines() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test-branch", "master"])?;
+    git.write_file("test1", "contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&[
+        "commit",
+        "-m",
+        "This commit has a very long message which doesn't fit",
+    ])?;
+
+    let options = GitRunOptions {
+        env: vec![("COLUMNS".to_string(), "40".to_string())],
+        ..Default::default()
+    };
+    {
+        let (stdout, _stderr) = git.run_with_options(&["smartlog"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 3f4a77b0 (test-branch) This commit ...
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.truncateLines", "false"])?;
+    {
+        let (stdout, _stderr) = git.run_with_options(&["smartlog"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 3f4a77b0 (test-branch) This commit has a very long message which doesn't fit
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_truncate_wide_characters() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test-branch", "master"])?;
+    git.write_file("test1", "contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "バグを修正する 🎉 and more"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["smartlog"],
+            &GitRunOptions {
+                env: vec![("COLUMNS".to_string(), "35".to_string())],
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initi...
+        |
+        @ f9f8bd96 (test-branch) バグを...
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_ci_status() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let command_path = git.repo_path.join(".git").join("fake-ci-status");
+    std::fs::write(
+        &command_path,
+        r#"#!/bin/sh
+dir=$(dirname "$0")
+echo "$1" >>"$dir/ci-status.log"
+case "$1" in
+f777ecc9*) echo success ;;
+62fc20d2*) echo failure ;;
+96d1c37a*) echo pending ;;
+esac
+"#,
+    )?;
+    std::fs::set_permissions(&command_path, std::fs::Permissions::from_mode(0o755))?;
+    git.run(&["config", "branchless.commitMetadata.ciStatus", "true"])?;
+    git.run(&[
+        "config",
+        "branchless.ciStatus.command",
+        command_path.to_str().unwrap(),
+    ])?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "bar"])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) + create initial.txt
+        |
+        o 62fc20d2 (foo) ! create test1.txt
+        |
+        o 96d1c37a (bar) * create test2.txt
+        |
+        @ 70deb1e2 create test3.txt
+        "###);
+    }
+
+    // The statuses should be cached, so the command shouldn't be run again.
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) + create initial.txt
+        |
+        o 62fc20d2 (foo) ! create test1.txt
+        |
+        o 96d1c37a (bar) * create test2.txt
+        |
+        @ 70deb1e2 create test3.txt
+        "###);
+        let log = std::fs::read_to_string(git.repo_path.join(".git").join("ci-status.log"))?;
+        assert_eq!(log.lines().count(), 3);
+    }
+
+    git.run(&["config", "branchless.ciStatus.cacheTtlSeconds", "0"])?;
+    {
+        git.run(&["smartlog"])?;
+        let log = std::fs::read_to_string(git.repo_path.join(".git").join("ci-status.log"))?;
+        assert_eq!(log.lines().count(), 6);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_hyperlinks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "branchless.smartlog.commitUrlTemplate",
+        "https://example.com/commit/{oid}",
+    ])?;
+    git.detach_head()?;
+    git.run(&[
+        "commit",
+        "--allow-empty",
+        "-m",
+        "foo\n\nDifferential Revision: https://phabricator.example.com/D123",
+    ])?;
+
+    // Hyperlinks aren't rendered by default when the output isn't a terminal.
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 8e338293 D123 foo
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.hyperlinks", "true"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        let stdout = stdout.replace('\x1b', "<ESC>");
+        insta::assert_snapshot!(stdout, @r###"
+        O <ESC>]8;;https://example.com/commit/f777ecc9b0db5ed372b2615695191a8a17f79f24<ESC>\f777ecc9<ESC>]8;;<ESC>\ (master) create initial.txt
+        |
+        @ <ESC>]8;;https://example.com/commit/8e338293c6d7474c66bc4ce93e1959c739798497<ESC>\8e338293<ESC>]8;;<ESC>\ <ESC>]8;;https://phabricator.example.com/D123<ESC>\D123<ESC>]8;;<ESC>\ foo
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_public_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/release-1",
+        &test2_oid.to_string(),
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        o 96d1c37a create test2.txt
+        |
+        @ 70deb1e2 create test3.txt
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.core.publicBranches",
+        "refs/remotes/origin/release-*",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        : (1 commit)
+        O 96d1c37a create test2.txt
+        |
+        @ 70deb1e2 create test3.txt
+        "###);
+    }
+
+    // Only the draft commit on top of the public branch should be moved.
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["move", "--dest", "master"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 98b9119d create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_all_users() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    for (name, user, time) in [("test1", "alice", 1), ("test2", "bob", 2)] {
+        let options = GitRunOptions {
+            time,
+            env: vec![("USER".to_string(), user.to_string())],
+            ..Default::default()
+        };
+        git.run_with_options(&["checkout", "--detach", "master"], &options)?;
+        std::fs::write(git.repo_path.join(format!("{}.txt", name)), name)?;
+        git.run_with_options(&["add", "."], &options)?;
+        git.run_with_options(&["commit", "-m", &format!("create {}.txt", name)], &options)?;
+    }
+    git.run(&["checkout", "master"])?;
+
+    let alice_options = GitRunOptions {
+        env: vec![("USER".to_string(), "alice".to_string())],
+        ..Default::default()
+    };
+    {
+        let (stdout, _stderr) = git.run_with_options(&["smartlog"], &alice_options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 145e0f5e create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run_with_options(&["smartlog", "--all-users"], &alice_options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |\
+        | o 145e0f5e create test1.txt
+        |
+        o b0b7889d create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |\
+        | o 145e0f5e create test1.txt
+        |
+        o b0b7889d create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_html() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo<bar>"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--html"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        <!DOCTYPE html>
+        <html>
+        <head>
+        <meta charset="utf-8">
+        <title>git smartlog</title>
+        </head>
+        <body>
+        <pre style="color: #e5e5e5; background-color: #1e1e1e; padding: 1em; font-family: monospace; line-height: 1.2;">:
+        <span style="font-weight: bold">@ </span><span style="color: #e5e510; font-weight: bold">62fc20d2</span><span style="font-weight: bold"> </span><span style="color: #55ff55; font-weight: bold">(</span><span style="color: #55ffff; font-weight: bold">foo&lt;bar&gt;</span><span style="color: #55ff55; font-weight: bold">, master)</span><span style="font-weight: bold"> create test1.txt</span>
+        </pre>
+        </body>
+        </html>
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_default_revset() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.write_file("test2", "test2 contents\n")?;
+    git.run(&["add", "test2.txt"])?;
+    git.run(&[
+        "commit",
+        "-m",
+        "create test2.txt",
+        "--author",
+        "Someone Else <someone@example.com>",
+    ])?;
+    git.run(&["checkout", "master"])?;
+    git.run(&[
+        "config",
+        "branchless.smartlog.defaultRevset",
+        "draft() & mine()",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--all"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |\
+        | o fc08443e create test2.txt
+        |
+        o 62fc20d2 create test1.txt
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.smartlog.defaultRevset",
+        "author(someone) | main()",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o fc08443e create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_other_repo() -> eyre::Result<()> {
+    let git = make_git()?;
+    let other_git = make_git()?;
+
+    git.init_repo()?;
+    other_git.init_repo()?;
+    other_git.detach_head()?;
+    other_git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "-C",
+            other_git.repo_path.to_str().unwrap(),
+            "smartlog",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 62fc20d2 create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "smartlog"],
+            &GitRunOptions {
+                env: vec![(
+                    "GIT_DIR".to_string(),
+                    other_git
+                        .repo_path
+                        .join(".git")
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                )],
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_color() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "smartlog"])?;
+        assert!(!stdout.contains('\u{1b}'), "{:?}", stdout);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+    }
+
+    git.run(&["config", "branchless.color", "always"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "smartlog"])?;
+        assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "never", "smartlog"])?;
+        assert!(!stdout.contains('\u{1b}'), "{:?}", stdout);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_color_env() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let run_with_env = |args: &[&str], env: &[(&str, &str)]| -> eyre::Result<String> {
+        let (stdout, _stderr) = git.run_with_options(
+            args,
+            &GitRunOptions {
+                env: env
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+        Ok(stdout)
+    };
+
+    let stdout = run_with_env(&["branchless", "smartlog"], &[("CLICOLOR_FORCE", "1")])?;
+    assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    let stdout = run_with_env(
+        &["branchless", "smartlog"],
+        &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")],
+    )?;
+    assert!(!stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    // The command-line flag and the configuration take precedence over the
+    // environment.
+    let stdout = run_with_env(
+        &["branchless", "--color", "always", "smartlog"],
+        &[("NO_COLOR", "1")],
+    )?;
+    assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    git.run(&["config", "branchless.color", "never"])?;
+    let stdout = run_with_env(&["branchless", "smartlog"], &[("CLICOLOR_FORCE", "1")])?;
+    assert!(!stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_palette() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&["config", "branchless.color.oid", "blue"])?;
+    git.run(&["config", "branchless.color.emphasis", "ul"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        insta::assert_debug_snapshot!(stdout, @r###"
+        ":\n\u{1b}[4m@\u{1b}[0m\u{1b}[4m \u{1b}[0m\u{1b}[34m\u{1b}[4m62fc20d2\u{1b}[0m\u{1b}[4m \u{1b}[0m\u{1b}[38;5;10m\u{1b}[4m(\u{1b}[0m\u{1b}[38;5;14m\u{1b}[1m\u{1b}[4mmaster\u{1b}[0m\u{1b}[38;5;10m\u{1b}[4m)\u{1b}[0m\u{1b}[4m \u{1b}[0m\u{1b}[4mcreate test1.txt\u{1b}[0m\n"
+        "###);
+    }
+
+    git.run(&["config", "branchless.color.oid", "sparkly"])?;
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "smartlog"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("Parsing branchless.color.oid"),
+            "{}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_anonymize() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "secret-branch"])?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["commit", "--amend", "-m", "secret message"])?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--anonymize"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ 8a911026 (ref-1) message-1
+        |\
+        | o 5d1c1787 message-2
+        | |
+        | o e006d098 (ref-2) message-3
+        |
+        o 9aa04756 message-4
+        "###);
+    }
+
+    git.run(&["checkout", &test2_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.run(&[
+            "smartlog",
+            "--anonymize",
+            "--format",
+            "%h %(provider:hidden-explanation) %d %(provider:transaction) %s",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O 8a911026 (ref-1) message-1
+        |\
+        | o 5d1c1787 message-2
+        | |\
+        | | o e006d098 (ref-2) message-3
+        | |
+        | % 45f72651 (rewritten as e006d098) message-4
+        |
+        o 9aa04756 message-5
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_header() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.smartlog.header", "true"])?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        On branch master, no uncommitted changes
+        :
+        @ 62fc20d2 (master) create test1.txt
+        "###);
+    }
+
+    git.detach_head()?;
+    git.write_file("test1", "modified contents")?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Detached HEAD at 62fc20d2, with uncommitted changes
+        :
+        @ 62fc20d2 (master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--anonymize"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Detached HEAD at 5d1c1787, with uncommitted changes
+        :
+        @ 5d1c1787 (ref-1) message-1
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_status_no_abandoned_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "status"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No abandoned commits.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_status_abandoned_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "HEAD^^"])?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "status"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found 1 abandoned subtree:
+        - 2 commits and 1 branch (bar) on 62fc20d2 create test1.txt, which was rewritten as 024c35ce amend test1.txt
+          To restack: git restack 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        To restack all of them: git restack
+        "###);
+    }
+
+    {
+        git.run(&["restack"])?;
+        let (stdout, _stderr) = git.run(&["branchless", "status"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No abandoned commits.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_status_merge_in_progress() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "branch1", "master"])?;
+    git.commit_file_with_contents("test", 1, "contents 1\n")?;
+    git.run(&["checkout", "-b", "branch2", "master"])?;
+    git.commit_file_with_contents("test", 2, "contents 2\n")?;
+
+    // Should produce a conflict.
+    git.run_with_options(
+        &["merge", "branch1"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "status"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        merge in progress, resolve and run git merge --continue
+        No abandoned commits.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,428 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use branchless::testing::{make_git, Git, GitRunOptions};
+
+#[test]
+fn test_submit_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "bar"])?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        These commits have no branch, so they won't be submitted:
+          355e173b create test4.txt
+        Would submit foo (onto master): create test1.txt
+        Would submit bar (onto foo): create test2.txt
+        "###);
+    }
+
+    git.run(&["checkout", "foo"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would submit foo (onto master): create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_draft_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.submit.draft", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would submit foo (onto master, draft): create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--dry-run", "--ready"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would submit foo (onto master): create test1.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "submit", "--backend", "gerrit", "--ready"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The gerrit backend doesn't support draft reviews, so --draft and --ready can't be used with it.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_github_requires_token() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&[
+        "remote",
+        "add",
+        "origin",
+        "git@github.com:example/example.git",
+    ])?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "submit", "--backend", "github"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        No GitHub token found. Set the GITHUB_TOKEN environment variable or the branchless.github.token config option.
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "submit", "--backend", "unknown"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Unknown submit backend: unknown (expected one of: gerrit, github, phabricator)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_gerrit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["remote", "add", "origin", "."])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&[
+        "commit",
+        "--allow-empty",
+        "-m",
+        "has change ID\n\nChange-Id: I0123456789abcdef0123456789abcdef01234567",
+    ])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "submit", "--backend", "gerrit", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would push to refs/for/master:
+          62fc20d2 create test1.txt (needs Change-Id)
+          7b07fea8 has change ID
+          08d4d60e create test3.txt (needs Change-Id)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--backend", "gerrit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Added Change-Id to 2 commits.
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout 457fabbe814becc78c9d93204a5736dfce2e9420
+        branchless: running command: <git-executable> push origin 457fabbe814becc78c9d93204a5736dfce2e9420:refs/for/master
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%(trailers)", "master.."])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+        Change-Id: Ib5d78a33784e3c1289897beea0d2f9343fa9cad8
+
+        has change ID
+        Change-Id: I0123456789abcdef0123456789abcdef01234567
+
+        create test1.txt
+        Change-Id: Id755314e7a1259ab5412265bfc3c5b9c3204ffff
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "submit", "--backend", "gerrit", "--update"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped pushing to refs/for/master: unchanged since the last submission.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "-1", "refs/for/master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o d0242f70 create test1.txt
+        |
+        o 9bd02742 has change ID
+        |
+        @ 457fabbe create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_gerrit_restacks_other_descendants() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["remote", "add", "origin", "."])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--backend", "gerrit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Added Change-Id to 2 commits.
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout 5e67bbd363bb6c6124d458eeba88ab0c1953cacc
+        branchless: running command: <git-executable> push origin 5e67bbd363bb6c6124d458eeba88ab0c1953cacc:refs/for/master
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o d0242f70 create test1.txt
+        |\
+        | o c2f9ec19 create test2.txt
+        |
+        @ 5e67bbd3 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+/// Write a fake `arc` executable which responds to Conduit calls with canned
+/// responses, assigning increasing IDs to new revisions.
+fn make_fake_arc(git: &Git) -> eyre::Result<PathBuf> {
+    let arc_path = git.repo_path.join(".git").join("fake-arc");
+    std::fs::write(
+        &arc_path,
+        r#"#!/bin/sh
+dir=$(dirname "$0")
+input=$(cat)
+printf '%s %s\n' "$3" "$input" >>"$dir/conduit.log"
+case "$3" in
+differential.createrawdiff)
+    echo '{"error":null,"errorMessage":null,"response":{"id":1,"phid":"PHID-DIFF-1"}}'
+    ;;
+differential.revision.edit)
+    id=$(printf '%s' "$input" | sed -n 's/.*"objectIdentifier":"D\([0-9]*\)".*/\1/p')
+    if [ -z "$id" ]; then
+        id=$(cat "$dir/fake-arc-id" 2>/dev/null || echo 100)
+        id=$((id + 1))
+        echo "$id" >"$dir/fake-arc-id"
+    fi
+    echo '{"error":null,"errorMessage":null,"response":{"object":{"id":'"$id"',"phid":"PHID-DREV-'"$id"'"}}}'
+    ;;
+differential.query)
+    id=$(cat "$dir/fake-arc-id")
+    echo '{"error":null,"errorMessage":null,"response":[{"uri":"https://phabricator.example.com/D'"$id"'"}]}'
+    ;;
+differential.revision.search)
+    echo '{"error":null,"errorMessage":null,"response":{"data":[{"id":101,"fields":{"status":{"value":"accepted"}}}]}}'
+    ;;
+esac
+"#,
+    )?;
+    std::fs::set_permissions(&arc_path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(arc_path)
+}
+
+#[test]
+fn test_submit_phabricator() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let arc_path = make_fake_arc(&git)?;
+    git.run(&[
+        "config",
+        "branchless.phabricator.arcPath",
+        arc_path.to_str().unwrap(),
+    ])?;
+    git.run(&["config", "branchless.submit.backend", "phabricator"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create a revision for: 62fc20d2 create test1.txt
+        Would create a revision for: 96d1c37a create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created D101: 62fc20d2 create test1.txt
+        Created D102: 96d1c37a create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout d1b81b12e2e1fee3ca10f9bb698d00387cea6be8
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "master.."])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+
+        Differential Revision: https://phabricator.example.com/D102
+
+        create test1.txt
+
+        Differential Revision: https://phabricator.example.com/D101
+        "###);
+    }
+
+    git.commit_file("test3", 3)?;
+    std::fs::remove_file(git.repo_path.join(".git").join("conduit.log"))?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "submit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Updated D101: 92bae4a8 create test1.txt
+        Updated D102: d1b81b12 create test2.txt
+        Created D103: e94671e0 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 21eae818c6f177267c80ef02cb4686c4e6b80a63
+        "###);
+    }
+
+    {
+        let conduit_log = std::fs::read_to_string(git.repo_path.join(".git").join("conduit.log"))?;
+        let methods: Vec<&str> = conduit_log
+            .lines()
+            .filter_map(|line| line.split(' ').next())
+            .collect();
+        insta::assert_debug_snapshot!(methods, @r###"
+        [
+            "differential.createrawdiff",
+            "differential.revision.edit",
+            "differential.createrawdiff",
+            "differential.revision.edit",
+            "differential.createrawdiff",
+            "differential.revision.edit",
+            "differential.query",
+        ]
+        "###);
+        assert!(conduit_log.contains(r#"{"type":"parents.set","value":["PHID-DREV-102"]}"#));
+    }
+
+    git.run(&[
+        "config",
+        "branchless.commitMetadata.differentialRevisionStatus",
+        "true",
+    ])?;
+    std::fs::remove_file(git.repo_path.join(".git").join("conduit.log"))?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 92bae4a8 D101 (accepted) create test1.txt
+        |
+        o d1b81b12 D102 create test2.txt
+        |
+        @ 21eae818 D103 create test3.txt
+        "###);
+    }
+
+    {
+        let conduit_log = std::fs::read_to_string(git.repo_path.join(".git").join("conduit.log"))?;
+        insta::assert_snapshot!(conduit_log, @r###"
+        differential.revision.search {"constraints":{"ids":[101,102,103]}}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_create_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["remote", "add", "origin", "."])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "submit", "--create-branches", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create branch create-test2-txt.
+        Would submit foo (onto master): create test1.txt
+        Would submit create-test2-txt (onto foo): create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "submit",
+            "--backend",
+            "gerrit",
+            "--create-branches",
+            "--dry-run",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would create branch create-test2-txt.
+        Would push to refs/for/master:
+          62fc20d2 create test1.txt (needs Change-Id)
+          96d1c37a create test2.txt (needs Change-Id)
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_swap_head() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "swap"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 4838e49b create test3.txt
+        [2/2] Committed as: d742fb97 create test2.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+        In-memory rebase succeeded.
+        Swapped 70deb1e2 create test3.txt with 96d1c37a create test2.txt.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        @ 4838e49b create test3.txt
+        |
+        o d742fb97 (foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_swap_moves_descendants() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "swap", &test2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/4] Committed as: fe65c1fe create test2.txt
+        [2/4] Committed as: 07709435 create test1.txt
+        [3/4] Committed as: b85f7eb5 create test3.txt
+        [4/4] Committed as: faf14c9a create test4.txt
+        branchless: processing 4 rewritten commits
+        branchless: running command: <git-executable> checkout faf14c9ace5baeb28bb6a233870dc17fe0700341
+        In-memory rebase succeeded.
+        Swapped 96d1c37a create test2.txt with 62fc20d2 create test1.txt.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o fe65c1fe create test2.txt
+        |
+        o 07709435 create test1.txt
+        |\
+        | o b85f7eb5 create test3.txt
+        |
+        @ faf14c9a create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_swap_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.write_file("test1", "updated contents\n")?;
+    git.run(&["commit", "-a", "-m", "update test1.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "swap"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Cannot swap fc84bc3f update test1.txt with its parent 62fc20d2 create test1.txt, since their changes conflict in these files:
+        test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        @ fc84bc3f update test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_swap_main_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "swap"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Cannot swap 62fc20d2 create test1.txt with its parent f777ecc9 create initial.txt, since the parent does not have exactly one parent.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use branchless::commands::switch::testing::select_switch_target;
+use branchless::core::formatting::Glyphs;
+use branchless::testing::{make_git, Git};
+use branchless::tui::testing::{
+    screen_to_string, styled_screen_to_string, CursiveTestingBackend, CursiveTestingEvent,
+};
+use branchless::tui::Effects;
+
+use cursive::event::Key;
+use cursive::theme::Effect;
+use cursive::CursiveRunnable;
+
+fn run_select_switch_target(
+    git: &Git,
+    initial_filter: &str,
+    events: Vec<CursiveTestingEvent>,
+) -> eyre::Result<Option<String>> {
+    let glyphs = Glyphs::text();
+    let effects = Effects::new_suppress_for_test(glyphs);
+    let repo = git.get_repo()?;
+    let siv = CursiveRunnable::new::<Infallible, _>(move || {
+        Ok(CursiveTestingBackend::init(events.clone()))
+    });
+    select_switch_target(siv.into_runner(), &effects, &repo, initial_filter)
+}
+
+fn type_text(text: &str) -> Vec<CursiveTestingEvent> {
+    text.chars()
+        .map(|c| CursiveTestingEvent::Event(c.into()))
+        .collect()
+}
+
+#[test]
+fn test_switch_interactive_filter() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "bar"])?;
+
+    {
+        let screenshot1 = Default::default();
+        let screenshot2 = Default::default();
+        let mut events = vec![CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1))];
+        events.extend(type_text("test2"));
+        events.push(CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)));
+        events.push(CursiveTestingEvent::Event(Key::Enter.into()));
+        let target = run_select_switch_target(&git, "", events)?;
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        ┌───────────────────────────────────────────────┤ Commits and branches ├───────────────────────────────────────────────┐
+        │> 70deb1e2 (bar) create test3.txt                                                                                     │
+        │  96d1c37a create test2.txt                                                                                           │
+        │  62fc20d2 (foo) create test1.txt                                                                                     │
+        │  f777ecc9 (master) create initial.txt                                                                                │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        ┌───────────────────────────────────┤ Filter (<enter> to check out, <esc> to quit) ├───────────────────────────────────┐
+        │______________________________________________________________________________________________________________________│
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+        ┌───────────────────────────────────────────────┤ Commits and branches ├───────────────────────────────────────────────┐
+        │> 96d1c37a create test2.txt                                                                                           │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        ┌───────────────────────────────────┤ Filter (<enter> to check out, <esc> to quit) ├───────────────────────────────────┐
+        │test2_________________________________________________________________________________________________________________│
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+        insta::assert_debug_snapshot!(target, @r###"
+        Some(
+            "96d1c37a3d4363611c49f7e52186e189a04c531f",
+        )
+        "###);
+    }
+
+    {
+        let target = run_select_switch_target(
+            &git,
+            "foo",
+            vec![CursiveTestingEvent::Event(Key::Enter.into())],
+        )?;
+        assert_eq!(target, Some("foo".to_string()));
+    }
+
+    {
+        let target = run_select_switch_target(
+            &git,
+            "",
+            vec![
+                CursiveTestingEvent::Event(Key::Down.into()),
+                CursiveTestingEvent::Event(Key::Down.into()),
+                CursiveTestingEvent::Event(Key::Up.into()),
+                CursiveTestingEvent::Event(Key::Enter.into()),
+            ],
+        )?;
+        insta::assert_debug_snapshot!(target, @r###"
+        Some(
+            "96d1c37a3d4363611c49f7e52186e189a04c531f",
+        )
+        "###);
+    }
+
+    {
+        let target =
+            run_select_switch_target(&git, "", vec![CursiveTestingEvent::Event(Key::Esc.into())])?;
+        assert_eq!(target, None);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_switch_non_interactive() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout foo --
+        :
+        @ 62fc20d2 (foo) create test1.txt
+        |
+        O 96d1c37a (master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_switch_interactive_resize() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let screenshot = Default::default();
+        let events = vec![
+            CursiveTestingEvent::Resize((40, 10).into()),
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot)),
+            CursiveTestingEvent::Event(Key::Esc.into()),
+        ];
+        run_select_switch_target(&git, "", events)?;
+        insta::assert_snapshot!(screen_to_string(&screenshot), @r###"
+        ┌───────┤ Commits and branches ├───────┐
+        │> 62fc20d2 (foo) create test1.txt     │
+        │  f777ecc9 (master) create initial.txt│
+        │                                      │
+        │                                      │
+        │                                      │
+        └──────────────────────────────────────┘
+        ┌┤ Filter (<enter> to check out, <esc> t
+        │______________________________________│
+        └──────────────────────────────────────┘
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_switch_interactive_styles() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let screenshot = Default::default();
+        let events = vec![
+            CursiveTestingEvent::Event(Key::Down.into()),
+            CursiveTestingEvent::TakeStyledScreenshot(Rc::clone(&screenshot)),
+            CursiveTestingEvent::Event(Key::Esc.into()),
+        ];
+        run_select_switch_target(&git, "", events)?;
+        insta::assert_snapshot!(
+            styled_screen_to_string(&screenshot, |cell| cell.effects.contains(Effect::Reverse)),
+            @r###"
+        > f777ecc9 (master) create initial.txt
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+
+        _____________________________________________________________________________________________________________________
+        "###
+        );
+    }
+
+    Ok(())
+}
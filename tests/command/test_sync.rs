@@ -0,0 +1,146 @@
+use branchless::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_sync_all_stacks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 4b9ce31b create test1.txt
+        [2/2] Committed as: 200e5477 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 98b9119d (master) create test3.txt
+        |\
+        | o 4b9ce31b create test1.txt
+        |
+        o 200e5477 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        All stacks are already up to date with the main branch.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_selected_stacks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "under-review"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "ready"])?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["sync", "ready"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 9799dd98 create test3.txt
+        branchless: processing 1 update: branch ready
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 create initial.txt
+        |\
+        | o 62fc20d2 (under-review) create test1.txt
+        | |
+        | o 96d1c37a create test2.txt
+        |
+        @ 8f7aef57 (master) create test4.txt
+        |
+        o 9799dd98 (ready) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["sync", "none()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No stacks matched the given revsets.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_skips_commits_applied_upstream() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Skipped commit (already in main as 047b7ad7): 62fc20d2 create test1.txt
+        [2/2] Committed as: fa466332 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 047b7ad7 (master) create test1.txt
+        |
+        o fa466332 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use branchless::git::GitRunInfo;
+use branchless::testing::{get_path_to_git, Git, GitInitOptions};
+
+#[test]
+fn test_sync_events_between_clones() -> eyre::Result<()> {
+    let path_to_git = get_path_to_git()?;
+    let temp_dir = tempfile::tempdir()?;
+    let git_run_info = GitRunInfo {
+        path_to_git,
+        working_directory: temp_dir.path().to_path_buf(),
+        env: Default::default(),
+    };
+    let original_repo_path = temp_dir.path().join("original");
+    std::fs::create_dir(&original_repo_path)?;
+    let original_repo = Git::new(original_repo_path, git_run_info.clone());
+    let laptop_repo = Git::new(temp_dir.path().join("laptop"), git_run_info.clone());
+    let desktop_repo = Git::new(temp_dir.path().join("desktop"), git_run_info);
+
+    {
+        let git = original_repo.clone();
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&[
+            "clone",
+            original_repo.repo_path.to_str().unwrap(),
+            laptop_repo.repo_path.to_str().unwrap(),
+        ])?;
+        git.run(&[
+            "clone",
+            original_repo.repo_path.to_str().unwrap(),
+            desktop_repo.repo_path.to_str().unwrap(),
+        ])?;
+    }
+
+    for git in [&laptop_repo, &desktop_repo] {
+        git.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            ..Default::default()
+        })?;
+        git.detach_head()?;
+    }
+
+    {
+        let git = laptop_repo.clone();
+        git.commit_file("test2", 2)?;
+        git.run(&["push", "origin", "HEAD:refs/heads/feature"])?;
+        let (stdout, _stderr) = git.run(&["branchless", "sync-events", "push"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push origin refs/branchless/event-log:refs/branchless/event-log
+        branchless: exported 5 events to origin
+        "###);
+    }
+
+    {
+        let git = desktop_repo.clone();
+        git.run(&["fetch"])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d2 (master) create test1.txt
+        "###);
+
+        let (stdout, _stderr) = git.run(&["branchless", "sync-events", "pull"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin +refs/branchless/event-log:refs/branchless/remotes/origin/event-log
+        branchless: imported 2 events from origin
+        "###);
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d2 (master) create test1.txt
+        |
+        o 96d1c37a create test2.txt
+        "###);
+
+        // Pulling again shouldn't import the same events twice.
+        let (stdout, _stderr) = git.run(&["branchless", "sync-events", "pull"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin +refs/branchless/event-log:refs/branchless/remotes/origin/event-log
+        branchless: imported 0 events from origin
+        "###);
+
+        git.run(&["hide", "origin/feature"])?;
+        let (stdout, _stderr) = git.run(&["branchless", "sync-events", "push"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin +refs/branchless/event-log:refs/branchless/remotes/origin/event-log
+        branchless: running command: <git-executable> push origin refs/branchless/event-log:refs/branchless/event-log
+        branchless: exported 4 events to origin
+        "###);
+    }
+
+    {
+        let git = laptop_repo.clone();
+        let (stdout, _stderr) = git.run(&["branchless", "sync-events", "pull"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin +refs/branchless/event-log:refs/branchless/remotes/origin/event-log
+        branchless: imported 2 events from origin
+        "###);
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        % 96d1c37a (manually hidden) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -2,20 +2,21 @@ use std::convert::Infallible;
 use std::mem::swap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use crate::util::trim_lines;
 
 use branchless::commands::undo::testing::{select_past_event, undo_events};
-use branchless::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
+use branchless::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
-use branchless::core::mergebase::make_merge_base_db;
 use branchless::git::{GitRunInfo, Repo};
-use branchless::testing::{make_git, Git};
+use branchless::testing::{make_git, Git, GitRunOptions};
 use branchless::tui::testing::{screen_to_string, CursiveTestingBackend, CursiveTestingEvent};
 use branchless::tui::Effects;
 
 use cursive::event::Key;
 use cursive::CursiveRunnable;
+use itertools::Itertools;
 use os_str_bytes::OsStrBytes;
 
 fn run_select_past_event(
@@ -26,8 +27,7 @@ fn run_select_past_event(
     let effects = Effects::new_suppress_for_test(glyphs);
     let conn = repo.get_db_conn()?;
     let event_log_db: EventLogDb = EventLogDb::new(&conn)?;
-    let mut event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
-    let merge_base_db = make_merge_base_db(&effects, repo, &conn, &event_replayer)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
     let siv = CursiveRunnable::new::<Infallible, _>(move || {
         Ok(CursiveTestingBackend::init(events.clone()))
     });
@@ -35,8 +35,8 @@ fn run_select_past_event(
         siv.into_runner(),
         &effects,
         repo,
-        &merge_base_db,
-        &mut event_replayer,
+        &event_log_db,
+        &event_replayer,
     )
 }
 
@@ -78,6 +78,7 @@ fn run_undo_events(git: &Git, event_cursor: EventCursor) -> eyre::Result<String>
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        false,
     )?;
     assert_eq!(result, 0);
 
@@ -110,18 +111,19 @@ fn test_undo_help() -> eyre::Result<()> {
             ],
         )?;
         insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │O f777ecc9 (master) create initial.txt                                                                                │
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │        ┌───────────────────────────────────────────┤─How to use ├───────────────────────────────────────────┐        │
+        │        ┌───────────────────────────────────────────┤ How to use ├───────────────────────────────────────────┐        │
         │        │ Use `git undo` to view and revert to previous states of the repository.                            │        │
         │        │                                                                                                    │        │
         │        │ h/?: Show this help.                                                                               │        │
         │        │ q: Quit.                                                                                           │        │
         │        │ p/n or <left>/<right>: View next/previous state.                                                   │        │
         │        │ g: Go to a provided event ID.                                                                      │        │
+        │        │ t: Show when each transaction happened, switching between relative and absolute times.             │        │
         │        │ <enter>: Revert the repository to the given state (requires confirmation).                         │        │
         │        │                                                                                                    │        │
         │        │ You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it. │        │
@@ -129,9 +131,8 @@ fn test_undo_help() -> eyre::Result<()> {
         │        │                                                                                            <Close> │        │
         │        └────────────────────────────────────────────────────────────────────────────────────────────────────┘        │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │There are no previous available events.                                                                               │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         "###);
@@ -173,7 +174,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
             )
             "###);
         insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │@ 96d1c37a (master) create test2.txt                                                                                  │
         │                                                                                                                      │
@@ -190,7 +191,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
         │1. Check out from 62fc20d2 create test1.txt                                                                           │
         │               to 96d1c37a create test2.txt                                                                           │
@@ -199,7 +200,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         "###);
         insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │@ 96d1c37a (master) create test2.txt                                                                                  │
         │                                                                                                                      │
@@ -218,7 +219,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
         │1. Commit 96d1c37a create test2.txt                                                                                   │
         │                                                                                                                      │
@@ -229,6 +230,60 @@ fn test_undo_navigate() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_navigate_past_smartlog_cache() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    for i in 1..=20 {
+        git.commit_file(&format!("test{}", i), i)?;
+    }
+
+    {
+        // Step back further than the number of smartlogs which are cached, so
+        // that the ones for the later states have to be rendered again.
+        let num_steps = 20;
+        let screenshot_latest1 = Default::default();
+        let screenshot_earliest1 = Default::default();
+        let screenshot_latest2 = Default::default();
+        let screenshot_earliest2 = Default::default();
+        let mut events = vec![CursiveTestingEvent::TakeScreenshot(Rc::clone(
+            &screenshot_latest1,
+        ))];
+        events.extend((0..num_steps).map(|_| CursiveTestingEvent::Event('p'.into())));
+        events.push(CursiveTestingEvent::TakeScreenshot(Rc::clone(
+            &screenshot_earliest1,
+        )));
+        events.extend((0..num_steps).map(|_| CursiveTestingEvent::Event('n'.into())));
+        events.push(CursiveTestingEvent::TakeScreenshot(Rc::clone(
+            &screenshot_latest2,
+        )));
+        events.extend((0..num_steps).map(|_| CursiveTestingEvent::Event('p'.into())));
+        events.push(CursiveTestingEvent::TakeScreenshot(Rc::clone(
+            &screenshot_earliest2,
+        )));
+        events.push(CursiveTestingEvent::Event('q'.into()));
+        run_select_past_event(&git.get_repo()?, events)?;
+
+        let screenshot_latest1 = screen_to_string(&screenshot_latest1);
+        let screenshot_earliest1 = screen_to_string(&screenshot_earliest1);
+        assert!(screenshot_latest1.contains("create test20.txt"));
+        assert!(!screenshot_earliest1.contains("create test20.txt"));
+        assert!(screenshot_earliest1.contains("create test10.txt"));
+        assert_eq!(screen_to_string(&screenshot_latest2), screenshot_latest1);
+        assert_eq!(
+            screen_to_string(&screenshot_earliest2),
+            screenshot_earliest1
+        );
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_go_to_event() -> eyre::Result<()> {
     let git = make_git()?;
@@ -256,7 +311,7 @@ fn test_go_to_event() -> eyre::Result<()> {
     )?;
 
     insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-    ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
     │:                                                                                                                     │
     │@ 96d1c37a (master) create test2.txt                                                                                  │
     │                                                                                                                      │
@@ -275,14 +330,14 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-    ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
     │1. Commit 96d1c37a create test2.txt                                                                                   │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     "###);
     insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
-    ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
     │:                                                                                                                     │
     │@ 62fc20d2 create test1.txt                                                                                           │
     │|                                                                                                                     │
@@ -301,7 +356,7 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-    ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 1 (event 1). Press 'h' for help, 'q' to quit.                                                  │
     │1. Check out from f777ecc9 create initial.txt                                                                         │
     │               to 62fc20d2 create test1.txt                                                                           │
@@ -437,6 +492,77 @@ fn test_undo_move_refs() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_wide_characters() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "修正"])?;
+    git.commit_file("test1", 1)?;
+    git.write_file("test2", "contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "修正する 🎉"])?;
+
+    let screenshot = Default::default();
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot)),
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+            CursiveTestingEvent::Event('y'.into()),
+        ],
+    )?;
+    insta::assert_snapshot!(screen_to_string(&screenshot), @r###"
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+    │O f777ecc9 (master) create initial.txt                                                                                │
+    │|                                                                                                                     │
+    │o 62fc20d2 create test1.txt                                                                                           │
+    │|                                                                                                                     │
+    │@ c508f393 (修正) 修正する 🎉                                                                                         │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+    │Repo after transaction 6 (event 8). Press 'h' for help, 'q' to quit.                                                  │
+    │1. Commit c508f393 修正する 🎉                                                                                        │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    "###);
+
+    {
+        let stdout = run_undo_events(&git, event_cursor.unwrap())?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Check out from c508f393 修正する 🎉
+                       to 62fc20d2 create test1.txt
+        2. Hide commit c508f393 修正する 🎉
+
+        3. Move branch 修正 from c508f393 修正する 🎉
+                              to 62fc20d2 create test1.txt
+        Confirm? [yN] branchless: running command: <git-executable> checkout --detach 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        Applied 3 inverse events.
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_historical_smartlog_visibility() -> eyre::Result<()> {
     let git = make_git()?;
@@ -459,7 +585,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
 
     if git.supports_reference_transactions()? {
         insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │% 62fc20d2 (manually hidden) (master) create test1.txt                                                                │
         │                                                                                                                      │
@@ -476,16 +602,16 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+        │Transaction: hide                                                                                                     │
         │1. Hide commit 62fc20d2 create test1.txt                                                                              │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         "###);
         insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │@ 62fc20d2 (master) create test1.txt                                                                                  │
         │                                                                                                                      │
@@ -504,7 +630,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 2 (event 3). Press 'h' for help, 'q' to quit.                                                  │
         │1. Commit 62fc20d2 create test1.txt                                                                                   │
         │                                                                                                                      │
@@ -512,7 +638,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         "###);
     } else {
         insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │% 62fc20d2 (manually hidden) (master) create test1.txt                                                                │
         │                                                                                                                      │
@@ -531,14 +657,14 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 2 (event 2). Press 'h' for help, 'q' to quit.                                                  │
         │1. Hide commit 62fc20d2 create test1.txt                                                                              │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         "###);
         insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
-        ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
         │:                                                                                                                     │
         │@ 62fc20d2 (master) create test1.txt                                                                                  │
         │                                                                                                                      │
@@ -557,7 +683,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-        ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 1 (event 1). Press 'h' for help, 'q' to quit.                                                  │
         │1. Commit 62fc20d2 create test1.txt                                                                                   │
         │                                                                                                                      │
@@ -598,7 +724,7 @@ fn test_undo_doesnt_make_working_dir_dirty() -> eyre::Result<()> {
         ],
     )?;
     insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-    ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
     │:                                                                                                                     │
     │O 62fc20d2 (master) create test1.txt                                                                                  │
     │                                                                                                                      │
@@ -619,7 +745,7 @@ fn test_undo_doesnt_make_working_dir_dirty() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-    ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │There are no previous available events.                                                                               │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     "###);
@@ -684,7 +810,7 @@ fn test_git_bisect_produces_empty_event() -> eyre::Result<()> {
         ],
     )?;
     insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-    ┌───────────────────────────────────────────────────┤─Commit graph ├───────────────────────────────────────────────────┐
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
     │:                                                                                                                     │
     │@ 62fc20d2 (master) create test1.txt                                                                                  │
     │                                                                                                                      │
@@ -703,7 +829,7 @@ fn test_git_bisect_produces_empty_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
-    ┌──────────────────────────────────────────────────────┤─Events ├──────────────────────────────────────────────────────┐
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
     │1. Empty event for BISECT_HEAD                                                                                        │
     │   This may be an unsupported use-case; see https://git.io/J0b7z                                                      │
@@ -712,3 +838,604 @@ fn test_git_bisect_produces_empty_event() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_undo_shows_failed_wrapped_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file_with_contents("test", 1, "foo")?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file_with_contents("test", 1, "bar")?;
+    git.run_with_options(
+        &["branchless", "wrap", "--", "rebase", &test1_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    let screenshot1 = Default::default();
+    run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+            CursiveTestingEvent::Event('q'.into()),
+        ],
+    )?;
+    insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+    │O f777ecc9 create initial.txt                                                                                         │
+    │|\                                                                                                                    │
+    │| o b2580022 create test.txt                                                                                          │
+    │|                                                                                                                     │
+    │@ f6b7a313 (master) create test.txt                                                                                   │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+    │Repo after transaction 7 (event 8). Press 'h' for help, 'q' to quit.                                                  │
+    │Command failed with exit code 1: git rebase f6b7a313ae802a0605c1257cc203d2b3ceed265d                                  │
+    │1. Check out from b2580022 create test.txt                                                                            │
+    │               to f6b7a313 create test.txt                                                                            │
+    │2. Check out from b2580022 create test.txt                                                                            │
+    │               to f6b7a313 create test.txt                                                                            │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_shows_annotations() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "annotate", "999", "-m", "nonexistent"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Event not found: 999
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "annotate",
+            "3",
+            "-m",
+            "this is where I broke things",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Annotated transaction 2 (event 3).
+        "###);
+    }
+
+    let screenshot1 = Default::default();
+    run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+            CursiveTestingEvent::Event('q'.into()),
+        ],
+    )?;
+    insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+    │:                                                                                                                     │
+    │@ 62fc20d2 (master) create test1.txt                                                                                  │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+    │Repo after transaction 2 (event 3). Press 'h' for help, 'q' to quit.                                                  │
+    │Note: this is where I broke things                                                                                    │
+    │1. Commit 62fc20d2 create test1.txt                                                                                   │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_toggle_time_format() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let mut event_log_db = EventLogDb::new(&conn)?;
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1633046399);
+        let event_tx_id = event_log_db.make_transaction_id(timestamp, "test")?;
+        event_log_db.add_events(vec![Event::CommitEvent {
+            timestamp: 1633046399.0,
+            event_tx_id,
+            commit_oid: test1_oid,
+        }])?;
+    }
+
+    let screenshot1 = Default::default();
+    run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('t'.into()),
+            CursiveTestingEvent::Event('t'.into()),
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+            CursiveTestingEvent::Event('q'.into()),
+        ],
+    )?;
+    insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+    ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+    │:                                                                                                                     │
+    │@ 62fc20d2 (master) create test1.txt                                                                                  │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+    │Repo after transaction 3 (event 4) (at 2021-09-30 23:59:59 UTC). Press 'h' for help, 'q' to quit.                     │
+    │Transaction: test                                                                                                     │
+    │1. Commit 62fc20d2 create test1.txt                                                                                   │
+    │                                                                                                                      │
+    └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_restores_deleted_tag() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["tag", "-a", "v1", "-m", "version 1"])?;
+    {
+        let (stdout, _stderr) = git.run(&["tag", "-d", "v1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Deleted tag 'v1' (was 34c9c0c)
+        "###);
+    }
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Create tag v1 at 62fc20d2 create test1.txt
+
+        Confirm? [yN] Applied 1 inverse event.
+        "###);
+    }
+
+    // The annotated tag object should be restored, not just a lightweight tag
+    // pointing to the same commit.
+    {
+        let (stdout, _stderr) = git.run(&["tag", "-n"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        v1              version 1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_removes_created_tag() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    {
+        let (_stdout, stderr) = git.run(&["tag", "v1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: tag v1
+        "###);
+    }
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Delete tag v1 at 62fc20d2 create test1.txt
+
+        Confirm? [yN] Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["tag", "--list"])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_worktree_checkouts() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let worktree_dir = tempfile::tempdir()?;
+    let worktree_path = worktree_dir.path().join("test-worktree");
+    git.run(&[
+        "worktree",
+        "add",
+        "--detach",
+        worktree_path.to_str().unwrap(),
+        &test1_oid.to_string(),
+    ])?;
+    let worktree_git = Git::new(worktree_path, git.get_git_run_info());
+
+    let event_cursor = {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(
+            &Effects::new_suppress_for_test(Glyphs::text()),
+            &repo,
+            &event_log_db,
+        )?;
+        event_replayer.make_default_cursor()
+    };
+    worktree_git.run(&["checkout", "HEAD^"])?;
+
+    {
+        // The worktree shares the event log with the main worktree, but its
+        // checkouts are recorded separately.
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let ref_names: Vec<String> = event_log_db
+            .get_events()?
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::RefUpdateEvent { ref_name, .. } => {
+                    Some(ref_name.to_string_lossy().into_owned())
+                }
+                _ => None,
+            })
+            .unique()
+            .collect();
+        insta::assert_debug_snapshot!(ref_names, @r###"
+        [
+            "HEAD",
+            "worktrees/test-worktree/HEAD",
+        ]
+        "###);
+    }
+
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        No undo actions to apply, exiting.
+        "###);
+    }
+
+    {
+        let stdout = run_undo_events(&worktree_git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Check out from f777ecc9 create initial.txt
+                       to 62fc20d2 create test1.txt
+        Confirm? [yN] branchless: running command: <git-executable> checkout --detach 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = worktree_git.run(&["rev-parse", "--short", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d
+        "###);
+        let (stdout, _stderr) = git.run(&["rev-parse", "--short", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_stash_drop() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.write_file("test1", "changed contents\n")?;
+    {
+        let (_stdout, stderr) = git.run(&["stash"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: stash
+        branchless: processing 2 updates: branch master, ref HEAD
+        "###);
+    }
+    {
+        let (_stdout, stderr) = git.run(&["stash", "drop"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: stash
+        "###);
+    }
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Push stash entry ae621564 WIP on master: 62fc20d create test1.txt
+
+        Confirm? [yN] branchless: running command: <git-executable> stash store -m WIP on master: 62fc20d create test1.txt ae621564c690fdb03826646dff0d7c1901cd9714
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        stash@{0}: WIP on master: 62fc20d create test1.txt
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["stash", "pop"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        On branch master
+        Changes not staged for commit:
+          (use "git add <file>..." to update what will be committed)
+          (use "git restore <file>..." to discard changes in working directory)
+        	modified:   test1.txt
+
+        no changes added to commit (use "git add" and/or "git commit -a")
+        Dropped refs/stash@{0} (ae621564c690fdb03826646dff0d7c1901cd9714)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_stash_drop_multiple_entries() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    for i in 1..=3 {
+        git.write_file("test1", &format!("changed contents {}\n", i))?;
+        git.run(&["stash", "push", "-m", &format!("stash {}", i)])?;
+    }
+    {
+        // Git doesn't invoke any hooks for this, since `refs/stash` itself
+        // isn't changed.
+        let (_stdout, stderr) =
+            git.run(&["branchless", "wrap", "--", "stash", "drop", "stash@{1}"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Push stash entry 74379f2c On master: stash 2
+
+        Confirm? [yN] branchless: running command: <git-executable> stash store -m On master: stash 2 74379f2c2f4e4eb025a5a665b86775a3101d782a
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        stash@{0}: On master: stash 2
+        stash@{1}: On master: stash 3
+        stash@{2}: On master: stash 1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_stash_pop_multiple_entries() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    for i in 1..=2 {
+        git.write_file("test1", &format!("changed contents {}\n", i))?;
+        git.run(&["stash", "push", "-m", &format!("stash {}", i)])?;
+    }
+    git.run(&["branchless", "wrap", "--", "stash", "pop"])?;
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        stash@{0}: On master: stash 1
+        "###);
+    }
+    git.run(&["checkout", "--", "test1.txt"])?;
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Push stash entry 74379f2c On master: stash 2
+
+        Confirm? [yN] branchless: running command: <git-executable> stash store -m On master: stash 2 74379f2c2f4e4eb025a5a665b86775a3101d782a
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        stash@{0}: On master: stash 2
+        stash@{1}: On master: stash 1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_stash_push() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.write_file("test1", "changed contents\n")?;
+    git.run(&["stash"])?;
+
+    let event_cursor = run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event(Key::Enter.into()),
+        ],
+    )?;
+    let event_cursor = event_cursor.expect("Should have an event cursor to undo");
+    {
+        let stdout = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Drop stash entry ae621564 WIP on master: 62fc20d create test1.txt
+
+        Confirm? [yN] branchless: running command: <git-executable> stash drop --quiet stash@{0}
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["stash", "list"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short", "--branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ## master
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use branchless::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
@@ -122,6 +124,7 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
                 9,
             ),
             commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+            reason: None,
         },
         RefUpdateEvent {
             timestamp: 0.0,
@@ -138,3 +141,150 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_change_version_across_rewrites() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended once"])?;
+    git.run(&["commit", "--amend", "-m", "amended twice"])?;
+    git.commit_file("test2", 2)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let cursor = event_replayer.make_default_cursor();
+
+    let head_oid = repo.get_head_info()?.oid.unwrap();
+    let amended_oid = repo
+        .find_commit_or_fail(head_oid)?
+        .get_only_parent_oid()
+        .unwrap();
+
+    let original_version = event_replayer.get_cursor_change_version(cursor, test1_oid);
+    assert_eq!(original_version.version, 1);
+
+    let amended_version = event_replayer.get_cursor_change_version(cursor, amended_oid);
+    assert_eq!(amended_version.change_id, original_version.change_id);
+    assert_eq!(amended_version.version, 3);
+    assert_eq!(
+        amended_version.change_id.to_string(),
+        format!("I{}", test1_oid)
+    );
+
+    let head_version = event_replayer.get_cursor_change_version(cursor, head_oid);
+    assert_ne!(head_version.change_id, original_version.change_id);
+    assert_eq!(head_version.version, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_event_log_ignore_refs() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "--add",
+        "branchless.eventLog.ignoreRefs",
+        "refs/notes/*",
+    ])?;
+    git.run(&[
+        "config",
+        "--add",
+        "branchless.eventLog.ignoreRefs",
+        "refs/tags/ci-*",
+    ])?;
+    {
+        let (_stdout, stderr) = git.run(&["notes", "add", "-m", "note"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["tag", "ci-build-1"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["tag", "v1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: tag v1
+        "###);
+    }
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let ref_names: Vec<String> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::RefUpdateEvent { ref_name, .. } => Some(ref_name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ref_names, vec!["refs/tags/v1".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_event_log_db_path() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let cache_dir = tempfile::tempdir()?;
+    git.run(&[
+        "config",
+        "branchless.db.path",
+        &cache_dir.path().join("{repo}").to_string_lossy(),
+    ])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let repo = git.get_repo()?;
+    let db_path = repo.get_db_path()?;
+    assert!(db_path.starts_with(cache_dir.path()));
+    assert!(db_path.exists());
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        @ 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_event_log_db_events_version() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let version = event_log_db.get_events_version()?;
+    assert_eq!(version, event_log_db.get_events_version()?);
+
+    git.commit_file("test1", 1)?;
+    let new_version = event_log_db.get_events_version()?;
+    assert_ne!(version, new_version);
+    assert_eq!(
+        new_version.0,
+        isize::try_from(event_log_db.get_events()?.len())?
+    );
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use branchless::testing::make_git;
+use branchless::core::eventlog::EventLogDb;
+use branchless::git::GitRunInfo;
+use branchless::testing::{get_path_to_git, make_git, Git};
 
 #[test]
 fn test_gc() -> eyre::Result<()> {
@@ -24,6 +26,7 @@ o 62fc20d2 create test1.txt
 "###);
     }
 
+    git.run(&["config", "branchless.gc.keepHiddenDays", "0"])?;
     git.run(&["hide", "62fc20d2"])?;
     {
         let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
@@ -47,3 +50,191 @@ branchless: collecting garbage
 
     Ok(())
 }
+
+#[test]
+fn test_gc_keeps_recently_hidden_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["hide", "62fc20d2"])?;
+    git.run(&["branchless", "gc"])?;
+    git.run(&["gc", "--prune=now"])?;
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_some())
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["for-each-ref", "--format=%(refname)", "refs/branchless/"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        refs/branchless/event-log
+        refs/branchless/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    {
+        git.run(&["unhide", "62fc20d2"])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_event_log_retention() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["hide", "62fc20d2"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["config", "branchless.gc.keepHiddenDays", "0"])?;
+
+    let count_events = || -> eyre::Result<usize> {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        Ok(event_log_db.get_events()?.len())
+    };
+    let num_events = count_events()?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        "###);
+        assert_eq!(count_events()?, num_events);
+    }
+
+    git.run(&["config", "branchless.eventLog.retention", "0d"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: pruned 9 events from the event log
+        "###);
+        assert!(count_events()? < num_events);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o fe65c1fe create test2.txt
+        |
+        @ 02067177 create test3.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.eventLog.retention", "90d"])?;
+    {
+        let num_events = count_events()?;
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        "###);
+        assert_eq!(count_events()?, num_events);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_write_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let commit_graph_path = git.repo_path.join(".git/objects/info/commit-graph");
+    git.run(&["branchless", "gc"])?;
+    assert!(!commit_graph_path.exists());
+
+    git.run(&["config", "branchless.commitGraph.write", "true"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: running command: <git-executable> commit-graph write --reachable
+        "###);
+    }
+    assert!(commit_graph_path.exists());
+
+    std::fs::remove_file(&commit_graph_path)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    assert!(commit_graph_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_event_log_survives_mirror_clone() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["hide", "HEAD"])?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["branchless", "gc"])?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let copy_path = temp_dir.path().join("copy");
+    git.run(&[
+        "clone",
+        "--mirror",
+        git.repo_path.to_str().unwrap(),
+        copy_path.join(".git").to_str().unwrap(),
+    ])?;
+    let copy = Git::new(
+        copy_path,
+        GitRunInfo {
+            path_to_git: get_path_to_git()?,
+            working_directory: temp_dir.path().to_path_buf(),
+            env: Default::default(),
+        },
+    );
+    copy.run(&["config", "--bool", "core.bare", "false"])?;
+    copy.run(&["checkout", "--detach", "master"])?;
+    copy.run(&["config", "branchless.commitMetadata.relativeTime", "false"])?;
+
+    {
+        let (stdout, _stderr) = copy.run(&["branchless", "init"])?;
+        let stdout: Vec<&str> = stdout
+            .lines()
+            .filter(|line| line.starts_with("Restored"))
+            .collect();
+        insta::assert_debug_snapshot!(stdout, @r###"
+        [
+            "Restored 3 events from the snapshot in refs/branchless/event-log",
+        ]
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = copy.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use branchless::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
-use branchless::testing::make_git;
+use branchless::git::GitRunInfo;
+use branchless::testing::{get_path_to_git, make_git, Git, GitInitOptions};
 use branchless::tui::Effects;
 use branchless::util::get_sh;
 use eyre::{eyre, Context};
@@ -84,6 +85,99 @@ fn test_abandoned_branch_message() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_abandoned_message_threshold() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.warnAbandonedThreshold", "3"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "abc"])?;
+    git.detach_head()?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe91 amend test1
+        branchless: processing 1 rewritten commit
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hooks_quiet() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.hooks.quiet", "true"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    {
+        // The events should still have been recorded.
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 create initial.txt
+        |\
+        | @ 9e8dbe91 amend test1
+        |
+        X 62fc20d2 (rewritten as 9e8dbe91) create test1.txt
+        |
+        O 96d1c37a (master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hooks_verbosity_warnings() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.hooks.verbosity", "warnings"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: This operation abandoned 1 commit!
+        branchless: Consider running one of the following:
+        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:     (this is most likely what you want to do)
+        branchless:   - git smartlog: assess the situation
+        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
+        branchless:   - git undo: undo the operation
+        branchless:   - git config branchless.restack.warnAbandoned false: suppress this message
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_fixup_no_abandoned_commit_message() -> eyre::Result<()> {
     let git = make_git()?;
@@ -321,3 +415,106 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hooks_invalid_color_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.color", "sometimes"])?;
+    git.run(&["config", "branchless.color.oid", "bogus-style"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::CommitEvent { commit_oid, .. } if *commit_oid == test1_oid
+    )));
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::RefUpdateEvent { ref_name, .. } if ref_name == "refs/heads/master"
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn test_hide_commits_rewritten_upstream() -> eyre::Result<()> {
+    let path_to_git = get_path_to_git()?;
+    let temp_dir = tempfile::tempdir()?;
+    let git_run_info = GitRunInfo {
+        path_to_git,
+        working_directory: temp_dir.path().to_path_buf(),
+        env: Default::default(),
+    };
+    let original_repo_path = temp_dir.path().join("original");
+    std::fs::create_dir(&original_repo_path)?;
+    let original_repo = Git::new(original_repo_path, git_run_info.clone());
+    let cloned_repo_path = temp_dir.path().join("cloned");
+    let cloned_repo = Git::new(cloned_repo_path, git_run_info);
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    {
+        let git = original_repo.clone();
+        git.init_repo()?;
+        git.run(&["checkout", "-b", "feature"])?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&[
+            "clone",
+            original_repo.repo_path.to_str().unwrap(),
+            cloned_repo.repo_path.to_str().unwrap(),
+        ])?;
+    }
+
+    {
+        let git = cloned_repo.clone();
+        git.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            ..Default::default()
+        })?;
+        git.run(&["checkout", "origin/feature"])?;
+        git.commit_file("test2", 2)?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        @ 96d1c37a create test2.txt
+        "###);
+    }
+
+    {
+        let git = original_repo.clone();
+        git.run(&["checkout", "feature"])?;
+        git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    }
+
+    {
+        let git = cloned_repo.clone();
+        git.run(&["fetch"])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc9 (master) create initial.txt
+        |
+        x 62fc20d2 (hidden: rewritten upstream in origin/feature) create test1.txt
+        |
+        @ 96d1c37a create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
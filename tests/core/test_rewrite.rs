@@ -0,0 +1,237 @@
+use std::time::SystemTime;
+
+use branchless::core::eventlog::{EventLogDb, EventReplayer};
+use branchless::core::formatting::Glyphs;
+use branchless::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use branchless::core::mergebase::make_merge_base_db;
+use branchless::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, RebasePlanBuilder,
+};
+use branchless::testing::make_git;
+use branchless::tui::Effects;
+
+#[test]
+fn test_custom_rebase_plan() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(&effects, &repo, &conn, &event_replayer)?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        &effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(repo.get_head_info()?.oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    // Reword `test2` by replacing it with a copy with a different message.
+    let test2_commit = repo.find_commit_or_fail(test2_oid)?;
+    let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+    let replacement_oid = repo.create_commit(
+        None,
+        &test2_commit.get_author(),
+        &test2_commit.get_committer(),
+        "reworded test2.txt\n",
+        &test2_commit.get_tree()?,
+        vec![&test1_commit],
+    )?;
+
+    let mut builder = RebasePlanBuilder::new(
+        &repo,
+        &graph,
+        &merge_base_db,
+        &MainBranchOid(main_branch_oid),
+    );
+    builder.move_subtree(test3_oid, test1_oid)?;
+    builder.replace_commit(test2_oid, replacement_oid)?;
+    builder.add_exec_after(test3_oid, "touch .git/exec-ran");
+    let rebase_plan = match builder.build(
+        &effects,
+        &BuildRebasePlanOptions {
+            dump_rebase_constraints: false,
+            dump_rebase_plan: false,
+            detect_duplicate_commits_via_patch_id: false,
+        },
+    )? {
+        Ok(Some(rebase_plan)) => rebase_plan,
+        Ok(None) => eyre::bail!("Expected a rebase plan"),
+        Err(err) => eyre::bail!("Could not build rebase plan: {:?}", err),
+    };
+
+    {
+        let commands = rebase_plan
+            .get_commands()
+            .iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        insta::assert_snapshot!(commands, @r###"
+        exec git branchless hook-register-extra-post-rewrite-hook
+        reset 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        pick 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        exec git branchless hook-detect-empty-commit 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        exec touch .git/exec-ran
+        reset 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        pick e437fdf7722ac8f28edf57403ac8bc9e23751729
+        exec git branchless hook-record-replaced-commit 96d1c37a3d4363611c49f7e52186e189a04c531f
+        exec git branchless hook-detect-empty-commit 96d1c37a3d4363611c49f7e52186e189a04c531f
+        "###);
+    }
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "test")?;
+    let exit_code = execute_rebase_plan(
+        &effects,
+        &git_run_info,
+        &repo,
+        &rebase_plan,
+        &ExecuteRebasePlanOptions {
+            now,
+            event_tx_id,
+            preserve_timestamps: true,
+            force_in_memory: false,
+            force_on_disk: false,
+            strategy_options: Vec::new(),
+            keep_originals: false,
+        },
+    )?;
+    assert_eq!(exit_code, 0);
+    assert!(git.repo_path.join(".git").join("exec-ran").exists());
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |\
+        | o e437fdf7 reworded test2.txt
+        |
+        o 4838e49b create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_replace_commit_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "foo", &test2_oid.to_string()])?;
+
+    // Make the replacement for `test2` with `git commit --amend`, then go back
+    // to where we were.
+    git.run(&["checkout", &test2_oid.to_string()])?;
+    git.write_file("test2", "amended contents")?;
+    git.run(&["commit", "--amend", "-a", "-m", "amend test2.txt"])?;
+    let replacement_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.run(&["checkout", "master"])?;
+    git.run(&["hide", &replacement_oid.to_string()])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let merge_base_db = make_merge_base_db(&effects, &repo, &conn, &event_replayer)?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let graph = make_graph(
+        &effects,
+        &repo,
+        &merge_base_db,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(repo.get_head_info()?.oid),
+        &MainBranchOid(main_branch_oid),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        true,
+    )?;
+
+    let mut builder = RebasePlanBuilder::new(
+        &repo,
+        &graph,
+        &merge_base_db,
+        &MainBranchOid(main_branch_oid),
+    );
+    builder.replace_commit(test2_oid, replacement_oid)?;
+    let rebase_plan = match builder.build(
+        &effects,
+        &BuildRebasePlanOptions {
+            dump_rebase_constraints: false,
+            dump_rebase_plan: false,
+            detect_duplicate_commits_via_patch_id: false,
+        },
+    )? {
+        Ok(Some(rebase_plan)) => rebase_plan,
+        Ok(None) => eyre::bail!("Expected a rebase plan"),
+        Err(err) => eyre::bail!("Could not build rebase plan: {:?}", err),
+    };
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "test")?;
+    let exit_code = execute_rebase_plan(
+        &effects,
+        &git_run_info,
+        &repo,
+        &rebase_plan,
+        &ExecuteRebasePlanOptions {
+            now,
+            event_tx_id,
+            preserve_timestamps: true,
+            force_in_memory: true,
+            force_on_disk: false,
+            strategy_options: Vec::new(),
+            keep_originals: false,
+        },
+    )?;
+    assert_eq!(exit_code, 0);
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc9 (master) create initial.txt
+        |
+        o 62fc20d2 create test1.txt
+        |
+        o afa66b93 (foo) amend test2.txt
+        |
+        o 9d2c7de4 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
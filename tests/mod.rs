@@ -4,15 +4,36 @@ mod core {
     mod test_eventlog;
     mod test_gc;
     mod test_hooks;
+    mod test_rewrite;
 }
 
 mod command {
+    mod test_benchmark;
+    mod test_branch;
+    mod test_bug_report;
+    mod test_diff;
+    mod test_doctor;
+    mod test_edit;
+    mod test_export_stack;
+    mod test_fold;
     mod test_hide;
     mod test_init;
+    mod test_man;
     mod test_move;
     mod test_navigation;
+    mod test_plugin;
+    mod test_prune;
+    mod test_rebase;
+    mod test_repair;
     mod test_restack;
+    mod test_restore_branch;
     mod test_smartlog;
+    mod test_status;
+    mod test_submit;
+    mod test_swap;
+    mod test_switch;
+    mod test_sync;
+    mod test_sync_events;
     mod test_undo;
     mod test_wrap;
 }
@@ -1,4 +1,4 @@
-use branchless::testing::make_git;
+use branchless::testing::{get_path_to_git, make_git, make_git_with_path_to_git};
 
 #[test]
 fn test_commands() -> eyre::Result<()> {
@@ -52,3 +52,51 @@ To hide this commit, run: git hide 3df4b935
 
     Ok(())
 }
+
+#[test]
+fn test_testing_harness_clock() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 0)?;
+    git.run(&["reset", "--hard", "HEAD^"])?;
+
+    // Committing the same file at the same time gives the same commit.
+    assert_eq!(git.commit_file("test1", 0)?, test1_oid);
+    git.run(&["reset", "--hard", "HEAD^"])?;
+
+    git.advance_time(1);
+    assert_eq!(git.get_time(), 1);
+    let test1_later_oid = git.commit_file("test1", 0)?;
+    assert_ne!(test1_later_oid, test1_oid);
+    git.run(&["reset", "--hard", "HEAD^"])?;
+
+    // The clock time and the per-command time are added together.
+    git.set_time(0);
+    assert_eq!(git.commit_file("test1", 1)?, test1_later_oid);
+
+    Ok(())
+}
+
+#[test]
+fn test_testing_harness_options() -> eyre::Result<()> {
+    let git = make_git_with_path_to_git(get_path_to_git()?)?;
+
+    git.init_repo()?;
+    git.set_config("branchless.test.key", "value")?;
+    {
+        let (stdout, _stderr) = git.run(&["config", "branchless.test.key"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        value
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_input(&["hash-object", "--stdin"], "foo\n")?;
+        insta::assert_snapshot!(stdout, @r###"
+        257cc5642cb1a054f08cc83f2d943e56fd3ebe99
+        "###);
+    }
+
+    Ok(())
+}